@@ -0,0 +1,76 @@
+use std::fmt::Display;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+
+/// The exit status of a run stopped by Ctrl+C, the shell's for SIGINT, whether the song in
+/// progress was wound up or a second Ctrl+C stopped it at once.
+pub const EXIT_INTERRUPTED: i32 = 130;
+
+/// Set by the Ctrl+C handler; a plain static so the handler only touches atomics.
+static CTRL_C: OnceLock<AbortSignal> = OnceLock::new();
+
+/// Asks whatever song is in progress to stop. The download loops check it between polls,
+/// cancel Chrome's in-flight transfers and return [`DownloadError::Cancelled`].
+///
+/// [`DownloadError::Cancelled`]: crate::tasks::download_song::DownloadError::Cancelled
+#[derive(Debug, Clone, Default)]
+pub struct AbortSignal(Arc<AtomicBool>);
+
+impl AbortSignal {
+    pub fn request(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_requested(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    pub fn reset(&self) {
+        self.0.store(false, Ordering::SeqCst);
+    }
+
+    /// Makes the first Ctrl+C request an abort instead of killing the process, so the song
+    /// in progress is cleaned up. A second Ctrl+C exits straight away. Only the first
+    /// signal installed this way is used.
+    ///
+    /// A Chrome the driver launched is in the same process group and gets the Ctrl+C too,
+    /// so the cleanup doesn't count on it: the song is cancelled whatever Chrome does, and
+    /// its partial files are removed from the disk.
+    pub fn on_ctrl_c(&self) {
+        if CTRL_C.set(self.clone()).is_ok() {
+            install_handler();
+        }
+    }
+}
+
+/// A run that stopped at an abort, once the song in progress was cleaned up and the batch
+/// state written. Ends the program with [`EXIT_INTERRUPTED`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Interrupted;
+
+impl Display for Interrupted {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Interrupted")
+    }
+}
+
+impl std::error::Error for Interrupted {}
+
+#[cfg(unix)]
+fn install_handler() {
+    extern "C" fn handle(_: libc::c_int) {
+        if let Some(signal) = CTRL_C.get() {
+            if signal.0.swap(true, Ordering::SeqCst) {
+                unsafe { libc::_exit(EXIT_INTERRUPTED) };
+            }
+        }
+    }
+    unsafe {
+        libc::signal(libc::SIGINT, handle as extern "C" fn(libc::c_int) as libc::sighandler_t);
+    }
+}
+
+#[cfg(not(unix))]
+fn install_handler() {
+    tracing::debug!("Ctrl+C still stops the process at once on this platform");
+}
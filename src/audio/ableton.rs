@@ -0,0 +1,142 @@
+use anyhow::Result;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+/// Version attributes of the root element, as written by Live 11.0. Live refuses sets whose
+/// schema it doesn't know, so these are pinned rather than derived.
+pub const MAJOR_VERSION: &str = "5";
+pub const MINOR_VERSION: &str = "11.0_433";
+pub const SCHEMA_CHANGE_COUNT: &str = "3";
+pub const CREATOR: &str = "Ableton Live 11.0";
+
+/// One mono stem placed at the start of the arrangement.
+#[derive(Debug, Clone)]
+pub struct AbletonTrack {
+    pub name: String,
+    /// Path of the WAV relative to the folder the set is saved in, with `/` separators.
+    pub relative_path: String,
+    pub absolute_path: String,
+    pub sample_rate: u32,
+    pub frames: u32,
+    pub file_size: u64,
+    /// -1.0 (hard left) to 1.0 (hard right).
+    pub pan: f64,
+    /// Linear gain, 1.0 being unity.
+    pub volume: f64,
+    /// Hardware output, counted from 1, instead of the master.
+    pub output: Option<u32>,
+}
+
+/// Writes the set as Live expects it: gzipped XML.
+pub fn write_als(path: &Path, tracks: &[AbletonTrack], tempo: f64) -> Result<()> {
+    let mut encoder = GzEncoder::new(File::create(path)?, Compression::default());
+    encoder.write_all(live_set_xml(tracks, tempo).as_bytes())?;
+    encoder.finish()?;
+    tracing::debug!("Wrote {:?}", path);
+    Ok(())
+}
+
+/// The uncompressed XML of a Live 11 set with one unwarped audio clip per track.
+pub fn live_set_xml(tracks: &[AbletonTrack], tempo: f64) -> String {
+    let mut xml = String::new();
+    let _ = writeln!(xml, r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    let _ = writeln!(
+        xml,
+        r#"<Ableton MajorVersion="{}" MinorVersion="{}" SchemaChangeCount="{}" Creator="{}" Revision="">"#,
+        MAJOR_VERSION, MINOR_VERSION, SCHEMA_CHANGE_COUNT, CREATOR
+    );
+    let _ = writeln!(xml, "  <LiveSet>");
+    // Ids 0..tracks are the tracks, the clips follow
+    let _ = writeln!(xml, r#"    <NextPointeeId Value="{}" />"#, tracks.len() * 2 + 1);
+    let _ = writeln!(xml, r#"    <OverwriteProtectionNumber Value="2816" />"#);
+    let _ = writeln!(xml, r#"    <LomId Value="0" />"#);
+    let _ = writeln!(xml, "    <Tracks>");
+    for (i, track) in tracks.iter().enumerate() {
+        write_audio_track(&mut xml, i, tracks.len() + i, track, tempo);
+    }
+    let _ = writeln!(xml, "    </Tracks>");
+    let _ = writeln!(xml, "    <MasterTrack>");
+    let _ = writeln!(xml, "      <DeviceChain>");
+    let _ = writeln!(xml, "        <Mixer>");
+    let _ = writeln!(xml, r#"          <Tempo><Manual Value="{}" /></Tempo>"#, tempo);
+    let _ = writeln!(xml, r#"          <TimeSignature><Manual Value="201" /></TimeSignature>"#);
+    let _ = writeln!(xml, "        </Mixer>");
+    let _ = writeln!(xml, "      </DeviceChain>");
+    let _ = writeln!(xml, "    </MasterTrack>");
+    let _ = writeln!(xml, "    <Transport>");
+    let _ = writeln!(xml, r#"      <LoopOn Value="false" />"#);
+    let _ = writeln!(xml, r#"      <CurrentTime Value="0" />"#);
+    let _ = writeln!(xml, "    </Transport>");
+    let _ = writeln!(xml, "  </LiveSet>");
+    let _ = writeln!(xml, "</Ableton>");
+    xml
+}
+
+fn write_audio_track(xml: &mut String, id: usize, clip_id: usize, track: &AbletonTrack, tempo: f64) {
+    let name = escape(&track.name);
+    let seconds = track.frames as f64 / track.sample_rate as f64;
+    // Unwarped clips still have their length expressed in beats at the set's tempo
+    let beats = seconds * tempo / 60.0;
+
+    let _ = writeln!(xml, r#"      <AudioTrack Id="{}">"#, id);
+    let _ = writeln!(xml, r#"        <LomId Value="0" />"#);
+    let _ = writeln!(xml, "        <Name>");
+    let _ = writeln!(xml, r#"          <EffectiveName Value="{}" />"#, name);
+    let _ = writeln!(xml, r#"          <UserName Value="{}" />"#, name);
+    let _ = writeln!(xml, r#"          <Annotation Value="" />"#);
+    let _ = writeln!(xml, "        </Name>");
+    let _ = writeln!(xml, "        <DeviceChain>");
+    if let Some(output) = track.output {
+        let _ = writeln!(xml, "          <AudioOutputRouting>");
+        let _ = writeln!(xml, r#"            <Target Value="AudioOut/External/M{}" />"#, output - 1);
+        let _ = writeln!(xml, r#"            <UpperDisplayString Value="Ext. Out" />"#);
+        let _ = writeln!(xml, r#"            <LowerDisplayString Value="{}" />"#, output);
+        let _ = writeln!(xml, "          </AudioOutputRouting>");
+    }
+    let _ = writeln!(xml, "          <Mixer>");
+    let _ = writeln!(xml, r#"            <Pan><Manual Value="{}" /></Pan>"#, track.pan);
+    let _ = writeln!(xml, r#"            <Volume><Manual Value="{}" /></Volume>"#, track.volume);
+    let _ = writeln!(xml, "          </Mixer>");
+    let _ = writeln!(xml, "          <MainSequencer>");
+    let _ = writeln!(xml, "            <Sample>");
+    let _ = writeln!(xml, "              <ArrangerAutomation>");
+    let _ = writeln!(xml, "                <Events>");
+    let _ = writeln!(xml, r#"                  <AudioClip Id="{}" Time="0">"#, clip_id);
+    let _ = writeln!(xml, r#"                    <CurrentStart Value="0" />"#);
+    let _ = writeln!(xml, r#"                    <CurrentEnd Value="{}" />"#, beats);
+    let _ = writeln!(xml, r#"                    <Name Value="{}" />"#, name);
+    let _ = writeln!(xml, r#"                    <IsWarped Value="false" />"#);
+    let _ = writeln!(xml, "                    <SampleRef>");
+    let _ = writeln!(xml, "                      <FileRef>");
+    let _ = writeln!(xml, r#"                        <RelativePathType Value="1" />"#);
+    let _ = writeln!(xml, r#"                        <RelativePath Value="{}" />"#, escape(&track.relative_path));
+    let _ = writeln!(xml, r#"                        <Path Value="{}" />"#, escape(&track.absolute_path));
+    let _ = writeln!(xml, r#"                        <Type Value="1" />"#);
+    let _ = writeln!(xml, r#"                        <LivePackName Value="" />"#);
+    let _ = writeln!(xml, r#"                        <LivePackId Value="" />"#);
+    let _ = writeln!(xml, r#"                        <OriginalFileSize Value="{}" />"#, track.file_size);
+    let _ = writeln!(xml, r#"                        <OriginalCrc Value="0" />"#);
+    let _ = writeln!(xml, "                      </FileRef>");
+    let _ = writeln!(xml, r#"                      <DefaultDuration Value="{}" />"#, track.frames);
+    let _ = writeln!(xml, r#"                      <DefaultSampleRate Value="{}" />"#, track.sample_rate);
+    let _ = writeln!(xml, "                    </SampleRef>");
+    let _ = writeln!(xml, "                  </AudioClip>");
+    let _ = writeln!(xml, "                </Events>");
+    let _ = writeln!(xml, "              </ArrangerAutomation>");
+    let _ = writeln!(xml, "            </Sample>");
+    let _ = writeln!(xml, "          </MainSequencer>");
+    let _ = writeln!(xml, "        </DeviceChain>");
+    let _ = writeln!(xml, "      </AudioTrack>");
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
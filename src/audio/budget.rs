@@ -0,0 +1,89 @@
+use std::sync::{Condvar, Mutex};
+use std::time::Duration;
+
+/// Budget used when the system's memory can't be read.
+const FALLBACK_BUDGET: u64 = 1024 * 1024 * 1024;
+/// Share of the system's memory the decoded stems may take up together.
+const SYSTEM_SHARE: u64 = 4;
+
+/// Bytes a stem takes up while it's processed: `duration × rate × channels × 4`, covering
+/// the decoded samples plus the copy the pipeline works on.
+pub fn decoded_size(duration: Duration, sample_rate: u32, channels: u16) -> u64 {
+    (duration.as_secs_f64() * sample_rate as f64) as u64 * channels as u64 * 4
+}
+
+/// A semaphore weighted in bytes, held by each stem from before it's decoded until its
+/// outputs are written, so processing threads only run side by side while their stems fit
+/// in memory together.
+#[derive(Debug)]
+pub struct MemoryBudget {
+    capacity: u64,
+    used: Mutex<u64>,
+    freed: Condvar,
+}
+
+impl MemoryBudget {
+    pub fn new(capacity: u64) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            used: Mutex::new(0),
+            freed: Condvar::new(),
+        }
+    }
+
+    /// A quarter of the system's memory.
+    pub fn from_system() -> Self {
+        Self::new(system_memory().map_or(FALLBACK_BUDGET, |total| total / SYSTEM_SHARE))
+    }
+
+    pub fn capacity(&self) -> u64 {
+        self.capacity
+    }
+
+    pub fn in_use(&self) -> u64 {
+        *self.used.lock().unwrap()
+    }
+
+    /// Blocks until `bytes` fit in the budget. A request larger than the whole budget
+    /// waits for everything else to finish and then runs alone, rather than never.
+    pub fn acquire(&self, bytes: u64) -> BudgetPermit<'_> {
+        let bytes = bytes.min(self.capacity);
+        let mut used = self.used.lock().unwrap();
+        if *used + bytes > self.capacity {
+            tracing::info!(
+                "Memory budget throttling: {} of {} MB in use, waiting for {} MB",
+                *used / (1024 * 1024),
+                self.capacity / (1024 * 1024),
+                bytes / (1024 * 1024)
+            );
+            used = self
+                .freed
+                .wait_while(used, |used| *used + bytes > self.capacity)
+                .unwrap();
+        }
+        *used += bytes;
+        BudgetPermit { budget: self, bytes }
+    }
+}
+
+/// A share of a [`MemoryBudget`], given back when dropped.
+#[derive(Debug)]
+pub struct BudgetPermit<'a> {
+    budget: &'a MemoryBudget,
+    bytes: u64,
+}
+
+impl Drop for BudgetPermit<'_> {
+    fn drop(&mut self) {
+        *self.budget.used.lock().unwrap() -= self.bytes;
+        self.budget.freed.notify_all();
+    }
+}
+
+/// Total memory in bytes, from `/proc/meminfo` where there is one.
+fn system_memory() -> Option<u64> {
+    let meminfo = std::fs::read_to_string("/proc/meminfo").ok()?;
+    let line = meminfo.lines().find(|line| line.starts_with("MemTotal:"))?;
+    let kb: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kb * 1024)
+}
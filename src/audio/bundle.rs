@@ -0,0 +1,232 @@
+use anyhow::{anyhow, Context, Result};
+use hound::{SampleFormat, WavSpec, WavWriter};
+use std::fmt::Display;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use super::click::ClickDetection;
+use super::pipeline::{Audio, Stage};
+use super::reaper::StemSet;
+use super::track_map::TrackMap;
+use super::AudioProcessor;
+
+/// Gain on the band sum before the limiter, so a song with many stems isn't squashed.
+pub const BAND_HEADROOM_DB: f64 = -6.0;
+/// The band sum never goes above this, in dBFS.
+pub const LIMITER_CEILING_DB: f64 = -1.0;
+/// How quickly the limiter lets go after a peak.
+const LIMITER_RELEASE_SECS: f32 = 0.1;
+/// Name of the set list written next to the audio.
+pub const SET_LIST: &str = "Set List.txt";
+
+/// How each song is laid out in the bundle.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BundleFormat {
+    /// `01 Song.wav`: the click in the left channel and the band summed to mono in the right.
+    #[default]
+    LrSplit,
+    /// `01 Song - Click.wav` in mono and `01 Song - Band.wav` keeping the band in stereo.
+    StereoBand,
+}
+
+impl FromStr for BundleFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "lr-split" => Ok(Self::LrSplit),
+            "stereo-band" => Ok(Self::StereoBand),
+            _ => Err(format!("expected 'lr-split' or 'stereo-band', got '{}'", s)),
+        }
+    }
+}
+
+impl Display for BundleFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::LrSplit => "lr-split",
+            Self::StereoBand => "stereo-band",
+        })
+    }
+}
+
+/// Writes `songs`, in set order, into the flat folder `out` as `format` describes, along
+/// with a [`SET_LIST`]. The click of each is the one its `tracks.json` marks, else the one
+/// `detection` tells by name. Returns the files written.
+pub fn write_bundle(songs: &[PathBuf], out: &Path, format: BundleFormat, detection: &ClickDetection) -> Result<Vec<PathBuf>> {
+    if songs.is_empty() {
+        return Err(anyhow!("No songs to bundle"));
+    }
+    fs::create_dir_all(out)?;
+    let width = songs.len().to_string().len().max(2);
+
+    let mut written = Vec::new();
+    let mut set_list = String::new();
+    for (i, song_dir) in songs.iter().enumerate() {
+        let title = song_dir
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .ok_or_else(|| anyhow!("{:?} isn't a song folder", song_dir))?;
+        let prefix = format!("{:0width$} {}", i + 1, title, width = width);
+        tracing::info!("Bundling {}", prefix);
+
+        let files = match format {
+            BundleFormat::LrSplit => {
+                let (click, band) = load_song(song_dir, StemSet::Mono, detection)?;
+                let band = band_mix(&band, 1);
+                let frames = click.frames().max(band.frames());
+                let mut split = Audio {
+                    sample_rate: click.sample_rate,
+                    channels: vec![click.channels[0].clone(), band.channels[0].clone()],
+                };
+                for channel in &mut split.channels {
+                    channel.resize(frames, 0.0);
+                }
+                vec![(format!("{}.wav", prefix), split)]
+            }
+            BundleFormat::StereoBand => {
+                let (click, _) = load_song(song_dir, StemSet::Mono, detection)?;
+                let (_, band) = load_song(song_dir, StemSet::Stereo, detection)?;
+                let click = Audio {
+                    sample_rate: click.sample_rate,
+                    channels: vec![click.channels[0].clone()],
+                };
+                vec![
+                    (format!("{} - Click.wav", prefix), click),
+                    (format!("{} - Band.wav", prefix), band_mix(&band, 2)),
+                ]
+            }
+        };
+        for (name, audio) in files {
+            let path = out.join(name);
+            write_wav(&path, &audio)?;
+            written.push(path);
+        }
+        set_list.push_str(&prefix);
+        set_list.push('\n');
+    }
+
+    let set_list_path = out.join(SET_LIST);
+    fs::write(&set_list_path, set_list)?;
+    written.push(set_list_path);
+    Ok(written)
+}
+
+/// The click and the other stems of a processed song, from the `set` folder. Every stem is
+/// padded to the click's length when processed, so they all line up.
+fn load_song(song_dir: &Path, set: StemSet, detection: &ClickDetection) -> Result<(Audio, Vec<Audio>)> {
+    let dir = song_dir.join("STEMS").join(set.dir_name());
+    let mut paths: Vec<PathBuf> = fs::read_dir(&dir)
+        .with_context(|| format!("No STEMS/{} folder in {:?}", set.dir_name(), song_dir))?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.extension().is_some_and(|e| e == "wav"))
+        // The full mix `--include-full-mix` keeps already holds every part of the band
+        .filter(|p| !AudioProcessor::is_full_mix(p))
+        .collect();
+    paths.sort();
+
+    let click_path = find_click(song_dir, set, &paths, detection)?.ok_or_else(|| anyhow!("No click in {:?}", dir))?;
+    let click = read_wav(&click_path)?;
+    let band = paths
+        .iter()
+        .filter(|p| **p != click_path)
+        .map(|p| read_wav(p))
+        .collect::<Result<Vec<_>>>()?;
+    if let Some(other) = band.iter().find(|a| a.sample_rate != click.sample_rate) {
+        return Err(anyhow!(
+            "The stems in {:?} mix {} Hz and {} Hz",
+            dir,
+            click.sample_rate,
+            other.sample_rate
+        ));
+    }
+    Ok((click, band))
+}
+
+/// The click among the stems at `paths`: the one the song's `tracks.json` marks, else the
+/// first `detection` takes for the click by its name.
+fn find_click(song_dir: &Path, set: StemSet, paths: &[PathBuf], detection: &ClickDetection) -> Result<Option<PathBuf>> {
+    if let Some(entry) = TrackMap::load(song_dir)?.and_then(|map| map.tracks.into_iter().find(|track| track.is_click)) {
+        let path = song_dir.join(match set {
+            StemSet::Mono => entry.mono_file,
+            StemSet::Stereo => entry.stereo_file,
+        });
+        if paths.contains(&path) {
+            return Ok(Some(path));
+        }
+    }
+    Ok(paths.iter().find(|path| detection.is_click(&stem_name(path))).cloned())
+}
+
+/// The track name of the stem at `path`: `Click` for `WAV MONO/01 Click_mono.wav`.
+fn stem_name(path: &Path) -> String {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    AudioProcessor::strip_stem_number(stem.trim_end_matches("_mono")).to_string()
+}
+
+/// Sums `stems` into `channels` channels: a stem with fewer channels is spread over all
+/// of them, one with more is folded down. Then [`BAND_HEADROOM_DB`] of gain and the limiter.
+pub fn band_mix(stems: &[Audio], channels: usize) -> Audio {
+    let sample_rate = stems.first().map_or(44100, |s| s.sample_rate);
+    let frames = stems.iter().map(Audio::frames).max().unwrap_or(0);
+    let mut mix = Audio {
+        sample_rate,
+        channels: vec![vec![0.0; frames]; channels],
+    };
+    for stem in stems {
+        let mut stem = stem.clone();
+        if stem.channels.len() != channels {
+            Stage::Mono.apply(&mut stem);
+        }
+        for (c, out) in mix.channels.iter_mut().enumerate() {
+            let source = &stem.channels[c.min(stem.channels.len() - 1)];
+            for (out, sample) in out.iter_mut().zip(source) {
+                *out += sample;
+            }
+        }
+    }
+    Stage::Gain { db: BAND_HEADROOM_DB }.apply(&mut mix);
+    limit(&mut mix, LIMITER_CEILING_DB);
+    mix
+}
+
+/// A peak limiter with instant attack, so no sample ends up above `ceiling_db`, and an
+/// exponential release. The channels share one gain so the stereo image doesn't move.
+pub fn limit(audio: &mut Audio, ceiling_db: f64) {
+    let ceiling = 10f64.powf(ceiling_db / 20.0) as f32;
+    let release = (-1.0 / (LIMITER_RELEASE_SECS * audio.sample_rate as f32)).exp();
+    let mut gain: f32 = 1.0;
+    for i in 0..audio.frames() {
+        let peak = audio.channels.iter().fold(0.0f32, |peak, c| peak.max(c[i].abs()));
+        let needed = if peak > ceiling { ceiling / peak } else { 1.0 };
+        // Recover towards unity, but never above what this sample allows
+        gain = (1.0 - (1.0 - gain) * release).min(needed);
+        for channel in &mut audio.channels {
+            channel[i] *= gain;
+        }
+    }
+}
+
+fn read_wav(path: &Path) -> Result<Audio> {
+    let mut reader = hound::WavReader::open(path).with_context(|| format!("Unable to read {:?}", path))?;
+    let spec = reader.spec();
+    let samples: Vec<i16> = reader.samples::<i16>().collect::<Result<_, _>>()?;
+    Ok(Audio::from_interleaved(&samples, spec.channels as usize, spec.sample_rate))
+}
+
+fn write_wav(path: &Path, audio: &Audio) -> Result<()> {
+    let spec = WavSpec {
+        channels: audio.channels.len() as u16,
+        sample_rate: audio.sample_rate,
+        bits_per_sample: 16,
+        sample_format: SampleFormat::Int,
+    };
+    let mut writer = WavWriter::create(path, spec)?;
+    for sample in audio.to_interleaved() {
+        writer.write_sample(sample)?;
+    }
+    writer.finalize()?;
+    Ok(())
+}
@@ -0,0 +1,139 @@
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+use crate::audio::AudioProcessor;
+
+/// What the click track is called on the storefronts, lowercased. The site names it in the
+/// storefront's language, so a song from karaoke-version.de has a "Klick" instead.
+pub const DEFAULT_CLICK_PATTERNS: &[&str] = &[
+    "click",
+    "klick",
+    "clic",
+    "claqueta",
+    "métronome",
+    "metronome",
+    "metrónomo",
+    "metronomo",
+];
+
+/// How the click is told from the other stems, from the `[click]` config and
+/// `--click-track`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct ClickDetection {
+    /// A track whose name contains one of these, ignoring case, is the click.
+    pub patterns: Vec<String>,
+    /// The track to take as the click whatever it's called, instead of the patterns.
+    pub track: Option<String>,
+}
+
+impl Default for ClickDetection {
+    fn default() -> Self {
+        Self {
+            patterns: DEFAULT_CLICK_PATTERNS.iter().map(|p| p.to_string()).collect(),
+            track: None,
+        }
+    }
+}
+
+impl ClickDetection {
+    /// With `track`, from `--click-track`, in place of the configured one.
+    pub fn with_track(self, track: Option<String>) -> Self {
+        Self {
+            track: track.or(self.track),
+            ..self
+        }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.patterns.iter().any(|p| p.trim().is_empty()) {
+            return Err(anyhow!("click.patterns can't have an empty pattern, which would match every track"));
+        }
+        Ok(())
+    }
+
+    /// Whether the track named `name` is the click: the one `track` names, else one
+    /// matching a pattern.
+    pub fn is_click(&self, name: &str) -> bool {
+        match &self.track {
+            Some(track) => AudioProcessor::same_track_name(track, name),
+            None => {
+                let name = name.to_lowercase();
+                self.patterns.iter().any(|p| name.contains(&p.to_lowercase()))
+            }
+        }
+    }
+}
+
+/// How the track the others are lined up to was chosen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClickMatch {
+    /// `--click-track` named it.
+    Named,
+    /// Its name matched a click pattern.
+    Pattern,
+    /// No click was downloaded; the full mix stands in for it.
+    FullMix,
+    /// Nothing was named like a click, so the track first in the mixer was taken.
+    MixerOrder,
+    /// No click could be told; the stems aren't padded.
+    None,
+}
+
+impl ClickMatch {
+    /// Whether the reference is taken to be a click rather than the full mix or nothing.
+    pub fn is_click(self) -> bool {
+        matches!(self, Self::Named | Self::Pattern | Self::MixerOrder)
+    }
+}
+
+/// A song's downloads, split into the reference the other stems are padded to and the rest.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SongTracks {
+    /// The click, or the full mix standing in for it. With [`ClickMatch::None`], just the
+    /// first track, which nothing is padded to.
+    pub reference: PathBuf,
+    pub others: Vec<PathBuf>,
+    pub click: ClickMatch,
+}
+
+/// Picks the click among the MP3s at `paths`: the `--click-track`, else the first named
+/// like a click, else the full mix, else the track first in `mixer_names` (the mixer's
+/// order). Only a `--click-track` that names none of them is an error.
+pub fn find(mut paths: Vec<PathBuf>, detection: &ClickDetection, mixer_names: &[String]) -> Result<SongTracks> {
+    paths.sort();
+    let names: Vec<String> = paths.iter().map(|path| track_name(path)).collect();
+    let found = match &detection.track {
+        Some(track) => {
+            let index = names.iter().position(|name| detection.is_click(name)).ok_or_else(|| {
+                anyhow!("--click-track '{}' names none of the downloaded tracks: {}", track, names.join(", "))
+            })?;
+            Some((index, ClickMatch::Named))
+        }
+        None => names
+            .iter()
+            .position(|name| detection.is_click(name))
+            .map(|index| (index, ClickMatch::Pattern))
+            .or_else(|| paths.iter().position(|path| AudioProcessor::is_full_mix(path)).map(|index| (index, ClickMatch::FullMix)))
+            .or_else(|| {
+                let first = mixer_names.first()?;
+                let index = names.iter().position(|name| AudioProcessor::same_track_name(first, name))?;
+                Some((index, ClickMatch::MixerOrder))
+            }),
+    };
+    if paths.is_empty() {
+        return Err(anyhow!("No downloaded tracks to process"));
+    }
+    let (index, click) = found.unwrap_or((0, ClickMatch::None));
+    let reference = paths.remove(index);
+    Ok(SongTracks {
+        reference,
+        others: paths,
+        click,
+    })
+}
+
+fn track_name(path: &Path) -> String {
+    AudioProcessor::normalize_track_name(&path.file_name().unwrap_or_default().to_string_lossy())
+}
@@ -0,0 +1,184 @@
+use anyhow::Result;
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use super::reaper::Rgb;
+use super::tempo::TimeSignature;
+
+/// DAWproject format version written.
+pub const VERSION: &str = "1.0";
+/// Folder inside the container embedded audio is stored in.
+const AUDIO_DIR: &str = "audio";
+
+/// One mono stem placed at the start of the arrangement.
+#[derive(Debug, Clone)]
+pub struct DawTrack {
+    pub name: String,
+    pub color: Rgb,
+    /// The WAV on disk, copied into the container when embedding.
+    pub source: PathBuf,
+    /// Path of the WAV relative to the folder the `.dawproject` is saved in, with `/`
+    /// separators; used when the audio isn't embedded.
+    pub relative_path: String,
+    pub sample_rate: u32,
+    pub frames: u32,
+    /// -1.0 (hard left) to 1.0 (hard right).
+    pub pan: f64,
+    /// Linear gain, 1.0 being unity.
+    pub volume: f64,
+}
+
+impl DawTrack {
+    /// Where `project.xml` points for the track's audio.
+    fn file_path(&self, embed: bool) -> String {
+        if embed {
+            format!("{}/{}", AUDIO_DIR, self.source.file_name().unwrap_or_default().to_string_lossy())
+        } else {
+            self.relative_path.clone()
+        }
+    }
+}
+
+/// Writes the zip container: `project.xml`, `metadata.xml` and, with `embed`, the stems.
+/// WAVs are stored rather than deflated; they barely compress and DAWs stream them faster.
+pub fn write_dawproject(
+    path: &Path,
+    title: &str,
+    tracks: &[DawTrack],
+    tempo: f64,
+    signature: TimeSignature,
+    embed: bool,
+) -> Result<()> {
+    let mut zip = ZipWriter::new(File::create(path)?);
+    let deflated = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    zip.start_file("project.xml", deflated)?;
+    zip.write_all(project_xml(tracks, tempo, signature, embed).as_bytes())?;
+    zip.start_file("metadata.xml", deflated)?;
+    zip.write_all(metadata_xml(title).as_bytes())?;
+    if embed {
+        let stored = SimpleFileOptions::default()
+            .compression_method(CompressionMethod::Stored)
+            .large_file(true);
+        for track in tracks {
+            zip.start_file(track.file_path(true), stored)?;
+            io::copy(&mut File::open(&track.source)?, &mut zip)?;
+        }
+    }
+    zip.finish()?;
+    tracing::debug!("Wrote {:?}", path);
+    Ok(())
+}
+
+/// The project with one audio track per stem and one unwarped clip on each, timed in
+/// seconds so the clips don't depend on the tempo being right.
+pub fn project_xml(tracks: &[DawTrack], tempo: f64, signature: TimeSignature, embed: bool) -> String {
+    // Ids only need to be unique within the document
+    let mut next_id = 0;
+    let mut id = || {
+        next_id += 1;
+        format!("id{}", next_id - 1)
+    };
+
+    let mut xml = String::new();
+    let _ = writeln!(xml, r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>"#);
+    let _ = writeln!(xml, r#"<Project version="{}">"#, VERSION);
+    let _ = writeln!(
+        xml,
+        r#"  <Application name="kv-downloader" version="{}"/>"#,
+        env!("CARGO_PKG_VERSION")
+    );
+    let _ = writeln!(xml, "  <Transport>");
+    let _ = writeln!(xml, r#"    <Tempo max="666" min="20" unit="bpm" value="{}" id="{}" name="Tempo"/>"#, tempo, id());
+    let _ = writeln!(
+        xml,
+        r#"    <TimeSignature denominator="{}" numerator="{}" id="{}"/>"#,
+        signature.denominator,
+        signature.numerator,
+        id()
+    );
+    let _ = writeln!(xml, "  </Transport>");
+
+    let track_ids: Vec<String> = tracks.iter().map(|_| id()).collect();
+    let _ = writeln!(xml, "  <Structure>");
+    for (track, track_id) in tracks.iter().zip(&track_ids) {
+        let name = escape(&track.name);
+        let _ = writeln!(
+            xml,
+            r#"    <Track contentType="audio" loaded="true" id="{}" name="{}" color="{}">"#,
+            track_id, name, track.color
+        );
+        let _ = writeln!(xml, r#"      <Channel audioChannels="1" role="regular" solo="false" id="{}">"#, id());
+        // Pan comes before Volume in the schema. DAWproject pans from 0 (left) to 1 (right)
+        let _ = writeln!(
+            xml,
+            r#"        <Pan max="1" min="0" unit="normalized" value="{}" id="{}" name="Pan"/>"#,
+            (track.pan + 1.0) / 2.0,
+            id()
+        );
+        let _ = writeln!(
+            xml,
+            r#"        <Volume max="2" min="0" unit="linear" value="{}" id="{}" name="Volume"/>"#,
+            track.volume,
+            id()
+        );
+        let _ = writeln!(xml, "      </Channel>");
+        let _ = writeln!(xml, "    </Track>");
+    }
+    let _ = writeln!(xml, "  </Structure>");
+
+    let _ = writeln!(xml, r#"  <Arrangement id="{}">"#, id());
+    let _ = writeln!(xml, r#"    <Lanes timeUnit="seconds" id="{}">"#, id());
+    for (track, track_id) in tracks.iter().zip(&track_ids) {
+        let seconds = track.frames as f64 / track.sample_rate as f64;
+        let _ = writeln!(xml, r#"      <Lanes track="{}" id="{}">"#, track_id, id());
+        let _ = writeln!(xml, r#"        <Clips id="{}">"#, id());
+        let _ = writeln!(
+            xml,
+            r#"          <Clip time="0" duration="{}" playStart="0" name="{}">"#,
+            seconds,
+            escape(&track.name)
+        );
+        let _ = writeln!(
+            xml,
+            r#"            <Audio channels="1" duration="{}" sampleRate="{}" timeUnit="seconds" id="{}">"#,
+            seconds,
+            track.sample_rate,
+            id()
+        );
+        let _ = writeln!(
+            xml,
+            r#"              <File path="{}" external="{}"/>"#,
+            escape(&track.file_path(embed)),
+            !embed
+        );
+        let _ = writeln!(xml, "            </Audio>");
+        let _ = writeln!(xml, "          </Clip>");
+        let _ = writeln!(xml, "        </Clips>");
+        let _ = writeln!(xml, "      </Lanes>");
+    }
+    let _ = writeln!(xml, "    </Lanes>");
+    let _ = writeln!(xml, "  </Arrangement>");
+    let _ = writeln!(xml, "</Project>");
+    xml
+}
+
+pub fn metadata_xml(title: &str) -> String {
+    let mut xml = String::new();
+    let _ = writeln!(xml, r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>"#);
+    let _ = writeln!(xml, "<MetaData>");
+    let _ = writeln!(xml, "  <Title>{}</Title>", escape(title));
+    let _ = writeln!(xml, "</MetaData>");
+    xml
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,87 @@
+use anyhow::Result;
+use std::path::Path;
+use std::time::Duration;
+
+use super::verify::{self, Window, BLOCK};
+
+/// Length of each compared window: a few beats of the click, yet short enough that a
+/// drifting stem still lines up across it.
+pub const DRIFT_WINDOW: Duration = verify::WINDOW;
+/// Furthest a stem's onsets are looked for either side of the click's.
+pub const MAX_LAG: Duration = Duration::from_millis(300);
+/// How far the offset may move between the first window and the last before the stem
+/// counts as time-drifted.
+pub const DRIFT_THRESHOLD: Duration = Duration::from_millis(30);
+/// Onset correlation a window needs for its offset to be trusted.
+const MIN_ONSET_CORRELATION: f64 = 0.3;
+/// Onsets are spread over this many blocks, so ones a little apart still overlap.
+const SMOOTHING: usize = 5;
+/// Where the windows start, as fractions of the song: its start, middle and end.
+const POSITIONS: [f64; 3] = [0.1, 0.5, 0.9];
+
+/// Offsets of a stem's onsets against the click's at the start, middle and end of a song.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DriftCheck {
+    /// Milliseconds, positive where the stem is late.
+    pub offsets_ms: [f64; 3],
+}
+
+impl DriftCheck {
+    /// How far the offset moved from the first window to the last.
+    pub fn drift_ms(&self) -> f64 {
+        self.offsets_ms[2] - self.offsets_ms[0]
+    }
+
+    /// Whether the offset grows steadily one way, by more than [`DRIFT_THRESHOLD`]. A stem
+    /// that's merely late by the same amount throughout isn't drifting.
+    pub fn is_drifted(&self) -> bool {
+        let [start, middle, end] = self.offsets_ms;
+        let steady = (start <= middle && middle <= end) || (start >= middle && middle >= end);
+        steady && self.drift_ms().abs() > DRIFT_THRESHOLD.as_secs_f64() * 1000.0
+    }
+}
+
+/// Rises of the envelope, which mark where notes and hits start, spread over
+/// [`SMOOTHING`] blocks.
+fn onsets(window: &Window) -> Vec<f64> {
+    let rises: Vec<f64> = window.envelope().windows(2).map(|pair| (pair[1] - pair[0]).max(0.0)).collect();
+    rises.windows(SMOOTHING).map(|spread| spread.iter().sum()).collect()
+}
+
+/// Measures `stem` against `click`, which is `length` long. `padding` is the silence the
+/// click has in front that the stem lacks, as with a download not yet padded.
+///
+/// Returns `None` when a window can't be placed or its onsets don't line up well enough
+/// to trust, which says nothing about drift: a stem that's silent or sustained at the
+/// start of the song is never flagged.
+pub fn measure(click: &Path, stem: &Path, padding: Duration, length: Duration) -> Result<Option<DriftCheck>> {
+    let window = DRIFT_WINDOW.min(length);
+    let span = length.saturating_sub(window);
+    let max_lag = (MAX_LAG.as_secs_f64() / BLOCK.as_secs_f64()).round() as usize;
+    let block_ms = BLOCK.as_secs_f64() * 1000.0;
+
+    let mut offsets_ms = [0.0; 3];
+    for (offset, position) in offsets_ms.iter_mut().zip(POSITIONS) {
+        let at = span.mul_f64(position);
+        // The stem's window reaches MAX_LAG either side of the click's
+        let Some(stem_at) = at.checked_sub(padding + MAX_LAG) else {
+            return Ok(None);
+        };
+        let reference = onsets(&verify::decode_window(click, at, window)?);
+        let candidate = onsets(&verify::decode_window(stem, stem_at, window + MAX_LAG * 2)?);
+
+        let best = (0..=max_lag * 2)
+            .filter_map(|lag| {
+                let slice = candidate.get(lag..lag + reference.len())?;
+                Some((lag, verify::correlation(&reference, slice)?))
+            })
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+        match best {
+            Some((lag, correlation)) if correlation >= MIN_ONSET_CORRELATION => {
+                *offset = (lag as f64 - max_lag as f64) * block_ms;
+            }
+            _ => return Ok(None),
+        }
+    }
+    Ok(Some(DriftCheck { offsets_ms }))
+}
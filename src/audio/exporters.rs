@@ -0,0 +1,137 @@
+use anyhow::{anyhow, Result};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::{mpsc, Arc};
+use std::time::Duration;
+
+use super::ProcessReport;
+
+pub const DEFAULT_EXPORTER_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// The DAWs a session is generated for, picked with `--daw`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DawTargets {
+    pub reaper: bool,
+    pub ableton: bool,
+    /// The open `.dawproject` container, for Bitwig, Studio One and others.
+    pub dawproject: bool,
+}
+
+impl Default for DawTargets {
+    fn default() -> Self {
+        Self {
+            reaper: true,
+            ableton: false,
+            dawproject: false,
+        }
+    }
+}
+
+impl FromStr for DawTargets {
+    type Err = String;
+
+    /// Parses a comma-separated list of `reaper`, `ableton`, `dawproject` and `all`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut targets = Self {
+            reaper: false,
+            ableton: false,
+            dawproject: false,
+        };
+        for daw in s.split(',') {
+            match daw.trim().to_lowercase().as_str() {
+                "reaper" => targets.reaper = true,
+                "ableton" => targets.ableton = true,
+                "dawproject" => targets.dawproject = true,
+                "all" => {
+                    targets.reaper = true;
+                    targets.ableton = true;
+                    targets.dawproject = true;
+                }
+                _ => return Err(format!("expected reaper, ableton, dawproject or all, got '{}'", daw.trim())),
+            }
+        }
+        Ok(targets)
+    }
+}
+
+/// Everything an exporter gets to work with once the audio is finished.
+#[derive(Debug, Clone)]
+pub struct ExportContext {
+    pub mt_project_dir: PathBuf,
+    pub stems_dir: PathBuf,
+    pub mono_paths: Vec<PathBuf>,
+}
+
+pub type ExportFn = dyn Fn(&ExportContext) -> Result<()> + Send + Sync;
+
+/// A project/interchange file generator that runs after the stems are written.
+#[derive(Clone)]
+pub struct Exporter {
+    pub name: &'static str,
+    run: Arc<ExportFn>,
+    /// Whether a failure only gets logged, rather than warned about in the report.
+    advisory: bool,
+}
+
+impl Exporter {
+    pub fn new(name: &'static str, run: impl Fn(&ExportContext) -> Result<()> + Send + Sync + 'static) -> Self {
+        Self {
+            name,
+            run: Arc::new(run),
+            advisory: false,
+        }
+    }
+
+    /// An exporter of an extra that songs used to pass without, such as the MIDI tempo map:
+    /// its failure is listed in `failed_exporters` but leaves the song clean.
+    pub fn advisory(name: &'static str, run: impl Fn(&ExportContext) -> Result<()> + Send + Sync + 'static) -> Self {
+        Self {
+            advisory: true,
+            ..Self::new(name, run)
+        }
+    }
+}
+
+/// Runs each exporter on its own thread with a time budget, so one that errors, panics or
+/// hangs can't take the finished song down with it. Failures are recorded in the report,
+/// as warnings unless the exporter is advisory; with `strict` the first one is returned as
+/// an error instead.
+pub fn run_exporters(
+    exporters: &[Exporter],
+    ctx: &ExportContext,
+    budget: Duration,
+    strict: bool,
+    report: &mut ProcessReport,
+) -> Result<()> {
+    for exporter in exporters {
+        let (tx, rx) = mpsc::channel();
+        let run = exporter.run.clone();
+        let thread_ctx = ctx.clone();
+        // Spans don't follow work onto other threads by themselves; carry the song's along
+        let span = tracing::info_span!("exporter", exporter = exporter.name);
+        std::thread::spawn(move || {
+            let _ = tx.send(span.in_scope(|| run(&thread_ctx)));
+        });
+
+        let outcome = match rx.recv_timeout(budget) {
+            Ok(result) => result,
+            Err(mpsc::RecvTimeoutError::Timeout) => Err(anyhow!("timed out after {:?}", budget)),
+            Err(mpsc::RecvTimeoutError::Disconnected) => Err(anyhow!("exporter panicked")),
+        };
+
+        match outcome {
+            Ok(()) => tracing::debug!("{} export finished", exporter.name),
+            Err(e) if strict => return Err(anyhow!("{} export failed: {}", exporter.name, e)),
+            Err(e) if exporter.advisory => {
+                tracing::warn!("{} export failed: {}", exporter.name, e);
+                report.failed_exporters.push(exporter.name.to_string());
+            }
+            Err(e) => {
+                tracing::warn!("{} export failed: {}", exporter.name, e);
+                report.warnings.push(format!("{} export failed: {}", exporter.name, e));
+                report.failed_exporters.push(exporter.name.to_string());
+            }
+        }
+    }
+    Ok(())
+}
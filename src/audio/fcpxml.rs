@@ -0,0 +1,133 @@
+use anyhow::Result;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+/// FCPXML version written. 1.8 still puts the media URL on the asset itself, which is what
+/// Resolve and the other importers outside Final Cut handle best.
+pub const VERSION: &str = "1.8";
+/// Timeline frame rate. The stems carry no video, but a sequence needs a format and its
+/// length has to land on a frame boundary.
+const FRAME_RATE: u64 = 25;
+
+/// One mono stem, placed at the start of the timeline.
+#[derive(Debug, Clone)]
+pub struct FcpClip {
+    pub name: String,
+    /// Path of the WAV relative to the folder the `.fcpxml` is saved in, with `/` separators.
+    pub relative_path: String,
+    pub sample_rate: u32,
+    pub frames: u32,
+}
+
+pub fn write_fcpxml(path: &Path, title: &str, clips: &[FcpClip]) -> Result<()> {
+    fs::write(path, fcpxml(title, clips))?;
+    tracing::debug!("Wrote {:?}", path);
+    Ok(())
+}
+
+/// A project holding every clip at timeline position zero, each on its own lane under a
+/// gap as long as the longest stem, so none of them can be shifted by the primary storyline.
+pub fn fcpxml(title: &str, clips: &[FcpClip]) -> String {
+    let title = escape(title);
+    // Audio may be sample accurate, but the gap holding it has to be whole frames
+    let frames = clips
+        .iter()
+        .map(|c| (c.frames as u64 * FRAME_RATE).div_ceil(c.sample_rate as u64))
+        .max()
+        .unwrap_or(0);
+    let length = format!("{}/{}s", frames, FRAME_RATE);
+    let sequence_rate = clips.first().map(|c| audio_rate(c.sample_rate)).unwrap_or("48k");
+
+    let mut xml = String::new();
+    let _ = writeln!(xml, r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    let _ = writeln!(xml, "<!DOCTYPE fcpxml>");
+    let _ = writeln!(xml, r#"<fcpxml version="{}">"#, VERSION);
+    let _ = writeln!(xml, "  <resources>");
+    let _ = writeln!(
+        xml,
+        r#"    <format id="r0" name="FFVideoFormat1080p25" frameDuration="1/{}s" width="1920" height="1080" />"#,
+        FRAME_RATE
+    );
+    for (i, clip) in clips.iter().enumerate() {
+        let _ = writeln!(
+            xml,
+            r#"    <asset id="r{}" name="{}" src="{}" start="0s" duration="{}" hasAudio="1" audioSources="1" audioChannels="1" audioRate="{}" />"#,
+            i + 1,
+            escape(&clip.name),
+            escape(&url_encode(&clip.relative_path)),
+            clip_duration(clip),
+            clip.sample_rate
+        );
+    }
+    let _ = writeln!(xml, "  </resources>");
+    let _ = writeln!(xml, "  <library>");
+    let _ = writeln!(xml, r#"    <event name="{}">"#, title);
+    let _ = writeln!(xml, r#"      <project name="{}">"#, title);
+    let _ = writeln!(
+        xml,
+        r#"        <sequence format="r0" duration="{}" tcStart="0s" tcFormat="NDF" audioLayout="stereo" audioRate="{}">"#,
+        length, sequence_rate
+    );
+    let _ = writeln!(xml, "          <spine>");
+    let _ = writeln!(xml, r#"            <gap name="Gap" offset="0s" start="0s" duration="{}">"#, length);
+    for (i, clip) in clips.iter().enumerate() {
+        let _ = writeln!(
+            xml,
+            r#"              <asset-clip ref="r{}" lane="{}" offset="0s" name="{}" start="0s" duration="{}" audioRole="dialogue" />"#,
+            i + 1,
+            -(i as i64 + 1),
+            escape(&clip.name),
+            clip_duration(clip)
+        );
+    }
+    let _ = writeln!(xml, "            </gap>");
+    let _ = writeln!(xml, "          </spine>");
+    let _ = writeln!(xml, "        </sequence>");
+    let _ = writeln!(xml, "      </project>");
+    let _ = writeln!(xml, "    </event>");
+    let _ = writeln!(xml, "  </library>");
+    let _ = writeln!(xml, "</fcpxml>");
+    xml
+}
+
+/// Exact length as a rational number of seconds, e.g. `88200/44100s`.
+fn clip_duration(clip: &FcpClip) -> String {
+    format!("{}/{}s", clip.frames, clip.sample_rate)
+}
+
+/// The sequence audio rates FCPXML allows, picking 48k for anything else.
+fn audio_rate(sample_rate: u32) -> &'static str {
+    match sample_rate {
+        32_000 => "32k",
+        44_100 => "44.1k",
+        88_200 => "88.2k",
+        96_000 => "96k",
+        176_400 => "176.4k",
+        192_000 => "192k",
+        _ => "48k",
+    }
+}
+
+/// Percent-encodes everything but unreserved characters and `/`, so stem names with
+/// spaces or punctuation still form a valid relative URL.
+fn url_encode(path: &str) -> String {
+    let mut encoded = String::new();
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => encoded.push(byte as char),
+            _ => {
+                let _ = write!(encoded, "%{:02X}", byte);
+            }
+        }
+    }
+    encoded
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,53 @@
+use anyhow::{anyhow, Result};
+use std::time::Duration;
+
+/// Largest `data` chunk a WAV's 32-bit RIFF size can describe, after the 36 bytes of
+/// header the size also counts.
+pub const WAV_MAX_DATA_BYTES: u64 = u32::MAX as u64 - 36;
+/// Largest delta between two events of a MIDI track, a 28-bit variable-length quantity.
+pub const MIDI_MAX_DELTA: u32 = 0x0FFF_FFFF;
+
+/// Whole frames in `duration` at `sample_rate`, counted in integers so long songs at high
+/// rates don't lose samples to floating point.
+pub fn frames_in(duration: Duration, sample_rate: u32) -> u64 {
+    (duration.as_nanos() * sample_rate as u128 / 1_000_000_000) as u64
+}
+
+/// Seconds of `frames` at `sample_rate`, as written into project files.
+pub fn frames_to_secs(frames: u64, sample_rate: u32) -> f64 {
+    frames as f64 / sample_rate as f64
+}
+
+/// Interleaved samples of silence that make up `duration`. Errors when the padded stem
+/// couldn't be written as a WAV at all.
+pub fn padding_samples(duration: Duration, sample_rate: u32, channels: u16, bits_per_sample: u16) -> Result<usize> {
+    let samples = frames_in(duration, sample_rate)
+        .checked_mul(channels as u64)
+        .ok_or_else(|| anyhow!("Padding of {:.1}s doesn't fit in memory", duration.as_secs_f64()))?;
+    check_wav_size(samples, bits_per_sample)?;
+    usize::try_from(samples).map_err(|_| anyhow!("Padding of {:.1}s doesn't fit in memory", duration.as_secs_f64()))
+}
+
+/// Errors when `samples` interleaved samples are more than one WAV file can hold.
+pub fn check_wav_size(samples: u64, bits_per_sample: u16) -> Result<()> {
+    let bytes = samples.saturating_mul(bits_per_sample.div_ceil(8) as u64);
+    if bytes > WAV_MAX_DATA_BYTES {
+        return Err(anyhow!(
+            "{} samples of {}-bit audio is {:.2} GB, more than the 4 GB a WAV file can hold",
+            samples,
+            bits_per_sample,
+            bytes as f64 / 1e9
+        ));
+    }
+    Ok(())
+}
+
+/// `secs` at `ticks_per_sec`, rounded. Errors instead of saturating when the result isn't a
+/// tick count a project or MIDI file can store.
+pub fn secs_to_ticks(secs: f64, ticks_per_sec: f64) -> Result<u32> {
+    let ticks = (secs * ticks_per_sec).round();
+    if !ticks.is_finite() || ticks < 0.0 || ticks > u32::MAX as f64 {
+        return Err(anyhow!("{:.3}s at {} ticks per second is out of range", secs, ticks_per_sec));
+    }
+    Ok(ticks as u32)
+}
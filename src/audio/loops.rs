@@ -0,0 +1,124 @@
+use anyhow::{anyhow, Result};
+use std::fmt::Display;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
+use super::riff;
+
+/// A loop region on the song's timeline, shared by every stem.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LoopRegion {
+    pub start: Duration,
+    pub end: Duration,
+}
+
+impl FromStr for LoopRegion {
+    type Err = String;
+
+    /// Parses `START-END`, where each side is `SS`, `MM:SS` or `HH:MM:SS`, with optional
+    /// fractional seconds (`01:00.5-01:30`).
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (start, end) = s
+            .split_once('-')
+            .ok_or_else(|| format!("expected START-END, got '{}'", s))?;
+        let start = parse_timestamp(start)?;
+        let end = parse_timestamp(end)?;
+        if end <= start {
+            return Err(format!("loop end must be after its start in '{}'", s));
+        }
+        Ok(Self { start, end })
+    }
+}
+
+impl Display for LoopRegion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:.3}s-{:.3}s", self.start.as_secs_f64(), self.end.as_secs_f64())
+    }
+}
+
+/// Parses `SS`, `MM:SS` or `HH:MM:SS`, with optional fractional seconds.
+pub fn parse_timestamp(s: &str) -> Result<Duration, String> {
+    let mut seconds = 0.0;
+    for part in s.trim().split(':') {
+        let value: f64 = part
+            .parse()
+            .map_err(|_| format!("invalid timestamp '{}'", s.trim()))?;
+        if value < 0.0 {
+            return Err(format!("invalid timestamp '{}'", s.trim()));
+        }
+        seconds = seconds * 60.0 + value;
+    }
+    Ok(Duration::from_secs_f64(seconds))
+}
+
+impl LoopRegion {
+    pub fn validate(&self, reference: Duration) -> Result<()> {
+        if self.end > reference {
+            return Err(anyhow!(
+                "Loop {} ends after the song, which is only {:.3}s long",
+                self,
+                reference.as_secs_f64()
+            ));
+        }
+        Ok(())
+    }
+
+    /// Start and (inclusive) end sample frames at the given rate.
+    pub fn sample_positions(&self, sample_rate: u32) -> Result<(u32, u32)> {
+        let to_frames = |d: Duration| {
+            let frames = (d.as_secs_f64() * sample_rate as f64).round();
+            u32::try_from(frames as u64).map_err(|_| anyhow!("Loop position out of range for a WAV file"))
+        };
+        let start = to_frames(self.start)?;
+        let end = to_frames(self.end)?.saturating_sub(1).max(start);
+        Ok((start, end))
+    }
+}
+
+/// Builds a `smpl` chunk holding a single forward loop.
+pub fn smpl_chunk(sample_rate: u32, start: u32, end: u32) -> Vec<u8> {
+    let sample_period = (1_000_000_000f64 / sample_rate as f64).round() as u32;
+    let fields: [u32; 15] = [
+        0,             // manufacturer
+        0,             // product
+        sample_period, // nanoseconds per sample
+        60,            // MIDI unity note (middle C)
+        0,             // MIDI pitch fraction
+        0,             // SMPTE format
+        0,             // SMPTE offset
+        1,             // number of sample loops
+        0,             // sampler data size
+        0,             // cue point id
+        0,             // loop type: forward
+        start,
+        end,
+        0, // fraction
+        0, // play count: infinite
+    ];
+    fields.iter().flat_map(|f| f.to_le_bytes()).collect()
+}
+
+/// Reads back the (start, end) of the first loop in a `smpl` chunk.
+pub fn read_smpl_loop(path: &Path) -> Result<Option<(u32, u32)>> {
+    let Some(smpl) = riff::find_chunk(path, b"smpl")? else {
+        return Ok(None);
+    };
+    let field = |i: usize| -> Result<u32> {
+        let bytes = smpl
+            .get(i * 4..i * 4 + 4)
+            .ok_or_else(|| anyhow!("Truncated smpl chunk in {:?}", path))?;
+        Ok(u32::from_le_bytes(bytes.try_into()?))
+    };
+    if field(7)? == 0 {
+        return Ok(None);
+    }
+    Ok(Some((field(11)?, field(12)?)))
+}
+
+/// Writes the loop into a WAV's `smpl` chunk at positions matching that file's sample rate.
+pub fn write_loop(path: &Path, region: &LoopRegion) -> Result<()> {
+    let sample_rate = hound::WavReader::open(path)?.spec().sample_rate;
+    let (start, end) = region.sample_positions(sample_rate)?;
+    riff::replace_chunk(path, b"smpl", &smpl_chunk(sample_rate, start, end))
+}
@@ -0,0 +1,163 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use super::loops;
+use super::track_map::TrackMap;
+
+/// Names of the stems manifest, inside each song folder.
+pub const MANIFEST_CSV: &str = "stems.csv";
+pub const MANIFEST_JSON: &str = "stems.json";
+
+const CSV_HEADER: &str =
+    "track_name,mono_file,stereo_file,duration_secs,duration_samples,sample_rate,channels,is_click,padding_secs";
+
+/// One stem as written to disk.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    /// The mixer's name for the track, or the stem's file name without one.
+    pub track_name: String,
+    /// Outputs, relative to the song folder.
+    pub mono_file: String,
+    pub stereo_file: String,
+    /// Length of the stereo WAV, from its header.
+    pub duration_secs: f64,
+    pub duration_samples: u32,
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub is_click: bool,
+    /// Silence put in front of the stem to line it up with the click.
+    pub padding_secs: f64,
+    /// How far the stem drifts against the click over the song, when `--verify-drift`
+    /// flagged it as time-drifted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time_drift_ms: Option<f64>,
+    /// The `--loop` region as written into each file's `smpl` chunk, in that file's samples.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mono_loop: Option<LoopPoints>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stereo_loop: Option<LoopPoints>,
+}
+
+/// A loop's first and last sample frame, as a `smpl` chunk holds them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LoopPoints {
+    pub start_samples: u32,
+    pub end_samples: u32,
+}
+
+impl LoopPoints {
+    /// The loop in the `smpl` chunk of the WAV at `path`, if it has one.
+    pub fn read(path: &Path) -> Result<Option<Self>> {
+        let points = loops::read_smpl_loop(path).with_context(|| format!("Unable to read the loop of {:?}", path))?;
+        Ok(points.map(|(start_samples, end_samples)| Self {
+            start_samples,
+            end_samples,
+        }))
+    }
+}
+
+/// Contents of `stems.json` and `stems.csv`: the stems of a song, in mixer order.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct StemManifest {
+    pub stems: Vec<ManifestEntry>,
+}
+
+impl StemManifest {
+    /// Reads the WAV headers and loops of the stems in `track_map`. `padding` is keyed by the original
+    /// MP3 file name; stems missing from it weren't padded.
+    pub fn build(song_dir: &Path, track_map: &TrackMap, padding: &HashMap<String, Duration>) -> Result<Self> {
+        let mut stems = Vec::new();
+        for track in &track_map.tracks {
+            let stereo = song_dir.join(&track.stereo_file);
+            let reader = hound::WavReader::open(&stereo).with_context(|| format!("Unable to read {:?}", stereo))?;
+            let spec = reader.spec();
+            let samples = reader.duration();
+            let track_name = track.mixer_name.clone().unwrap_or_else(|| {
+                Path::new(&track.mono_file)
+                    .file_stem()
+                    .map(|s| s.to_string_lossy().trim_end_matches("_mono").to_string())
+                    .unwrap_or_default()
+            });
+            let padding = padding.get(&track.original_filename).copied().unwrap_or_default();
+            stems.push(ManifestEntry {
+                track_name,
+                mono_file: track.mono_file.clone(),
+                stereo_file: track.stereo_file.clone(),
+                duration_secs: round_ms(samples as f64 / spec.sample_rate as f64),
+                duration_samples: samples,
+                sample_rate: spec.sample_rate,
+                channels: spec.channels,
+                is_click: track.is_click,
+                padding_secs: round_ms(padding.as_secs_f64()),
+                time_drift_ms: None,
+                mono_loop: LoopPoints::read(&song_dir.join(&track.mono_file))?,
+                stereo_loop: LoopPoints::read(&stereo)?,
+            });
+        }
+        Ok(Self { stems })
+    }
+
+    pub fn load(song_dir: &Path) -> Result<Option<Self>> {
+        let path = song_dir.join(MANIFEST_JSON);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let data = fs::read_to_string(&path)?;
+        let manifest = serde_json::from_str(&data).with_context(|| format!("Failed to parse {:?}", path))?;
+        Ok(Some(manifest))
+    }
+
+    /// Flags the stems in `drifts`, keyed by the path of their stereo WAV, as time-drifted
+    /// by the milliseconds given.
+    pub fn flag_drift(&mut self, song_dir: &Path, drifts: &HashMap<PathBuf, f64>) {
+        for stem in &mut self.stems {
+            stem.time_drift_ms = drifts.get(&song_dir.join(&stem.stereo_file)).copied();
+        }
+    }
+
+    /// Writes both files, replacing any from an earlier run.
+    pub fn save(&self, song_dir: &Path) -> Result<()> {
+        let mut data = serde_json::to_string_pretty(self)?;
+        data.push('\n');
+        fs::write(song_dir.join(MANIFEST_JSON), data)?;
+        fs::write(song_dir.join(MANIFEST_CSV), self.to_csv())?;
+        Ok(())
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut csv = format!("{}\n", CSV_HEADER);
+        for stem in &self.stems {
+            let row = [
+                csv_field(&stem.track_name),
+                csv_field(&stem.mono_file),
+                csv_field(&stem.stereo_file),
+                stem.duration_secs.to_string(),
+                stem.duration_samples.to_string(),
+                stem.sample_rate.to_string(),
+                stem.channels.to_string(),
+                stem.is_click.to_string(),
+                stem.padding_secs.to_string(),
+            ];
+            csv.push_str(&row.join(","));
+            csv.push('\n');
+        }
+        csv
+    }
+}
+
+fn round_ms(secs: f64) -> f64 {
+    (secs * 1000.0).round() / 1000.0
+}
+
+/// Quotes a field holding a comma, quote or line break, doubling the quotes inside.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
@@ -0,0 +1,146 @@
+use anyhow::{anyhow, Result};
+use midly::num::{u15, u24, u28};
+use midly::{Format, Header, MetaMessage, Smf, Timing, TrackEvent, TrackEventKind};
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
+use super::limits;
+use super::tempo::{ClickTiming, TimeSignature};
+
+pub const TICKS_PER_QUARTER: u16 = 480;
+/// Longest beat the lead-in before the first barline is split into, so its tempo stays sane.
+const MAX_PICKUP_BEAT: f64 = 4.0;
+
+/// What the MIDI file does with the count-in bars of a song downloaded with `--count-in`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MidiCountIn {
+    /// Count-in bars are written as bars, with bar 1 marked after them.
+    #[default]
+    Include,
+    /// The grid starts at the song's first downbeat; the count-in is folded into the lead-in.
+    Skip,
+}
+
+impl FromStr for MidiCountIn {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "include" => Ok(Self::Include),
+            "skip" => Ok(Self::Skip),
+            _ => Err(format!("expected 'include' or 'skip', got '{}'", s)),
+        }
+    }
+}
+
+/// Everything the MIDI file is laid out from.
+#[derive(Debug, Clone, Copy)]
+pub struct MidiLayout {
+    pub timing: ClickTiming,
+    pub signature: TimeSignature,
+    pub count_in_bars: u32,
+    pub count_in: MidiCountIn,
+    /// Length of the stems.
+    pub length: Duration,
+}
+
+/// Writes a type-1 SMF with a tempo track and one empty instrument track, both as long as
+/// the stems. Anything before the first barline (silence before the first click, plus the
+/// count-in with [`MidiCountIn::Skip`]) becomes a lead-in of its own tempo, so the grid after
+/// it lines up with the click exactly while the file still starts where the stems start.
+pub fn write_midi_file(path: &Path, layout: &MidiLayout) -> Result<()> {
+    let MidiLayout {
+        timing,
+        signature,
+        count_in_bars,
+        count_in,
+        length,
+    } = *layout;
+    let ppq = TICKS_PER_QUARTER as u32;
+    let ticks_per_beat = ppq * 4 / signature.denominator as u32;
+    let beat_secs = timing.beat_length().as_secs_f64();
+
+    let grid_start = match count_in {
+        MidiCountIn::Include => timing.first_beat,
+        MidiCountIn::Skip => timing.downbeat(signature, count_in_bars),
+    };
+    if grid_start > length {
+        return Err(anyhow!("The click's first downbeat is past the end of the stems"));
+    }
+
+    let mut tempo_track: Vec<(u32, MetaMessage)> = vec![(0, MetaMessage::TrackName(b"Tempo"))];
+
+    let mut grid_tick = 0;
+    let lead_in = grid_start.as_secs_f64();
+    if lead_in >= 0.001 {
+        let beats = u8::try_from((lead_in / MAX_PICKUP_BEAT).ceil().max(1.0) as u64)
+            .map_err(|_| anyhow!("A lead-in of {:.1}s before the first click is too long for MIDI", lead_in))?;
+        tempo_track.push((0, time_signature(beats, 4)));
+        tempo_track.push((0, tempo(lead_in / beats as f64)?));
+        grid_tick = beats as u32 * ppq;
+    }
+
+    tempo_track.push((grid_tick, time_signature(signature.numerator, signature.denominator)));
+    tempo_track.push((grid_tick, tempo(beat_secs * 4.0 / signature.denominator as f64)?));
+    let bar_ticks = signature.numerator as u32 * ticks_per_beat;
+    match count_in {
+        MidiCountIn::Include if count_in_bars > 0 => {
+            tempo_track.push((grid_tick, MetaMessage::Marker(b"Count-in")));
+            tempo_track.push((grid_tick + count_in_bars * bar_ticks, MetaMessage::Marker(b"Bar 1")));
+        }
+        _ => tempo_track.push((grid_tick, MetaMessage::Marker(b"Bar 1"))),
+    }
+
+    let remaining = (length - grid_start).as_secs_f64();
+    let end_tick = limits::secs_to_ticks(remaining, ticks_per_beat as f64 / beat_secs)?
+        .checked_add(grid_tick)
+        .ok_or_else(|| anyhow!("The stems are too long for a MIDI file"))?;
+    if tempo_track.iter().any(|(tick, _)| *tick > end_tick) {
+        return Err(anyhow!("The song's first downbeat is past the end of the stems"));
+    }
+    tempo_track.push((end_tick, MetaMessage::EndOfTrack));
+
+    let instrument_track = vec![
+        (0, MetaMessage::TrackName(b"Stems")),
+        (end_tick, MetaMessage::EndOfTrack),
+    ];
+
+    let mut smf = Smf::new(Header::new(Format::Parallel, Timing::Metrical(u15::new(TICKS_PER_QUARTER))));
+    smf.tracks.push(to_deltas(tempo_track)?);
+    smf.tracks.push(to_deltas(instrument_track)?);
+    smf.save(path)?;
+    Ok(())
+}
+
+fn time_signature(numerator: u8, denominator: u8) -> MetaMessage<'static> {
+    MetaMessage::TimeSignature(numerator, denominator.trailing_zeros() as u8, 24, 8)
+}
+
+fn tempo(quarter_secs: f64) -> Result<MetaMessage<'static>> {
+    let micros = (quarter_secs * 1_000_000.0).round() as u32;
+    if micros == 0 || micros > u24::max_value().as_int() {
+        return Err(anyhow!("Tempo of {:.3}s per quarter note can't be stored in MIDI", quarter_secs));
+    }
+    Ok(MetaMessage::Tempo(u24::new(micros)))
+}
+
+/// Errors when two events are further apart than a delta can say, which `u28::new` would
+/// otherwise silently wrap.
+fn to_deltas(events: Vec<(u32, MetaMessage)>) -> Result<Vec<TrackEvent>> {
+    let mut last = 0;
+    events
+        .into_iter()
+        .map(|(tick, message)| {
+            let delta = tick - last;
+            if delta > limits::MIDI_MAX_DELTA {
+                return Err(anyhow!("{} ticks between two MIDI events is more than a delta can hold", delta));
+            }
+            last = tick;
+            Ok(TrackEvent {
+                delta: u28::new(delta),
+                kind: TrackEventKind::Meta(message),
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,101 @@
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+
+/// Where the click and the rest of the band sit in the generated sessions, from the `[mix]`
+/// config and the command line. By default everything is centered at unity gain and the
+/// click goes to the master like any other track.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct MonitorMix {
+    /// -1.0 (hard left) to 1.0 (hard right).
+    pub click_pan: f64,
+    /// Pan of every track but the click.
+    pub band_pan: f64,
+    /// Gain of the click track in dB.
+    pub click_volume_db: f64,
+    /// Hardware output, counted from 1, the click is sent to instead of the master.
+    pub click_output: Option<u32>,
+}
+
+/// Command-line overrides of the `[mix]` config.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MixOverrides {
+    /// Start from [`MonitorMix::legacy`] instead of the config's pans.
+    pub legacy_panning: bool,
+    pub click_pan: Option<f64>,
+    pub band_pan: Option<f64>,
+    pub click_volume_db: Option<f64>,
+    pub click_output: Option<u32>,
+}
+
+impl MonitorMix {
+    /// The in-ear split the sessions used to be hard-coded to: the click hard left and the
+    /// band hard right.
+    pub fn legacy() -> Self {
+        Self {
+            click_pan: -1.0,
+            band_pan: 1.0,
+            ..Default::default()
+        }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        for (name, pan) in [("click_pan", self.click_pan), ("band_pan", self.band_pan)] {
+            if !(-1.0..=1.0).contains(&pan) {
+                return Err(anyhow!("{} must be between -1 and 1, got {}", name, pan));
+            }
+        }
+        if !self.click_volume_db.is_finite() {
+            return Err(anyhow!("click_volume_db must be a number of dB"));
+        }
+        if self.click_output == Some(0) {
+            return Err(anyhow!("click_output counts from 1"));
+        }
+        Ok(())
+    }
+
+    /// Applies the command line's flags on top of the config.
+    pub fn with_overrides(self, overrides: MixOverrides) -> Result<Self> {
+        // The legacy split only moves the pans; the config's volume and output stay
+        let base = if overrides.legacy_panning {
+            let legacy = Self::legacy();
+            Self {
+                click_pan: legacy.click_pan,
+                band_pan: legacy.band_pan,
+                ..self
+            }
+        } else {
+            self
+        };
+        let mix = Self {
+            click_pan: overrides.click_pan.unwrap_or(base.click_pan),
+            band_pan: overrides.band_pan.unwrap_or(base.band_pan),
+            click_volume_db: overrides.click_volume_db.unwrap_or(base.click_volume_db),
+            click_output: overrides.click_output.or(base.click_output),
+        };
+        mix.validate()?;
+        Ok(mix)
+    }
+
+    pub fn pan(&self, is_click: bool) -> f64 {
+        if is_click {
+            self.click_pan
+        } else {
+            self.band_pan
+        }
+    }
+
+    /// Linear gain of a track, as both Reaper and Live store it.
+    pub fn volume(&self, is_click: bool) -> f64 {
+        if is_click {
+            10f64.powf(self.click_volume_db / 20.0)
+        } else {
+            1.0
+        }
+    }
+
+    /// The hardware output a track is routed to, if it isn't sent to the master.
+    pub fn output(&self, is_click: bool) -> Option<u32> {
+        self.click_output.filter(|_| is_click)
+    }
+}
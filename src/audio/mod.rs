@@ -1,2 +1,26 @@
+pub mod ableton;
+pub mod budget;
+pub mod bundle;
+pub mod click;
+pub mod dawproject;
+pub mod drift;
+pub mod exporters;
+pub mod fcpxml;
+pub mod limits;
+pub mod loops;
+pub mod manifest;
+pub mod midi;
+pub mod mix;
+pub mod pipeline;
+pub mod plan;
 pub mod processor;
-pub use processor::AudioProcessor;
\ No newline at end of file
+pub mod reaper;
+pub mod reaper_template;
+pub mod riff;
+pub mod taxonomy;
+pub mod tempo;
+pub mod title;
+pub mod track_map;
+pub mod validation;
+pub mod verify;
+pub use processor::{AudioProcessor, ProcessOptions, ProcessReport, SongFolder, FULL_MIX};
\ No newline at end of file
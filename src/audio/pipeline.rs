@@ -0,0 +1,294 @@
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use std::fmt::Display;
+
+/// What a sample of 1.0 is in 16 bits.
+const FULL_SCALE: f32 = 32768.0;
+/// The rate KV delivers stems at, which the click and the projects are laid out in.
+pub const DEFAULT_RATE: u32 = 44_100;
+
+/// Decoded audio, one sample vector per channel.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Audio {
+    pub sample_rate: u32,
+    pub channels: Vec<Vec<f32>>,
+}
+
+impl Audio {
+    /// Scaled by a power of two, so [`Self::to_interleaved`] gives back the same samples
+    /// when no stage changed them.
+    pub fn from_interleaved(samples: &[i16], channels: usize, sample_rate: u32) -> Self {
+        let mut planar = vec![Vec::with_capacity(samples.len() / channels.max(1)); channels];
+        for frame in samples.chunks(channels) {
+            for (channel, sample) in planar.iter_mut().zip(frame) {
+                channel.push(*sample as f32 / FULL_SCALE);
+            }
+        }
+        Self {
+            sample_rate,
+            channels: planar,
+        }
+    }
+
+    pub fn to_interleaved(&self) -> Vec<i16> {
+        let mut samples = Vec::with_capacity(self.frames() * self.channels.len());
+        for i in 0..self.frames() {
+            for channel in &self.channels {
+                samples.push((channel[i] * FULL_SCALE).round().clamp(i16::MIN as f32, i16::MAX as f32) as i16);
+            }
+        }
+        samples
+    }
+
+    pub fn frames(&self) -> usize {
+        self.channels.first().map(|c| c.len()).unwrap_or(0)
+    }
+
+    fn peak(&self) -> f32 {
+        self.channels
+            .iter()
+            .flatten()
+            .fold(0.0, |peak, s| peak.max(s.abs()))
+    }
+}
+
+/// A built-in processing step and its parameters, as written in the `[pipeline]` config.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "stage", rename_all = "kebab-case", deny_unknown_fields)]
+pub enum Stage {
+    Gain { db: f64 },
+    DcFilter,
+    Resample { rate: u32 },
+    /// Sums the channels; the stem keeps its channel count, each carrying the sum.
+    Mono,
+    /// Scales the stem so its loudest sample peaks at `peak_db` dBFS.
+    Normalize {
+        #[serde(default)]
+        peak_db: f64,
+    },
+    /// Cuts `start` seconds off the front and, if given, everything after `end` seconds.
+    Trim {
+        #[serde(default)]
+        start: f64,
+        end: Option<f64>,
+    },
+    Fade {
+        #[serde(default, rename = "in")]
+        fade_in: f64,
+        #[serde(default, rename = "out")]
+        fade_out: f64,
+    },
+    /// Flips the polarity.
+    Invert,
+    /// Swaps the first two channels.
+    SwapChannels,
+}
+
+impl Display for Stage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Gain { db } => write!(f, "gain({} dB)", db),
+            Self::DcFilter => write!(f, "dc-filter"),
+            Self::Resample { rate } => write!(f, "resample({} Hz)", rate),
+            Self::Mono => write!(f, "mono"),
+            Self::Normalize { peak_db } => write!(f, "normalize({} dBFS)", peak_db),
+            Self::Trim { start, end: Some(end) } => write!(f, "trim({}s-{}s)", start, end),
+            Self::Trim { start, end: None } => write!(f, "trim({}s-)", start),
+            Self::Fade { fade_in, fade_out } => write!(f, "fade(in {}s, out {}s)", fade_in, fade_out),
+            Self::Invert => write!(f, "invert"),
+            Self::SwapChannels => write!(f, "swap-channels"),
+        }
+    }
+}
+
+impl Stage {
+    fn validate(&self) -> Result<()> {
+        let ok = match self {
+            Self::Gain { db } => db.is_finite(),
+            Self::Resample { rate } => (8_000..=192_000).contains(rate),
+            Self::Normalize { peak_db } => peak_db.is_finite() && *peak_db <= 0.0,
+            Self::Trim { start, end } => *start >= 0.0 && end.is_none_or(|end| end > *start),
+            Self::Fade { fade_in, fade_out } => *fade_in >= 0.0 && *fade_out >= 0.0,
+            Self::DcFilter | Self::Mono | Self::Invert | Self::SwapChannels => true,
+        };
+        if ok {
+            Ok(())
+        } else {
+            Err(anyhow!("Invalid parameters for pipeline stage {}", self))
+        }
+    }
+
+    pub(crate) fn apply(&self, audio: &mut Audio) {
+        let rate = audio.sample_rate as f64;
+        match self {
+            Self::Gain { db } => scale(audio, db_to_gain(*db)),
+            Self::DcFilter => {
+                // One-pole high-pass at roughly 10 Hz
+                let r = 1.0 - (2.0 * std::f64::consts::PI * 10.0 / rate) as f32;
+                for channel in &mut audio.channels {
+                    let (mut last_in, mut last_out) = (0.0, 0.0);
+                    for sample in channel.iter_mut() {
+                        let out = *sample - last_in + r * last_out;
+                        last_in = *sample;
+                        last_out = out;
+                        *sample = out;
+                    }
+                }
+            }
+            Self::Resample { rate: target } => resample(audio, *target),
+            Self::Mono => {
+                let count = audio.channels.len() as f32;
+                let summed: Vec<f32> = (0..audio.frames())
+                    .map(|i| audio.channels.iter().map(|c| c[i]).sum::<f32>() / count)
+                    .collect();
+                for channel in &mut audio.channels {
+                    channel.clone_from(&summed);
+                }
+            }
+            Self::Normalize { peak_db } => {
+                let peak = audio.peak();
+                if peak > 0.0 {
+                    scale(audio, db_to_gain(*peak_db) / peak);
+                }
+            }
+            Self::Trim { start, end } => {
+                let first = ((start * rate) as usize).min(audio.frames());
+                let last = end.map_or(audio.frames(), |end| ((end * rate) as usize).min(audio.frames()));
+                for channel in &mut audio.channels {
+                    channel.truncate(last.max(first));
+                    channel.drain(..first);
+                }
+            }
+            Self::Fade { fade_in, fade_out } => {
+                let fade_in = (fade_in * rate) as usize;
+                let fade_out = (fade_out * rate) as usize;
+                for channel in &mut audio.channels {
+                    for (i, sample) in channel.iter_mut().take(fade_in).enumerate() {
+                        *sample *= i as f32 / fade_in as f32;
+                    }
+                    for (i, sample) in channel.iter_mut().rev().take(fade_out).enumerate() {
+                        *sample *= i as f32 / fade_out as f32;
+                    }
+                }
+            }
+            Self::Invert => scale(audio, -1.0),
+            Self::SwapChannels => {
+                if audio.channels.len() >= 2 {
+                    audio.channels.swap(0, 1);
+                }
+            }
+        }
+    }
+}
+
+fn db_to_gain(db: f64) -> f32 {
+    10f64.powf(db / 20.0) as f32
+}
+
+fn scale(audio: &mut Audio, gain: f32) {
+    for sample in audio.channels.iter_mut().flatten() {
+        *sample *= gain;
+    }
+}
+
+/// Linear interpolation; good enough for the odd stem delivered at the wrong rate.
+fn resample(audio: &mut Audio, target: u32) {
+    if target == audio.sample_rate || audio.frames() == 0 {
+        return;
+    }
+    let ratio = audio.sample_rate as f64 / target as f64;
+    let frames = (audio.frames() as f64 / ratio).round() as usize;
+    for channel in &mut audio.channels {
+        let source = std::mem::take(channel);
+        *channel = (0..frames)
+            .map(|i| {
+                let position = i as f64 * ratio;
+                let index = position as usize;
+                let next = source.get(index + 1).copied().unwrap_or(source[source.len() - 1]);
+                let current = source.get(index).copied().unwrap_or(next);
+                current + (next - current) * position.fract() as f32
+            })
+            .collect();
+    }
+    audio.sample_rate = target;
+}
+
+/// A stage in the pipeline, optionally limited to some stems.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct StageSpec {
+    #[serde(flatten)]
+    pub stage: Stage,
+    /// Track names the stage applies to, matched case-insensitively against the normalized
+    /// stem name as a substring ("guitar" matches "Electric Guitar"). Empty means every stem.
+    #[serde(default)]
+    pub tracks: Vec<String>,
+}
+
+impl StageSpec {
+    pub fn matches(&self, track_name: &str) -> bool {
+        let name = track_name.to_lowercase();
+        self.tracks.is_empty() || self.tracks.iter().any(|t| name.contains(&t.to_lowercase()))
+    }
+}
+
+/// The ordered stages every stem goes through between decoding (and padding to the click)
+/// and being written as a stereo WAV; the mono WAVs are always a downmix of the result.
+/// The stages of a `[pipeline]` replace the [`Pipeline::default`] ones.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Pipeline {
+    #[serde(default = "default_stages")]
+    pub stages: Vec<StageSpec>,
+}
+
+impl Default for Pipeline {
+    fn default() -> Self {
+        Self { stages: default_stages() }
+    }
+}
+
+/// What processing has always done to a stem: keep it at [`DEFAULT_RATE`], which leaves
+/// KV's stems as decoded and brings the odd one delivered at another rate in line.
+fn default_stages() -> Vec<StageSpec> {
+    vec![StageSpec {
+        stage: Stage::Resample { rate: DEFAULT_RATE },
+        tracks: Vec::new(),
+    }]
+}
+
+impl Pipeline {
+    pub fn validate(&self) -> Result<()> {
+        for spec in &self.stages {
+            spec.stage.validate()?;
+        }
+        Ok(())
+    }
+
+    /// Whether the stages for `track_name` leave every sample where it was, so the output
+    /// still lines up with the source.
+    pub fn keeps_timing(&self, track_name: &str) -> bool {
+        !self
+            .stages_for(track_name)
+            .iter()
+            .any(|stage| matches!(stage, Stage::Trim { .. } | Stage::Fade { .. }))
+    }
+
+    /// The stages that run for `track_name`, in order.
+    pub fn stages_for(&self, track_name: &str) -> Vec<&Stage> {
+        self.stages
+            .iter()
+            .filter(|spec| spec.matches(track_name))
+            .map(|spec| &spec.stage)
+            .collect()
+    }
+
+    /// Runs the stages for `track_name` over `audio`, returning what was applied.
+    pub fn apply(&self, track_name: &str, audio: &mut Audio) -> Vec<String> {
+        self.stages_for(track_name)
+            .into_iter()
+            .map(|stage| {
+                stage.apply(audio);
+                stage.to_string()
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,262 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use super::pipeline::Stage;
+use super::validation::{HeaderProbe, ReferenceSource};
+use super::{AudioProcessor, ProcessOptions};
+use crate::table;
+
+/// What processing will do with one downloaded stem.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StemPlan {
+    /// The download the stem is decoded from.
+    pub source: PathBuf,
+    /// The stem's name in the song folder, numbered if another stem has the same one, and
+    /// led by its place in the mixer with `--numbered-stems`.
+    pub name: String,
+    /// Whether this is the click (or the full mix standing in for it) the others are padded to.
+    pub is_reference: bool,
+    /// Length, sample rate and channels as the source's headers claim them; `None` where
+    /// they don't say.
+    pub duration_secs: Option<f64>,
+    pub sample_rate: Option<u32>,
+    pub channels: Option<usize>,
+    /// Silence put in front of the stem to line it up with the reference, from the header
+    /// length. Processing works it out again from the decoded length.
+    pub padding_secs: f64,
+    /// Pipeline stages the stem goes through, in order.
+    pub stages: Vec<String>,
+    /// Sample rate the stem is written at, when the source's is known or a stage sets it.
+    pub output_rate: Option<u32>,
+    /// Sum of the pipeline's gain stages.
+    pub gain_db: f64,
+    /// Peak the last normalize stage scales the stem to.
+    pub normalize_peak_db: Option<f64>,
+    /// The stereo WAV as first written, named after the download.
+    pub transcoded: PathBuf,
+    pub stereo: PathBuf,
+    pub mono: PathBuf,
+    /// Where the download is kept, with `--keep-mp3s`.
+    pub mp3: Option<PathBuf>,
+}
+
+impl StemPlan {
+    /// Whether the stem is written at another rate than it was delivered at.
+    pub fn resamples(&self) -> bool {
+        self.output_rate != self.sample_rate
+    }
+}
+
+/// Everything processing a song's downloads will do, worked out before any audio is
+/// decoded or written. The click comes first, then the other stems as found.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ProcessPlan {
+    pub song_dir: PathBuf,
+    /// Length every stem is padded to.
+    pub reference_secs: f64,
+    pub reference_source: ReferenceSource,
+    pub stems: Vec<StemPlan>,
+    /// Project exporters that write into `MT PROJECT`, in the order they run.
+    pub exporters: Vec<String>,
+}
+
+impl ProcessPlan {
+    /// Plans `click` and `others` into `song_dir`. Each stem is probed for its headers only,
+    /// and planned to be padded from the length they claim to `reference`.
+    pub fn build(
+        song_dir: &Path,
+        click: &Path,
+        others: &[PathBuf],
+        reference: Duration,
+        reference_source: ReferenceSource,
+        mixer_names: &[String],
+        options: &ProcessOptions,
+    ) -> Self {
+        let stems_dir = song_dir.join("STEMS");
+        let wav_st_dir = stems_dir.join("WAV ST");
+        let wav_mono_dir = stems_dir.join("WAV MONO");
+        let mp3_dir = stems_dir.join("MP3");
+        let mut used = HashMap::new();
+        let sources: Vec<&Path> = std::iter::once(click).chain(others.iter().map(PathBuf::as_path)).collect();
+        let numbers = options.numbered_stems.then(|| {
+            let names: Vec<String> = sources
+                .iter()
+                .map(|source| AudioProcessor::normalize_track_name(&source.file_name().unwrap().to_string_lossy()))
+                .collect();
+            mixer_numbers(&names, mixer_names)
+        });
+
+        let stems = sources
+            .into_iter()
+            .enumerate()
+            .map(|(i, source)| {
+                let filename = source.file_name().unwrap().to_string_lossy().into_owned();
+                let track_name = AudioProcessor::normalize_track_name(&filename);
+                let name = AudioProcessor::disambiguate(&track_name, &mut used);
+                let name = match &numbers {
+                    Some(numbers) => numbers[i].prefix(&name),
+                    None => name,
+                };
+                let header = HeaderProbe::probe(source).unwrap_or_else(|e| {
+                    tracing::warn!("Unable to read the headers of {:?}: {}", source, e);
+                    HeaderProbe::default()
+                });
+                // The reference itself is never padded
+                let padding = match header.duration {
+                    Some(duration) if i > 0 => reference.saturating_sub(duration),
+                    _ => Duration::ZERO,
+                };
+
+                let stages = options.pipeline.stages_for(&track_name);
+                let mut output_rate = header.sample_rate;
+                let mut gain_db = 0.0;
+                let mut normalize_peak_db = None;
+                for stage in &stages {
+                    match stage {
+                        Stage::Resample { rate } => output_rate = Some(*rate),
+                        Stage::Gain { db } => gain_db += db,
+                        Stage::Normalize { peak_db } => normalize_peak_db = Some(*peak_db),
+                        _ => {}
+                    }
+                }
+
+                // A download whose name says mono keeps saying so, as it always has
+                let stereo_name = if filename.contains("_mono") {
+                    format!("{}_mono.wav", name)
+                } else {
+                    format!("{}.wav", name)
+                };
+                StemPlan {
+                    source: source.to_path_buf(),
+                    is_reference: i == 0,
+                    duration_secs: header.duration.map(|d| d.as_secs_f64()),
+                    sample_rate: header.sample_rate,
+                    channels: header.channels,
+                    padding_secs: padding.as_secs_f64(),
+                    stages: stages.iter().map(|stage| stage.to_string()).collect(),
+                    output_rate,
+                    gain_db,
+                    normalize_peak_db,
+                    transcoded: wav_st_dir.join(&filename).with_extension("wav"),
+                    stereo: wav_st_dir.join(stereo_name),
+                    mono: wav_mono_dir.join(format!("{}_mono.wav", name)),
+                    mp3: options.keep_mp3s.then(|| mp3_dir.join(format!("{}.mp3", name))),
+                    name,
+                }
+            })
+            .collect();
+
+        Self {
+            song_dir: song_dir.to_path_buf(),
+            reference_secs: reference.as_secs_f64(),
+            reference_source,
+            stems,
+            exporters: AudioProcessor::exporters(options).iter().map(|e| e.name.to_string()).collect(),
+        }
+    }
+
+    /// Whether any exporter writes into `MT PROJECT`.
+    pub fn wants_projects(&self) -> bool {
+        !self.exporters.is_empty()
+    }
+
+    fn relative<'a>(&self, path: &'a Path) -> std::borrow::Cow<'a, str> {
+        path.strip_prefix(&self.song_dir).unwrap_or(path).to_string_lossy()
+    }
+}
+
+/// The place of a stem in the mixer, and how many digits every stem's is written with.
+struct StemNumber {
+    number: usize,
+    width: usize,
+}
+
+impl StemNumber {
+    fn prefix(&self, name: &str) -> String {
+        format!("{:0width$} {}", self.number, name, width = self.width)
+    }
+}
+
+/// Numbers `track_names` by where they are in `mixer_names`, counting from 1; a name the
+/// mixer holds twice takes its places in turn. Stems the mixer doesn't list, or all of them
+/// when its order isn't known, follow in the order given. Only the names decide the numbers,
+/// so processing a song again writes the same ones.
+fn mixer_numbers(track_names: &[String], mixer_names: &[String]) -> Vec<StemNumber> {
+    let mut taken = vec![false; mixer_names.len()];
+    let places: Vec<Option<usize>> = track_names
+        .iter()
+        .map(|name| {
+            let index = (0..mixer_names.len())
+                .find(|&index| !taken[index] && AudioProcessor::same_track_name(&mixer_names[index], name))?;
+            taken[index] = true;
+            Some(index)
+        })
+        .collect();
+    let mut next = mixer_names.len();
+    let numbers: Vec<usize> = places
+        .into_iter()
+        .map(|place| {
+            place.unwrap_or_else(|| {
+                next += 1;
+                next - 1
+            }) + 1
+        })
+        .collect();
+    let width = numbers.iter().max().map_or(0, |n| n.to_string().len()).max(2);
+    numbers.into_iter().map(|number| StemNumber { number, width }).collect()
+}
+
+/// A table of the stems, then where each is written.
+impl Display for ProcessPlan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{}", self.song_dir.display())?;
+        writeln!(f, "Padded to {:.2}s from {}", self.reference_secs, self.reference_source)?;
+
+        let unknown = || "?".to_string();
+        let mut rows: Vec<[String; 8]> = self
+            .stems
+            .iter()
+            .map(|stem| {
+                let resample = match (stem.sample_rate, stem.output_rate) {
+                    (from, Some(to)) if stem.resamples() => {
+                        format!("{} -> {}", from.map_or_else(unknown, |r| r.to_string()), to)
+                    }
+                    (_, rate) => format!("keep {}", rate.map_or_else(unknown, |r| r.to_string())),
+                };
+                [
+                    stem.name.clone(),
+                    stem.source.file_name().unwrap_or_default().to_string_lossy().into_owned(),
+                    stem.duration_secs.map_or_else(unknown, |d| format!("{:.2}s", d)),
+                    format!("{:.2}s", stem.padding_secs),
+                    resample,
+                    format!("{} dB", stem.gain_db),
+                    stem.normalize_peak_db.map_or_else(|| "-".to_string(), |db| format!("{} dBFS", db)),
+                    if stem.stages.is_empty() {
+                        "(none)".to_string()
+                    } else {
+                        stem.stages.join(" -> ")
+                    },
+                ]
+            })
+            .collect();
+        rows.insert(0, ["STEM", "SOURCE", "LENGTH", "PADDING", "RATE", "GAIN", "NORMALIZE", "STAGES"].map(String::from));
+        write!(f, "{}", table::render(&rows))?;
+
+        writeln!(f, "Outputs:")?;
+        for stem in &self.stems {
+            let mut outputs = vec![self.relative(&stem.stereo), self.relative(&stem.mono)];
+            if let Some(mp3) = &stem.mp3 {
+                outputs.push(self.relative(mp3));
+            }
+            writeln!(f, "  {}: {}", stem.name, outputs.join(", "))?;
+        }
+        if self.wants_projects() {
+            write!(f, "Projects in MT PROJECT: {}", self.exporters.join(", "))
+        } else {
+            write!(f, "No projects")
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, Context, Result};
 use symphonia::core::{
     audio::AudioBufferRef,
     codecs::DecoderOptions,
@@ -10,28 +10,345 @@ use symphonia::core::{
 use symphonia::core::audio::Signal;
 use symphonia::default::{get_codecs, get_probe};
 use hound::{WavWriter, WavSpec};
+use regex::Regex;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
-use std::fs::{File, OpenOptions, create_dir_all};
-use std::io::{BufReader, Write, Seek, SeekFrom};
+use std::fs::{File, create_dir_all};
+use std::io::BufReader;
 
-use std::time::Duration;
-use reqwest;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use crate::abort::{AbortSignal, Interrupted};
+use crate::audit;
+use crate::metadata::{self, SongInfo};
+use crate::permissions::OutputPermissions;
+use crate::proxy;
+
+use super::ableton::{self, AbletonTrack};
+use super::click::{self, ClickDetection, ClickMatch, SongTracks};
+use super::budget::{self, MemoryBudget};
+use super::dawproject::{self, DawTrack};
+use super::drift;
+use super::exporters::{self, DawTargets, ExportContext, Exporter};
+use super::fcpxml::{self, FcpClip};
+use super::limits;
+use super::loops::{self, LoopRegion};
+use super::manifest::StemManifest;
+use super::midi::{self, MidiCountIn, MidiLayout};
+use super::mix::MonitorMix;
+use super::pipeline::{Audio, Pipeline};
+use super::plan::{ProcessPlan, StemPlan};
+use super::reaper::{self, ProjectMidiTrack, ProjectTempo, ReaperLayout, RppStems, DEFAULT_PROJECT_BPM};
+use super::reaper_template::{ReaperTemplate, Section};
+use super::tempo::{self, TimeSignature};
+use super::title;
+use super::track_map::{TrackEntry, TrackMap, TRACKS_FILE};
+use super::validation::{self, ReferenceSource};
+use super::verify::{self, OutputPair};
+
+/// A stem as first written to `WAV ST`, named after its download.
+struct Transcoded {
+    source: PathBuf,
+    wav: PathBuf,
+    /// Packets of the source that failed to decode.
+    decode_errors: usize,
+    /// Silence put in front of the audio to line it up with the click.
+    padding: Duration,
+}
+
+type StemResult = Result<Transcoded>;
+
+#[derive(Default, Clone)]
+pub struct ProcessOptions {
+    pub keep_mp3s: bool,
+    pub skip_validation: bool,
+    pub skip_rpp: bool,
+    pub skip_fcpxml: bool,
+    /// DAWs to generate a session for; `skip_rpp` still turns the Reaper one off.
+    pub daws: DawTargets,
+    /// Track colors and folders of the Reaper project.
+    pub reaper: ReaperLayout,
+    /// Write absolute stem paths into the Reaper project instead of paths relative to
+    /// `MT PROJECT`, which survive moving the song folder.
+    pub rpp_absolute_paths: bool,
+    /// Template the Reaper project is filled into; the built-in one reproduces the layout
+    /// the generator has always written.
+    pub rpp_template: ReaperTemplate,
+    /// Which WAV folder the Reaper project plays, or a project for each.
+    pub rpp_stems: RppStems,
+    /// The MIDI track after the stems in the Reaper project.
+    pub project_midi_track: ProjectMidiTrack,
+    /// Pan, gain and routing of the click and the band in the DAW sessions.
+    pub mix: MonitorMix,
+    /// Copy the stems into the `.dawproject` container instead of referencing them in STEMS.
+    pub dawproject_embed: bool,
+    pub loop_region: Option<LoopRegion>,
+    /// Fail the song when a project exporter fails, instead of keeping the stems with a warning.
+    pub strict_exporters: bool,
+    /// Time budget per exporter; defaults to [`exporters::DEFAULT_EXPORTER_TIMEOUT`].
+    pub exporter_timeout: Option<Duration>,
+    /// When the current song's download started. Files in the download root older than this
+    /// aren't swept into the song folder; without it, the oldest stem marks the start.
+    pub song_started: Option<SystemTime>,
+    pub skip_midi: bool,
+    /// Tempo for the MIDI file instead of the one detected from the click.
+    pub tempo: Option<f64>,
+    /// Defaults to 4/4.
+    pub time_signature: Option<TimeSignature>,
+    /// Bars of count-in at the start of the click; 0 when downloaded without `--count-in`.
+    pub count_in_bars: u32,
+    pub midi_count_in: MidiCountIn,
+    /// Stages applied to every stem before it's written.
+    pub pipeline: Pipeline,
+    /// Print the stages each stem goes through before processing it.
+    pub print_pipeline: bool,
+    /// Length to pad the stems to when the click doesn't decode.
+    pub reference_duration: Option<Duration>,
+    /// Stems transcoded at the same time; 0 and 1 both mean one after the other.
+    pub process_threads: usize,
+    /// Bytes the stems being transcoded may take up together; defaults to a share of the
+    /// system's memory.
+    pub memory_budget: Option<u64>,
+    /// Compare windows of every output WAV with its source MP3 and fail the song on a
+    /// mismatch.
+    pub verify_outputs: bool,
+    /// Compare the onsets of every stem with the click's at the start, middle and end of
+    /// the song, and flag the stems that drift against it.
+    pub verify_drift: bool,
+    /// Modes and group given to the song folder once it's written.
+    pub permissions: OutputPermissions,
+    /// URLs of the same arrangement on other storefronts, recorded in `song.json`.
+    pub alternate_urls: Vec<String>,
+    /// The song page's details read during the download, saved into `song.json` instead
+    /// of fetching the page again.
+    pub song_info: Option<SongInfo>,
+    /// Put the full mix in the DAW sessions along with the stems; it's always transcoded.
+    pub include_full_mix: bool,
+    /// How the click is told from the other stems.
+    pub click: ClickDetection,
+    /// Process the MP3s even if they don't pair off with the mixer's tracks, warning
+    /// about the missing and unexpected ones.
+    pub allow_partial: bool,
+    /// Fail the song when the click's count-in doesn't match `count_in_bars`, instead of
+    /// warning.
+    pub strict_count_in: bool,
+    /// Prefix the stems' file names with their place in the mixer, such as `01 Click.wav`,
+    /// so they sort the way the mixer lists them.
+    pub numbered_stems: bool,
+    /// Stops processing before the next stem once requested, failing the song with
+    /// [`Interrupted`]. The stem being written is finished first.
+    pub abort: AbortSignal,
+}
+
+impl ProcessOptions {
+    /// Whether any exporter writes into `MT PROJECT`.
+    fn wants_projects(&self) -> bool {
+        self.wants_reaper() || self.daws.ableton || self.daws.dawproject || !self.skip_fcpxml || !self.skip_midi
+    }
+
+    fn wants_reaper(&self) -> bool {
+        self.daws.reaper && !self.skip_rpp
+    }
+
+    /// Whether the stem or WAV named `name` is the click.
+    fn is_click(&self, name: &str) -> bool {
+        let name = name.trim_end_matches("_mono");
+        self.click.is_click(name) || self.click.is_click(AudioProcessor::strip_stem_number(name))
+    }
+}
+
+/// Outcome of a song that made it through audio processing. Non-fatal problems (such as a
+/// project generator failing after the stems were written) are collected as warnings.
+#[derive(Debug, Default)]
+pub struct ProcessReport {
+    pub warnings: Vec<String>,
+    /// Exporters that failed even though the audio outputs are complete; regenerating the
+    /// projects can fix these later.
+    pub failed_exporters: Vec<String>,
+    /// Files that arrived with the song but were left in the download root because the
+    /// sweep doesn't know where they belong (unknown types, unfinished downloads).
+    pub leftover_files: Vec<PathBuf>,
+    /// Re-renders of the site's mixer noticed while downloading the stems. Each was
+    /// recovered from by pairing the tracks and solo buttons again, so they aren't warnings.
+    pub mixer_rerenders: Vec<String>,
+}
+
+impl ProcessReport {
+    pub fn is_clean(&self) -> bool {
+        self.warnings.is_empty()
+    }
+
+    pub fn audio_complete_exporters_failed(&self) -> bool {
+        !self.failed_exporters.is_empty()
+    }
+}
+
+/// What [`AudioProcessor::song_folder`] found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SongFolder {
+    Missing,
+    /// Processing created the folder but never got as far as writing its track map. The
+    /// song's MP3s are only removed once it does, so the song can be picked up again.
+    Unfinished,
+    Processed,
+}
+
+/// Suffixes KV appends to the track name inside the parentheses of a download's filename,
+/// lowercased. Localized storefronts use their own wording.
+const KV_TRACK_SUFFIXES: &[&str] = &[
+    "_custom_backing_track",
+    "_custom_backingtrack",
+    "_playback_personnalise",
+    "_playback_personnalisé",
+    "_individueller_playback",
+    "_pista_de_acompanamiento_personalizada",
+    "_base_musicale_personalizzata",
+];
+
+/// `name` lowercased, with every run of characters other than letters and digits made a
+/// single space.
+fn track_name_key(name: &str) -> String {
+    name.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn strip_download_extension(filename: &str) -> &str {
+    let lower = filename.to_lowercase();
+    for ext in [".mp3", ".wav"] {
+        if lower.ends_with(ext) {
+            return &filename[..filename.len() - ext.len()];
+        }
+    }
+    filename
+}
+
+/// Removes the ` (1)`-style counter Chrome adds when a file of the same name exists.
+pub(crate) fn strip_duplicate_marker(stem: &str) -> &str {
+    let trimmed = stem.trim_end();
+    if let Some(open) = trimmed.rfind(" (") {
+        let inner = &trimmed[open + 2..];
+        if let Some(digits) = inner.strip_suffix(')') {
+            if !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit()) {
+                return &trimmed[..open];
+            }
+        }
+    }
+    trimmed
+}
+
+/// Track name of the full mix downloaded with `--full-mix`, saved as `<Song> (Full Mix).mp3`.
+pub const FULL_MIX: &str = "Full Mix";
 
 pub struct AudioProcessor;
 
 impl AudioProcessor {
-    fn normalize_track_name(filename: &str) -> String {
-        // Find everything after the first parenthesis but before "_Custom_Backing_Track)"
-        if let Some(start_idx) = filename.find('(') {
-            if let Some(end_idx) = filename[start_idx..].find("_Custom_Backing_Track)") {
-                let track_name = filename[start_idx + 1..start_idx + end_idx].to_string();
-                // Replace underscores with spaces in the track name
-                return track_name.replace('_', " ");
+    /// Extracts the mixer track name from a downloaded filename such as
+    /// `Artist_Song(Bass_Custom_Backing_Track).mp3`. Parentheses in the song title, nested
+    /// parentheses in the track name, and the ` (1)` Chrome appends to duplicates are all
+    /// handled. Names that don't carry a known suffix fall back to the bare filename with
+    /// underscores turned into spaces.
+    pub fn normalize_track_name(filename: &str) -> String {
+        let stem = strip_download_extension(filename);
+
+        // Searched in `stem` itself: lowercasing may change the length of what comes before
+        let suffixes = KV_TRACK_SUFFIXES.iter().map(|suffix| regex::escape(suffix)).collect::<Vec<_>>().join("|");
+        let suffix_pattern = Regex::new(&format!(r"(?i)(?:{})\)", suffixes)).expect("valid regex");
+        let suffix_match = suffix_pattern.find_iter(stem).last().map(|found| found.start());
+
+        if let Some(suffix_idx) = suffix_match {
+            // walk back to the parenthesis that opens this group, skipping balanced pairs
+            let mut depth = 0;
+            for (i, c) in stem[..suffix_idx].char_indices().rev() {
+                match c {
+                    ')' => depth += 1,
+                    '(' if depth == 0 => {
+                        return stem[i + 1..suffix_idx].replace('_', " ").trim().to_string();
+                    }
+                    '(' => depth -= 1,
+                    _ => {}
+                }
             }
         }
-        filename.replace('_', " ")
+
+        let bare = strip_duplicate_marker(stem);
+        if bare.to_lowercase().ends_with(&format!("({})", FULL_MIX.to_lowercase())) {
+            return FULL_MIX.to_string();
+        }
+        bare.replace('_', " ").trim().to_string()
     }
-    
+
+    /// Whether two track names, such as a mixer track's and one normalized from a download,
+    /// are the same track. Case, punctuation and spacing are ignored, since Chrome replaces
+    /// characters a filename can't hold and the site turns spaces into underscores.
+    pub fn same_track_name(a: &str, b: &str) -> bool {
+        track_name_key(a) == track_name_key(b)
+    }
+
+    /// Whether `filename` is an MP3 named the way the site names its downloads, in any
+    /// storefront's language, such as `Artist_Song(Bass_Custom_Backing_Track).mp3`, give or
+    /// take Chrome's ` (1)`.
+    pub fn is_kv_download(filename: &str) -> bool {
+        if !filename.to_lowercase().ends_with(".mp3") {
+            return false;
+        }
+        let bare = strip_duplicate_marker(strip_download_extension(filename)).to_lowercase();
+        KV_TRACK_SUFFIXES.iter().any(|suffix| bare.ends_with(&format!("{})", &suffix[1..])))
+    }
+
+    /// Whether `path`, a download or one of the WAVs made from it, is the full mix rather
+    /// than a stem.
+    pub fn is_full_mix(path: &Path) -> bool {
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        Self::normalize_track_name(Self::strip_stem_number(stem.trim_end_matches("_mono"))) == FULL_MIX
+    }
+
+    /// `name` without the place in the mixer `--numbered-stems` puts in front of it:
+    /// `Click` for `01 Click`. Names that don't start with two or more digits and a space
+    /// are kept whole.
+    pub fn strip_stem_number(name: &str) -> &str {
+        let digits = name.chars().take_while(char::is_ascii_digit).count();
+        match name[digits..].strip_prefix(' ') {
+            Some(rest) if digits >= 2 && !rest.trim().is_empty() => rest,
+            _ => name,
+        }
+    }
+
+    /// Makes `name` unique among the names already handed out for this song by appending
+    /// " 2", " 3", ... so two stems that normalize the same way don't overwrite each other.
+    pub fn disambiguate(name: &str, used: &mut HashMap<String, usize>) -> String {
+        let count = used.entry(name.to_lowercase()).or_insert(0);
+        *count += 1;
+        if *count == 1 {
+            name.to_string()
+        } else {
+            let candidate = format!("{} {}", name, count);
+            // the numbered name itself could collide with a real track name
+            match used.entry(candidate.to_lowercase()) {
+                Entry::Occupied(_) => Self::disambiguate(&candidate, used),
+                Entry::Vacant(slot) => {
+                    slot.insert(1);
+                    candidate
+                }
+            }
+        }
+    }
+
+    /// The formatted title of the song whose `STEMS` folder is `stems_dir`, for the names of
+    /// the projects made of its stems.
+    fn stems_song_title(stems_dir: &Path) -> Result<String> {
+        let song_dir = stems_dir
+            .parent()
+            .and_then(Path::file_name)
+            .ok_or_else(|| anyhow!("{:?} isn't in a song folder", stems_dir))?;
+        Self::format_song_title(&Self::extract_song_title(&song_dir.to_string_lossy())?)
+    }
+
     fn format_song_title(song_title: &str) -> Result<String> {
         // Clean up the song title
         let clean_title = song_title
@@ -64,15 +381,183 @@ impl AudioProcessor {
         }
     }
 
-    pub fn check_folder_exists(download_dir: &Path, song_url: &str) -> Result<bool> {
-        let song_title = Self::extract_song_title(song_url)?;
-        let song_dir = download_dir.join(&song_title);
-        Ok(song_dir.exists())
+    /// The span every log line about a song is recorded under, whichever thread it comes
+    /// from. `index` is the song's position in a batch, starting at 1.
+    pub fn song_span(song_url: &str, index: Option<usize>) -> tracing::Span {
+        let title = Self::extract_song_title(song_url).unwrap_or_default();
+        tracing::info_span!("song", title = %title, url = %song_url, index)
+    }
+
+    /// A step of a song's processing, nested in its [`song_span`](Self::song_span).
+    pub fn phase_span(phase: &'static str) -> tracing::Span {
+        tracing::info_span!("phase", phase)
+    }
+
+    fn stem_span(path: &Path) -> tracing::Span {
+        let stem = Self::normalize_track_name(&path.file_name().unwrap_or_default().to_string_lossy());
+        tracing::info_span!("stem", stem = %stem)
+    }
+
+    /// How far processing got with the song's folder in `download_dir`.
+    pub fn song_folder(download_dir: &Path, song_url: &str) -> Result<SongFolder> {
+        Ok(Self::folder_state(&Self::song_dir(download_dir, song_url)?))
+    }
+
+    /// The folder the song at `song_url` has, or would get, in `download_dir`.
+    pub fn song_dir(download_dir: &Path, song_url: &str) -> Result<PathBuf> {
+        match metadata::find_song_dir(download_dir, song_url) {
+            Some(song_dir) => Ok(song_dir),
+            None => Ok(download_dir.join(Self::extract_song_title(song_url)?)),
+        }
+    }
+
+    /// [`song_folder`](Self::song_folder) of a song whose details are already known, such
+    /// as from its `song.json`: their title names the folder instead of the page's.
+    pub fn known_song_folder(download_dir: &Path, song: &SongInfo) -> Result<SongFolder> {
+        let song_dir = match (metadata::find_song_dir(download_dir, &song.url), &song.title) {
+            (Some(song_dir), _) => song_dir,
+            (None, Some(song_title)) => download_dir.join(song_title),
+            (None, None) => download_dir.join(Self::extract_song_title(&song.url)?),
+        };
+        Ok(Self::folder_state(&song_dir))
+    }
+
+    fn folder_state(song_dir: &Path) -> SongFolder {
+        if song_dir.join(TRACKS_FILE).exists() {
+            SongFolder::Processed
+        } else if song_dir.exists() {
+            SongFolder::Unfinished
+        } else {
+            SongFolder::Missing
+        }
     }
 
-    pub fn process_downloads(download_dir: &Path, song_url: &str, keep_mp3s: bool) -> Result<()> {
-        let song_title = Self::extract_song_title(song_url)?;
-        let song_dir = download_dir.join(&song_title);
+    /// Turns the MP3s in `download_dir` into a song folder. `track_names` are the mixer track
+    /// names returned by the download, or empty when processing files already on disk.
+    pub fn process_downloads(
+        download_dir: &Path,
+        song_url: &str,
+        track_names: &[String],
+        options: &ProcessOptions,
+    ) -> Result<ProcessReport> {
+        let mut report = ProcessReport::default();
+        let mp3s = Self::downloaded_mp3s(download_dir)?;
+        // Only a download knows what the mixer held; a stale or missing MP3 would otherwise
+        // go unnoticed until the project is opened
+        if !track_names.is_empty() {
+            if let Err(mismatch) = validation::check_track_set(&mp3s, track_names) {
+                if !options.allow_partial {
+                    return Err(mismatch.into());
+                }
+                for problem in mismatch.problems() {
+                    tracing::warn!("{}", problem);
+                    report.warnings.push(problem);
+                }
+            }
+        }
+        let mixer_names = Self::mixer_order(download_dir, song_url, track_names);
+        let tracks = Self::find_tracks(download_dir, mp3s, &options.click, &mixer_names)?;
+        let options = &Self::with_click(options, &tracks);
+        let (click_path, other_tracks) = (tracks.reference, tracks.others);
+
+        // Every stem gets padded to this, so a click that decodes to nothing must not be
+        // trusted as the reference
+        let decoded_click = if tracks.click == ClickMatch::None {
+            Duration::ZERO
+        } else {
+            Self::get_mp3_duration(&click_path).unwrap_or_else(|e| {
+                tracing::warn!("Unable to decode the click {:?}: {}", click_path, e);
+                Duration::ZERO
+            })
+        };
+        let (click_duration, reference_source) = if tracks.click == ClickMatch::None {
+            let warning = format!(
+                "No click track among the downloads ({}); the stems weren't padded, so check they line up. \
+                 Name the click with --click-track",
+                std::iter::once(&click_path)
+                    .chain(&other_tracks)
+                    .map(|path| path.file_name().unwrap_or_default().to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+            tracing::warn!("{}", warning);
+            report.warnings.push(warning);
+            (Duration::ZERO, ReferenceSource::Unpadded)
+        } else {
+            validation::resolve_reference(
+                decoded_click,
+                options.reference_duration,
+                || validation::header_duration(&click_path).ok().flatten(),
+                || other_tracks.iter().filter_map(|path| Self::get_mp3_duration(path).ok()).collect(),
+            )?
+        };
+        let fallback_reference = if matches!(reference_source, ReferenceSource::Click | ReferenceSource::Unpadded) {
+            None
+        } else {
+            let warning = format!(
+                "Click decodes to only {:.2}s; padded stems to {:.2}s from {}. Check the song by ear",
+                decoded_click.as_secs_f64(),
+                click_duration.as_secs_f64(),
+                reference_source
+            );
+            tracing::warn!("{}", warning);
+            report.warnings.push(warning);
+            Some(click_duration)
+        };
+
+        // Catch truncated or undecodable downloads before anything is created or moved
+        if options.skip_validation {
+            tracing::warn!("Skipping validation of downloaded MP3s");
+        } else if tracks.click == ClickMatch::None {
+            let all_tracks: Vec<PathBuf> = std::iter::once(click_path.clone()).chain(other_tracks.iter().cloned()).collect();
+            Self::phase_span("validate").in_scope(|| validation::validate_unreferenced(&all_tracks))?;
+        } else {
+            Self::phase_span("validate")
+                .in_scope(|| validation::validate_tracks(&click_path, &other_tracks, fallback_reference))?;
+        }
+
+        // A count-in toggle click that didn't register only shows at rehearsal otherwise
+        let count_in = if reference_source == ReferenceSource::Click {
+            let longest_other = other_tracks
+                .iter()
+                .filter_map(|path| match validation::header_duration(path) {
+                    Ok(Some(duration)) => Some(duration),
+                    _ => Self::get_mp3_duration(path).ok(),
+                })
+                .max()
+                .unwrap_or_default();
+            match validation::check_count_in(click_duration, longest_other, options.count_in_bars > 0) {
+                Ok(measured) => Some(measured),
+                Err(mismatch) if options.strict_count_in => return Err(mismatch.into()),
+                Err(mismatch) => {
+                    tracing::warn!("{}", mismatch);
+                    report.warnings.push(mismatch.to_string());
+                    // A count-in that's missing measures within the tolerance of none
+                    Some(if mismatch.requested { Duration::ZERO } else { mismatch.measured })
+                }
+            }
+        } else {
+            None
+        };
+
+        let song_title = Self::folder_title(download_dir, song_url, options, &click_path)?;
+        let plan = ProcessPlan::build(
+            &download_dir.join(&song_title),
+            &click_path,
+            &other_tracks,
+            click_duration,
+            reference_source,
+            &mixer_names,
+            options,
+        );
+        if options.print_pipeline {
+            for stem in &plan.stems {
+                let stages = if stem.stages.is_empty() { "(none)".to_string() } else { stem.stages.join(" -> ") };
+                println!("{}: {}", stem.name, stages);
+            }
+        }
+
+        let song_dir = plan.song_dir.clone();
         let stems_dir = song_dir.join("STEMS");
 
         // Create all necessary directories upfront
@@ -86,152 +571,535 @@ impl AudioProcessor {
         create_dir_all(&mp3_dir)?;
         create_dir_all(&wav_st_dir)?;
         create_dir_all(&wav_mono_dir)?;
-        create_dir_all(&mt_project_dir)?;
+        if options.wants_projects() {
+            create_dir_all(&mt_project_dir)?;
+        }
+
+        if let Some(region) = &options.loop_region {
+            if tracks.click == ClickMatch::None {
+                tracing::warn!("No click to check the loop region against");
+            } else {
+                region.validate(click_duration)?;
+            }
+        }
+        let transcode = Self::phase_span("transcode");
+        let click = transcode.in_scope(|| Self::process_click_track(&plan.stems[0], &options.pipeline))?;
 
-        let (click_path, _other_tracks) = Self::find_tracks(download_dir)?;
-        let click_duration = Self::get_mp3_duration(&click_path)?;
-        let click_wav_path = Self::process_click_track(&click_path, &wav_st_dir)?;
-        
         // Process all non-click tracks found in the directory
-        let other_wav_paths = Self::process_non_click_tracks(download_dir, &wav_st_dir, click_duration)?;
-        
+        let others =
+            transcode.in_scope(|| Self::process_non_click_tracks(&plan.stems[1..], click_duration, options))?;
+        let transcoded: Vec<Transcoded> = std::iter::once(click).chain(others).collect();
+        let decode_errors: Vec<usize> = transcoded.iter().map(|t| t.decode_errors).collect();
+        if options.abort.is_requested() {
+            return Err(Interrupted.into());
+        }
+
         // Convert to mono and adjust gain
-        let mono_paths = Self::convert_to_mono(&click_wav_path, &other_wav_paths, &wav_mono_dir)?;
+        let mono_paths = Self::phase_span("mono")
+            .in_scope(|| Self::convert_to_mono(&transcoded, &plan.stems))?;
         
-        // Move all WAV files to their respective directories
-        let all_wav_files: Vec<PathBuf> = std::fs::read_dir(&wav_st_dir)?
-            .filter_map(|entry| entry.ok())
-            .map(|entry| entry.path())
-            .filter(|path| path.extension().map_or(false, |ext| ext == "wav"))
+        // The stereo and mono WAVs share the plan's names, so duplicate names get the same
+        // numbering in both folders
+        let all_wav_files: Vec<PathBuf> = transcoded.iter().map(|t| t.wav.clone()).collect();
+        let stereo_paths = Self::move_wav_files(&plan.stems)?;
+
+        if let Some(region) = &options.loop_region {
+            Self::write_loop_points(&[&wav_st_dir, &wav_mono_dir], region)?;
+        }
+
+        // Before the sources are deleted, and before anything is built on a mix-up
+        if options.verify_outputs {
+            let pairs = Self::output_pairs(&transcoded, &stereo_paths, &mono_paths, &options.pipeline);
+            Self::phase_span("verify").in_scope(|| verify::verify_outputs(&pairs))?;
+        }
+        let drifts = if options.verify_drift && tracks.click.is_click() {
+            Self::phase_span("drift").in_scope(|| {
+                Self::check_drift(&transcoded, &stereo_paths, click_duration, &options.pipeline, &mut report)
+            })?
+        } else {
+            HashMap::new()
+        };
+
+        let mut track_map = Self::build_track_map(
+            &song_dir,
+            &all_wav_files,
+            &stereo_paths,
+            &mono_paths,
+            &decode_errors,
+            &options.pipeline,
+            tracks.click.is_click(),
+        )?;
+        track_map.assign_mixer_names(track_names, TrackMap::load(&song_dir).unwrap_or_default().as_ref());
+        track_map.save(&song_dir)?;
+        // Keyed the way the track map names the downloads
+        let padding: HashMap<String, Duration> = transcoded
+            .iter()
+            .map(|t| (t.wav.with_extension("mp3").file_name().unwrap().to_string_lossy().into_owned(), t.padding))
             .collect();
-            
-        // Move all processed WAV files to their respective folders
-        Self::move_wav_files(&wav_st_dir, &all_wav_files)?;
+        let mut manifest = StemManifest::build(&song_dir, &track_map, &padding)?;
+        manifest.flag_drift(&song_dir, &drifts);
+        manifest.save(&song_dir)?;
+        Self::save_song_info(&song_dir, song_url, &track_map, count_in, options);
         
-        // Generate Reaper project file
-        Self::generate_reaper_project(&mt_project_dir, &mono_paths, &stems_dir)?;
+        Self::phase_span("export")
+            .in_scope(|| Self::generate_projects(&mt_project_dir, &mono_paths, &stems_dir, options, &mut report))?;
 
-        // Generate AAF file
-        Self::generate_aaf(&mt_project_dir, &mono_paths, &stems_dir)?;
+        let since = match options.song_started {
+            Some(started) => started,
+            None => Self::earliest_modified(std::iter::once(&click_path).chain(&other_tracks))?,
+        };
+        let sweep = Self::phase_span("sweep").in_scope(|| audit::sweep_download_root(download_dir, &song_dir, since))?;
+        for path in sweep.partial.iter().chain(&sweep.unknown) {
+            tracing::warn!("Left {:?} in the download folder", path);
+        }
+        report.leftover_files = sweep.partial.into_iter().chain(sweep.unknown).collect();
 
-        if keep_mp3s {
-            Self::move_mp3s(download_dir, &mp3_dir)?;
+        if options.keep_mp3s {
+            Self::move_mp3s(&plan.stems)?;
         } else {
-            Self::cleanup_mp3s(download_dir)?;
+            Self::cleanup_mp3s(&plan.stems)?;
         }
+        // Last, once everything the song gets is in its folder
+        report.warnings.extend(options.permissions.apply_tree(&song_dir));
 
-        Ok(())
+        Ok(report)
     }
 
-    fn move_wav_files(dest_dir: &Path, files: &[PathBuf]) -> Result<()> {
-        for path in files {
-            let original_name = path.file_name().unwrap().to_str().unwrap();
-            let normalized_name = Self::normalize_track_name(original_name);
-            
-            // Handle mono vs stereo naming
-            let new_filename = if original_name.contains("_mono") {
-                format!("{}_mono.wav", normalized_name)
-            } else {
-                format!("{}.wav", normalized_name)
-            };
-            
-            let new_path = dest_dir.join(new_filename);
-            std::fs::rename(path, new_path)?;
+    /// The plan [`process_downloads`](Self::process_downloads) would follow for the MP3s in
+    /// `download_dir`, from their headers alone: nothing is decoded, validated or written.
+    /// The click's header length stands in for its decoded one.
+    pub fn plan_downloads(download_dir: &Path, song_url: &str, options: &ProcessOptions) -> Result<ProcessPlan> {
+        let mixer_names = Self::mixer_order(download_dir, song_url, &[]);
+        let tracks = Self::find_tracks(download_dir, Self::downloaded_mp3s(download_dir)?, &options.click, &mixer_names)?;
+        let (click_path, other_tracks) = (tracks.reference, tracks.others);
+        let (reference, reference_source) = match validation::header_duration(&click_path).ok().flatten() {
+            _ if tracks.click == ClickMatch::None => (Duration::ZERO, ReferenceSource::Unpadded),
+            Some(header) if header >= validation::MIN_REFERENCE => (header, ReferenceSource::ClickHeader),
+            _ => validation::resolve_reference(
+                Duration::ZERO,
+                options.reference_duration,
+                || None,
+                || other_tracks.iter().filter_map(|path| validation::header_duration(path).ok().flatten()).collect(),
+            )?,
+        };
+        let song_title = Self::folder_title(download_dir, song_url, options, &click_path)?;
+        Ok(ProcessPlan::build(
+            &download_dir.join(song_title),
+            &click_path,
+            &other_tracks,
+            reference,
+            reference_source,
+            &mixer_names,
+            options,
+        ))
+    }
+
+    /// The downloads in `download_dir` that drift against its click, for fetching again
+    /// before they're processed. The stems are measured where padding will put them; none
+    /// are without a click.
+    pub fn drifted_downloads(download_dir: &Path, click: &ClickDetection) -> Result<Vec<PathBuf>> {
+        let tracks = Self::find_tracks(download_dir, Self::downloaded_mp3s(download_dir)?, click, &[])?;
+        if !tracks.click.is_click() {
+            return Ok(vec![]);
         }
+        let (click_path, other_tracks) = (tracks.reference, tracks.others);
+        let click_duration = Self::get_mp3_duration(&click_path)?;
+        let mut drifted = Vec::new();
+        for path in other_tracks {
+            let padding = click_duration.saturating_sub(Self::get_mp3_duration(&path)?);
+            if let Some(check) = drift::measure(&click_path, &path, padding, click_duration)? {
+                if check.is_drifted() {
+                    tracing::warn!("{:?} drifts {:+.0} ms against the click", path, check.drift_ms());
+                    drifted.push(path);
+                }
+            }
+        }
+        Ok(drifted)
+    }
+
+    /// Re-runs only the project generators for an already processed song folder, using the
+    /// mono WAVs in `STEMS/WAV MONO`. No audio is decoded or touched.
+    pub fn regenerate_projects(song_dir: &Path, options: &ProcessOptions) -> Result<ProcessReport> {
+        let mut report = ProcessReport::default();
+        let stems_dir = song_dir.join("STEMS");
+        let wav_mono_dir = stems_dir.join("WAV MONO");
+        if !wav_mono_dir.is_dir() {
+            return Err(anyhow!("No STEMS/WAV MONO folder found in {:?}", song_dir));
+        }
+
+        let mut mono_paths: Vec<PathBuf> = std::fs::read_dir(&wav_mono_dir)?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "wav"))
+            .collect();
+        if mono_paths.is_empty() {
+            return Err(anyhow!("No mono WAVs found in {:?}", wav_mono_dir));
+        }
+        // Keep the click first, the way process_downloads orders it
+        mono_paths.sort_by_key(|path| {
+            let name = path.file_name().unwrap_or_default().to_string_lossy().to_lowercase();
+            (!options.is_click(&path.file_stem().unwrap_or_default().to_string_lossy()), name)
+        });
+
+        let mt_project_dir = song_dir.join("MT PROJECT");
+        if options.wants_projects() {
+            create_dir_all(&mt_project_dir)?;
+        }
+        Self::phase_span("export")
+            .in_scope(|| Self::generate_projects(&mt_project_dir, &mono_paths, &stems_dir, options, &mut report))?;
+        report.warnings.extend(options.permissions.apply_tree(song_dir));
+        Ok(report)
+    }
+
+    /// The project exporters enabled by `options`, in the order they run.
+    pub fn exporters(options: &ProcessOptions) -> Vec<Exporter> {
+        let mut exporters = Vec::new();
+        if options.wants_reaper() {
+            let options = options.clone();
+            exporters.push(Exporter::new("Reaper project", move |ctx| {
+                Self::generate_reaper_project(&ctx.mt_project_dir, &ctx.mono_paths, &ctx.stems_dir, &options)
+            }));
+        }
+        if options.daws.ableton {
+            let options = options.clone();
+            exporters.push(Exporter::new("Ableton set", move |ctx| {
+                Self::generate_ableton_set(&ctx.mt_project_dir, &ctx.mono_paths, &ctx.stems_dir, &options)
+            }));
+        }
+        if options.daws.dawproject {
+            let options = options.clone();
+            exporters.push(Exporter::new("DAWproject", move |ctx| {
+                Self::generate_dawproject(&ctx.mt_project_dir, &ctx.mono_paths, &ctx.stems_dir, &options)
+            }));
+        }
+        if !options.skip_fcpxml {
+            exporters.push(Exporter::new("FCPXML", |ctx| {
+                Self::generate_fcpxml(&ctx.mt_project_dir, &ctx.mono_paths, &ctx.stems_dir)
+            }));
+        }
+        if !options.skip_midi {
+            let options = options.clone();
+            // A click without a steady tempo mustn't fail a song that used to pass
+            exporters.push(Exporter::advisory("MIDI file", move |ctx| {
+                Self::generate_midi_file(&ctx.mt_project_dir, &ctx.mono_paths, &ctx.stems_dir, &options)
+            }));
+        }
+        exporters
+    }
+
+    fn generate_projects(
+        mt_project_dir: &Path,
+        mono_paths: &[PathBuf],
+        stems_dir: &Path,
+        options: &ProcessOptions,
+        report: &mut ProcessReport,
+    ) -> Result<()> {
+        // The full mix would double every part it plays over, so it stays out unless asked for
+        let mono_paths: Vec<PathBuf> = mono_paths
+            .iter()
+            .filter(|path| options.include_full_mix || !Self::is_full_mix(path))
+            .cloned()
+            .collect();
+        if mono_paths.is_empty() {
+            tracing::info!("Only the full mix was downloaded; not generating any projects");
+            return Ok(());
+        }
+        // Project files are a convenience on top of the stems, so by default failures here
+        // shouldn't throw away the finished audio
+        let ctx = ExportContext {
+            mt_project_dir: mt_project_dir.to_path_buf(),
+            stems_dir: stems_dir.to_path_buf(),
+            mono_paths,
+        };
+        exporters::run_exporters(
+            &Self::exporters(options),
+            &ctx,
+            options.exporter_timeout.unwrap_or(exporters::DEFAULT_EXPORTER_TIMEOUT),
+            options.strict_exporters,
+            report,
+        )
+    }
+
+    /// Every stem is padded to the click's length, so the same timeline positions line up
+    /// across all of them.
+    fn write_loop_points(dirs: &[&Path], region: &LoopRegion) -> Result<()> {
+        for dir in dirs {
+            for entry in std::fs::read_dir(dir)? {
+                let path = entry?.path();
+                if path.extension().is_some_and(|ext| ext == "wav") {
+                    loops::write_loop(&path, region)?;
+                }
+            }
+        }
+        tracing::info!("Wrote loop {} into the WAV stems", region);
         Ok(())
     }
 
-    fn move_mp3s(src_dir: &Path, dest_dir: &Path) -> Result<()> {
-        for entry in std::fs::read_dir(src_dir)? {
-            let path = entry?.path();
-            if path.extension().map(|e| e == "mp3").unwrap_or(false) {
-                let original_name = path.file_name().unwrap().to_str().unwrap();
-                let normalized_name = Self::normalize_track_name(original_name);
-                let new_filename = format!("{}.mp3", normalized_name);
-                let new_path = dest_dir.join(new_filename);
-                std::fs::rename(&path, new_path)?;
+    /// Renames the stereo WAVs as first written to their planned names.
+    fn move_wav_files(stems: &[StemPlan]) -> Result<Vec<PathBuf>> {
+        let mut moved = Vec::new();
+        for stem in stems {
+            std::fs::rename(&stem.transcoded, &stem.stereo)?;
+            moved.push(stem.stereo.clone());
+        }
+        Ok(moved)
+    }
+
+    /// `transcoded` are the stereo WAVs as first written (named after the downloaded MP3s),
+    /// reference first, in the same order as the renamed `stereo_paths` and `mono_paths`.
+    /// The reference is only the click if `has_click`.
+    fn build_track_map(
+        song_dir: &Path,
+        transcoded: &[PathBuf],
+        stereo_paths: &[PathBuf],
+        mono_paths: &[PathBuf],
+        decode_errors: &[usize],
+        pipeline: &Pipeline,
+        has_click: bool,
+    ) -> Result<TrackMap> {
+        let relative = |path: &Path| -> String {
+            let rel = path.strip_prefix(song_dir).unwrap_or(path);
+            rel.components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/")
+        };
+
+        let mut tracks = Vec::new();
+        let outputs = transcoded.iter().zip(stereo_paths).zip(mono_paths).zip(decode_errors);
+        for (i, (((original, stereo), mono), errors)) in outputs.enumerate() {
+            let reader = hound::WavReader::open(mono)?;
+            let seconds = reader.duration() as f64 / reader.spec().sample_rate as f64;
+            let original_filename = original.with_extension("mp3").file_name().unwrap().to_string_lossy().into_owned();
+            let stages = pipeline
+                .stages_for(&Self::normalize_track_name(&original_filename))
+                .iter()
+                .map(|stage| stage.to_string())
+                .collect();
+            tracks.push(TrackEntry {
+                mixer_name: None,
+                mixer_index: None,
+                original_filename,
+                stereo_file: relative(stereo),
+                mono_file: relative(mono),
+                duration_secs: (seconds * 1000.0).round() / 1000.0,
+                is_click: i == 0 && has_click,
+                stages,
+                decode_errors: *errors,
+            });
+        }
+        Ok(TrackMap { tracks })
+    }
+
+    fn move_mp3s(stems: &[StemPlan]) -> Result<()> {
+        for stem in stems {
+            if let Some(mp3) = &stem.mp3 {
+                std::fs::rename(&stem.source, mp3)?;
             }
         }
         Ok(())
     }
 
+    fn earliest_modified<'a>(paths: impl Iterator<Item = &'a PathBuf>) -> Result<SystemTime> {
+        let mut earliest = SystemTime::now();
+        for path in paths {
+            earliest = earliest.min(std::fs::metadata(path)?.modified()?);
+        }
+        Ok(earliest)
+    }
+
+    /// Saves the song page's details into `song.json` with how the song was downloaded and
+    /// its tracks and the `count_in` measured on the click, merged into what the file
+    /// already holds. The page is only fetched when the download didn't read it; only the
+    /// details are lost if that fails, so it isn't an error.
+    fn save_song_info(
+        song_dir: &Path,
+        song_url: &str,
+        track_map: &TrackMap,
+        count_in: Option<Duration>,
+        options: &ProcessOptions,
+    ) {
+        let page = match &options.song_info {
+            Some(info) => Ok(SongInfo {
+                downloaded_at: Some(chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true)),
+                ..info.clone()
+            }),
+            None if song_url.starts_with("http") => proxy::get_text(song_url).map(|html| SongInfo::from_html(song_url, &html)),
+            None => return,
+        };
+        let saved = page.and_then(|page| {
+            let info = SongInfo {
+                title: song_dir.file_name().map(|name| name.to_string_lossy().into_owned()),
+                tracks: track_map.tracks.iter().filter_map(|track| track.mixer_name.clone()).collect(),
+                count_in_secs: count_in.map(|measured| (measured.as_secs_f64() * 1000.0).round() / 1000.0),
+                alternate_urls: options.alternate_urls.clone(),
+                ..page
+            };
+            // A resumed or repeated download keeps what was recorded before, such as the tags
+            let info = match SongInfo::load(song_dir) {
+                Ok(Some(saved)) => info.merge(saved),
+                _ => info,
+            };
+            info.save(song_dir)
+        });
+        if let Err(e) = saved {
+            tracing::warn!("Unable to save the song's details: {}", e);
+        }
+    }
+
+    /// The name of the song's folder: the one already recording `song_url` in its
+    /// `song.json`, such as a resumed song's, else the title read during the download,
+    /// else [`song_title`](Self::song_title).
+    fn folder_title(download_dir: &Path, song_url: &str, options: &ProcessOptions, click_path: &Path) -> Result<String> {
+        let saved = metadata::find_song_dir(download_dir, song_url)
+            .and_then(|song_dir| song_dir.file_name().map(|name| name.to_string_lossy().into_owned()));
+        match saved.or_else(|| options.song_info.as_ref().and_then(|info| info.title.clone())) {
+            Some(song_title) => Ok(song_title),
+            None => Self::song_title(song_url, Some(click_path)),
+        }
+    }
+
     fn extract_song_title(url: &str) -> Result<String> {
-        if url.starts_with("http") {
-            let response = reqwest::blocking::get(url)?;
-            let body = response.text()?;
-            
-            // Try to extract from HTML first
-            if let Some(title_start) = body.find(r#"<h1 class="song-details__title""#) {
-                if let Some(title_end) = body[title_start..].find("</h1>") {
-                    let title_html = &body[title_start..title_start + title_end];
-                    if let Some(content_start) = title_html.find('>') {
-                        let mut title = title_html[content_start + 1..].trim().to_string();
-                        
-                        // Remove " - Custom Backing Track MP3" from the end
-                        if let Some(index) = title.rfind(" - Custom Backing Track MP3") {
-                            title.truncate(index);
-                        }
-                        return Ok(title);
-                    }
-                }
+        Self::song_title(url, None)
+    }
+
+    /// The song folder's title. For a URL that's the song page's heading, then the tags of
+    /// `stem` when there is one, then the URL's path; anything else (such as a folder name)
+    /// is only formatted.
+    fn song_title(url: &str, stem: Option<&Path>) -> Result<String> {
+        if !url.starts_with("http") {
+            return Self::format_song_title(url);
+        }
+
+        let from_page = match proxy::get_text(url) {
+            Ok(html) => title::from_page(&html),
+            Err(e) => {
+                tracing::warn!("Unable to fetch the song page: {}", e);
+                None
             }
+        };
+        if let Some(song_title) = from_page {
+            return Ok(song_title);
         }
-        
-        // Fallback to URL parsing if HTML extraction fails
-        Self::format_song_title(url)
+        if let Some(song_title) = stem.and_then(title::from_tags) {
+            tracing::warn!("No title on the song page, using the stems' tags: {}", song_title);
+            return Ok(song_title);
+        }
+        let song_title = title::from_url(url).ok_or_else(|| anyhow!("Unable to work out the song title of {}", url))?;
+        tracing::warn!("No title on the song page or in the stems' tags, using the URL: {}", song_title);
+        Ok(song_title)
     }
 
-    fn find_tracks(dir: &Path) -> Result<(PathBuf, Vec<PathBuf>)> {
-        let mut click = None;
-        let mut others = Vec::new();
-    
+    fn downloaded_mp3s(dir: &Path) -> Result<Vec<PathBuf>> {
+        let mut paths = Vec::new();
         for entry in std::fs::read_dir(dir)? {
             let path = entry?.path();
-            if let Some(filename) = path.file_name().and_then(|n| n.to_str()) {
-                if filename.to_lowercase().contains("click") {
-                    click = Some(path.clone());
-                    tracing::info!("Found click track: {:?}", path);
-                } else if path.extension().map(|e| e == "mp3").unwrap_or(false) {
-                    others.push(path.clone());
-                    tracing::info!("Found other track: {:?}", path);
-                }
+            if path.extension().is_some_and(|e| e == "mp3") {
+                paths.push(path);
             }
         }
-    
-        if click.is_none() {
-            tracing::error!("No click track found in directory: {:?}", dir);
-            tracing::info!("Files found in directory:");
-            for entry in std::fs::read_dir(dir)? {
-                let path = entry?.path();
-                tracing::info!("  {:?}", path);
+        Ok(paths)
+    }
+
+    fn find_tracks(dir: &Path, paths: Vec<PathBuf>, detection: &ClickDetection, mixer_names: &[String]) -> Result<SongTracks> {
+        let tracks = click::find(paths, detection, mixer_names).with_context(|| format!("In {:?}", dir))?;
+        match tracks.click {
+            ClickMatch::Named | ClickMatch::Pattern => tracing::info!("Found click track: {:?}", tracks.reference),
+            ClickMatch::FullMix => {
+                tracing::info!("No click track; lining the stems up to the full mix {:?}", tracks.reference)
             }
+            ClickMatch::MixerOrder => tracing::warn!(
+                "No track is named like a click; taking {:?}, first in the mixer, as the click",
+                tracks.reference
+            ),
+            ClickMatch::None => tracing::warn!("No click track found in {:?}", dir),
         }
-    
-        Ok((
-            click.ok_or_else(|| anyhow!("Click track not found in {:?}", dir))?,
-            others,
-        ))
+        for path in &tracks.others {
+            tracing::info!("Found other track: {:?}", path);
+        }
+        Ok(tracks)
+    }
+
+    /// The mixer's track names in order: `track_names` from the download, else those
+    /// recorded in the `tracks.json` of the song's folder from an earlier run.
+    fn mixer_order(download_dir: &Path, song_url: &str, track_names: &[String]) -> Vec<String> {
+        if !track_names.is_empty() {
+            return track_names.to_vec();
+        }
+        let Some(track_map) = metadata::find_song_dir(download_dir, song_url)
+            .and_then(|song_dir| TrackMap::load(&song_dir).ok().flatten())
+        else {
+            return vec![];
+        };
+        let mut tracks: Vec<_> = track_map.tracks.into_iter().filter(|track| track.mixer_index.is_some()).collect();
+        tracks.sort_by_key(|track| track.mixer_index);
+        tracks.into_iter().filter_map(|track| track.mixer_name).collect()
+    }
+
+    /// `options` with the click named after the track taken as the click by its place in
+    /// the mixer, so the exporters treat it as one too.
+    fn with_click(options: &ProcessOptions, tracks: &SongTracks) -> ProcessOptions {
+        let mut options = options.clone();
+        if tracks.click == ClickMatch::MixerOrder {
+            let name = tracks.reference.file_name().unwrap_or_default().to_string_lossy();
+            options.click.track = Some(Self::normalize_track_name(&name));
+        }
+        options
     }
 
     fn get_mp3_duration(path: &Path) -> Result<Duration> {
-        let (spec, samples) = Self::decode_mp3(path)?;
+        let (spec, samples, _) = Self::decode_mp3(path)?;
         let duration_seconds = samples.len() as f64 / (spec.channels as f64 * spec.sample_rate as f64);
         Ok(Duration::from_secs_f64(duration_seconds))
     }
 
-    fn transcode_to_wav(src: &Path, dest_dir: &Path) -> Result<PathBuf> {
-        let (spec, samples) = Self::decode_mp3(src)?;
-        let dest = dest_dir.join(src.file_name().unwrap()).with_extension("wav");
-        
-        let mut writer = WavWriter::create(&dest, spec)?;
+    /// Returns how many packets failed to decode.
+    fn transcode_to_wav(src: &Path, dest: &Path, pipeline: &Pipeline) -> Result<usize> {
+        let (spec, samples, errors) = Self::decode_mp3(src)?;
+        Self::write_stem(dest, spec, samples, pipeline)?;
+        Ok(errors)
+    }
+
+    /// Runs the pipeline stages matching the stem's track name over `samples` and writes
+    /// the result to `path`.
+    fn write_stem(path: &Path, spec: WavSpec, samples: Vec<i16>, pipeline: &Pipeline) -> Result<()> {
+        let track_name = Self::normalize_track_name(&path.file_name().unwrap().to_string_lossy());
+        let (spec, samples) = if pipeline.stages_for(&track_name).is_empty() {
+            (spec, samples)
+        } else {
+            let mut audio = Audio::from_interleaved(&samples, spec.channels as usize, spec.sample_rate);
+            let applied = pipeline.apply(&track_name, &mut audio);
+            tracing::debug!("Applied {} to {}", applied.join(" -> "), track_name);
+            let spec = WavSpec {
+                channels: audio.channels.len() as u16,
+                sample_rate: audio.sample_rate,
+                ..spec
+            };
+            (spec, audio.to_interleaved())
+        };
+
+        // hound only notices a partial last frame once the whole file is written
+        if samples.len() % spec.channels as usize != 0 {
+            return Err(anyhow!(
+                "{} samples don't make whole {}-channel frames for {:?}",
+                samples.len(),
+                spec.channels,
+                path
+            ));
+        }
+        limits::check_wav_size(samples.len() as u64, spec.bits_per_sample)?;
+        let mut writer = WavWriter::create(path, spec)?;
         for sample in samples {
             writer.write_sample(sample)?;
         }
-        
-        Ok(dest)
+        writer.finalize()?;
+        tracing::debug!("Wrote {:?}", path);
+        Ok(())
     }
 
-    fn decode_mp3(path: &Path) -> Result<(WavSpec, Vec<i16>)> {
+    /// Decodes to interleaved stereo, skipping packets that fail to decode. The number of
+    /// skipped packets is returned alongside the audio.
+    fn decode_mp3(path: &Path) -> Result<(WavSpec, Vec<i16>, usize)> {
         let file = File::open(path)?;
         let source = ReadOnlySource::new(BufReader::new(file));
         let mss = MediaSourceStream::new(Box::new(source), Default::default());
@@ -245,9 +1113,9 @@ impl AudioProcessor {
         let track = probed.format.default_track().ok_or(anyhow!("No default track"))?;
         let mut decoder = get_codecs().make(&track.codec_params, &decoder_opts)?;
         let mut samples = Vec::new();
+        let mut decode_errors = 0;
 
-        let channels = 2; // Force stereo
-        let sample_rate = track.codec_params.sample_rate.unwrap_or(44100);
+        let spec = Self::stereo_spec(track.codec_params.sample_rate);
 
         while let Ok(packet) = probed.format.next_packet() {
             match decoder.decode(&packet) {
@@ -278,91 +1146,216 @@ impl AudioProcessor {
                     },
                     _ => return Err(anyhow!("Unsupported audio format")),
                 },
-                Err(symphonia::core::errors::Error::DecodeError(_)) => continue,
+                Err(symphonia::core::errors::Error::DecodeError(_)) => {
+                    decode_errors += 1;
+                    continue;
+                }
                 Err(e) => return Err(e.into()),
             }
         }
 
-        let spec = WavSpec {
-            channels,
-            sample_rate,
+        if decode_errors > 0 {
+            tracing::warn!("Skipped {} packets of {:?} that failed to decode", decode_errors, path);
+        }
+
+        Ok((spec, samples, decode_errors))
+    }
+
+    /// The spec [`Self::decode_mp3`] yields for `path`, read from its headers without decoding it.
+    fn decoded_spec(path: &Path) -> Result<WavSpec> {
+        let source = ReadOnlySource::new(BufReader::new(File::open(path)?));
+        let mss = MediaSourceStream::new(Box::new(source), Default::default());
+        let probed = get_probe().format(&Hint::new(), mss, &FormatOptions::default(), &MetadataOptions::default())?;
+        let track = probed.format.default_track().ok_or(anyhow!("No default track"))?;
+        Ok(Self::stereo_spec(track.codec_params.sample_rate))
+    }
+
+    /// Decoding always yields 16-bit stereo, at the track's rate or else 44.1 kHz.
+    fn stereo_spec(sample_rate: Option<u32>) -> WavSpec {
+        WavSpec {
+            channels: 2,
+            sample_rate: sample_rate.unwrap_or(44100),
             bits_per_sample: 16,
             sample_format: hound::SampleFormat::Int,
-        };
+        }
+    }
 
-        Ok((spec, samples))
+    fn process_click_track(click: &StemPlan, pipeline: &Pipeline) -> StemResult {
+        let _stem = Self::stem_span(&click.source).entered();
+        let decode_errors = Self::transcode_to_wav(&click.source, &click.transcoded, pipeline)?;
+        Ok(Transcoded {
+            source: click.source.clone(),
+            wav: click.transcoded.clone(),
+            decode_errors,
+            padding: Duration::ZERO,
+        })
     }
 
-    fn process_click_track(click_path: &Path, wav_st_dir: &Path) -> Result<PathBuf> {
-        Self::transcode_to_wav(click_path, wav_st_dir)
+    /// Each source with the stereo and mono WAV it ended up as. Stems whose pipeline trims
+    /// or fades them no longer line up with their source and are left out.
+    fn output_pairs(
+        transcoded: &[Transcoded],
+        stereo_paths: &[PathBuf],
+        mono_paths: &[PathBuf],
+        pipeline: &Pipeline,
+    ) -> Vec<OutputPair> {
+        transcoded
+            .iter()
+            .zip(stereo_paths.iter().zip(mono_paths))
+            .filter(|(stem, _)| {
+                let name = Self::normalize_track_name(&stem.wav.file_name().unwrap().to_string_lossy());
+                let keeps_timing = pipeline.keeps_timing(&name);
+                if !keeps_timing {
+                    tracing::info!("Not verifying {}: its pipeline trims or fades it", name);
+                }
+                keeps_timing
+            })
+            .map(|(stem, (stereo, mono))| OutputPair {
+                source: stem.source.clone(),
+                outputs: vec![stereo.clone(), mono.clone()],
+                padding: stem.padding,
+            })
+            .collect()
     }
 
-    fn process_non_click_tracks(dir: &Path, wav_st_dir: &Path, click_duration: Duration) -> Result<Vec<PathBuf>> {
-        let mut processed_paths = Vec::new();
-        for entry in std::fs::read_dir(dir)? {
-            let path = entry?.path();
-            if let Some(filename) = path.file_name().and_then(|n| n.to_str()) {
-                if !filename.to_lowercase().contains("click")
-                    && path.extension().map(|e| e == "mp3").unwrap_or(false)
-                {
-                    let output_path = wav_st_dir.join(path.file_name().unwrap()).with_extension("wav");
-                    let track_duration = Self::get_mp3_duration(&path)?;
-                    let padding_duration = click_duration.saturating_sub(track_duration);
-                    Self::apply_padding(&path, &output_path, padding_duration)?;
-                    processed_paths.push(output_path);
+    /// Measures each stereo output against the click's, which all line up from the start
+    /// once padded. Returns the stems that drift, keyed by their stereo WAV, with how far;
+    /// each is also warned about in `report`.
+    fn check_drift(
+        transcoded: &[Transcoded],
+        stereo_paths: &[PathBuf],
+        click_duration: Duration,
+        pipeline: &Pipeline,
+        report: &mut ProcessReport,
+    ) -> Result<HashMap<PathBuf, f64>> {
+        let mut drifts = HashMap::new();
+        let click = &stereo_paths[0];
+        for (stem, stereo) in transcoded.iter().zip(stereo_paths).skip(1) {
+            let name = Self::normalize_track_name(&stem.wav.file_name().unwrap().to_string_lossy());
+            if !pipeline.keeps_timing(&name) {
+                tracing::info!("Not checking {} for drift: its pipeline trims or fades it", name);
+                continue;
+            }
+            match drift::measure(click, stereo, Duration::ZERO, click_duration)? {
+                Some(check) if check.is_drifted() => {
+                    let warning = format!(
+                        "{} is time-drifted: it moves {:+.0} ms against the click over the song",
+                        name,
+                        check.drift_ms()
+                    );
+                    tracing::warn!("{}", warning);
+                    report.warnings.push(warning);
+                    drifts.insert(stereo.clone(), check.drift_ms());
                 }
+                Some(check) => tracing::debug!("{} keeps time with the click: {:?} ms", name, check.offsets_ms),
+                None => tracing::info!("Not enough onsets in {} to check it for drift", name),
             }
         }
-        Ok(processed_paths)
+        Ok(drifts)
     }
 
-    fn apply_padding(input_path: &Path, output_path: &Path, padding_duration: Duration) -> Result<()> {
-        let (spec, samples) = Self::decode_mp3(input_path)?;
-        
-        let mut writer = WavWriter::create(output_path, spec)?;
-        
-        // Calculate the number of padding samples
-        let padding_samples = (padding_duration.as_secs_f64() * spec.sample_rate as f64) as u32 * spec.channels as u32;
-        
-        // Add silence at the beginning
-        for _ in 0..padding_samples {
-            writer.write_sample(0i16)?;
-        }
+    /// Transcodes and pads the stems on `options.process_threads` threads, each holding its
+    /// stem's share of the memory budget while it's in memory.
+    fn process_non_click_tracks(
+        stems: &[StemPlan],
+        click_duration: Duration,
+        options: &ProcessOptions,
+    ) -> Result<Vec<Transcoded>> {
+        let budget = options.memory_budget.map_or_else(MemoryBudget::from_system, MemoryBudget::new);
+        let next = AtomicUsize::new(0);
+        let results: Mutex<Vec<Option<StemResult>>> = Mutex::new(stems.iter().map(|_| None).collect());
+        let threads = options.process_threads.clamp(1, stems.len().max(1));
+        std::thread::scope(|scope| {
+            for _ in 0..threads {
+                // Carry the song's span onto the worker
+                let span = tracing::Span::current();
+                let (next, results, budget) = (&next, &results, &budget);
+                scope.spawn(move || {
+                    span.in_scope(|| loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(stem) = stems.get(i) else { break };
+                        let result = if options.abort.is_requested() {
+                            Err(Interrupted.into())
+                        } else {
+                            Self::process_stem(stem, click_duration, &options.pipeline, budget)
+                        };
+                        results.lock().unwrap()[i] = Some(result);
+                    })
+                });
+            }
+        });
+
+        results
+            .into_inner()
+            .unwrap()
+            .into_iter()
+            .map(|result| result.expect("every stem is processed"))
+            .collect()
+    }
+
+    /// Transcodes and pads one stem once its share of the budget is free.
+    fn process_stem(
+        stem: &StemPlan,
+        click_duration: Duration,
+        pipeline: &Pipeline,
+        budget: &MemoryBudget,
+    ) -> StemResult {
+        let path = &stem.source;
+        let _stem = Self::stem_span(path).entered();
+        // Stems get padded to the click, so they take up at least that much
+        let duration = stem
+            .duration_secs
+            .map(Duration::from_secs_f64)
+            .unwrap_or_default()
+            .max(click_duration);
+        let spec = Self::decoded_spec(path)?;
+        let _permit = budget.acquire(budget::decoded_size(duration, spec.sample_rate, spec.channels));
+
+        // The plan's padding is from the headers; the decoded length is what lines up
+        let track_duration = Self::get_mp3_duration(path)?;
+        let padding_duration = click_duration.saturating_sub(track_duration);
+        let decode_errors = Self::apply_padding(path, &stem.transcoded, padding_duration, pipeline)?;
+        Ok(Transcoded {
+            source: path.to_path_buf(),
+            wav: stem.transcoded.clone(),
+            decode_errors,
+            padding: padding_duration,
+        })
+    }
+
+    fn apply_padding(input_path: &Path, output_path: &Path, padding_duration: Duration, pipeline: &Pipeline) -> Result<usize> {
+        let (spec, samples, errors) = Self::decode_mp3(input_path)?;
         
-        // Write original samples
-        for sample in samples {
-            writer.write_sample(sample)?;
-        }
-        Ok(())
+        let padding_samples = limits::padding_samples(padding_duration, spec.sample_rate, spec.channels, spec.bits_per_sample)
+            .with_context(|| format!("Unable to pad {:?}", input_path))?;
+
+        // Add silence at the beginning, then the original samples
+        let mut padded = vec![0i16; padding_samples];
+        padded.extend(samples);
+        Self::write_stem(output_path, spec, padded, pipeline)?;
+        Ok(errors)
     }
 
-    fn cleanup_mp3s(dir: &Path) -> Result<()> {
-        for entry in std::fs::read_dir(dir)? {
-            let path = entry?.path();
-            if path.extension().map(|e| e == "mp3").unwrap_or(false) {
-                std::fs::remove_file(path)?;
-            }
+    /// Removes the downloads the song's stems were decoded from, and nothing else of the
+    /// folder they're in.
+    fn cleanup_mp3s(stems: &[StemPlan]) -> Result<()> {
+        for stem in stems {
+            std::fs::remove_file(&stem.source)?;
         }
         Ok(())
     }
 
-    fn convert_to_mono(click_path: &Path, other_paths: &[PathBuf], wav_mono_dir: &Path) -> Result<Vec<PathBuf>> {
+    fn convert_to_mono(transcoded: &[Transcoded], stems: &[StemPlan]) -> Result<Vec<PathBuf>> {
         let mut mono_paths = Vec::new();
-
-        // Process click track
-        let mono_click_path = Self::stereo_to_mono(click_path, wav_mono_dir)?;
-        mono_paths.push(mono_click_path);
-
-        // Process other tracks
-        for path in other_paths {
-            let mono_path = Self::stereo_to_mono(path, wav_mono_dir)?;
-            mono_paths.push(mono_path);
+        for (stem, plan) in transcoded.iter().zip(stems) {
+            Self::stereo_to_mono(&stem.wav, &plan.mono)?;
+            mono_paths.push(plan.mono.clone());
         }
-
         Ok(mono_paths)
     }
 
-    fn stereo_to_mono(input_path: &Path, wav_mono_dir: &Path) -> Result<PathBuf> {
+    fn stereo_to_mono(input_path: &Path, output_path: &Path) -> Result<()> {
+        let _stem = Self::stem_span(input_path).entered();
         let mut reader = hound::WavReader::open(input_path)?;
         let spec = reader.spec();
         
@@ -370,12 +1363,8 @@ impl AudioProcessor {
             return Err(anyhow!("Input file is not stereo"));
         }
         
-        // Get the original filename and normalize it
-        let original_name = input_path.file_stem().unwrap().to_str().unwrap();
-        let normalized_name = Self::normalize_track_name(original_name);
-        let output_path = wav_mono_dir.join(format!("{}_mono.wav", normalized_name));
         let mut writer = hound::WavWriter::create(
-            &output_path,
+            output_path,
             WavSpec {
                 channels: 1,
                 sample_rate: spec.sample_rate,
@@ -384,223 +1373,330 @@ impl AudioProcessor {
             },
         )?;
 
-        let mut samples: Vec<i16> = reader.samples().map(|s| s.unwrap()).collect();
-        for chunk in samples.chunks_mut(2) {
-            let mono_sample = ((chunk[0] as i32 + chunk[1] as i32) / 2) as i16;
+        let samples = reader.samples::<i16>().collect::<Result<Vec<_>, _>>()?;
+        // A stray sample past the last whole frame is dropped rather than read past
+        for frame in samples.chunks_exact(2) {
+            let mono_sample = ((frame[0] as i32 + frame[1] as i32) / 2) as i16;
             writer.write_sample(mono_sample)?;
         }
 
         writer.finalize()?;
-        Ok(output_path)
-    }
-
-
-    fn generate_reaper_project(mt_project_dir: &Path, mono_paths: &[PathBuf], stems_dir: &Path) -> Result<()> {
-        let song_title = Self::extract_song_title(&stems_dir.parent().unwrap().file_name().unwrap().to_str().unwrap())?;
-        let formatted_title = Self::format_song_title(&song_title)?;
-        let project_path = mt_project_dir.join(format!("{}.rpp", formatted_title));
-        let mut file = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .truncate(true)
-            .open(project_path)?;
-
-        writeln!(file, "<REAPER_PROJECT 0.1 \"6.13/linux64\" 1681658689")?;
-        writeln!(file, "  TEMPO 120 4 4")?;
-        writeln!(file, "  MASTER_VOLUME 1 0 -1 -1 1")?;
-        writeln!(file, "  <METRONOME 6 2")?;
-        writeln!(file, "    VOL 0.25 0.125")?;
-        writeln!(file, "    FREQ 800 1600 1")?;
-        writeln!(file, "    BEATLEN 4")?;
-        writeln!(file, "    SAMPLES \"\" \"\"")?;
-        writeln!(file, "    PATTERN 2863311530 2863311529")?;
-        writeln!(file, "  >")?;
+        Ok(())
+    }
+
+
+    /// Writes a Reaper project for each stem set `options.rpp_stems` asks for. The sets are
+    /// checked before anything is written, so a missing folder fails the exporter instead of
+    /// leaving a project of dead references.
+    fn generate_reaper_project(
+        mt_project_dir: &Path,
+        mono_paths: &[PathBuf],
+        stems_dir: &Path,
+        options: &ProcessOptions,
+    ) -> Result<()> {
+        let formatted_title = Self::stems_song_title(stems_dir)?;
+
+        let mut projects = Vec::new();
+        for (set, suffix) in options.rpp_stems.sets() {
+            let paths: Vec<PathBuf> = mono_paths.iter().map(|mono| set.path_for(stems_dir, mono)).collect();
+            if let Some(missing) = paths.iter().find(|path| !path.is_file()) {
+                return Err(anyhow!(
+                    "The Reaper project uses the {} stems, but {:?} wasn't written",
+                    set.dir_name(),
+                    missing
+                ));
+            }
+            let file_name = match suffix {
+                Some(suffix) => format!("{} ({}).rpp", formatted_title, suffix),
+                None => format!("{}.rpp", formatted_title),
+            };
+            projects.push((mt_project_dir.join(file_name), paths));
+        }
+        for (project_path, paths) in projects {
+            Self::write_reaper_project(&project_path, &formatted_title, &paths, stems_dir, options)?;
+        }
+        Ok(())
+    }
+
+    /// Fills in `options.rpp_template` (the built-in one unless `--rpp-template` was given)
+    /// with a track per stem, a folder track per group from the layout and, when
+    /// `options.project_midi_track` asks for one, the MIDI track after them.
+    fn write_reaper_project(
+        project_path: &Path,
+        formatted_title: &str,
+        stem_paths: &[PathBuf],
+        stems_dir: &Path,
+        options: &ProcessOptions,
+    ) -> Result<()> {
+        let template = &options.rpp_template;
 
         let mut max_duration: f64 = 0.0;
+        let mut tracks = String::new();
 
-        for (i, path) in mono_paths.iter().enumerate() {
-            let is_click = path.file_stem().unwrap().to_str().unwrap().to_lowercase().contains("click");
-            let pan = if is_click { -1.0 } else { 1.0 };
+        let stem_names: Vec<String> = stem_paths
+            .iter()
+            .map(|path| path.file_stem().unwrap().to_string_lossy().to_string())
+            .collect();
+        let slots = options.reaper.arrange(&stem_names);
+
+        for (i, slot) in slots.iter().enumerate() {
+            let mut values = HashMap::from([
+                ("index", (i + 1).to_string()),
+                ("track_name", slot.name.clone()),
+                ("color", slot.color.peakcol().to_string()),
+                ("folder", slot.folder.isbus().to_string()),
+                ("guid", format!("{{7FE0D07C-DFA2-4D85-8A77-6AB24173DC8{}}}", i)),
+            ]);
+            let Some(stem) = slot.stem else {
+                template.render(Section::Folder, &values, &mut tracks);
+                continue;
+            };
+            let path = &stem_paths[stem];
+            let is_click = options.is_click(&slot.name);
 
             let wav_reader = hound::WavReader::open(path)?;
-            let duration_seconds = wav_reader.duration() as f64 / wav_reader.spec().sample_rate as f64;
+            let duration_seconds = limits::frames_to_secs(wav_reader.duration() as u64, wav_reader.spec().sample_rate);
+            // Reaper tracks have an even number of channels, two at the least
+            let channels = (wav_reader.spec().channels as usize).max(2).next_multiple_of(2);
             max_duration = max_duration.max(duration_seconds);
 
-            // Use the absolute path for the audio file
-            let absolute_path = path.canonicalize()?;
-            let file_path = absolute_path.to_str().unwrap().replace("\\", "/");
-
-            writeln!(file, "  <TRACK {}", i + 1)?;
-            writeln!(file, "    NAME \"{}\"", path.file_stem().unwrap().to_str().unwrap())?;
-            writeln!(file, "    PEAKCOL 16576")?;
-            writeln!(file, "    BEAT -1")?;
-            writeln!(file, "    AUTOMODE 0")?;
-            writeln!(file, "    VOLPAN 1 {} -1 -1 1", pan)?;
-            writeln!(file, "    MUTESOLO 0 0 0")?;
-            writeln!(file, "    IPHASE 0")?;
-            writeln!(file, "    ISBUS 0 0")?;
-            writeln!(file, "    BUSCOMP 0 0 0 0 0")?;
-            writeln!(file, "    SHOWINMIX 1 0.6667 0.5 1 0.5 0 -1 0")?;
-            writeln!(file, "    FREEMODE 0")?;
-            writeln!(file, "    SEL 0")?;
-            writeln!(file, "    REC 0 0 1 0 0 0 0")?;
-            writeln!(file, "    VU 2")?;
-            writeln!(file, "    TRACKHEIGHT 0 0 0 0 0 0")?;
-            writeln!(file, "    INQ 0 0 0 0.5 100 0 0 100")?;
-            writeln!(file, "    NCHAN 2")?;
-            writeln!(file, "    FX 1")?;
-            writeln!(file, "    TRACKID {{7FE0D07C-DFA2-4D85-8A77-6AB24173DC8{}}}", i)?;
-            writeln!(file, "    PERF 0")?;
-            writeln!(file, "    MIDIOUT -1")?;
-            writeln!(file, "    MAINSEND 1 0")?;
-            writeln!(file, "    <ITEM")?;
-            writeln!(file, "      POSITION 0")?;
-            writeln!(file, "      SNAPOFFS 0")?;
-            writeln!(file, "      LENGTH {}", duration_seconds)?;
-            writeln!(file, "      LOOP 1")?;
-            writeln!(file, "      ALLTAKES 0")?;
-            writeln!(file, "      FADEIN 1 0.01 0 1 0 0 0")?;
-            writeln!(file, "      FADEOUT 1 0.01 0 1 0 0 0")?;
-            writeln!(file, "      MUTE 0 0")?;
-            writeln!(file, "      SEL 0")?;
-            writeln!(file, "      IGUID {{EAE098FB-B9B0-4F57-9D7C-2656D9861A0{}}}", i)?;
-            writeln!(file, "      IID 1")?;
-            writeln!(file, "      NAME \"{}\"", path.file_name().unwrap().to_str().unwrap())?;
-            writeln!(file, "      VOLPAN 1 0 1 -1")?;
-            writeln!(file, "      SOFFS 0")?;
-            writeln!(file, "      PLAYRATE 1 1 0 -1 0 0.0025")?;
-            writeln!(file, "      CHANMODE 0")?;
-            writeln!(file, "      GUID {{5E5B68F0-4717-4D85-8A77-6AB24173DC8{}}}", i)?;
-            writeln!(file, "      <SOURCE WAVE")?;
-            writeln!(file, "        FILE \"{}\"", file_path)?;
-            writeln!(file, "      >")?;
-            writeln!(file, "    >")?;
-            writeln!(file, "  >")?;
-        }
-
-        // Add MIDI track
-        writeln!(file, "  <TRACK {}", mono_paths.len() + 1)?;
-        writeln!(file, "    NAME \"MIDI\"")?;
-        writeln!(file, "    PEAKCOL 16576")?;
-        writeln!(file, "    BEAT -1")?;
-        writeln!(file, "    AUTOMODE 0")?;
-        writeln!(file, "    VOLPAN 1 0 -1 -1 1")?;
-        writeln!(file, "    MUTESOLO 0 0 0")?;
-        writeln!(file, "    IPHASE 0")?;
-        writeln!(file, "    ISBUS 0 0")?;
-        writeln!(file, "    BUSCOMP 0 0 0 0 0")?;
-        writeln!(file, "    SHOWINMIX 1 0.6667 0.5 1 0.5 0 -1 0")?;
-        writeln!(file, "    FREEMODE 0")?;
-        writeln!(file, "    SEL 0")?;
-        writeln!(file, "    REC 1 5088 1 0 0 0 0")?;
-        writeln!(file, "    VU 2")?;
-        writeln!(file, "    TRACKHEIGHT 0 0 0 0 0 0")?;
-        writeln!(file, "    INQ 0 0 0 0.5 100 0 0 100")?;
-        writeln!(file, "    NCHAN 2")?;
-        writeln!(file, "    FX 1")?;
-        writeln!(file, "    TRACKID {{7FE0D07C-DFA2-4D85-8A77-6AB24173DC9{}}}", mono_paths.len())?;
-        writeln!(file, "    PERF 0")?;
-        writeln!(file, "    MIDIOUT -1")?;
-        writeln!(file, "    MAINSEND 1 0")?;
-        writeln!(file, "    <ITEM MIDI")?;
-        writeln!(file, "      POSITION 0")?;
-        writeln!(file, "      SNAPOFFS 0")?;
-        writeln!(file, "      LENGTH {}", max_duration)?;
-        writeln!(file, "      ALLTAKES 0")?;
-        writeln!(file, "      FADEIN 1 0.01 0 1 0 0 0")?;
-        writeln!(file, "      FADEOUT 1 0.01 0 1 0 0 0")?;
-        writeln!(file, "      MUTE 0 0")?;
-        writeln!(file, "      SEL 0")?;
-        writeln!(file, "      IGUID {{EAE098FB-B9B0-4F57-9D7C-2656D9861A1{}}}", mono_paths.len())?;
-        writeln!(file, "      IID 2")?;
-        writeln!(file, "      NAME \"MIDI\"")?;
-        writeln!(file, "      VOLPAN 1 0 1 -1")?;
-        writeln!(file, "      SOFFS 0")?;
-        writeln!(file, "      PLAYRATE 1 1 0 -1 0 0.0025")?;
-        writeln!(file, "      CHANMODE 0")?;
-        writeln!(file, "      GUID {{5E5B68F0-4717-4D85-8A77-6AB24173DC9{}}}", mono_paths.len())?;
-        writeln!(file, "      <SOURCE MIDI")?;
-        writeln!(file, "        HASDATA 1 960 QN")?;
-        writeln!(file, "        E 0 b0 7b 00")?;
-        // Calculate MIDI ticks: duration * (120 BPM * 960 PPQN / 60 seconds)
-        writeln!(file, "        E {} b0 7b 00", (max_duration * 120.0 * 960.0 / 60.0) as u32)?;        writeln!(file, "      >")?;
-        writeln!(file, "    >")?;
-        writeln!(file, "  >")?;
-
-        writeln!(file, ">")?;
+            // Built from the folder layout rather than the filesystem, so a stem renamed
+            // since it was written doesn't fail the whole project
+            let file_path = if options.rpp_absolute_paths {
+                forward_slashes(&std::path::absolute(path)?)
+            } else {
+                let relative = path.strip_prefix(stems_dir).unwrap_or(path);
+                format!("../STEMS/{}", forward_slashes(relative))
+            };
+
+            values.extend([
+                ("file_name", path.file_name().unwrap().to_string_lossy().to_string()),
+                ("file_path", file_path),
+                ("length", duration_seconds.to_string()),
+                ("channels", channels.to_string()),
+                ("pan", options.mix.pan(is_click).to_string()),
+                ("volume", options.mix.volume(is_click).to_string()),
+                // A track on its own hardware output stays out of the master
+                ("mainsend", if options.mix.output(is_click).is_some() { "0 0" } else { "1 0" }.to_string()),
+                (
+                    "hwout",
+                    // Mono output, post-fader, at unity
+                    options.mix.output(is_click).map_or(String::new(), |output| {
+                        format!("HWOUT {} 0 1 0 0 0 0 -1:U -1", 1024 + output - 1)
+                    }),
+                ),
+                ("item_guid", format!("{{EAE098FB-B9B0-4F57-9D7C-2656D9861A0{}}}", i)),
+                ("take_guid", format!("{{5E5B68F0-4717-4D85-8A77-6AB24173DC8{}}}", i)),
+            ]);
+            template.render(Section::Track, &values, &mut tracks);
+        }
+
+        let signature = options.time_signature.unwrap_or_default();
+        let click = Self::project_click_timing(stem_paths, options)?;
+        let tempo = match &click {
+            Some(timing) => ProjectTempo::from_click(timing, signature),
+            None => ProjectTempo {
+                bpm: options.tempo.map_or(DEFAULT_PROJECT_BPM, |bpm| bpm * 4.0 / signature.denominator as f64),
+                signature,
+            },
+        };
+
+        let mut project = String::new();
+        template.render(
+            Section::Header,
+            &HashMap::from([
+                ("title", formatted_title.to_string()),
+                ("length", max_duration.to_string()),
+                ("tempo", tempo.bpm.to_string()),
+                ("numerator", signature.numerator.to_string()),
+                ("denominator", signature.denominator.to_string()),
+            ]),
+            &mut project,
+        );
+        project.push_str(&tracks);
+        // The MIDI item spans the longest stem, in ticks at the project's tempo
+        if options.project_midi_track != ProjectMidiTrack::Off {
+            let notes = click.as_ref().filter(|_| options.project_midi_track == ProjectMidiTrack::ClickNotes);
+            let midi = HashMap::from([
+                ("index", (slots.len() + 1).to_string()),
+                ("length", max_duration.to_string()),
+                ("ppq", reaper::RPP_TICKS_PER_QUARTER.to_string()),
+                ("events", reaper::midi_item_events(&tempo, max_duration, notes)?),
+                ("guid", format!("{{7FE0D07C-DFA2-4D85-8A77-6AB24173DC9{}}}", slots.len())),
+                ("item_guid", format!("{{EAE098FB-B9B0-4F57-9D7C-2656D9861A1{}}}", slots.len())),
+                ("take_guid", format!("{{5E5B68F0-4717-4D85-8A77-6AB24173DC9{}}}", slots.len())),
+            ]);
+            template.render(Section::Midi, &midi, &mut project);
+        }
+        // Custom templates from before the MIDI block may still draw the MIDI track here
+        let length_ticks = tempo.ticks(max_duration)?;
+        let footer = HashMap::from([
+            ("title", formatted_title.to_string()),
+            ("index", (slots.len() + 1).to_string()),
+            ("length", max_duration.to_string()),
+            ("length_ticks", length_ticks.to_string()),
+            ("guid", format!("{{7FE0D07C-DFA2-4D85-8A77-6AB24173DC9{}}}", slots.len())),
+            ("item_guid", format!("{{EAE098FB-B9B0-4F57-9D7C-2656D9861A1{}}}", slots.len())),
+            ("take_guid", format!("{{5E5B68F0-4717-4D85-8A77-6AB24173DC9{}}}", slots.len())),
+        ]);
+        template.render(Section::Footer, &footer, &mut project);
+
+        std::fs::write(project_path, project)?;
+        tracing::debug!("Wrote {:?}", project_path);
         Ok(())
     }
 
-    fn generate_aaf(mt_project_dir: &Path, mono_paths: &[PathBuf], stems_dir: &Path) -> Result<()> {
-        let omf_path = mt_project_dir.join("project.omf");
-        let mut file = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .truncate(true)
-            .open(omf_path)?;
-    
-        // Write OMF header
-        file.write_all(b"FORM")?;
-        // 4-byte length placeholder
-        file.write_all(&[0, 0, 0, 0])?;
-        file.write_all(b"OMFI")?;
-        file.write_all(b"HEAD")?;
-        
-        // Write HEAD chunk length (4 bytes)
-        file.write_all(&[0, 0, 0, 24])?;
-        
-        // Write version (2.0)
-        file.write_all(&[0x02, 0x00])?;
-        
-        // Write byte order (big-endian)
-        file.write_all(&[0x00, 0x00])?;
-    
-        // Write time stamp (current time as 32-bit unix timestamp)
-        let timestamp = chrono::Utc::now().timestamp() as u32;
-        file.write_all(&timestamp.to_be_bytes())?;
-    
-        // Write MOBJ chunk header
-        file.write_all(b"MOBJ")?;
-        // Write MOBJ chunk length placeholder
-        let mobj_pos = file.stream_position()?;
-        file.write_all(&[0, 0, 0, 0])?;
-    
+    /// The click's timing, when the project's MIDI track needs it. Without a click stem the
+    /// empty item falls back to the default tempo, while click notes fail the project.
+    fn project_click_timing(stem_paths: &[PathBuf], options: &ProcessOptions) -> Result<Option<tempo::ClickTiming>> {
+        if options.project_midi_track == ProjectMidiTrack::Off {
+            return Ok(None);
+        }
+        let click = stem_paths
+            .iter()
+            .find(|path| options.is_click(&path.file_stem().unwrap().to_string_lossy()));
+        let timing = click
+            .ok_or_else(|| anyhow!("No click stem to take the tempo from"))
+            .and_then(|path| tempo::detect_click_timing(path, options.tempo));
+        match timing {
+            Ok(timing) => Ok(Some(timing)),
+            Err(e) if options.project_midi_track == ProjectMidiTrack::Empty => {
+                tracing::warn!("{}; the project's MIDI item is laid out at the --tempo or the default tempo", e);
+                Ok(None)
+            }
+            Err(e) => Err(e.context("Unable to place the click notes of the project's MIDI track")),
+        }
+    }
+
+    /// Writes a Live 11 set with the same layout as the Reaper project: one unwarped clip per
+    /// mono stem, panned and routed like the Reaper project.
+    fn generate_ableton_set(mt_project_dir: &Path, mono_paths: &[PathBuf], stems_dir: &Path, options: &ProcessOptions) -> Result<()> {
+        let mut tracks = Vec::new();
         for path in mono_paths {
-            let wav_reader = hound::WavReader::open(path)?;
-            let relative_path = path.strip_prefix(stems_dir)?;
-            let file_path = format!("STEMS/{}", relative_path.to_str().unwrap().replace("\\", "/"));
-            
-            // Write CLIP chunk
-            file.write_all(b"CLIP")?;
-            let clip_len_pos = file.stream_position()?;
-            file.write_all(&[0, 0, 0, 0])?;
-    
-            // Write file path
-            let path_bytes = file_path.as_bytes();
-            file.write_all(&(path_bytes.len() as u32).to_be_bytes())?;
-            file.write_all(path_bytes)?;
-    
-            // Write audio properties
-            file.write_all(&wav_reader.spec().sample_rate.to_be_bytes())?;
-            file.write_all(&wav_reader.spec().channels.to_be_bytes())?;
-            file.write_all(&wav_reader.duration().to_be_bytes())?;
-    
-            // Update CLIP chunk length
-            let current_pos = file.stream_position()?;
-            file.seek(SeekFrom::Start(clip_len_pos))?;
-            file.write_all(&((current_pos - clip_len_pos - 4) as u32).to_be_bytes())?;
-            file.seek(SeekFrom::Start(current_pos))?;
+            let stem = path.file_stem().unwrap().to_string_lossy();
+            // Named after the stem, not its mono WAV
+            let name = stem.strip_suffix("_mono").unwrap_or(&stem).to_string();
+            let is_click = options.is_click(&name);
+            let reader = hound::WavReader::open(path)?;
+            let mono_dir = path.parent().and_then(|p| p.file_name()).unwrap_or_default();
+            let file_name = path.file_name().unwrap().to_string_lossy();
+            tracks.push(AbletonTrack {
+                relative_path: format!("../STEMS/{}/{}", mono_dir.to_string_lossy(), file_name),
+                absolute_path: forward_slashes(&path.canonicalize()?),
+                sample_rate: reader.spec().sample_rate,
+                frames: reader.duration(),
+                file_size: std::fs::metadata(path)?.len(),
+                pan: options.mix.pan(is_click),
+                volume: options.mix.volume(is_click),
+                output: options.mix.output(is_click),
+                name,
+            });
         }
-    
-        // Update MOBJ chunk length
-        let end_pos = file.stream_position()?;
-        file.seek(SeekFrom::Start(mobj_pos))?;
-        file.write_all(&((end_pos - mobj_pos - 4) as u32).to_be_bytes())?;
-    
-        // Update total file length
-        file.seek(SeekFrom::Start(4))?;
-        file.write_all(&((end_pos - 8) as u32).to_be_bytes())?;
-    
+
+        let tempo = Self::session_tempo(mono_paths, options);
+        let formatted_title = Self::stems_song_title(stems_dir)?;
+        ableton::write_als(&mt_project_dir.join(format!("{}.als", formatted_title)), &tracks, tempo)
+    }
+
+    /// The clips in DAW sessions aren't warped, so the tempo only matters for the grid; fall
+    /// back to the DAWs' usual default when the click doesn't give one.
+    fn session_tempo(mono_paths: &[PathBuf], options: &ProcessOptions) -> f64 {
+        let click = mono_paths
+            .iter()
+            .find(|path| options.is_click(&path.file_stem().unwrap().to_string_lossy()));
+        options
+            .tempo
+            .or_else(|| click.and_then(|c| tempo::detect_click_timing(c, None).ok()).map(|t| t.bpm))
+            .unwrap_or(120.0)
+    }
+
+    /// Writes a `.dawproject` with the same tracks, colors and mix as the Reaper project.
+    fn generate_dawproject(mt_project_dir: &Path, mono_paths: &[PathBuf], stems_dir: &Path, options: &ProcessOptions) -> Result<()> {
+        let mut tracks = Vec::new();
+        for path in mono_paths {
+            let name = path.file_stem().unwrap().to_string_lossy().to_string();
+            let is_click = options.is_click(&name);
+            let reader = hound::WavReader::open(path)?;
+            let relative = path.strip_prefix(stems_dir).unwrap_or(path);
+            tracks.push(DawTrack {
+                color: options.reaper.color_for(&name),
+                source: path.clone(),
+                relative_path: format!("../STEMS/{}", forward_slashes(relative)),
+                sample_rate: reader.spec().sample_rate,
+                frames: reader.duration(),
+                pan: options.mix.pan(is_click),
+                volume: options.mix.volume(is_click),
+                name,
+            });
+        }
+
+        let formatted_title = Self::stems_song_title(stems_dir)?;
+        dawproject::write_dawproject(
+            &mt_project_dir.join(format!("{}.dawproject", formatted_title)),
+            &formatted_title,
+            &tracks,
+            Self::session_tempo(mono_paths, options),
+            options.time_signature.unwrap_or_default(),
+            options.dawproject_embed,
+        )
+    }
+
+    /// Writes a `.mid` with the click's tempo and a marker at bar 1, for players that sync
+    /// to a tempo map rather than a DAW session.
+    fn generate_midi_file(mt_project_dir: &Path, mono_paths: &[PathBuf], stems_dir: &Path, options: &ProcessOptions) -> Result<()> {
+        let click_path = mono_paths
+            .iter()
+            .find(|path| options.is_click(&path.file_stem().unwrap().to_string_lossy()))
+            .ok_or_else(|| anyhow!("No click stem to take the tempo from"))?;
+        let timing = tempo::detect_click_timing(click_path, options.tempo)?;
+
+        let reader = hound::WavReader::open(click_path)?;
+        let length = Duration::from_secs_f64(reader.duration() as f64 / reader.spec().sample_rate as f64);
+        let layout = MidiLayout {
+            timing,
+            signature: options.time_signature.unwrap_or_default(),
+            count_in_bars: options.count_in_bars,
+            count_in: options.midi_count_in,
+            length,
+        };
+
+        let formatted_title = Self::stems_song_title(stems_dir)?;
+        midi::write_midi_file(&mt_project_dir.join(format!("{}.mid", formatted_title)), &layout)?;
+        tracing::info!(
+            "Wrote MIDI file at {} BPM, first click at {:.3}s",
+            timing.bpm,
+            timing.first_beat.as_secs_f64()
+        );
         Ok(())
     }
-}
\ No newline at end of file
+
+    /// Writes an FCPXML timeline with every mono stem at position zero, the interchange
+    /// format for editors and DAWs that can't read the Reaper project (e.g. DaVinci Resolve).
+    fn generate_fcpxml(mt_project_dir: &Path, mono_paths: &[PathBuf], stems_dir: &Path) -> Result<()> {
+        let mut clips = Vec::new();
+        for path in mono_paths {
+            let reader = hound::WavReader::open(path)?;
+            let relative_path = path.strip_prefix(stems_dir)?;
+            clips.push(FcpClip {
+                name: path.file_stem().unwrap().to_string_lossy().to_string(),
+                relative_path: format!("../STEMS/{}", forward_slashes(relative_path)),
+                sample_rate: reader.spec().sample_rate,
+                frames: reader.duration(),
+            });
+        }
+
+        let formatted_title = Self::stems_song_title(stems_dir)?;
+        fcpxml::write_fcpxml(&mt_project_dir.join(format!("{}.fcpxml", formatted_title)), &formatted_title, &clips)
+    }
+}
+
+/// `path` with `/` separators, as the project files want them. Only Windows separators are
+/// converted; on other systems a backslash is part of a file name.
+fn forward_slashes(path: &Path) -> String {
+    let path = path.to_string_lossy();
+    if std::path::MAIN_SEPARATOR == '\\' {
+        path.replace('\\', "/")
+    } else {
+        path.into_owned()
+    }
+}
@@ -0,0 +1,363 @@
+use anyhow::Result;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use super::click::ClickDetection;
+use super::limits;
+use super::taxonomy::Instrument;
+use super::tempo::{ClickTiming, TimeSignature};
+
+/// Stems sharing their first word with more than this many others get a folder track.
+pub const DEFAULT_FOLDER_THRESHOLD: usize = 2;
+
+/// Default colors by instrument family; the others get a color from their name.
+fn instrument_color(instrument: Instrument) -> Option<Rgb> {
+    match instrument {
+        Instrument::Click => Some(Rgb(0x80, 0x80, 0x80)),
+        Instrument::Drums => Some(Rgb(0xD0, 0x30, 0x30)),
+        Instrument::Bass => Some(Rgb(0x30, 0x60, 0xD0)),
+        Instrument::Guitar => Some(Rgb(0xE0, 0x80, 0x20)),
+        Instrument::Keys => Some(Rgb(0x90, 0x40, 0xC0)),
+        Instrument::Vocals => Some(Rgb(0xE0, 0xC8, 0x20)),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Rgb(pub u8, pub u8, pub u8);
+
+impl Rgb {
+    /// The value of a track's `PEAKCOL`: the color in Reaper's native byte order with the
+    /// flag that marks it as a custom color.
+    pub fn peakcol(&self) -> u32 {
+        0x0100_0000 | (self.2 as u32) << 16 | (self.1 as u32) << 8 | self.0 as u32
+    }
+
+    /// A stable color for a name no instrument matches, so regenerating keeps it.
+    fn from_name(name: &str) -> Self {
+        // FNV-1a; std's hasher isn't guaranteed to be stable between releases
+        let hash = name
+            .to_lowercase()
+            .bytes()
+            .fold(0xcbf2_9ce4_8422_2325u64, |h, b| (h ^ b as u64).wrapping_mul(0x0100_0000_01b3));
+        // Keep every channel in the middle of the range so the color reads on dark and light themes
+        let channel = |shift: u32| 0x40 + ((hash >> shift) & 0x7F) as u8;
+        Self(channel(0), channel(8), channel(16))
+    }
+}
+
+impl FromStr for Rgb {
+    type Err = String;
+
+    /// Parses `#RRGGBB`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("expected a color like #FF8800, got '{}'", s);
+        let hex = s.trim().strip_prefix('#').ok_or_else(invalid)?;
+        if hex.len() != 6 {
+            return Err(invalid());
+        }
+        let value = u32::from_str_radix(hex, 16).map_err(|_| invalid())?;
+        Ok(Self((value >> 16) as u8, (value >> 8) as u8, value as u8))
+    }
+}
+
+impl TryFrom<String> for Rgb {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl Display for Rgb {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "#{:02X}{:02X}{:02X}", self.0, self.1, self.2)
+    }
+}
+
+/// Which of the WAV folders the Reaper project plays.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RppStems {
+    /// `STEMS/WAV MONO`, as the project has always used.
+    #[default]
+    Mono,
+    /// `STEMS/WAV ST`, keeping the stereo image of e.g. the drums.
+    Stereo,
+    /// One project of each, `Song (Mono).rpp` and `Song (Stereo).rpp`.
+    Both,
+}
+
+impl RppStems {
+    /// The stem folders to write a project for, with the suffix that tells the projects
+    /// apart when there is more than one.
+    pub fn sets(&self) -> &'static [(StemSet, Option<&'static str>)] {
+        match self {
+            Self::Mono => &[(StemSet::Mono, None)],
+            Self::Stereo => &[(StemSet::Stereo, None)],
+            Self::Both => &[(StemSet::Mono, Some("Mono")), (StemSet::Stereo, Some("Stereo"))],
+        }
+    }
+}
+
+impl FromStr for RppStems {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "mono" => Ok(Self::Mono),
+            "stereo" => Ok(Self::Stereo),
+            "both" => Ok(Self::Both),
+            _ => Err(format!("expected 'stereo', 'mono' or 'both', got '{}'", s)),
+        }
+    }
+}
+
+/// Ticks per quarter note of the project's MIDI item.
+pub const RPP_TICKS_PER_QUARTER: u32 = 960;
+/// Project tempo when the click's isn't known.
+pub const DEFAULT_PROJECT_BPM: f64 = 120.0;
+/// GM percussion, on channel 10: hi wood block on the downbeats, low on the other beats.
+const DOWNBEAT_NOTE: u8 = 76;
+const BEAT_NOTE: u8 = 77;
+
+/// What the Reaper project's MIDI track holds, if it has one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ProjectMidiTrack {
+    /// No MIDI track.
+    #[default]
+    Off,
+    /// An empty item as long as the stems, to record or draw into.
+    Empty,
+    /// A note on every click, accented on the downbeats, to trigger lights or samples from.
+    ClickNotes,
+}
+
+impl FromStr for ProjectMidiTrack {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "off" => Ok(Self::Off),
+            "empty" => Ok(Self::Empty),
+            "click-notes" => Ok(Self::ClickNotes),
+            _ => Err(format!("expected 'off', 'empty' or 'click-notes', got '{}'", s)),
+        }
+    }
+}
+
+/// The project's tempo map: one tempo and time signature from the start.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ProjectTempo {
+    /// Quarter notes per minute, the way Reaper counts it.
+    pub bpm: f64,
+    pub signature: TimeSignature,
+}
+
+impl ProjectTempo {
+    /// The click's tempo, whose beats are `signature`'s beats.
+    pub fn from_click(timing: &ClickTiming, signature: TimeSignature) -> Self {
+        Self {
+            bpm: timing.bpm * 4.0 / signature.denominator as f64,
+            signature,
+        }
+    }
+
+    /// `secs` into the project, in MIDI item ticks.
+    pub fn ticks(&self, secs: f64) -> Result<u32> {
+        limits::secs_to_ticks(secs, RPP_TICKS_PER_QUARTER as f64 * self.bpm / 60.0)
+    }
+}
+
+/// The `E` lines of the MIDI item's source, `length` seconds long. Reaper gives each event
+/// as the ticks since the one before it. The item always ends in an all-notes-off at its
+/// end, which is also what sets its length; `click` adds the click's notes before that.
+pub fn midi_item_events(tempo: &ProjectTempo, length: f64, click: Option<&ClickTiming>) -> Result<String> {
+    let end = tempo.ticks(length)?;
+    // (tick, status, data 1, data 2)
+    let mut events: Vec<(u32, u8, u8, u8)> = vec![(0, 0xb0, 0x7b, 0x00)];
+    if let Some(click) = click {
+        let beat = click.beat_length().as_secs_f64();
+        // A 32nd note, short enough not to overlap the next click at any sane tempo
+        let note_ticks = RPP_TICKS_PER_QUARTER / 8;
+        let mut index: u32 = 0;
+        loop {
+            let on = tempo.ticks(click.first_beat.as_secs_f64() + index as f64 * beat)?;
+            if on >= end {
+                break;
+            }
+            let downbeat = index.is_multiple_of(tempo.signature.numerator as u32);
+            let (note, velocity) = if downbeat { (DOWNBEAT_NOTE, 127) } else { (BEAT_NOTE, 100) };
+            events.push((on, 0x99, note, velocity));
+            events.push(((on + note_ticks).min(end), 0x89, note, 0));
+            index += 1;
+        }
+    }
+    events.push((end, 0xb0, 0x7b, 0x00));
+    // Stable, so each note-off stays after its note-on
+    events.sort_by_key(|event| event.0);
+
+    let mut lines = Vec::with_capacity(events.len());
+    let mut last = 0;
+    for (tick, status, data1, data2) in events {
+        lines.push(format!("E {} {:02x} {:02x} {:02x}", tick - last, status, data1, data2));
+        last = tick;
+    }
+    Ok(lines.join("\n"))
+}
+
+/// One of the folders of WAV stems.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StemSet {
+    Mono,
+    Stereo,
+}
+
+impl StemSet {
+    /// The folder inside `STEMS`.
+    pub fn dir_name(&self) -> &'static str {
+        match self {
+            Self::Mono => "WAV MONO",
+            Self::Stereo => "WAV ST",
+        }
+    }
+
+    /// Where the stem written as `mono` lives in this set: `WAV ST/Bass.wav` for
+    /// `WAV MONO/Bass_mono.wav`.
+    pub fn path_for(&self, stems_dir: &Path, mono: &Path) -> PathBuf {
+        match self {
+            Self::Mono => mono.to_path_buf(),
+            Self::Stereo => {
+                let stem = mono.file_stem().unwrap_or_default().to_string_lossy();
+                let name = stem.strip_suffix("_mono").unwrap_or(&stem);
+                stems_dir.join(self.dir_name()).join(format!("{}.wav", name))
+            }
+        }
+    }
+}
+
+/// How a track sits in Reaper's folder hierarchy, written as its `ISBUS` line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FolderDepth {
+    None,
+    /// A folder track; the tracks after it are inside.
+    Open,
+    /// The last track of a folder.
+    Close,
+}
+
+impl FolderDepth {
+    pub fn isbus(&self) -> &'static str {
+        match self {
+            Self::None => "0 0",
+            Self::Open => "1 1",
+            Self::Close => "2 -1",
+        }
+    }
+}
+
+/// One track of the project, in order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrackSlot {
+    /// Index of the stem the track plays, or `None` for a folder track.
+    pub stem: Option<usize>,
+    pub name: String,
+    pub color: Rgb,
+    pub folder: FolderDepth,
+}
+
+/// Colors and folders of the tracks in the Reaper project, from the `[reaper]` config.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct ReaperLayout {
+    /// Colors by track name, matched case-insensitively as a substring like the pipeline's
+    /// `tracks`. These win over the built-in instrument colors.
+    pub colors: BTreeMap<String, Rgb>,
+    pub folder_threshold: usize,
+}
+
+impl Default for ReaperLayout {
+    fn default() -> Self {
+        Self {
+            colors: BTreeMap::new(),
+            folder_threshold: DEFAULT_FOLDER_THRESHOLD,
+        }
+    }
+}
+
+impl ReaperLayout {
+    pub fn color_for(&self, track_name: &str) -> Rgb {
+        let name = track_name.to_lowercase();
+        // Longest match first, so "lead vocal" can override "vocal"
+        let configured = self
+            .colors
+            .iter()
+            .filter(|(pattern, _)| name.contains(&pattern.to_lowercase()))
+            .max_by_key(|(pattern, _)| pattern.len())
+            .map(|(_, color)| *color);
+        configured
+            .or_else(|| Instrument::classify(&name).and_then(instrument_color))
+            .unwrap_or_else(|| Rgb::from_name(&name))
+    }
+
+    /// Orders the stems into tracks. Stems sharing a first word with more than
+    /// `folder_threshold` others are moved together under a folder track named after that
+    /// word, placed where the first of them was. The click is never put in a folder.
+    pub fn arrange(&self, stem_names: &[String]) -> Vec<TrackSlot> {
+        let prefixes: Vec<Option<String>> = stem_names
+            .iter()
+            .map(|name| {
+                let first = name.split([' ', '_', '-']).next().unwrap_or_default();
+                (!first.is_empty() && !ClickDetection::default().is_click(name)).then(|| first.to_lowercase())
+            })
+            .collect();
+        let group_size = |prefix: &str| prefixes.iter().filter(|p| p.as_deref() == Some(prefix)).count();
+
+        let mut slots = Vec::new();
+        let mut placed = vec![false; stem_names.len()];
+        for (i, name) in stem_names.iter().enumerate() {
+            if placed[i] {
+                continue;
+            }
+            let prefix = prefixes[i].as_deref().filter(|p| group_size(p) > self.folder_threshold);
+            let Some(prefix) = prefix else {
+                placed[i] = true;
+                slots.push(self.stem_slot(i, name, FolderDepth::None));
+                continue;
+            };
+
+            let folder_name = name.split([' ', '_', '-']).next().unwrap_or_default().to_string();
+            slots.push(TrackSlot {
+                stem: None,
+                color: self.color_for(&folder_name),
+                name: folder_name,
+                folder: FolderDepth::Open,
+            });
+            let members: Vec<usize> = (i..stem_names.len())
+                .filter(|&j| prefixes[j].as_deref() == Some(prefix))
+                .collect();
+            for (n, &j) in members.iter().enumerate() {
+                placed[j] = true;
+                let depth = if n + 1 == members.len() {
+                    FolderDepth::Close
+                } else {
+                    FolderDepth::None
+                };
+                slots.push(self.stem_slot(j, &stem_names[j], depth));
+            }
+        }
+        slots
+    }
+
+    fn stem_slot(&self, index: usize, name: &str, folder: FolderDepth) -> TrackSlot {
+        TrackSlot {
+            stem: Some(index),
+            name: name.to_string(),
+            color: self.color_for(name),
+            folder,
+        }
+    }
+}
@@ -0,0 +1,262 @@
+use anyhow::{anyhow, bail, Context, Result};
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// The project the generator has always written, as a template.
+pub const DEFAULT_TEMPLATE: &str = include_str!("templates/default.rpp");
+
+/// A part of the template, filled in once (`Header`, `Footer`) or once per track.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Section {
+    /// Everything before the first block.
+    Header,
+    /// `{{#track}}` … `{{/track}}`, repeated for every stem.
+    Track,
+    /// `{{#folder}}` … `{{/folder}}`, repeated for every folder track.
+    Folder,
+    /// `{{#midi}}` … `{{/midi}}`, the MIDI track after the stems when there is one.
+    Midi,
+    /// Everything after the first block that isn't in a block.
+    Footer,
+}
+
+impl Section {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Header => "header",
+            Self::Track => "track",
+            Self::Folder => "folder",
+            Self::Midi => "midi",
+            Self::Footer => "footer",
+        }
+    }
+
+    /// The placeholders the generator fills in for this section.
+    pub fn placeholders(&self) -> &'static [&'static str] {
+        match self {
+            Self::Header => &["title", "length", "tempo", "numerator", "denominator"],
+            Self::Track => &[
+                "index",
+                "track_name",
+                "file_name",
+                "file_path",
+                "length",
+                "channels",
+                "pan",
+                "volume",
+                "mainsend",
+                "hwout",
+                "color",
+                "folder",
+                "guid",
+                "item_guid",
+                "take_guid",
+            ],
+            Self::Folder => &["index", "track_name", "color", "folder", "guid"],
+            Self::Midi => &["index", "length", "ppq", "events", "guid", "item_guid", "take_guid"],
+            Self::Footer => &["title", "index", "length", "length_ticks", "guid", "item_guid", "take_guid"],
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Piece {
+    Text(String),
+    Placeholder(&'static str),
+    /// A placeholder between double quotes, written as an RPP string with [`quote`].
+    Quoted(&'static str),
+    /// A placeholder alone on its line; the line is left out when the value is empty, and
+    /// each line of a value of several is indented the same.
+    Line { indent: String, name: &'static str },
+}
+
+/// A Reaper project template: a header, a `{{#track}}` block written once per stem, an
+/// optional `{{#folder}}` block for folder tracks, an optional `{{#midi}}` block for the
+/// MIDI track and a footer, with `{{placeholder}}`s
+/// filled in by the generator. Parsing checks every placeholder against its section, so a
+/// broken template fails when it's loaded rather than after the downloads.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReaperTemplate {
+    sections: HashMap<Section, Vec<Piece>>,
+}
+
+impl Default for ReaperTemplate {
+    fn default() -> Self {
+        Self::parse(DEFAULT_TEMPLATE).expect("built-in Reaper template is valid")
+    }
+}
+
+impl ReaperTemplate {
+    pub fn load(path: &Path) -> Result<Self> {
+        let data = fs::read_to_string(path).with_context(|| format!("Unable to read Reaper template {:?}", path))?;
+        Self::parse(&data).with_context(|| format!("Invalid Reaper template {:?}", path))
+    }
+
+    /// Parses a template. Block markers go on lines of their own. A line holding nothing
+    /// but a placeholder is left out when the placeholder is empty, like `{{hwout}}` on
+    /// tracks that go to the master. A placeholder in double quotes, like
+    /// `NAME "{{track_name}}"`, is written as an RPP string, so names with quotes in them
+    /// don't break the project. A template without a folder or MIDI block uses the built-in
+    /// one.
+    pub fn parse(data: &str) -> Result<Self> {
+        let mut text: HashMap<Section, String> = HashMap::new();
+        let mut open: Option<(Section, usize)> = None;
+        let mut seen_block = false;
+
+        for (number, line) in data.split_inclusive('\n').enumerate().map(|(i, line)| (i + 1, line)) {
+            let marker = line.trim();
+            if let Some(name) = marker.strip_prefix("{{#").and_then(|m| m.strip_suffix("}}")) {
+                let section = block(name).ok_or_else(|| anyhow!("Unknown block {{{{#{}}}}} on line {}", name, number))?;
+                if let Some((inside, _)) = open {
+                    bail!("{{{{#{}}}}} on line {} is inside the {} block", name, number, inside.name());
+                }
+                if text.contains_key(&section) {
+                    bail!("Second {} block on line {}", section.name(), number);
+                }
+                text.insert(section, String::new());
+                open = Some((section, number));
+                seen_block = true;
+            } else if let Some(name) = marker.strip_prefix("{{/").and_then(|m| m.strip_suffix("}}")) {
+                match open {
+                    Some((section, _)) if block(name) == Some(section) => open = None,
+                    _ => bail!("{} on line {} doesn't close an open block", marker, number),
+                }
+            } else {
+                let section = match open {
+                    Some((section, _)) => section,
+                    None if seen_block => Section::Footer,
+                    None => Section::Header,
+                };
+                text.entry(section).or_default().push_str(line);
+            }
+        }
+        if let Some((section, number)) = open {
+            bail!("The {} block opened on line {} is never closed", section.name(), number);
+        }
+        if !text.contains_key(&Section::Track) {
+            bail!("No {{{{#track}}}} … {{{{/track}}}} block");
+        }
+
+        let mut sections = HashMap::new();
+        for (section, text) in text {
+            let pieces = pieces(section, &text).with_context(|| format!("In the {} of the template", section.name()))?;
+            sections.insert(section, pieces);
+        }
+        for section in [Section::Folder, Section::Midi] {
+            if let Entry::Vacant(missing) = sections.entry(section) {
+                missing.insert(Self::default().sections.remove(&section).unwrap_or_default());
+            }
+        }
+        Ok(Self { sections })
+    }
+
+    /// Appends `section` to `out` with its placeholders replaced by `values`.
+    pub fn render(&self, section: Section, values: &HashMap<&str, String>, out: &mut String) {
+        for piece in self.sections.get(&section).into_iter().flatten() {
+            match piece {
+                Piece::Text(text) => out.push_str(text),
+                Piece::Placeholder(name) => out.push_str(values.get(name).map_or("", String::as_str)),
+                Piece::Quoted(name) => out.push_str(&quote(values.get(name).map_or("", String::as_str))),
+                Piece::Line { indent, name } => {
+                    if let Some(value) = values.get(name).filter(|value| !value.is_empty()) {
+                        for line in value.lines() {
+                            out.push_str(indent);
+                            out.push_str(line);
+                            out.push('\n');
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn block(name: &str) -> Option<Section> {
+    match name {
+        "track" => Some(Section::Track),
+        "folder" => Some(Section::Folder),
+        "midi" => Some(Section::Midi),
+        _ => None,
+    }
+}
+
+/// Splits a section's text at its `{{placeholder}}`s.
+fn pieces(section: Section, text: &str) -> Result<Vec<Piece>> {
+    let mut pieces = Vec::new();
+    // Start of the text not yet in a piece
+    let mut from = 0;
+    while let Some(found) = text[from..].find("{{") {
+        let start = from + found;
+        let end = text[start..]
+            .find("}}")
+            .map(|end| start + end)
+            .ok_or_else(|| anyhow!("Unclosed {{{{ in {:?}", line_at(text, start)))?;
+        let name = text[start + 2..end].trim();
+        let placeholder = section
+            .placeholders()
+            .iter()
+            .find(|p| **p == name)
+            .ok_or_else(|| {
+                anyhow!(
+                    "Unknown placeholder {{{{{}}}}} in {:?}; the {} can use {}",
+                    name,
+                    line_at(text, start),
+                    section.name(),
+                    section.placeholders().join(", ")
+                )
+            })?;
+
+        let line_start = text[..start].rfind('\n').map_or(0, |i| i + 1);
+        let alone = line_start >= from
+            && text[line_start..start].trim().is_empty()
+            && text[end + 2..].starts_with('\n');
+        let quoted = !alone && start > from && text[..start].ends_with('"') && text[end + 2..].starts_with('"');
+        let text_end = if alone {
+            line_start
+        } else if quoted {
+            start - 1
+        } else {
+            start
+        };
+        if text_end > from {
+            pieces.push(Piece::Text(text[from..text_end].to_string()));
+        }
+        if quoted {
+            pieces.push(Piece::Quoted(placeholder));
+            from = end + 3;
+        } else if alone {
+            pieces.push(Piece::Line {
+                indent: text[line_start..start].to_string(),
+                name: placeholder,
+            });
+            from = end + 3;
+        } else {
+            pieces.push(Piece::Placeholder(placeholder));
+            from = end + 2;
+        }
+    }
+    if from < text.len() {
+        pieces.push(Piece::Text(text[from..].to_string()));
+    }
+    Ok(pieces)
+}
+
+/// `value` as an RPP string. Reaper has no escapes; it quotes with `"`, `'` or `` ` ``,
+/// whichever the value doesn't contain, and when it contains all three its backticks become
+/// `'`. Line breaks would end the line, so they become spaces.
+pub fn quote(value: &str) -> String {
+    let value = value.replace(['\r', '\n'], " ");
+    match ['"', '\'', '`'].into_iter().find(|q| !value.contains(*q)) {
+        Some(q) => format!("{q}{value}{q}"),
+        None => format!("`{}`", value.replace('`', "'")),
+    }
+}
+
+/// The line of `text` around byte `at`, to point at in errors.
+fn line_at(text: &str, at: usize) -> &str {
+    let start = text[..at].rfind('\n').map_or(0, |i| i + 1);
+    let end = text[at..].find('\n').map_or(text.len(), |i| at + i);
+    text[start..end].trim()
+}
@@ -0,0 +1,122 @@
+use anyhow::{anyhow, Result};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// A chunk found at the top level of a RIFF/WAVE file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chunk {
+    pub id: [u8; 4],
+    pub data: Vec<u8>,
+}
+
+/// Reads all top-level chunks of a WAV file.
+pub fn read_chunks(path: &Path) -> Result<Vec<Chunk>> {
+    let bytes = std::fs::read(path)?;
+    if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+        return Err(anyhow!("{:?} is not a RIFF/WAVE file", path));
+    }
+
+    let mut chunks = Vec::new();
+    let mut pos = 12;
+    while pos + 8 <= bytes.len() {
+        let id: [u8; 4] = bytes[pos..pos + 4].try_into()?;
+        let size = u32::from_le_bytes(bytes[pos + 4..pos + 8].try_into()?) as usize;
+        let start = pos + 8;
+        let end = start
+            .checked_add(size)
+            .filter(|end| *end <= bytes.len())
+            .ok_or_else(|| anyhow!("Chunk {:?} overruns {:?}", String::from_utf8_lossy(&id), path))?;
+        chunks.push(Chunk {
+            id,
+            data: bytes[start..end].to_vec(),
+        });
+        // chunks are word aligned
+        pos = end + (size & 1);
+    }
+    Ok(chunks)
+}
+
+/// The data of the first top-level chunk `id` of a WAV file, seeking past the others rather
+/// than reading the audio.
+pub fn find_chunk(path: &Path, id: &[u8; 4]) -> Result<Option<Vec<u8>>> {
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+    let mut header = [0; 12];
+    file.read_exact(&mut header)
+        .map_err(|_| anyhow!("{:?} is not a RIFF/WAVE file", path))?;
+    if &header[0..4] != b"RIFF" || &header[8..12] != b"WAVE" {
+        return Err(anyhow!("{:?} is not a RIFF/WAVE file", path));
+    }
+
+    let mut pos = 12;
+    while pos + 8 <= len {
+        let mut chunk_header = [0; 8];
+        file.read_exact(&mut chunk_header)?;
+        let size = u32::from_le_bytes(chunk_header[4..8].try_into()?) as u64;
+        let end = pos + 8 + size;
+        if end > len {
+            return Err(anyhow!("Chunk {:?} overruns {:?}", String::from_utf8_lossy(&chunk_header[0..4]), path));
+        }
+        if &chunk_header[0..4] == id {
+            let mut data = vec![0; size as usize];
+            file.read_exact(&mut data)?;
+            return Ok(Some(data));
+        }
+        // chunks are word aligned
+        pos = end + (size & 1);
+        file.seek(SeekFrom::Start(pos))?;
+    }
+    Ok(None)
+}
+
+/// Appends a chunk to the end of a WAV file and fixes up the RIFF size. Readers that don't
+/// know the chunk skip it, so the audio itself is unaffected. An existing chunk with the same
+/// id is not replaced; use [`replace_chunk`] for that.
+pub fn append_chunk(path: &Path, id: &[u8; 4], data: &[u8]) -> Result<()> {
+    let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+    let len = file.seek(SeekFrom::End(0))?;
+    if len % 2 == 1 {
+        file.write_all(&[0])?;
+    }
+
+    let size = u32::try_from(data.len()).map_err(|_| anyhow!("Chunk too large for a WAV file"))?;
+    file.write_all(id)?;
+    file.write_all(&size.to_le_bytes())?;
+    file.write_all(data)?;
+    if data.len() % 2 == 1 {
+        file.write_all(&[0])?;
+    }
+
+    let total = file.stream_position()?;
+    let riff_size =
+        u32::try_from(total - 8).map_err(|_| anyhow!("{:?} would exceed the 4 GB WAV limit", path))?;
+    file.seek(SeekFrom::Start(4))?;
+    file.write_all(&riff_size.to_le_bytes())?;
+    Ok(())
+}
+
+/// Like [`append_chunk`], but drops any existing chunks with the same id first, so re-running
+/// doesn't stack duplicates.
+pub fn replace_chunk(path: &Path, id: &[u8; 4], data: &[u8]) -> Result<()> {
+    let chunks = read_chunks(path)?;
+    if chunks.iter().any(|c| &c.id == id) {
+        let mut out = Vec::new();
+        out.extend_from_slice(b"RIFF");
+        out.extend_from_slice(&[0; 4]);
+        out.extend_from_slice(b"WAVE");
+        for chunk in chunks.iter().filter(|c| &c.id != id) {
+            out.extend_from_slice(&chunk.id);
+            out.extend_from_slice(&(chunk.data.len() as u32).to_le_bytes());
+            out.extend_from_slice(&chunk.data);
+            if chunk.data.len() % 2 == 1 {
+                out.push(0);
+            }
+        }
+        let riff_size = (out.len() - 8) as u32;
+        out[4..8].copy_from_slice(&riff_size.to_le_bytes());
+        let mut file = OpenOptions::new().write(true).truncate(true).open(path)?;
+        file.write_all(&out)?;
+    }
+    append_chunk(path, id, data)
+}
@@ -0,0 +1,88 @@
+use serde::Serialize;
+use std::fmt::Display;
+
+/// The instrument family of a stem, told from its track name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Instrument {
+    Click,
+    Drums,
+    Bass,
+    Guitar,
+    Keys,
+    Vocals,
+    Saxophone,
+    Brass,
+    Woodwinds,
+    Strings,
+}
+
+/// Keywords of each family, matched in order against the lowercased track name, so
+/// "bass drum" is a drum and "bassoon" isn't a bass.
+const KEYWORDS: &[(Instrument, &[&str])] = &[
+    (Instrument::Click, &["click", "count"]),
+    (
+        Instrument::Drums,
+        &["drum", "kick", "snare", "hi-hat", "hihat", "tom", "overhead", "cymbal", "percussion"],
+    ),
+    (Instrument::Woodwinds, &["bassoon", "flute", "clarinet", "oboe", "piccolo", "woodwind"]),
+    (Instrument::Bass, &["bass"]),
+    (Instrument::Guitar, &["guitar"]),
+    (Instrument::Keys, &["piano", "keys", "keyboard", "organ", "synth", "rhodes"]),
+    (Instrument::Vocals, &["vocal", "vox", "voice", "choir"]),
+    (Instrument::Saxophone, &["sax"]),
+    (Instrument::Brass, &["brass", "trumpet", "trombone", "horn", "tuba", "cornet"]),
+    (Instrument::Strings, &["string", "violin", "viola", "cello", "fiddle"]),
+];
+
+/// Query words shorter than this only match a family by its name, not a keyword prefix.
+const MIN_PREFIX: usize = 3;
+
+impl Instrument {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Click => "click",
+            Self::Drums => "drums",
+            Self::Bass => "bass",
+            Self::Guitar => "guitar",
+            Self::Keys => "keys",
+            Self::Vocals => "vocals",
+            Self::Saxophone => "saxophone",
+            Self::Brass => "brass",
+            Self::Woodwinds => "woodwinds",
+            Self::Strings => "strings",
+        }
+    }
+
+    /// The family `track_name` belongs to, if any keyword names it.
+    pub fn classify(track_name: &str) -> Option<Self> {
+        let name = track_name.to_lowercase();
+        KEYWORDS
+            .iter()
+            .find(|(_, keywords)| keywords.iter().any(|k| name.contains(k)))
+            .map(|(instrument, _)| *instrument)
+    }
+
+    /// The families a search word stands for: "sax" and "saxophone" both mean
+    /// [`Instrument::Saxophone`], "vocals" means [`Instrument::Vocals`].
+    pub fn from_query(word: &str) -> Vec<Self> {
+        let word = word.trim().to_lowercase();
+        KEYWORDS
+            .iter()
+            .filter(|(instrument, keywords)| {
+                let name = instrument.name();
+                name == word
+                    || (word.len() >= MIN_PREFIX
+                        && (name.starts_with(&word)
+                            || std::iter::once(&name).chain(keywords.iter()).any(|k| word.starts_with(k))))
+            })
+            .map(|(instrument, _)| *instrument)
+            .collect()
+    }
+}
+
+impl Display for Instrument {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
@@ -0,0 +1,147 @@
+use anyhow::{anyhow, Result};
+use std::fmt::Display;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// How many bars KV plays before the song when the count-in is enabled.
+pub const COUNT_IN_BARS: u32 = 1;
+
+/// Peaks quieter than this fraction of the loudest one aren't clicks.
+const ONSET_THRESHOLD: f64 = 0.3;
+/// Window the click's amplitude is measured over.
+const WINDOW: Duration = Duration::from_millis(5);
+/// Minimum gap between two clicks; nothing plays faster than 600 BPM.
+const MIN_GAP: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeSignature {
+    pub numerator: u8,
+    /// Note value of one beat (4 = quarter note). Always a power of two.
+    pub denominator: u8,
+}
+
+impl Default for TimeSignature {
+    fn default() -> Self {
+        Self {
+            numerator: 4,
+            denominator: 4,
+        }
+    }
+}
+
+impl FromStr for TimeSignature {
+    type Err = String;
+
+    /// Parses `N/D`, e.g. `4/4` or `6/8`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("expected a time signature like 4/4, got '{}'", s);
+        let (numerator, denominator) = s.trim().split_once('/').ok_or_else(invalid)?;
+        let numerator: u8 = numerator.trim().parse().map_err(|_| invalid())?;
+        let denominator: u8 = denominator.trim().parse().map_err(|_| invalid())?;
+        if numerator == 0 || !denominator.is_power_of_two() || denominator > 64 {
+            return Err(invalid());
+        }
+        Ok(Self {
+            numerator,
+            denominator,
+        })
+    }
+}
+
+impl Display for TimeSignature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.numerator, self.denominator)
+    }
+}
+
+/// Where the click's beats fall on the stems' timeline.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClickTiming {
+    pub bpm: f64,
+    /// Position of the first click, which is the start of the count-in when there is one.
+    pub first_beat: Duration,
+}
+
+impl ClickTiming {
+    pub fn beat_length(&self) -> Duration {
+        Duration::from_secs_f64(60.0 / self.bpm)
+    }
+
+    /// Position of the first downbeat of the song proper, after `count_in_bars` bars.
+    pub fn downbeat(&self, signature: TimeSignature, count_in_bars: u32) -> Duration {
+        self.first_beat + self.beat_length() * (count_in_bars * signature.numerator as u32)
+    }
+}
+
+/// Finds the clicks in a click stem and derives the tempo from the median gap between them.
+/// With `bpm_override`, only the position of the first click is detected.
+pub fn detect_click_timing(path: &Path, bpm_override: Option<f64>) -> Result<ClickTiming> {
+    let onsets = click_onsets(path)?;
+    let first_beat = *onsets
+        .first()
+        .ok_or_else(|| anyhow!("No clicks found in {:?}", path))?;
+
+    let bpm = match bpm_override {
+        Some(bpm) => bpm,
+        None => {
+            let mut gaps: Vec<f64> = onsets.windows(2).map(|w| (w[1] - w[0]).as_secs_f64()).collect();
+            if gaps.is_empty() {
+                return Err(anyhow!("Only one click found in {:?}, can't tell the tempo", path));
+            }
+            gaps.sort_by(|a, b| a.total_cmp(b));
+            let median = gaps[gaps.len() / 2];
+            (60.0 / median * 100.0).round() / 100.0
+        }
+    };
+    if !(bpm.is_finite() && bpm > 0.0) {
+        return Err(anyhow!("Invalid tempo {} BPM", bpm));
+    }
+
+    Ok(ClickTiming { bpm, first_beat })
+}
+
+/// Start positions of the clicks in a WAV file, judged on the first channel.
+fn click_onsets(path: &Path) -> Result<Vec<Duration>> {
+    let mut reader = hound::WavReader::open(path)?;
+    let spec = reader.spec();
+    let channels = spec.channels as usize;
+    let samples: Vec<i32> = reader
+        .samples::<i32>()
+        .step_by(channels)
+        .collect::<Result<_, _>>()?;
+
+    let rate = spec.sample_rate as f64;
+    let window = ((WINDOW.as_secs_f64() * rate) as usize).max(1);
+    let peaks: Vec<u32> = samples
+        .chunks(window)
+        .map(|w| w.iter().map(|s| s.unsigned_abs()).max().unwrap_or(0))
+        .collect();
+    let loudest = peaks.iter().copied().max().unwrap_or(0);
+    if loudest == 0 {
+        return Ok(vec![]);
+    }
+
+    let threshold = (loudest as f64 * ONSET_THRESHOLD) as u32;
+    let min_gap = (MIN_GAP.as_secs_f64() * rate) as usize;
+    let mut onsets = Vec::new();
+    let mut last: Option<usize> = None;
+    for (i, peak) in peaks.iter().enumerate() {
+        if *peak < threshold {
+            continue;
+        }
+        // Pin the onset down to the first loud sample in the window
+        let start = i * window;
+        let offset = samples[start..(start + window).min(samples.len())]
+            .iter()
+            .position(|s| s.unsigned_abs() >= threshold)
+            .unwrap_or(0);
+        let position = start + offset;
+        if last.is_some_and(|l| position - l < min_gap) {
+            continue;
+        }
+        last = Some(position);
+        onsets.push(Duration::from_secs_f64(position as f64 / rate));
+    }
+    Ok(onsets)
+}
@@ -0,0 +1,115 @@
+use std::fs::File;
+use std::path::Path;
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::{MetadataOptions, StandardTagKey, Tag};
+use symphonia::core::probe::Hint;
+use symphonia::default::get_probe;
+
+/// Path segments that name a section of the site rather than an artist.
+const SITE_SECTIONS: &[&str] = &["custombackingtrack", "backingtrack", "karaoke", "mp3-backingtrack"];
+/// Characters a folder can't be named with somewhere: the path separators and the rest of
+/// what Windows reserves.
+const RESERVED: &[char] = &['/', '\\', ':', '*', '?', '"', '<', '>', '|'];
+
+/// The song title from the song page's heading, e.g. `Cherub Rock - The Smashing Pumpkins`.
+pub fn from_page(html: &str) -> Option<String> {
+    let title_start = html.find(r#"<h1 class="song-details__title""#)?;
+    let title_end = html[title_start..].find("</h1>")?;
+    let title_html = &html[title_start..title_start + title_end];
+    let content_start = title_html.find('>')?;
+    let mut title = title_html[content_start + 1..].trim().to_string();
+
+    // Remove " - Custom Backing Track MP3" from the end
+    if let Some(index) = title.rfind(" - Custom Backing Track MP3") {
+        title.truncate(index);
+    }
+    folder_name(&title)
+}
+
+/// `{title} - {artist}` from the ID3 tags the site puts on every stem, falling back to the
+/// album tag when there is no title tag.
+pub fn from_tags(mp3: &Path) -> Option<String> {
+    let file = File::open(mp3).ok()?;
+    let mss = MediaSourceStream::new(Box::new(file), Default::default());
+    let mut hint = Hint::new();
+    hint.with_extension("mp3");
+    let mut probed = get_probe()
+        .format(&hint, mss, &FormatOptions::default(), &MetadataOptions::default())
+        .ok()?;
+
+    // ID3v2 ahead of the stream is read during probing, anything else by the format reader
+    let mut tags: Vec<Tag> = Vec::new();
+    if let Some(metadata) = probed.metadata.get() {
+        if let Some(revision) = metadata.current() {
+            tags.extend(revision.tags().iter().cloned());
+        }
+    }
+    if let Some(revision) = probed.format.metadata().current() {
+        tags.extend(revision.tags().iter().cloned());
+    }
+    let tag = |key: StandardTagKey| {
+        tags.iter()
+            .find(|tag| tag.std_key == Some(key))
+            .map(|tag| tag.value.to_string().trim().to_string())
+            .filter(|value| !value.is_empty())
+    };
+
+    let title = tag(StandardTagKey::TrackTitle).or_else(|| tag(StandardTagKey::Album))?;
+    // The stem's title tag names the instrument in parentheses; the song is what's before
+    let title = title.split(" (").next().unwrap_or(&title).trim().to_string();
+    folder_name(&match tag(StandardTagKey::Artist) {
+        Some(artist) if !title.contains(&artist) => format!("{} - {}", title, artist),
+        _ => title,
+    })
+}
+
+/// `title` as a folder name: `AC/DC` becomes `AC-DC`, like every reserved character, control
+/// characters go, and so do the trailing dots and spaces Windows drops. None when nothing
+/// is left, or only dots.
+pub fn folder_name(title: &str) -> Option<String> {
+    let name: String = title
+        .chars()
+        .filter(|c| !c.is_control())
+        .map(|c| if RESERVED.contains(&c) { '-' } else { c })
+        .collect();
+    let name = name.trim().trim_end_matches(['.', ' ']);
+    (!name.trim_matches('.').is_empty()).then(|| name.to_string())
+}
+
+/// `{title} - {artist}` from the song's URL path, e.g.
+/// `/custombackingtrack/the-smashing-pumpkins/cherub-rock.html` gives
+/// `Cherub Rock - The Smashing Pumpkins`. Only the path is used, never the scheme or host.
+pub fn from_url(url: &str) -> Option<String> {
+    let parsed = url::Url::parse(url).ok()?;
+    let segments: Vec<&str> = parsed.path_segments()?.filter(|s| !s.is_empty()).collect();
+    let (last, rest) = segments.split_last()?;
+    let slug = last.rsplit_once('.').map_or(*last, |(stem, _)| stem);
+    let title = deslug(slug)?;
+
+    let artist = rest
+        .last()
+        .filter(|segment| !SITE_SECTIONS.contains(&segment.to_lowercase().as_str()))
+        .and_then(|segment| deslug(segment));
+    Some(match artist {
+        Some(artist) => format!("{} - {}", title, artist),
+        None => title,
+    })
+}
+
+/// `the-smashing-pumpkins` to `The Smashing Pumpkins`.
+fn deslug(slug: &str) -> Option<String> {
+    let decoded = slug.replace("%20", " ");
+    let words: Vec<String> = decoded
+        .split(['-', '_', ' '])
+        .filter(|word| !word.is_empty())
+        .map(|word| {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars.flat_map(char::to_lowercase)).collect(),
+                None => String::new(),
+            }
+        })
+        .collect();
+    (!words.is_empty()).then(|| words.join(" "))
+}
@@ -0,0 +1,96 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+use super::AudioProcessor;
+
+/// Name of the file, inside each song folder, that records where every stem came from.
+pub const TRACKS_FILE: &str = "tracks.json";
+
+/// One processed stem.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TrackEntry {
+    /// Name of the track in the KV mixer, when the run that processed it knew the mixer.
+    pub mixer_name: Option<String>,
+    /// Position of the track in the KV mixer, starting at 0.
+    pub mixer_index: Option<usize>,
+    /// File name of the MP3 as Chrome saved it.
+    pub original_filename: String,
+    /// Outputs, relative to the song folder.
+    pub stereo_file: String,
+    pub mono_file: String,
+    pub duration_secs: f64,
+    pub is_click: bool,
+    /// Pipeline stages applied to the stem, in order.
+    #[serde(default)]
+    pub stages: Vec<String>,
+    /// Packets of the downloaded MP3 skipped because they failed to decode.
+    #[serde(default)]
+    pub decode_errors: usize,
+}
+
+/// Contents of `tracks.json`: the stems of a song, in mixer order.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TrackMap {
+    pub tracks: Vec<TrackEntry>,
+}
+
+impl TrackMap {
+    /// Reads the map of an already processed song, or `None` if it has none.
+    pub fn load(song_dir: &Path) -> Result<Option<Self>> {
+        let path = song_dir.join(TRACKS_FILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let data = fs::read_to_string(&path)?;
+        let map = serde_json::from_str(&data).with_context(|| format!("Failed to parse {:?}", path))?;
+        Ok(Some(map))
+    }
+
+    pub fn save(&self, song_dir: &Path) -> Result<()> {
+        let mut data = serde_json::to_string_pretty(self)?;
+        data.push('\n');
+        fs::write(song_dir.join(TRACKS_FILE), data)?;
+        Ok(())
+    }
+
+    /// Matches the stems against the track names scraped from the mixer, the same way the
+    /// downloader matches local MP3s, and orders them like the mixer. When no names are
+    /// given (e.g. processing without downloading), names from `previous` are kept so a
+    /// re-run doesn't lose them.
+    pub fn assign_mixer_names(&mut self, mixer_names: &[String], previous: Option<&TrackMap>) {
+        for entry in &mut self.tracks {
+            let stem_name = Path::new(&entry.mono_file)
+                .file_stem()
+                .map(|s| s.to_string_lossy().trim_end_matches("_mono").to_string())
+                .unwrap_or_default();
+
+            if mixer_names.is_empty() {
+                if let Some(old) = previous
+                    .and_then(|p| p.tracks.iter().find(|t| t.original_filename == entry.original_filename))
+                {
+                    entry.mixer_name = old.mixer_name.clone();
+                    entry.mixer_index = old.mixer_index;
+                }
+            } else if let Some(index) = mixer_names
+                .iter()
+                .position(|n| AudioProcessor::same_track_name(n, &stem_name))
+                .or_else(|| {
+                    // Named with `--numbered-stems`
+                    let bare = AudioProcessor::strip_stem_number(&stem_name);
+                    mixer_names.iter().position(|n| AudioProcessor::same_track_name(n, bare))
+                })
+            {
+                entry.mixer_name = Some(mixer_names[index].clone());
+                entry.mixer_index = Some(index);
+            }
+        }
+
+        // Unmatched stems go last; the click leads those, then file name keeps it stable
+        self.tracks.sort_by(|a, b| {
+            (a.mixer_index.is_none(), a.mixer_index, !a.is_click, &a.stereo_file)
+                .cmp(&(b.mixer_index.is_none(), b.mixer_index, !b.is_click, &b.stereo_file))
+        });
+    }
+}
@@ -0,0 +1,432 @@
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use std::error::Error;
+use std::fmt::Display;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use symphonia::core::{
+    codecs::DecoderOptions,
+    formats::FormatOptions,
+    io::{MediaSourceStream, ReadOnlySource},
+    meta::MetadataOptions,
+    probe::Hint,
+};
+use symphonia::default::{get_codecs, get_probe};
+
+use crate::audio::AudioProcessor;
+
+/// How much longer than the click a stem may be before it's considered suspicious.
+const MAX_OVER_CLICK: Duration = Duration::from_secs(1);
+/// How much shorter than the click a stem may be. The click carries the count-in, so some
+/// slack is expected, but a stem missing more than this was almost certainly cut off.
+const MAX_UNDER_CLICK: Duration = Duration::from_secs(15);
+const EDGE: Duration = Duration::from_secs(1);
+/// A click that decodes to less than this can't be the song's reference length.
+pub const MIN_REFERENCE: Duration = EDGE;
+/// How much longer than the longest other stem the click may run and still have no
+/// count-in; MP3 framing alone can account for a few hundred milliseconds.
+const COUNT_IN_TOLERANCE: Duration = Duration::from_millis(500);
+
+/// What a full decode pass learned about a single MP3.
+#[derive(Debug, Clone)]
+pub struct Mp3Probe {
+    pub path: PathBuf,
+    pub sample_rate: u32,
+    /// Duration claimed by the container headers, when the file provides one.
+    pub header_duration: Option<Duration>,
+    /// Duration of the audio that actually decoded.
+    pub decoded_duration: Duration,
+    /// Number of packets skipped because they failed to decode.
+    pub decode_errors: usize,
+    /// Decoded position of the first and last packet that failed to decode.
+    pub first_error_at: Option<Duration>,
+    pub last_error_at: Option<Duration>,
+}
+
+impl Mp3Probe {
+    pub fn probe(path: &Path) -> Result<Self> {
+        let file = File::open(path)?;
+        let source = ReadOnlySource::new(BufReader::new(file));
+        let mss = MediaSourceStream::new(Box::new(source), Default::default());
+
+        let mut probed = get_probe().format(
+            &Hint::new(),
+            mss,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )?;
+        let track = probed
+            .format
+            .default_track()
+            .ok_or(anyhow!("No default track"))?;
+        let sample_rate = track.codec_params.sample_rate.unwrap_or(44100);
+        let header_duration = track
+            .codec_params
+            .n_frames
+            .map(|n| Duration::from_secs_f64(n as f64 / sample_rate as f64));
+        let mut decoder = get_codecs().make(&track.codec_params, &DecoderOptions::default())?;
+
+        let mut frames: u64 = 0;
+        let mut decode_errors = 0;
+        let mut first_error_at = None;
+        let mut last_error_at = None;
+        let position = |frames: u64| Duration::from_secs_f64(frames as f64 / sample_rate as f64);
+
+        while let Ok(packet) = probed.format.next_packet() {
+            match decoder.decode(&packet) {
+                Ok(buffer) => frames += buffer.frames() as u64,
+                Err(symphonia::core::errors::Error::DecodeError(_)) => {
+                    decode_errors += 1;
+                    first_error_at.get_or_insert(position(frames));
+                    last_error_at = Some(position(frames));
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            sample_rate,
+            header_duration,
+            decoded_duration: position(frames),
+            decode_errors,
+            first_error_at,
+            last_error_at,
+        })
+    }
+
+    fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.decoded_duration < EDGE {
+            problems.push(format!(
+                "decodes to only {:.2}s of audio",
+                self.decoded_duration.as_secs_f64()
+            ));
+            return problems;
+        }
+        if self.first_error_at.is_some_and(|at| at < EDGE) {
+            problems.push("fails to decode within its first second".to_string());
+        }
+        if self
+            .last_error_at
+            .is_some_and(|at| at + EDGE > self.decoded_duration)
+        {
+            problems.push("fails to decode within its last second".to_string());
+        }
+        if let Some(expected) = self.header_duration {
+            if self.decoded_duration + EDGE < expected {
+                problems.push(format!(
+                    "looks truncated: decodes {:.2}s of the {:.2}s its header claims",
+                    self.decoded_duration.as_secs_f64(),
+                    expected.as_secs_f64()
+                ));
+            }
+        }
+        problems
+    }
+}
+
+/// What a stream's headers say about it, read without decoding any audio.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct HeaderProbe {
+    pub duration: Option<Duration>,
+    pub sample_rate: Option<u32>,
+    pub channels: Option<usize>,
+}
+
+impl HeaderProbe {
+    pub fn probe(path: &Path) -> Result<Self> {
+        let file = File::open(path)?;
+        let source = ReadOnlySource::new(BufReader::new(file));
+        let mss = MediaSourceStream::new(Box::new(source), Default::default());
+        let probed = get_probe().format(
+            &Hint::new(),
+            mss,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )?;
+        let track = probed
+            .format
+            .default_track()
+            .ok_or(anyhow!("No default track"))?;
+        let params = &track.codec_params;
+        let rate = params.sample_rate.unwrap_or(44100);
+        Ok(Self {
+            duration: params.n_frames.map(|n| Duration::from_secs_f64(n as f64 / rate as f64)),
+            sample_rate: params.sample_rate,
+            channels: params.channels.map(|c| c.count()),
+        })
+    }
+}
+
+/// Reads the duration the MP3's headers claim (e.g. from a Xing/Info frame) without
+/// decoding any audio.
+pub fn header_duration(path: &Path) -> Result<Option<Duration>> {
+    Ok(HeaderProbe::probe(path)?.duration)
+}
+
+/// Where the length every stem gets padded to came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ReferenceSource {
+    /// The decoded click, which is the normal case.
+    Click,
+    /// `--reference` on the command line.
+    Override,
+    /// The duration the click's headers claim.
+    ClickHeader,
+    /// The median decoded length of the other stems.
+    StemMedian,
+    /// No click was found, so nothing is padded.
+    Unpadded,
+}
+
+impl Display for ReferenceSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Click => "the decoded click",
+            Self::Override => "the --reference override",
+            Self::ClickHeader => "the click's header",
+            Self::StemMedian => "the median length of the other stems",
+            Self::Unpadded => "nothing, without a click",
+        })
+    }
+}
+
+/// Picks the song's reference length. The decoded click is used unless it's (nearly) empty;
+/// then an explicit override wins, followed by the click's header duration and finally the
+/// median of the other stems. The fallbacks are only evaluated when needed, since the last
+/// one decodes every stem.
+pub fn resolve_reference(
+    decoded_click: Duration,
+    reference_override: Option<Duration>,
+    click_header: impl FnOnce() -> Option<Duration>,
+    stem_durations: impl FnOnce() -> Vec<Duration>,
+) -> Result<(Duration, ReferenceSource)> {
+    if decoded_click >= MIN_REFERENCE {
+        return Ok((decoded_click, ReferenceSource::Click));
+    }
+    if let Some(reference) = reference_override {
+        return Ok((reference, ReferenceSource::Override));
+    }
+    if let Some(header) = click_header().filter(|d| *d >= MIN_REFERENCE) {
+        return Ok((header, ReferenceSource::ClickHeader));
+    }
+    let mut durations: Vec<Duration> = stem_durations()
+        .into_iter()
+        .filter(|d| *d >= MIN_REFERENCE)
+        .collect();
+    if !durations.is_empty() {
+        durations.sort();
+        return Ok((durations[durations.len() / 2], ReferenceSource::StemMedian));
+    }
+    Err(anyhow!(
+        "The click decodes to only {:.2}s and no other reference length is available; pass --reference",
+        decoded_click.as_secs_f64()
+    ))
+}
+
+#[derive(Debug)]
+pub struct ValidationError {
+    pub problems: Vec<String>,
+}
+
+impl Display for ValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Downloaded MP3s failed validation:")?;
+        for problem in &self.problems {
+            writeln!(f, " - {}", problem)?;
+        }
+        Ok(())
+    }
+}
+impl Error for ValidationError {}
+
+/// The downloaded MP3s that don't pair off one to one with the mixer's tracks.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrackSetMismatch {
+    /// Mixer tracks no MP3 was found for.
+    pub missing: Vec<String>,
+    /// MP3s of no mixer track, or a second MP3 of one, by file name.
+    pub unexpected: Vec<String>,
+}
+
+impl TrackSetMismatch {
+    /// Each problem on a line of its own, as a warning of `--allow-partial` reads it.
+    pub fn problems(&self) -> Vec<String> {
+        let missing = self.missing.iter().map(|name| format!("no MP3 was downloaded for '{}'", name));
+        let unexpected = self.unexpected.iter().map(|file| format!("{} is no track of the mixer's", file));
+        missing.chain(unexpected).collect()
+    }
+}
+
+impl Display for TrackSetMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "The downloaded MP3s don't match the mixer's tracks:")?;
+        if !self.missing.is_empty() {
+            writeln!(f, " - missing: {}", self.missing.join(", "))?;
+        }
+        if !self.unexpected.is_empty() {
+            writeln!(f, " - unexpected: {}", self.unexpected.join(", "))?;
+        }
+        write!(f, "Pass --allow-partial to process them anyway")
+    }
+}
+impl Error for TrackSetMismatch {}
+
+/// Checks that exactly one of the MP3s at `paths` is named after each of the mixer's
+/// `expected` tracks, going by [`AudioProcessor::normalize_track_name`]. The full mix,
+/// downloaded besides the stems, is no mismatch.
+pub fn check_track_set(paths: &[PathBuf], expected: &[String]) -> Result<(), TrackSetMismatch> {
+    let mut missing: Vec<String> = expected.to_vec();
+    let mut unexpected = Vec::new();
+    let mut paths = paths.to_vec();
+    paths.sort();
+    for path in paths.iter().filter(|path| !AudioProcessor::is_full_mix(path)) {
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+        let name = AudioProcessor::normalize_track_name(&file_name);
+        match missing.iter().position(|track| AudioProcessor::same_track_name(track, &name)) {
+            Some(index) => {
+                missing.remove(index);
+            }
+            None => unexpected.push(file_name.into_owned()),
+        }
+    }
+    if missing.is_empty() && unexpected.is_empty() {
+        Ok(())
+    } else {
+        Err(TrackSetMismatch { missing, unexpected })
+    }
+}
+
+/// The click of a song that doesn't have the count-in it was downloaded for, or has one it
+/// wasn't.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CountInMismatch {
+    pub requested: bool,
+    /// How much longer than the longest other stem the click is.
+    pub measured: Duration,
+}
+
+impl Display for CountInMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.requested {
+            write!(
+                f,
+                "The click is only {:.2}s longer than the other stems, so it has no count-in although --count-in was given; \
+                 the count-in toggle may not have registered",
+                self.measured.as_secs_f64()
+            )
+        } else {
+            write!(
+                f,
+                "The click is {:.2}s longer than the other stems, so it has a count-in although --count-in wasn't given",
+                self.measured.as_secs_f64()
+            )
+        }
+    }
+}
+impl Error for CountInMismatch {}
+
+/// Measures the count-in at the start of a click `click` long, whose longest other stem is
+/// `longest_other`, and checks it's there exactly when `requested`. A click within
+/// [`COUNT_IN_TOLERANCE`] of the stems has none, which is measured as zero.
+pub fn check_count_in(click: Duration, longest_other: Duration, requested: bool) -> Result<Duration, CountInMismatch> {
+    let gap = click.saturating_sub(longest_other);
+    let measured = if gap <= COUNT_IN_TOLERANCE { Duration::ZERO } else { gap };
+    if requested != measured.is_zero() {
+        Ok(measured)
+    } else {
+        Err(CountInMismatch { requested, measured: gap })
+    }
+}
+
+/// Probes the click and every other stem, returning all problems found in one error.
+/// With `fallback_reference`, the click is already known to be unusable: it isn't probed and
+/// the stems are measured against the fallback length instead.
+pub fn validate_tracks(
+    click: &Path,
+    others: &[PathBuf],
+    fallback_reference: Option<Duration>,
+) -> Result<(), ValidationError> {
+    validate(Some(click), others, fallback_reference)
+}
+
+/// Probes every stem of a song without a click, with no length to measure them against.
+pub fn validate_unreferenced(tracks: &[PathBuf]) -> Result<(), ValidationError> {
+    validate(None, tracks, None)
+}
+
+fn validate(click: Option<&Path>, others: &[PathBuf], fallback_reference: Option<Duration>) -> Result<(), ValidationError> {
+    let mut problems = Vec::new();
+    let mut describe = |path: &Path, problem: String| {
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        problems.push(format!("{}: {}", name, problem));
+    };
+
+    let (reference, reference_name) = match fallback_reference {
+        Some(reference) => (Some(reference), "reference length"),
+        None => match click.map(|click| (click, Mp3Probe::probe(click))) {
+            None => (None, "click"),
+            Some((click, Ok(probe))) => {
+                for problem in probe.problems() {
+                    describe(click, problem);
+                }
+                (Some(probe.decoded_duration), "click")
+            }
+            Some((click, Err(e))) => {
+                describe(click, format!("could not be decoded: {}", e));
+                (None, "click")
+            }
+        },
+    };
+
+    for path in others {
+        let probe = match Mp3Probe::probe(path) {
+            Ok(probe) => probe,
+            Err(e) => {
+                describe(path, format!("could not be decoded: {}", e));
+                continue;
+            }
+        };
+        for problem in probe.problems() {
+            describe(path, problem);
+        }
+
+        if let Some(click_duration) = reference {
+            let duration = probe.decoded_duration;
+            if duration > click_duration + MAX_OVER_CLICK {
+                describe(
+                    path,
+                    format!(
+                        "is {:.2}s long, longer than the {:.2}s {}",
+                        duration.as_secs_f64(),
+                        click_duration.as_secs_f64(),
+                        reference_name
+                    ),
+                );
+            } else if duration + MAX_UNDER_CLICK < click_duration {
+                describe(
+                    path,
+                    format!(
+                        "is {:.2}s long, far shorter than the {:.2}s {}",
+                        duration.as_secs_f64(),
+                        click_duration.as_secs_f64(),
+                        reference_name
+                    ),
+                );
+            }
+        }
+    }
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(ValidationError { problems })
+    }
+}
@@ -0,0 +1,219 @@
+use anyhow::{anyhow, Result};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::DecoderOptions;
+use symphonia::core::errors::Error as SymphoniaError;
+use symphonia::core::formats::{FormatOptions, SeekMode, SeekTo};
+use symphonia::core::io::{MediaSourceStream, ReadOnlySource};
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+use symphonia::core::units::Time;
+use symphonia::default::{get_codecs, get_probe};
+
+/// Length of each compared window.
+pub const WINDOW: Duration = Duration::from_secs(2);
+/// Envelopes are RMS over blocks this long, so a resampled output still lines up.
+pub const BLOCK: Duration = Duration::from_millis(10);
+/// Envelope correlation below which a window is taken to be different audio.
+pub const MIN_CORRELATION: f64 = 0.8;
+/// RMS under which a window counts as silence.
+const SILENCE: f64 = 1e-3;
+
+/// A source download and the WAVs made from it.
+#[derive(Debug, Clone)]
+pub struct OutputPair {
+    pub source: PathBuf,
+    pub outputs: Vec<PathBuf>,
+    /// Silence in front of the outputs that isn't in the source.
+    pub padding: Duration,
+}
+
+/// Mono audio, the channels averaged.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Window {
+    pub sample_rate: u32,
+    pub samples: Vec<f32>,
+}
+
+impl Window {
+    /// RMS per [`BLOCK`].
+    pub fn envelope(&self) -> Vec<f64> {
+        let block = ((self.sample_rate as f64 * BLOCK.as_secs_f64()) as usize).max(1);
+        self.samples
+            .chunks(block)
+            .map(|chunk| (chunk.iter().map(|s| (*s as f64).powi(2)).sum::<f64>() / chunk.len() as f64).sqrt())
+            .collect()
+    }
+}
+
+/// Decodes `length` of audio from `start` on, seeking rather than decoding what comes
+/// before. Works on anything Symphonia reads; the window ends early at the end of the file.
+pub fn decode_window(path: &Path, start: Duration, length: Duration) -> Result<Window> {
+    let source = ReadOnlySource::new(BufReader::new(File::open(path)?));
+    let mss = MediaSourceStream::new(Box::new(source), Default::default());
+    let mut probed = get_probe().format(&Hint::new(), mss, &FormatOptions::default(), &MetadataOptions::default())?;
+    let track = probed.format.default_track().ok_or(anyhow!("No default track in {:?}", path))?;
+    let track_id = track.id;
+    let sample_rate = track.codec_params.sample_rate.unwrap_or(44100);
+    let mut decoder = get_codecs().make(&track.codec_params, &DecoderOptions::default())?;
+
+    // Frames to drop from the first packets, since a seek lands on a packet boundary
+    let mut skip = 0u64;
+    if !start.is_zero() {
+        let seeked = probed.format.seek(
+            SeekMode::Accurate,
+            SeekTo::Time {
+                time: Time::from(start.as_secs_f64()),
+                track_id: Some(track_id),
+            },
+        )?;
+        skip = seeked.required_ts.saturating_sub(seeked.actual_ts);
+        decoder.reset();
+    }
+
+    let wanted = (length.as_secs_f64() * sample_rate as f64) as usize;
+    let mut samples = Vec::with_capacity(wanted);
+    while samples.len() < wanted {
+        let packet = match probed.format.next_packet() {
+            Ok(packet) => packet,
+            Err(SymphoniaError::IoError(_)) => break,
+            Err(e) => return Err(e.into()),
+        };
+        if packet.track_id() != track_id {
+            continue;
+        }
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            Err(SymphoniaError::DecodeError(_)) => continue,
+            Err(e) => return Err(e.into()),
+        };
+        let channels = decoded.spec().channels.count().max(1);
+        let mut buffer = SampleBuffer::<f32>::new(decoded.capacity() as u64, *decoded.spec());
+        buffer.copy_interleaved_ref(decoded);
+        for frame in buffer.samples().chunks(channels) {
+            if skip > 0 {
+                skip -= 1;
+                continue;
+            }
+            samples.push(frame.iter().sum::<f32>() / channels as f32);
+        }
+    }
+    samples.truncate(wanted);
+    Ok(Window { sample_rate, samples })
+}
+
+/// Reads `length` of a 16-bit WAV from `start` on.
+pub fn wav_window(path: &Path, start: Duration, length: Duration) -> Result<Window> {
+    let mut reader = hound::WavReader::open(path)?;
+    let spec = reader.spec();
+    let channels = spec.channels.max(1) as usize;
+    let first = ((start.as_secs_f64() * spec.sample_rate as f64) as u32).min(reader.duration());
+    reader.seek(first)?;
+    let frames = (length.as_secs_f64() * spec.sample_rate as f64) as usize;
+    let interleaved: Vec<i16> = reader
+        .samples::<i16>()
+        .take(frames * channels)
+        .collect::<Result<_, _>>()?;
+    let samples = interleaved
+        .chunks(channels)
+        .map(|frame| frame.iter().map(|s| *s as f32 / i16::MAX as f32).sum::<f32>() / channels as f32)
+        .collect();
+    Ok(Window {
+        sample_rate: spec.sample_rate,
+        samples,
+    })
+}
+
+/// Pearson correlation of the two windows' envelopes, ignoring gain and polarity. Two
+/// silent windows match; silence against audio doesn't.
+pub fn similarity(a: &Window, b: &Window) -> f64 {
+    let (a, b) = (a.envelope(), b.envelope());
+    let n = a.len().min(b.len());
+    let (a, b) = (&a[..n], &b[..n]);
+    let silent = |e: &[f64]| e.iter().all(|v| *v < SILENCE);
+    match (silent(a), silent(b)) {
+        (true, true) => return 1.0,
+        (true, false) | (false, true) => return 0.0,
+        _ => {}
+    }
+
+    correlation(a, b).unwrap_or_else(|| {
+        // A steady level on both sides, like a sustained pad, says nothing either way
+        let steady = |e: &[f64]| correlation(e, e).is_none();
+        if steady(a) && steady(b) {
+            1.0
+        } else {
+            0.0
+        }
+    })
+}
+
+/// Pearson correlation of two envelopes over the length of the shorter, or `None` when
+/// either holds steady and there's nothing to correlate.
+pub fn correlation(a: &[f64], b: &[f64]) -> Option<f64> {
+    let n = a.len().min(b.len());
+    let (a, b) = (&a[..n], &b[..n]);
+    let mean = |e: &[f64]| e.iter().sum::<f64>() / n as f64;
+    let (mean_a, mean_b) = (mean(a), mean(b));
+    let mut covariance = 0.0;
+    let mut variance_a = 0.0;
+    let mut variance_b = 0.0;
+    for (x, y) in a.iter().zip(b) {
+        covariance += (x - mean_a) * (y - mean_b);
+        variance_a += (x - mean_a).powi(2);
+        variance_b += (y - mean_b).powi(2);
+    }
+    if variance_a < f64::EPSILON || variance_b < f64::EPSILON {
+        return None;
+    }
+    Some(covariance / (variance_a * variance_b).sqrt())
+}
+
+/// Compares the start, middle and end of every source with the same stretch of each of its
+/// outputs. Every mismatch is reported, naming both files, so a mix-up between two stems
+/// shows as both of them.
+pub fn verify_outputs(pairs: &[OutputPair]) -> Result<()> {
+    let mut mismatches = Vec::new();
+    for pair in pairs {
+        for output in &pair.outputs {
+            if let Some(mismatch) = compare(pair, output)? {
+                tracing::error!("{}", mismatch);
+                mismatches.push(mismatch);
+            }
+        }
+    }
+    if mismatches.is_empty() {
+        tracing::info!("Verified {} outputs against their sources", pairs.iter().map(|p| p.outputs.len()).sum::<usize>());
+        Ok(())
+    } else {
+        Err(anyhow!("Output verification failed:\n - {}", mismatches.join("\n - ")))
+    }
+}
+
+fn compare(pair: &OutputPair, output: &Path) -> Result<Option<String>> {
+    let reader = hound::WavReader::open(output)?;
+    let output_length = Duration::from_secs_f64(reader.duration() as f64 / reader.spec().sample_rate as f64);
+    let source_length = output_length.saturating_sub(pair.padding);
+    let window = WINDOW.min(source_length);
+    let last = source_length.saturating_sub(window);
+
+    for (position, at) in [("start", Duration::ZERO), ("middle", last / 2), ("end", last)] {
+        let source = decode_window(&pair.source, at, window)?;
+        let written = wav_window(output, at + pair.padding, window)?;
+        let correlation = similarity(&source, &written);
+        if correlation < MIN_CORRELATION {
+            return Ok(Some(format!(
+                "{:?} doesn't match its source {:?} at the {} ({:.1}s, correlation {:.2})",
+                output.file_name().unwrap_or_default(),
+                pair.source.file_name().unwrap_or_default(),
+                position,
+                at.as_secs_f64(),
+                correlation
+            )));
+        }
+    }
+    Ok(None)
+}
@@ -0,0 +1,111 @@
+use anyhow::Result;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::retention::DEBUG_DIR;
+
+/// What a file sitting in the flat download root is, judging by its name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileKind {
+    /// A downloaded stem.
+    Stem,
+    /// A lyric sheet the site sometimes bundles with a song.
+    Lyrics,
+    /// Cover art.
+    Cover,
+    /// A download Chrome never finished.
+    Partial,
+    /// Files this tool writes into the root itself.
+    Bookkeeping,
+    Unknown,
+}
+
+impl FileKind {
+    /// Where an auxiliary file belongs inside the song folder, as a name without extension.
+    pub fn destination_stem(&self) -> Option<&'static str> {
+        match self {
+            Self::Lyrics => Some("LYRICS"),
+            Self::Cover => Some("COVER"),
+            _ => None,
+        }
+    }
+}
+
+pub fn classify(path: &Path) -> FileKind {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    // A profile's track list is `track_list.<profile>.json`
+    if (name.starts_with("track_list.") && name.ends_with(".json")) || name == DEBUG_DIR {
+        return FileKind::Bookkeeping;
+    }
+    let extension = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    match extension.as_str() {
+        "mp3" => FileKind::Stem,
+        "pdf" => FileKind::Lyrics,
+        "jpg" | "jpeg" | "png" | "webp" => FileKind::Cover,
+        "crdownload" | "part" | "tmp" => FileKind::Partial,
+        _ => FileKind::Unknown,
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct SweepReport {
+    /// Auxiliary files moved into the song folder, as (from, to).
+    pub relocated: Vec<(PathBuf, PathBuf)>,
+    /// Unfinished downloads from this song, left in place.
+    pub partial: Vec<PathBuf>,
+    /// Files from this song the sweep doesn't recognize, left in place.
+    pub unknown: Vec<PathBuf>,
+}
+
+/// Moves the auxiliary files that arrived with a song from the download root into its song
+/// folder. Stems are left for the processor, and anything modified before `since` belongs to
+/// something else and is never touched or reported.
+pub fn sweep_download_root(root: &Path, song_dir: &Path, since: SystemTime) -> Result<SweepReport> {
+    let mut report = SweepReport::default();
+    let mut entries: Vec<PathBuf> = fs::read_dir(root)?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.is_file())
+        .collect();
+    entries.sort();
+
+    for path in entries {
+        if fs::metadata(&path)?.modified()? < since {
+            continue;
+        }
+        let kind = classify(&path);
+        if let Some(stem) = kind.destination_stem() {
+            let extension = path
+                .extension()
+                .map(|e| e.to_string_lossy().to_lowercase())
+                .unwrap_or_default();
+            let dest = free_path(song_dir, stem, &extension);
+            fs::rename(&path, &dest)?;
+            tracing::info!("Moved {:?} to {:?}", path, dest);
+            report.relocated.push((path, dest));
+        } else if kind == FileKind::Partial {
+            report.partial.push(path);
+        } else if kind == FileKind::Unknown {
+            report.unknown.push(path);
+        }
+    }
+    Ok(report)
+}
+
+/// `dir/STEM.ext`, or `dir/STEM 2.ext`, ... if that's already taken.
+fn free_path(dir: &Path, stem: &str, extension: &str) -> PathBuf {
+    let mut candidate = dir.join(format!("{}.{}", stem, extension));
+    let mut n = 2;
+    while candidate.exists() {
+        candidate = dir.join(format!("{} {}.{}", stem, n, extension));
+        n += 1;
+    }
+    candidate
+}
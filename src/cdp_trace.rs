@@ -0,0 +1,282 @@
+use anyhow::Result;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use headless_chrome::protocol::cdp::types::{Event, Method};
+use headless_chrome::protocol::cdp::Runtime::RemoteObject;
+use headless_chrome::{Element, Tab};
+use serde_json::{json, Value};
+use std::fs::{self, File};
+use std::io::Write;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::retention;
+
+/// Longest string kept in a trace record, in bytes; the rest of e.g. a page's HTML is cut.
+pub const TRACE_VALUE_CAP: usize = 4096;
+/// Stands in for a credential or cookie value.
+pub const REDACTED: &str = "[redacted]";
+/// Fields whose values are dropped wherever they appear, matched case-insensitively.
+/// `headersText` is the raw text of a response's headers, cookies and all.
+const SENSITIVE_KEYS: &[&str] = &["password", "cookie", "authorization", "token", "secret", "credential", "headerstext"];
+
+/// A record of the CDP traffic of one song: method calls with their parameters and results,
+/// and the events Chrome sent, as gzipped ndjson. The default trace is off and records
+/// nothing; its only cost is a check of an `Option` per call.
+#[derive(Clone, Default)]
+pub struct CdpTrace {
+    sink: Option<Arc<TraceSink>>,
+}
+
+struct TraceSink {
+    out: Mutex<Option<Box<dyn Write + Send>>>,
+    started: Instant,
+    /// Strings, such as the password, blanked wherever they turn up.
+    secrets: Vec<String>,
+}
+
+impl CdpTrace {
+    /// Starts a trace file for `song_url` in `debug_dir`, where the retention policy prunes
+    /// it with the other debug artifacts.
+    pub fn create(debug_dir: &Path, song_url: &str, secrets: Vec<String>) -> Result<(Self, PathBuf)> {
+        fs::create_dir_all(debug_dir)?;
+        let slug = retention::artifact_slug(song_url);
+        let stamp = chrono::Local::now().format("%Y%m%d-%H%M%S");
+        let path = debug_dir.join(format!("cdp-trace-{}-{}.ndjson.gz", slug, stamp));
+        let out = GzEncoder::new(File::create(&path)?, Compression::default());
+        let trace = Self::to_writer(out, secrets);
+        trace.record("start", "song", || json!({ "url": song_url }));
+        Ok((trace, path))
+    }
+
+    /// A trace written, uncompressed, to `out`.
+    pub fn to_writer(out: impl Write + Send + 'static, secrets: Vec<String>) -> Self {
+        Self {
+            sink: Some(Arc::new(TraceSink {
+                out: Mutex::new(Some(Box::new(out))),
+                started: Instant::now(),
+                secrets: secrets.into_iter().filter(|s| !s.is_empty()).collect(),
+            })),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.sink.is_some()
+    }
+
+    /// Appends a record. `data` is only built when the trace is on.
+    pub fn record(&self, kind: &str, name: &str, data: impl FnOnce() -> Value) {
+        let Some(sink) = &self.sink else {
+            return;
+        };
+        let mut data = data();
+        sanitize(&mut data, &sink.secrets);
+        let line = json!({
+            "ms": sink.started.elapsed().as_millis() as u64,
+            "kind": kind,
+            "name": name,
+            "data": data,
+        });
+        if let Some(out) = sink.out.lock().unwrap().as_mut() {
+            if let Err(e) = writeln!(out, "{}", line) {
+                tracing::warn!("Unable to write the CDP trace: {}", e);
+            }
+        }
+    }
+
+    /// Flushes and closes the trace; anything recorded afterwards is dropped.
+    pub fn finish(&self) -> Result<()> {
+        if let Some(sink) = &self.sink {
+            if let Some(mut out) = sink.out.lock().unwrap().take() {
+                out.flush()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Blanks sensitive fields and `secrets`, and cuts long strings to [`TRACE_VALUE_CAP`].
+pub fn sanitize(value: &mut Value, secrets: &[String]) {
+    match value {
+        Value::String(s) => {
+            for secret in secrets {
+                if s.contains(secret.as_str()) {
+                    *s = s.replace(secret.as_str(), REDACTED);
+                }
+            }
+            if s.len() > TRACE_VALUE_CAP {
+                let mut end = TRACE_VALUE_CAP;
+                while !s.is_char_boundary(end) {
+                    end -= 1;
+                }
+                let cut = s.len() - end;
+                s.truncate(end);
+                s.push_str(&format!("…[{} more bytes]", cut));
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(|item| sanitize(item, secrets)),
+        Value::Object(fields) => {
+            for (key, field) in fields.iter_mut() {
+                let key = key.to_lowercase();
+                if SENSITIVE_KEYS.iter().any(|k| key.contains(k)) {
+                    *field = Value::String(REDACTED.to_string());
+                } else {
+                    sanitize(field, secrets);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// A tab whose element queries, evaluations and method calls go into a [`CdpTrace`]. It
+/// derefs to the [`Tab`], so anything it doesn't wrap still works untraced.
+pub struct TracedTab<'a> {
+    tab: &'a Tab,
+    trace: CdpTrace,
+}
+
+impl Deref for TracedTab<'_> {
+    type Target = Tab;
+
+    fn deref(&self) -> &Tab {
+        self.tab
+    }
+}
+
+impl<'a> TracedTab<'a> {
+    pub fn new(tab: &'a Tab, trace: CdpTrace) -> Self {
+        Self { tab, trace }
+    }
+
+    /// A tab that isn't traced.
+    pub fn plain(tab: &'a Tab) -> Self {
+        Self::new(tab, CdpTrace::default())
+    }
+
+    /// Records every event Chrome sends the tab from now on.
+    pub fn follow_events(&self) -> Result<()> {
+        if !self.trace.is_enabled() {
+            return Ok(());
+        }
+        let trace = self.trace.clone();
+        self.tab.add_event_listener(Arc::new(move |event: &Event| {
+            let debug = format!("{:?}", event);
+            let name = debug.split('(').next().unwrap_or_default();
+            trace.record("event", name, || json!({ "event": event_data(event) }));
+        }))?;
+        Ok(())
+    }
+
+    pub fn navigate_to(&self, url: &str) -> Result<&'a Tab> {
+        self.traced("Page.navigate", || json!({ "url": url }), || self.tab.navigate_to(url), |_| Value::Null)
+    }
+
+    pub fn evaluate(&self, expression: &str, await_promise: bool) -> Result<RemoteObject> {
+        self.traced(
+            "Runtime.evaluate",
+            || json!({ "expression": expression, "await_promise": await_promise }),
+            || self.tab.evaluate(expression, await_promise),
+            |result| {
+                json!({
+                    "type": result.Type,
+                    "value": result.value,
+                    "description": result.description,
+                })
+            },
+        )
+    }
+
+    pub fn find_element(&self, selector: &str) -> Result<Element<'a>> {
+        self.traced("find_element", || json!({ "selector": selector }), || self.tab.find_element(selector), found)
+    }
+
+    pub fn find_elements(&self, selector: &str) -> Result<Vec<Element<'a>>> {
+        self.traced(
+            "find_elements",
+            || json!({ "selector": selector }),
+            || self.tab.find_elements(selector),
+            |elements| json!({ "count": elements.len() }),
+        )
+    }
+
+    pub fn wait_for_element(&self, selector: &str) -> Result<Element<'a>> {
+        self.traced("wait_for_element", || json!({ "selector": selector }), || self.tab.wait_for_element(selector), found)
+    }
+
+    pub fn wait_for_element_with_custom_timeout(&self, selector: &str, timeout: Duration) -> Result<Element<'a>> {
+        self.traced(
+            "wait_for_element",
+            || json!({ "selector": selector, "timeout_ms": timeout.as_millis() as u64 }),
+            || self.tab.wait_for_element_with_custom_timeout(selector, timeout),
+            found,
+        )
+    }
+
+    pub fn call_method<C>(&self, method: C) -> Result<C::ReturnObject>
+    where
+        C: Method + serde::Serialize + std::fmt::Debug,
+        C::ReturnObject: serde::Serialize,
+    {
+        let params = if self.trace.is_enabled() {
+            serde_json::to_value(&method).unwrap_or(Value::Null)
+        } else {
+            Value::Null
+        };
+        self.traced(C::NAME, || params, || self.tab.call_method(method), |result| {
+            serde_json::to_value(result).unwrap_or(Value::Null)
+        })
+    }
+
+    /// Runs `call`, recording it with `params` and then its result or error.
+    fn traced<T>(
+        &self,
+        name: &str,
+        params: impl FnOnce() -> Value,
+        call: impl FnOnce() -> Result<T>,
+        result: impl FnOnce(&T) -> Value,
+    ) -> Result<T> {
+        self.trace.record("call", name, params);
+        let outcome = call();
+        match &outcome {
+            Ok(value) => self.trace.record("result", name, || result(value)),
+            Err(e) => self.trace.record("error", name, || json!(e.to_string())),
+        }
+        outcome
+    }
+}
+
+/// The parameters of `event`, as sent, for the events a song's download turns on: page
+/// loads, downloads, network requests, console messages and crashes. The others are
+/// recorded by name only.
+pub fn event_data(event: &Event) -> Value {
+    let data = match event {
+        Event::BrowserDownloadWillBegin(e) => serde_json::to_value(e),
+        Event::BrowserDownloadProgress(e) => serde_json::to_value(e),
+        Event::PageDownloadWillBegin(e) => serde_json::to_value(e),
+        Event::PageDownloadProgress(e) => serde_json::to_value(e),
+        Event::PageDomContentEventFired(e) => serde_json::to_value(e),
+        Event::PageLoadEventFired(e) => serde_json::to_value(e),
+        Event::PageFrameNavigated(e) => serde_json::to_value(e),
+        Event::PageNavigatedWithinDocument(e) => serde_json::to_value(e),
+        Event::PageJavascriptDialogOpening(e) => serde_json::to_value(e),
+        Event::PageWindowOpen(e) => serde_json::to_value(e),
+        Event::NetworkRequestWillBeSent(e) => serde_json::to_value(e),
+        Event::NetworkResponseReceived(e) => serde_json::to_value(e),
+        Event::NetworkResponseReceivedExtraInfo(e) => serde_json::to_value(e),
+        Event::NetworkLoadingFailed(e) => serde_json::to_value(e),
+        Event::RuntimeConsoleAPICalled(e) => serde_json::to_value(e),
+        Event::RuntimeExceptionThrown(e) => serde_json::to_value(e),
+        Event::TargetCrashed(e) => serde_json::to_value(e),
+        Event::InspectorTargetCrashed(e) => serde_json::to_value(e),
+        Event::InspectorDetached(e) => serde_json::to_value(e),
+        _ => Ok(Value::Null),
+    };
+    data.unwrap_or(Value::Null)
+}
+
+fn found(element: &Element) -> Value {
+    json!({ "node_id": element.node_id })
+}
@@ -0,0 +1,55 @@
+use std::error::Error;
+use std::fmt::Display;
+
+/// Page titles of Cloudflare's browser check and block pages, lowercased.
+const CHALLENGE_TITLES: &[&str] = &["just a moment", "attention required", "checking your browser", "verify you are human"];
+
+/// Elements only Cloudflare's challenge pages have. Its scripts are left out: Cloudflare
+/// injects them into every page it serves, challenge or not.
+const CHALLENGE_MARKERS: &[&str] = &["id=\"challenge-form\"", "id=\"challenge-running\"", "id=\"cf-challenge-running\""];
+
+/// Markup of the CAPTCHAs a page may put in the way. The login form may carry one of its
+/// own, which the sign-in gets past, so they only count on a page without it.
+const CAPTCHA_MARKERS: &[&str] = &[
+    "class=\"g-recaptcha\"",
+    "google.com/recaptcha/",
+    "class=\"h-captcha\"",
+    "hcaptcha.com/",
+    "class=\"cf-turnstile\"",
+];
+
+/// The site's login form.
+const LOGIN_FORM: &str = "id=\"frm_login\"";
+
+/// What gives away a browser check or CAPTCHA in a page with the title `title` and the
+/// markup `html`, or `None` for an ordinary page.
+pub fn detect(title: &str, html: &str) -> Option<&'static str> {
+    let title = title.to_lowercase();
+    let captchas = if html.contains(LOGIN_FORM) { &[][..] } else { CAPTCHA_MARKERS };
+    CHALLENGE_TITLES
+        .iter()
+        .find(|marker| title.contains(**marker))
+        .or_else(|| CHALLENGE_MARKERS.iter().chain(captchas).find(|marker| html.contains(**marker)))
+        .copied()
+}
+
+/// The site put a browser check or CAPTCHA in front of a page, which a headless Chrome has
+/// no one to solve it in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChallengeDetected {
+    pub url: String,
+    /// What on the page gave it away, from [`detect`].
+    pub marker: String,
+}
+
+impl Display for ChallengeDetected {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "The site is asking to check the browser or solve a CAPTCHA at {} (found {:?}); run again with a window, without --headless or with --headless-mode off, to solve it",
+            self.url, self.marker
+        )
+    }
+}
+
+impl Error for ChallengeDetected {}
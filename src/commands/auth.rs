@@ -1,12 +1,185 @@
-use crate::{keystore, prompt};
-use anyhow::Result;
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
-pub fn run() -> Result<()> {
+use crate::config::Config;
+use crate::driver::{self, Driver, DEFAULT_WINDOW_SIZE};
+use crate::keystore::{Backend, CookieFile, Credentials, SecretStore};
+use crate::profile::ProfileStore;
+use crate::tasks::sign_in::SignInMethod;
+use crate::{domain, keystore, profile, prompt, proxy};
+use anyhow::{anyhow, Result};
+use clap::{Args, Subcommand};
+use serde::Serialize;
+
+use super::download::{credentials, credentials_from_env};
+
+#[derive(Debug, Args)]
+pub struct AuthArgs {
+    #[arg(long, value_name = "PATH", help = "Read the [keystore] backend from this TOML file")]
+    config: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "NAME",
+        value_parser = profile::parse_profile,
+        help = "Use the credentials and session of this named profile, e.g. of another account, instead of the default one"
+    )]
+    profile: Option<String>,
+}
+
+impl AuthArgs {
+    fn load_config(&self) -> Result<Config> {
+        match &self.config {
+            Some(path) => Config::load(path),
+            None => Ok(Config::default()),
+        }
+    }
+
+    /// The secrets of the profile in the store the config selects, the OS keychain without
+    /// one.
+    pub(super) fn secrets(&self) -> Result<Arc<dyn SecretStore>> {
+        self.open_secrets(&self.load_config()?)
+    }
+
+    fn open_secrets(&self, config: &Config) -> Result<Arc<dyn SecretStore>> {
+        Ok(profile::secrets(keystore::open(&config.keystore)?, self.profile.as_deref()))
+    }
+
+    /// Takes the named profile, if there is one, off `auth list`.
+    pub(super) fn unregister_profile(&self) -> Result<()> {
+        match &self.profile {
+            Some(name) => profile::unregister(keystore::open(&self.load_config()?.keystore)?.as_ref(), name),
+            None => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct AuthCommand {
+    #[command(flatten)]
+    args: AuthArgs,
+
+    #[command(subcommand)]
+    action: Option<AuthAction>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum AuthAction {
+    /// Signs in with the stored credentials and writes the site's cookies to a file, for
+    /// --cookies-file on a machine without a keychain
+    ExportCookies {
+        #[arg(value_name = "PATH")]
+        path: PathBuf,
+    },
+    /// Signs in with the stored credentials in a headless browser, as a download would, and
+    /// reports how it went, for which account, and how many backing tracks it has
+    Check {
+        #[arg(long, value_name = "PATH", help = "Restore the session from this JSON file of the site's cookies")]
+        cookies_file: Option<PathBuf>,
+
+        #[arg(long, help = "Print the result as JSON")]
+        json: bool,
+    },
+    /// Lists the profiles credentials were stored for
+    List,
+    /// Copies the credentials and sessions of every profile from one credential store to
+    /// another, e.g. from the keychain to the encrypted file
+    Migrate {
+        #[arg(long, value_enum, value_name = "STORE")]
+        from: Backend,
+
+        #[arg(long, value_enum, value_name = "STORE")]
+        to: Backend,
+    },
+    /// Deletes the stored credentials and session cookies of the profile, and its cookies
+    /// file, so the machine can be handed over
+    Logout {
+        #[arg(long, help = "Also sign out on the site, so the session ends there too")]
+        remote: bool,
+
+        #[arg(long, help = "Wipe every profile, and the encrypted credential file if it's in use")]
+        all: bool,
+
+        #[arg(long, value_name = "PATH", help = "A cookies file to delete, besides the config's")]
+        cookies_file: Option<PathBuf>,
+    },
+}
+
+/// Where `auth check` found what it signed in with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CredentialSource {
+    /// `KV_USERNAME` and `KV_PASSWORD`.
+    Environment,
+    Keystore,
+    /// Only the cookies of `--cookies-file`; there are no credentials to log in with.
+    CookiesFile,
+}
+
+impl Display for CredentialSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Environment => "KV_USERNAME and KV_PASSWORD",
+            Self::Keystore => "the keystore",
+            Self::CookiesFile => "the cookies file",
+        })
+    }
+}
+
+/// What `auth check` found, as far as it got.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct AuthCheck {
+    pub ok: bool,
+    pub domain: String,
+    pub credentials: Option<CredentialSource>,
+    pub method: Option<SignInMethod>,
+    /// Whether a saved session was reused rather than a fresh login made.
+    pub session_reused: Option<bool>,
+    pub account_email: Option<String>,
+    /// The custom backing tracks the downloads page lists.
+    pub custom_backing_tracks: Option<usize>,
+    pub error: Option<String>,
+}
+
+/// The lines of what was found; the error is left to the command's.
+impl Display for AuthCheck {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Site: {}", self.domain)?;
+        if let Some(credentials) = self.credentials {
+            writeln!(f, "Credentials: {}", credentials)?;
+        }
+        if let Some(method) = self.method {
+            writeln!(f, "Signed in with {}", method)?;
+        }
+        if let Some(email) = &self.account_email {
+            writeln!(f, "Account: {}", email)?;
+        }
+        if let Some(count) = self.custom_backing_tracks {
+            writeln!(f, "Custom backing tracks: {}", count)?;
+        }
+        write!(f, "{}", if self.ok { "Authentication works" } else { "Authentication failed" })
+    }
+}
+
+/// Stores credentials for every site, or only for `domain` when it's given.
+pub fn run(command: AuthCommand, domain: Option<&str>) -> Result<()> {
+    let args = command.args;
+    match command.action {
+        Some(AuthAction::ExportCookies { path }) => return export_cookies(&args, &path, domain),
+        Some(AuthAction::Check { cookies_file, json }) => return check(&args, cookies_file, json, domain),
+        Some(AuthAction::List) => return list(&args),
+        Some(AuthAction::Migrate { from, to }) => return migrate(&args, from, to),
+        Some(AuthAction::Logout { remote, all, cookies_file }) => return logout(&args, remote, all, cookies_file, domain),
+        None => {}
+    }
+    let config = args.load_config()?;
+    let secrets = args.open_secrets(&config)?;
     println!(
         r#"
-        This will store your username & password securely using your operating system's keychain store.
-        These credentials will only be used to pass to the browser during the sign-in process and will
-        otherwise not leave this device.
+        This will store your username & password securely using your operating system's keychain store,
+        or the keystore command of --config. These credentials will only be used to pass to the browser
+        during the sign-in process and will otherwise not leave this device.
 
         "#
     );
@@ -14,7 +187,197 @@ pub fn run() -> Result<()> {
     let user = prompt::prompt("Username: ", false)?;
     let pass = prompt::prompt("Password: ", true)?;
 
-    _ = keystore::Keystore::login(&user, &pass);
+    let domain = domain.map(domain::normalize);
+    if let Some(domain) = &domain {
+        println!("Storing these credentials for {} only.", domain);
+    }
+    if let Some(name) = &args.profile {
+        println!("Storing these credentials in the profile {}.", name);
+    }
+    secrets.login(&user, &pass, domain.as_deref())?;
+    if let Some(name) = &args.profile {
+        profile::register(keystore::open(&config.keystore)?.as_ref(), name)?;
+    }
+
+    Ok(())
+}
+
+/// Prints the default profile and the named ones, one per line.
+fn list(args: &AuthArgs) -> Result<()> {
+    let store = keystore::open(&args.load_config()?.keystore)?;
+    println!("default");
+    for name in profile::list(store.as_ref())? {
+        println!("{}", name);
+    }
+    Ok(())
+}
+
+/// Copies the secrets of the default profile and of each named one from the store `from`
+/// to the store `to`, leaving `from` as it was.
+fn migrate(args: &AuthArgs, from: Backend, to: Backend) -> Result<()> {
+    if from == to {
+        return Err(anyhow!("--from and --to are the same credential store"));
+    }
+    let config = args.load_config()?;
+    let source = keystore::open_backend(&config.keystore, from)?;
+    let target = keystore::open_backend(&config.keystore, to)?;
+    let mut copied = keystore::copy_secrets(source.as_ref(), target.as_ref())?;
+    for name in profile::list(source.as_ref())? {
+        copied += keystore::copy_secrets(
+            &ProfileStore::new(source.clone(), &name),
+            &ProfileStore::new(target.clone(), &name),
+        )?;
+        profile::register(target.as_ref(), &name)?;
+    }
+    println!(
+        "Copied {} secrets from the {:?} store to the {:?} one; the {:?} store still has them",
+        copied, from, to, from
+    );
+    Ok(())
+}
+
+/// Wipes the profile, or every one with `all`: signs out on the site first with `remote`,
+/// then deletes the secrets and cookies files, printing what it removed. What's already
+/// gone is skipped.
+fn logout(args: &AuthArgs, remote: bool, all: bool, cookies_file: Option<PathBuf>, domain: Option<&str>) -> Result<()> {
+    if all && args.profile.is_some() {
+        return Err(anyhow!("--all wipes every profile; it can't be given with --profile"));
+    }
+    let config = args.load_config()?;
+    proxy::install(config.download.proxy.clone());
+    let domain = domain::resolve(None, domain, config.domain.as_deref());
+    let store = keystore::open(&config.keystore)?;
+    let profiles: Vec<Option<String>> = match all {
+        true => std::iter::once(None).chain(profile::list(store.as_ref())?.into_iter().map(Some)).collect(),
+        false => vec![args.profile.clone()],
+    };
+    let cookies_files: Vec<PathBuf> = cookies_file.into_iter().chain(config.download.cookies_file.clone()).collect();
+
+    for name in &profiles {
+        let label = name.as_deref().unwrap_or("default");
+        let secrets = profile::secrets(store.clone(), name.as_deref());
+        if remote {
+            // Signing in restores the session that's to end; without credentials there may be none
+            match credentials(secrets.as_ref(), &domain) {
+                Ok(credentials) => {
+                    let driver = start_headless(&config, &domain, secrets.clone(), cookies_files.first().cloned())?;
+                    driver.sign_in(&credentials.user, &credentials.password)?;
+                    match driver.sign_out()? {
+                        true => println!("Signed the {} profile out of {}", label, domain),
+                        false => println!("The {} profile has no session on {} to sign out of", label, domain),
+                    }
+                }
+                Err(_) => println!("The {} profile has no credentials to sign in to {} with; not signing out there", label, domain),
+            }
+        }
+        let removed = keystore::remove_secrets(secrets.as_ref())?;
+        match removed.is_empty() {
+            true => println!("The {} profile had nothing stored", label),
+            false => println!("Removed from the {} profile: {}", label, removed.join(", ")),
+        }
+        if let Some(name) = name {
+            profile::unregister(store.as_ref(), name)?;
+        }
+    }
+
+    for path in cookies_files {
+        match std::fs::remove_file(&path) {
+            Ok(()) => println!("Removed the cookies file {:?}", path),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(anyhow!("Unable to remove {:?}: {}", path, e)),
+        }
+    }
+    if all {
+        if let Some(path) = keystore::file_in_use(&config.keystore)? {
+            match std::fs::remove_file(&path) {
+                Ok(()) => println!("Removed the credential file {:?}", path),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(anyhow!("Unable to remove {:?}: {}", path, e)),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Signs in to `domain` in a headless browser, as a download would, and saves the session
+/// to `path`.
+fn export_cookies(args: &AuthArgs, path: &Path, domain: Option<&str>) -> Result<()> {
+    let config = args.load_config()?;
+    proxy::install(config.download.proxy.clone());
+    let domain = domain::resolve(None, domain, config.domain.as_deref());
+    let secrets = args.open_secrets(&config)?;
+    let credentials = credentials(secrets.as_ref(), &domain)?;
+    let driver = start_headless(&config, &domain, secrets, None)?;
+    driver.sign_in(&credentials.user, &credentials.password)?;
+    let count = driver.save_cookies(&CookieFile::new(path))?;
+    println!("Saved {} cookies of {} to {:?}", count, domain, path);
+    Ok(())
+}
+
+/// A headless driver for `domain`, with the browser settings of `config`.
+pub(super) fn start_headless(config: &Config, domain: &str, secrets: Arc<dyn SecretStore>, cookies_file: Option<PathBuf>) -> Result<Driver> {
+    Driver::start(driver::Config {
+        domain: domain.to_string(),
+        headless: true,
+        headless_mode: config.download.headless_mode,
+        window_size: config.download.window_size.map_or(DEFAULT_WINDOW_SIZE, Into::into),
+        secrets,
+        proxy: proxy::current().cloned(),
+        user_agent: config.download.user_agent.clone(),
+        accept_language: config.download.accept_language.clone(),
+        keepalive: None,
+        cookies_file,
+        ..Default::default()
+    })
+}
+
+/// Signs in to `domain` in a headless browser and prints what [`AuthCheck`] found, failing
+/// the command when the sign-in or the account's pages did.
+fn check(args: &AuthArgs, cookies_file: Option<PathBuf>, json: bool, domain: Option<&str>) -> Result<()> {
+    let config = args.load_config()?;
+    proxy::install(config.download.proxy.clone());
+    let domain = domain::resolve(None, domain, config.domain.as_deref());
+    let cookies_file = cookies_file.or_else(|| config.download.cookies_file.clone());
+    let mut report = AuthCheck { domain: domain.clone(), ..Default::default() };
+    let result = args.open_secrets(&config).and_then(|secrets| run_check(&config, &domain, secrets, cookies_file, &mut report));
+    report.ok = result.is_ok();
+    report.error = result.as_ref().err().map(|e| format!("{:#}", e));
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        println!("{}", report);
+    }
+    result
+}
+
+fn run_check(
+    config: &Config,
+    domain: &str,
+    secrets: Arc<dyn SecretStore>,
+    cookies_file: Option<PathBuf>,
+    report: &mut AuthCheck,
+) -> Result<()> {
+    let (source, credentials) = match credentials_from_env() {
+        Some(credentials) => (CredentialSource::Environment, credentials),
+        None => match (secrets.get_credentials(domain), &cookies_file) {
+            (Ok(credentials), _) => (CredentialSource::Keystore, credentials),
+            // The cookies may do without; if they're rejected the login fails
+            (Err(_), Some(_)) => (CredentialSource::CookiesFile, Credentials { user: String::new(), password: String::new() }),
+            (Err(e), None) => return Err(anyhow!("Authentication required. Run `kv-downloader auth` first.\n{}", e)),
+        },
+    };
+    report.credentials = Some(source);
 
+    let driver = start_headless(config, domain, secrets, cookies_file)?;
+    let method = driver.sign_in(&credentials.user, &credentials.password)?;
+    report.method = Some(method);
+    report.session_reused = Some(method.reused_session());
+    report.account_email = driver.account_email()?;
+    // A session without access to the account would list nothing
+    let count = match driver.advertised_track_total()? {
+        Some(count) => count,
+        None => driver.collect_all_custom_track_urls()?.urls.len(),
+    };
+    report.custom_backing_tracks = Some(count);
     Ok(())
 }
@@ -0,0 +1,161 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::audio::bundle::{self, BundleFormat};
+use crate::config::Config;
+use crate::metadata::SongInfo;
+use crate::tasks::setlist::{self, Setlist, SetlistSong};
+use anyhow::{anyhow, Result};
+use clap::Args;
+
+#[derive(Debug, Args)]
+pub struct BundleArgs {
+    #[arg(
+        long,
+        required_unless_present = "setlist",
+        value_name = "SONGS",
+        help = "Songs in set order: a comma-separated list of song folders, or a text file with one per line"
+    )]
+    songs: Option<String>,
+
+    #[arg(
+        long,
+        conflicts_with = "songs",
+        value_name = "PATH",
+        help = "Bundle the library's songs this setlist names, in its order: one title, URL or arrangement ID per line"
+    )]
+    setlist: Option<PathBuf>,
+
+    #[arg(
+        long,
+        default_value = ".",
+        value_name = "PATH",
+        help = "Folder the song names are looked up in"
+    )]
+    library: PathBuf,
+
+    #[arg(long, value_name = "PATH", help = "Folder to write the bundle into")]
+    out: PathBuf,
+
+    #[arg(
+        long,
+        default_value = "lr-split",
+        value_name = "lr-split|stereo-band",
+        help = "Click left and band right in one file, or the click and a stereo band as two files"
+    )]
+    format: BundleFormat,
+
+    #[arg(long, value_name = "NAME", help = "Take the track with this name as the click instead of detecting it")]
+    click_track: Option<String>,
+
+    #[arg(long, value_name = "PATH", help = "Read settings such as the [output] modes and [click] patterns from this TOML file")]
+    config: Option<PathBuf>,
+}
+
+pub struct Bundle;
+
+impl Bundle {
+    pub fn run(args: BundleArgs) -> Result<()> {
+        let config = match &args.config {
+            Some(path) => Config::load(path)?,
+            None => Config::default(),
+        };
+        let songs = match (&args.songs, &args.setlist) {
+            (_, Some(path)) => setlist_songs(&args.library, path)?,
+            (Some(songs), None) => song_list(songs)?
+                .iter()
+                .map(|name| find_song(&args.library, name))
+                .collect::<Result<Vec<_>>>()?,
+            (None, None) => return Err(anyhow!("Either --songs or --setlist is needed")),
+        };
+        let detection = config.click.with_track(args.click_track.clone());
+        let written = bundle::write_bundle(&songs, &args.out, args.format, &detection)?;
+        config
+            .output
+            .apply_each(std::iter::once(args.out.as_path()).chain(written.iter().map(PathBuf::as_path)));
+        tracing::info!(
+            "Wrote {} songs as {} into {:?} ({} files)",
+            songs.len(),
+            args.format,
+            args.out,
+            written.len()
+        );
+        Ok(())
+    }
+}
+
+/// The entries of `--songs`: the lines of the file it names, or its comma-separated items.
+fn song_list(songs: &str) -> Result<Vec<String>> {
+    let path = Path::new(songs);
+    let entries: Vec<String> = if path.is_file() {
+        fs::read_to_string(path)?
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(String::from)
+            .collect()
+    } else {
+        songs.split(',').map(str::trim).filter(|s| !s.is_empty()).map(String::from).collect()
+    };
+    if entries.is_empty() {
+        return Err(anyhow!("--songs doesn't name any songs"));
+    }
+    Ok(entries)
+}
+
+/// The song folders of `library` the setlist at `path` matches, in its order, once the
+/// entries nothing matched and the loose matches have been reported.
+fn setlist_songs(library: &Path, path: &Path) -> Result<Vec<PathBuf>> {
+    let setlist = Setlist::load(path)?;
+    let folders: Vec<PathBuf> = fs::read_dir(library)
+        .map_err(|e| anyhow!("Unable to read {:?}: {}", library, e))?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| is_song(p))
+        .collect();
+    let candidates = folders
+        .iter()
+        .map(|folder| {
+            let name = folder.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+            let info = SongInfo::load(folder)?;
+            Ok(SetlistSong {
+                titles: vec![name.clone()],
+                urls: info
+                    .as_ref()
+                    .map(|info| std::iter::once(&info.url).chain(&info.alternate_urls).cloned().collect())
+                    .unwrap_or_default(),
+                arrangement_id: info.and_then(|info| info.arrangement_id),
+                name,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let order = setlist::order_songs(&setlist, &candidates);
+    setlist::review(&order, &candidates)?;
+    Ok(order.setlist_songs().into_iter().map(|i| folders[i].clone()).collect())
+}
+
+fn is_song(path: &Path) -> bool {
+    path.join("STEMS").join("WAV MONO").is_dir()
+}
+
+/// A processed song folder: `name` itself, `name` inside `library`, or the one folder in
+/// `library` whose name matches it ignoring case.
+fn find_song(library: &Path, name: &str) -> Result<PathBuf> {
+    for candidate in [PathBuf::from(name), library.join(name)] {
+        if is_song(&candidate) {
+            return Ok(candidate);
+        }
+    }
+    let matches: Vec<PathBuf> = fs::read_dir(library)
+        .map_err(|e| anyhow!("Unable to read {:?}: {}", library, e))?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| is_song(p))
+        .filter(|p| p.file_name().is_some_and(|n| n.to_string_lossy().eq_ignore_ascii_case(name)))
+        .collect();
+    match matches.as_slice() {
+        [song] => Ok(song.clone()),
+        [] => Err(anyhow!("No processed song folder {:?} in {:?}", name, library)),
+        _ => Err(anyhow!("{:?} matches more than one song folder in {:?}", name, library)),
+    }
+}
@@ -0,0 +1,265 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::audio::manifest::StemManifest;
+use crate::audio::tempo::COUNT_IN_BARS;
+use crate::audio::track_map::{TrackMap, TRACKS_FILE};
+use crate::audio::{AudioProcessor, ProcessOptions};
+use crate::config::Config;
+use crate::driver::{HeadlessMode, Timeouts, WindowSize};
+use crate::metadata::SongInfo;
+use crate::tasks::download_song::{DownloadOptions, DownloadWait, DEFAULT_TRACK_RETRIES};
+use crate::tasks::song_diff::{self, LiveSong, SongDiff, TrackChange};
+use crate::tasks::track_filter::{TrackFilter, TrackPatterns};
+use crate::proxy::{self, Proxy};
+use crate::{domain, driver, keepalive, keystore, profile};
+use anyhow::{anyhow, Result};
+use clap::Args;
+
+use super::download::credentials_from_env;
+
+#[derive(Debug, Args)]
+pub struct DiffArgs {
+    song_url: String,
+
+    #[arg(long, value_name = "PATH", help = "The song's existing folder")]
+    song_dir: PathBuf,
+
+    #[arg(long, help = "Download the new and renamed tracks into the folder and regenerate its projects")]
+    apply: bool,
+
+    #[arg(short = 'H', long, help = "Run headless")]
+    headless: bool,
+
+    #[arg(long, value_name = "MODE", help = "Run Chrome with a window (off), or headless in its old (old) or new (new) mode; overrides --headless")]
+    headless_mode: Option<HeadlessMode>,
+
+    #[arg(long, value_name = "WIDTHxHEIGHT", help = "Size of the browser window, headless or not [default: 1440x1200]")]
+    window_size: Option<WindowSize>,
+
+    #[arg(
+        long,
+        value_name = "URL",
+        conflicts_with = "connect_port",
+        help = "Drive the Chrome already running with this DevTools address instead of launching one"
+    )]
+    connect_ws: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "PORT",
+        help = "Drive the Chrome already running on this machine with --remote-debugging-port=PORT instead of launching one"
+    )]
+    connect_port: Option<u16>,
+
+    #[arg(long, value_name = "URL", help = "Send the browser's and the song page requests through this proxy")]
+    proxy: Option<Proxy>,
+
+    #[arg(long, value_name = "AGENT", help = "User-Agent the browser sends instead of its own")]
+    user_agent: Option<String>,
+
+    #[arg(long, value_name = "PATH", help = "Launch this Chrome instead of the one found on the machine")]
+    chrome_path: Option<PathBuf>,
+
+    #[arg(long, value_name = "LANGUAGES", help = "Accept-Language the browser sends, such as en-US,en")]
+    accept_language: Option<String>,
+
+    #[arg(long, value_name = "PATH", help = "Restore the session from this JSON file of the site's cookies, and save it there after signing in")]
+    cookies_file: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "NAME",
+        value_parser = profile::parse_profile,
+        help = "Sign in with the credentials and session of this named profile (see `auth --profile`)"
+    )]
+    profile: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "CODE",
+        help = "The code the site emailed, should it ask for one on signing in from a new device"
+    )]
+    verification_code: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "DIR",
+        conflicts_with_all = ["connect_ws", "connect_port"],
+        help = "Launch Chrome with this profile folder, which keeps its session between runs, instead of a fresh incognito one"
+    )]
+    persistent_profile: Option<PathBuf>,
+
+    #[arg(long, value_name = "SECS", value_parser = clap::value_parser!(u64).range(1..), help = "Seconds an element of a loaded page gets to show [default: 10]")]
+    element_timeout: Option<u64>,
+
+    #[arg(long, value_name = "SECS", value_parser = clap::value_parser!(u64).range(1..), help = "Seconds a page gets to load [default: 60]")]
+    navigation_timeout: Option<u64>,
+
+    #[arg(long, value_name = "SECS", value_parser = clap::value_parser!(u64).range(1..), help = "Seconds each step of the sign-in gets [default: 30]")]
+    login_timeout: Option<u64>,
+
+    #[arg(short = 'C', long, help = "The folder was downloaded with a count-in")]
+    count_in: bool,
+
+    #[arg(
+        short = 'T',
+        long,
+        value_parser = clap::value_parser!(i8).range(-12..=12),
+        default_value = "0",
+        allow_hyphen_values = true,
+        help = "Transposition the folder was downloaded with"
+    )]
+    transpose: i8,
+
+    #[arg(long, value_name = "PATH", help = "Read settings such as the [pipeline] stages from this TOML file")]
+    config: Option<PathBuf>,
+}
+
+pub struct Diff;
+
+impl Diff {
+    /// `domain` is the global `--domain`; the song URL's own site still wins over it.
+    pub fn run(args: DiffArgs, domain: Option<&str>) -> Result<()> {
+        let config = match &args.config {
+            Some(path) => Config::load(path)?,
+            None => Config::default(),
+        };
+        proxy::install(args.proxy.clone().or_else(|| config.download.proxy.clone()));
+        let manifest = folder_manifest(&args.song_dir)?;
+        let saved = SongInfo::load(&args.song_dir)?;
+        let domain = domain::resolve(Some(&args.song_url), domain, config.domain.as_deref());
+        let secrets = profile::secrets(keystore::open(&config.keystore)?, args.profile.as_deref());
+        let credentials = match credentials_from_env() {
+            Some(credentials) => credentials,
+            None => secrets
+                .get_credentials(&domain)
+                .map_err(|e| anyhow!("Authentication required. Run `kv-downloader auth` first.\n{}", e))?,
+        };
+
+        // Downloads of an update go next to the folder, never into it
+        let staging = args.apply.then(|| staging_dir(&args.song_dir));
+        if let Some(staging) = &staging {
+            fs::create_dir_all(staging)?;
+        }
+        let driver = driver::Driver::start(driver::Config {
+            domain,
+            headless: args.headless,
+            headless_mode: args.headless_mode.or(config.download.headless_mode),
+            window_size: args.window_size.or(config.download.window_size).map_or(driver::DEFAULT_WINDOW_SIZE, Into::into),
+            download_path: staging.as_ref().map(|path| path.to_string_lossy().into_owned()),
+            secrets,
+            connect: driver::RemoteChrome::from_args(args.connect_ws.as_deref(), args.connect_port),
+            proxy: proxy::current().cloned(),
+            user_agent: args.user_agent.clone().or_else(|| config.download.user_agent.clone()),
+            accept_language: args.accept_language.clone().or_else(|| config.download.accept_language.clone()),
+            timeouts: Timeouts::from_secs(
+                args.element_timeout.or(config.timeouts.element_secs),
+                args.navigation_timeout.or(config.timeouts.navigation_secs),
+                args.login_timeout.or(config.timeouts.login_secs),
+            ),
+            keepalive: keepalive::interval_from_secs(config.download.keepalive_secs),
+            cookies_file: args.cookies_file.clone().or_else(|| config.download.cookies_file.clone()),
+            persistent_profile: match (&args.connect_ws, args.connect_port) {
+                (None, None) => args.persistent_profile.clone().or_else(|| config.download.persistent_profile.clone()),
+                _ => None,
+            },
+            verification_code: args.verification_code.clone(),
+            chrome_path: args.chrome_path.clone(),
+        })?;
+        driver.sign_in(&credentials.user, &credentials.password)?;
+
+        let live = driver.read_live_song(&args.song_url)?;
+        let diff = SongDiff::compare(&manifest, saved.as_ref(), &live);
+        println!("{}", diff);
+
+        let Some(staging) = staging else {
+            return Ok(());
+        };
+        let applied = Self::apply(&driver, &args, config, &staging, &diff, &live);
+        if let Err(e) = fs::remove_dir_all(&staging) {
+            tracing::warn!("Unable to remove {:?}: {}", staging, e);
+        }
+        applied
+    }
+
+    fn apply(
+        driver: &driver::Driver,
+        args: &DiffArgs,
+        config: Config,
+        staging: &Path,
+        diff: &SongDiff,
+        live: &LiveSong,
+    ) -> Result<()> {
+        for change in &diff.tracks {
+            if let TrackChange::Removed(name) = change {
+                tracing::warn!("'{}' is no longer on the page; its stem is kept", name);
+            }
+        }
+
+        let fetch = diff.to_fetch();
+        if !fetch.is_empty() {
+            tracing::info!("Downloading {}", fetch.join(", "));
+            let download_options = DownloadOptions {
+                count_in: args.count_in,
+                transpose: args.transpose,
+                track_retries: DEFAULT_TRACK_RETRIES,
+                wait: DownloadWait::resolve(None, None, &config.download),
+                tracks: TrackFilter {
+                    only: TrackPatterns::exact(&fetch),
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            let track_names = driver.download_song(&args.song_url, download_options)?.track_names;
+            // Only the stems are wanted; the folder's projects are regenerated below
+            let stem_options = ProcessOptions {
+                skip_rpp: true,
+                skip_fcpxml: true,
+                skip_midi: true,
+                pipeline: config.pipeline.clone(),
+                ..Default::default()
+            };
+            AudioProcessor::process_downloads(staging, &args.song_url, &track_names, &stem_options)?;
+            let staged = fs::read_dir(staging)?
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .find(|path| path.join(TRACKS_FILE).is_file())
+                .ok_or_else(|| anyhow!("The downloaded tracks weren't processed into a song folder"))?;
+            song_diff::merge_staged(&args.song_dir, &staged, diff, live)?;
+        }
+        song_diff::save_live_info(&args.song_dir, live)?;
+
+        let project_options = ProcessOptions {
+            mix: config.mix,
+            reaper: config.reaper,
+            pipeline: config.pipeline,
+            permissions: config.output,
+            count_in_bars: if args.count_in { COUNT_IN_BARS } else { 0 },
+            ..Default::default()
+        };
+        let report = AudioProcessor::regenerate_projects(&args.song_dir, &project_options)?;
+        for warning in &report.warnings {
+            tracing::warn!("{}", warning);
+        }
+        tracing::info!("Updated {:?}", args.song_dir);
+        Ok(())
+    }
+}
+
+/// The folder's `stems.json`, or one built from its track map for a folder processed
+/// before manifests were written.
+fn folder_manifest(song_dir: &Path) -> Result<StemManifest> {
+    if let Some(manifest) = StemManifest::load(song_dir)? {
+        return Ok(manifest);
+    }
+    let map = TrackMap::load(song_dir)?
+        .ok_or_else(|| anyhow!("{:?} has no stems.json or tracks.json; is it a song folder?", song_dir))?;
+    StemManifest::build(song_dir, &map, &HashMap::new())
+}
+
+fn staging_dir(song_dir: &Path) -> PathBuf {
+    let name = song_dir.file_name().unwrap_or_default().to_string_lossy();
+    song_dir.with_file_name(format!(".{}.update", name))
+}
@@ -0,0 +1,320 @@
+use std::{
+    collections::VecDeque,
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread,
+    time::SystemTime,
+};
+
+use super::{
+    already_processed, cdp_trace, download_options, finish_trace, log_eta, note_rerenders, record_failure, record_processed,
+    settled_in_state, DownloadArgs,
+};
+use crate::{
+    abort::AbortSignal,
+    audio::{AudioProcessor, ProcessOptions},
+    driver::{self, Driver},
+    keystore::{Credentials, SecretStore},
+    status::StatusHandle,
+    tasks::{
+        batch::{self, Delay, RateLimiter},
+        batch_report::{BatchReport, Outcome, Stage},
+        batch_state::{BatchStateFile, SongStatus},
+        download_song::{DownloadError, DownloadOptions, DownloadWait, DownloadedSong},
+        sign_in::BadCredentials,
+        song_plan::PlannedSong,
+    },
+};
+use anyhow::{Context, Result};
+
+/// What the workers of a `--concurrency` batch share.
+pub(super) struct Batch<'a> {
+    pub args: &'a DownloadArgs,
+    pub download_root: &'a Path,
+    pub domain: &'a str,
+    pub credentials: &'a Credentials,
+    pub secrets: Arc<dyn SecretStore>,
+    pub process_options: &'a ProcessOptions,
+    pub download_wait: DownloadWait,
+    pub status: &'a StatusHandle,
+    pub state: &'a BatchStateFile,
+    pub report: &'a BatchReport,
+    /// Shared by every worker's driver, so Ctrl+C stops them all.
+    pub abort: AbortSignal,
+}
+
+/// A song whose files are all in its staging folder, waiting to be processed.
+struct Downloaded<'s> {
+    index: usize,
+    song: &'s PlannedSong,
+    staging: PathBuf,
+    started: SystemTime,
+    downloaded: DownloadedSong,
+}
+
+impl Batch<'_> {
+    /// Downloads `songs`, skipping the first `skip_count` of the URL list, `concurrency` at a
+    /// time. Each download worker has a browser of its own and downloads every song into a
+    /// staging folder of its own; the songs are processed on as many other threads as they
+    /// finish, then moved into the download directory. A song or worker that fails is
+    /// reported and the others carry on, unless the site rejected the credentials, which
+    /// stops them all. Returns whether the batch was interrupted, or an error when a worker
+    /// thread couldn't be started, after stopping the ones that were.
+    pub fn run(&self, songs: &[PlannedSong], skip_count: usize, concurrency: usize) -> Result<bool> {
+        let queue: Mutex<VecDeque<(usize, &PlannedSong)>> =
+            Mutex::new(songs.iter().enumerate().filter(|(_, song)| song.first_seen >= skip_count).collect());
+        let limiter = RateLimiter::with_delay(Delay::from_secs(self.args.delay, self.args.delay_jitter));
+        let (finished, downloads) = mpsc::channel();
+        let downloads = Mutex::new(downloads);
+        tracing::info!("Downloading {} songs at a time", concurrency);
+
+        thread::scope(|scope| -> Result<()> {
+            let mut downloaders = Vec::new();
+            let mut processors = Vec::new();
+            let mut spawn_all = || -> std::io::Result<()> {
+                for worker in 0..concurrency {
+                    let (queue, limiter, finished) = (&queue, &limiter, finished.clone());
+                    downloaders.push(
+                        thread::Builder::new()
+                            .name(format!("download-{}", worker + 1))
+                            .spawn_scoped(scope, move || self.download_worker(worker, queue, limiter, finished))?,
+                    );
+                }
+                for worker in 0..concurrency {
+                    let downloads = &downloads;
+                    processors.push(
+                        thread::Builder::new()
+                            .name(format!("process-{}", worker + 1))
+                            .spawn_scoped(scope, move || self.process_worker(downloads))?,
+                    );
+                }
+                Ok(())
+            };
+            let spawned = spawn_all();
+            // The processors stop once every downloader has dropped its sender
+            drop(finished);
+            if spawned.is_err() {
+                // The workers that did start stop before their next song
+                self.abort.request();
+            }
+
+            for (worker, handle) in downloaders.into_iter().enumerate() {
+                if handle.join().is_err() {
+                    tracing::error!("Download worker {} crashed; the others carry on", worker + 1);
+                }
+            }
+            for (worker, handle) in processors.into_iter().enumerate() {
+                if handle.join().is_err() {
+                    tracing::error!("Processing worker {} crashed; the others carry on", worker + 1);
+                }
+            }
+            spawned.context("Unable to start the workers of the batch")
+        })?;
+
+        let left = queue.into_inner().unwrap().len();
+        if left > 0 {
+            tracing::warn!("{} songs were never started", left);
+        }
+        Ok(self.abort.is_requested())
+    }
+
+    /// A browser of the worker's own, signed in.
+    fn start_driver(&self) -> Result<Driver> {
+        // Workers can't share a remote browser or a profile
+        let mut driver = Driver::start(driver::Config {
+            download_path: Some(self.download_root.to_string_lossy().into_owned()),
+            connect: None,
+            persistent_profile: None,
+            ..self.args.driver_config(self.domain, self.secrets.clone())
+        })?;
+        driver.abort = self.abort.clone();
+        driver.sign_in(&self.credentials.user, &self.credentials.password)?;
+        Ok(driver)
+    }
+
+    fn download_worker<'s>(
+        &self,
+        worker: usize,
+        queue: &Mutex<VecDeque<(usize, &'s PlannedSong)>>,
+        limiter: &RateLimiter,
+        finished: Sender<Downloaded<'s>>,
+    ) {
+        let mut driver = match self.start_driver() {
+            Ok(driver) => driver,
+            Err(e) => {
+                tracing::error!("Download worker {} couldn't sign in, leaving its songs to the others: {}", worker + 1, e);
+                self.stop_on_bad_credentials(&e);
+                return;
+            }
+        };
+
+        while !self.abort.is_requested() {
+            let Some((index, song)) = queue.lock().unwrap().pop_front() else {
+                break;
+            };
+            let url = &song.url;
+            let _song = AudioProcessor::song_span(url, Some(index + 1)).entered();
+            if settled_in_state(self.state, url, self.args.max_attempts) {
+                self.report.record(url, Outcome::Skipped);
+                self.status.skip_song(index, url);
+                continue;
+            }
+            match already_processed(self.download_root, url) {
+                Ok(true) => {
+                    tracing::info!("Skipping track {} - folder already exists", url);
+                    self.state.record(|state| state.set(url, SongStatus::Processed, None));
+                    self.report.record(url, Outcome::Skipped);
+                    self.status.skip_song(index, url);
+                    continue;
+                }
+                Ok(false) => {}
+                Err(e) => {
+                    self.fail_song(index, url, &e);
+                    continue;
+                }
+            }
+            if !limiter.wait(&self.abort) {
+                break;
+            }
+            tracing::info!("Download worker {} is on track {}: {}", worker + 1, index + 1, url);
+            self.status.start_song(index, url);
+            self.state.record(|state| state.start(url));
+
+            // A batch of days outlives the session cookie
+            if let Err(e) = driver.renew_expiring_session(&self.credentials.user, &self.credentials.password) {
+                tracing::warn!("Download worker {} couldn't renew its session: {}", worker + 1, e);
+                if self.stop_on_bad_credentials(&e) {
+                    self.fail_song(index, url, &e);
+                    break;
+                }
+            }
+
+            let staging = match batch::stage_song(self.download_root, url) {
+                Ok(staging) => staging,
+                Err(e) => {
+                    self.fail_song(index, url, &e);
+                    continue;
+                }
+            };
+            let started = SystemTime::now();
+            let downloaded = batch::prepare_staging(&staging).and_then(|()| {
+                let trace = cdp_trace(self.args, url, self.download_root, self.credentials);
+                let options = DownloadOptions {
+                    download_dir: Some(staging.to_string_lossy().into_owned()),
+                    progress: Some(self.status.song(index)),
+                    ..download_options(self.args, self.download_wait, &trace, &self.process_options.click)
+                };
+                let downloaded = AudioProcessor::phase_span("download").in_scope(|| {
+                    driver.download_song_in_session(url, options, &self.credentials.user, &self.credentials.password)
+                });
+                finish_trace(self.args, self.download_root, &trace);
+                downloaded
+            });
+            match downloaded {
+                Ok(downloaded) => {
+                    self.state.record(|state| state.set(url, SongStatus::Downloaded, None));
+                    let job = Downloaded {
+                        index,
+                        song,
+                        staging,
+                        started,
+                        downloaded,
+                    };
+                    if finished.send(job).is_err() {
+                        tracing::error!("No processing worker left; download worker {} stops", worker + 1);
+                        break;
+                    }
+                }
+                Err(e) => {
+                    self.fail_song(index, url, &e);
+                    if self.stop_on_bad_credentials(&e) {
+                        break;
+                    }
+                    // The stems it finished stay staged for the next run to resume from;
+                    // the folder only goes if it's empty
+                    let _ = fs::remove_dir(&staging);
+                    if !self.abort.is_requested() && driver.new_tab().and_then(|tab| tab.close(true)).is_err() {
+                        tracing::warn!("The browser of download worker {} stopped responding, starting another", worker + 1);
+                        driver = match self.start_driver() {
+                            Ok(driver) => driver,
+                            Err(e) => {
+                                tracing::error!("Download worker {} couldn't start again: {}", worker + 1, e);
+                                return;
+                            }
+                        };
+                    }
+                }
+            }
+        }
+    }
+
+    fn process_worker(&self, downloads: &Mutex<Receiver<Downloaded<'_>>>) {
+        loop {
+            let job = downloads.lock().unwrap().recv();
+            let Ok(job) = job else {
+                break;
+            };
+            let url = &job.song.url;
+            let _song = AudioProcessor::song_span(url, Some(job.index + 1)).entered();
+            let song_options = ProcessOptions {
+                song_started: Some(job.started),
+                alternate_urls: job.song.alternate_urls.clone(),
+                song_info: job.downloaded.page.clone(),
+                ..self.process_options.clone()
+            };
+            let processed = AudioProcessor::process_downloads(&job.staging, url, &job.downloaded.track_names, &song_options)
+                .and_then(|mut report| {
+                    report.mixer_rerenders = job.downloaded.mixer_rerenders.iter().map(ToString::to_string).collect();
+                    batch::publish_staged(&job.staging, self.download_root)?;
+                    Ok(report)
+                });
+            match processed {
+                Ok(report) => {
+                    note_rerenders(url, &report);
+                    record_processed(self.state, self.report, url);
+                    self.status.finish_song(job.index);
+                    log_eta(self.status);
+                    if report.is_clean() {
+                        tracing::info!("Successfully processed track {}", url)
+                    } else {
+                        tracing::warn!("Processed track {} with warnings:\n - {}", url, report.warnings.join("\n - "))
+                    }
+                }
+                Err(e) => {
+                    record_failure(self.state, self.report, url, Stage::Process, &e);
+                    self.status.fail_song(job.index, url, &e.to_string());
+                    tracing::error!("Failed to process {}, its files are in {:?}: {}", url, job.staging, e);
+                }
+            }
+        }
+    }
+
+    /// Stops every worker when `e` says the site rejected the credentials, which every song
+    /// after would fail on too; returns whether it did.
+    fn stop_on_bad_credentials(&self, e: &anyhow::Error) -> bool {
+        if !BadCredentials::caused(e) {
+            return false;
+        }
+        if !self.abort.is_requested() {
+            tracing::error!("Stopping the batch: the site rejected the credentials");
+            self.abort.request();
+        }
+        true
+    }
+
+    /// A song that failed before it reached processing.
+    fn fail_song(&self, index: usize, url: &str, e: &anyhow::Error) {
+        record_failure(self.state, self.report, url, Stage::Download, e);
+        if DownloadError::is_cancelled(e) {
+            self.status.cancel_song(index, url, &e.to_string());
+            tracing::warn!("Cancelled {}: {}", url, e);
+        } else {
+            self.status.fail_song(index, url, &e.to_string());
+            tracing::error!("Failed to process {}: {}", url, e);
+        }
+    }
+}
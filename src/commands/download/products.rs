@@ -0,0 +1,89 @@
+use std::{path::Path, thread::sleep};
+
+use super::{cdp_trace, download_options, finish_trace, record_failure, record_processed, settled_in_state, DownloadArgs};
+use crate::{
+    audio::{click::ClickDetection, AudioProcessor},
+    driver::Driver,
+    keystore::Credentials,
+    status::StatusHandle,
+    tasks::{
+        batch::Delay,
+        batch_report::{BatchReport, Outcome, Stage},
+        batch_state::BatchStateFile,
+        download_song::{DownloadError, DownloadOptions, DownloadWait},
+        song_list::ListedSong,
+    },
+};
+
+/// The videos and lyrics files of a `-A --product-types` batch, downloaded one after the
+/// other once the backing tracks are through. They skip the audio pipeline, but share the
+/// batch's state, report and progress with the backing tracks.
+pub(super) struct ProductBatch<'a> {
+    pub args: &'a DownloadArgs,
+    pub download_root: &'a Path,
+    pub credentials: &'a Credentials,
+    pub download_wait: DownloadWait,
+    pub click: &'a ClickDetection,
+    pub status: &'a StatusHandle,
+    pub state: &'a BatchStateFile,
+    pub report: &'a BatchReport,
+}
+
+impl ProductBatch<'_> {
+    /// Downloads `products`, which come after `first_index` songs in the batch; those whose
+    /// place in the list is below `skip_count` are skipped as `-A SKIP` would. Returns
+    /// whether the batch was interrupted.
+    pub fn run(&self, driver: &Driver, products: &[ListedSong], first_index: usize, skip_count: usize) -> bool {
+        let song_delay = Delay::from_secs(self.args.delay, self.args.delay_jitter);
+        let mut downloaded_any = false;
+        for (offset, product) in products.iter().enumerate() {
+            let index = first_index + offset;
+            if index < skip_count {
+                continue;
+            }
+            let url = &product.url;
+            if driver.abort.is_requested() {
+                tracing::warn!("Interrupted, stopping before the {} of {}", product.product, url);
+                return true;
+            }
+            let _song = AudioProcessor::song_span(url, Some(index + 1)).entered();
+            if settled_in_state(self.state, url, self.args.max_attempts) {
+                self.report.record(url, Outcome::Skipped);
+                self.status.skip_song(index, url);
+                continue;
+            }
+            if downloaded_any {
+                sleep(song_delay.next());
+            }
+            downloaded_any = true;
+
+            self.status.start_song(index, url);
+            self.state.record(|state| state.start(url));
+            let trace = cdp_trace(self.args, url, self.download_root, self.credentials);
+            let options = DownloadOptions {
+                progress: Some(self.status.song(index)),
+                ..download_options(self.args, self.download_wait, &trace, self.click)
+            };
+            let downloaded = driver.download_product(url, product.product, &options);
+            finish_trace(self.args, self.download_root, &trace);
+            match downloaded {
+                Ok(path) => {
+                    record_processed(self.state, self.report, url);
+                    self.status.finish_song(index);
+                    tracing::info!("Saved the {} of {} as {:?}", product.product, url, path);
+                }
+                Err(e) => {
+                    record_failure(self.state, self.report, url, Stage::Download, &e);
+                    if DownloadError::is_cancelled(&e) {
+                        self.status.cancel_song(index, url, &e.to_string());
+                        tracing::warn!("Cancelled the {} of {}: {}", product.product, url, e);
+                    } else {
+                        self.status.fail_song(index, url, &e.to_string());
+                        tracing::error!("Failed to download the {} of {}: {}", product.product, url, e);
+                    }
+                }
+            }
+        }
+        driver.abort.is_requested()
+    }
+}
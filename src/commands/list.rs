@@ -0,0 +1,115 @@
+use std::path::PathBuf;
+
+use crate::config::Config;
+use crate::tasks::products::ProductType;
+use crate::tasks::purchases::{self, Downloads, Purchase};
+use crate::tasks::song_list::{self, TRACK_LIST_FILE};
+use crate::{domain, keystore, profile, proxy};
+use anyhow::Result;
+use clap::Args;
+
+use super::auth::start_headless;
+use super::download::credentials;
+
+#[derive(Debug, Args)]
+pub struct ListArgs {
+    #[arg(
+        short,
+        long,
+        value_name = "PATH",
+        help = "Download directory to check the purchases against, whose track list is refreshed for `download -A --reuse`"
+    )]
+    download_path: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_delimiter = ',',
+        default_value = "cbt",
+        value_name = "cbt,video,lyrics",
+        help = "Purchases to list: custom backing tracks, karaoke videos and synced lyrics files"
+    )]
+    product_types: Vec<ProductType>,
+
+    #[arg(long, conflicts_with = "csv", help = "Print the purchases as JSON")]
+    json: bool,
+
+    #[arg(long, help = "Print the purchases as CSV")]
+    csv: bool,
+
+    #[arg(
+        long,
+        requires = "download_path",
+        conflicts_with = "missing_only",
+        help = "Only list the purchases already in the download directory"
+    )]
+    downloaded_only: bool,
+
+    #[arg(long, requires = "download_path", help = "Only list the purchases not yet in the download directory")]
+    missing_only: bool,
+
+    #[arg(long, value_name = "PATH", help = "Restore the session from this JSON file of the site's cookies")]
+    cookies_file: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "NAME",
+        value_parser = profile::parse_profile,
+        help = "Sign in with the credentials and session of this named profile, and refresh its track list"
+    )]
+    profile: Option<String>,
+
+    #[arg(long, value_name = "PATH", help = "Read settings such as the [keystore] backend from this TOML file")]
+    config: Option<PathBuf>,
+}
+
+pub struct List;
+
+impl List {
+    /// Signs in headless and prints the account's purchases; `domain` is the global `--domain`.
+    pub fn run(args: ListArgs, domain: Option<&str>) -> Result<()> {
+        let config = match &args.config {
+            Some(path) => Config::load(path)?,
+            None => Config::default(),
+        };
+        proxy::install(config.download.proxy.clone());
+        let domain = domain::resolve(None, domain, config.domain.as_deref());
+        let secrets = profile::secrets(keystore::open(&config.keystore)?, args.profile.as_deref());
+        let credentials = credentials(secrets.as_ref(), &domain)?;
+        let cookies_file = args.cookies_file.clone().or_else(|| config.download.cookies_file.clone());
+
+        let driver = start_headless(&config, &domain, secrets, cookies_file)?;
+        driver.sign_in(&credentials.user, &credentials.password)?;
+        let list = driver.collect_purchased(&args.product_types)?;
+        // The same list `download -A` collects, so a run after this one can reuse it
+        if let Some(download_path) = &args.download_path {
+            let path = download_path.join(profile::file_name(TRACK_LIST_FILE, args.profile.as_deref()));
+            song_list::save_track_list(&path, &list)?;
+            tracing::info!("Saved the track list to {:?} for `download -A --reuse`", path);
+        }
+
+        let downloads = args.download_path.as_deref().map(Downloads::scan);
+        let total = list.songs.len();
+        let purchases: Vec<Purchase> = list
+            .songs
+            .into_iter()
+            .map(|song| Purchase::new(song, downloads.as_ref()))
+            .filter(|purchase| match purchase.downloaded {
+                Some(downloaded) => (downloaded || !args.downloaded_only) && (!downloaded || !args.missing_only),
+                None => true,
+            })
+            .collect();
+
+        if args.json {
+            println!("{}", serde_json::to_string_pretty(&purchases)?);
+        } else if args.csv {
+            print!("{}", purchases::csv(&purchases));
+        } else {
+            print!("{}", purchases::table(&purchases));
+        }
+        match &downloads {
+            Some(_) => tracing::info!("Listed {} of {} purchases", purchases.len(), total),
+            None => tracing::info!("Listed {} purchases", total),
+        }
+        Ok(())
+    }
+}
@@ -1,6 +1,14 @@
-use crate::keystore;
+use crate::domain;
 use anyhow::Result;
 
-pub fn run() -> Result<()> {
-    keystore::Keystore::logout()
+use super::auth::AuthArgs;
+
+pub fn run(args: AuthArgs, domain: Option<&str>) -> Result<()> {
+    let domain = domain.map(domain::normalize);
+    args.secrets()?.logout(domain.as_deref())?;
+    // Only a logout from every site forgets the profile itself
+    if domain.is_none() {
+        args.unregister_profile()?;
+    }
+    Ok(())
 }
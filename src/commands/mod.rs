@@ -1,6 +1,27 @@
 pub mod auth;
+mod bundle;
+mod diff;
 mod download;
+mod list;
 pub mod logout;
+mod process;
+mod report_issue;
+mod search;
+mod verify;
 
+pub use bundle::Bundle;
+pub use bundle::BundleArgs;
+pub use diff::Diff;
+pub use diff::DiffArgs;
 pub use download::Download;
 pub use download::DownloadArgs;
+pub use list::List;
+pub use list::ListArgs;
+pub use process::Process;
+pub use process::ProcessArgs;
+pub use report_issue::ReportIssue;
+pub use report_issue::ReportIssueArgs;
+pub use search::Search;
+pub use search::SearchArgs;
+pub use verify::Verify;
+pub use verify::VerifyArgs;
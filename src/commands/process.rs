@@ -0,0 +1,427 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::abort::Interrupted;
+use crate::audio::exporters::DawTargets;
+use crate::audio::loops::{parse_timestamp, LoopRegion};
+use crate::audio::midi::MidiCountIn;
+use crate::audio::mix::MixOverrides;
+use crate::audio::reaper::{ProjectMidiTrack, RppStems};
+use crate::audio::reaper_template::ReaperTemplate;
+use crate::audio::tempo::{TimeSignature, COUNT_IN_BARS};
+use crate::audio::{AudioProcessor, ProcessOptions, SongFolder};
+use crate::config::Config;
+use crate::status::StatusHandle;
+use crate::tasks::batch_report::{BatchReport, Outcome, Stage};
+use crate::tasks::download_song::DownloadError;
+use crate::tasks::local_songs::{self, LocalSong};
+use anyhow::{anyhow, Result};
+use clap::Args;
+
+use super::download::finish_report;
+
+#[derive(Debug, Args)]
+pub struct ProcessArgs {
+    #[arg(
+        required = true,
+        help = "Song folder of MP3s, a folder containing song folders, or a pattern such as 'downloads/.kv-staging/*'"
+    )]
+    paths: Vec<PathBuf>,
+
+    #[arg(
+        short,
+        long,
+        value_name = "PATH",
+        help = "Folder the processed songs go into [default: the folder holding each song folder]"
+    )]
+    download_path: Option<PathBuf>,
+
+    #[arg(long, help = "Only regenerate the project files from the existing WAVs")]
+    projects_only: bool,
+
+    #[arg(short = 'K', long, help = "Keep original MP3 files after processing")]
+    keep_mp3s: bool,
+
+    #[arg(long, help = "Prefix the stems' file names with their place in the mixer, such as '01 Click.wav'")]
+    numbered_stems: bool,
+
+    #[arg(long, help = "Skip checking the MP3s for truncation before processing")]
+    skip_validation: bool,
+
+    #[arg(
+        long,
+        help = "Process the MP3s even if they don't match the mixer's tracks one to one, warning about the missing and unexpected ones"
+    )]
+    allow_partial: bool,
+
+    #[arg(long, help = "Fail the song if the click's count-in doesn't match --count-in, instead of warning")]
+    strict_count_in: bool,
+
+    #[arg(long, help = "Don't generate the Reaper project")]
+    no_rpp: bool,
+
+    #[arg(long, help = "Write absolute stem paths into the Reaper project instead of relative ones")]
+    rpp_absolute_paths: bool,
+
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Fill the Reaper project into this template instead of the built-in one"
+    )]
+    rpp_template: Option<PathBuf>,
+
+    #[arg(
+        long,
+        default_value = "mono",
+        value_name = "stereo|mono|both",
+        help = "Stems the Reaper project plays; both writes a project for each"
+    )]
+    rpp_stems: RppStems,
+
+    #[arg(
+        long,
+        default_value = "off",
+        value_name = "off|empty|click-notes",
+        help = "MIDI track after the stems in the Reaper project: none, an empty item, or a note on every click"
+    )]
+    project_midi_track: ProjectMidiTrack,
+
+    #[arg(long, help = "Pan the click hard left and the band hard right, as projects used to be")]
+    legacy_panning: bool,
+
+    #[arg(long, value_name = "-1..1", allow_hyphen_values = true, help = "Pan of the click track [default: 0]")]
+    click_pan: Option<f64>,
+
+    #[arg(
+        long,
+        value_name = "-1..1",
+        allow_hyphen_values = true,
+        help = "Pan of every track but the click [default: 0]"
+    )]
+    band_pan: Option<f64>,
+
+    #[arg(long, value_name = "DB", allow_hyphen_values = true, help = "Gain of the click track [default: 0]")]
+    click_volume: Option<f64>,
+
+    #[arg(long, value_name = "N", help = "Send the click to this hardware output instead of the master")]
+    click_output: Option<u32>,
+
+    #[arg(
+        long,
+        default_value = "reaper",
+        value_name = "reaper,ableton,dawproject,all",
+        help = "DAWs to generate a session for in MT PROJECT"
+    )]
+    daw: DawTargets,
+
+    #[arg(long, help = "Copy the stems into the .dawproject instead of referencing them in STEMS")]
+    dawproject_embed: bool,
+
+    #[arg(long, alias = "no-omf", help = "Don't generate the FCPXML timeline")]
+    no_fcpxml: bool,
+
+    #[arg(
+        long,
+        value_name = "MM:SS",
+        value_parser = parse_timestamp,
+        help = "Song length to pad the stems to if the click track doesn't decode"
+    )]
+    reference: Option<Duration>,
+
+    #[arg(long, help = "Don't generate the MIDI tempo map")]
+    no_midi: bool,
+
+    #[arg(long, value_name = "BPM", help = "Tempo for the MIDI file instead of detecting it from the click")]
+    tempo: Option<f64>,
+
+    #[arg(long, value_name = "N/D", help = "Time signature for the MIDI file [default: 4/4]")]
+    time_signature: Option<TimeSignature>,
+
+    #[arg(short = 'C', long, help = "The songs were downloaded with a count-in")]
+    count_in: bool,
+
+    #[arg(
+        long,
+        default_value = "include",
+        value_name = "include|skip",
+        help = "With --count-in, write the count-in bars into the MIDI file or start it at the first downbeat"
+    )]
+    midi_count_in: MidiCountIn,
+
+    #[arg(long, help = "Fail the song if any project exporter fails")]
+    strict_exporters: bool,
+
+    #[arg(long, help = "Put the full mix in the DAW projects along with the stems")]
+    include_full_mix: bool,
+
+    #[arg(long, value_name = "NAME", help = "Take the track with this name as the click instead of detecting it")]
+    click_track: Option<String>,
+
+    #[arg(long, value_name = "SECS", help = "Time budget for each project exporter")]
+    exporter_timeout: Option<u64>,
+
+    #[arg(
+        long = "loop",
+        value_name = "START-END",
+        help = "Embed a loop region (e.g. 01:00-01:30) into the WAV stems"
+    )]
+    loop_region: Option<LoopRegion>,
+
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Read settings such as the [pipeline] stages and [reaper] track colors from this TOML file"
+    )]
+    config: Option<PathBuf>,
+
+    #[arg(long, help = "Print the pipeline stages each stem goes through")]
+    print_pipeline: bool,
+
+    #[arg(
+        long,
+        conflicts_with = "projects_only",
+        help = "Print what processing would do to each stem, from the MP3s' headers, without writing anything"
+    )]
+    dry_run: bool,
+
+    #[arg(long, requires = "dry_run", help = "Print the --dry-run plan as JSON")]
+    json: bool,
+
+    #[arg(long, default_value_t = 1, value_name = "N", help = "Stems to transcode at the same time")]
+    process_threads: usize,
+
+    #[arg(
+        long,
+        value_name = "MB",
+        help = "Memory the stems being transcoded may use together [default: a quarter of the system's memory]"
+    )]
+    memory_budget: Option<u64>,
+
+    #[arg(long, help = "Check each output WAV against windows of its source MP3 and fail the song on a mismatch")]
+    verify_outputs: bool,
+
+    #[arg(
+        long,
+        help = "Compare the onsets of each stem with the click's at the start, middle and end of the song, and flag the stems that drift"
+    )]
+    verify_drift: bool,
+}
+
+pub struct Process;
+
+impl Process {
+    /// Processes the MP3s of song folders already on disk, or with `--projects-only` only
+    /// regenerates the projects of processed ones. Neither signs in or goes to the site.
+    pub fn run(args: ProcessArgs) -> Result<()> {
+        let config = match &args.config {
+            Some(path) => Config::load(path)?,
+            None => Config::default(),
+        };
+        let mix = config.mix.with_overrides(MixOverrides {
+            legacy_panning: args.legacy_panning,
+            click_pan: args.click_pan,
+            band_pan: args.band_pan,
+            click_volume_db: args.click_volume,
+            click_output: args.click_output,
+        })?;
+        let rpp_template = match &args.rpp_template {
+            Some(path) => ReaperTemplate::load(path)?,
+            None => ReaperTemplate::default(),
+        };
+
+        let options = ProcessOptions {
+            keep_mp3s: args.keep_mp3s,
+            skip_validation: args.skip_validation,
+            skip_rpp: args.no_rpp,
+            rpp_absolute_paths: args.rpp_absolute_paths,
+            rpp_template,
+            rpp_stems: args.rpp_stems,
+            project_midi_track: args.project_midi_track,
+            mix,
+            skip_fcpxml: args.no_fcpxml,
+            daws: args.daw,
+            dawproject_embed: args.dawproject_embed,
+            loop_region: args.loop_region,
+            skip_midi: args.no_midi,
+            tempo: args.tempo,
+            time_signature: args.time_signature,
+            count_in_bars: if args.count_in { COUNT_IN_BARS } else { 0 },
+            midi_count_in: args.midi_count_in,
+            strict_exporters: args.strict_exporters,
+            exporter_timeout: args.exporter_timeout.map(Duration::from_secs),
+            pipeline: config.pipeline,
+            reaper: config.reaper,
+            permissions: config.output,
+            print_pipeline: args.print_pipeline,
+            reference_duration: args.reference,
+            process_threads: args.process_threads,
+            memory_budget: args.memory_budget.map(|mb| mb.saturating_mul(1024 * 1024)),
+            verify_outputs: args.verify_outputs,
+            verify_drift: args.verify_drift,
+            include_full_mix: args.include_full_mix,
+            click: config.click.with_track(args.click_track.clone()),
+            allow_partial: args.allow_partial,
+            strict_count_in: args.strict_count_in,
+            numbered_stems: args.numbered_stems,
+            ..Default::default()
+        };
+
+        if !args.projects_only {
+            let mut songs = Vec::new();
+            for path in &args.paths {
+                songs.extend(local_songs::find_local_songs(&path.to_string_lossy())?);
+            }
+            if songs.is_empty() {
+                return Err(anyhow!("No song folders with MP3s found in {:?}", args.paths));
+            }
+            return process_songs(&songs, args.download_path.as_deref(), &options, args.dry_run, args.json, &StatusHandle::default());
+        }
+
+        let song_dirs = find_song_dirs(&args.paths)?;
+        if song_dirs.is_empty() {
+            return Err(anyhow!("No processed song folders found in {:?}", args.paths));
+        }
+
+        let mut failures = 0;
+        for (index, song_dir) in song_dirs.iter().enumerate() {
+            let title = song_dir.file_name().unwrap_or_default().to_string_lossy();
+            let _song = tracing::info_span!("song", title = %title, index = index + 1).entered();
+            tracing::info!("Regenerating projects for {:?}", song_dir);
+            match AudioProcessor::regenerate_projects(song_dir, &options) {
+                Ok(report) if report.is_clean() => {}
+                Ok(report) => {
+                    failures += 1;
+                    tracing::warn!("{:?}:\n - {}", song_dir, report.warnings.join("\n - "));
+                }
+                Err(e) => {
+                    failures += 1;
+                    tracing::error!("Failed to regenerate projects for {:?}: {}", song_dir, e);
+                }
+            }
+        }
+
+        if failures > 0 {
+            return Err(anyhow!("{} of {} songs failed", failures, song_dirs.len()));
+        }
+        Ok(())
+    }
+}
+
+/// Processes the MP3s of each of `songs` into a song folder in `download_root`, or next to
+/// the song's own folder without one. The title comes from the song's `song.json` or its
+/// folder's name, so nothing is fetched. `download --skip-download` is processed here too.
+pub(super) fn process_songs(
+    songs: &[LocalSong],
+    download_root: Option<&Path>,
+    options: &ProcessOptions,
+    dry_run: bool,
+    json: bool,
+    status: &StatusHandle,
+) -> Result<()> {
+    tracing::info!("Found {} song folders to process", songs.len());
+    if dry_run {
+        let plans = songs
+            .iter()
+            .map(|song| AudioProcessor::plan_downloads(&song.dir, &song.url, &song_options(song, options)))
+            .collect::<Result<Vec<_>>>()?;
+        if json {
+            println!("{}", serde_json::to_string_pretty(&plans)?);
+        } else {
+            for plan in plans {
+                println!("{}", plan);
+            }
+        }
+        return Ok(());
+    }
+
+    // The first Ctrl+C stops processing before the next stem
+    options.abort.on_ctrl_c();
+    status.set_total(songs.len());
+    crate::status::attach_bar(status);
+    let report = BatchReport::default();
+    let mut interrupted = false;
+    for (index, song) in songs.iter().enumerate() {
+        let url = &song.url;
+        if options.abort.is_requested() {
+            tracing::warn!("Interrupted, stopping before song folder {} of {}", index + 1, songs.len());
+            interrupted = true;
+            break;
+        }
+        let _song = AudioProcessor::song_span(url, Some(index + 1)).entered();
+        let root = download_root.map_or_else(|| local_songs::default_root(&song.dir), Path::to_path_buf);
+        // A song without a known title is looked up by its page; processing falls back to the tags
+        let folder = AudioProcessor::known_song_folder(&root, &song.info).unwrap_or_else(|e| {
+            tracing::debug!("Couldn't tell whether {} is processed: {}", url, e);
+            SongFolder::Missing
+        });
+        if folder == SongFolder::Processed {
+            tracing::info!("Skipping {:?} - the song's folder already exists", song.dir);
+            report.record(url, Outcome::Skipped);
+            status.skip_song(index, url);
+            continue;
+        }
+        tracing::info!("Processing song folder {} of {}: {:?}", index + 1, songs.len(), song.dir);
+        status.start_song(index, url);
+        let processed = AudioProcessor::process_downloads(&song.dir, url, &[], &song_options(song, options))
+            .and_then(|song_report| local_songs::publish(&song.dir, &root).map(|_| song_report));
+        match processed {
+            Ok(song_report) => {
+                report.record(url, Outcome::Processed);
+                status.finish_song(index);
+                if !song_report.is_clean() {
+                    tracing::warn!("Processed {:?} with warnings:\n - {}", song.dir, song_report.warnings.join("\n - "));
+                }
+            }
+            Err(e) if DownloadError::is_cancelled(&e) => {
+                report.record(url, Outcome::Cancelled { stage: Stage::Process, error: e.to_string() });
+                status.cancel_song(index, url, &e.to_string());
+                tracing::warn!("Stopped processing {:?}; its stems are left as they were", song.dir);
+            }
+            Err(e) => {
+                report.record(url, Outcome::Failed { stage: Stage::Process, error: e.to_string() });
+                status.fail_song(index, url, &e.to_string());
+                tracing::error!("Failed to process {:?}: {}", song.dir, e);
+            }
+        }
+    }
+    status.finish_batch();
+    // Only songs with a URL could be downloaded again
+    let outcome = finish_report(&report, download_root.filter(|_| songs.iter().all(|song| song.url.starts_with("http"))));
+    if interrupted {
+        return Err(Interrupted.into());
+    }
+    outcome
+}
+
+/// `options` with what's known of `song`, so its page isn't fetched.
+fn song_options(song: &LocalSong, options: &ProcessOptions) -> ProcessOptions {
+    ProcessOptions {
+        song_info: Some(song.info.clone()),
+        ..options.clone()
+    }
+}
+
+fn is_song_dir(path: &Path) -> bool {
+    path.join("STEMS").join("WAV MONO").is_dir()
+}
+
+/// Expands each path to itself if it's a processed song folder, otherwise to its immediate
+/// subfolders that are.
+fn find_song_dirs(paths: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    let mut song_dirs = Vec::new();
+    for path in paths {
+        if is_song_dir(path) {
+            song_dirs.push(path.clone());
+            continue;
+        }
+        let mut children: Vec<PathBuf> = std::fs::read_dir(path)
+            .map_err(|e| anyhow!("Unable to read {:?}: {}", path, e))?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|p| is_song_dir(p))
+            .collect();
+        children.sort();
+        song_dirs.extend(children);
+    }
+    Ok(song_dirs)
+}
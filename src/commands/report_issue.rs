@@ -0,0 +1,43 @@
+use std::path::PathBuf;
+
+use crate::retention;
+use crate::tasks::issue_report;
+use anyhow::{anyhow, Result};
+use clap::Args;
+
+#[derive(Debug, Args)]
+pub struct ReportIssueArgs {
+    #[arg(
+        long,
+        default_value = ".",
+        value_name = "PATH",
+        help = "Download folder whose debug directory the last run wrote to"
+    )]
+    download_path: PathBuf,
+
+    #[arg(long, value_name = "PATH", help = "Zip to write [default: kv-downloader-report-<timestamp>.zip]")]
+    out: Option<PathBuf>,
+}
+
+pub struct ReportIssue;
+
+impl ReportIssue {
+    pub fn run(args: ReportIssueArgs) -> Result<()> {
+        let debug_dir = args.download_path.join(retention::DEBUG_DIR);
+        if retention::last_run_artifacts(&debug_dir)?.is_empty() {
+            return Err(anyhow!("The last download recorded no debug artifacts in {:?}", debug_dir));
+        }
+        let out = args.out.unwrap_or_else(|| {
+            PathBuf::from(format!("kv-downloader-report-{}.zip", chrono::Local::now().format("%Y%m%d-%H%M%S")))
+        });
+        let report = issue_report::write_issue_report(&debug_dir, &out)?;
+        println!("Wrote {} debug artifacts of the last run to {:?}", report.included.len(), out);
+        if !report.pruned.is_empty() {
+            println!(
+                "Warning: {} artifacts of the last run were already pruned; --debug-max-size, --debug-max-age and --debug-captures keep more",
+                report.pruned.len()
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,68 @@
+use std::path::PathBuf;
+
+use crate::tasks::library_search::{self, Query};
+use anyhow::{anyhow, Result};
+use clap::Args;
+
+#[derive(Debug, Args)]
+pub struct SearchArgs {
+    #[arg(
+        required = true,
+        value_name = "QUERY",
+        help = "Words to find in titles, artists, tags and stems, or filters like artist:toto stem:sax tag:setlist2025"
+    )]
+    query: Vec<String>,
+
+    #[arg(
+        long,
+        default_value = ".",
+        value_name = "PATH",
+        help = "Folder the song folders are in"
+    )]
+    library: PathBuf,
+
+    #[arg(long, conflicts_with = "open", help = "Print the matches as JSON")]
+    json: bool,
+
+    #[arg(long, help = "Print only the folder of the one matching song, for `cd`")]
+    open: bool,
+}
+
+pub struct Search;
+
+impl Search {
+    pub fn run(args: SearchArgs) -> Result<()> {
+        let query: Query = args.query.join(" ").parse().map_err(|e: String| anyhow!(e))?;
+        let songs = library_search::scan_library(&args.library)?;
+        let matches = query.search(&songs);
+
+        if args.json {
+            println!("{}", serde_json::to_string_pretty(&matches)?);
+            return Ok(());
+        }
+        if args.open {
+            // Anything but the one folder would be handed to `cd`
+            return match matches.as_slice() {
+                [found] => {
+                    println!("{}", found.path.display());
+                    Ok(())
+                }
+                [] => Err(anyhow!("No song in {:?} matches", args.library)),
+                _ => Err(anyhow!(
+                    "{} songs match: {}; narrow the search",
+                    matches.len(),
+                    matches.iter().map(|m| m.title.as_str()).collect::<Vec<_>>().join(", ")
+                )),
+            };
+        }
+
+        for found in &matches {
+            println!("{}", found.path.display());
+            for field in &found.matched {
+                println!("  {}: {}", field.field, field.value);
+            }
+        }
+        tracing::info!("{} of {} songs match", matches.len(), songs.len());
+        Ok(())
+    }
+}
@@ -0,0 +1,88 @@
+use std::path::PathBuf;
+
+use crate::audio::exporters::DawTargets;
+use crate::audio::tempo::COUNT_IN_BARS;
+use crate::audio::ProcessOptions;
+use crate::config::Config;
+use crate::metadata::SongInfo;
+use crate::tasks::song_check::{self, SongCheck};
+use anyhow::{anyhow, Result};
+use clap::Args;
+
+#[derive(Debug, Args)]
+pub struct VerifyArgs {
+    #[arg(required = true, help = "Song folder, or a folder containing song folders")]
+    paths: Vec<PathBuf>,
+
+    #[arg(long, help = "Print the report as JSON")]
+    json: bool,
+
+    #[arg(
+        long,
+        help = "Regenerate the projects and stems.json of the songs that fail, from their stems; nothing is downloaded"
+    )]
+    fix: bool,
+
+    #[arg(
+        long,
+        default_value = "reaper",
+        value_name = "reaper,ableton,dawproject,all",
+        help = "DAWs --fix generates a session for in MT PROJECT"
+    )]
+    daw: DawTargets,
+
+    #[arg(long, value_name = "PATH", help = "Read settings such as the [reaper] track colors for --fix from this TOML file")]
+    config: Option<PathBuf>,
+}
+
+pub struct Verify;
+
+impl Verify {
+    pub fn run(args: VerifyArgs) -> Result<()> {
+        let song_dirs = song_check::find_song_dirs(&args.paths)?;
+        if song_dirs.is_empty() {
+            return Err(anyhow!("No song folders found in {:?}", args.paths));
+        }
+        let config = match &args.config {
+            Some(path) => Config::load(path)?,
+            None => Config::default(),
+        };
+        let options = ProcessOptions {
+            daws: args.daw,
+            reaper: config.reaper,
+            permissions: config.output,
+            click: config.click,
+            ..Default::default()
+        };
+
+        let mut checks: Vec<SongCheck> = Vec::new();
+        for song_dir in &song_dirs {
+            let check = song_check::check(song_dir);
+            let check = if args.fix && !check.passed {
+                // The click of a song downloaded with a count-in starts with it
+                let count_in = SongInfo::load(song_dir).ok().flatten().and_then(|info| info.count_in).unwrap_or(false);
+                let options = ProcessOptions {
+                    count_in_bars: if count_in { COUNT_IN_BARS } else { 0 },
+                    ..options.clone()
+                };
+                song_check::fix(song_dir, &check, &options)
+            } else {
+                check
+            };
+            if !args.json {
+                println!("{}", check);
+            }
+            checks.push(check);
+        }
+        if args.json {
+            println!("{}", serde_json::to_string_pretty(&checks)?);
+        }
+
+        let failed = checks.iter().filter(|check| !check.passed).count();
+        if failed > 0 {
+            return Err(anyhow!("{} of {} songs failed verification", failed, checks.len()));
+        }
+        tracing::info!("All {} songs passed", checks.len());
+        Ok(())
+    }
+}
@@ -0,0 +1,297 @@
+use anyhow::{anyhow, Context, Result};
+use clap::{ArgAction, Command};
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use toml::Value;
+
+use crate::audio::click::ClickDetection;
+use crate::audio::mix::MonitorMix;
+use crate::audio::pipeline::Pipeline;
+use crate::audio::reaper::ReaperLayout;
+use crate::driver::{HeadlessMode, WindowSize};
+use crate::keystore::KeystoreConfig;
+use crate::permissions::OutputPermissions;
+use crate::proxy::Proxy;
+
+/// The tables of flag defaults: each of their keys that isn't a setting of its own is the
+/// default of the flag of that name, `keep_mp3s = true` for `--keep-mp3s`. They only group
+/// the flags for whoever reads the file; a flag's key works in any of them.
+pub const FLAG_TABLES: &[&str] = &["download", "audio", "project", "driver"];
+/// The config file read without `--config`, in the user's config folder.
+const DEFAULT_CONFIG: &str = "kv-downloader/config.toml";
+
+/// The user's config folder: `$XDG_CONFIG_HOME`, `%APPDATA%` or `~/.config`.
+pub fn config_dir() -> Option<PathBuf> {
+    std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("APPDATA").map(PathBuf::from))
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+}
+
+/// `~/.config/kv-downloader/config.toml`, or its like, if there's one.
+pub fn default_path() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join(DEFAULT_CONFIG)).filter(|path| path.is_file())
+}
+
+/// The path of the `--config` of the command line `args`, which is needed before they're
+/// parsed, as the file holds their defaults.
+pub fn path_in_args(args: &[OsString]) -> Option<PathBuf> {
+    let mut args = args.iter().skip(1);
+    while let Some(arg) = args.next() {
+        let arg = arg.to_string_lossy();
+        if arg == "--" {
+            break;
+        }
+        if arg == "--config" {
+            return args.next().map(PathBuf::from);
+        }
+        if let Some(path) = arg.strip_prefix("--config=") {
+            return Some(PathBuf::from(path));
+        }
+    }
+    None
+}
+
+/// A key of the [`FLAG_TABLES`] that isn't a setting of its own, for the flag of its name.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FlagDefault {
+    /// Where it is in the file, such as `download.keep_mp3s`.
+    pub key: String,
+    /// The id of the flag, `keep_mp3s`; a `keep-mp3s` key has the same.
+    pub id: String,
+    pub value: Value,
+}
+
+impl FlagDefault {
+    /// The value as the flag's default on the command line, an array as one value each;
+    /// `None` for a table, which no flag takes.
+    pub fn values(&self) -> Option<Vec<String>> {
+        fn scalar(value: &Value) -> Option<String> {
+            match value {
+                Value::String(text) => Some(text.clone()),
+                Value::Integer(number) => Some(number.to_string()),
+                Value::Float(number) => Some(number.to_string()),
+                Value::Boolean(flag) => Some(flag.to_string()),
+                Value::Datetime(datetime) => Some(datetime.to_string()),
+                Value::Array(_) | Value::Table(_) => None,
+            }
+        }
+        match &self.value {
+            Value::Array(items) => items.iter().map(scalar).collect(),
+            value => scalar(value).map(|value| vec![value]),
+        }
+    }
+}
+
+/// Makes `flags` the defaults of the flags they name, on `command` and every subcommand
+/// that has one, and gives every flag an environment variable, `KV_KEEP_MP3S` for
+/// `--keep-mp3s`: a flag given wins over its variable, which wins over the config file,
+/// which wins over the built-in default. `config`, the file they're from, becomes the
+/// default of `--config`, so the commands read the rest of its settings. Returns the keys no
+/// flag took, to be warned about.
+pub fn with_flag_defaults(command: Command, flags: &[FlagDefault], config: Option<&Path>) -> (Command, Vec<String>) {
+    fn apply(mut command: Command, flags: &[FlagDefault], config: Option<&Path>, taken: &mut HashSet<usize>) -> Command {
+        let ids: Vec<String> =
+            command.get_arguments().filter(|arg| !arg.is_positional()).map(|arg| arg.get_id().to_string()).collect();
+        for id in ids {
+            command = command.mut_arg(&id, |mut arg| {
+                if arg.get_env().is_none() {
+                    arg = arg.env(format!("KV_{}", id.to_uppercase()));
+                }
+                // The last of two keys for one flag wins, as in a table
+                let flag = flags.iter().enumerate().rev().find(|(_, flag)| flag.id == id);
+                if let Some(values) = flag.and_then(|(index, flag)| {
+                    taken.insert(index);
+                    flag.values()
+                }) {
+                    // A flag of one value, such as `--daw reaper,ableton`, takes an array joined
+                    let list = arg.get_value_delimiter().is_some() || matches!(arg.get_action(), ArgAction::Append);
+                    arg = if list { arg.default_values(values) } else { arg.default_value(values.join(",")) };
+                } else if let Some(path) = config.filter(|_| id == "config") {
+                    arg = arg.default_value(path.as_os_str().to_owned());
+                }
+                arg
+            });
+        }
+        let names: Vec<String> = command.get_subcommands().map(|sub| sub.get_name().to_string()).collect();
+        for name in names {
+            command = command.mut_subcommand(&name, |sub| apply(sub, flags, config, taken));
+        }
+        command
+    }
+
+    let mut taken = HashSet::new();
+    let command = apply(command, flags, config, &mut taken);
+    let unused = flags
+        .iter()
+        .enumerate()
+        .filter(|(index, flag)| !taken.contains(index) || flag.values().is_none())
+        .map(|(_, flag)| flag.key.clone())
+        .collect();
+    (command, unused)
+}
+
+/// Settings read from a TOML file passed with `--config`, or from the [`default_path`].
+#[derive(Debug, Default, Deserialize)]
+pub struct Config {
+    /// Site to use when neither the song URL nor `--domain` names one.
+    #[serde(default)]
+    pub domain: Option<String>,
+    #[serde(default)]
+    pub pipeline: Pipeline,
+    /// Track colors and folders of the Reaper project.
+    #[serde(default)]
+    pub reaper: ReaperLayout,
+    /// Pan, gain and routing of the click and the band in the DAW sessions.
+    #[serde(default)]
+    pub mix: MonitorMix,
+    /// Where the credentials and session cookies are kept.
+    #[serde(default)]
+    pub keystore: KeystoreConfig,
+    #[serde(default)]
+    pub download: DownloadSettings,
+    /// Modes and group of everything written.
+    #[serde(default)]
+    pub output: OutputPermissions,
+    /// What the click track is called; `--click-track` wins over it.
+    #[serde(default)]
+    pub click: ClickDetection,
+    #[serde(default)]
+    pub timeouts: TimeoutSettings,
+    /// The keys of the [`FLAG_TABLES`] that are defaults of flags.
+    #[serde(skip)]
+    pub flags: Vec<FlagDefault>,
+    /// The keys this version doesn't know, such as `mix.click_gain`; they're ignored with a
+    /// warning, so a config keeps working across versions.
+    #[serde(skip)]
+    pub unknown: Vec<String>,
+}
+
+/// The `[timeouts]` table of the browser's waits on the site; the matching flags win over it.
+#[derive(Debug, Default, Deserialize)]
+pub struct TimeoutSettings {
+    /// `--element-timeout`
+    pub element_secs: Option<u64>,
+    /// `--navigation-timeout`
+    pub navigation_secs: Option<u64>,
+    /// `--login-timeout`
+    pub login_secs: Option<u64>,
+}
+
+impl TimeoutSettings {
+    fn validate(&self) -> Result<()> {
+        for (name, secs) in [("element_secs", self.element_secs), ("navigation_secs", self.navigation_secs), ("login_secs", self.login_secs)] {
+            if secs == Some(0) {
+                return Err(anyhow!("timeouts.{} must be at least 1", name));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The `[download]` table; the matching flags win over it.
+#[derive(Debug, Default, Deserialize)]
+pub struct DownloadSettings {
+    /// `--download-timeout`
+    pub timeout_secs: Option<u64>,
+    /// `--stability-interval`
+    pub stability_interval_ms: Option<u64>,
+    /// How long the site may spend generating a track before its download begins.
+    pub generation_timeout_secs: Option<u64>,
+    /// `--track-list-max-age`
+    pub track_list_max_age_days: Option<u64>,
+    /// `--proxy`
+    pub proxy: Option<Proxy>,
+    /// `--user-agent`
+    pub user_agent: Option<String>,
+    /// `--accept-language`
+    pub accept_language: Option<String>,
+    /// `--headless-mode`
+    pub headless_mode: Option<HeadlessMode>,
+    /// `--window-size`
+    pub window_size: Option<WindowSize>,
+    /// `--keepalive`
+    pub keepalive_secs: Option<u64>,
+    /// `--cookies-file`
+    pub cookies_file: Option<PathBuf>,
+    /// `--persistent-profile`
+    pub persistent_profile: Option<PathBuf>,
+}
+
+impl DownloadSettings {
+    fn validate(&self) -> Result<()> {
+        if self.timeout_secs == Some(0) {
+            return Err(anyhow!("download.timeout_secs must be at least 1"));
+        }
+        if self.stability_interval_ms == Some(0) {
+            return Err(anyhow!("download.stability_interval_ms must be at least 1"));
+        }
+        if self.generation_timeout_secs == Some(0) {
+            return Err(anyhow!("download.generation_timeout_secs must be at least 1"));
+        }
+        if self.track_list_max_age_days == Some(0) {
+            return Err(anyhow!("download.track_list_max_age_days must be at least 1"));
+        }
+        if self.user_agent.as_deref().is_some_and(|value| value.trim().is_empty()) {
+            return Err(anyhow!("download.user_agent must not be empty"));
+        }
+        if self.accept_language.as_deref().is_some_and(|value| value.trim().is_empty()) {
+            return Err(anyhow!("download.accept_language must not be empty"));
+        }
+        Ok(())
+    }
+}
+
+impl Config {
+    /// Reads and validates the config, so a bad value fails the run before anything is
+    /// downloaded, and warns about the keys it doesn't know.
+    pub fn load(path: &Path) -> Result<Self> {
+        let config = Self::read(path)?;
+        for key in &config.unknown {
+            tracing::warn!("Ignoring {} of {:?}, which isn't a setting", key, path);
+        }
+        Ok(config)
+    }
+
+    /// [`Config::load`], without the warnings.
+    pub fn read(path: &Path) -> Result<Self> {
+        let data = fs::read_to_string(path).with_context(|| format!("Unable to read config file {:?}", path))?;
+        Self::parse(&data).with_context(|| format!("Invalid config file {:?}", path))
+    }
+
+    pub fn parse(data: &str) -> Result<Self> {
+        let mut ignored = vec![];
+        let mut config: Self = serde_ignored::deserialize(toml::Deserializer::new(data), |path| ignored.push(path.to_string()))?;
+        let tables: toml::Table = toml::from_str(data)?;
+        for key in ignored {
+            let (table, name) = key.split_once('.').unwrap_or((key.as_str(), ""));
+            let Some(Value::Table(values)) = tables.get(table).filter(|_| FLAG_TABLES.contains(&table)) else {
+                config.unknown.push(key);
+                continue;
+            };
+            // A table with no settings of its own is ignored as a whole
+            let names: Vec<&str> = if name.is_empty() { values.keys().map(String::as_str).collect() } else { vec![name] };
+            for name in names {
+                match values.get(name) {
+                    Some(value) => config.flags.push(FlagDefault {
+                        key: format!("{}.{}", table, name),
+                        id: name.replace('-', "_"),
+                        value: value.clone(),
+                    }),
+                    None => config.unknown.push(format!("{}.{}", table, name)),
+                }
+            }
+        }
+        config.pipeline.validate()?;
+        config.mix.validate()?;
+        config.keystore.validate()?;
+        config.download.validate()?;
+        config.output.validate()?;
+        config.click.validate()?;
+        config.timeouts.validate()?;
+        Ok(config)
+    }
+}
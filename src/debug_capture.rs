@@ -0,0 +1,78 @@
+use anyhow::Result;
+use base64::Engine;
+use headless_chrome::protocol::cdp::Page;
+use headless_chrome::Tab;
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::retention::{self, DebugRetention, CAPTURE_MARKER};
+
+/// Longest the page gets to answer each part of a capture; the tab is about to be closed,
+/// and a hung page mustn't hold the error up.
+const CAPTURE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Where the page of a failed download step is captured, for a failure that doesn't show
+/// when the song is tried again with a visible browser.
+#[derive(Debug, Clone)]
+pub struct DebugCapture {
+    /// The debug directory the captures go into, pruned after each one.
+    pub retention: DebugRetention,
+}
+
+impl DebugCapture {
+    /// Writes a full-page `screenshot.png`, the DOM as `page.html` and the address as
+    /// `url.txt` of `tab` into a new `<timestamp>-<song-slug>` directory, and returns it.
+    /// A part the page can't give is left out.
+    pub fn capture(&self, tab: &Tab, song_url: &str) -> Result<PathBuf> {
+        let dir = self.new_dir(song_url)?;
+        tab.set_default_timeout(CAPTURE_TIMEOUT);
+        fs::write(dir.join(CAPTURE_MARKER), format!("{}\n", tab.get_url()))?;
+        match tab.get_content() {
+            Ok(html) => fs::write(dir.join("page.html"), html)?,
+            Err(e) => tracing::warn!("Unable to capture the page's DOM: {}", e),
+        }
+        match full_page_screenshot(tab) {
+            Ok(png) => fs::write(dir.join("screenshot.png"), png)?,
+            Err(e) => tracing::warn!("Unable to capture a screenshot of the page: {}", e),
+        }
+        self.retention.record(&dir);
+        self.retention.prune();
+        Ok(dir)
+    }
+
+    fn new_dir(&self, song_url: &str) -> Result<PathBuf> {
+        let debug_dir = &self.retention.dir;
+        fs::create_dir_all(debug_dir)?;
+        let name = format!("{}-{}", chrono::Local::now().format("%Y%m%d-%H%M%S"), retention::artifact_slug(song_url));
+        let mut dir = debug_dir.join(&name);
+        let mut n = 1;
+        while dir.exists() {
+            n += 1;
+            dir = debug_dir.join(format!("{}-{}", name, n));
+        }
+        fs::create_dir(&dir)?;
+        Ok(dir)
+    }
+}
+
+/// The whole page as a PNG, not just the part in the window.
+fn full_page_screenshot(tab: &Tab) -> Result<Vec<u8>> {
+    let size = tab.call_method(Page::GetLayoutMetrics(None))?.css_content_size;
+    let data = tab
+        .call_method(Page::CaptureScreenshot {
+            format: Some(Page::CaptureScreenshotFormatOption::Png),
+            quality: None,
+            clip: Some(Page::Viewport {
+                x: 0.0,
+                y: 0.0,
+                width: size.width,
+                height: size.height,
+                scale: 1.0,
+            }),
+            from_surface: Some(true),
+            capture_beyond_viewport: Some(true),
+        })?
+        .data;
+    Ok(base64::prelude::BASE64_STANDARD.decode(data)?)
+}
@@ -0,0 +1,57 @@
+/// The site used when nothing else names one.
+pub const DEFAULT_DOMAIN: &str = "www.karaoke-version.com";
+
+/// The storefronts of the site. They share accounts and page layouts, but each keeps its
+/// own session and purchases.
+pub const KNOWN_DOMAINS: &[&str] = &[
+    "www.karaoke-version.com",
+    "www.karaoke-version.co.uk",
+    "www.karaoke-version.de",
+    "www.version-karaoke.fr",
+    "www.version-karaoke.es",
+    "www.versione-karaoke.it",
+    "www.karaoke-versie.nl",
+];
+
+/// The host of a URL, e.g. `www.karaoke-version.co.uk` for a song on the UK site.
+pub fn from_url(url: &str) -> Option<String> {
+    url::Url::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(|h| h.to_string()))
+}
+
+/// Accepts a host, a bare domain or a URL: `karaoke-version.co.uk` and
+/// `https://www.karaoke-version.co.uk/` both give `www.karaoke-version.co.uk`.
+pub fn normalize(domain: &str) -> String {
+    let domain = domain.trim().to_lowercase();
+    let host = from_url(&domain).unwrap_or_else(|| domain.trim_end_matches('/').to_string());
+    let with_www = format!("www.{}", host);
+    if KNOWN_DOMAINS.contains(&with_www.as_str()) {
+        with_www
+    } else {
+        host
+    }
+}
+
+pub fn is_known(domain: &str) -> bool {
+    KNOWN_DOMAINS.contains(&domain)
+}
+
+/// The site to sign in to and download from: the song URL's own site, then `--domain`,
+/// then the config's `domain`, then [`DEFAULT_DOMAIN`]. Unknown sites are allowed with a
+/// warning, since storefronts come and go.
+pub fn resolve(song_url: Option<&str>, flag: Option<&str>, config: Option<&str>) -> String {
+    let domain = song_url
+        .and_then(from_url)
+        .or_else(|| flag.map(normalize))
+        .or_else(|| config.map(normalize))
+        .unwrap_or_else(|| DEFAULT_DOMAIN.to_string());
+    if !is_known(&domain) {
+        tracing::warn!(
+            "{} isn't a known Karaoke Version site ({}); using it anyway",
+            domain,
+            KNOWN_DOMAINS.join(", ")
+        );
+    }
+    domain
+}
@@ -1,15 +1,233 @@
-use headless_chrome::{Browser, LaunchOptions, Tab};
-use std::sync::Arc;
-use std::time::Duration;
+use crate::abort::AbortSignal;
+use crate::challenge::{self, ChallengeDetected};
+use crate::forms::{self, FieldNotFilled, FillProblem};
+use crate::keepalive::{KeepAlive, DEFAULT_KEEPALIVE_INTERVAL};
+use crate::keystore::{self, Keystore, SecretStore};
+use crate::navigation::Retries;
+use crate::prompt::prompt;
+use crate::proxy::Proxy;
+use headless_chrome::protocol::cdp::Browser::{SetDownloadBehavior, SetDownloadBehaviorBehaviorOption};
+use headless_chrome::protocol::cdp::Network::GetAllCookies;
+use headless_chrome::protocol::cdp::Target::CreateTarget;
+use headless_chrome::{Browser, Element, LaunchOptions, Tab};
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::fmt::Display;
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::thread::sleep;
+use std::time::{Duration, Instant, SystemTime};
 use std::error::Error;
-use anyhow::{Result, anyhow};
+use anyhow::{Context, Result, anyhow};
 use std::ffi::OsStr;
 
 
+/// Chrome's window unless `--window-size` says otherwise.
+pub const DEFAULT_WINDOW_SIZE: (u32, u32) = (1440, 1200);
+/// Widest or tallest window `--window-size` takes.
+const MAX_WINDOW_SIDE: u32 = 10_000;
+/// The file in a `--persistent-profile` folder that only one run at a time holds a lock on.
+const PROFILE_LOCK: &str = "kv-downloader.lock";
+/// How long an element gets to show, unless `--element-timeout` says otherwise.
+pub const DEFAULT_ELEMENT_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long a page gets to load, unless `--navigation-timeout` says otherwise.
+pub const DEFAULT_NAVIGATION_TIMEOUT: Duration = Duration::from_secs(60);
+/// How long each step of the sign-in gets, unless `--login-timeout` says otherwise.
+pub const DEFAULT_LOGIN_TIMEOUT: Duration = Duration::from_secs(30);
+/// How often [`Driver::wait_interactable`] looks at an element again.
+const INTERACTABLE_POLL: Duration = Duration::from_millis(200);
+
 pub struct Config {
     pub domain: String,
     pub headless: bool,
+    /// Which headless Chrome to run, or none; overrides `headless` when set.
+    pub headless_mode: Option<HeadlessMode>,
+    /// Width and height of Chrome's window, headless or not, so a visible browser lays the
+    /// mixer out as the headless one does.
+    pub window_size: (u32, u32),
     pub download_path: Option<String>,
+    /// Where the session cookie is restored from and saved to.
+    pub secrets: Arc<dyn SecretStore>,
+    /// A Chrome that's already running to drive instead of launching one; `headless` doesn't
+    /// apply to it.
+    pub connect: Option<RemoteChrome>,
+    /// The proxy Chrome goes through; a Chrome connected to keeps its own, but is still
+    /// given the credentials.
+    pub proxy: Option<Proxy>,
+    /// The `User-Agent` every tab sends instead of Chrome's own, whose `HeadlessChrome` the
+    /// site may take for a bot.
+    pub user_agent: Option<String>,
+    /// The `Accept-Language` every tab sends, such as `en-US,en`, which the site picks the
+    /// page's language by.
+    pub accept_language: Option<String>,
+    pub timeouts: Timeouts,
+    /// How often the driver's own tab is pinged so the browser's connection isn't dropped
+    /// while nothing else is going on; `None` doesn't ping it.
+    pub keepalive: Option<Duration>,
+    /// A JSON file the site's cookies are restored from before the keystore's session, and
+    /// saved to after each sign-in.
+    pub cookies_file: Option<PathBuf>,
+    /// A Chrome profile folder to launch with, which keeps Chrome's own session between
+    /// runs. Without one, Chrome starts from an empty profile and the driver's tabs open in
+    /// a browser context of their own, like an incognito window.
+    pub persistent_profile: Option<PathBuf>,
+    /// The code the site emails when a new device signs in, for a run that can't be asked
+    /// for it; `KV_VERIFICATION_CODE` is read without one.
+    pub verification_code: Option<String>,
+    /// The Chrome to launch instead of the one found on the machine.
+    pub chrome_path: Option<PathBuf>,
+}
+
+/// How long the driver waits on the site. A track's download has a wait of its own,
+/// [`DownloadWait`](crate::tasks::download_song::DownloadWait).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timeouts {
+    /// For an element of a page that's loaded; every tab's default.
+    pub element: Duration,
+    /// For a page to load, the rows of the downloads page to come with it, and the mixer
+    /// to come back after it reloads.
+    pub navigation: Duration,
+    /// For each page and field of the sign-in.
+    pub login: Duration,
+}
+
+impl Timeouts {
+    /// The given seconds, each falling back to its default.
+    pub fn from_secs(element: Option<u64>, navigation: Option<u64>, login: Option<u64>) -> Self {
+        let default = Self::default();
+        Self {
+            element: element.map_or(default.element, Duration::from_secs),
+            navigation: navigation.map_or(default.navigation, Duration::from_secs),
+            login: login.map_or(default.login, Duration::from_secs),
+        }
+    }
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self {
+            element: DEFAULT_ELEMENT_TIMEOUT,
+            navigation: DEFAULT_NAVIGATION_TIMEOUT,
+            login: DEFAULT_LOGIN_TIMEOUT,
+        }
+    }
+}
+
+/// `--headless-mode`: Chrome with a window, the headless mode Chrome has always had, or the
+/// one newer versions render like a windowed Chrome.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum HeadlessMode {
+    Off,
+    Old,
+    New,
+}
+
+impl FromStr for HeadlessMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "off" => Ok(Self::Off),
+            "old" => Ok(Self::Old),
+            "new" => Ok(Self::New),
+            other => Err(format!("Unknown headless mode '{}'; use off, old or new", other)),
+        }
+    }
+}
+
+impl TryFrom<String> for HeadlessMode {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+/// `--window-size`, such as `1920x1080`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct WindowSize {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl FromStr for WindowSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("'{}' isn't a window size; give it as WIDTHxHEIGHT, such as 1920x1080", s);
+        let (width, height) = s.trim().to_lowercase().split_once('x').ok_or_else(invalid).and_then(|(width, height)| {
+            Ok((width.trim().parse::<u32>().map_err(|_| invalid())?, height.trim().parse::<u32>().map_err(|_| invalid())?))
+        })?;
+        if !(1..=MAX_WINDOW_SIDE).contains(&width) || !(1..=MAX_WINDOW_SIDE).contains(&height) {
+            return Err(format!("Window size {}x{} is out of range; each side must be 1 to {}", width, height, MAX_WINDOW_SIDE));
+        }
+        Ok(Self { width, height })
+    }
+}
+
+impl TryFrom<String> for WindowSize {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl Display for WindowSize {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}x{}", self.width, self.height)
+    }
+}
+
+impl From<WindowSize> for (u32, u32) {
+    fn from(size: WindowSize) -> Self {
+        (size.width, size.height)
+    }
+}
+
+/// A Chrome started elsewhere with `--remote-debugging-port`, such as on the host of a
+/// container, with the session it's signed in with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RemoteChrome {
+    /// The browser's DevTools address, `ws://127.0.0.1:9222/devtools/browser/<id>`.
+    WebSocket(String),
+    /// The debugging port on this machine, whose `/json/version` gives the address.
+    Port(u16),
+}
+
+impl RemoteChrome {
+    /// From `--connect-ws` and `--connect-port`, which clap keeps from being given together.
+    pub fn from_args(websocket: Option<&str>, port: Option<u16>) -> Option<Self> {
+        websocket.map(|url| Self::WebSocket(url.to_string())).or(port.map(Self::Port))
+    }
+
+    /// The DevTools address to connect to, asked of Chrome for a port.
+    pub fn websocket_url(&self) -> Result<String> {
+        let port = match self {
+            Self::WebSocket(url) => return Ok(url.clone()),
+            Self::Port(port) => port,
+        };
+        let version_url = format!("http://127.0.0.1:{}/json/version", port);
+        let body = reqwest::blocking::get(&version_url)
+            .and_then(|response| response.text())
+            .with_context(|| {
+                format!(
+                    "Unable to reach Chrome at {}; is it running with --remote-debugging-port={}?",
+                    version_url, port
+                )
+            })?;
+        let version: serde_json::Value =
+            serde_json::from_str(&body).with_context(|| format!("{} doesn't answer like Chrome", version_url))?;
+        version["webSocketDebuggerUrl"]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| anyhow!("{} doesn't give Chrome's webSocketDebuggerUrl", version_url))
+    }
 }
 
 impl Default for Config {
@@ -17,67 +235,336 @@ impl Default for Config {
         Self {
             domain: "www.karaoke-version.com".to_owned(),
             headless: false,
+            headless_mode: None,
+            window_size: DEFAULT_WINDOW_SIZE,
             download_path: None,
+            secrets: Arc::new(Keystore {}),
+            connect: None,
+            proxy: None,
+            user_agent: None,
+            accept_language: None,
+            timeouts: Timeouts::default(),
+            keepalive: Some(DEFAULT_KEEPALIVE_INTERVAL),
+            cookies_file: None,
+            persistent_profile: None,
+            verification_code: None,
+            chrome_path: None,
         }
     }
 }
 
+impl Config {
+    /// The mode Chrome is launched in: `headless_mode`, or else the old headless one for
+    /// `headless`.
+    pub fn launch_mode(&self) -> HeadlessMode {
+        self.headless_mode.unwrap_or(if self.headless { HeadlessMode::Old } else { HeadlessMode::Off })
+    }
+}
+
 pub struct Driver {
     pub config: Config,
     pub browser: Browser,
-    main_tab: Arc<Tab>,
+    /// Stops the song in progress, e.g. on Ctrl+C.
+    pub abort: AbortSignal,
+    /// Shared with the keep-alive thread, which pings whichever tab is in it.
+    main_tab: Arc<Mutex<Arc<Tab>>>,
+    /// The tabs a connected Chrome already had open, which are left alone when the driver
+    /// goes; `None` for a Chrome the driver launched, which goes with it.
+    foreign_tabs: Option<HashSet<String>>,
+    /// What every tab sends as its `User-Agent`, Chrome's own when only the language is
+    /// overridden; `None` leaves the tabs as Chrome makes them.
+    user_agent: Option<String>,
+    /// The browser context the driver's tabs open in, with the cookies of its sign-in and
+    /// its download folder; `None` for a connected Chrome, whose session is in its default
+    /// context.
+    context: Option<String>,
+    keepalive: Option<KeepAlive>,
+    /// Held while the persistent profile is in use; after the browser, so it's only
+    /// released once Chrome is closed.
+    _profile_lock: Option<File>,
+    /// When the site's session cookie expires, as of the last sign-in; `None` before one,
+    /// or for a cookie that lasts as long as Chrome.
+    pub(crate) session_expiry: Mutex<Option<SystemTime>>,
 }
 
 impl Driver {
+    /// Launches Chrome, or connects to the one `config.connect` names; panics if neither
+    /// works. See [`Driver::start`] for an error instead.
     pub fn new(config: Config) -> Self {
-        let browser = Browser::new(LaunchOptions {
-            headless: config.headless,
-            window_size: Some((1440, 1200)),
+        Self::start(config).unwrap_or_else(|e| panic!("{:#}", e))
+    }
+
+    pub fn start(config: Config) -> Result<Self> {
+        let profile_lock = match (&config.persistent_profile, &config.connect) {
+            (Some(_), Some(_)) => return Err(anyhow!("A persistent profile can't be used with a Chrome that's connected to")),
+            (Some(profile), None) => Some(lock_profile(profile)?),
+            (None, _) => None,
+        };
+        let (browser, foreign_tabs) = match &config.connect {
+            Some(remote) => {
+                let url = remote.websocket_url()?;
+                let browser = Browser::connect(url.clone()).map_err(|e| {
+                    anyhow!(
+                        "Unable to connect to Chrome at {}: {}; start it with --remote-debugging-port and pass the address it prints, or --connect-port",
+                        url,
+                        e
+                    )
+                })?;
+                // A round trip, so the tabs Chrome announced on connecting are all known
+                browser.get_version().map_err(|e| anyhow!("Chrome at {} doesn't answer: {}", url, e))?;
+                let existing = browser.get_tabs().lock().unwrap().iter().map(|tab| tab.get_target_id().clone()).collect();
+                tracing::info!("Connected to the running Chrome at {}", url);
+                if config.proxy.is_some() {
+                    tracing::warn!("The connected Chrome goes through the proxy it was started with, not --proxy");
+                }
+                (browser, Some(existing))
+            }
+            None => (Self::launch(&config)?, None),
+        };
+
+        // The profile's session is in Chrome's default context, as is a connected Chrome's
+        let context = match foreign_tabs.is_some() || profile_lock.is_some() {
+            true => None,
+            false => Some(browser.new_context().map_err(|e| anyhow!("Failed to create a browser context: {}", e))?.get_id().to_string()),
+        };
+
+        // The override takes a user agent along with the language
+        let user_agent = match (&config.user_agent, &config.accept_language) {
+            (Some(user_agent), _) => Some(user_agent.clone()),
+            (None, Some(_)) => Some(browser.get_version().map_err(|e| anyhow!("Unable to read Chrome's user agent: {}", e))?.user_agent),
+            (None, None) => None,
+        };
+
+        let raw_tab = Self::open_tab(&browser, context.as_deref()).map_err(|e| anyhow!("Failed to create tab: {}", e))?;
+        if let Some(download_path) = &config.download_path {
+            Self::set_download_path(&raw_tab, context.as_deref(), download_path)
+                .map_err(|e| anyhow!("Failed to set download path: {}", e))?;
+        }
+        let mut driver = Self {
+            config,
+            browser,
+            abort: AbortSignal::default(),
+            main_tab: Arc::new(Mutex::new(raw_tab)),
+            foreign_tabs,
+            user_agent,
+            context,
+            keepalive: None,
+            _profile_lock: profile_lock,
+            session_expiry: Mutex::new(None),
+        };
+        driver.prepare_tab(&driver.main_tab())?;
+        if let Some(interval) = driver.config.keepalive {
+            driver.start_keepalive(interval);
+        }
+        Ok(driver)
+    }
+
+    /// Pings the driver's own tab every `interval` from a thread of its own, in place of
+    /// the one the driver started with. The thread follows the tab [`Driver::revive_tab`]
+    /// replaces it with, and stops when the driver is dropped.
+    pub fn start_keepalive(&mut self, interval: Duration) {
+        self.stop_keepalive();
+        let main_tab = Arc::clone(&self.main_tab);
+        self.keepalive = Some(KeepAlive::start(interval, move || {
+            let tab = main_tab.lock().unwrap().clone();
+            match tab.evaluate("true;", true) {
+                Ok(_) => tracing::debug!("Keep-alive ping succeeded"),
+                Err(e) => tracing::warn!("Keep-alive ping failed: {}", e),
+            }
+        }));
+    }
+
+    pub fn stop_keepalive(&mut self) {
+        self.keepalive = None;
+    }
+
+    /// Replaces the driver's own tab with a new one if it stopped answering. Returns
+    /// whether it did, after which the session may need signing in again.
+    pub fn revive_tab(&self) -> Result<bool> {
+        let mut main_tab = self.main_tab.lock().unwrap();
+        if main_tab.evaluate("true;", true).is_ok() {
+            return Ok(false);
+        }
+        *main_tab = self.new_tab()?;
+        Ok(true)
+    }
+
+    fn main_tab(&self) -> Arc<Tab> {
+        self.main_tab.lock().unwrap().clone()
+    }
+
+    /// A new tab in the driver's browser context, which answers the proxy's sign-in
+    /// challenge when it has credentials and sends the user agent and language the config
+    /// asks for.
+    pub fn new_tab(&self) -> Result<Arc<Tab>> {
+        self.new_tab_in(self.context.as_deref())
+    }
+
+    /// A new tab like [`Driver::new_tab`], in the browser context `context`, or Chrome's
+    /// default one.
+    pub fn new_tab_in(&self, context: Option<&str>) -> Result<Arc<Tab>> {
+        let tab = Self::open_tab(&self.browser, context)?;
+        self.prepare_tab(&tab)?;
+        Ok(tab)
+    }
+
+    fn open_tab(browser: &Browser, context: Option<&str>) -> Result<Arc<Tab>> {
+        browser.new_tab_with_options(CreateTarget {
+            url: "about:blank".to_string(),
+            width: None,
+            height: None,
+            browser_context_id: context.map(str::to_string),
+            enable_begin_frame_control: None,
+            new_window: None,
+            background: None,
+        })
+    }
+
+    /// Creates a browser context of its own, with no cookies or cache shared with the other
+    /// contexts, whose downloads go to `download_path`. It's given the cookies of the
+    /// driver's context, so a signed-in driver's session carries over. Returns its id, for
+    /// [`Driver::new_tab_in`]; it lasts as long as Chrome does.
+    pub fn create_context(&self, download_path: Option<&str>) -> Result<String> {
+        let id = self.browser.new_context()?.get_id().to_string();
+        let tab = Self::open_tab(&self.browser, Some(&id))?;
+        if let Some(download_path) = download_path {
+            Self::set_download_path(&tab, Some(&id), download_path).map_err(|e| anyhow!("Failed to set download path: {}", e))?;
+        }
+        let cookies = self.main_tab().call_method(GetAllCookies(None))?.cookies;
+        if !cookies.is_empty() {
+            tab.set_cookies(cookies.into_iter().map(keystore::cookie_param).collect())?;
+        }
+        let _ = tab.close(true);
+        Ok(id)
+    }
+
+    /// Waits for the page `tab` is navigating to to load, for the navigation timeout rather
+    /// than the element one the tab waits for otherwise.
+    pub fn wait_until_loaded(&self, tab: &Tab) -> Result<()> {
+        tab.set_default_timeout(self.config.timeouts.navigation);
+        let loaded = tab.wait_until_navigated().map(|_| ());
+        tab.set_default_timeout(self.config.timeouts.element);
+        loaded
+    }
+
+    /// Navigates `tab` to `url` and waits for the page to load, loading it again when the
+    /// network fails it for a moment.
+    pub fn load(&self, tab: &Tab, url: &str) -> Result<()> {
+        self.load_until(tab, url, |tab| self.wait_until_loaded(tab))
+    }
+
+    /// Like [`Driver::load`], with `loaded` telling when the page is ready; a page that
+    /// isn't is loaded again too.
+    pub fn load_until<T>(&self, tab: &Tab, url: &str, loaded: impl Fn(&Tab) -> Result<T>) -> Result<T> {
+        Retries::default().run(url, || {
+            tab.navigate_to(url)?;
+            loaded(tab)
+        })
+    }
+
+    /// The browser context the driver's own tabs open in.
+    pub fn context(&self) -> Option<&str> {
+        self.context.as_deref()
+    }
+
+    fn prepare_tab(&self, tab: &Tab) -> Result<()> {
+        tab.set_default_timeout(self.config.timeouts.element);
+        Self::authenticate(tab, self.config.proxy.as_ref())?;
+        if let Some(user_agent) = &self.user_agent {
+            tab.set_user_agent(user_agent, self.config.accept_language.as_deref(), None)?;
+        }
+        Ok(())
+    }
+
+    /// Has `tab` give the proxy's credentials when asked. Chrome can't be given them on the
+    /// command line, so every request of the tab passes through the Fetch domain.
+    fn authenticate(tab: &Tab, proxy: Option<&Proxy>) -> Result<()> {
+        let Some(username) = proxy.and_then(|proxy| proxy.username.clone()) else {
+            return Ok(());
+        };
+        tab.authenticate(Some(username), proxy.and_then(|proxy| proxy.password.clone()))?;
+        tab.enable_fetch(None, Some(true))?;
+        Ok(())
+    }
+
+    fn launch(config: &Config) -> Result<Browser> {
+        let proxy_server = config.proxy.as_ref().map(Proxy::server);
+        let mode = config.launch_mode();
+        let mut args = vec![
+            OsStr::new("--disable-dev-shm-usage"),
+            OsStr::new("--no-sandbox"),
+            OsStr::new("--disable-setuid-sandbox"),
+            OsStr::new("--disable-gpu"),
+            OsStr::new("--disable-software-rasterizer"),
+            OsStr::new("--disable-background-timer-throttling"),
+            OsStr::new("--disable-backgrounding-occluded-windows"),
+            OsStr::new("--disable-renderer-backgrounding"),
+        ];
+        // headless_chrome only knows the plain --headless, which it passes for the old mode
+        if mode == HeadlessMode::New {
+            args.push(OsStr::new("--headless=new"));
+        }
+        Browser::new(LaunchOptions {
+            headless: mode == HeadlessMode::Old,
+            window_size: Some(config.window_size),
             enable_logging: true,
             ignore_certificate_errors: true,
             sandbox: false,
-            args: vec![
-                OsStr::new("--disable-dev-shm-usage"),
-                OsStr::new("--no-sandbox"),
-                OsStr::new("--disable-setuid-sandbox"),
-                OsStr::new("--disable-gpu"),
-                OsStr::new("--disable-software-rasterizer"),
-                OsStr::new("--disable-background-timer-throttling"),
-                OsStr::new("--disable-backgrounding-occluded-windows"),
-                OsStr::new("--disable-renderer-backgrounding"),
-            ],            
+            args,
+            proxy_server: proxy_server.as_deref(),
+            user_data_dir: config.persistent_profile.clone(),
+            path: config.chrome_path.clone(),
             ..Default::default()
         })
-        .expect("Unable to create headless Chromium browser");
-                
-        if let Some(download_path) = &config.download_path {
-            Self::set_download_path(&browser, download_path)
-                .expect("Failed to set download path");
-        }
+        .map_err(|e| anyhow!("Unable to create headless Chromium browser: {}", e))
+    }
 
-        let raw_tab = browser.new_tab().expect("Failed to create tab");
-        raw_tab.set_default_timeout(Duration::from_secs(3600));
-        
-        Self {
-            config,
-            browser,
-            main_tab: raw_tab,
+    /// Whether the driver is working in a Chrome it connected to rather than launched.
+    pub fn is_connected(&self) -> bool {
+        self.foreign_tabs.is_some()
+    }
+
+    /// Whether the driver launched a Chrome without a window, which no one can solve a
+    /// CAPTCHA in.
+    pub fn is_headless(&self) -> bool {
+        !self.is_connected() && self.config.launch_mode() != HeadlessMode::Off
+    }
+
+    /// Hands a browser check or CAPTCHA on the page of `tab` to the user: with a window, they
+    /// solve it there and press Enter, until the page no longer shows one; headless, it's a
+    /// [`ChallengeDetected`] error at once rather than a timeout later. Returns whether
+    /// there was one, after which the caller should check the page again.
+    pub fn pass_challenge(&self, tab: &Tab) -> Result<bool> {
+        let mut solved = false;
+        loop {
+            let title = tab.get_title().unwrap_or_default();
+            let html = tab.get_content().unwrap_or_default();
+            let Some(marker) = challenge::detect(&title, &html) else {
+                return Ok(solved);
+            };
+            if self.is_headless() {
+                return Err(ChallengeDetected { url: tab.get_url(), marker: marker.to_string() }.into());
+            }
+            tracing::warn!("The site is asking to check the browser at {} (found {:?})", tab.get_url(), marker);
+            prompt(&format!("Solve the check at {} in its Chrome window, then press Enter to continue... ", tab.get_url()), false)?;
+            solved = true;
         }
     }
 
     pub fn get_tab(&self) -> Result<Arc<Tab>> {
-        if self.main_tab.evaluate("true;", true).is_err() {
+        let tab = self.main_tab();
+        if tab.evaluate("true;", true).is_err() {
             return Err(anyhow!("Browser tab is no longer responsive"));
         }
-        Ok(self.main_tab.clone())
+        Ok(tab)
     }
 
-    fn set_download_path(browser: &Browser, download_path: &str) -> Result<(), Box<dyn Error>> {
-        let tab = browser.new_tab()?;
-        
-        let download_behavior_method = headless_chrome::protocol::cdp::Browser::SetDownloadBehavior {
-            browser_context_id: None,
-            behavior: headless_chrome::protocol::cdp::Browser::SetDownloadBehaviorBehaviorOption::Allow,
+    /// Sends the downloads of the browser context `context`, or of Chrome's default one,
+    /// to `download_path`; `tab` only carries the command.
+    fn set_download_path(tab: &Tab, context: Option<&str>, download_path: &str) -> Result<(), Box<dyn Error>> {
+        let download_behavior_method = SetDownloadBehavior {
+            browser_context_id: context.map(str::to_string),
+            behavior: SetDownloadBehaviorBehaviorOption::Allow,
             download_path: Some(download_path.to_string()),
             events_enabled: None
         };
@@ -86,133 +573,106 @@ impl Driver {
         Ok(())
     }
 
-    fn verify_table_content(&self, tab: &Tab) -> Result<bool> {
-        let verify_js = r#"
-            document.querySelectorAll('td.my-downloaded-files__song.min-w-120').length > 0
-        "#;
-        
-        let result = tab.evaluate(verify_js, true)?;
-        Ok(result.value.and_then(|v| v.as_bool()).unwrap_or(false))
-    }
-
-    pub fn collect_all_custom_track_urls(&self) -> Result<Vec<String>> {
-        use std::time::Duration;
-        use std::thread::sleep;
-    
-        let mut all_urls = Vec::new();
-        let tab = self.browser.new_tab()?;
-        tab.set_default_timeout(Duration::from_secs(60));
-    
-        tracing::info!("Navigating to downloads page...");
-        tab.navigate_to(&format!("https://{}/my/download.html", self.config.domain))?;
-        tab.wait_until_navigated()?;
-        sleep(Duration::from_secs(2));
-    
-        tracing::info!("Selecting Custom Backing Track filter...");
-        // Wait for the select element and set the filter.
-        tab.wait_for_element("select[name='file_type']")?;
-        let set_filter_js = r#"
-          let select = document.querySelector('select[name="file_type"]');
-          if(select) {
-            select.value = '1';
-            select.dispatchEvent(new Event('change'));
-          }
-          true;
-        "#;
-        tab.evaluate(set_filter_js, true)?;
-        sleep(Duration::from_secs(2));
-    
-        let mut page_number = 1;
-        loop {
-            tracing::info!("Processing page {}...", page_number);
-            // Wait for the table rows.
-            if let Err(e) = tab.wait_for_element_with_custom_timeout("#tab_files tbody tr", Duration::from_secs(60)) {
-                tracing::warn!("Rows did not appear on page {}: {}", page_number, e);
-                break;
-            }
-            sleep(Duration::from_secs(2)); // Allow extra time for the rows to be populated.
-    
-            // Evaluate our extraction snippet.
-            let extraction_js = r#"
-                (function(){
-                try {
-                    let tbody = document.querySelector('#tab_files tbody');
-                    if (!tbody) {
-                    return JSON.stringify({error: "No tbody found"});
-                    }
-                    let rows = tbody.querySelectorAll('tr');
-                    console.log("Number of rows found:", rows.length);
-                    let links = Array.from(rows).map(function(row){
-                    let anchor = row.querySelector('td.my-downloaded-files__song.min-w-120 a');
-                    return anchor ? { href: anchor.getAttribute('href'), title: anchor.textContent.trim() } : null;
-                    }).filter(x => x !== null);
-                    return JSON.stringify(links);
-                } catch(e) {
-                    return JSON.stringify({error: e.toString()});
+    /// Clicks the accept button of a cookie-consent banner over the page, if there's one.
+    pub fn dismiss_consent(&self, tab: &Tab) -> bool {
+        for selector in forms::CONSENT_BUTTONS {
+            if let Ok(button) = tab.find_element(selector) {
+                if button.click().is_ok() {
+                    tracing::debug!("Dismissed the consent banner with {}", selector);
+                    return true;
                 }
-                })();
-            "#;
-            let result = tab.evaluate(extraction_js, true)?;
-            tracing::debug!("Extraction result raw: {:?}", result.value);
-            
-            // Expect result.value to be a JSON string
-            if let Some(json_str) = result.value.and_then(|v| v.as_str().map(|s| s.to_owned())) {
-                let parsed: serde_json::Value = serde_json::from_str(&json_str)?;
-
-                if let serde_json::Value::Array(items) = parsed {
-                    for item in items {
-                        if let (Some(href), Some(title)) = (
-                            item.get("href").and_then(|v| v.as_str()),
-                            item.get("title").and_then(|v| v.as_str())
-                        ) {
-                            let full_url = format!("https://{}{}", self.config.domain, href);
-                            tracing::info!("Found track: {} at {}", title, full_url);
-                            all_urls.push(full_url);
-                        }
-                    }
-                } else if let Some(error) = parsed.get("error").and_then(|v| v.as_str()) {
-                    tracing::warn!("Extraction error on page {}: {}", page_number, error);
+            }
+        }
+        false
+    }
+
+    /// The element `selector` of `tab` once it can be clicked or typed into, dismissing a
+    /// consent banner that covers it, within the login timeout.
+    pub fn wait_interactable<'a>(&self, tab: &'a Tab, selector: &str) -> Result<Element<'a>> {
+        let timeout = self.config.timeouts.login;
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Ok(element) = tab.find_element(selector) {
+                let ready = element
+                    .call_js_fn(forms::IS_INTERACTABLE, vec![], false)
+                    .ok()
+                    .and_then(|result| result.value)
+                    .and_then(|value| value.as_bool())
+                    .unwrap_or(false);
+                if ready {
+                    return Ok(element);
                 }
-            } else {
-                tracing::warn!("Extraction result on page {}: None", page_number);
             }
+            if Instant::now() >= deadline {
+                return Err(anyhow!("{} didn't become usable within {:?}", selector, timeout));
+            }
+            self.dismiss_consent(tab);
+            sleep(INTERACTABLE_POLL);
+        }
+    }
 
-            // Pagination: Get next page link.
-            let next_js = r#"
-              (function(){
-                let nextElem = document.querySelector('.pagination a.next');
-                return nextElem ? nextElem.getAttribute('href') : null;
-              })();
-            "#;
-            let next_result = tab.evaluate(next_js, true)?;
-            // Convert the result to an owned String.
-            let next_href_opt = next_result.value.and_then(|v| v.as_str().map(String::from));
-            if let Some(next_href_value) = next_href_opt {
-                tracing::info!("Found next page link: {}", next_href_value);
-                let full_next_url = if next_href_value.starts_with("http") {
-                    next_href_value
-                } else {
-                    format!("https://{}{}", self.config.domain, next_href_value)
-                };
-                tracing::info!("Navigating to next page: {}", full_next_url);
-                tab.navigate_to(&full_next_url)?;
-                tab.wait_until_navigated()?;
-                sleep(Duration::from_secs(2));
-                page_number += 1;
-            } else {
-                tracing::info!("No more pages (current page: {})", page_number);
-                break;
+    /// Fills the field `selector` of `tab` with `value`, set on the field itself rather than
+    /// typed into whatever has the focus, and checks it kept it. A banner taking the focus is
+    /// dismissed and the field filled again, once.
+    pub fn fill_field(&self, tab: &Tab, selector: &str, value: &str) -> Result<()> {
+        let mut dismissed = false;
+        loop {
+            let field = self.wait_interactable(tab, selector)?;
+            let result = field.call_js_fn(forms::FILL_FIELD, vec![serde_json::json!(value)], false)?;
+            let name = result.value.as_ref().and_then(|value| value.as_str()).unwrap_or("value");
+            let Some(problem) = FillProblem::from_name(name) else {
+                return Ok(());
+            };
+            if problem == FillProblem::Focus && !dismissed && self.dismiss_consent(tab) {
+                dismissed = true;
+                continue;
+            }
+            return Err(FieldNotFilled {
+                selector: selector.to_string(),
+                problem,
             }
+            .into());
         }
-    
-        tracing::info!("Collection complete! Found {} total tracks", all_urls.len());
-        Ok(all_urls)
     }
-        
-    pub fn type_fast(&self, tab: &Tab, text: &str) {
-        for c in text.chars() {
-            tab.send_character(&c.to_string())
-                .expect("failed to send character");
+}
+
+/// Creates the profile folder `dir` if need be and locks it for this run, so a second run
+/// fails instead of launching a Chrome that would corrupt the profile or hand its tabs over
+/// to the first one.
+pub fn lock_profile(dir: &Path) -> Result<File> {
+    fs::create_dir_all(dir).map_err(|e| anyhow!("Unable to create the profile folder {:?}: {}", dir, e))?;
+    let path = dir.join(PROFILE_LOCK);
+    let mut file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&path)
+        .map_err(|e| anyhow!("Unable to open {:?}: {}", path, e))?;
+    match file.try_lock() {
+        Ok(()) => {}
+        Err(TryLockError::WouldBlock) => {
+            return Err(anyhow!("The profile {:?} is in use by another kv-downloader run", dir));
         }
+        Err(TryLockError::Error(e)) => return Err(anyhow!("Unable to lock {:?}: {}", path, e)),
     }
-}
\ No newline at end of file
+    // Which process has it, for whoever finds the file
+    file.set_len(0)?;
+    writeln!(file, "{}", std::process::id())?;
+    Ok(file)
+}
+
+/// A connected Chrome keeps running with the tabs it had; only those the driver opened are
+/// closed. A launched one is closed along with the browser.
+impl Drop for Driver {
+    fn drop(&mut self) {
+        // Before the browser goes, so the last ping isn't sent to a closed one
+        self.stop_keepalive();
+        let Some(foreign) = &self.foreign_tabs else {
+            return;
+        };
+        let tabs = self.browser.get_tabs().lock().unwrap().clone();
+        for tab in tabs.iter().filter(|tab| !foreign.contains(tab.get_target_id())) {
+            let _ = tab.close(false);
+        }
+    }
+}
@@ -0,0 +1,76 @@
+use std::error::Error;
+use std::fmt::Display;
+
+/// The accept buttons of the cookie-consent banners a page may put over its forms, which
+/// would take the focus or the clicks meant for a field.
+pub const CONSENT_BUTTONS: &[&str] = &[
+    "#didomi-notice-agree-button",
+    "#onetrust-accept-btn-handler",
+    "#axeptio_btn_acceptAll",
+    "#CybotCookiebotDialogBodyLevelButtonLevelOptinAllowAll",
+    "button[data-cookiefirst-action='accept']",
+    ".cc-allow",
+];
+
+/// Whether the element can be clicked or typed into: enabled, laid out, and not covered by
+/// another element such as a banner. It's scrolled into view first.
+pub const IS_INTERACTABLE: &str = "function() {
+    if (this.disabled || this.readOnly) { return false; }
+    this.scrollIntoView({ block: 'center' });
+    const rect = this.getBoundingClientRect();
+    if (rect.width === 0 || rect.height === 0) { return false; }
+    const top = document.elementFromPoint(rect.left + rect.width / 2, rect.top + rect.height / 2);
+    return top !== null && (top === this || this.contains(top) || top.contains(this));
+}";
+
+/// Focuses the field and sets its value to the argument, through the setter of its type so
+/// pages that track the value see it, then raises the events typing would. Returns what
+/// went wrong, as a [`FillProblem`] name, or `""`. The value itself never comes back.
+pub const FILL_FIELD: &str = "function(value) {
+    this.focus();
+    if (document.activeElement !== this) { return 'focus'; }
+    const property = Object.getOwnPropertyDescriptor(Object.getPrototypeOf(this), 'value');
+    if (property && property.set) { property.set.call(this, value); } else { this.value = value; }
+    this.dispatchEvent(new Event('input', { bubbles: true }));
+    this.dispatchEvent(new Event('change', { bubbles: true }));
+    return this.value === value ? '' : 'value';
+}";
+
+/// Why a field couldn't be filled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FillProblem {
+    /// Something else, e.g. a banner or the page's own script, kept the focus.
+    Focus,
+    /// The field didn't keep the value, e.g. it's shorter than the value.
+    Value,
+}
+
+impl FillProblem {
+    /// The problem [`FILL_FIELD`] reported as `name`, or `None` for none.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "focus" => Some(Self::Focus),
+            "value" => Some(Self::Value),
+            _ => None,
+        }
+    }
+}
+
+/// A field of a form couldn't be filled. It doesn't carry the value, which may be a
+/// password.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldNotFilled {
+    pub selector: String,
+    pub problem: FillProblem,
+}
+
+impl Display for FieldNotFilled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.problem {
+            FillProblem::Focus => write!(f, "Unable to fill {}: another element of the page kept the focus", self.selector),
+            FillProblem::Value => write!(f, "Unable to fill {}: the field didn't keep the value it was given", self.selector),
+        }
+    }
+}
+
+impl Error for FieldNotFilled {}
@@ -0,0 +1,57 @@
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// How often the driver pings its tab, unless `--keepalive` says otherwise.
+pub const DEFAULT_KEEPALIVE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// The keep-alive interval of `--keepalive` or `keepalive_secs`: the default when not
+/// given, and none at all for 0.
+pub fn interval_from_secs(secs: Option<u64>) -> Option<Duration> {
+    match secs {
+        None => Some(DEFAULT_KEEPALIVE_INTERVAL),
+        Some(0) => None,
+        Some(secs) => Some(Duration::from_secs(secs)),
+    }
+}
+
+/// A thread that calls `ping` every `interval` until it's dropped, which wakes it at once
+/// rather than after the interval and waits for it to finish.
+pub struct KeepAlive {
+    stop: Arc<(Mutex<bool>, Condvar)>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl KeepAlive {
+    pub fn start(interval: Duration, ping: impl Fn() + Send + 'static) -> Self {
+        let stop = Arc::new((Mutex::new(false), Condvar::new()));
+        let thread = {
+            let stop = Arc::clone(&stop);
+            std::thread::spawn(move || {
+                let (stopped, wake) = &*stop;
+                let mut stopped = stopped.lock().unwrap();
+                loop {
+                    stopped = wake.wait_timeout_while(stopped, interval, |stopped| !*stopped).unwrap().0;
+                    if *stopped {
+                        return;
+                    }
+                    // The ping may be slow on a busy browser; stopping needn't wait for the lock
+                    drop(stopped);
+                    ping();
+                    stopped = stop.0.lock().unwrap();
+                }
+            })
+        };
+        Self { stop, thread: Some(thread) }
+    }
+}
+
+impl Drop for KeepAlive {
+    fn drop(&mut self) {
+        *self.stop.0.lock().unwrap() = true;
+        self.stop.1.notify_all();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use super::SecretStore;
+
+/// Keeps the secrets wherever an external program does, such as a small wrapper around
+/// `op read` or `vault kv get`.
+///
+/// The program is run once per operation, with its configured arguments only, and reads one
+/// JSON request from stdin:
+///
+/// ```json
+/// {"action": "get", "name": "KV_CREDENTIALS@www.karaoke-version.com"}
+/// {"action": "set", "name": "KV_SESSION@www.karaoke-version.com", "secret": "..."}
+/// {"action": "delete", "name": "KV_SESSION@www.karaoke-version.com"}
+/// ```
+///
+/// It exits with 0 on success. For `get` it prints `{"secret": "..."}` to stdout, or
+/// `{"secret": null}` when there's no such secret; what `set` and `delete` print is
+/// ignored, and deleting a missing secret must succeed. Any other exit status fails the
+/// operation with the program's stderr.
+pub struct CommandStore {
+    program: String,
+    args: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct Request<'a> {
+    action: &'a str,
+    name: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    secret: Option<&'a str>,
+}
+
+#[derive(Deserialize)]
+struct Response {
+    secret: Option<String>,
+}
+
+impl CommandStore {
+    /// `command` is the program followed by its arguments.
+    pub fn new(command: Vec<String>) -> Result<Self> {
+        let mut command = command.into_iter();
+        let program = command.next().ok_or_else(|| anyhow!("No keystore command to run"))?;
+        Ok(Self {
+            program,
+            args: command.collect(),
+        })
+    }
+
+    /// Runs the program with `request` and returns its stdout.
+    fn run(&self, request: &Request) -> Result<Vec<u8>> {
+        let mut child = Command::new(&self.program)
+            .args(&self.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("Unable to run the keystore command {:?}", self.program))?;
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        serde_json::to_writer(&mut stdin, request)?;
+        stdin.write_all(b"\n")?;
+        drop(stdin);
+
+        let output = child.wait_with_output()?;
+        if !output.status.success() {
+            return Err(anyhow!(
+                "Keystore command {:?} failed to {} {} ({}): {}",
+                self.program,
+                request.action,
+                request.name,
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        Ok(output.stdout)
+    }
+}
+
+impl SecretStore for CommandStore {
+    fn get_secret(&self, name: &str) -> Result<Option<String>> {
+        let stdout = self.run(&Request {
+            action: "get",
+            name,
+            secret: None,
+        })?;
+        let response: Response = serde_json::from_slice(&stdout)
+            .with_context(|| format!("Keystore command {:?} didn't print a JSON secret for {}", self.program, name))?;
+        Ok(response.secret)
+    }
+
+    fn set_secret(&self, name: &str, secret: &str) -> Result<()> {
+        self.run(&Request {
+            action: "set",
+            name,
+            secret: Some(secret),
+        })?;
+        Ok(())
+    }
+
+    fn delete_secret(&self, name: &str) -> Result<()> {
+        self.run(&Request {
+            action: "delete",
+            name,
+            secret: None,
+        })?;
+        Ok(())
+    }
+}
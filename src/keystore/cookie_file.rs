@@ -0,0 +1,89 @@
+use anyhow::{anyhow, Result};
+use headless_chrome::protocol::cdp::Network::{Cookie, CookieParam};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::cookie_param;
+
+/// The cookies of a site's session in a JSON file, for `--cookies-file` on a machine
+/// without a keychain. The whole of the site's jar is kept, not just the session cookie,
+/// and only the owner may read the file.
+pub struct CookieFile {
+    path: PathBuf,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedCookies {
+    /// The site signed in to; another site's file isn't applied.
+    domain: String,
+    cookies: Vec<Cookie>,
+}
+
+impl CookieFile {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The unexpired cookies saved for `domain`, ready to set on a tab; none when there's
+    /// no file yet or it was saved for another site.
+    pub fn load(&self, domain: &str) -> Result<Vec<CookieParam>> {
+        let data = match fs::read_to_string(&self.path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(anyhow!("Unable to read the cookies file {:?}: {}", self.path, e)),
+        };
+        let saved: SavedCookies =
+            serde_json::from_str(&data).map_err(|e| anyhow!("{:?} isn't a cookies file: {}", self.path, e))?;
+        if saved.domain != domain {
+            tracing::warn!("The cookies file {:?} is for {}, not {}; signing in without it", self.path, saved.domain, domain);
+            return Ok(vec![]);
+        }
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0.0, |now| now.as_secs_f64());
+        Ok(saved.cookies.into_iter().filter(|cookie| !is_expired(cookie, now)).map(cookie_param).collect())
+    }
+
+    /// Replaces the file with the cookies of `cookies` that belong to `domain`, and returns
+    /// how many there were. The file is written owner-only and replaced in one rename.
+    pub fn save(&self, domain: &str, cookies: Vec<Cookie>) -> Result<usize> {
+        let cookies: Vec<Cookie> = cookies.into_iter().filter(|cookie| belongs_to(&cookie.domain, domain)).collect();
+        let count = cookies.len();
+        let mut data = serde_json::to_string_pretty(&SavedCookies { domain: domain.to_string(), cookies })?;
+        data.push('\n');
+        if let Some(parent) = self.path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            fs::create_dir_all(parent).map_err(|e| anyhow!("Unable to create {:?}: {}", parent, e))?;
+        }
+        let partial = self.path.with_extension("json.partial");
+        let _ = fs::remove_file(&partial);
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        options
+            .open(&partial)
+            .and_then(|mut file| file.write_all(data.as_bytes()))
+            .map_err(|e| anyhow!("Unable to write {:?}: {}", partial, e))?;
+        fs::rename(&partial, &self.path).map_err(|e| anyhow!("Unable to replace {:?}: {}", self.path, e))?;
+        Ok(count)
+    }
+}
+
+/// Whether a cookie of `cookie_domain`, such as `.karaoke-version.com`, is sent to the site
+/// `domain` or to another host of it, such as its `www.` and bare names.
+pub fn belongs_to(cookie_domain: &str, domain: &str) -> bool {
+    let cookie_domain = cookie_domain.trim_start_matches('.');
+    let site = domain.strip_prefix("www.").unwrap_or(domain);
+    cookie_domain == site || cookie_domain.ends_with(&format!(".{}", site)) || domain.ends_with(&format!(".{}", cookie_domain))
+}
+
+/// A session cookie, whose `expires` is -1, never expires here; Chrome ends it with the
+/// browser, but the site may still take it.
+fn is_expired(cookie: &Cookie, now: f64) -> bool {
+    cookie.expires > 0.0 && cookie.expires <= now
+}
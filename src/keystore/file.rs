@@ -0,0 +1,184 @@
+use anyhow::{anyhow, Context, Result};
+use argon2::Argon2;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use super::SecretStore;
+use crate::prompt::prompt;
+
+/// The passphrase of the file store, instead of asking for it.
+pub const STORE_KEY_VAR: &str = "KV_STORE_KEY";
+const FORMAT_VERSION: u32 = 1;
+const SALT_LEN: usize = 16;
+
+/// The file as written: the secrets, by name, as JSON sealed with ChaCha20-Poly1305 under a
+/// key derived from the passphrase with Argon2.
+#[derive(Serialize, Deserialize)]
+struct Sealed {
+    version: u32,
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+/// The key of an open file, with the salt it was derived with.
+struct Unlocked {
+    salt: Vec<u8>,
+    key: Key,
+}
+
+/// Keeps the secrets in an encrypted file, for a machine without a keychain, such as a
+/// headless Raspberry Pi. The passphrase comes from [`STORE_KEY_VAR`] or is asked for once,
+/// the first time a secret is needed. The file is only readable by its owner, and replaced
+/// in one rename on every change.
+pub struct FileStore {
+    path: PathBuf,
+    passphrase: Option<String>,
+    unlocked: Mutex<Option<Unlocked>>,
+}
+
+impl FileStore {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            passphrase: std::env::var(STORE_KEY_VAR).ok(),
+            unlocked: Mutex::new(None),
+        }
+    }
+
+    /// A store of `path` that's opened with `passphrase` rather than asking for one.
+    pub fn with_passphrase(path: PathBuf, passphrase: &str) -> Self {
+        Self {
+            path,
+            passphrase: Some(passphrase.to_string()),
+            unlocked: Mutex::new(None),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The passphrase, asked for twice when it's that of a new file, so a typo doesn't
+    /// lock the secrets away.
+    fn passphrase(&self) -> Result<String> {
+        if let Some(passphrase) = &self.passphrase {
+            return Ok(passphrase.clone());
+        }
+        if self.path.exists() {
+            return prompt(&format!("Passphrase of {:?}: ", self.path), true);
+        }
+        let passphrase = prompt(&format!("New passphrase for {:?}: ", self.path), true)?;
+        if prompt("Repeat the passphrase: ", true)? != passphrase {
+            return Err(anyhow!("The passphrases of {:?} don't match", self.path));
+        }
+        Ok(passphrase)
+    }
+
+    /// The key for `salt`, derived only the first time.
+    fn key<'a>(&self, unlocked: &'a mut Option<Unlocked>, salt: &[u8]) -> Result<&'a Key> {
+        if unlocked.as_ref().is_none_or(|unlocked| unlocked.salt != salt) {
+            let mut key = [0u8; 32];
+            Argon2::default()
+                .hash_password_into(self.passphrase()?.as_bytes(), salt, &mut key)
+                .map_err(|e| anyhow!("Unable to derive the key of {:?}: {}", self.path, e))?;
+            *unlocked = Some(Unlocked {
+                salt: salt.to_vec(),
+                key: key.into(),
+            });
+        }
+        Ok(&unlocked.as_ref().expect("just derived").key)
+    }
+
+    fn load(&self, unlocked: &mut Option<Unlocked>) -> Result<BTreeMap<String, String>> {
+        if !self.path.exists() {
+            return Ok(BTreeMap::new());
+        }
+        let data = fs::read_to_string(&self.path).with_context(|| format!("Unable to read {:?}", self.path))?;
+        let sealed: Sealed = serde_json::from_str(&data).with_context(|| format!("{:?} isn't a credential store", self.path))?;
+        if sealed.version != FORMAT_VERSION {
+            return Err(anyhow!("{:?} is a version {} credential store; this one reads {}", self.path, sealed.version, FORMAT_VERSION));
+        }
+        let salt = STANDARD.decode(&sealed.salt)?;
+        let nonce = STANDARD.decode(&sealed.nonce)?;
+        if nonce.len() != 12 {
+            return Err(anyhow!("{:?} is damaged", self.path));
+        }
+        let cipher = ChaCha20Poly1305::new(self.key(unlocked, &salt)?);
+        let plain = cipher
+            .decrypt(Nonce::from_slice(&nonce), STANDARD.decode(&sealed.ciphertext)?.as_slice())
+            .map_err(|_| {
+                // A wrong passphrase mustn't stick for the next attempt
+                *unlocked = None;
+                anyhow!("Unable to open {:?}: wrong passphrase, or the file is damaged", self.path)
+            })?;
+        Ok(serde_json::from_slice(&plain)?)
+    }
+
+    fn save(&self, unlocked: &mut Option<Unlocked>, secrets: &BTreeMap<String, String>) -> Result<()> {
+        let salt = match unlocked {
+            Some(unlocked) => unlocked.salt.clone(),
+            None => {
+                let mut salt = vec![0u8; SALT_LEN];
+                OsRng.fill_bytes(&mut salt);
+                salt
+            }
+        };
+        let cipher = ChaCha20Poly1305::new(self.key(unlocked, &salt)?);
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = cipher
+            .encrypt(&nonce, serde_json::to_vec(secrets)?.as_slice())
+            .map_err(|_| anyhow!("Unable to encrypt the secrets of {:?}", self.path))?;
+        let sealed = Sealed {
+            version: FORMAT_VERSION,
+            salt: STANDARD.encode(&salt),
+            nonce: STANDARD.encode(nonce),
+            ciphertext: STANDARD.encode(ciphertext),
+        };
+
+        if let Some(parent) = self.path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            fs::create_dir_all(parent).with_context(|| format!("Unable to create {:?}", parent))?;
+        }
+        let partial = self.path.with_extension("tmp");
+        let mut options = OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut file = options.open(&partial).with_context(|| format!("Unable to write {:?}", partial))?;
+        file.write_all(serde_json::to_string_pretty(&sealed)?.as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&partial, &self.path).with_context(|| format!("Unable to replace {:?}", self.path))?;
+        Ok(())
+    }
+}
+
+impl SecretStore for FileStore {
+    fn get_secret(&self, name: &str) -> Result<Option<String>> {
+        let mut unlocked = self.unlocked.lock().unwrap();
+        Ok(self.load(&mut unlocked)?.remove(name))
+    }
+
+    fn set_secret(&self, name: &str, secret: &str) -> Result<()> {
+        let mut unlocked = self.unlocked.lock().unwrap();
+        let mut secrets = self.load(&mut unlocked)?;
+        secrets.insert(name.to_string(), secret.to_string());
+        self.save(&mut unlocked, &secrets)
+    }
+
+    fn delete_secret(&self, name: &str) -> Result<()> {
+        let mut unlocked = self.unlocked.lock().unwrap();
+        let mut secrets = self.load(&mut unlocked)?;
+        if secrets.remove(name).is_some() {
+            self.save(&mut unlocked, &secrets)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,292 @@
+use anyhow::{anyhow, Result};
+use headless_chrome::protocol::cdp::Network::{Cookie, CookieParam};
+use keyring::credential::CredentialPersistence;
+use keyring::Entry;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::{Arc, OnceLock};
+
+use crate::domain::{DEFAULT_DOMAIN, KNOWN_DOMAINS};
+
+mod command;
+mod cookie_file;
+mod file;
+
+pub use command::CommandStore;
+pub use cookie_file::{belongs_to, CookieFile};
+pub use file::{FileStore, STORE_KEY_VAR};
+
+/// The backend `--credential-store` picked, over the config's.
+static BACKEND: OnceLock<Backend> = OnceLock::new();
+
+const KEYSTORE_SERVICE: &str = "kv-downloader";
+const KV_CREDENTIALS_KEY: &str = "KV_CREDENTIALS";
+const KV_SESSION_COOKIE_KEY: &str = "KV_SESSION";
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Credentials {
+    pub user: String,
+    pub password: String,
+}
+
+/// Keystore entry of the credentials: one per site when stored with `--domain`, otherwise
+/// the one shared by every site.
+pub fn credentials_key(domain: Option<&str>) -> String {
+    match domain {
+        Some(domain) => format!("{}@{}", KV_CREDENTIALS_KEY, domain),
+        None => KV_CREDENTIALS_KEY.to_string(),
+    }
+}
+
+/// Keystore entry of a site's session cookie, so signing in to one site doesn't replace
+/// the session of another.
+pub fn session_key(domain: &str) -> String {
+    format!("{}@{}", KV_SESSION_COOKIE_KEY, domain)
+}
+
+/// Where the credentials and session cookies are kept. Implementations only store named
+/// secrets; the credentials and cookies are built on top of those.
+pub trait SecretStore: Send + Sync {
+    /// The secret named `name`, or `None` when there's no such secret.
+    fn get_secret(&self, name: &str) -> Result<Option<String>>;
+
+    fn set_secret(&self, name: &str, secret: &str) -> Result<()>;
+
+    /// Removes the secret named `name`; removing one that doesn't exist is not an error.
+    fn delete_secret(&self, name: &str) -> Result<()>;
+
+    fn login(&self, user: &str, password: &str, domain: Option<&str>) -> Result<Credentials> {
+        let creds = Credentials {
+            user: user.to_string(),
+            password: password.to_string(),
+        };
+        self.set_secret(&credentials_key(domain), &serde_json::to_string(&creds)?)?;
+        Ok(creds)
+    }
+
+    /// Forgets the credentials stored for `domain` (or the shared ones) and, for a site,
+    /// its session.
+    fn logout(&self, domain: Option<&str>) -> Result<()> {
+        self.delete_secret(&credentials_key(domain))?;
+        if let Some(domain) = domain {
+            self.delete_secret(&session_key(domain))?;
+        }
+        Ok(())
+    }
+
+    /// The credentials stored for `domain`, falling back to the shared ones.
+    fn get_credentials(&self, domain: &str) -> Result<Credentials> {
+        let secret = match self.get_secret(&credentials_key(Some(domain)))? {
+            Some(secret) => secret,
+            None => self
+                .get_secret(KV_CREDENTIALS_KEY)?
+                .ok_or_else(|| anyhow!("No credentials stored for {}", domain))?,
+        };
+        Ok(serde_json::from_str(&secret)?)
+    }
+
+    /// The session cookie saved by the last sign-in to `domain`, if any.
+    fn get_auth_cookie(&self, domain: &str) -> Result<Option<CookieParam>> {
+        let mut secret = self.get_secret(&session_key(domain))?;
+        // Sessions saved before they were kept per site all belong to the default one
+        if secret.is_none() && domain == DEFAULT_DOMAIN {
+            secret = self.get_secret(KV_SESSION_COOKIE_KEY)?;
+        }
+        let Some(secret) = secret else {
+            return Ok(None);
+        };
+        let cookie: Cookie = serde_json::from_str(&secret)?;
+        Ok(Some(cookie_param(cookie)))
+    }
+
+    fn set_auth_cookie(&self, domain: &str, cookie: &Cookie) -> Result<()> {
+        self.set_secret(&session_key(domain), &serde_json::to_string_pretty(&cookie)?)
+    }
+}
+
+/// `cookie` as it's set on a tab; reading and setting cookies use different types.
+pub fn cookie_param(cookie: Cookie) -> CookieParam {
+    CookieParam {
+        name: cookie.name,
+        value: cookie.value,
+        url: None,
+        domain: Some(cookie.domain),
+        secure: Some(cookie.secure),
+        http_only: Some(cookie.http_only),
+        same_site: cookie.same_site,
+        path: Some(cookie.path),
+        expires: Some(cookie.expires),
+        priority: Some(cookie.priority),
+        same_party: Some(cookie.same_party),
+        source_scheme: Some(cookie.source_scheme),
+        source_port: Some(cookie.source_port),
+        partition_key: cookie.partition_key,
+    }
+}
+
+/// The operating system's keychain.
+pub struct Keystore {}
+
+impl SecretStore for Keystore {
+    fn get_secret(&self, name: &str) -> Result<Option<String>> {
+        match Entry::new(KEYSTORE_SERVICE, name)?.get_secret() {
+            Ok(secret) => Ok(Some(String::from_utf8(secret)?)),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn set_secret(&self, name: &str, secret: &str) -> Result<()> {
+        Entry::new(KEYSTORE_SERVICE, name)?.set_secret(secret.as_bytes())?;
+        Ok(())
+    }
+
+    fn delete_secret(&self, name: &str) -> Result<()> {
+        match Entry::new(KEYSTORE_SERVICE, name)?.delete_credential() {
+            Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Backend {
+    /// The OS keychain, or the file when there's no usable keychain.
+    #[default]
+    Keyring,
+    /// A [`CommandStore`].
+    Command,
+    /// A [`FileStore`].
+    File,
+}
+
+/// The `[keystore]` table of the config file.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct KeystoreConfig {
+    #[serde(default)]
+    pub backend: Backend,
+    /// Program and arguments of the `command` backend.
+    #[serde(default)]
+    pub command: Vec<String>,
+    /// The encrypted file of the `file` backend, and of the fallback from the keychain;
+    /// [`default_file`] when not given.
+    #[serde(default)]
+    pub file: Option<PathBuf>,
+}
+
+impl KeystoreConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self.backend == Backend::Command && self.command.is_empty() {
+            return Err(anyhow!("keystore.backend = \"command\" needs keystore.command to run"));
+        }
+        Ok(())
+    }
+}
+
+/// Makes [`open`] use `backend` whatever the config says, for `--credential-store`. Only
+/// the first call counts.
+pub fn force_backend(backend: Backend) {
+    let _ = BACKEND.set(backend);
+}
+
+/// The secret store `config` selects, or `--credential-store` does.
+pub fn open(config: &KeystoreConfig) -> Result<Arc<dyn SecretStore>> {
+    open_backend(config, BACKEND.get().copied().unwrap_or(config.backend))
+}
+
+/// The secret store of `backend`, with the settings of `config`. The keychain falls back to
+/// the file when the machine has none it can use.
+pub fn open_backend(config: &KeystoreConfig, backend: Backend) -> Result<Arc<dyn SecretStore>> {
+    let file = || config.file.clone().map_or_else(default_file, Ok);
+    Ok(match backend {
+        Backend::Keyring if keyring_usable() => Arc::new(Keystore {}),
+        Backend::Keyring => {
+            let path = file()?;
+            tracing::info!("No usable keychain; keeping the secrets in {:?}", path);
+            Arc::new(FileStore::new(path))
+        }
+        Backend::Command => {
+            config.validate()?;
+            Arc::new(CommandStore::new(config.command.clone())?)
+        }
+        Backend::File => Arc::new(FileStore::new(file()?)),
+    })
+}
+
+/// Whether the OS keychain answers at all; a missing entry is an answer. A build without
+/// a keychain backend only has keyring's in-memory mock, which forgets everything on exit.
+fn keyring_usable() -> bool {
+    let persistence = keyring::default::default_credential_builder().persistence();
+    if matches!(persistence, CredentialPersistence::EntryOnly | CredentialPersistence::ProcessOnly) {
+        tracing::debug!("The keychain only keeps secrets in memory");
+        return false;
+    }
+    match Entry::new(KEYSTORE_SERVICE, KV_CREDENTIALS_KEY) {
+        Ok(entry) => match entry.get_secret() {
+            Ok(_) | Err(keyring::Error::NoEntry) => true,
+            Err(e) => {
+                tracing::debug!("The keychain is unusable: {}", e);
+                false
+            }
+        },
+        Err(_) => false,
+    }
+}
+
+/// Where the `file` backend keeps the secrets unless `keystore.file` says otherwise:
+/// `kv-downloader/secrets.enc` in the user's config folder.
+pub fn default_file() -> Result<PathBuf> {
+    let config_dir = crate::config::config_dir().ok_or_else(|| anyhow!("No config folder for the credential store; set keystore.file"))?;
+    Ok(config_dir.join(KEYSTORE_SERVICE).join("secrets.enc"))
+}
+
+/// The names this program keeps the credentials and session cookies under, for every
+/// known site. A keychain can't list what it holds, so these are the ones looked for.
+fn secret_names() -> Vec<String> {
+    let mut names = vec![KV_CREDENTIALS_KEY.to_string(), KV_SESSION_COOKIE_KEY.to_string()];
+    for domain in KNOWN_DOMAINS {
+        names.push(credentials_key(Some(domain)));
+        names.push(session_key(domain));
+    }
+    names
+}
+
+/// Copies the credentials and session cookies of every known site from one store to
+/// another, and returns how many there were.
+pub fn copy_secrets(from: &dyn SecretStore, to: &dyn SecretStore) -> Result<usize> {
+    let mut copied = 0;
+    for name in secret_names() {
+        if let Some(secret) = from.get_secret(&name)? {
+            to.set_secret(&name, &secret)?;
+            copied += 1;
+        }
+    }
+    Ok(copied)
+}
+
+/// Deletes the credentials and session cookies of every known site from `store`, and
+/// returns the names of those it had.
+pub fn remove_secrets(store: &dyn SecretStore) -> Result<Vec<String>> {
+    let mut removed = vec![];
+    for name in secret_names() {
+        if store.get_secret(&name)?.is_some() {
+            store.delete_secret(&name)?;
+            removed.push(name);
+        }
+    }
+    Ok(removed)
+}
+
+/// The encrypted file [`open`] keeps the secrets in, or `None` when it picks another store.
+pub fn file_in_use(config: &KeystoreConfig) -> Result<Option<PathBuf>> {
+    let uses_file = match BACKEND.get().copied().unwrap_or(config.backend) {
+        Backend::Keyring => !keyring_usable(),
+        Backend::Command => false,
+        Backend::File => true,
+    };
+    if !uses_file {
+        return Ok(None);
+    }
+    config.file.clone().map_or_else(default_file, Ok).map(Some)
+}
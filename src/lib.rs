@@ -1,6 +1,23 @@
+pub mod abort;
+pub mod audit;
+pub mod cdp_trace;
+pub mod challenge;
 pub mod commands;
+pub mod config;
+pub mod debug_capture;
+pub mod domain;
 pub mod driver;
+pub mod forms;
+pub mod keepalive;
 pub mod keystore;
+pub mod metadata;
+pub mod navigation;
+pub mod permissions;
+pub mod profile;
 pub mod prompt;
+pub mod proxy;
+pub mod retention;
+pub mod status;
+pub mod table;
 pub mod tasks;
 pub mod audio;
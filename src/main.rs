@@ -1,13 +1,12 @@
 use anyhow::Result;
-use clap::{command, Parser, Subcommand};
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
 use dotenv::dotenv;
-
-mod commands;
-mod driver;
-mod keystore;
-mod prompt;
-mod tasks;
-pub mod audio;
+use kv_downloader::abort::{Interrupted, EXIT_INTERRUPTED};
+use kv_downloader::commands;
+use kv_downloader::config::{self, Config};
+use kv_downloader::keystore::{self, Backend};
+use kv_downloader::status::LogWriter;
+use kv_downloader::tasks::batch_report::BatchFailed;
 
 #[derive(Debug, Parser)]
 #[command(name = "kv-downloader")]
@@ -18,30 +17,124 @@ struct Cli {
 
     #[arg(global = true, long, help = "enable debug logging")]
     debug: bool,
+
+    #[arg(global = true, long, help = "log as JSON lines, with the song and stem of each line")]
+    log_json: bool,
+
+    #[arg(
+        global = true,
+        long,
+        value_name = "DOMAIN",
+        help = "Karaoke Version site to use when the song URL doesn't name one, e.g. karaoke-version.co.uk"
+    )]
+    domain: Option<String>,
+
+    #[arg(
+        global = true,
+        long,
+        value_enum,
+        value_name = "STORE",
+        help = "Keep the credentials and sessions in the OS keychain (keyring), the keystore command of --config (command), or an encrypted file (file), whatever the config says"
+    )]
+    credential_store: Option<Backend>,
 }
 
 #[derive(Debug, Subcommand)]
 enum Commands {
-    Auth,
-    Logout,
+    Auth(commands::auth::AuthCommand),
+    Logout(commands::auth::AuthArgs),
+    #[command(arg_required_else_help = true)]
+    Download(Box<commands::DownloadArgs>),
+    /// Processes song folders of downloaded MP3s, without signing in or going to the site
+    #[command(arg_required_else_help = true)]
+    Process(commands::ProcessArgs),
+    /// Writes songs into one flat folder for a hardware backing-track player
+    #[command(arg_required_else_help = true)]
+    Bundle(commands::BundleArgs),
+    /// Compares a song folder with its page, and with --apply downloads what changed
+    #[command(arg_required_else_help = true)]
+    Diff(commands::DiffArgs),
+    /// Lists the account's purchases, and which of them are downloaded
+    List(commands::ListArgs),
+    /// Finds songs in the library by title, artist, tag or stem
+    #[command(arg_required_else_help = true)]
+    Search(commands::SearchArgs),
+    /// Checks song folders for missing, empty or unreadable files, and with --fix rebuilds their projects
     #[command(arg_required_else_help = true)]
-    Download(commands::DownloadArgs),
+    Verify(commands::VerifyArgs),
+    /// Zips the debug artifacts of the last download, such as page captures and CDP traces, to attach to an issue
+    ReportIssue(commands::ReportIssueArgs),
 }
 
 fn main() -> Result<()> {
+    let result = run();
+    // A batch tells some songs failing from all of them failing by its exit status
+    if let Some(failed) = result.as_ref().err().and_then(|e| e.downcast_ref::<BatchFailed>()) {
+        eprintln!("Error: {}", failed);
+        std::process::exit(failed.exit_code);
+    }
+    if result.as_ref().err().is_some_and(|e| e.downcast_ref::<Interrupted>().is_some()) {
+        eprintln!("Interrupted; run the same command again to pick up where it stopped");
+        std::process::exit(EXIT_INTERRUPTED);
+    }
+    result
+}
+
+fn run() -> Result<()> {
     dotenv().ok();
-    let cli = Cli::parse();
-    tracing_subscriber::fmt()
-        .with_max_level(if cli.debug {
-            tracing::Level::DEBUG
-        } else {
-            tracing::Level::INFO
-        })
-        .init();
+    // The config file holds the defaults of the flags, so it's read before they're parsed
+    let args: Vec<_> = std::env::args_os().collect();
+    let config_path = config::path_in_args(&args).or_else(config::default_path);
+    // A broken file mustn't keep even `--help` from working; the command that loads it
+    // reports it again
+    let (flags, unreadable) = match config_path.as_deref().map(Config::read) {
+        Some(Ok(config)) => (config.flags, None),
+        Some(Err(e)) => (vec![], Some(e)),
+        None => (vec![], None),
+    };
+    let (command, unused) = config::with_flag_defaults(Cli::command(), &flags, config_path.as_deref());
+    let cli = Cli::from_arg_matches(&command.get_matches_from(args)).unwrap_or_else(|e| e.exit());
+    let level = if cli.debug {
+        tracing::Level::DEBUG
+    } else {
+        tracing::Level::INFO
+    };
+    // Spans (song, phase, stem) are part of every line in both formats. The logs go to
+    // stderr, around the progress bar, leaving stdout to what commands such as
+    // `list --json` print
+    if cli.log_json {
+        tracing_subscriber::fmt()
+            .json()
+            .with_current_span(false)
+            .with_span_list(true)
+            .with_max_level(level)
+            .with_writer(LogWriter)
+            .init();
+    } else {
+        tracing_subscriber::fmt().with_max_level(level).with_writer(LogWriter).init();
+    }
+    if let Some(e) = unreadable {
+        tracing::warn!("Not taking flag defaults from the config file: {:#}", e);
+    }
+    if let Some(path) = &config_path {
+        for key in unused {
+            tracing::warn!("Ignoring {} of {:?}, which is neither a setting nor a flag", key, path);
+        }
+    }
+    if let Some(backend) = cli.credential_store {
+        keystore::force_backend(backend);
+    }
     match cli.command {
-        Commands::Auth => commands::auth::run()?,
-        Commands::Logout => commands::logout::run()?,
-        Commands::Download(args) => commands::Download::run(args)?,
+        Commands::Auth(args) => commands::auth::run(args, cli.domain.as_deref())?,
+        Commands::Logout(args) => commands::logout::run(args, cli.domain.as_deref())?,
+        Commands::Download(args) => commands::Download::run(*args, cli.domain.as_deref())?,
+        Commands::Process(args) => commands::Process::run(args)?,
+        Commands::Bundle(args) => commands::Bundle::run(args)?,
+        Commands::Diff(args) => commands::Diff::run(args, cli.domain.as_deref())?,
+        Commands::List(args) => commands::List::run(args, cli.domain.as_deref())?,
+        Commands::Search(args) => commands::Search::run(args)?,
+        Commands::Verify(args) => commands::Verify::run(args)?,
+        Commands::ReportIssue(args) => commands::ReportIssue::run(args)?,
     }
 
     Ok(())
@@ -0,0 +1,399 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::audio::title;
+
+/// Name of the file, inside each song folder, with what was scraped from the song page and
+/// how the song was downloaded.
+pub const SONG_INFO_FILE: &str = "song.json";
+/// What [`SONG_INFO_FILE`] was called before it held more than the credits; still read.
+pub const LEGACY_SONG_INFO_FILE: &str = "song_info.json";
+
+/// Labels of the songwriter line in the "About" block, lowercased, per storefront locale.
+const COMPOSER_LABELS: &[&str] = &[
+    "songwriter",
+    "composer",
+    "auteur",
+    "compositeur",
+    "komponist",
+    "texter",
+    "compositor",
+    "autor",
+];
+
+/// Words, lowercased, that mark the key line of the audio details per storefront locale.
+const KEY_LABELS: &[&str] = &["key", "tonalité", "tonart", "tonalidad", "tonalità"];
+
+/// Words, lowercased, that mark the duration line of the audio details per storefront locale.
+const DURATION_LABELS: &[&str] = &["duration", "durée", "dauer", "duración", "durata"];
+
+/// Labels of the genres line in the "About" block, lowercased, per storefront locale.
+const GENRE_LABELS: &[&str] = &["genre", "género", "gener", "stil"];
+
+/// What's known of a downloaded song: its page's details and how it was downloaded. The
+/// page keeps the credits apart: the performer is who the arrangement is "in the style of"
+/// (what a library groups by), the composers are the songwriters (what licensing
+/// paperwork needs).
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SongInfo {
+    pub url: String,
+    /// The song folder's name, so the page needn't be fetched again to find it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    pub performer: Option<String>,
+    /// Empty on pages without a songwriter line.
+    #[serde(default)]
+    pub composers: Vec<String>,
+    /// The site's ID for the arrangement, the same on every storefront.
+    #[serde(default)]
+    pub arrangement_id: Option<String>,
+    /// The tempo line of the audio details, e.g. `variable (around 87 BPM)`.
+    #[serde(default)]
+    pub tempo: Option<String>,
+    /// The key the arrangement is in, e.g. `E`.
+    #[serde(default)]
+    pub key: Option<String>,
+    /// As the page shows it, e.g. `04:58`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub genres: Vec<String>,
+    /// RFC 3339, UTC.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub downloaded_at: Option<String>,
+    /// Semitones the stems were transposed by.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transpose: Option<i8>,
+    /// Whether the click track has the count-in.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub count_in: Option<bool>,
+    /// How much longer than the other stems the click measured, i.e. where the song starts
+    /// on the click; 0 without a count-in.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub count_in_secs: Option<f64>,
+    /// The mixer tracks of the song folder, in mixer order.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tracks: Vec<String>,
+    /// URLs of the same arrangement on other storefronts that weren't downloaded again.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub alternate_urls: Vec<String>,
+    /// The user's own labels, such as `setlist2025`, for `search` to filter on. Never
+    /// scraped, so kept when the page's credits are saved again.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+}
+
+impl SongInfo {
+    /// Scrapes the song's details from its page's HTML. Missing blocks leave their field
+    /// empty rather than failing, since not every storefront shows them all.
+    pub fn from_html(url: &str, html: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            title: title::from_page(html),
+            performer: performer(html),
+            composers: general_info(html, COMPOSER_LABELS),
+            arrangement_id: arrangement_id(html),
+            tempo: audio_detail(html, |line| line.contains("bpm")).map(|line| after_label(&line, false)),
+            key: audio_detail(html, |line| KEY_LABELS.iter().any(|l| line.contains(l))).map(|line| after_label(&line, true)),
+            duration: audio_detail(html, |line| DURATION_LABELS.iter().any(|l| line.starts_with(l))).map(|line| {
+                let duration = after_label(&line, false);
+                // Followed by the preview's position
+                duration.split(" - ").next().unwrap_or_default().to_string()
+            }),
+            genres: general_info(html, GENRE_LABELS),
+            ..Self::default()
+        }
+    }
+
+    /// Fills in what this lacks from `saved`, an earlier record of the same song, so a
+    /// resumed or repeated download doesn't lose it. The user's tags and the alternate
+    /// URLs of both are kept.
+    pub fn merge(self, saved: Self) -> Self {
+        fn either<T>(new: Vec<T>, saved: Vec<T>) -> Vec<T> {
+            if new.is_empty() {
+                saved
+            } else {
+                new
+            }
+        }
+        let mut alternate_urls = saved.alternate_urls;
+        for url in self.alternate_urls {
+            if !alternate_urls.contains(&url) {
+                alternate_urls.push(url);
+            }
+        }
+        Self {
+            url: if self.url.is_empty() { saved.url } else { self.url },
+            title: self.title.or(saved.title),
+            performer: self.performer.or(saved.performer),
+            composers: either(self.composers, saved.composers),
+            arrangement_id: self.arrangement_id.or(saved.arrangement_id),
+            tempo: self.tempo.or(saved.tempo),
+            key: self.key.or(saved.key),
+            duration: self.duration.or(saved.duration),
+            genres: either(self.genres, saved.genres),
+            downloaded_at: self.downloaded_at.or(saved.downloaded_at),
+            transpose: self.transpose.or(saved.transpose),
+            count_in: self.count_in.or(saved.count_in),
+            count_in_secs: self.count_in_secs.or(saved.count_in_secs),
+            tracks: either(self.tracks, saved.tracks),
+            alternate_urls,
+            tags: either(saved.tags, self.tags),
+        }
+    }
+
+    /// Reads [`SONG_INFO_FILE`], or [`LEGACY_SONG_INFO_FILE`] in folders from before it.
+    pub fn load(song_dir: &Path) -> Result<Option<Self>> {
+        let Some(path) = [SONG_INFO_FILE, LEGACY_SONG_INFO_FILE]
+            .iter()
+            .map(|name| song_dir.join(name))
+            .find(|path| path.exists())
+        else {
+            return Ok(None);
+        };
+        Ok(Some(serde_json::from_str(&fs::read_to_string(path)?)?))
+    }
+
+    /// Writes [`SONG_INFO_FILE`], replacing a [`LEGACY_SONG_INFO_FILE`].
+    pub fn save(&self, song_dir: &Path) -> Result<()> {
+        let mut data = serde_json::to_string_pretty(self)?;
+        data.push('\n');
+        fs::write(song_dir.join(SONG_INFO_FILE), data)?;
+        let legacy = song_dir.join(LEGACY_SONG_INFO_FILE);
+        if legacy.exists() {
+            fs::remove_file(legacy)?;
+        }
+        Ok(())
+    }
+}
+
+/// The folder directly inside `download_dir` whose song file records `url`, found without
+/// fetching the page.
+pub fn find_song_dir(download_dir: &Path, url: &str) -> Option<PathBuf> {
+    fs::read_dir(download_dir)
+        .ok()?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|path| path.is_dir())
+        .find(|path| matches!(SongInfo::load(path), Ok(Some(info)) if info.url == url))
+}
+
+/// The artist linked from the "as made famous by" line under the title, falling back to
+/// the page's `og:audio:artist` meta tag.
+fn performer(html: &str) -> Option<String> {
+    let from_description = element_body(html, "song-details__description").and_then(|description| {
+        let link = description.find("data-prodartistid")?;
+        let start = link + description[link..].find('>')? + 1;
+        let end = start + description[start..].find("</a>")?;
+        Some(text(&description[start..end]))
+    });
+    from_description
+        .or_else(|| meta_content(html, "og:audio:artist"))
+        .filter(|name| !name.is_empty())
+}
+
+/// The song ID on the page's heading, e.g. `68109` from `data-prodsongid="68109-1"`. The
+/// suffix after the dash names the product type, not the arrangement.
+pub fn arrangement_id(html: &str) -> Option<String> {
+    let heading = html.find("song-details__title")?;
+    let tag_end = heading + html[heading..].find('>')?;
+    let tag = &html[heading..tag_end];
+    let start = tag.find("data-prodsongid=\"")? + "data-prodsongid=\"".len();
+    let end = start + tag[start..].find('"')?;
+    let id = tag[start..end].split('-').next().unwrap_or_default().trim();
+    (!id.is_empty()).then(|| id.to_string())
+}
+
+/// `{title} - {artist}` from the page's `og:audio` meta tags, which every storefront fills
+/// in untranslated.
+pub fn audio_title(html: &str) -> Option<String> {
+    let title = meta_content(html, "og:audio:title").filter(|t| !t.is_empty())?;
+    title::folder_name(&match meta_content(html, "og:audio:artist").filter(|a| !a.is_empty()) {
+        Some(artist) => format!("{} - {}", title, artist),
+        None => title,
+    })
+}
+
+/// The first line of the audio details (tempo, key, duration, ...) under the song's picture
+/// that `is_wanted`, given lowercased.
+fn audio_detail(html: &str, is_wanted: impl Fn(&str) -> bool) -> Option<String> {
+    element_body(html, "song-details__audio-infos")?
+        .split("</p>")
+        .map(text)
+        .find(|line| is_wanted(&line.to_lowercase()))
+}
+
+/// The value after a line's `label:`, taken from the `last` colon for labels that hold one.
+fn after_label(line: &str, last: bool) -> String {
+    let colon = if last { line.rfind(':') } else { line.find(':') };
+    colon.map_or(line, |at| &line[at + 1..]).trim().to_string()
+}
+
+/// The values on the line of the "About" block whose label has one of `labels`, up to the
+/// line break: the text of each of its links, or else its text split at the commas the
+/// site lists names with. A name such as "Earth, Wind & Fire" is only kept whole as a link.
+fn general_info(html: &str, labels: &[&str]) -> Vec<String> {
+    let Some(block) = element_body(html, "song_general_infos") else {
+        return Vec::new();
+    };
+    let tokens = tokens(block);
+    let mut at = 0;
+    while let Some(label_start) = tokens[at..].iter().position(|token| token.opens("b")).map(|i| at + i) {
+        let Some(label_end) = tokens[label_start..].iter().position(|token| token.closes("b")).map(|i| label_start + i) else {
+            break;
+        };
+        let label = text_of(&tokens[label_start + 1..label_end]).to_lowercase();
+        let value_end = tokens[label_end + 1..]
+            .iter()
+            .position(|token| token.opens("br") || token.opens("b") || token.closes("p"))
+            .map_or(tokens.len(), |i| label_end + 1 + i);
+        if labels.iter().any(|l| label.contains(l)) {
+            return list(&tokens[label_end + 1..value_end]);
+        }
+        at = value_end;
+    }
+    Vec::new()
+}
+
+/// The text of each link in `tokens`, or, without links, their text split at its commas.
+fn list(tokens: &[Token]) -> Vec<String> {
+    let items: Vec<String> = if tokens.iter().any(|token| token.opens("a")) {
+        let mut items = Vec::new();
+        let mut rest = tokens;
+        while let Some(start) = rest.iter().position(|token| token.opens("a")) {
+            let end = rest[start..].iter().position(|token| token.closes("a")).map_or(rest.len(), |i| start + i);
+            items.push(text_of(&rest[start + 1..end]));
+            rest = &rest[end..];
+            if !rest.is_empty() {
+                rest = &rest[1..];
+            }
+        }
+        items
+    } else {
+        text_of(tokens).split(',').map(|item| item.trim().to_string()).collect()
+    };
+    items.into_iter().filter(|item| !item.is_empty()).collect()
+}
+
+/// The HTML after the opening tag of the first element with `marker` in its attributes,
+/// up to the end of the closing `</div>` nearest to it.
+fn element_body<'a>(html: &'a str, marker: &str) -> Option<&'a str> {
+    let at = html.find(marker)?;
+    let start = at + html[at..].find('>')? + 1;
+    let end = start + html[start..].find("</div>")?;
+    Some(&html[start..end])
+}
+
+fn meta_content(html: &str, property: &str) -> Option<String> {
+    let at = html.find(&format!("property=\"{}\"", property))?;
+    let tag_end = at + html[at..].find('>')?;
+    let tag = &html[at..tag_end];
+    let start = tag.find("content=\"")? + "content=\"".len();
+    let end = start + tag[start..].find('"')?;
+    Some(text(&tag[start..end]))
+}
+
+/// A piece of markup: an opening or closing tag, by its lowercased name, or the text
+/// between tags.
+#[derive(Debug)]
+enum Token<'a> {
+    Open(String),
+    Close(String),
+    Text(&'a str),
+}
+
+impl Token<'_> {
+    fn opens(&self, tag: &str) -> bool {
+        matches!(self, Token::Open(name) if name == tag)
+    }
+
+    fn closes(&self, tag: &str) -> bool {
+        matches!(self, Token::Close(name) if name == tag)
+    }
+}
+
+/// Splits `html` into tags and text the way a browser reads it: a `>` in a quoted
+/// attribute doesn't end its tag, comments and doctypes are dropped, and a `<` that starts
+/// no tag is text.
+fn tokens(html: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut text_start = 0;
+    let mut at = 0;
+    while let Some(offset) = html[at..].find('<') {
+        let start = at + offset;
+        let rest = &html[start + 1..];
+        let end = if rest.starts_with("!--") {
+            rest.find("-->").map(|end| start + 1 + end + 3)
+        } else if rest.starts_with(|c: char| c.is_ascii_alphabetic() || c == '/' || c == '!') {
+            tag_end(rest).map(|end| start + 1 + end + 1)
+        } else {
+            None
+        };
+        let Some(end) = end else {
+            at = start + 1;
+            continue;
+        };
+        if text_start < start {
+            tokens.push(Token::Text(&html[text_start..start]));
+        }
+        let tag = &html[start + 1..end - 1];
+        if let Some(name) = tag.strip_prefix('/') {
+            tokens.push(Token::Close(tag_name(name)));
+        } else if !tag.starts_with('!') {
+            tokens.push(Token::Open(tag_name(tag)));
+        }
+        text_start = end;
+        at = end;
+    }
+    if text_start < html.len() {
+        tokens.push(Token::Text(&html[text_start..]));
+    }
+    tokens
+}
+
+/// Where the `>` ending the tag that `rest` starts in is, past its quoted attribute values.
+fn tag_end(rest: &str) -> Option<usize> {
+    let mut quote = None;
+    for (i, c) in rest.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(open), c) if c == open => quote = None,
+            (None, '>') => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+fn tag_name(tag: &str) -> String {
+    tag.split(|c: char| c.is_whitespace() || c == '/')
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase()
+}
+
+/// Strips tags, decodes the entities the site uses and collapses whitespace.
+fn text(html: &str) -> String {
+    text_of(&tokens(html))
+}
+
+fn text_of(tokens: &[Token]) -> String {
+    let plain: String = tokens
+        .iter()
+        .filter_map(|token| match token {
+            Token::Text(text) => Some(*text),
+            _ => None,
+        })
+        .collect();
+    let decoded = plain
+        .replace("&#039;", "'")
+        .replace("&#39;", "'")
+        .replace("&quot;", "\"")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&");
+    decoded.split_whitespace().collect::<Vec<_>>().join(" ")
+}
@@ -0,0 +1,88 @@
+use anyhow::Result;
+use headless_chrome::util::Timeout;
+use std::thread::sleep;
+use std::time::Duration;
+
+/// Times a page load is tried again after a transient failure.
+pub const NAVIGATION_RETRIES: u32 = 3;
+/// Wait before the first retry of a page load; it doubles with every further attempt.
+pub const NAVIGATION_BACKOFF: Duration = Duration::from_secs(1);
+/// Longest wait between two attempts at a page load.
+const NAVIGATION_BACKOFF_CAP: Duration = Duration::from_secs(15);
+
+/// Chrome's network errors that a flaky connection causes and a reload a moment later
+/// gets past.
+const TRANSIENT_NET_ERRORS: &[&str] = &[
+    "ERR_NETWORK_CHANGED",
+    "ERR_TIMED_OUT",
+    "ERR_CONNECTION_TIMED_OUT",
+    "ERR_CONNECTION_RESET",
+    "ERR_CONNECTION_CLOSED",
+    "ERR_CONNECTION_REFUSED",
+    "ERR_INTERNET_DISCONNECTED",
+    "ERR_NAME_NOT_RESOLVED",
+    "ERR_ADDRESS_UNREACHABLE",
+    "ERR_NETWORK_IO_SUSPENDED",
+    "ERR_EMPTY_RESPONSE",
+];
+
+/// Whether loading a page again may get past `error`: one of Chrome's network errors in
+/// [`TRANSIENT_NET_ERRORS`], or a page or element that never came. Anything else, from a
+/// closed tab to an abort, is permanent.
+pub fn is_transient(error: &anyhow::Error) -> bool {
+    if error.downcast_ref::<Timeout>().is_some() {
+        return true;
+    }
+    error.chain().any(|cause| {
+        let text = cause.to_string();
+        TRANSIENT_NET_ERRORS.iter().any(|code| text.contains(code))
+    })
+}
+
+/// How many times, and how far apart, a page load that fails transiently is tried again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Retries {
+    pub retries: u32,
+    /// Wait before the first retry; it doubles with every further one.
+    pub backoff: Duration,
+}
+
+impl Default for Retries {
+    fn default() -> Self {
+        Self { retries: NAVIGATION_RETRIES, backoff: NAVIGATION_BACKOFF }
+    }
+}
+
+impl Retries {
+    /// The wait before attempt `attempt + 1`, after `attempt` failed ones.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        self.backoff.saturating_mul(factor).min(NAVIGATION_BACKOFF_CAP)
+    }
+
+    /// Runs `attempt` until it succeeds, fails with an error [`is_transient`] doesn't
+    /// count, or has been retried `retries` times; `what` names it in the log.
+    pub fn run<T>(&self, what: &str, mut attempt: impl FnMut() -> Result<T>) -> Result<T> {
+        let mut failed = 0;
+        loop {
+            match attempt() {
+                Ok(value) => return Ok(value),
+                Err(e) if failed < self.retries && is_transient(&e) => {
+                    failed += 1;
+                    let delay = self.delay(failed);
+                    tracing::warn!(
+                        "Loading {} failed ({}); reloading in {:.1}s (attempt {} of {})",
+                        what,
+                        e,
+                        delay.as_secs_f32(),
+                        failed + 1,
+                        self.retries + 1
+                    );
+                    sleep(delay);
+                }
+                Err(e) if failed > 0 => return Err(e.context(format!("Loading {} failed {} times", what, failed + 1))),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
@@ -0,0 +1,176 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Deserializer};
+use std::fs;
+use std::path::Path;
+
+/// The `[output]` table: the modes and group given to every song folder, project and bundle
+/// written, so bandmates sharing the folder can edit them. Whatever is left unset keeps
+/// what the umask gave. Only Unix has them; elsewhere they're ignored.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct OutputPermissions {
+    /// Octal, like `"664"`.
+    #[serde(default, deserialize_with = "octal_mode")]
+    pub file_mode: Option<u32>,
+    /// Octal, like `"2775"` to have new files inherit the folder's group.
+    #[serde(default, deserialize_with = "octal_mode")]
+    pub dir_mode: Option<u32>,
+    /// Group name or numeric id.
+    pub group: Option<String>,
+}
+
+/// Parses an octal mode such as `"664"`, `"0664"` or `"0o664"`.
+pub fn parse_mode(mode: &str) -> Result<u32, String> {
+    let digits = mode.trim().trim_start_matches("0o");
+    match u32::from_str_radix(digits, 8) {
+        Ok(bits) if bits <= 0o7777 => Ok(bits),
+        _ => Err(format!("'{}' isn't an octal mode like \"664\"", mode)),
+    }
+}
+
+fn octal_mode<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u32>, D::Error> {
+    match Option::<String>::deserialize(deserializer)? {
+        Some(mode) => parse_mode(&mode).map(Some).map_err(serde::de::Error::custom),
+        None => Ok(None),
+    }
+}
+
+impl OutputPermissions {
+    /// Checks that the group exists, so a typo fails the run before anything is written.
+    pub fn validate(&self) -> Result<()> {
+        self.gid().map(|_| ())
+    }
+
+    pub fn is_set(&self) -> bool {
+        self.file_mode.is_some() || self.dir_mode.is_some() || self.group.is_some()
+    }
+
+    #[cfg(unix)]
+    fn gid(&self) -> Result<Option<u32>> {
+        let Some(group) = &self.group else {
+            return Ok(None);
+        };
+        if let Ok(gid) = group.parse() {
+            return Ok(Some(gid));
+        }
+        let name = std::ffi::CString::new(group.as_str()).map_err(|_| anyhow!("Invalid output.group {:?}", group))?;
+        // getgrnam_r rather than getgrnam, whose entry another thread's lookup may overwrite
+        let mut buffer: Vec<libc::c_char> = vec![0; 1024];
+        loop {
+            let mut entry: libc::group = unsafe { std::mem::zeroed() };
+            let mut found: *mut libc::group = std::ptr::null_mut();
+            // SAFETY: every pointer is to a live local, and `buffer.len()` is its real size
+            let status = unsafe {
+                libc::getgrnam_r(name.as_ptr(), &mut entry, buffer.as_mut_ptr(), buffer.len(), &mut found)
+            };
+            match status {
+                0 if found.is_null() => return Err(anyhow!("output.group {:?} isn't a group on this system", group)),
+                0 => return Ok(Some(entry.gr_gid)),
+                // A group with many members needs more room
+                libc::ERANGE if buffer.len() < 1 << 20 => buffer.resize(buffer.len() * 4, 0),
+                errno => {
+                    return Err(anyhow!(
+                        "Unable to look up output.group {:?}: {}",
+                        group,
+                        std::io::Error::from_raw_os_error(errno)
+                    ))
+                }
+            }
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn gid(&self) -> Result<Option<u32>> {
+        Ok(None)
+    }
+
+    /// Applies the settings to `root` and everything under it. Failures, such as files
+    /// owned by someone else, don't stop the run: they're summed up in the warning returned.
+    pub fn apply_tree(&self, root: &Path) -> Option<String> {
+        if !self.is_set() {
+            return None;
+        }
+        let mut failures = Vec::new();
+        let gid = self.resolve_gid(&mut failures);
+        self.walk(root, gid, &mut failures);
+        Self::summarize(root, failures)
+    }
+
+    /// Applies the settings to each of `paths`, not what's inside them.
+    pub fn apply_each<'p>(&self, paths: impl IntoIterator<Item = &'p Path>) -> Option<String> {
+        if !self.is_set() {
+            return None;
+        }
+        let mut failures = Vec::new();
+        let gid = self.resolve_gid(&mut failures);
+        let mut first = None;
+        for path in paths {
+            first.get_or_insert(path);
+            if let Err(e) = self.apply(path, gid) {
+                failures.push(e);
+            }
+        }
+        Self::summarize(first?, failures)
+    }
+
+    /// The group's id, looked up once for a whole tree; a group that went away since the
+    /// config was validated is a failure like any other.
+    fn resolve_gid(&self, failures: &mut Vec<anyhow::Error>) -> Option<u32> {
+        self.gid().unwrap_or_else(|e| {
+            failures.push(e);
+            None
+        })
+    }
+
+    fn walk(&self, path: &Path, gid: Option<u32>, failures: &mut Vec<anyhow::Error>) {
+        if let Err(e) = self.apply(path, gid) {
+            failures.push(e);
+        }
+        if !path.is_dir() || path.is_symlink() {
+            return;
+        }
+        match fs::read_dir(path) {
+            Ok(entries) => {
+                for entry in entries.filter_map(|e| e.ok()) {
+                    self.walk(&entry.path(), gid, failures);
+                }
+            }
+            Err(e) => failures.push(anyhow!("{:?}: {}", path, e)),
+        }
+    }
+
+    #[cfg(unix)]
+    fn apply(&self, path: &Path, gid: Option<u32>) -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let metadata = fs::symlink_metadata(path).map_err(|e| anyhow!("{:?}: {}", path, e))?;
+        // A link's target may be anywhere, and isn't ours to change
+        if metadata.file_type().is_symlink() {
+            return Ok(());
+        }
+        if gid.is_some() {
+            std::os::unix::fs::chown(path, None, gid).map_err(|e| anyhow!("chown {:?}: {}", path, e))?;
+        }
+        let mode = if metadata.is_dir() { self.dir_mode } else { self.file_mode };
+        if let Some(mode) = mode {
+            fs::set_permissions(path, fs::Permissions::from_mode(mode)).map_err(|e| anyhow!("chmod {:?}: {}", path, e))?;
+        }
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    fn apply(&self, _path: &Path, _gid: Option<u32>) -> Result<()> {
+        Ok(())
+    }
+
+    fn summarize(root: &Path, failures: Vec<anyhow::Error>) -> Option<String> {
+        let first = failures.first()?;
+        let warning = format!(
+            "Unable to set the output permissions of {} paths in {:?}, e.g. {}",
+            failures.len(),
+            root,
+            first
+        );
+        tracing::warn!("{}", warning);
+        Some(warning)
+    }
+}
@@ -0,0 +1,100 @@
+use anyhow::Result;
+use std::sync::Arc;
+
+use crate::keystore::SecretStore;
+
+/// Keystore entry listing the named profiles, which a keychain can't enumerate.
+const PROFILES_KEY: &str = "KV_PROFILES";
+
+/// A `--profile` name: letters, digits, `-` and `_`, as it becomes part of keystore entries
+/// and file names.
+pub fn parse_profile(name: &str) -> Result<String, String> {
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        return Err(format!("{:?} isn't a profile name; use letters, digits, '-' and '_'", name));
+    }
+    Ok(name.to_string())
+}
+
+/// The name `file` of the download directory has for `profile`: `track_list.json` is
+/// `track_list.bandA.json`. The default profile keeps the name files had before profiles.
+pub fn file_name(file: &str, profile: Option<&str>) -> String {
+    let Some(profile) = profile else {
+        return file.to_string();
+    };
+    match file.rsplit_once('.') {
+        Some((stem, extension)) => format!("{}.{}.{}", stem, profile, extension),
+        None => format!("{}.{}", file, profile),
+    }
+}
+
+/// The secrets of one named profile, kept in `store` under names of their own, so each
+/// profile signs in with its own credentials and keeps its own session cookies.
+pub struct ProfileStore {
+    store: Arc<dyn SecretStore>,
+    profile: String,
+}
+
+impl ProfileStore {
+    pub fn new(store: Arc<dyn SecretStore>, profile: &str) -> Self {
+        Self {
+            store,
+            profile: profile.to_string(),
+        }
+    }
+
+    fn name(&self, name: &str) -> String {
+        format!("{}#{}", name, self.profile)
+    }
+}
+
+impl SecretStore for ProfileStore {
+    fn get_secret(&self, name: &str) -> Result<Option<String>> {
+        self.store.get_secret(&self.name(name))
+    }
+
+    fn set_secret(&self, name: &str, secret: &str) -> Result<()> {
+        self.store.set_secret(&self.name(name), secret)
+    }
+
+    fn delete_secret(&self, name: &str) -> Result<()> {
+        self.store.delete_secret(&self.name(name))
+    }
+}
+
+/// The secrets of `profile` in `store`: the store itself for the default profile, whose
+/// entries are those stored before there were profiles.
+pub fn secrets(store: Arc<dyn SecretStore>, profile: Option<&str>) -> Arc<dyn SecretStore> {
+    match profile {
+        Some(profile) => Arc::new(ProfileStore::new(store, profile)),
+        None => store,
+    }
+}
+
+/// The named profiles `auth --profile` stored credentials for, sorted.
+pub fn list(store: &dyn SecretStore) -> Result<Vec<String>> {
+    match store.get_secret(PROFILES_KEY)? {
+        Some(secret) => Ok(serde_json::from_str(&secret)?),
+        None => Ok(vec![]),
+    }
+}
+
+/// Adds `profile` to the [`list`].
+pub fn register(store: &dyn SecretStore, profile: &str) -> Result<()> {
+    let mut profiles = list(store)?;
+    if !profiles.iter().any(|known| known == profile) {
+        profiles.push(profile.to_string());
+        profiles.sort();
+        store.set_secret(PROFILES_KEY, &serde_json::to_string(&profiles)?)?;
+    }
+    Ok(())
+}
+
+/// Takes `profile` off the [`list`].
+pub fn unregister(store: &dyn SecretStore, profile: &str) -> Result<()> {
+    let profiles = list(store)?;
+    if profiles.iter().any(|known| known == profile) {
+        let profiles: Vec<&String> = profiles.iter().filter(|known| *known != profile).collect();
+        store.set_secret(PROFILES_KEY, &serde_json::to_string(&profiles)?)?;
+    }
+    Ok(())
+}
@@ -1,7 +1,13 @@
 use anyhow::Result;
 use std::io::{stdin, stdout, Write};
+use std::sync::Mutex;
+
+/// Held while a prompt waits for its answer, so the workers of a `--concurrency` batch
+/// ask one at a time and each answer goes to the prompt it was typed for.
+static PROMPT: Mutex<()> = Mutex::new(());
 
 pub fn prompt(msg: &str, secure: bool) -> Result<String> {
+    let _asking = PROMPT.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
     print!("{}", msg);
     stdout().flush()?;
 
@@ -0,0 +1,217 @@
+use anyhow::Result;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime};
+
+/// Name of the directory (under the download path) that holds debug artifacts.
+pub const DEBUG_DIR: &str = "debug";
+
+pub const DEFAULT_MAX_TOTAL_BYTES: u64 = 500 * 1024 * 1024;
+pub const DEFAULT_MAX_AGE: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+/// Page captures kept unless `--debug-captures` says otherwise.
+pub const DEFAULT_MAX_CAPTURES: usize = 20;
+/// Records the page's address, and marks a directory of the debug directory as a capture.
+pub const CAPTURE_MARKER: &str = "url.txt";
+/// Names the artifacts the latest download wrote, one per line, for `report-issue`. It's
+/// never pruned itself.
+pub const LAST_RUN_FILE: &str = "last-run.txt";
+
+static SESSION_START: OnceLock<SystemTime> = OnceLock::new();
+
+#[derive(Debug, Clone)]
+pub struct RetentionPolicy {
+    pub max_total_bytes: u64,
+    pub max_age: Duration,
+    /// Page captures kept; the oldest beyond it go, like those over the size cap.
+    pub max_captures: usize,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        Self {
+            max_total_bytes: DEFAULT_MAX_TOTAL_BYTES,
+            max_age: DEFAULT_MAX_AGE,
+            max_captures: DEFAULT_MAX_CAPTURES,
+        }
+    }
+}
+
+/// When the running session began: the first time it was asked for, which the download
+/// command does before it writes anything.
+pub fn session_start() -> SystemTime {
+    *SESSION_START.get_or_init(SystemTime::now)
+}
+
+/// The debug directory of a run and the policy it's pruned by, after each artifact it writes.
+#[derive(Debug, Clone)]
+pub struct DebugRetention {
+    pub dir: PathBuf,
+    pub policy: RetentionPolicy,
+    /// Nothing modified since is pruned; see [`session_start`].
+    pub session_start: SystemTime,
+}
+
+impl DebugRetention {
+    /// Empties the record of the last run's artifacts, for a new run.
+    pub fn start_run(&self) {
+        let result = fs::create_dir_all(&self.dir).and_then(|()| fs::write(self.dir.join(LAST_RUN_FILE), ""));
+        if let Err(e) = result {
+            tracing::warn!("Unable to start the record of this run's debug artifacts: {}", e);
+        }
+    }
+
+    /// Adds `artifact`, an entry of the debug directory, to the record of the run's artifacts.
+    pub fn record(&self, artifact: &Path) {
+        let Some(name) = artifact.file_name() else {
+            return;
+        };
+        let result = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.dir.join(LAST_RUN_FILE))
+            .and_then(|mut file| writeln!(file, "{}", name.to_string_lossy()));
+        if let Err(e) = result {
+            tracing::warn!("Unable to record the debug artifact {:?}: {}", artifact, e);
+        }
+    }
+
+    /// Prunes the directory, warning instead of failing: a download doesn't fail over its
+    /// debug artifacts.
+    pub fn prune(&self) {
+        if let Err(e) = prune(&self.dir, &self.policy, self.session_start) {
+            tracing::warn!("Failed to prune debug artifacts: {}", e);
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct PruneReport {
+    pub removed: Vec<PathBuf>,
+    pub freed_bytes: u64,
+}
+
+struct Entry {
+    path: PathBuf,
+    size: u64,
+    modified: SystemTime,
+    is_capture: bool,
+}
+
+/// Prunes the top-level entries (files or artifact directories) of `dir`, oldest first.
+///
+/// Entries older than `policy.max_age` are always removed; after that, the oldest remaining
+/// entries are removed until the directory fits in `policy.max_total_bytes` and holds no more
+/// than `policy.max_captures` page captures. Anything modified at or after `session_start`
+/// belongs to the running session and is never touched, even if that means the size cap
+/// can't be met; neither is the [`LAST_RUN_FILE`].
+pub fn prune(dir: &Path, policy: &RetentionPolicy, session_start: SystemTime) -> Result<PruneReport> {
+    let mut report = PruneReport::default();
+    if !dir.is_dir() {
+        return Ok(report);
+    }
+
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.file_name().is_some_and(|name| name == LAST_RUN_FILE) {
+            continue;
+        }
+        let modified = fs::metadata(&path)?.modified()?;
+        let size = dir_size(&path)?;
+        let is_capture = path.join(CAPTURE_MARKER).is_file();
+        entries.push(Entry {
+            path,
+            size,
+            modified,
+            is_capture,
+        });
+    }
+    entries.sort_by_key(|e| e.modified);
+
+    let now = SystemTime::now();
+    let mut total: u64 = entries.iter().map(|e| e.size).sum();
+    let mut captures = entries.iter().filter(|e| e.is_capture).count();
+
+    for entry in entries {
+        if entry.modified >= session_start {
+            continue;
+        }
+        let age = now.duration_since(entry.modified).unwrap_or_default();
+        let reason = if age > policy.max_age {
+            "expired"
+        } else if total > policy.max_total_bytes {
+            "over size cap"
+        } else if entry.is_capture && captures > policy.max_captures {
+            "over capture cap"
+        } else {
+            continue;
+        };
+
+        remove_entry(&entry.path)?;
+        tracing::info!("Pruned debug artifact {:?} ({} bytes, {})", entry.path, entry.size, reason);
+        total = total.saturating_sub(entry.size);
+        if entry.is_capture {
+            captures -= 1;
+        }
+        report.freed_bytes += entry.size;
+        report.removed.push(entry.path);
+    }
+
+    if total > policy.max_total_bytes {
+        tracing::warn!(
+            "{:?} is still {} bytes after pruning (cap {}); the rest belongs to the current session",
+            dir,
+            total,
+            policy.max_total_bytes
+        );
+    }
+
+    Ok(report)
+}
+
+/// The artifacts the [`LAST_RUN_FILE`] of `dir` names, whether or not they're still there;
+/// none if no run recorded any.
+pub fn last_run_artifacts(dir: &Path) -> Result<Vec<PathBuf>> {
+    let record = match fs::read_to_string(dir.join(LAST_RUN_FILE)) {
+        Ok(record) => record,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e.into()),
+    };
+    Ok(record.lines().filter(|line| !line.is_empty()).map(|name| dir.join(name)).collect())
+}
+
+/// The last part of `song_url`, such as `cherub-rock`, fit for the name of a debug artifact.
+pub(crate) fn artifact_slug(song_url: &str) -> String {
+    song_url
+        .trim_end_matches('/')
+        .rsplit('/')
+        .next()
+        .unwrap_or_default()
+        .trim_end_matches(".html")
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' })
+        .collect()
+}
+
+pub(crate) fn dir_size(path: &Path) -> Result<u64> {
+    let meta = fs::symlink_metadata(path)?;
+    if !meta.is_dir() {
+        return Ok(meta.len());
+    }
+    let mut size = 0;
+    for entry in fs::read_dir(path)? {
+        size += dir_size(&entry?.path())?;
+    }
+    Ok(size)
+}
+
+fn remove_entry(path: &Path) -> Result<()> {
+    if fs::symlink_metadata(path)?.is_dir() {
+        fs::remove_dir_all(path)?;
+    } else {
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
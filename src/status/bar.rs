@@ -0,0 +1,106 @@
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+use std::collections::BTreeMap;
+use std::io::{self, IsTerminal, Write};
+use std::sync::Mutex;
+use std::time::Duration;
+use tracing_subscriber::fmt::MakeWriter;
+
+use super::{ProgressEvent, StatusHandle};
+use crate::audio::title;
+
+/// The bar being drawn, which log lines clear out of the way of.
+static BAR: Mutex<Option<ProgressBar>> = Mutex::new(None);
+
+/// Draws the batch as a progress bar on stderr, with the songs in flight, their tracks and
+/// the ETA. Does nothing unless stderr is a terminal, so redirected logs stay plain.
+pub fn attach_bar(status: &StatusHandle) {
+    if !io::stderr().is_terminal() {
+        return;
+    }
+    let bar = ProgressBar::with_draw_target(Some(status.snapshot().total as u64), ProgressDrawTarget::stderr());
+    bar.set_style(
+        ProgressStyle::with_template("{bar:30} {pos}/{len} songs {prefix} {wide_msg}")
+            .unwrap_or_else(|_| ProgressStyle::default_bar()),
+    );
+    bar.enable_steady_tick(Duration::from_millis(500));
+    *BAR.lock().unwrap() = Some(bar.clone());
+
+    // The title of each song in flight, by its 1-based index, and the track it's on
+    let songs: Mutex<BTreeMap<usize, (String, Option<String>)>> = Mutex::new(BTreeMap::new());
+    status.subscribe(move |event| {
+        let mut songs = songs.lock().unwrap();
+        match event {
+            ProgressEvent::SongStarted { index, total, url } => {
+                bar.set_length(*total as u64);
+                songs.insert(*index, (title::from_url(url).unwrap_or_else(|| url.clone()), None));
+            }
+            ProgressEvent::TrackStarted { song, index, total, name } => {
+                if let Some((_, track)) = songs.get_mut(song) {
+                    *track = Some(format!("track {} of {}, {}", index, total, name));
+                }
+            }
+            ProgressEvent::SongSkipped { .. } => bar.inc(1),
+            ProgressEvent::SongFinished { index, eta_seconds, .. } => {
+                songs.remove(index);
+                bar.inc(1);
+                if let Some(eta) = eta_seconds {
+                    bar.set_prefix(format!("(about {} left)", format_eta(*eta)));
+                }
+            }
+            ProgressEvent::SongFailed { index, .. } => {
+                songs.remove(index);
+                bar.inc(1);
+            }
+            ProgressEvent::BatchFinished { .. } => {
+                bar.finish_and_clear();
+                BAR.lock().unwrap().take();
+            }
+        }
+        let message: Vec<String> = songs
+            .values()
+            .map(|(title, track)| match track {
+                Some(track) => format!("{}: {}", title, track),
+                None => title.clone(),
+            })
+            .collect();
+        bar.set_message(message.join(" | "));
+    });
+}
+
+/// Where the logs go: stderr, with the progress bar, if there's one, cleared while a line is
+/// written and drawn again below it.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LogWriter;
+
+impl Write for LogWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let bar = BAR.lock().unwrap().clone();
+        match bar {
+            Some(bar) => bar.suspend(|| io::stderr().write_all(buf))?,
+            None => io::stderr().write_all(buf)?,
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stderr().flush()
+    }
+}
+
+impl<'a> MakeWriter<'a> for LogWriter {
+    type Writer = LogWriter;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        LogWriter
+    }
+}
+
+/// `eta_seconds` as "1h 05m", "12m 30s" or "45s".
+pub fn format_eta(eta_seconds: f64) -> String {
+    let seconds = eta_seconds.round() as u64;
+    match (seconds / 3600, seconds / 60 % 60, seconds % 60) {
+        (0, 0, s) => format!("{}s", s),
+        (0, m, s) => format!("{}m {:02}s", m, s),
+        (h, m, _) => format!("{}h {:02}m", h, m),
+    }
+}
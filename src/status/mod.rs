@@ -0,0 +1,331 @@
+mod bar;
+#[cfg(feature = "net")]
+mod server;
+pub use bar::{attach_bar, format_eta, LogWriter};
+#[cfg(feature = "net")]
+pub use server::StatusServer;
+
+use serde::Serialize;
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+const RECENT_FAILURES: usize = 10;
+/// Songs the ETA averages over, so it follows a batch that speeds up or slows down.
+pub const ROLLING_SONGS: usize = 5;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PhaseTiming {
+    pub phase: String,
+    pub seconds: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Failure {
+    pub url: String,
+    pub error: String,
+    /// The song was aborted and its downloads cancelled, rather than failing.
+    pub cancelled: bool,
+}
+
+/// Snapshot of a batch run, serialized as-is by the status endpoint.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct BatchStatus {
+    /// The song started last of those in flight.
+    pub current_song: Option<String>,
+    /// 1-based position of the current song in the batch.
+    pub index: usize,
+    pub total: usize,
+    pub completed: usize,
+    pub failed: usize,
+    pub cancelled: usize,
+    /// Phase timings of the current song so far.
+    pub phases: Vec<PhaseTiming>,
+    pub recent_failures: VecDeque<Failure>,
+    /// Songs skipped because their folder already exists.
+    pub skipped: usize,
+    /// The track of the current song being downloaded, 1-based, and how many it downloads.
+    pub track: Option<(usize, usize)>,
+    /// Every song started and not done yet, in batch order; more than one with `--concurrency`.
+    pub in_flight: Vec<SongInFlight>,
+    pub eta_seconds: Option<f64>,
+    pub finished: bool,
+}
+
+/// A song of the batch being worked on.
+#[derive(Debug, Clone, Serialize)]
+pub struct SongInFlight {
+    /// 1-based position of the song in the batch.
+    pub index: usize,
+    pub url: String,
+    pub phases: Vec<PhaseTiming>,
+    /// The track being downloaded, 1-based, and how many the song downloads.
+    pub track: Option<(usize, usize)>,
+}
+
+/// What a batch reports as it goes, to whoever [subscribed](StatusHandle::subscribe).
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum ProgressEvent {
+    /// `index` is 1-based.
+    SongStarted { index: usize, total: usize, url: String },
+    /// The `index`-th of the `total` tracks the `song`-th song downloads, 1-based.
+    TrackStarted { song: usize, index: usize, total: usize, name: String },
+    SongSkipped { index: usize, url: String },
+    SongFinished { index: usize, seconds: f64, eta_seconds: Option<f64> },
+    SongFailed { index: usize, url: String, error: String, cancelled: bool },
+    BatchFinished { completed: usize, failed: usize, cancelled: usize, skipped: usize },
+}
+
+type Listener = Arc<dyn Fn(&ProgressEvent) + Send + Sync>;
+
+/// A song between [`StatusHandle::start_song`] and its finish, failure or cancellation.
+struct InFlight {
+    status: SongInFlight,
+    started: Instant,
+    phase_started: Instant,
+}
+
+struct Tracker {
+    status: BatchStatus,
+    /// Keyed by the 0-based index of the song, so concurrent workers keep apart.
+    in_flight: BTreeMap<usize, InFlight>,
+    /// The last [`ROLLING_SONGS`] songs finished.
+    song_durations: VecDeque<Duration>,
+    listeners: Vec<Listener>,
+}
+
+/// Cheaply cloneable handle the batch loop uses to publish its progress.
+#[derive(Clone)]
+pub struct StatusHandle {
+    inner: Arc<Mutex<Tracker>>,
+}
+
+impl Default for StatusHandle {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+impl StatusHandle {
+    pub fn new(total: usize) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(Tracker {
+                status: BatchStatus {
+                    total,
+                    ..Default::default()
+                },
+                in_flight: BTreeMap::new(),
+                song_durations: VecDeque::new(),
+                listeners: vec![],
+            })),
+        }
+    }
+
+    /// Calls `listener` with every event from now on, on the thread that caused it.
+    pub fn subscribe(&self, listener: impl Fn(&ProgressEvent) + Send + Sync + 'static) {
+        self.inner.lock().unwrap().listeners.push(Arc::new(listener));
+    }
+
+    /// Hands `event` to the listeners once the tracker is unlocked, so they may look at it.
+    fn emit(&self, tracker: std::sync::MutexGuard<'_, Tracker>, event: ProgressEvent) {
+        let listeners = tracker.listeners.clone();
+        drop(tracker);
+        for listener in listeners {
+            listener(&event);
+        }
+    }
+
+    pub fn snapshot(&self) -> BatchStatus {
+        let tracker = self.inner.lock().unwrap();
+        let mut status = tracker.status.clone();
+        status.in_flight = tracker.in_flight.values().map(|song| song.status.clone()).collect();
+        if let Some(latest) = tracker.in_flight.values().max_by_key(|song| song.started) {
+            status.current_song = Some(latest.status.url.clone());
+            status.index = latest.status.index;
+            status.phases = latest.status.phases.clone();
+            status.track = latest.status.track;
+        }
+        status
+    }
+
+    pub fn set_total(&self, total: usize) {
+        self.inner.lock().unwrap().status.total = total;
+    }
+
+    /// The view of the `index`-th song, 0-based, that downloading it reports through.
+    pub fn song(&self, index: usize) -> SongProgress {
+        SongProgress {
+            status: self.clone(),
+            index,
+        }
+    }
+
+    /// Starts timing the `index`-th song, 0-based, alongside any other in flight.
+    pub fn start_song(&self, index: usize, url: &str) {
+        let mut tracker = self.inner.lock().unwrap();
+        let now = Instant::now();
+        tracker.in_flight.insert(
+            index,
+            InFlight {
+                status: SongInFlight {
+                    index: index + 1,
+                    url: url.to_string(),
+                    phases: vec![],
+                    track: None,
+                },
+                started: now,
+                phase_started: now,
+            },
+        );
+        let event = ProgressEvent::SongStarted {
+            index: index + 1,
+            total: tracker.status.total,
+            url: url.to_string(),
+        };
+        self.emit(tracker, event);
+    }
+
+    /// The `song`-th song started downloading its `index`-th track of `total`, all 0-based.
+    pub fn start_track(&self, song: usize, index: usize, total: usize, name: &str) {
+        let mut tracker = self.inner.lock().unwrap();
+        if let Some(in_flight) = tracker.in_flight.get_mut(&song) {
+            in_flight.status.track = Some((index + 1, total));
+        }
+        let event = ProgressEvent::TrackStarted {
+            song: song + 1,
+            index: index + 1,
+            total,
+            name: name.to_string(),
+        };
+        self.emit(tracker, event);
+    }
+
+    /// Records that the `index`-th song, 0-based, was already there. It doesn't count
+    /// towards the ETA.
+    pub fn skip_song(&self, index: usize, url: &str) {
+        let mut tracker = self.inner.lock().unwrap();
+        tracker.status.skipped += 1;
+        let event = ProgressEvent::SongSkipped {
+            index: index + 1,
+            url: url.to_string(),
+        };
+        self.emit(tracker, event);
+    }
+
+    /// Records the time the `index`-th song spent since its previous phase (or its start)
+    /// under `phase`.
+    pub fn finish_phase(&self, index: usize, phase: &str) {
+        let mut tracker = self.inner.lock().unwrap();
+        if let Some(in_flight) = tracker.in_flight.get_mut(&index) {
+            let now = Instant::now();
+            let started = std::mem::replace(&mut in_flight.phase_started, now);
+            in_flight.status.phases.push(PhaseTiming {
+                phase: phase.to_string(),
+                seconds: now.duration_since(started).as_secs_f64(),
+            });
+        }
+    }
+
+    /// Marks the `index`-th song as done, feeding its duration into the ETA.
+    pub fn finish_song(&self, index: usize) {
+        let mut tracker = self.inner.lock().unwrap();
+        let elapsed = tracker.in_flight.remove(&index).map(|song| song.started.elapsed());
+        if let Some(elapsed) = elapsed {
+            tracker.song_durations.push_back(elapsed);
+            if tracker.song_durations.len() > ROLLING_SONGS {
+                tracker.song_durations.pop_front();
+            }
+        }
+        tracker.status.completed += 1;
+        Self::update_eta(&mut tracker);
+        let event = ProgressEvent::SongFinished {
+            index: index + 1,
+            seconds: elapsed.unwrap_or_default().as_secs_f64(),
+            eta_seconds: tracker.status.eta_seconds,
+        };
+        self.emit(tracker, event);
+    }
+
+    /// Marks the `index`-th song as failed; it may not have started.
+    pub fn fail_song(&self, index: usize, url: &str, error: &str) {
+        let mut tracker = self.inner.lock().unwrap();
+        tracker.status.failed += 1;
+        Self::record_failure(&mut tracker, index, url, error, false);
+        let event = ProgressEvent::SongFailed {
+            index: index + 1,
+            url: url.to_string(),
+            error: error.to_string(),
+            cancelled: false,
+        };
+        self.emit(tracker, event);
+    }
+
+    /// Marks the `index`-th song as aborted, e.g. by a timeout or Ctrl+C, as opposed to failed.
+    pub fn cancel_song(&self, index: usize, url: &str, reason: &str) {
+        let mut tracker = self.inner.lock().unwrap();
+        tracker.status.cancelled += 1;
+        Self::record_failure(&mut tracker, index, url, reason, true);
+        let event = ProgressEvent::SongFailed {
+            index: index + 1,
+            url: url.to_string(),
+            error: reason.to_string(),
+            cancelled: true,
+        };
+        self.emit(tracker, event);
+    }
+
+    fn record_failure(tracker: &mut Tracker, index: usize, url: &str, error: &str, cancelled: bool) {
+        tracker.in_flight.remove(&index);
+        tracker.status.recent_failures.push_back(Failure {
+            url: url.to_string(),
+            error: error.to_string(),
+            cancelled,
+        });
+        if tracker.status.recent_failures.len() > RECENT_FAILURES {
+            tracker.status.recent_failures.pop_front();
+        }
+        Self::update_eta(tracker);
+    }
+
+    pub fn finish_batch(&self) {
+        let mut tracker = self.inner.lock().unwrap();
+        tracker.in_flight.clear();
+        tracker.status.eta_seconds = Some(0.0);
+        tracker.status.finished = true;
+        let status = &tracker.status;
+        let event = ProgressEvent::BatchFinished {
+            completed: status.completed,
+            failed: status.failed,
+            cancelled: status.cancelled,
+            skipped: status.skipped,
+        };
+        self.emit(tracker, event);
+    }
+
+    fn update_eta(tracker: &mut Tracker) {
+        if tracker.song_durations.is_empty() {
+            return;
+        }
+        let average = tracker.song_durations.iter().sum::<Duration>().as_secs_f64()
+            / tracker.song_durations.len() as f64;
+        let status = &tracker.status;
+        let done = status.completed + status.failed + status.cancelled + status.skipped;
+        let remaining = status.total.saturating_sub(done);
+        tracker.status.eta_seconds = Some(average * remaining as f64);
+    }
+}
+
+/// What the code downloading one song reports through, without knowing its place in the batch.
+#[derive(Clone)]
+pub struct SongProgress {
+    status: StatusHandle,
+    index: usize,
+}
+
+impl SongProgress {
+    /// The song started downloading its `index`-th track of `total`, 0-based.
+    pub fn start_track(&self, index: usize, total: usize, name: &str) {
+        self.status.start_track(self.index, index, total, name);
+    }
+}
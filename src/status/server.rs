@@ -0,0 +1,155 @@
+use super::StatusHandle;
+use anyhow::{anyhow, Result};
+use std::io::Cursor;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+use tiny_http::{Header, Method, Request, Response};
+
+const PAGE: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>kv-downloader</title>
+<style>body { font-family: sans-serif; margin: 1em; } td { padding: 0 1em 0 0; }</style>
+</head>
+<body>
+<h1>kv-downloader</h1>
+<div id="status">Loading...</div>
+<script>
+const token = new URLSearchParams(location.search).get('token');
+const url = 'status.json' + (token ? '?token=' + encodeURIComponent(token) : '');
+function fmt(secs) {
+  if (secs == null) return '-';
+  const m = Math.floor(secs / 60), s = Math.round(secs % 60);
+  return m + 'm ' + s + 's';
+}
+function el(tag, text) {
+  const e = document.createElement(tag);
+  if (text != null) e.textContent = text;
+  return e;
+}
+async function refresh() {
+  const res = await fetch(url);
+  if (!res.ok) { document.getElementById('status').textContent = 'HTTP ' + res.status; return; }
+  const s = await res.json();
+  const view = document.createDocumentFragment();
+  if (s.finished) view.appendChild(el('p', 'Finished'));
+  else if (!s.in_flight.length) view.appendChild(el('p', 'Between songs'));
+  for (const song of s.in_flight) {
+    view.appendChild(el('p', 'Song ' + song.index + ' of ' + s.total + ': ' + song.url + (song.track ? ', track ' + song.track[0] + ' of ' + song.track[1] : '')));
+  }
+  view.appendChild(el('p', 'Completed: ' + s.completed + ', failed: ' + s.failed + ', cancelled: ' + s.cancelled + ', ETA: ' + fmt(s.eta_seconds)));
+  const table = el('table');
+  for (const p of s.phases) {
+    const row = table.appendChild(el('tr'));
+    row.appendChild(el('td', p.phase));
+    row.appendChild(el('td', p.seconds.toFixed(1) + 's'));
+  }
+  view.appendChild(table);
+  if (s.recent_failures.length) {
+    view.appendChild(el('h2', 'Recent failures'));
+    const list = view.appendChild(el('ul'));
+    for (const f of s.recent_failures) {
+      list.appendChild(el('li', f.url + ': ' + f.error + (f.cancelled ? ' (cancelled)' : '')));
+    }
+  }
+  document.getElementById('status').replaceChildren(view);
+}
+refresh();
+setInterval(refresh, 5000);
+</script>
+</body>
+</html>
+"#;
+
+/// Read-only HTTP view of a batch's [`StatusHandle`]. Stops when dropped.
+pub struct StatusServer {
+    server: Arc<tiny_http::Server>,
+    handler: Option<JoinHandle<()>>,
+    shall_exit: Arc<AtomicBool>,
+}
+
+impl StatusServer {
+    pub fn start(addr: &str, token: Option<String>, status: StatusHandle) -> Result<Self> {
+        let server = tiny_http::Server::http(addr)
+            .map_err(|e| anyhow!("Unable to start status server on {}: {}", addr, e))?;
+        let server = Arc::new(server);
+        let shall_exit = Arc::new(AtomicBool::new(false));
+
+        let srv = server.clone();
+        let exit = shall_exit.clone();
+        let handler = std::thread::spawn(move || {
+            while !exit.load(Ordering::Relaxed) {
+                match srv.recv_timeout(Duration::from_millis(500)) {
+                    Ok(Some(request)) => {
+                        if let Err(e) = respond(request, token.as_deref(), &status) {
+                            tracing::debug!("Status server failed to respond: {}", e);
+                        }
+                    }
+                    Ok(None) => {}
+                    Err(e) => {
+                        tracing::warn!("Status server stopped: {}", e);
+                        break;
+                    }
+                }
+            }
+        });
+
+        tracing::info!("Status server listening on http://{}", addr);
+        Ok(Self {
+            server,
+            handler: Some(handler),
+            shall_exit,
+        })
+    }
+
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.server.server_addr().to_ip()
+    }
+}
+
+impl Drop for StatusServer {
+    fn drop(&mut self) {
+        self.shall_exit.store(true, Ordering::Relaxed);
+        self.server.unblock();
+        if let Some(handler) = self.handler.take() {
+            let _ = handler.join();
+        }
+    }
+}
+
+fn respond(request: Request, token: Option<&str>, status: &StatusHandle) -> std::io::Result<()> {
+    if *request.method() != Method::Get {
+        return request.respond(Response::empty(405));
+    }
+
+    let url = request.url().to_string();
+    let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+
+    if let Some(expected) = token {
+        let from_query = url::form_urlencoded::parse(query.as_bytes()).any(|(k, v)| k == "token" && v == expected);
+        let from_header = request.headers().iter().any(|h| {
+            h.field.equiv("Authorization") && h.value.as_str() == format!("Bearer {}", expected)
+        });
+        if !from_query && !from_header {
+            return request.respond(Response::empty(401));
+        }
+    }
+
+    match path {
+        "/" | "/index.html" => request.respond(with_type(PAGE.to_string(), "text/html; charset=utf-8")),
+        "/status.json" => {
+            let body = serde_json::to_string(&status.snapshot()).unwrap_or_else(|_| "{}".to_string());
+            request.respond(with_type(body, "application/json"))
+        }
+        _ => request.respond(Response::empty(404)),
+    }
+}
+
+fn with_type(body: String, content_type: &str) -> Response<Cursor<Vec<u8>>> {
+    let header = Header::from_bytes(&b"Content-Type"[..], content_type.as_bytes()).unwrap();
+    Response::from_string(body).with_header(header)
+}
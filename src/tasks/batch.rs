@@ -0,0 +1,165 @@
+use anyhow::{anyhow, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use crate::abort::AbortSignal;
+use crate::audio::{title, AudioProcessor, SongFolder};
+use crate::audit::{self, FileKind};
+
+/// Folder of the download directory holding the songs being downloaded side by side, one
+/// subfolder each.
+pub const STAGING_DIR: &str = ".kv-staging";
+/// Time between two songs starting, unless `--delay` says otherwise.
+pub const DEFAULT_SONG_INTERVAL: Duration = Duration::from_secs(5);
+
+/// A pause of `base` plus a random part of up to `jitter`, so the site doesn't see
+/// requests at a steady beat.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Delay {
+    pub base: Duration,
+    pub jitter: Duration,
+}
+
+impl Delay {
+    /// From seconds as given on the command line; negative ones count as none.
+    pub fn from_secs(base: f64, jitter: f64) -> Self {
+        Self {
+            base: Duration::from_secs_f64(base.max(0.0)),
+            jitter: Duration::from_secs_f64(jitter.max(0.0)),
+        }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.base.is_zero() && self.jitter.is_zero()
+    }
+
+    /// A pause of the delay, with a fresh random part each time.
+    pub fn next(&self) -> Duration {
+        self.base + self.jitter.mul_f64(fastrand::f64())
+    }
+}
+
+/// Spaces out the songs of a batch across every worker, so downloading several at once
+/// doesn't load song pages any faster than one at a time would.
+pub struct RateLimiter {
+    delay: Delay,
+    next: Mutex<Option<Instant>>,
+}
+
+impl RateLimiter {
+    pub fn new(interval: Duration) -> Self {
+        Self::with_delay(Delay {
+            base: interval,
+            jitter: Duration::ZERO,
+        })
+    }
+
+    /// Spaces the songs out by `delay`, drawn again for every slot.
+    pub fn with_delay(delay: Delay) -> Self {
+        Self {
+            delay,
+            next: Mutex::new(None),
+        }
+    }
+
+    /// Takes the next free slot and sleeps until it comes. The first call returns at once.
+    /// Returns `false` if `abort` was requested while waiting.
+    pub fn wait(&self, abort: &AbortSignal) -> bool {
+        let slot = {
+            let mut next = self.next.lock().unwrap();
+            let slot = next.map_or_else(Instant::now, |next| next.max(Instant::now()));
+            *next = Some(slot + self.delay.next());
+            slot
+        };
+        while Instant::now() < slot {
+            if abort.is_requested() {
+                return false;
+            }
+            sleep((slot - Instant::now()).min(Duration::from_millis(100)));
+        }
+        !abort.is_requested()
+    }
+}
+
+/// The folder the song at `url` is downloaded and processed in. It's named after the song,
+/// so a later run finds what an interrupted one left of it.
+pub fn staging_dir(download_root: &Path, url: &str) -> PathBuf {
+    let name = title::from_url(url).unwrap_or_else(|| url.replace(|c: char| !c.is_ascii_alphanumeric(), "_"));
+    download_root.join(STAGING_DIR).join(name)
+}
+
+/// The [`staging_dir`] of the song at `url`. A folder an earlier run left unfinished for
+/// it in `download_root` is moved in, to be finished there and published like any other.
+pub fn stage_song(download_root: &Path, url: &str) -> Result<PathBuf> {
+    let staging = staging_dir(download_root, url);
+    if AudioProcessor::song_folder(download_root, url)? != SongFolder::Unfinished {
+        return Ok(staging);
+    }
+    let song_dir = AudioProcessor::song_dir(download_root, url)?;
+    let target = staging.join(song_dir.file_name().ok_or_else(|| anyhow!("{:?} has no folder name", song_dir))?);
+    if target.exists() {
+        tracing::warn!("{:?} was left unfinished, and so was {:?}; finishing the staged one", song_dir, target);
+        return Ok(staging);
+    }
+    fs::create_dir_all(&staging)?;
+    fs::rename(&song_dir, &target)?;
+    tracing::info!("Resuming {:?}, left unfinished by an earlier run, in {:?}", song_dir, staging);
+    Ok(staging)
+}
+
+/// Creates `staging`, or clears the unfinished downloads out of what an interrupted run
+/// left in it. The stems it finished are kept, so only the missing ones are fetched.
+pub fn prepare_staging(staging: &Path) -> Result<()> {
+    fs::create_dir_all(staging)?;
+    for entry in fs::read_dir(staging)? {
+        let path = entry?.path();
+        if path.is_file() && audit::classify(&path) == FileKind::Partial {
+            tracing::info!("Removing {:?}, left unfinished by an earlier run", path);
+            fs::remove_file(&path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Moves the song folders processing wrote into `staging` up into `download_root`, then
+/// removes `staging` if nothing else was left in it. Returns the folders moved.
+pub fn publish_staged(staging: &Path, download_root: &Path) -> Result<Vec<PathBuf>> {
+    let mut moved = Vec::new();
+    let mut folders: Vec<PathBuf> = fs::read_dir(staging)?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.is_dir())
+        .collect();
+    folders.sort();
+    for folder in folders {
+        let target = download_root.join(folder.file_name().unwrap());
+        if target.exists() {
+            return Err(anyhow!(
+                "{:?} already exists; the song processed in {:?} was left there",
+                target,
+                staging
+            ));
+        }
+        fs::rename(&folder, &target)?;
+        moved.push(target);
+    }
+    remove_staging(staging);
+    Ok(moved)
+}
+
+/// Removes `staging` if it's empty, otherwise leaves it for a look and says so.
+pub fn remove_staging(staging: &Path) {
+    let is_empty = fs::read_dir(staging).is_ok_and(|mut entries| entries.next().is_none());
+    if is_empty {
+        let _ = fs::remove_dir(staging);
+        if let Some(parent) = staging.parent() {
+            // Only goes once every song is done with it
+            let _ = fs::remove_dir(parent);
+        }
+    } else {
+        tracing::warn!("Left files behind in {:?}", staging);
+    }
+}
@@ -0,0 +1,176 @@
+use anyhow::Result;
+use std::fmt::Display;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::audio::title;
+
+/// The songs of a batch that didn't finish, written into the download directory in the
+/// format `--from-file` reads, to retry them.
+pub const RETRY_LIST_FILE: &str = "failed_urls.txt";
+/// Exit status of a batch in which some songs failed.
+pub const EXIT_SOME_FAILED: i32 = 3;
+/// Exit status of a batch in which every song it tried failed, as when the session
+/// broke early on.
+pub const EXIT_ALL_FAILED: i32 = 4;
+
+/// How far a song got before it failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    Download,
+    Process,
+}
+
+impl Display for Stage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Download => "download",
+            Self::Process => "process",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    Processed,
+    Skipped,
+    Failed { stage: Stage, error: String },
+    /// Aborted, e.g. by Ctrl+C or a timeout, rather than failing.
+    Cancelled { stage: Stage, error: String },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SongResult {
+    pub url: String,
+    pub title: String,
+    pub outcome: Outcome,
+}
+
+impl SongResult {
+    fn unfinished(&self) -> Option<(&Stage, &String)> {
+        match &self.outcome {
+            Outcome::Failed { stage, error } | Outcome::Cancelled { stage, error } => Some((stage, error)),
+            _ => None,
+        }
+    }
+}
+
+/// What became of each song a batch came to, in the order they ended. Shared by every
+/// worker of the batch.
+#[derive(Debug, Default)]
+pub struct BatchReport {
+    results: Mutex<Vec<SongResult>>,
+}
+
+impl BatchReport {
+    pub fn record(&self, url: &str, outcome: Outcome) {
+        self.results.lock().unwrap().push(SongResult {
+            url: url.to_string(),
+            title: title::from_url(url).unwrap_or_else(|| url.to_string()),
+            outcome,
+        });
+    }
+
+    pub fn results(&self) -> Vec<SongResult> {
+        self.results.lock().unwrap().clone()
+    }
+
+    fn count(&self, matches: impl Fn(&Outcome) -> bool) -> usize {
+        self.results.lock().unwrap().iter().filter(|result| matches(&result.outcome)).count()
+    }
+
+    pub fn processed(&self) -> usize {
+        self.count(|outcome| *outcome == Outcome::Processed)
+    }
+
+    pub fn skipped(&self) -> usize {
+        self.count(|outcome| *outcome == Outcome::Skipped)
+    }
+
+    /// Songs that failed or were cancelled.
+    pub fn unfinished(&self) -> usize {
+        self.count(|outcome| matches!(outcome, Outcome::Failed { .. } | Outcome::Cancelled { .. }))
+    }
+
+    /// Writes the unfinished songs into [`RETRY_LIST_FILE`] in `download_dir`, each after a
+    /// comment with its error, or removes a list an earlier batch left when there are none.
+    /// Returns the list's path if one was written.
+    pub fn write_retry_list(&self, download_dir: &Path) -> Result<Option<PathBuf>> {
+        let path = download_dir.join(RETRY_LIST_FILE);
+        let results = self.results();
+        let unfinished: Vec<_> = results.iter().filter_map(|r| r.unfinished().map(|u| (r, u))).collect();
+        if unfinished.is_empty() {
+            if path.exists() {
+                fs::remove_file(&path)?;
+            }
+            return Ok(None);
+        }
+        let mut text = String::from("# Songs the last batch didn't finish; retry them with --from-file\n");
+        for (result, (stage, error)) in unfinished {
+            text.push_str(&format!("# {} ({}): {}\n{}\n", result.title, stage, one_line(error), result.url));
+        }
+        fs::write(&path, text)?;
+        Ok(Some(path))
+    }
+
+    /// The exit status the batch ends with: 0 unless a song failed, then
+    /// [`EXIT_SOME_FAILED`], or [`EXIT_ALL_FAILED`] if none that was tried succeeded.
+    pub fn exit_code(&self) -> i32 {
+        match (self.unfinished(), self.processed()) {
+            (0, _) => 0,
+            (_, 0) => EXIT_ALL_FAILED,
+            _ => EXIT_SOME_FAILED,
+        }
+    }
+}
+
+fn one_line(error: &str) -> String {
+    error.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+impl Display for BatchReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} succeeded, {} skipped, {} failed",
+            self.processed(),
+            self.skipped(),
+            self.unfinished()
+        )?;
+        for result in self.results() {
+            if let Some((stage, error)) = result.unfinished() {
+                let cancelled = matches!(result.outcome, Outcome::Cancelled { .. });
+                write!(
+                    f,
+                    "\n - {} ({}{}): {}",
+                    result.title,
+                    if cancelled { "cancelled during " } else { "" },
+                    stage,
+                    one_line(error)
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A batch that finished with songs failed, ending the program with
+/// [`BatchReport::exit_code`].
+#[derive(Debug)]
+pub struct BatchFailed {
+    pub failed: usize,
+    pub exit_code: i32,
+}
+
+impl Display for BatchFailed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.exit_code == EXIT_ALL_FAILED {
+            write!(f, "Every song the batch tried failed ({})", self.failed)
+        } else {
+            write!(f, "{} songs of the batch failed", self.failed)
+        }
+    }
+}
+
+impl std::error::Error for BatchFailed {}
@@ -0,0 +1,183 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::profile;
+
+/// Where a batch got with each song, in the download directory, so a run that crashed or
+/// was stopped picks up where it left off.
+pub const BATCH_STATE_FILE: &str = "batch_state.json";
+/// Times a song is tried across runs before a batch stops retrying it.
+pub const DEFAULT_MAX_ATTEMPTS: u32 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SongStatus {
+    Pending,
+    /// Its files are down but weren't processed yet.
+    Downloaded,
+    Processed,
+    Failed,
+}
+
+impl Display for SongStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Pending => "pending",
+            Self::Downloaded => "downloaded",
+            Self::Processed => "processed",
+            Self::Failed => "failed",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SongRecord {
+    pub status: SongStatus,
+    /// Runs that started the song.
+    pub attempts: u32,
+    /// RFC 3339, UTC.
+    pub added_at: String,
+    pub updated_at: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// What a batch does with a song, going by its record.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Resume {
+    /// Never finished, or failed fewer times than allowed.
+    Run,
+    Done,
+    /// Failed on every attempt allowed.
+    GaveUp { attempts: u32, error: Option<String> },
+}
+
+/// The contents of [`BATCH_STATE_FILE`], by song URL.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BatchState {
+    pub songs: BTreeMap<String, SongRecord>,
+}
+
+impl BatchState {
+    /// Records the songs not seen before as pending.
+    pub fn add_pending<'u>(&mut self, urls: impl IntoIterator<Item = &'u String>) {
+        let now = now();
+        for url in urls {
+            self.songs.entry(url.clone()).or_insert_with(|| SongRecord {
+                status: SongStatus::Pending,
+                attempts: 0,
+                added_at: now.clone(),
+                updated_at: now.clone(),
+                error: None,
+            });
+        }
+    }
+
+    pub fn resume(&self, url: &str, max_attempts: u32) -> Resume {
+        match self.songs.get(url) {
+            Some(record) if record.status == SongStatus::Processed => Resume::Done,
+            Some(record) if record.status == SongStatus::Failed && record.attempts >= max_attempts => Resume::GaveUp {
+                attempts: record.attempts,
+                error: record.error.clone(),
+            },
+            _ => Resume::Run,
+        }
+    }
+
+    /// Counts an attempt at the song.
+    pub fn start(&mut self, url: &str) {
+        let record = self.record(url);
+        record.attempts += 1;
+        record.status = SongStatus::Pending;
+        record.updated_at = now();
+    }
+
+    /// Sets the song's status; `error` is kept only for a failure.
+    pub fn set(&mut self, url: &str, status: SongStatus, error: Option<&str>) {
+        let record = self.record(url);
+        record.status = status;
+        record.error = error.filter(|_| status == SongStatus::Failed).map(String::from);
+        record.updated_at = now();
+    }
+
+    pub fn count(&self, status: SongStatus) -> usize {
+        self.songs.values().filter(|record| record.status == status).count()
+    }
+
+    fn record(&mut self, url: &str) -> &mut SongRecord {
+        self.add_pending(std::iter::once(&url.to_string()));
+        self.songs.get_mut(url).expect("just added")
+    }
+}
+
+fn now() -> String {
+    chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+}
+
+/// A [`BatchState`] kept in sync with its file, shared by every worker of a batch.
+pub struct BatchStateFile {
+    path: PathBuf,
+    state: Mutex<BatchState>,
+}
+
+impl BatchStateFile {
+    /// Loads the state of `download_dir`, or starts an empty one. `reset` discards what
+    /// was there.
+    pub fn open(download_dir: &Path, reset: bool) -> Result<Self> {
+        Self::open_for(download_dir, None, reset)
+    }
+
+    /// Like [`BatchStateFile::open`], for the batches of `profile`, which keep a state of
+    /// their own.
+    pub fn open_for(download_dir: &Path, profile: Option<&str>, reset: bool) -> Result<Self> {
+        let path = download_dir.join(profile::file_name(BATCH_STATE_FILE, profile));
+        let state = if reset || !path.exists() {
+            BatchState::default()
+        } else {
+            let data = fs::read_to_string(&path).map_err(|e| anyhow!("Unable to read {:?}: {}", path, e))?;
+            serde_json::from_str(&data).map_err(|e| anyhow!("Unable to parse {:?}: {}", path, e))?
+        };
+        let file = Self {
+            path,
+            state: Mutex::new(state),
+        };
+        if reset {
+            file.update(|_| ())?;
+        }
+        Ok(file)
+    }
+
+    pub fn resume(&self, url: &str, max_attempts: u32) -> Resume {
+        self.state.lock().unwrap().resume(url, max_attempts)
+    }
+
+    pub fn snapshot(&self) -> BatchState {
+        self.state.lock().unwrap().clone()
+    }
+
+    /// Changes the state and writes it out. The file is replaced in one rename, so a crash
+    /// leaves either the old state or the new one.
+    pub fn update<T>(&self, change: impl FnOnce(&mut BatchState) -> T) -> Result<T> {
+        let mut state = self.state.lock().unwrap();
+        let changed = change(&mut state);
+        let mut data = serde_json::to_string_pretty(&*state)?;
+        data.push('\n');
+        let partial = self.path.with_extension("json.partial");
+        fs::write(&partial, data).map_err(|e| anyhow!("Unable to write {:?}: {}", partial, e))?;
+        fs::rename(&partial, &self.path).map_err(|e| anyhow!("Unable to replace {:?}: {}", self.path, e))?;
+        Ok(changed)
+    }
+
+    /// Like [`BatchStateFile::update`], but a file that can't be written is only warned
+    /// about: the batch goes on, it just can't resume as precisely.
+    pub fn record(&self, change: impl FnOnce(&mut BatchState)) {
+        if let Err(e) = self.update(change) {
+            tracing::warn!("Unable to save the batch state: {}", e);
+        }
+    }
+}
@@ -1,23 +1,556 @@
+use crate::abort::Interrupted;
+use crate::audio::click::ClickDetection;
+use crate::audio::processor::strip_duplicate_marker;
+use crate::audio::{title, AudioProcessor, FULL_MIX};
+use crate::cdp_trace::{CdpTrace, TracedTab};
+use crate::config::DownloadSettings;
+use crate::debug_capture::DebugCapture;
 use crate::driver::Driver;
+use crate::metadata::SongInfo;
+use crate::navigation::Retries;
+use crate::status::SongProgress;
+use crate::tasks::batch::Delay;
+use crate::tasks::track_filter::TrackFilter;
+use crate::tasks::transfers::{remove_partials, StallTimer, Transfer, TransferState, Transfers};
 use anyhow::{anyhow, Result};
+use headless_chrome::protocol::cdp::Browser::CancelDownload;
+use serde::Deserialize;
 use headless_chrome::{Element, Tab};
 use std::fmt::Display;
-use std::{error::Error, thread::sleep, time::{Duration, Instant}};
-use std::path::Path;
+use std::{error::Error, thread::sleep, time::{Duration, Instant, SystemTime}};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
 use std::fs;
 
+/// Every row of the mixer, and the solo button inside one.
+const MIXER_ROW: &str = ".mixer .track";
+const SOLO_BUTTON: &str = ".track__controls.track__solo";
+/// The loader the song page shows next to the pitch from the click on download until the
+/// download's modal opens (its `editMixCallback` and `activeDownloadBtn`), while the site
+/// renders the mix.
+const GENERATION_PROGRESS: &str = "#mixprod_loader";
+/// The pitch buttons of the mixer, told apart by their place on either side of the value;
+/// their titles are in the page's language.
+const PITCH_UP: &str = "div.pitch span.pitch__value ~ button.btn--pitch";
+const PITCH_DOWN: &str = "div.pitch button.btn--pitch:has(~ span.pitch__value)";
+/// How long the pitch value gets to change after a pitch button is clicked.
+const PITCH_STEP_TIMEOUT: Duration = Duration::from_secs(2);
+/// How long a mixer that kept its rows after the pitch changed gets to render them again
+/// before it's used as it is, since the site may have updated it in place.
+const MIXER_IN_PLACE_WAIT: Duration = Duration::from_secs(4);
+/// Semitones the site transposes a song by at most, either way; some songs allow less.
+pub const MAX_TRANSPOSE: i8 = 12;
+/// How long the mixer gets to settle on one soloed track before it's reset.
+const SOLO_TIMEOUT: Duration = Duration::from_secs(10);
+/// Wait after clicking a stale solo off, before the mixer is checked again.
+const SOLO_SETTLE: Duration = Duration::from_millis(500);
+/// How long Chrome gets to confirm that an aborted song's downloads are cancelled.
+const CANCEL_CONFIRMATION: Duration = Duration::from_secs(5);
+/// How long after a track's file shows up Chrome's download events get to arrive, before
+/// the file's size is watched instead.
+const DOWNLOAD_EVENT_GRACE: Duration = Duration::from_secs(2);
+/// Wait before the first retry of a track; it doubles with every further attempt.
+pub const RETRY_BACKOFF: Duration = Duration::from_secs(2);
+/// Longest wait between two attempts at a track.
+pub const RETRY_BACKOFF_CAP: Duration = Duration::from_secs(60);
+/// Retries of a track after its first attempt, unless `--track-retries` says otherwise.
+pub const DEFAULT_TRACK_RETRIES: u32 = 2;
+/// How long a track's file gets to appear and finish, unless `--download-timeout` says otherwise.
+pub const DEFAULT_DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(30);
+/// Time a finished file's size must hold still, unless `--stability-interval` says otherwise.
+pub const DEFAULT_STABILITY_INTERVAL: Duration = Duration::from_millis(500);
+/// How long the site may spend generating a track before its download begins, unless
+/// `download.generation_timeout_secs` says otherwise.
+pub const DEFAULT_GENERATION_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// How long to wait for a track's file and how to tell it's complete.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DownloadWait {
+    /// How long a download gets to begin once the click went through, and to finish once
+    /// it began.
+    pub timeout: Duration,
+    /// Also how often the download directory is checked for the file.
+    pub stability_interval: Duration,
+    /// How long the site's "generating your track" progress may stay up; `timeout` only
+    /// runs again once it's gone.
+    pub generation_timeout: Duration,
+}
+
+impl Default for DownloadWait {
+    fn default() -> Self {
+        Self {
+            timeout: DEFAULT_DOWNLOAD_TIMEOUT,
+            stability_interval: DEFAULT_STABILITY_INTERVAL,
+            generation_timeout: DEFAULT_GENERATION_TIMEOUT,
+        }
+    }
+}
+
+impl DownloadWait {
+    /// The flags' values, falling back to the config file's and then the defaults.
+    pub fn resolve(
+        timeout_secs: Option<u64>,
+        stability_interval_ms: Option<u64>,
+        config: &DownloadSettings,
+    ) -> Self {
+        let default = Self::default();
+        Self {
+            timeout: timeout_secs.or(config.timeout_secs).map_or(default.timeout, Duration::from_secs),
+            stability_interval: stability_interval_ms
+                .or(config.stability_interval_ms)
+                .map_or(default.stability_interval, Duration::from_millis),
+            generation_timeout: config
+                .generation_timeout_secs
+                .map_or(default.generation_timeout, Duration::from_secs),
+        }
+    }
+}
+
+/// Where a track's download has got to since its button was clicked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DownloadPhase {
+    /// Clicked; neither generating nor begun yet.
+    Requested,
+    /// The site shows its track-generation progress.
+    Generating,
+    /// Chrome has begun receiving the file.
+    Receiving,
+}
+
+/// Times a track's download phase by phase, so a track the site first has to generate
+/// isn't held to the download's timeout: each phase gets its own time limit, starting
+/// when the phase does.
+#[derive(Debug, Clone)]
+pub struct DownloadClock {
+    wait: DownloadWait,
+    phase: DownloadPhase,
+    since: Instant,
+}
+
+impl DownloadClock {
+    pub fn new(wait: &DownloadWait, now: Instant) -> Self {
+        Self {
+            wait: *wait,
+            phase: DownloadPhase::Requested,
+            since: now,
+        }
+    }
+
+    pub fn phase(&self) -> DownloadPhase {
+        self.phase
+    }
+
+    /// When the current phase began.
+    pub fn since(&self) -> Instant {
+        self.since
+    }
+
+    /// Takes what the page and Chrome show at `now`. Once the file is being received the
+    /// generation progress no longer matters; before that, the progress coming and going
+    /// starts a phase each time. Fails when the current phase has run out of time.
+    pub fn observe(&mut self, generating: bool, receiving: bool, now: Instant) -> Result<DownloadPhase, DownloadError> {
+        let phase = match (self.phase, receiving, generating) {
+            (DownloadPhase::Receiving, _, _) | (_, true, _) => DownloadPhase::Receiving,
+            (_, false, true) => DownloadPhase::Generating,
+            (_, false, false) => DownloadPhase::Requested,
+        };
+        if phase != self.phase {
+            self.phase = phase;
+            self.since = now;
+        }
+        let limit = match self.phase {
+            DownloadPhase::Generating => self.wait.generation_timeout,
+            DownloadPhase::Requested | DownloadPhase::Receiving => self.wait.timeout,
+        };
+        if now.duration_since(self.since) <= limit {
+            Ok(self.phase)
+        } else if self.phase == DownloadPhase::Generating {
+            Err(DownloadError::GenerationTimeout(limit))
+        } else {
+            Err(DownloadError::DownloadTimeout)
+        }
+    }
+}
+
+/// The site's track-generation progress, while it shows.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct GenerationProgress {
+    /// How far along the page says it is, when it says.
+    pub percent: Option<f64>,
+}
+
+/// The mixer's pitch buttons and their value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub struct PitchControl {
+    pub current: i8,
+    /// The range the page declares, when it does.
+    pub min: Option<i8>,
+    pub max: Option<i8>,
+    /// A pitch button is disabled once the pitch is at that end of the song's range.
+    pub up_disabled: bool,
+    pub down_disabled: bool,
+}
+
+impl PitchControl {
+    /// The lowest and highest pitch the song is known to allow: a disabled button puts the
+    /// limit at the current pitch; otherwise it's the declared one, or the site's widest.
+    pub fn range(&self) -> (i8, i8) {
+        let min = if self.down_disabled { self.current } else { self.min.unwrap_or(-MAX_TRANSPOSE) };
+        let max = if self.up_disabled { self.current } else { self.max.unwrap_or(MAX_TRANSPOSE) };
+        (min, max)
+    }
+
+    pub fn check(&self, desired_pitch: i8) -> Result<(), DownloadError> {
+        let (min, max) = self.range();
+        if (min..=max).contains(&desired_pitch) {
+            Ok(())
+        } else {
+            Err(DownloadError::TransposeOutOfRange {
+                requested: desired_pitch,
+                min,
+                max,
+            })
+        }
+    }
+}
+
+/// What the mixer looks like while it's waited on to come back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub struct MixerState {
+    /// Rows with a solo button.
+    pub rows: usize,
+    /// Of those, the rows already there before the mixer was reloaded.
+    pub stale_rows: usize,
+}
+
+impl MixerState {
+    /// Whether the mixer can be used again: it has rows, and none from before the reload.
+    pub fn is_interactive(&self) -> bool {
+        self.rows > 0 && self.stale_rows == 0
+    }
+}
+
+/// Whether the song's full mix, the mixer with nothing soloed, is downloaded.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum FullMix {
+    #[default]
+    Off,
+    /// Before the stems.
+    Also,
+    /// Instead of the stems.
+    Only,
+}
+
 #[derive(Default, Clone)]
 pub struct DownloadOptions {
     pub count_in: bool,
     pub transpose: i8,
+    pub trust_site_state: bool,
+    /// Further attempts at a track whose download fails before the song is given up.
+    pub track_retries: u32,
+    /// Mixer tracks to download; empty downloads them all.
+    pub tracks: TrackFilter,
+    pub full_mix: FullMix,
+    pub wait: DownloadWait,
+    /// Directory the song's files are downloaded into instead of the driver's, so songs
+    /// downloaded at the same time each keep to their own.
+    pub download_dir: Option<String>,
+    /// Records the song's CDP traffic; off by default.
+    pub trace: CdpTrace,
+    /// Download the stems that drift against the click once more, since the site's
+    /// transpose rendering sometimes gets it right the second time.
+    pub retry_drifted: bool,
+    /// Told about each track as its download starts.
+    pub progress: Option<SongProgress>,
+    /// Pause between two tracks of the song; none by default.
+    pub track_delay: Delay,
+    /// How the click is told from the stems, to measure their drift against it.
+    pub click: ClickDetection,
+    /// Where the page is captured when the song fails; off by default.
+    pub debug_capture: Option<DebugCapture>,
+}
+
+/// What a song's download brought back.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DownloadedSong {
+    /// The mixer tracks to process, in mixer order.
+    pub track_names: Vec<String>,
+    /// Times the site re-rendered the mixer while the stems were being downloaded.
+    pub mixer_rerenders: Vec<MixerRerender>,
+    /// The song page's details, with the transpose and count-in the stems were downloaded
+    /// with, for processing to save.
+    pub page: Option<SongInfo>,
+}
+
+/// The mixer found re-rendered just before a track was soloed, such as after an ad
+/// rotation, so the solo buttons captured earlier no longer lined up with the tracks.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MixerRerender {
+    pub track_name: String,
+    /// Index of the track's solo button before the re-render, and after it.
+    pub before: Option<usize>,
+    pub after: Option<usize>,
+}
+
+impl Display for MixerRerender {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let row = |at: Option<usize>| at.map_or("nowhere".to_string(), |at| format!("row {}", at + 1));
+        if self.before == self.after {
+            write!(f, "'{}' is still at {}", self.track_name, row(self.after))
+        } else {
+            write!(f, "'{}' moved from {} to {}", self.track_name, row(self.before), row(self.after))
+        }
+    }
+}
+
+/// Index of the `occurrence`-th of `captions`, the tracks under the solo buttons, named
+/// `track_name`.
+pub fn locate_track(captions: &[String], track_name: &str, occurrence: usize) -> Option<usize> {
+    captions
+        .iter()
+        .enumerate()
+        .filter(|(_, caption)| *caption == track_name)
+        .nth(occurrence)
+        .map(|(i, _)| i)
+}
+
+/// What the file of a download about to start looks like, so that a stray file landing in
+/// the directory, such as a sync client's conflicted copy or an earlier song's late stem,
+/// isn't taken for it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ExpectedDownload {
+    /// The mixer track soloed for it; `None` for the full mix.
+    pub track_name: Option<String>,
+    /// The name Chrome gave the download when it began, once that event has arrived.
+    pub suggested_filename: Option<String>,
+}
+
+impl ExpectedDownload {
+    /// Whether `filename` can be this download: an MP3 named as Chrome announced it, ` (1)`
+    /// and the like included, or before that, one named like the site's downloads of the
+    /// soloed track.
+    pub fn matches(&self, filename: &str) -> bool {
+        let Some(stem) = strip_mp3(filename) else {
+            return false;
+        };
+        if let Some(suggested) = &self.suggested_filename {
+            let suggested = strip_mp3(suggested).unwrap_or(suggested);
+            return stem == suggested || strip_duplicate_marker(stem) == suggested;
+        }
+        if !AudioProcessor::is_kv_download(filename) {
+            return false;
+        }
+        match &self.track_name {
+            Some(track) => AudioProcessor::same_track_name(&AudioProcessor::normalize_track_name(filename), track),
+            None => true,
+        }
+    }
+
+    /// The newest of `new_files`, with their modification times, that [`matches`] this
+    /// download. Several candidates are logged, since all but one will be left behind.
+    ///
+    /// [`matches`]: ExpectedDownload::matches
+    pub fn pick(&self, new_files: &[(PathBuf, SystemTime)]) -> Option<PathBuf> {
+        let candidates: Vec<&(PathBuf, SystemTime)> = new_files
+            .iter()
+            .filter(|(path, _)| path.file_name().is_some_and(|n| self.matches(&n.to_string_lossy())))
+            .collect();
+        let (newest, _) = candidates.iter().max_by_key(|(_, modified)| *modified)?;
+        if candidates.len() > 1 {
+            tracing::warn!(
+                "{} new files could be the download of {}, using the newest {:?}: {:?}",
+                candidates.len(),
+                self,
+                newest,
+                candidates.iter().map(|(path, _)| path).collect::<Vec<_>>()
+            );
+        }
+        Some(newest.clone())
+    }
+}
+
+impl Display for ExpectedDownload {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (&self.suggested_filename, &self.track_name) {
+            (Some(filename), _) => write!(f, "{:?}", filename),
+            (None, Some(track)) => write!(f, "'{}'", track),
+            (None, None) => write!(f, "the {}", FULL_MIX),
+        }
+    }
+}
+
+/// `filename` without its `.mp3` extension, or `None` when it isn't an MP3.
+fn strip_mp3(filename: &str) -> Option<&str> {
+    let split = filename.len().checked_sub(4)?;
+    (filename.is_char_boundary(split) && filename[split..].eq_ignore_ascii_case(".mp3")).then(|| &filename[..split])
 }
 
-#[derive(Debug)]
+/// The download directory and Chrome's downloads just before a download is started, to
+/// tell its file from the ones already there.
+struct PendingDownload {
+    dir: PathBuf,
+    existing: HashSet<PathBuf>,
+    known_transfers: Vec<String>,
+    expected: ExpectedDownload,
+    /// Chrome's download of it, once that has begun.
+    transfer: Option<String>,
+}
+
+/// A single row of the mixer.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct TrackInfo {
+    pub index: usize,
+    pub name: String,
+    /// Whether the site marks this stem as recently rendered/downloaded. `None` when the page
+    /// doesn't show the indicator at all.
+    pub downloaded_on_site: Option<bool>,
+}
+
+/// A mixer row as the page has it, before it's known to be one that can be downloaded.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct MixerRow {
+    pub index: usize,
+    /// The row's caption; empty when it has none.
+    pub name: String,
+    /// Whether the row has a solo button of its own.
+    pub has_solo: bool,
+    pub downloaded_on_site: Option<bool>,
+}
+
+/// The rows that have both a caption and a solo button, each read from the same `.track`
+/// element so the two can't belong to different tracks. Any other row is skipped with a
+/// warning.
+pub fn pair_mixer_rows(rows: Vec<MixerRow>) -> Vec<TrackInfo> {
+    rows.into_iter()
+        .filter_map(|row| {
+            let missing = match (row.name.is_empty(), row.has_solo) {
+                (false, true) => {
+                    return Some(TrackInfo {
+                        index: row.index,
+                        name: row.name,
+                        downloaded_on_site: row.downloaded_on_site,
+                    })
+                }
+                (true, true) => "caption",
+                (false, false) => "solo button",
+                (true, false) => "caption or solo button",
+            };
+            tracing::warn!("Skipping mixer row {}: it has no {}", row.index, missing);
+            None
+        })
+        .collect()
+}
+
+/// The mixer's solo buttons, as they stand against the one track that should be soloed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SoloState {
+    /// The track is the only one soloed.
+    Exclusive,
+    /// The track is soloed, and so are these rows still.
+    Stale(Vec<usize>),
+    /// The track isn't soloed (yet).
+    Missing,
+}
+
+/// Where `active`, the rows whose solo button is active, leaves the `wanted` row.
+pub fn solo_state(active: &[usize], wanted: usize) -> SoloState {
+    if !active.contains(&wanted) {
+        SoloState::Missing
+    } else if active.len() == 1 {
+        SoloState::Exclusive
+    } else {
+        SoloState::Stale(active.iter().copied().filter(|row| *row != wanted).collect())
+    }
+}
+
+/// How a track ended up the only one soloed, for the log.
+#[derive(Debug, Clone, PartialEq)]
+enum SoloPath {
+    /// The site made the solo exclusive by itself.
+    Exclusive,
+    /// These rows were still soloed and were clicked off.
+    UnsoloedStale(Vec<usize>),
+    /// The mixer had to be reset and the track soloed again.
+    Reset,
+}
+
+impl Display for SoloPath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Exclusive => f.write_str("soloed on its own"),
+            Self::UnsoloedStale(rows) => write!(f, "unsoloed the stale rows {:?}", rows),
+            Self::Reset => f.write_str("reset the mixer and soloed it again"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StemDecision {
+    Download,
+    SkipLocalFile,
+    SkipSiteState,
+    /// Left out by `--tracks`.
+    SkipNotSelected,
+}
+
+impl StemDecision {
+    pub fn needs_download(&self) -> bool {
+        matches!(self, Self::Download)
+    }
+
+    /// Whether the stem's MP3 is in the song's folder once its tracks are downloaded. One
+    /// the site marks as downloaded before is left wherever it went then.
+    pub fn leaves_a_file(&self) -> bool {
+        matches!(self, Self::Download | Self::SkipLocalFile)
+    }
+}
+
+/// The names of `track_names` whose MP3s processing finds in the song's folder, given the
+/// `decisions` made for them.
+pub fn expected_stems(track_names: &[String], decisions: &[StemDecision]) -> Vec<String> {
+    track_names
+        .iter()
+        .zip(decisions)
+        .filter(|(_, decision)| decision.leaves_a_file())
+        .map(|(name, _)| name.clone())
+        .collect()
+}
+
+/// Decides which stems still need to be fetched. A stem with a matching MP3 among
+/// `local_names`, compared as [`AudioProcessor::same_track_name`] does, is always skipped,
+/// so a song that failed part way only fetches what's missing; with `trust_site_state`, so
+/// is one the site already marks as downloaded.
+pub fn plan_track_downloads(
+    tracks: &[TrackInfo],
+    local_names: &[String],
+    trust_site_state: bool,
+) -> Vec<StemDecision> {
+    tracks
+        .iter()
+        .map(|track| {
+            if local_names.iter().any(|n| AudioProcessor::same_track_name(n, &track.name)) {
+                StemDecision::SkipLocalFile
+            } else if trust_site_state && track.downloaded_on_site == Some(true) {
+                StemDecision::SkipSiteState
+            } else {
+                StemDecision::Download
+            }
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum DownloadError {
     NotPurchased,
     NotASongPage,
     ResetButtonNotFound,
     DownloadTimeout,
+    /// The site was still generating the track when its time ran out.
+    GenerationTimeout(Duration),
+    /// `--transpose` is out of the range the song allows.
+    TransposeOutOfRange { requested: i8, min: i8, max: i8 },
+    /// The song was aborted, by a timeout or on request, and its downloads cancelled.
+    Cancelled(String),
     BrowserError(String),
 }
 
@@ -28,44 +561,244 @@ impl Display for DownloadError {
             Self::NotASongPage => f.write_str("This doesn't look like a song page. Check the url."),
             Self::ResetButtonNotFound => f.write_str("Reset button not found on the page"),
             Self::DownloadTimeout => f.write_str("Download operation timed out"),
+            Self::TransposeOutOfRange { requested, min, max } => write!(
+                f,
+                "This song can only be transposed from {} to {} semitones, not {}",
+                min, max, requested
+            ),
+            Self::GenerationTimeout(limit) => {
+                write!(f, "The site was still generating the track after {}s", limit.as_secs())
+            }
+            Self::Cancelled(reason) => write!(f, "Cancelled: {}", reason),
             Self::BrowserError(msg) => write!(f, "Browser error: {}", msg),
         }
     }
 }
 impl Error for DownloadError {}
 
+impl DownloadError {
+    /// Whether `error` is a song that was aborted rather than one that failed.
+    pub fn is_cancelled(error: &anyhow::Error) -> bool {
+        matches!(error.downcast_ref::<Self>(), Some(Self::Cancelled(_))) || error.downcast_ref::<Interrupted>().is_some()
+    }
+}
+
+/// The wait before attempt `attempt + 1` at a track, after `attempt` failed ones:
+/// [`RETRY_BACKOFF`] doubled for every attempt after the first, up to [`RETRY_BACKOFF_CAP`].
+pub fn retry_delay(attempt: u32) -> Duration {
+    let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+    RETRY_BACKOFF.saturating_mul(factor).min(RETRY_BACKOFF_CAP)
+}
+
 impl Driver {
-    pub fn download_song(&self, url: &str, options: DownloadOptions) -> anyhow::Result<Vec<String>> {
+    /// Where `options`' song is downloaded: its own directory, or else the driver's.
+    pub(crate) fn download_path(&self, options: &DownloadOptions) -> String {
+        options
+            .download_dir
+            .clone()
+            .or_else(|| self.config.download_path.clone())
+            .unwrap_or_else(|| ".".to_string())
+    }
+
+    pub fn download_song(&self, url: &str, options: DownloadOptions) -> anyhow::Result<DownloadedSong> {
+        if !options.retry_drifted {
+            return self.download_once(url, options);
+        }
+        let downloaded = self.download_once(url, options.clone())?;
+        let drifted = AudioProcessor::drifted_downloads(Path::new(&self.download_path(&options)), &options.click)?;
+        if drifted.is_empty() {
+            return Ok(downloaded);
+        }
+        tracing::warn!("Downloading {} time-drifted stems of {} again", drifted.len(), url);
+        for path in &drifted {
+            fs::remove_file(path)?;
+        }
+        // The stems already on disk are skipped, so only the drifted ones are fetched
+        let again = self.download_once(url, options)?;
+        Ok(DownloadedSong {
+            track_names: downloaded.track_names,
+            mixer_rerenders: downloaded.mixer_rerenders.into_iter().chain(again.mixer_rerenders).collect(),
+            page: downloaded.page,
+        })
+    }
+
+    /// Downloads `url` like [`Driver::download_song`], for a batch that can sign in again.
+    /// Every song looks unbought, or not like a song, once the session has ended, so those
+    /// failures only stand with the session confirmed valid; otherwise the driver signs in
+    /// afresh and the song is tried once more.
+    pub fn download_song_in_session(
+        &self,
+        url: &str,
+        options: DownloadOptions,
+        user: &str,
+        pass: &str,
+    ) -> anyhow::Result<DownloadedSong> {
+        let error = match self.download_song(url, options.clone()) {
+            Err(e) if matches!(e.downcast_ref(), Some(DownloadError::NotPurchased | DownloadError::NotASongPage)) => e,
+            downloaded => return downloaded,
+        };
+        match self.session_valid() {
+            Ok(true) => return Err(error),
+            Ok(false) => tracing::warn!("{} failed with \"{}\" and the session has ended; signing in again", url, error),
+            Err(e) => tracing::warn!("Unable to check the session after \"{}\": {}; signing in again", error, e),
+        }
+        self.renew_session(user, pass)?;
+        self.download_song(url, options)
+    }
+
+    fn download_once(&self, url: &str, options: DownloadOptions) -> anyhow::Result<DownloadedSong> {
         // Create a fresh tab for this download.
-        let tab = self.browser.new_tab()?;
-        tab.set_default_timeout(std::time::Duration::from_secs(3600));
+        let raw_tab = self.new_tab()?;
+        let tab = TracedTab::new(&raw_tab, options.trace.clone());
+        if let Err(e) = tab.follow_events() {
+            tracing::warn!("Unable to trace the tab's events: {}", e);
+        }
 
+        let download_path = self.download_path(&options);
+        let transfers = Transfers::default();
+        let started = SystemTime::now();
+        if let Err(e) = transfers.listen(&tab, &download_path) {
+            tracing::warn!("Download events unavailable, an aborted song can't cancel its downloads: {}", e);
+        }
+
+        let debug_capture = options.debug_capture.clone();
+        match self.download_in_tab(&tab, &transfers, url, options, &download_path) {
+            Ok(downloaded) => {
+                // Close the temporary tab to free resources.
+                tab.close(true)?;
+                Ok(downloaded)
+            }
+            Err(e) => {
+                let e = match &debug_capture {
+                    Some(capture) if !self.abort.is_requested() => Self::capture_failure(capture, &tab, url, e),
+                    _ => e,
+                };
+                let timed_out = matches!(e.downcast_ref::<DownloadError>(), Some(DownloadError::DownloadTimeout));
+                // Only an aborted song's transfers are cut short and its partial files removed;
+                // a song that failed otherwise leaves what arrived for the next run
+                if self.abort.is_requested() || timed_out {
+                    if let Err(cleanup) = self.abort_downloads(&tab, &transfers, &download_path, started) {
+                        tracing::warn!("Unable to clean up after {}: {}", url, cleanup);
+                    }
+                }
+                let _ = tab.close(true);
+                if timed_out {
+                    Err(anyhow!(DownloadError::Cancelled(e.to_string())))
+                } else if self.abort.is_requested() && !DownloadError::is_cancelled(&e) {
+                    // Such as Chrome quitting on the same Ctrl+C
+                    Err(anyhow!(DownloadError::Cancelled(format!("interrupted: {}", e))))
+                } else {
+                    Err(e)
+                }
+            }
+        }
+    }
+
+    /// Captures the page `error` left the tab on, and names the capture in the error; what
+    /// the error is, such as a [`DownloadError`], is kept.
+    fn capture_failure(capture: &DebugCapture, tab: &Tab, url: &str, error: anyhow::Error) -> anyhow::Error {
+        match capture.capture(tab, url) {
+            Ok(dir) => {
+                let message = format!("{}; the page was captured in {:?}", error, dir);
+                error.context(message)
+            }
+            Err(e) => {
+                tracing::warn!("Unable to capture the page of the failed download: {}", e);
+                error
+            }
+        }
+    }
+
+    fn download_in_tab(
+        &self,
+        tab: &TracedTab,
+        transfers: &Transfers,
+        url: &str,
+        options: DownloadOptions,
+        download_path: &str,
+    ) -> Result<DownloadedSong> {
         tracing::debug!("Navigating to URL: {}", url);
-        tab.navigate_to(url)?.wait_until_navigated()?;
+        Retries::default().run(url, || self.wait_until_loaded(tab.navigate_to(url)?))?;
+        if self.pass_challenge(tab)? {
+            // Solving it may have ended the session, or left Chrome on another page
+            if !self.validate_session(tab) {
+                return Err(anyhow!("The session ended while the site checked the browser; run again to sign in"));
+            }
+            Retries::default().run(url, || self.wait_until_loaded(tab.navigate_to(url)?))?;
+        }
 
         // Wait for mixer to be present instead of arbitrary sleep
-        if let Err(_) = tab.wait_for_element_with_custom_timeout(".mixer", Duration::from_secs(10)) {
+        if tab.wait_for_element_with_custom_timeout(".mixer", self.config.timeouts.element).is_err() {
              tracing::warn!("Mixer element not found immediately, page might be slow.");
         }
 
         // Validate that we are on a song page.
-        if !self.is_a_song_page(&tab) {
+        if !self.is_a_song_page(tab) {
             return Err(anyhow::anyhow!(DownloadError::NotASongPage));
         }
 
         // Check if the track is downloadable (i.e. it has been purchased).
-        if !self.is_downloadable(&tab) {
+        if !self.is_downloadable(tab) {
             return Err(anyhow::anyhow!(DownloadError::NotPurchased));
         }
 
         tracing::debug!("Adjusting pitch if needed");
-        self.adjust_pitch(options.transpose, &tab)?;
+        self.adjust_pitch(options.transpose, tab)?;
+
+        // Read while the page is open, so processing needn't fetch it again
+        let page = match tab.get_content() {
+            Ok(html) => Some(SongInfo {
+                transpose: Some(options.transpose),
+                count_in: Some(options.count_in),
+                ..SongInfo::from_html(url, &html)
+            }),
+            Err(e) => {
+                tracing::warn!("Unable to read the song's details from its page: {}", e);
+                None
+            }
+        };
 
         tracing::debug!("Extracting track names");
-        let track_names = Self::extract_track_names(&tab)?;
+        let tracks = Self::read_tracks(tab)?;
+        let track_names: Vec<String> = tracks.iter().map(|t| t.name.clone()).collect();
+
+        let local_names = local_track_names(Path::new(download_path));
+        let mut decisions = plan_track_downloads(&tracks, &local_names, options.trust_site_state);
+        options.tracks.apply(&tracks, &mut decisions)?;
+        let present = decisions.iter().filter(|d| **d == StemDecision::SkipLocalFile).count();
+        if present > 0 {
+            tracing::info!(
+                "Resuming: {} of {} stems are already in {}",
+                present,
+                tracks.len(),
+                download_path
+            );
+        }
+        for (track, decision) in tracks.iter().zip(&decisions) {
+            match decision {
+                StemDecision::Download => tracing::info!("'{}': will download", track.name),
+                StemDecision::SkipLocalFile => tracing::info!("'{}': skipping, MP3 already present locally", track.name),
+                StemDecision::SkipSiteState => tracing::info!("'{}': skipping, site marks it as already downloaded", track.name),
+                StemDecision::SkipNotSelected => tracing::info!("'{}': skipping, not in --tracks", track.name),
+            }
+        }
+
+        if options.full_mix != FullMix::Off {
+            if local_names.iter().any(|name| name == FULL_MIX) {
+                tracing::info!("'{}': skipping, MP3 already present locally", FULL_MIX);
+            } else {
+                self.download_full_mix(tab, transfers, url, &options)?;
+            }
+        }
+        if options.full_mix == FullMix::Only {
+            return Ok(DownloadedSong {
+                page,
+                ..DownloadedSong::default()
+            });
+        }
 
         tracing::debug!("Beginning download process for {} tracks", track_names.len());
-        self.solo_and_download_tracks(&tab, &track_names, options.count_in)?;
+        let mixer_rerenders = self.solo_and_download_tracks(tab, transfers, &track_names, &decisions, &options)?;
 
         // Instead of immediately erroring out if the tab is unresponsive,
         // log a warning and continue.
@@ -74,14 +807,54 @@ impl Driver {
             Err(e) => tracing::warn!("Post-download evaluation failed: {}", e),
         }
 
-        // Close the temporary tab to free resources.
-        tab.close(true)?;
-
-        Ok(track_names)
+        // Processing shouldn't look for stems that were filtered out or left on the site
+        Ok(DownloadedSong {
+            track_names: expected_stems(&track_names, &decisions),
+            mixer_rerenders,
+            page,
+        })
     }
 
+    /// Cancels the downloads `transfers` still has in flight, gives Chrome a moment to
+    /// confirm, and removes the partial files written to `download_path` since `since`, so
+    /// nothing from an aborted song lands in the next one's directory diff. Returns the
+    /// files it removed.
+    pub fn abort_downloads(
+        &self,
+        tab: &Tab,
+        transfers: &Transfers,
+        download_path: &str,
+        since: SystemTime,
+    ) -> Result<Vec<PathBuf>> {
+        let in_flight = transfers.in_flight();
+        let mut chrome_answered = false;
+        for transfer in &in_flight {
+            tracing::info!("Cancelling download of {:?}", transfer.suggested_filename);
+            match tab.call_method(CancelDownload {
+                guid: transfer.guid.clone(),
+                browser_context_id: None,
+            }) {
+                Ok(_) => chrome_answered = true,
+                Err(e) => tracing::warn!("Unable to cancel download {}: {}", transfer.guid, e),
+            }
+        }
 
-    fn click_reset_button(&self, tab: &Tab) -> Result<()> {
+        let guids: Vec<String> = in_flight.iter().map(|t| t.guid.clone()).collect();
+        // Chrome gets the terminal's Ctrl+C too and may be gone; its partial files are
+        // removed all the same
+        if chrome_answered && !transfers.wait_until_settled(&guids, CANCEL_CONFIRMATION) {
+            tracing::warn!("Chrome didn't confirm cancelling {} download(s)", guids.len());
+        }
+        // A download that completed in the meantime is left for the next run to pick up
+        let canceled: Vec<Transfer> = guids
+            .iter()
+            .filter_map(|guid| transfers.get(guid))
+            .filter(|t| t.state != TransferState::Completed)
+            .collect();
+        remove_partials(Path::new(download_path), &canceled, since)
+    }
+
+    fn click_reset_button(&self, tab: &TracedTab) -> Result<()> {
         let reset_button = tab.wait_for_element(".mixer__reset")
             .map_err(|_| anyhow!(DownloadError::ResetButtonNotFound))?;
 
@@ -99,13 +872,19 @@ impl Driver {
     }
 
 
-    fn solo_and_download_tracks(&self, tab: &Tab, track_names: &[String], count_in: bool) -> Result<()> {
-        let solo_button_sel = ".track__controls.track__solo";
+    /// Solos and downloads each track `decisions` keeps. Returns the times the mixer was
+    /// found re-rendered along the way.
+    fn solo_and_download_tracks(
+        &self,
+        tab: &TracedTab,
+        transfers: &Transfers,
+        track_names: &[String],
+        decisions: &[StemDecision],
+        options: &DownloadOptions,
+    ) -> Result<Vec<MixerRerender>> {
         // Ensure buttons are loaded
-        tab.wait_for_element(solo_button_sel)?;
-
-        let solo_buttons = tab.find_elements(solo_button_sel)?;
-        let download_button = tab.find_element("a.download")?;
+        tab.wait_for_element(SOLO_BUTTON)?;
+        let download_path = self.download_path(options);
 
         // Click the reset button before processing tracks to ensure clean state
         self.click_reset_button(tab)?;
@@ -114,64 +893,42 @@ impl Driver {
         let mut current_count_in_state = self.is_count_in_enabled(tab)?;
         tracing::info!("Initial count-in state: {}", if current_count_in_state { "Enabled" } else { "Disabled" });
 
-        // Get download path from config
-        let download_path = self.config.download_path.clone()
-            .unwrap_or_else(|| ".".to_string());
-
-        for (index, solo_btn) in solo_buttons.iter().enumerate() {
-            let track_name = &track_names[index];
-
-            tracing::info!("Processing track {} '{}'", index + 1, track_name);
-            solo_btn.scroll_into_view()?;
-
-            // Click and wait for active state
-            solo_btn.click()?;
-            self.wait_for_solo_active(tab, index)?;
-
-            // Handle count-in toggle
-            // We use a shorter timeout for the element check since it should be there
-            if let Ok(count_in_toggle) = tab.wait_for_element_with_custom_timeout("input#precount", Duration::from_secs(5)) {
-                if index == 0 {
-                    // For the first track (click track)
-                    if count_in && !current_count_in_state {
-                        tracing::info!("Enabling count-in for the first track");
-                        count_in_toggle.click()?;
-                        self.wait_for_count_in_state(tab, true)?;
-                        current_count_in_state = true;
-                    } else if !count_in && current_count_in_state {
-                        tracing::info!("Disabling count-in for the first track");
-                        count_in_toggle.click()?;
-                        self.wait_for_count_in_state(tab, false)?;
-                        current_count_in_state = false;
-                    }
-                } else {
-                    // For subsequent tracks
-                    if current_count_in_state {
-                        tracing::info!("Disabling count-in for track: {}", track_name);
-                        count_in_toggle.click()?;
-                        self.wait_for_count_in_state(tab, false)?;
-                        current_count_in_state = false;
-                    }
-                }
+        // The captions next to the solo buttons as last seen, to notice the site re-rendering
+        // the mixer and moving the tracks under the buttons
+        let mut mixer = Self::solo_captions(tab)?;
+        let mut rerenders = Vec::new();
+        let to_download = (0..track_names.len())
+            .filter(|&index| decisions.get(index).is_none_or(|d| d.needs_download()))
+            .count();
+        let mut downloading = 0;
+
+        for (index, track_name) in track_names.iter().enumerate() {
+            if decisions.get(index).is_some_and(|d| !d.needs_download()) {
+                continue;
+            }
+            let occurrence = track_names[..index].iter().filter(|name| *name == track_name).count();
+            if downloading > 0 && !options.track_delay.is_zero() {
+                sleep(options.track_delay.next());
             }
 
-            // Download the track
-            tracing::info!("- starting download...");
-            download_button.scroll_into_view()?;
-            download_button.click()?;
-
-            // Wait for download to complete by watching file system
-            match self.wait_for_download(&download_path, Duration::from_secs(30)) {
-                Ok(filename) => tracing::info!("- '{}' downloaded successfully as {}", track_name, filename),
-                Err(e) => {
-                    tracing::error!("- download failed for '{}': {}", track_name, e);
-                    // Try to recover by closing modal if it exists
-                     if let Ok(close_btn) = tab.find_element("button.js-modal-close") {
-                        let _ = close_btn.click();
-                    }
-                    return Err(e);
-                }
+            tracing::info!("Processing track {} '{}' ({} of {} to download)", index + 1, track_name, downloading + 1, to_download);
+            if let Some(progress) = &options.progress {
+                progress.start_track(downloading, to_download, track_name);
             }
+            downloading += 1;
+            self.download_with_retries(tab, transfers, track_name, options, || {
+                // The elements are looked up again every time, as a re-render replaces them
+                let at = self.locate_solo_button(tab, track_name, occurrence, &mut mixer, &mut rerenders)?;
+                let solo_btn = Self::solo_button(tab, at)
+                    .map_err(|_| anyhow!("No solo button for '{}'", track_name))?;
+                let download_button = tab.find_element("a.download")?;
+
+                let pending = self.prepare_download(&download_path, transfers, Some(track_name))?;
+                // Only the first track (the click) gets the count-in
+                let count_in = index == 0 && options.count_in;
+                self.start_track_download(tab, &solo_btn, &download_button, at, count_in, &mut current_count_in_state)?;
+                self.wait_for_download(tab, pending, transfers, &options.wait)
+            })?;
 
             // Handle the "Begin Download" modal if it appears and stays (sometimes it auto-closes, sometimes not?)
             // If the download started, the modal might still be there.
@@ -184,41 +941,283 @@ impl Driver {
             }
         }
 
+        let expected = expected_stems(track_names, decisions);
         tracing::info!(
             "Done! Check your download folder to make sure you have all of these tracks: {:?}\n - ",
-            track_names.join("\n - ")
+            expected.join("\n - ")
         );
 
+        Ok(rerenders)
+    }
+
+    /// The caption of each mixer row, in row order; empty for a row without a solo button,
+    /// so it's never the one located.
+    fn solo_captions(tab: &TracedTab) -> Result<Vec<String>> {
+        let js = r#"
+            (function() {
+                return JSON.stringify(Array.from(document.querySelectorAll('.mixer .track')).map(function(row) {
+                    if (!row.querySelector('.track__controls.track__solo')) return '';
+                    let caption = row.querySelector('.track__caption');
+                    let text = caption && caption.lastChild ? caption.lastChild.nodeValue || '' : '';
+                    return text.replace(/\s+/g, ' ').trim();
+                }));
+            })()
+        "#;
+        let result = tab.evaluate(js, true)?;
+        let json = result
+            .value
+            .and_then(|v| v.as_str().map(String::from))
+            .ok_or_else(|| anyhow!("Unable to read the solo buttons' tracks"))?;
+        Ok(serde_json::from_str(&json)?)
+    }
+
+    /// Index of the row of `track_name` (its `occurrence`-th row with that name),
+    /// checked against the captions the mixer shows right now. When they differ from
+    /// `mixer`, the site re-rendered it: the re-render is added to `rerenders` and `mixer`
+    /// becomes the new pairing.
+    fn locate_solo_button(
+        &self,
+        tab: &TracedTab,
+        track_name: &str,
+        occurrence: usize,
+        mixer: &mut Vec<String>,
+        rerenders: &mut Vec<MixerRerender>,
+    ) -> Result<usize> {
+        let captions = Self::solo_captions(tab)?;
+        let at = locate_track(&captions, track_name, occurrence);
+        if captions != *mixer {
+            let rerender = MixerRerender {
+                track_name: track_name.to_string(),
+                before: locate_track(mixer, track_name, occurrence),
+                after: at,
+            };
+            tracing::warn!("The mixer was re-rendered: {}", rerender);
+            rerenders.push(rerender);
+            *mixer = captions;
+        }
+        at.ok_or_else(|| anyhow!("'{}' is no longer in the mixer", track_name))
+    }
+
+    /// Downloads the mixer with nothing soloed, before any stem, and saves it as
+    /// `<Song> (Full Mix).mp3`. It gets the count-in whenever the click does.
+    fn download_full_mix(&self, tab: &TracedTab, transfers: &Transfers, url: &str, options: &DownloadOptions) -> Result<()> {
+        let download_button = tab.find_element("a.download")?;
+        let mut current_count_in_state = self.is_count_in_enabled(tab)?;
+        let download_path = self.download_path(options);
+
+        tracing::info!("Processing the full mix");
+        let filename = self.download_with_retries(tab, transfers, FULL_MIX, options, || {
+            // A failed attempt may have left a track soloed
+            self.click_reset_button(tab)?;
+            self.set_count_in(tab, options.count_in, &mut current_count_in_state)?;
+            tracing::info!("- starting download...");
+            let pending = self.prepare_download(&download_path, transfers, None)?;
+            download_button.scroll_into_view()?;
+            download_button.click()?;
+            self.wait_for_download(tab, pending, transfers, &options.wait)
+        })?;
+
+        let song = title::from_url(url).unwrap_or_else(|| "Song".to_string());
+        let target = Path::new(&download_path).join(format!("{} ({}).mp3", song, FULL_MIX));
+        fs::rename(Path::new(&download_path).join(&filename), &target)
+            .map_err(|e| anyhow!("Unable to rename the full mix {} to {:?}: {}", filename, target, e))?;
+        if let Ok(close_btn) = tab.find_element("button.js-modal-close") {
+            let _ = close_btn.click();
+            sleep(Duration::from_millis(500));
+        }
         Ok(())
     }
 
-    fn wait_for_solo_active(&self, tab: &Tab, index: usize) -> Result<()> {
-        let start = Instant::now();
-        let timeout = Duration::from_secs(10);
+    /// Runs `download` until it succeeds, up to `options.track_retries` more times, waiting
+    /// [`retry_delay`] between attempts and removing what a failed one left behind.
+    /// Returns the downloaded file's name.
+    fn download_with_retries(
+        &self,
+        tab: &TracedTab,
+        transfers: &Transfers,
+        track_name: &str,
+        options: &DownloadOptions,
+        mut download: impl FnMut() -> Result<String>,
+    ) -> Result<String> {
+        let download_path = self.download_path(options);
+        let attempts = options.track_retries + 1;
+        let mut attempt = 1;
+        loop {
+            if self.abort.is_requested() {
+                return Err(anyhow!(DownloadError::Cancelled("interrupted".to_string())));
+            }
+            let attempt_started = SystemTime::now();
+            let e = match download() {
+                Ok(filename) => {
+                    tracing::info!("- '{}' downloaded successfully as {}", track_name, filename);
+                    return Ok(filename);
+                }
+                Err(e) => e,
+            };
+
+            // Try to recover by closing modal if it exists
+            if let Ok(close_btn) = tab.find_element("button.js-modal-close") {
+                let _ = close_btn.click();
+            }
+            if DownloadError::is_cancelled(&e) || attempt >= attempts {
+                tracing::error!("- download failed for '{}' after {} attempt(s): {}", track_name, attempt, e);
+                return Err(e);
+            }
+
+            tracing::warn!("- attempt {} of {} failed for '{}': {}", attempt, attempts, track_name, e);
+            // A half-written file would be taken for this track's download next time
+            match self.abort_downloads(tab, transfers, &download_path, attempt_started) {
+                Ok(removed) if !removed.is_empty() => {
+                    tracing::info!("- removed {} partial file(s) of '{}'", removed.len(), track_name)
+                }
+                Ok(_) => {}
+                Err(cleanup) => tracing::warn!("- unable to clean up after '{}': {}", track_name, cleanup),
+            }
+            let delay = retry_delay(attempt);
+            attempt += 1;
+            tracing::info!(
+                "- retrying '{}' in {}s (attempt {} of {})",
+                track_name,
+                delay.as_secs(),
+                attempt,
+                attempts
+            );
+            sleep(delay);
+        }
+    }
+
+    /// Starts one attempt at the track whose solo button is `solo_btn`, in the `index`-th row: solos
+    /// it unless it already is, turns the count-in on or off and clicks download.
+    fn start_track_download(
+        &self,
+        tab: &TracedTab,
+        solo_btn: &Element,
+        download_button: &Element,
+        index: usize,
+        count_in: bool,
+        current_count_in_state: &mut bool,
+    ) -> Result<()> {
+        // A retry may find the track still soloed, and a click would undo that
+        if !self.is_solo_active(tab, index)? {
+            solo_btn.scroll_into_view()?;
+            solo_btn.click()?;
+        }
+        // The previous track's solo may still be on, and it would bleed into this stem
+        let path = self.ensure_exclusive_solo(tab, index)?;
+        tracing::info!("- solo verified: {}", path);
+
+        self.set_count_in(tab, count_in, current_count_in_state)?;
 
+        // Download the track
+        tracing::info!("- starting download...");
+        download_button.scroll_into_view()?;
+        download_button.click()?;
+        Ok(())
+    }
+
+    /// Turns the count-in on or off unless `current_state` says it already is.
+    fn set_count_in(&self, tab: &TracedTab, wanted: bool, current_state: &mut bool) -> Result<()> {
+        // We use a shorter timeout for the element check since it should be there
+        if let Ok(count_in_toggle) = tab.wait_for_element_with_custom_timeout("input#precount", self.config.timeouts.element) {
+            if wanted != *current_state {
+                tracing::info!("{} count-in", if wanted { "Enabling" } else { "Disabling" });
+                count_in_toggle.click()?;
+                self.wait_for_count_in_state(tab, wanted)?;
+                *current_state = wanted;
+            }
+        }
+        Ok(())
+    }
+
+    fn is_solo_active(&self, tab: &TracedTab, index: usize) -> Result<bool> {
         let js = format!(
             r#"
             (function() {{
-                let btns = document.querySelectorAll('.track__controls.track__solo');
-                if (btns.length <= {}) return false;
-                return btns[{}].classList.contains('is-active');
+                let row = document.querySelectorAll('.mixer .track')[{}];
+                let btn = row && row.querySelector('.track__controls.track__solo');
+                return !!btn && btn.classList.contains('is-active');
             }})()
             "#,
-            index, index
+            index
         );
+        let result = tab.evaluate(&js, true)?;
+        Ok(result.value.and_then(|v| v.as_bool()) == Some(true))
+    }
 
-        while start.elapsed() < timeout {
-            let result = tab.evaluate(&js, true)?;
-            if let Some(true) = result.value.and_then(|v| v.as_bool()) {
-                return Ok(());
-            }
-            sleep(Duration::from_millis(100));
+    /// The solo button of the `row`-th mixer row.
+    fn solo_button<'t>(tab: &TracedTab<'t>, row: usize) -> Result<Element<'t>> {
+        let row = tab
+            .find_elements(MIXER_ROW)?
+            .into_iter()
+            .nth(row)
+            .ok_or_else(|| anyhow!("No mixer row {}", row))?;
+        row.find_element(SOLO_BUTTON)
+    }
+
+    /// Indices of the rows whose solo button is active.
+    fn active_solos(&self, tab: &TracedTab) -> Result<Vec<usize>> {
+        let js = r#"
+            (function() {
+                let active = [];
+                document.querySelectorAll('.mixer .track').forEach(function(row, index) {
+                    let btn = row.querySelector('.track__controls.track__solo');
+                    if (btn && btn.classList.contains('is-active')) active.push(index);
+                });
+                return JSON.stringify(active);
+            })()
+        "#;
+        let result = tab.evaluate(js, true)?;
+        let json = result
+            .value
+            .and_then(|v| v.as_str().map(String::from))
+            .ok_or_else(|| anyhow!("Unable to read the solo buttons"))?;
+        Ok(serde_json::from_str(&json)?)
+    }
+
+    /// Waits until the `index`-th row is the only one soloed, clicking off any other solo
+    /// still on. When that doesn't settle within [`SOLO_TIMEOUT`], the mixer is reset and
+    /// the row soloed again.
+    fn ensure_exclusive_solo(&self, tab: &TracedTab, index: usize) -> Result<SoloPath> {
+        let mut unsoloed: Vec<usize> = Vec::new();
+        if self.settle_solo(tab, index, &mut unsoloed)? {
+            return Ok(if unsoloed.is_empty() { SoloPath::Exclusive } else { SoloPath::UnsoloedStale(unsoloed) });
         }
 
-        Err(anyhow!("Timed out waiting for solo button {} to become active", index))
+        tracing::warn!("Row {} isn't the only one soloed after {:?}; resetting the mixer", index, SOLO_TIMEOUT);
+        self.click_reset_button(tab)?;
+        let solo_btn = Self::solo_button(tab, index)?;
+        solo_btn.scroll_into_view()?;
+        solo_btn.click()?;
+        if self.settle_solo(tab, index, &mut unsoloed)? {
+            return Ok(SoloPath::Reset);
+        }
+        Err(anyhow!("Unable to solo row {} on its own, even after resetting the mixer", index))
     }
 
-    fn wait_for_count_in_state(&self, tab: &Tab, expected_checked: bool) -> Result<()> {
+    /// Polls the solo buttons for up to [`SOLO_TIMEOUT`], clicking off the stale ones, until
+    /// the `index`-th row is soloed on its own. The rows clicked off are added to `unsoloed`.
+    fn settle_solo(&self, tab: &TracedTab, index: usize, unsoloed: &mut Vec<usize>) -> Result<bool> {
+        let start = Instant::now();
+        while start.elapsed() < SOLO_TIMEOUT {
+            match solo_state(&self.active_solos(tab)?, index) {
+                SoloState::Exclusive => return Ok(true),
+                SoloState::Stale(rows) => {
+                    for row in rows {
+                        tracing::warn!("Row {} is still soloed along with row {}; unsoloing it", row, index);
+                        Self::solo_button(tab, row)?.click()?;
+                        if !unsoloed.contains(&row) {
+                            unsoloed.push(row);
+                        }
+                    }
+                    sleep(SOLO_SETTLE);
+                }
+                SoloState::Missing => sleep(Duration::from_millis(100)),
+            }
+        }
+        Ok(false)
+    }
+
+    fn wait_for_count_in_state(&self, tab: &TracedTab, expected_checked: bool) -> Result<()> {
         let start = Instant::now();
         let timeout = Duration::from_secs(5);
 
@@ -233,76 +1232,227 @@ impl Driver {
         Err(anyhow!("Timed out waiting for count-in state to become {}", expected_checked))
     }
 
-    fn wait_for_download(&self, download_path: &str, timeout: Duration) -> Result<String> {
-        let start = Instant::now();
-        let path = Path::new(download_path);
-
-        // Take a snapshot of existing files to identify the new one
-        let initial_files: Vec<String> = fs::read_dir(path)?
-            .filter_map(|e| e.ok())
-            .map(|e| e.path().to_string_lossy().into_owned())
-            .collect();
+    /// Notes what's in `download_path` and which downloads Chrome knows of, before the
+    /// click that starts the download of `track_name` (or of the full mix).
+    fn prepare_download(&self, download_path: &str, transfers: &Transfers, track_name: Option<&str>) -> Result<PendingDownload> {
+        let dir = PathBuf::from(download_path);
+        let existing = fs::read_dir(&dir)?.filter_map(|e| e.ok()).map(|e| e.path()).collect();
+        Ok(PendingDownload {
+            dir,
+            existing,
+            known_transfers: transfers.guids(),
+            expected: ExpectedDownload {
+                track_name: track_name.map(String::from),
+                suggested_filename: None,
+            },
+            transfer: None,
+        })
+    }
 
-        tracing::debug!("Waiting for new file in {:?}", path);
+    /// Waits for Chrome to report `pending`'s download completed, and returns the name of its
+    /// file. Other new files in the directory are left alone. While the site shows it's
+    /// generating the track, the wait goes on up to `wait.generation_timeout`; once Chrome is
+    /// receiving the file, only `wait.timeout` without a byte more times it out. A file that
+    /// shows up without Chrome's events is taken once it stops growing.
+    fn wait_for_download(
+        &self,
+        tab: &TracedTab,
+        mut pending: PendingDownload,
+        transfers: &Transfers,
+        wait: &DownloadWait,
+    ) -> Result<String> {
+        let mut clock = DownloadClock::new(wait, Instant::now());
+        let mut progress: Option<GenerationProgress> = None;
+        let mut ignored: HashSet<PathBuf> = HashSet::new();
+        let mut stall: Option<StallTimer> = None;
+        let mut file_seen: Option<Instant> = None;
+        tracing::debug!("Waiting for the download of {} in {:?}", pending.expected, pending.dir);
 
         loop {
-            if start.elapsed() > timeout {
-                return Err(anyhow!(DownloadError::DownloadTimeout));
+            if self.abort.is_requested() {
+                return Err(anyhow!(DownloadError::Cancelled("interrupted".to_string())));
             }
 
-            // Check for new files
-            if let Ok(entries) = fs::read_dir(path) {
-                for entry in entries.filter_map(|e| e.ok()) {
-                    let p = entry.path();
-                    let s = p.to_string_lossy().into_owned();
-
-                    if !initial_files.contains(&s) {
-                        // Found a new file!
-                        // Check if it's a temporary download file
-                        let extension = p.extension().and_then(|e| e.to_str()).unwrap_or("");
-                        if extension == "crdownload" || extension == "part" {
-                            tracing::debug!("Found temp file: {:?}", p);
-                            sleep(Duration::from_millis(500));
-                            continue;
-                        }
-
-                        // It seems to be a final file.
-                        // Let's verify size is stable (download finished)
-                        if self.is_file_stable(&p)? {
-                            tracing::info!("Download detected: {:?}", p);
-                            return Ok(p.file_name().unwrap().to_string_lossy().into_owned());
-                        }
+            // Chrome names the file when the download begins, which pins down the one to wait for
+            if pending.transfer.is_none() {
+                if let Some(transfer) = transfers.begun_since(&pending.known_transfers).into_iter().next() {
+                    tracing::debug!("Chrome is downloading {} as {:?}", pending.expected, transfer.suggested_filename);
+                    pending.expected.suggested_filename = Some(transfer.suggested_filename);
+                    pending.transfer = Some(transfer.guid);
+                }
+            }
+            let transfer = pending.transfer.as_deref().and_then(|guid| transfers.get(guid));
+
+            let new_files: Vec<(PathBuf, SystemTime)> = fs::read_dir(&pending.dir)
+                .map(|entries| {
+                    entries
+                        .filter_map(|e| e.ok())
+                        .map(|e| e.path())
+                        .filter(|p| !pending.existing.contains(p))
+                        .filter_map(|p| {
+                            let modified = fs::metadata(&p).and_then(|m| m.modified()).ok()?;
+                            Some((p, modified))
+                        })
+                        .collect()
+                })
+                .unwrap_or_default();
+
+            let picked = pending.expected.pick(&new_files);
+            match &transfer {
+                Some(transfer) if transfer.state == TransferState::Completed => {
+                    // Chrome renames the file into place before it reports the download done
+                    if let Some(p) = &picked {
+                        tracing::info!("Download detected: {:?} ({} bytes)", p, transfer.received_bytes);
+                        return Ok(p.file_name().unwrap().to_string_lossy().into_owned());
                     }
                 }
+                Some(transfer) if transfer.state == TransferState::Canceled => {
+                    return Err(anyhow!(DownloadError::Cancelled(format!("the download of {} was canceled", pending.expected))));
+                }
+                _ => {}
+            }
+            let in_progress = transfer.as_ref().filter(|t| t.state == TransferState::InProgress).map(|t| t.received_bytes);
+            let receiving = picked.is_some() || transfer.is_some();
+            // The page is only looked at until the file comes
+            let generating = if receiving { None } else { Self::read_generation_progress(tab) };
+            if generating.is_some() && generating != progress {
+                match generating.as_ref().and_then(|g| g.percent) {
+                    Some(percent) => tracing::info!("- the site is generating {} ({:.0}%)", pending.expected, percent),
+                    None => tracing::info!("- the site is generating {}", pending.expected),
+                }
+            }
+            progress = generating;
+            let phase_before = clock.phase();
+            let phase = match clock.observe(progress.is_some(), receiving, Instant::now()) {
+                Ok(phase) => phase,
+                // A file Chrome reports coming in is timed by its progress instead
+                Err(_) if in_progress.is_some() => DownloadPhase::Receiving,
+                Err(e) => return Err(anyhow!(e)),
+            };
+            if phase_before == DownloadPhase::Generating && phase == DownloadPhase::Requested {
+                tracing::info!("- {} was generated, waiting for its download", pending.expected);
+            }
+            if let Some(bytes) = in_progress {
+                if stall.get_or_insert_with(|| StallTimer::new(wait.timeout, Instant::now())).observe(bytes, Instant::now()) {
+                    return Err(anyhow!(DownloadError::DownloadTimeout));
+                }
             }
 
-            sleep(Duration::from_millis(500));
+            if let Some(p) = &picked {
+                let seen = *file_seen.get_or_insert_with(Instant::now);
+                if transfer.is_none() && seen.elapsed() >= DOWNLOAD_EVENT_GRACE {
+                    // Follow it until it stops growing rather than going back to scanning the
+                    // whole directory
+                    tracing::debug!("No download events for {:?}, watching its size instead", p);
+                    self.wait_until_stable(p, wait, clock.since())?;
+                    tracing::info!("Download detected: {:?}", p);
+                    return Ok(p.file_name().unwrap().to_string_lossy().into_owned());
+                }
+            }
+            for (p, _) in new_files {
+                if Some(&p) != picked.as_ref() && ignored.insert(p.clone()) {
+                    tracing::debug!("Ignoring {:?}, it isn't the download of {}", p, pending.expected);
+                }
+            }
+
+            transfers.wait_for_change(wait.stability_interval);
         }
     }
 
-    fn is_file_stable(&self, path: &Path) -> Result<bool> {
-        // Check if file size remains constant for a short period
-        let meta1 = fs::metadata(path)?;
-        let size1 = meta1.len();
-
-        sleep(Duration::from_millis(500));
+    /// The site's track-generation progress, if it shows. A page that can't be read counts
+    /// as showing none.
+    pub fn generation_progress(tab: &Tab) -> Option<GenerationProgress> {
+        Self::read_generation_progress(&TracedTab::plain(tab))
+    }
 
-        let meta2 = fs::metadata(path)?;
-        let size2 = meta2.len();
+    fn read_generation_progress(tab: &TracedTab) -> Option<GenerationProgress> {
+        let js = format!(
+            r#"
+            (function() {{
+                let shown = el => el.getClientRects().length > 0 && getComputedStyle(el).visibility !== 'hidden';
+                let el = Array.from(document.querySelectorAll('{}')).find(shown);
+                if (!el) return null;
+                let percent = null;
+                let bar = el.querySelector('progress, [role="progressbar"], .progress__bar, .progress-bar');
+                if (bar && bar.tagName === 'PROGRESS' && bar.max) {{
+                    percent = bar.value * 100 / bar.max;
+                }} else if (bar && bar.getAttribute('aria-valuenow')) {{
+                    percent = parseFloat(bar.getAttribute('aria-valuenow'));
+                }} else if (bar && bar.style.width.endsWith('%')) {{
+                    percent = parseFloat(bar.style.width);
+                }}
+                if (percent === null || isNaN(percent)) {{
+                    let match = el.textContent.match(/(\d{{1,3}})\s*%/);
+                    percent = match ? parseFloat(match[1]) : null;
+                }}
+                return JSON.stringify({{ percent: percent }});
+            }})()
+            "#,
+            GENERATION_PROGRESS
+        );
+        let json = tab.evaluate(&js, true).ok()?.value?.as_str()?.to_string();
+        serde_json::from_str(&json).ok()
+    }
 
-        if size1 == size2 && size1 > 0 {
-             Ok(true)
-        } else {
-             Ok(false)
+    /// Waits until `path` is the same non-zero size twice in a row, `wait.stability_interval`
+    /// apart. Still growing counts as still downloading, up to `wait.timeout` after `start`.
+    fn wait_until_stable(&self, path: &Path, wait: &DownloadWait, start: Instant) -> Result<()> {
+        let mut size = fs::metadata(path)?.len();
+        loop {
+            sleep(wait.stability_interval);
+            if self.abort.is_requested() {
+                return Err(anyhow!(DownloadError::Cancelled("interrupted".to_string())));
+            }
+            let current = fs::metadata(path)?.len();
+            if current == size && current > 0 {
+                return Ok(());
+            }
+            if start.elapsed() > wait.timeout {
+                return Err(anyhow!(DownloadError::DownloadTimeout));
+            }
+            tracing::debug!("{:?} is still growing: {} bytes", path, current);
+            size = current;
         }
     }
 
-    fn is_count_in_enabled(&self, tab: &Tab) -> Result<bool> {
-        let count_in_toggle = tab.wait_for_element_with_custom_timeout("input#precount", Duration::from_secs(60))?;
+    fn is_count_in_enabled(&self, tab: &TracedTab) -> Result<bool> {
+        let count_in_toggle = tab.wait_for_element_with_custom_timeout("input#precount", self.config.timeouts.element)?;
         Ok(count_in_toggle.is_checked())
     }
 
 
+    /// Reads every mixer row's caption along with the site's per-track download indicator,
+    /// leaving out the rows [`pair_mixer_rows`] skips.
+    pub fn extract_tracks(tab: &Tab) -> Result<Vec<TrackInfo>> {
+        Self::read_tracks(&TracedTab::plain(tab))
+    }
+
+    fn read_tracks(tab: &TracedTab) -> Result<Vec<TrackInfo>> {
+        let js = r#"
+            (function() {
+                let rows = Array.from(document.querySelectorAll('.mixer .track'));
+                let hasIndicators = rows.some(row => row.querySelector('.track__download-status'));
+                return JSON.stringify(rows.map(function(row, index) {
+                    let caption = row.querySelector('.track__caption');
+                    let text = caption && caption.lastChild ? caption.lastChild.nodeValue || '' : '';
+                    let status = row.querySelector('.track__download-status');
+                    return {
+                        index: index,
+                        name: text.replace(/\s+/g, ' ').trim(),
+                        has_solo: !!row.querySelector('.track__controls.track__solo'),
+                        downloaded_on_site: hasIndicators ? !!(status && status.classList.contains('is-downloaded')) : null
+                    };
+                }));
+            })()
+        "#;
+        let result = tab.evaluate(js, true)?;
+        let json = result
+            .value
+            .and_then(|v| v.as_str().map(String::from))
+            .ok_or_else(|| anyhow!("Unable to read the mixer tracks"))?;
+        Ok(pair_mixer_rows(serde_json::from_str(&json)?))
+    }
+
     pub fn extract_track_names(tab: &Tab) -> Result<Vec<String>> {
         let track_names = tab.find_elements(".mixer .track .track__caption")?;
         let mut names: Vec<String> = vec![];
@@ -329,89 +1479,188 @@ impl Driver {
         Ok(names)
     }
 
-    fn is_a_song_page(&self, tab: &Tab) -> bool {
+    fn is_a_song_page(&self, tab: &TracedTab) -> bool {
         let has_mixer = tab.find_element("div.mixer").is_ok();
         let has_download_button = tab.find_element("a.download").is_ok();
         has_mixer && has_download_button
     }
 
-    fn is_downloadable(&self, tab: &Tab) -> bool {
+    fn is_downloadable(&self, tab: &TracedTab) -> bool {
         // if the download button also has the addtocart class, then this hasn't been purchased
         let el = tab.find_element("a.download.addtocart").ok();
         el.is_none()
     }
 
-    fn adjust_pitch(&self, desired_pitch: i8, tab: &Tab) -> Result<()> {
-        // pitch is remembered per-son on your account, so this logic cannot be deterministic. Instead
-        // we''l try to infer the direction we need to go based on what the pitch is currently set to.
-        let pitch_label = tab
-            .find_element("span.pitch__value")
-            .expect("can't find pitch value");
-        let pitch_up_btn = tab
-            .find_element("div.pitch button.btn--pitch[title='Key up' i]")
-            .expect("can't find pitch up button");
-        let pitch_down_btn = tab
-            .find_element("div.pitch button.btn--pitch[title='Key down' i]")
-            .expect("can't find pitch down button");
-
-        pitch_up_btn.focus()?;
-
-        let current_pitch: i8 = pitch_label.get_inner_text()?.parse()?;
-        let diff = desired_pitch - current_pitch;
-        if diff == 0 {
+    fn adjust_pitch(&self, desired_pitch: i8, tab: &TracedTab) -> Result<()> {
+        if !Self::step_pitch(tab, desired_pitch)? {
             return Ok(());
         }
-        tracing::info!(
-            "Setting pitch to {} (currently: {})",
-            desired_pitch,
-            current_pitch
-        );
 
-        let button = if diff > 0 {
-            pitch_up_btn
-        } else {
-            pitch_down_btn
-        };
+        // need to reload the song after pitching
+        tracing::info!("Reloading tracks after pitching...");
+        Self::mark_mixer_stale(tab)?;
+        tab.find_element("a#pitch-link")
+            .map_err(|_| anyhow!("No reload link next to the pitch buttons"))?
+            .click()?;
+        self.wait_for_mixer(tab, self.config.timeouts.navigation)
+    }
 
-        let mut iterations_allowed = 10;
-        loop {
-            assert!(
-                iterations_allowed > 0,
-                "failed to set pitch, breaking to avoid infinite loop"
-            );
-            iterations_allowed -= 1;
+    /// The pitch buttons and their value as the page shows them.
+    pub fn read_pitch(tab: &Tab) -> Result<PitchControl> {
+        let js = format!(
+            r#"
+            (function() {{
+                let pitch = document.querySelector('div.pitch');
+                let label = pitch && pitch.querySelector('span.pitch__value');
+                let up = pitch && pitch.querySelector("{}");
+                let down = pitch && pitch.querySelector("{}");
+                if (!label || !up || !down) return null;
+                let disabled = b => b.disabled || b.classList.contains('is-disabled') || b.classList.contains('disabled')
+                    || b.getAttribute('aria-disabled') === 'true';
+                let limit = (...names) => {{
+                    let value = parseInt(names.map(name => pitch.dataset[name]).find(v => v !== undefined));
+                    return isNaN(value) ? null : value;
+                }};
+                let current = parseInt(label.textContent.trim());
+                return JSON.stringify({{
+                    current: isNaN(current) ? null : current,
+                    min: limit('min', 'pitchMin'),
+                    max: limit('max', 'pitchMax'),
+                    up_disabled: disabled(up),
+                    down_disabled: disabled(down)
+                }});
+            }})()
+            "#,
+            PITCH_UP, PITCH_DOWN
+        );
+        let json = tab
+            .evaluate(&js, true)?
+            .value
+            .and_then(|v| v.as_str().map(String::from))
+            .ok_or_else(|| anyhow!("No pitch buttons on the page"))?;
+        serde_json::from_str(&json).map_err(|e| anyhow!("Unable to read the pitch: {}", e))
+    }
 
-            tracing::debug!("Pitching tracks...");
-            button.click().expect("couldn't click pitch button");
+    /// Clicks the pitch up or down until it reads `desired_pitch`, without reloading the
+    /// mixer. Returns whether it had to change. The pitch is remembered per song on the
+    /// account, so the direction is worked out from what it's currently at.
+    pub fn set_pitch(tab: &Tab, desired_pitch: i8) -> Result<bool> {
+        Self::step_pitch(&TracedTab::plain(tab), desired_pitch)
+    }
 
-            // Wait for pitch value to change
-            // We'll just wait a bit because pitch logic is tricky to verify quickly
-            // without knowing the exact step latency
-            sleep(Duration::from_millis(250));
+    fn step_pitch(tab: &TracedTab, desired_pitch: i8) -> Result<bool> {
+        let mut control = Self::read_pitch(tab)?;
+        control.check(desired_pitch)?;
+        if control.current == desired_pitch {
+            return Ok(false);
+        }
+        tracing::info!("Setting pitch to {} (currently: {})", desired_pitch, control.current);
 
-            let new_pitch: i8 = pitch_label.get_inner_text()?.parse()?;
-            tracing::debug!("Pitching is now {}, target: {}", new_pitch, desired_pitch);
+        let (selector, direction) = if desired_pitch > control.current { (PITCH_UP, "Key up") } else { (PITCH_DOWN, "Key down") };
+        while control.current != desired_pitch {
+            let before = control.current;
+            tracing::debug!("Pitching tracks...");
+            tab.find_element(selector)?.click()?;
+            control = Self::wait_for_pitch_change(tab, before)?;
+            tracing::debug!("Pitching is now {}, target: {}", control.current, desired_pitch);
+            // A button that went disabled shows where the song's range ends
+            control.check(desired_pitch)?;
+            if control.current == before {
+                return Err(anyhow!("The pitch stayed at {} after clicking {}", before, direction));
+            }
+            if (control.current > before) != (desired_pitch > before) {
+                return Err(anyhow!("Clicking {} moved the pitch from {} to {}", direction, before, control.current));
+            }
+        }
+        Ok(true)
+    }
 
-            if new_pitch == desired_pitch {
-                break;
+    /// Waits up to [`PITCH_STEP_TIMEOUT`] for the pitch to read something other than
+    /// `before`, and returns the pitch control as it is then.
+    fn wait_for_pitch_change(tab: &TracedTab, before: i8) -> Result<PitchControl> {
+        let start = Instant::now();
+        loop {
+            sleep(Duration::from_millis(100));
+            let control = Self::read_pitch(tab)?;
+            if control.current != before || start.elapsed() > PITCH_STEP_TIMEOUT {
+                return Ok(control);
             }
         }
+    }
 
-        // need to reload the song after pitching
-        tracing::info!("Reloading tracks after pitching...");
-        tab.find_element("a#pitch-link")
-            .expect("can't find pitch link")
-            .click()?;
+    /// Tags the mixer's rows, so [`Driver::wait_for_mixer`] can tell the rows rendered
+    /// since apart from them.
+    pub fn mark_mixer_stale(tab: &Tab) -> Result<()> {
+        let js = format!(
+            "document.querySelectorAll('{}').forEach(row => row.dataset.kvStale = '1'); true;",
+            MIXER_ROW
+        );
+        tab.evaluate(&js, false)?;
+        Ok(())
+    }
 
-        // Wait for mixer to reload instead of fixed sleep
-        // The mixer element might stay there, but we can wait for the URL to change or just wait a bit longer than usual
-        // Reloading usually takes a moment.
-        sleep(Duration::from_secs(4)); // Still keeping this sleep as page reload is hard to track perfectly without navigation events, and we are on same page
+    pub fn mixer_state(tab: &Tab) -> Result<MixerState> {
+        let js = format!(
+            r#"
+            (function() {{
+                let rows = Array.from(document.querySelectorAll('{}')).filter(row => row.querySelector('{}'));
+                return JSON.stringify({{
+                    rows: rows.length,
+                    stale_rows: rows.filter(row => row.dataset.kvStale === '1').length
+                }});
+            }})()
+            "#,
+            MIXER_ROW, SOLO_BUTTON
+        );
+        let json = tab
+            .evaluate(&js, true)?
+            .value
+            .and_then(|v| v.as_str().map(String::from))
+            .ok_or_else(|| anyhow!("Unable to read the mixer"))?;
+        Ok(serde_json::from_str(&json)?)
+    }
 
-        Ok(())
+    /// Waits for the mixer marked by [`Driver::mark_mixer_stale`] to render its rows again.
+    /// One that kept its rows is used as it is after [`MIXER_IN_PLACE_WAIT`]; one without
+    /// rows gets until `timeout`.
+    pub fn wait_for_mixer(&self, tab: &Tab, timeout: Duration) -> Result<()> {
+        let start = Instant::now();
+        loop {
+            if self.abort.is_requested() {
+                return Err(anyhow!(DownloadError::Cancelled("interrupted".to_string())));
+            }
+            // The page itself may be replaced while it reloads
+            if let Ok(state) = Self::mixer_state(tab) {
+                if state.is_interactive() {
+                    tracing::debug!("The mixer is back after {:?}", start.elapsed());
+                    return Ok(());
+                }
+                if state.rows > 0 && start.elapsed() > MIXER_IN_PLACE_WAIT {
+                    tracing::debug!("The mixer kept its rows for {:?}; using it as it is", MIXER_IN_PLACE_WAIT);
+                    return Ok(());
+                }
+            }
+            if start.elapsed() > timeout {
+                return Err(anyhow!("The mixer didn't come back within {:?} of changing the pitch", timeout));
+            }
+            sleep(Duration::from_millis(250));
+        }
     }
 }
 
+/// Names of the stems already sitting in the download directory as MP3s.
+fn local_track_names(dir: &Path) -> Vec<String> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return vec![];
+    };
+    entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.extension().is_some_and(|e| e == "mp3"))
+        .filter_map(|p| p.file_name().and_then(|n| n.to_str()).map(AudioProcessor::normalize_track_name))
+        .collect()
+}
+
 trait Checkable {
     fn is_checked(&self) -> bool;
 }
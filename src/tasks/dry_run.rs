@@ -0,0 +1,166 @@
+use anyhow::Result;
+use std::fmt::Display;
+use std::fs;
+use std::path::Path;
+
+use crate::audio::track_map::TRACKS_FILE;
+use crate::audio::{title, AudioProcessor, SongFolder};
+use crate::retention;
+use crate::table;
+use crate::tasks::song_plan::PlannedSong;
+use crate::tasks::track_filter::TrackFilter;
+
+/// What a run would do with a song.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DryRunStatus {
+    Download,
+    /// Its folder was left unfinished; only the missing stems would be fetched.
+    Resume,
+    Skip,
+}
+
+impl Display for DryRunStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Download => "download",
+            Self::Resume => "resume",
+            Self::Skip => "skip",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DryRunSong {
+    pub url: String,
+    pub title: String,
+    pub status: DryRunStatus,
+    pub reason: String,
+    /// The mixer tracks that would be downloaded, when the song page was read.
+    pub tracks: Option<Vec<String>>,
+}
+
+impl DryRunSong {
+    /// Records the page's `tracks` the filter keeps.
+    pub fn read_tracks(&mut self, tracks: &[String], filter: &TrackFilter) {
+        let kept: Vec<String> = tracks.iter().filter(|name| filter.selects(name)).cloned().collect();
+        if kept.len() < tracks.len() {
+            self.reason = format!("{}; {} of {} tracks", self.reason, kept.len(), tracks.len());
+        }
+        self.tracks = Some(kept);
+    }
+}
+
+/// What `download --dry-run` found: each song of the run with what would happen to it,
+/// decided from the folders on disk alone.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DryRun {
+    pub songs: Vec<DryRunSong>,
+    /// Average size of the song folders already processed, to estimate the run's disk use.
+    pub bytes_per_song: Option<u64>,
+    /// How many processed folders the average is from.
+    pub sampled_songs: usize,
+}
+
+impl DryRun {
+    /// Checks each of `songs` against its folder in `download_path`. The songs before
+    /// `skip_count` are skipped as `-A` would.
+    pub fn plan(download_path: &Path, songs: &[PlannedSong], skip_count: usize) -> Result<Self> {
+        let mut planned = Vec::new();
+        for song in songs {
+            let (status, reason) = if song.first_seen < skip_count {
+                (DryRunStatus::Skip, format!("among the first {} skipped", skip_count))
+            } else {
+                match AudioProcessor::song_folder(download_path, &song.url)? {
+                    SongFolder::Processed => (DryRunStatus::Skip, "folder already exists".to_string()),
+                    SongFolder::Unfinished => (
+                        DryRunStatus::Resume,
+                        "folder was left unfinished; the missing stems are fetched".to_string(),
+                    ),
+                    SongFolder::Missing => (DryRunStatus::Download, "not downloaded yet".to_string()),
+                }
+            };
+            let reason = match song.alternate_urls.len() {
+                0 => reason,
+                n => format!("{}; {} other storefront URL(s) recorded", reason, n),
+            };
+            planned.push(DryRunSong {
+                url: song.url.clone(),
+                title: title::from_url(&song.url).unwrap_or_else(|| song.url.clone()),
+                status,
+                reason,
+                tracks: None,
+            });
+        }
+        let sizes = processed_sizes(download_path);
+        Ok(Self {
+            songs: planned,
+            bytes_per_song: (!sizes.is_empty()).then(|| sizes.iter().sum::<u64>() / sizes.len() as u64),
+            sampled_songs: sizes.len(),
+        })
+    }
+
+    pub fn count(&self, status: DryRunStatus) -> usize {
+        self.songs.iter().filter(|song| song.status == status).count()
+    }
+
+    /// Whether any song would be downloaded or resumed.
+    pub fn has_work(&self) -> bool {
+        self.songs.iter().any(|song| song.status != DryRunStatus::Skip)
+    }
+
+    /// Disk the songs to download or resume would take, going by the average processed
+    /// song. Resumed songs are counted in full, so this leans high.
+    pub fn estimated_bytes(&self) -> Option<u64> {
+        let pending = self.songs.len() - self.count(DryRunStatus::Skip);
+        self.bytes_per_song.map(|bytes| bytes * pending as u64)
+    }
+}
+
+/// Sizes of the processed song folders directly inside `download_path`.
+fn processed_sizes(download_path: &Path) -> Vec<u64> {
+    let Ok(entries) = fs::read_dir(download_path) else {
+        return vec![];
+    };
+    entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|path| path.join(TRACKS_FILE).is_file())
+        .filter_map(|path| retention::dir_size(&path).ok())
+        .collect()
+}
+
+impl Display for DryRun {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut rows: Vec<[String; 3]> = self
+            .songs
+            .iter()
+            .map(|song| [song.title.clone(), song.status.to_string(), song.reason.clone()])
+            .collect();
+        rows.insert(0, ["SONG", "STATUS", "REASON"].map(String::from));
+        write!(f, "{}", table::render(&rows))?;
+        for song in &self.songs {
+            if let Some(tracks) = &song.tracks {
+                writeln!(f, "Tracks of {}: {}", song.title, tracks.join(", "))?;
+            }
+        }
+
+        write!(
+            f,
+            "{} songs: {} to download, {} to resume, {} skipped",
+            self.songs.len(),
+            self.count(DryRunStatus::Download),
+            self.count(DryRunStatus::Resume),
+            self.count(DryRunStatus::Skip)
+        )?;
+        match self.estimated_bytes() {
+            Some(bytes) if self.has_work() => write!(
+                f,
+                "\nAbout {} MB of disk, going by the {} song(s) already processed",
+                bytes / (1024 * 1024),
+                self.sampled_songs
+            ),
+            None if self.has_work() => write!(f, "\nNo processed songs yet to estimate the disk use from"),
+            _ => Ok(()),
+        }
+    }
+}
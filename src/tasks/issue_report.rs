@@ -0,0 +1,55 @@
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::retention;
+
+/// What went into an issue report.
+#[derive(Debug, Default)]
+pub struct IssueReport {
+    /// The last run's artifacts in the zip.
+    pub included: Vec<PathBuf>,
+    /// The last run's artifacts retention had already removed.
+    pub pruned: Vec<PathBuf>,
+}
+
+/// Zips the artifacts the last run recorded in `debug_dir` into `out`, under their names in
+/// the debug directory. One that has been pruned since is left out with a warning.
+pub fn write_issue_report(debug_dir: &Path, out: &Path) -> Result<IssueReport> {
+    let mut report = IssueReport::default();
+    let mut zip = ZipWriter::new(File::create(out)?);
+    let options = SimpleFileOptions::default()
+        .compression_method(CompressionMethod::Deflated)
+        .large_file(true);
+    for artifact in retention::last_run_artifacts(debug_dir)? {
+        if !artifact.exists() {
+            tracing::warn!("{:?} from the last run was already pruned from the debug directory", artifact);
+            report.pruned.push(artifact);
+            continue;
+        }
+        add(&mut zip, debug_dir, &artifact, options)?;
+        report.included.push(artifact);
+    }
+    zip.finish()?;
+    Ok(report)
+}
+
+/// Adds `path`, a file or a directory of them, under its path relative to `root`.
+fn add(zip: &mut ZipWriter<File>, root: &Path, path: &Path, options: SimpleFileOptions) -> Result<()> {
+    if path.is_dir() {
+        let mut entries: Vec<PathBuf> = fs::read_dir(path)?.map(|e| e.map(|e| e.path())).collect::<io::Result<_>>()?;
+        entries.sort();
+        for entry in entries {
+            add(zip, root, &entry, options)?;
+        }
+        return Ok(());
+    }
+    let name = path.strip_prefix(root)?.to_string_lossy().replace('\\', "/");
+    zip.start_file(name, options)?;
+    io::copy(&mut File::open(path)?, zip)?;
+    Ok(())
+}
@@ -0,0 +1,236 @@
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use std::fmt::Display;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::audio::manifest::StemManifest;
+use crate::audio::taxonomy::Instrument;
+use crate::audio::track_map::TrackMap;
+use crate::metadata::SongInfo;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Field {
+    Title,
+    Artist,
+    Tag,
+    Stem,
+}
+
+impl Field {
+    const ALL: [Field; 4] = [Field::Title, Field::Artist, Field::Tag, Field::Stem];
+}
+
+impl Display for Field {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Title => "title",
+            Self::Artist => "artist",
+            Self::Tag => "tag",
+            Self::Stem => "stem",
+        })
+    }
+}
+
+impl FromStr for Field {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|field| field.to_string() == s.to_lowercase())
+            .ok_or_else(|| format!("Unknown search field '{}'; expected title, artist, tag or stem", s))
+    }
+}
+
+/// One word of a query: `field:value`, or a bare value any field may match.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Term {
+    pub field: Option<Field>,
+    pub value: String,
+}
+
+/// A search such as `toto stem:sax tag:setlist2025`. A song must match every term; values
+/// with spaces are quoted, as in `artist:"steely dan"`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Query {
+    pub terms: Vec<Term>,
+}
+
+impl FromStr for Query {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut terms = Vec::new();
+        for word in split_words(s)? {
+            let term = match word.split_once(':') {
+                Some((field, value)) => Term {
+                    field: Some(field.parse()?),
+                    value: value.to_string(),
+                },
+                None => Term { field: None, value: word },
+            };
+            if term.value.is_empty() {
+                return Err(format!("'{}:' needs a value", term.field.map(|f| f.to_string()).unwrap_or_default()));
+            }
+            terms.push(term);
+        }
+        if terms.is_empty() {
+            return Err("The search is empty".to_string());
+        }
+        Ok(Self { terms })
+    }
+}
+
+/// Splits on whitespace outside double quotes, dropping the quotes.
+fn split_words(s: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut quoted = false;
+    for c in s.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => {
+                if !word.is_empty() {
+                    words.push(std::mem::take(&mut word));
+                }
+            }
+            c => word.push(c),
+        }
+    }
+    if quoted {
+        return Err(format!("Unclosed quote in '{}'", s));
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    Ok(words)
+}
+
+/// What a song folder is searched by.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LibrarySong {
+    pub path: PathBuf,
+    /// The folder's name.
+    pub title: String,
+    pub artist: Option<String>,
+    pub tags: Vec<String>,
+    /// Track names from `stems.json`, or `tracks.json` for older folders.
+    pub stems: Vec<String>,
+}
+
+impl LibrarySong {
+    pub fn load(song_dir: &Path) -> Result<Self> {
+        let info = SongInfo::load(song_dir)?.unwrap_or_default();
+        let stems = match StemManifest::load(song_dir)? {
+            Some(manifest) => manifest.stems.into_iter().map(|stem| stem.track_name).collect(),
+            None => TrackMap::load(song_dir)?
+                .unwrap_or_default()
+                .tracks
+                .into_iter()
+                .filter_map(|track| track.mixer_name)
+                .collect(),
+        };
+        Ok(Self {
+            path: song_dir.to_path_buf(),
+            title: song_dir.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default(),
+            artist: info.performer,
+            tags: info.tags,
+            stems,
+        })
+    }
+}
+
+/// Loads the processed song folders directly inside `library`, sorted by title. Only
+/// their JSON files are read, never the audio.
+pub fn scan_library(library: &Path) -> Result<Vec<LibrarySong>> {
+    let mut songs = fs::read_dir(library)
+        .map_err(|e| anyhow!("Unable to read {:?}: {}", library, e))?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.join("STEMS").join("WAV MONO").is_dir())
+        .map(|p| LibrarySong::load(&p))
+        .collect::<Result<Vec<_>>>()?;
+    songs.sort_by_key(|song| song.title.to_lowercase());
+    Ok(songs)
+}
+
+/// A value of a song that matched one of the query's terms.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MatchedField {
+    pub field: Field,
+    pub value: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SearchMatch {
+    pub path: PathBuf,
+    pub title: String,
+    pub artist: Option<String>,
+    pub matched: Vec<MatchedField>,
+}
+
+impl Term {
+    /// The song's values of the term's field, or of every field, that it matches. Text
+    /// matches ignoring case anywhere in the value; a stem also matches when the term
+    /// names its instrument, so "sax" finds "Tenor Sax" and "saxophone" finds "Alto Sax".
+    fn matches(&self, song: &LibrarySong) -> Vec<MatchedField> {
+        let wanted = self.value.to_lowercase();
+        let instruments = Instrument::from_query(&wanted);
+        let fields = match self.field {
+            Some(field) => vec![field],
+            None => Field::ALL.to_vec(),
+        };
+        let mut matched = Vec::new();
+        for field in fields {
+            let values: Vec<&String> = match field {
+                Field::Title => vec![&song.title],
+                Field::Artist => song.artist.iter().collect(),
+                Field::Tag => song.tags.iter().collect(),
+                Field::Stem => song.stems.iter().collect(),
+            };
+            for value in values {
+                let hit = value.to_lowercase().contains(&wanted)
+                    || (field == Field::Stem
+                        && Instrument::classify(value).is_some_and(|instrument| instruments.contains(&instrument)));
+                if hit {
+                    matched.push(MatchedField {
+                        field,
+                        value: value.clone(),
+                    });
+                }
+            }
+        }
+        matched
+    }
+}
+
+impl Query {
+    /// What `song` matched, or `None` if any term found nothing.
+    pub fn matches(&self, song: &LibrarySong) -> Option<SearchMatch> {
+        let mut matched: Vec<MatchedField> = Vec::new();
+        for term in &self.terms {
+            let found = term.matches(song);
+            if found.is_empty() {
+                return None;
+            }
+            for field in found {
+                if !matched.contains(&field) {
+                    matched.push(field);
+                }
+            }
+        }
+        Some(SearchMatch {
+            path: song.path.clone(),
+            title: song.title.clone(),
+            artist: song.artist.clone(),
+            matched,
+        })
+    }
+
+    pub fn search(&self, songs: &[LibrarySong]) -> Vec<SearchMatch> {
+        songs.iter().filter_map(|song| self.matches(song)).collect()
+    }
+}
@@ -0,0 +1,167 @@
+use anyhow::{anyhow, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::audio::title;
+use crate::metadata::SongInfo;
+use crate::tasks::batch::{self, STAGING_DIR};
+use crate::tasks::track_filter;
+
+/// A folder of one song's downloaded MP3s, such as the staging folder a failed batch left,
+/// processed by the `process` command without going to the site.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LocalSong {
+    pub dir: PathBuf,
+    /// The song's URL from a `song.json` in the folder, else the folder's name, which the
+    /// song folder's title is made from.
+    pub url: String,
+    /// What the `song.json` records, or just the URL; given to processing so the song page
+    /// isn't fetched.
+    pub info: SongInfo,
+}
+
+impl LocalSong {
+    fn read(dir: PathBuf) -> Self {
+        // The folder's own, or that of the song folder processing left unfinished in it
+        let saved = std::iter::once(dir.clone())
+            .chain(subfolders(&dir))
+            .find_map(|path| SongInfo::load(&path).ok().flatten());
+        let folder_name = dir.file_name().unwrap_or_default().to_string_lossy().into_owned();
+        let info = match saved {
+            Some(info) => SongInfo {
+                // Processing would otherwise read the title off the page
+                title: info.title.clone().or_else(|| title::from_url(&info.url)),
+                ..info
+            },
+            None => SongInfo {
+                url: folder_name,
+                ..SongInfo::default()
+            },
+        };
+        Self {
+            dir,
+            url: info.url.clone(),
+            info,
+        }
+    }
+
+    /// The MP3s in `dir` as those of the song at `url`. The title is left to processing,
+    /// which reads it from the page, then the tags, as a download would.
+    pub fn with_url(dir: PathBuf, url: &str) -> Self {
+        Self {
+            dir,
+            url: url.to_string(),
+            info: SongInfo {
+                url: url.to_string(),
+                ..SongInfo::default()
+            },
+        }
+    }
+}
+
+/// Whether the song argument of `--skip-download` names song folders: a folder on disk or
+/// a pattern of them, rather than the URL of the song whose MP3s are in the download path.
+pub fn is_song_source(arg: &str) -> bool {
+    !arg.starts_with("http") && (is_pattern(arg) || Path::new(arg).is_dir())
+}
+
+fn is_pattern(arg: &str) -> bool {
+    arg.contains(['*', '?'])
+}
+
+/// The song folders `source` names: the folders matching it when it's a pattern such as
+/// `downloads/.kv-staging/*`, where only the last part may hold `*` and `?`; otherwise the
+/// subfolders of the folder it names, or the folder itself when it holds the MP3s. Only
+/// folders with MP3s directly in them count.
+pub fn find_local_songs(source: &str) -> Result<Vec<LocalSong>> {
+    let path = Path::new(source);
+    let candidates = if is_pattern(source) {
+        let pattern = path.file_name().unwrap_or_default().to_string_lossy();
+        let parent = path.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        if is_pattern(&parent.to_string_lossy()) {
+            return Err(anyhow!("Only the last part of {:?} may be a pattern", source));
+        }
+        let pattern: Vec<char> = pattern.chars().collect();
+        subfolders(parent)
+            .into_iter()
+            .filter(|dir| {
+                let name: Vec<char> = dir.file_name().unwrap_or_default().to_string_lossy().chars().collect();
+                track_filter::glob(&pattern, &name)
+            })
+            .collect()
+    } else {
+        let children: Vec<PathBuf> = subfolders(path).into_iter().filter(|dir| has_mp3s(dir)).collect();
+        if children.is_empty() {
+            vec![path.to_path_buf()]
+        } else {
+            children
+        }
+    };
+    Ok(candidates.into_iter().filter(|dir| has_mp3s(dir)).map(LocalSong::read).collect())
+}
+
+/// Where the song processed in `dir` goes without a download path: the folder holding
+/// `dir`, or for a staged song, the one holding the staging folder.
+pub fn default_root(dir: &Path) -> PathBuf {
+    let dir = fs::canonicalize(dir).unwrap_or_else(|_| dir.to_path_buf());
+    let parent = dir.parent().unwrap_or(&dir);
+    match parent.parent() {
+        Some(root) if parent.file_name().is_some_and(|name| name == STAGING_DIR) => root.to_path_buf(),
+        _ => parent.to_path_buf(),
+    }
+}
+
+/// Moves the song folder processing wrote into `dir` up into `download_root`, as a staged
+/// song's is, and removes `dir` if nothing was left in it. A folder named after its song,
+/// such as `Cherub Rock` in the download path, makes way for the processed one.
+pub fn publish(dir: &Path, download_root: &Path) -> Result<Vec<PathBuf>> {
+    if same_dir(dir, download_root) {
+        return Ok(vec![]);
+    }
+    if dir.parent().and_then(Path::file_name).is_some_and(|name| name == STAGING_DIR) {
+        return batch::publish_staged(dir, download_root);
+    }
+    let mut moved = Vec::new();
+    for folder in subfolders(dir) {
+        let name = folder.file_name().unwrap_or_default().to_os_string();
+        let target = download_root.join(&name);
+        if same_dir(&target, dir) {
+            let aside = download_root.join(format!(".{}.kv-processed", name.to_string_lossy()));
+            fs::rename(&folder, &aside)?;
+            if fs::remove_dir(dir).is_err() {
+                fs::rename(&aside, &folder)?;
+                return Err(anyhow!("{:?} still holds files; the song processed in it was left there", dir));
+            }
+            fs::rename(&aside, &target)?;
+            return Ok(vec![target]);
+        }
+        if target.exists() {
+            return Err(anyhow!("{:?} already exists; the song processed in {:?} was left there", target, dir));
+        }
+        fs::rename(&folder, &target)?;
+        moved.push(target);
+    }
+    let _ = fs::remove_dir(dir);
+    Ok(moved)
+}
+
+fn same_dir(a: &Path, b: &Path) -> bool {
+    matches!((fs::canonicalize(a), fs::canonicalize(b)), (Ok(a), Ok(b)) if a == b)
+}
+
+fn subfolders(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return vec![];
+    };
+    let mut folders: Vec<PathBuf> = entries.filter_map(|e| e.ok()).map(|e| e.path()).filter(|p| p.is_dir()).collect();
+    folders.sort();
+    folders
+}
+
+fn has_mp3s(dir: &Path) -> bool {
+    fs::read_dir(dir).is_ok_and(|entries| {
+        entries
+            .filter_map(|e| e.ok())
+            .any(|e| e.path().is_file() && e.path().extension().is_some_and(|ext| ext == "mp3"))
+    })
+}
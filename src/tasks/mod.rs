@@ -1,2 +1,19 @@
+pub mod batch;
+pub mod batch_report;
+pub mod batch_state;
 pub mod download_song;
+pub mod dry_run;
+pub mod issue_report;
+pub mod library_search;
+pub mod local_songs;
+pub mod products;
+pub mod purchases;
+pub mod setlist;
 pub mod sign_in;
+pub mod song_check;
+pub mod song_diff;
+pub mod song_list;
+pub mod song_plan;
+pub mod track_filter;
+pub mod transfers;
+pub mod url_list;
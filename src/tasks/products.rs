@@ -0,0 +1,236 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::thread::sleep;
+use std::time::Instant;
+
+use crate::audio::title;
+use crate::cdp_trace::TracedTab;
+use crate::driver::Driver;
+use crate::tasks::download_song::{DownloadError, DownloadOptions, DownloadWait};
+use crate::tasks::transfers::{StallTimer, TransferState, Transfers};
+
+/// The download link of a product page without a mixer.
+const PRODUCT_DOWNLOAD_LINK: &str = "a.download, a.js-download, .song-details a.btn--download";
+
+/// A kind of file the account can have bought, as the downloads page's `file_type` filter
+/// tells them apart.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProductType {
+    /// Custom Backing Tracks, downloaded stem by stem from the mixer.
+    #[default]
+    Cbt,
+    /// Karaoke Video MP4s.
+    Video,
+    /// Synchronized lyrics files.
+    Lyrics,
+}
+
+impl ProductType {
+    const ALL: [ProductType; 3] = [ProductType::Cbt, ProductType::Video, ProductType::Lyrics];
+
+    pub fn is_cbt(&self) -> bool {
+        *self == Self::Cbt
+    }
+
+    /// The `file_type` option of the downloads page, when its value is known.
+    pub fn filter_value(&self) -> Option<&'static str> {
+        match self {
+            Self::Cbt => Some("1"),
+            Self::Video | Self::Lyrics => None,
+        }
+    }
+
+    /// Words, lowercased, the filter's option for the product is labeled with per
+    /// storefront locale, for the options whose value isn't known.
+    pub fn filter_labels(&self) -> &'static [&'static str] {
+        match self {
+            Self::Cbt => &["custom backing track"],
+            Self::Video => &["video", "vidéo", "vídeo"],
+            Self::Lyrics => &["lyric", "paroles", "liedtext", "songtext", "letra", "testo"],
+        }
+    }
+
+    /// The folder inside the song folder the product's file goes into; `None` for the
+    /// stems, which go through the audio pipeline instead.
+    pub fn folder(&self) -> Option<&'static str> {
+        match self {
+            Self::Cbt => None,
+            Self::Video => Some("VIDEO"),
+            Self::Lyrics => Some("LYRICS"),
+        }
+    }
+}
+
+impl Display for ProductType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Cbt => "cbt",
+            Self::Video => "video",
+            Self::Lyrics => "lyrics",
+        })
+    }
+}
+
+impl FromStr for ProductType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|product| product.to_string() == s.trim().to_lowercase())
+            .ok_or_else(|| format!("unknown product type '{}'; expected cbt, video or lyrics", s))
+    }
+}
+
+/// The script that sets the downloads page's `file_type` filter to `product`, returning
+/// whether the page has an option for it.
+pub fn filter_script(product: ProductType) -> String {
+    format!(
+        r#"
+          (function(){{
+            let select = document.querySelector('select[name="file_type"]');
+            if (!select) {{ return false; }}
+            let value = {};
+            let labels = {};
+            let option = Array.from(select.options).find(o =>
+              (value !== null && o.value === value) || labels.some(l => o.text.toLowerCase().includes(l)));
+            if (!option) {{ return false; }}
+            select.value = option.value;
+            select.dispatchEvent(new Event('change'));
+            return true;
+          }})();
+        "#,
+        serde_json::to_string(&product.filter_value()).unwrap_or_default(),
+        serde_json::to_string(product.filter_labels()).unwrap_or_default()
+    )
+}
+
+/// Where a product of the song titled `song_title` is filed in `download_dir`.
+pub fn product_dir(download_dir: &Path, song_title: &str, product: ProductType) -> PathBuf {
+    let song_dir = download_dir.join(song_title);
+    match product.folder() {
+        Some(folder) => song_dir.join(folder),
+        None => song_dir,
+    }
+}
+
+/// The finished files in a product folder; Chrome's partial downloads don't count.
+pub fn product_files(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return vec![];
+    };
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|path| path.is_file() && path.extension().is_none_or(|ext| ext != "crdownload"))
+        .collect();
+    files.sort();
+    files
+}
+
+impl Driver {
+    /// Downloads a product without a mixer, such as a karaoke video: the page's download
+    /// link is clicked and the file moved into the song folder's product folder. Nothing
+    /// is processed. Returns where the file went, or the file already there.
+    pub fn download_product(&self, url: &str, product: ProductType, options: &DownloadOptions) -> Result<PathBuf> {
+        let folder = product.folder().ok_or_else(|| anyhow!("{} products are downloaded from the mixer", product))?;
+        let raw_tab = self.new_tab()?;
+        let tab = TracedTab::new(&raw_tab, options.trace.clone());
+        if let Err(e) = tab.follow_events() {
+            tracing::warn!("Unable to trace the tab's events: {}", e);
+        }
+        let download_path = self.download_path(options);
+        let transfers = Transfers::default();
+        transfers
+            .listen(&tab, &download_path)
+            .map_err(|e| anyhow!("Download events are needed to follow a {} download: {}", product, e))?;
+
+        let downloaded = (|| -> Result<PathBuf> {
+            self.wait_until_loaded(tab.navigate_to(url)?)?;
+            let song_title = tab
+                .get_content()
+                .ok()
+                .and_then(|html| title::from_page(&html))
+                .or_else(|| title::from_url(url))
+                .ok_or_else(|| anyhow!("Unable to work out the song title of {}", url))?;
+            let target_dir = product_dir(Path::new(&download_path), &song_title, product);
+            if let Some(existing) = product_files(&target_dir).into_iter().next() {
+                tracing::info!("Skipping the {} of {} - {:?} already exists", product, song_title, existing);
+                return Ok(existing);
+            }
+
+            let link = tab
+                .wait_for_element_with_custom_timeout(PRODUCT_DOWNLOAD_LINK, self.config.timeouts.element)
+                .map_err(|_| anyhow!(DownloadError::NotPurchased))?;
+            tracing::info!("Downloading the {} of {}", product, song_title);
+            let known = transfers.guids();
+            link.scroll_into_view()?;
+            link.click()?;
+            let file = self.wait_for_transfer(&transfers, &known, Path::new(&download_path), &options.wait)?;
+
+            fs::create_dir_all(&target_dir)?;
+            let target = target_dir.join(file.file_name().unwrap_or_default());
+            fs::rename(&file, &target).map_err(|e| anyhow!("Unable to move {:?} into {}: {}", file, folder, e))?;
+            Ok(target)
+        })();
+        if downloaded.is_err() {
+            for transfer in transfers.in_flight() {
+                tracing::warn!("Leaving the unfinished download {:?} behind", transfer.suggested_filename);
+            }
+        }
+        let _ = tab.close(true);
+        downloaded
+    }
+
+    /// Waits for the first download begun after `known` to complete in `dir`. A large file
+    /// may take as long as it needs: only `wait.timeout` without any progress, or without
+    /// the download beginning at all, times it out.
+    fn wait_for_transfer(&self, transfers: &Transfers, known: &[String], dir: &Path, wait: &DownloadWait) -> Result<PathBuf> {
+        let started = Instant::now();
+        let transfer = loop {
+            if self.abort.is_requested() {
+                return Err(anyhow!(DownloadError::Cancelled("interrupted".to_string())));
+            }
+            if let Some(transfer) = transfers.begun_since(known).into_iter().next() {
+                break transfer;
+            }
+            if started.elapsed() > wait.timeout {
+                return Err(anyhow!(DownloadError::DownloadTimeout));
+            }
+            sleep(wait.stability_interval);
+        };
+
+        let mut stall = StallTimer::new(wait.timeout, Instant::now());
+        loop {
+            if self.abort.is_requested() {
+                return Err(anyhow!(DownloadError::Cancelled("interrupted".to_string())));
+            }
+            let current = transfers.get(&transfer.guid).unwrap_or_else(|| transfer.clone());
+            match current.state {
+                TransferState::Completed => {
+                    let path = dir.join(&current.suggested_filename);
+                    tracing::info!("Download detected: {:?} ({} bytes)", path, current.received_bytes);
+                    return Ok(path);
+                }
+                TransferState::Canceled => {
+                    return Err(anyhow!(DownloadError::Cancelled(format!(
+                        "the download of {} was canceled",
+                        current.suggested_filename
+                    ))))
+                }
+                TransferState::InProgress => {
+                    if stall.observe(current.received_bytes, Instant::now()) {
+                        return Err(anyhow!(DownloadError::DownloadTimeout));
+                    }
+                    tracing::debug!("{} is at {} bytes", current.suggested_filename, current.received_bytes);
+                }
+            }
+            sleep(wait.stability_interval);
+        }
+    }
+}
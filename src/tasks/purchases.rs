@@ -0,0 +1,133 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::audio::title;
+use crate::audio::track_map::TRACKS_FILE;
+use crate::metadata::SongInfo;
+use crate::tasks::products::{self, ProductType};
+use crate::tasks::song_list::ListedSong;
+
+/// A purchase as `list` prints it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Purchase {
+    pub url: String,
+    pub title: Option<String>,
+    pub product: ProductType,
+    /// As the downloads page writes it.
+    pub purchased: Option<String>,
+    /// Whether it's in the download directory; `None` without one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub downloaded: Option<bool>,
+}
+
+impl Purchase {
+    /// `song`, checked against `downloads` when there are some.
+    pub fn new(song: ListedSong, downloads: Option<&Downloads>) -> Self {
+        let downloaded = downloads.map(|downloads| downloads.has(&song));
+        Self {
+            url: song.url,
+            title: song.title,
+            product: song.product,
+            purchased: song.purchased,
+            downloaded,
+        }
+    }
+}
+
+/// The song folders of a download directory, found without going to the site: by the URL
+/// their `song.json` records, else by the title the URL gives.
+#[derive(Debug, Default)]
+pub struct Downloads {
+    root: PathBuf,
+    by_url: HashMap<String, PathBuf>,
+}
+
+impl Downloads {
+    /// Reads the `song.json` of every folder in `root` once.
+    pub fn scan(root: &Path) -> Self {
+        let by_url = fs::read_dir(root)
+            .into_iter()
+            .flatten()
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|path| path.is_dir())
+            .filter_map(|path| match SongInfo::load(&path) {
+                Ok(Some(info)) => Some((info.url, path)),
+                _ => None,
+            })
+            .collect();
+        Self {
+            root: root.to_path_buf(),
+            by_url,
+        }
+    }
+
+    /// Whether `song` was downloaded: a backing track's folder was processed, and another
+    /// product's file is in its folder of the song folder.
+    pub fn has(&self, song: &ListedSong) -> bool {
+        let Some(song_dir) = self
+            .by_url
+            .get(&song.url)
+            .cloned()
+            .or_else(|| title::from_url(&song.url).map(|title| self.root.join(title)))
+        else {
+            return false;
+        };
+        match song.product.folder() {
+            None => song_dir.join(TRACKS_FILE).exists(),
+            Some(folder) => !products::product_files(&song_dir.join(folder)).is_empty(),
+        }
+    }
+}
+
+/// `purchases` as a table for the terminal, with the downloaded column only when they were
+/// checked against a download directory.
+pub fn table(purchases: &[Purchase]) -> String {
+    let checked = purchases.iter().any(|purchase| purchase.downloaded.is_some());
+    let mut rows = vec![columns(["TITLE", "TYPE", "PURCHASED", "DOWNLOADED", "URL"].map(String::from), checked)];
+    rows.extend(purchases.iter().map(|purchase| columns(fields(purchase), checked)));
+    crate::table::render(&rows)
+}
+
+/// `purchases` as CSV, with a header row.
+pub fn csv(purchases: &[Purchase]) -> String {
+    let mut text = String::from("title,product,purchased,downloaded,url\n");
+    for purchase in purchases {
+        let cells: Vec<String> = fields(purchase).iter().map(|cell| csv_field(cell)).collect();
+        text.push_str(&cells.join(","));
+        text.push('\n');
+    }
+    text
+}
+
+/// The title, type, purchase date, whether downloaded and URL of `purchase`, blank where
+/// unknown.
+fn fields(purchase: &Purchase) -> [String; 5] {
+    [
+        purchase.title.clone().unwrap_or_default(),
+        purchase.product.to_string(),
+        purchase.purchased.clone().unwrap_or_default(),
+        match purchase.downloaded {
+            Some(true) => "yes".to_string(),
+            Some(false) => "no".to_string(),
+            None => String::new(),
+        },
+        purchase.url.clone(),
+    ]
+}
+
+/// `fields` without the downloaded column unless `checked`.
+fn columns(fields: [String; 5], checked: bool) -> Vec<String> {
+    fields.into_iter().enumerate().filter(|(index, _)| checked || *index != 3).map(|(_, field)| field).collect()
+}
+
+/// `value` quoted, with its quotes doubled, if it holds a comma, quote or line break.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
@@ -0,0 +1,244 @@
+use anyhow::{anyhow, Context, Result};
+use std::fs;
+use std::io::IsTerminal;
+use std::path::Path;
+
+use crate::audio::title;
+use crate::prompt::prompt;
+use crate::tasks::song_diff::{name_similarity, RENAME_SIMILARITY};
+use crate::tasks::song_plan::{PlannedSong, SongIdentity};
+
+/// How alike a setlist title and a song's title must be, from 0 to 1, for the song to be
+/// matched at all.
+pub const MATCH_SIMILARITY: f64 = RENAME_SIMILARITY;
+/// Matches less alike than this are listed for confirmation before they're used.
+pub const CONFIDENT_SIMILARITY: f64 = 0.9;
+
+/// One line of a setlist file.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SetlistEntry {
+    /// A song page on any storefront.
+    Url(String),
+    /// The site's arrangement ID, as in `song.json`.
+    ArrangementId(String),
+    /// A title, matched loosely against the songs' titles.
+    Title(String),
+}
+
+impl SetlistEntry {
+    pub fn parse(line: &str) -> Self {
+        let line = line.trim();
+        if line.starts_with("http://") || line.starts_with("https://") {
+            Self::Url(line.to_string())
+        } else if !line.is_empty() && line.chars().all(|c| c.is_ascii_digit()) {
+            Self::ArrangementId(line.to_string())
+        } else {
+            Self::Title(line.to_string())
+        }
+    }
+}
+
+impl std::fmt::Display for SetlistEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Url(s) | Self::Title(s) => f.write_str(s),
+            Self::ArrangementId(id) => write!(f, "arrangement {}", id),
+        }
+    }
+}
+
+/// Songs in performance order.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Setlist {
+    pub entries: Vec<SetlistEntry>,
+}
+
+impl Setlist {
+    /// One song per line; blank lines and lines starting with `#` are skipped.
+    pub fn parse(text: &str) -> Self {
+        let entries = text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(SetlistEntry::parse)
+            .collect();
+        Self { entries }
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path).with_context(|| format!("Unable to read the setlist {:?}", path))?;
+        let setlist = Self::parse(&text);
+        if setlist.entries.is_empty() {
+            return Err(anyhow!("The setlist {:?} doesn't name any songs", path));
+        }
+        Ok(setlist)
+    }
+}
+
+/// What a setlist entry can be matched against.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SetlistSong {
+    /// Shown when reporting the match.
+    pub name: String,
+    pub urls: Vec<String>,
+    pub arrangement_id: Option<String>,
+    /// `{title} - {artist}` or just the title; either half is matched on its own too.
+    pub titles: Vec<String>,
+}
+
+impl SetlistSong {
+    /// A planned download, titled from its identity or its URLs' paths since the page
+    /// usually hasn't been read.
+    pub fn from_planned(song: &PlannedSong) -> Self {
+        let urls: Vec<String> = std::iter::once(&song.url).chain(&song.alternate_urls).cloned().collect();
+        let mut titles: Vec<String> = urls.iter().filter_map(|url| title::from_url(url)).collect();
+        let arrangement_id = match &song.identity {
+            SongIdentity::Arrangement(id) => Some(id.clone()),
+            SongIdentity::TitleAndStems { title, .. } => {
+                titles.insert(0, title.clone());
+                None
+            }
+            SongIdentity::Url(_) => None,
+        };
+        Self {
+            name: song.url.clone(),
+            urls,
+            arrangement_id,
+            titles,
+        }
+    }
+
+    /// How alike `entry` is to the song, from 0 to 1.
+    fn similarity(&self, entry: &SetlistEntry) -> f64 {
+        match entry {
+            SetlistEntry::Url(url) => {
+                if self.urls.iter().any(|own| same_url(own, url)) {
+                    return 1.0;
+                }
+                // The same song bought on another storefront only shares the URL's path
+                title::from_url(url).map_or(0.0, |title| self.title_similarity(&title))
+            }
+            SetlistEntry::ArrangementId(id) => {
+                if self.arrangement_id.as_deref() == Some(id.as_str()) {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            SetlistEntry::Title(title) => self.title_similarity(title),
+        }
+    }
+
+    /// The best of `wanted` against each title, and against each title's song half so an
+    /// entry can leave the artist out.
+    fn title_similarity(&self, wanted: &str) -> f64 {
+        self.titles
+            .iter()
+            .map(|title| {
+                let song = title.split(" - ").next().unwrap_or(title);
+                name_similarity(title, wanted).max(name_similarity(song, wanted))
+            })
+            .fold(0.0, f64::max)
+    }
+}
+
+fn same_url(a: &str, b: &str) -> bool {
+    a.trim_end_matches('/').eq_ignore_ascii_case(b.trim_end_matches('/'))
+}
+
+/// A setlist entry and the song it picked.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SetlistMatch {
+    pub entry: SetlistEntry,
+    /// Index into the songs ordered.
+    pub song: usize,
+    pub similarity: f64,
+}
+
+/// Where a setlist puts the songs of a batch.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SetlistOrder {
+    /// Indices of the songs: the setlist's in performance order, then the rest as they were.
+    pub order: Vec<usize>,
+    /// The matched entries, in setlist order.
+    pub matched: Vec<SetlistMatch>,
+    /// Entries no song matched.
+    pub unmatched: Vec<SetlistEntry>,
+}
+
+impl SetlistOrder {
+    /// Matches worth a second look before the batch is reordered by them.
+    pub fn uncertain(&self) -> impl Iterator<Item = &SetlistMatch> {
+        self.matched.iter().filter(|m| m.similarity < CONFIDENT_SIMILARITY)
+    }
+
+    /// Indices of the matched songs only, in performance order.
+    pub fn setlist_songs(&self) -> Vec<usize> {
+        self.matched.iter().map(|m| m.song).collect()
+    }
+}
+
+/// Matches each setlist entry, in order, to the most alike song no earlier entry took, by
+/// URL, arrangement ID, or a title at least [`MATCH_SIMILARITY`] alike.
+pub fn order_songs(setlist: &Setlist, songs: &[SetlistSong]) -> SetlistOrder {
+    let mut taken = vec![false; songs.len()];
+    let mut result = SetlistOrder::default();
+    for entry in &setlist.entries {
+        let best = songs
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| !taken[*i])
+            .map(|(i, song)| (i, song.similarity(entry)))
+            .filter(|(_, similarity)| *similarity >= MATCH_SIMILARITY)
+            .fold(None, |best: Option<(usize, f64)>, (i, similarity)| match best {
+                Some((_, top)) if top >= similarity => best,
+                _ => Some((i, similarity)),
+            });
+        match best {
+            Some((song, similarity)) => {
+                taken[song] = true;
+                result.order.push(song);
+                result.matched.push(SetlistMatch {
+                    entry: entry.clone(),
+                    song,
+                    similarity,
+                });
+            }
+            None => result.unmatched.push(entry.clone()),
+        }
+    }
+    result.order.extend((0..songs.len()).filter(|i| !taken[*i]));
+    result
+}
+
+/// Reports the setlist entries nothing matched, then the uncertain matches: on a terminal
+/// they're listed for confirmation and declining fails, otherwise they're logged as warnings.
+pub fn review(order: &SetlistOrder, songs: &[SetlistSong]) -> Result<()> {
+    if !order.unmatched.is_empty() {
+        tracing::warn!(
+            "{} setlist entries match no song:\n - {}",
+            order.unmatched.len(),
+            order.unmatched.iter().map(ToString::to_string).collect::<Vec<_>>().join("\n - ")
+        );
+    }
+    let uncertain: Vec<String> = order
+        .uncertain()
+        .map(|m| format!("{} -> {} ({:.0}% alike)", m.entry, songs[m.song].name, m.similarity * 100.0))
+        .collect();
+    if uncertain.is_empty() {
+        return Ok(());
+    }
+    if !std::io::stdin().is_terminal() {
+        tracing::warn!("Setlist matches to check:\n - {}", uncertain.join("\n - "));
+        return Ok(());
+    }
+    println!("These setlist entries only loosely match a song:");
+    for line in &uncertain {
+        println!("  {}", line);
+    }
+    let answer = prompt("Use these matches? [Y/n] ", false)?;
+    if answer.eq_ignore_ascii_case("n") || answer.eq_ignore_ascii_case("no") {
+        return Err(anyhow!("Setlist matches not confirmed"));
+    }
+    Ok(())
+}
@@ -1,12 +1,180 @@
-use crate::keystore::Keystore;
-use std::{thread::sleep, time::Duration};
+use std::{thread::sleep, time::{Duration, SystemTime, UNIX_EPOCH}};
+use std::io::{stdin, IsTerminal};
+use std::sync::mpsc;
 use crate::driver::Driver;
+use crate::keystore::{belongs_to, CookieFile};
+use crate::navigation::Retries;
+use crate::prompt::prompt;
 use anyhow::{Result, anyhow};
+use headless_chrome::Tab;
+use headless_chrome::protocol::cdp::Network::GetAllCookies;
+use regex::Regex;
+use serde::Serialize;
+use std::fmt::Display;
 
-const LOGIN_TIMEOUT: std::time::Duration = Duration::from_secs(30);
+/// The site's session cookie.
+const SESSION_COOKIE: &str = "karaoke-version";
+/// How long before the session cookie expires a batch signs in again, rather than have it
+/// expire in the middle of a song.
+pub const SESSION_RENEWAL_MARGIN: Duration = Duration::from_secs(30 * 60);
+
+/// The code the site emails to a new device, when there's no `--verification-code`.
+pub const VERIFICATION_CODE_VAR: &str = "KV_VERIFICATION_CODE";
+/// How long a run without a terminal waits for a verification code on its standard input.
+const VERIFICATION_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+/// The field of the form asking for the emailed code, which the login form doesn't have.
+const VERIFICATION_FIELDS: [&str; 4] = [
+    "input[autocomplete='one-time-code']",
+    "#frm_code",
+    "input[name*='verification']",
+    "input[name*='code']:not([type='hidden'])",
+];
+
+/// The site asked for the code it emailed, and none could be had.
+#[derive(Debug)]
+pub struct VerificationRequired;
+
+impl Display for VerificationRequired {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "The site emailed a verification code for this new sign-in and none was given; run once interactively to enter it, \
+             or pass it with --verification-code or {}",
+            VERIFICATION_CODE_VAR
+        )
+    }
+}
+
+impl std::error::Error for VerificationRequired {}
+
+/// Times the whole sign-in is tried again after a network failure or timeout.
+const LOGIN_RETRIES: u32 = 2;
+/// Wait before the first retry of the sign-in; it doubles with the next.
+const LOGIN_BACKOFF: Duration = Duration::from_secs(5);
+/// Where the login page says why it turned the credentials down. Only the form's own alert
+/// boxes: the site uses a bare `.error` for unrelated notices elsewhere on the page.
+const LOGIN_ERROR_SELECTORS: &[&str] = &[".alert-danger", ".alert-error", ".form-error", ".error-message", ".message-error", "#login_error"];
+/// What the site's error says when the credentials are wrong, lowercased, in the languages
+/// of its storefronts. Whole phrases, so a notice that only mentions a password isn't one.
+const BAD_CREDENTIALS_PHRASES: &[&str] = &[
+    "invalid email or password",
+    "invalid username or password",
+    "incorrect email or password",
+    "incorrect password",
+    "wrong password",
+    "password is incorrect",
+    "email or password is incorrect",
+    "identifiant ou mot de passe",
+    "mot de passe incorrect",
+    "falsches passwort",
+    "passwort ist falsch",
+    "benutzername oder passwort",
+    "contraseña incorrecta",
+    "usuario o contraseña",
+];
+
+/// Whether the login page's error `text` says the credentials are wrong.
+pub fn credentials_rejected(text: &str) -> bool {
+    let text = text.to_lowercase();
+    BAD_CREDENTIALS_PHRASES.iter().any(|phrase| text.contains(phrase))
+}
+
+/// The site turned the username or password down. Signing in again won't help, so it isn't
+/// retried, and a batch stops on it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BadCredentials {
+    /// What the site said.
+    pub message: String,
+}
+
+impl BadCredentials {
+    /// Whether `error` comes from wrong credentials.
+    pub fn caused(error: &anyhow::Error) -> bool {
+        error.chain().any(|cause| cause.is::<Self>())
+    }
+}
+
+impl Display for BadCredentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "The site rejected the username or password ({:?}); run `kv_downloader auth` to enter them again",
+            self.message
+        )
+    }
+}
+
+impl std::error::Error for BadCredentials {}
+
+/// A page of the sign-in finished loading without an element it always had: the site
+/// changed, and retrying won't bring it back.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PageLayoutChanged {
+    pub url: String,
+    pub selector: String,
+}
+
+impl Display for PageLayoutChanged {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} loaded without {}; the site's pages may have changed, and kv-downloader with them",
+            self.url, self.selector
+        )
+    }
+}
+
+impl std::error::Error for PageLayoutChanged {}
+
+/// When a cookie whose `expires` is `expires` seconds since the epoch does; `None` for a
+/// session cookie, which Chrome gives as -1.
+pub fn cookie_expiry(expires: f64) -> Option<SystemTime> {
+    (expires > 0.0).then(|| UNIX_EPOCH + Duration::from_secs_f64(expires))
+}
+
+/// How [`Driver::sign_in`] got its session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SignInMethod {
+    /// The Chrome connected to was signed in already.
+    ConnectedBrowser,
+    /// The cookies of `--cookies-file`.
+    CookiesFile,
+    /// The session cookie saved in the keystore.
+    SavedCookie,
+    /// The login page found a session, e.g. of a `--persistent-profile`.
+    ExistingSession,
+    /// The credentials, on the login form.
+    Login,
+}
+
+impl SignInMethod {
+    /// Whether a session was reused rather than a fresh login made.
+    pub fn reused_session(self) -> bool {
+        self != Self::Login
+    }
+}
+
+impl Display for SignInMethod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::ConnectedBrowser => "the connected browser's session",
+            Self::CookiesFile => "the cookies file",
+            Self::SavedCookie => "the saved session cookie",
+            Self::ExistingSession => "the browser's existing session",
+            Self::Login => "a fresh login",
+        })
+    }
+}
+
+/// The first email address in `text`, e.g. of the account page.
+pub fn find_email(text: &str) -> Option<String> {
+    let email = Regex::new(r"[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}").expect("valid regex");
+    email.find(text).map(|found| found.as_str().to_string())
+}
 
 impl Driver {
-    fn validate_session(&self, tab: &headless_chrome::Tab) -> bool {
+    pub(crate) fn validate_session(&self, tab: &headless_chrome::Tab) -> bool {
         tracing::debug!("Validating session state...");
         
         // Check if we're redirected to login page
@@ -45,7 +213,7 @@ impl Driver {
 
         // Try navigating to account page as final check
         tracing::debug!("No clear indicators found, checking account page access...");
-        if let Ok(_) = tab.navigate_to(&format!("https://{}/my/account", self.config.domain)) {
+        if tab.navigate_to(&format!("https://{}/my/account", self.config.domain)).is_ok() {
             sleep(Duration::from_secs(2));
             if !tab.get_url().contains("/my/login") {
                 tracing::debug!("Can access account page - session valid");
@@ -57,20 +225,172 @@ impl Driver {
         false
     }
 
-    pub fn sign_in(&self, user: &str, pass: &str) -> Result<()> {
-        let tab = self.browser.new_tab()?;
-        tab.set_default_timeout(LOGIN_TIMEOUT);
+    /// Signs in, from the cookies file, the saved session cookie or the credentials, and
+    /// then saves the session to the cookies file, if there is one. Returns which of them
+    /// it took.
+    pub fn sign_in(&self, user: &str, pass: &str) -> Result<SignInMethod> {
+        let retries = Retries {
+            retries: LOGIN_RETRIES,
+            backoff: LOGIN_BACKOFF,
+        };
+        // A network failure or timeout is worth another go from the start; wrong
+        // credentials or a changed page aren't
+        let method = retries.run("the sign-in", || {
+            let tab = self.new_tab()?;
+            let method = self.sign_in_tab(&tab, user, pass);
+            if method.is_err() {
+                let _ = tab.close(true);
+            }
+            method
+        })?;
+        self.record_session_expiry();
+        if let Some(path) = &self.config.cookies_file {
+            match self.save_cookies(&CookieFile::new(path)) {
+                Ok(count) => tracing::info!("Saved {} cookies to {:?}", count, path),
+                Err(e) => tracing::warn!("Unable to save the cookies to {:?}: {}", path, e),
+            }
+        }
+        Ok(method)
+    }
+
+    /// The email address the account page shows, once signed in; `None` when it shows none.
+    pub fn account_email(&self) -> Result<Option<String>> {
+        let tab = self.new_tab()?;
+        self.load(&tab, &format!("https://{}/my/account", self.config.domain))?;
+        // A field holds it on a form, which the page's text leaves out
+        let fields = tab.evaluate(
+            r#"Array.from(document.querySelectorAll("input[type='email'], input[name*='mail']")).map(i => i.value).join(' ')"#,
+            false,
+        )?;
+        let text = tab.evaluate("document.body ? document.body.innerText : ''", false)?;
+        let _ = tab.close(true);
+        let text = [fields, text].into_iter().filter_map(|result| result.value.and_then(|v| v.as_str().map(String::from)));
+        Ok(text.map(|text| find_email(&text)).find(Option::is_some).flatten())
+    }
+
+    /// Notes when the session cookie the sign-in left expires.
+    fn record_session_expiry(&self) {
+        let cookies = self.get_tab().and_then(|tab| Ok(tab.call_method(GetAllCookies(None))?.cookies));
+        let expiry = cookies
+            .unwrap_or_default()
+            .into_iter()
+            .find(|cookie| cookie.name == SESSION_COOKIE && belongs_to(&cookie.domain, &self.config.domain))
+            .and_then(|cookie| cookie_expiry(cookie.expires));
+        if let Some(expiry) = expiry {
+            let left = expiry.duration_since(SystemTime::now()).unwrap_or_default();
+            tracing::debug!("The session expires in {} minutes", left.as_secs() / 60);
+        }
+        *self.session_expiry.lock().unwrap() = expiry;
+    }
+
+    /// Whether the session cookie expires within `margin`; one without an expiry never does.
+    pub fn session_expiring(&self, margin: Duration) -> bool {
+        self.session_expiry.lock().unwrap().is_some_and(|expiry| SystemTime::now() + margin >= expiry)
+    }
+
+    /// Whether the site still takes the session, from its account page.
+    pub fn session_valid(&self) -> Result<bool> {
+        let tab = self.new_tab()?;
+        self.load(&tab, &format!("https://{}/my/account", self.config.domain))?;
+        let valid = self.validate_session(&tab);
+        let _ = tab.close(true);
+        Ok(valid)
+    }
+
+    /// Replaces the session with a fresh login: it's ended on the site first, so neither
+    /// the browser's cookies nor the saved ones can stand in for the login.
+    pub fn renew_session(&self, user: &str, pass: &str) -> Result<()> {
+        if let Err(e) = self.sign_out() {
+            tracing::warn!("Unable to end the old session: {}", e);
+        }
+        self.sign_in(user, pass)?;
+        Ok(())
+    }
+
+    /// Signs in afresh when the session expires within [`SESSION_RENEWAL_MARGIN`], before a
+    /// song of a long batch. Returns whether it did.
+    pub fn renew_expiring_session(&self, user: &str, pass: &str) -> Result<bool> {
+        if !self.session_expiring(SESSION_RENEWAL_MARGIN) {
+            return Ok(false);
+        }
+        tracing::info!("The session is about to expire; signing in again");
+        self.renew_session(user, pass)?;
+        Ok(true)
+    }
+
+    /// Ends the session on the site too, by following the account page's logout link.
+    /// Returns whether there was one, which there isn't when the session had already ended.
+    pub fn sign_out(&self) -> Result<bool> {
+        let tab = self.new_tab()?;
+        self.load(&tab, &format!("https://{}/my/account", self.config.domain))?;
+        let href = tab.evaluate("(document.querySelector(\"a[href*='logout']\") || {}).href || null", false)?;
+        let Some(href) = href.value.and_then(|v| v.as_str().map(String::from)) else {
+            let _ = tab.close(true);
+            return Ok(false);
+        };
+        self.load(&tab, &href)?;
+        let _ = tab.close(true);
+        Ok(true)
+    }
+
+    /// Writes the cookies of the site to `file`, replacing what it had.
+    pub fn save_cookies(&self, file: &CookieFile) -> Result<usize> {
+        let cookies = self.get_tab()?.call_method(GetAllCookies(None))?.cookies;
+        file.save(&self.config.domain, cookies)
+    }
+
+    /// Restores a session from the cookies file, which goes first on a machine without a
+    /// keychain; expired or rejected cookies leave the tab to sign in otherwise.
+    fn restore_cookies_file(&self, tab: &Tab) -> Result<bool> {
+        let Some(path) = &self.config.cookies_file else {
+            return Ok(false);
+        };
+        let cookies = CookieFile::new(path).load(&self.config.domain).unwrap_or_else(|e| {
+            tracing::warn!("{}", e);
+            vec![]
+        });
+        if cookies.is_empty() {
+            return Ok(false);
+        }
+        tracing::info!("Restoring the session from {:?}...", path);
+        tab.set_cookies(cookies)?;
+        tab.reload(true, None)?;
+        sleep(Duration::from_secs(3));
+        if self.validate_session(tab) {
+            tracing::info!("Successfully restored the session of the cookies file");
+            return Ok(true);
+        }
+        tracing::info!("The cookies of {:?} are expired or were rejected", path);
+        Ok(false)
+    }
+
+    fn sign_in_tab(&self, tab: &Tab, user: &str, pass: &str) -> Result<SignInMethod> {
+        tab.set_default_timeout(self.config.timeouts.login);
         
         tracing::info!("Starting sign-in process for user: {}", user);
         
         // First navigate to homepage
         tracing::info!("Navigating to homepage...");
-        tab.navigate_to(&format!("https://{}", self.config.domain))?;
-        tab.wait_until_navigated()?;
+        self.load_until(tab, &format!("https://{}", self.config.domain), |tab| tab.wait_until_navigated().map(|_| ()))?;
         sleep(Duration::from_secs(3));
+        self.pass_challenge(tab)?;
+
+        // A Chrome connected to may well be signed in already
+        if self.is_connected() && self.validate_session(tab) {
+            tracing::info!("The connected browser is already signed in");
+            return Ok(SignInMethod::ConnectedBrowser);
+        }
+
+        if self.restore_cookies_file(tab)? {
+            return Ok(SignInMethod::CookiesFile);
+        }
 
         // Check for existing session cookie
-        if let Some(cookie) = Keystore::get_auth_cookie().ok() {
+        let saved_cookie = self.config.secrets.get_auth_cookie(&self.config.domain).unwrap_or_else(|e| {
+            tracing::warn!("Unable to read the saved session cookie: {}", e);
+            None
+        });
+        if let Some(cookie) = saved_cookie {
             tracing::info!("Found previous session cookie, attempting to restore...");
             
             tab.set_cookies(vec![cookie])?;
@@ -78,9 +398,9 @@ impl Driver {
             sleep(Duration::from_secs(3));
             
             // Validate the session
-            if self.validate_session(&tab) {
+            if self.validate_session(tab) {
                 tracing::info!("Successfully restored previous session");
-                return Ok(());
+                return Ok(SignInMethod::SavedCookie);
             }
             tracing::info!("Previous session expired or invalid");
         }
@@ -91,62 +411,157 @@ impl Driver {
         // Navigate to login page directly
         let login_url = format!("https://{}/my/login.html", self.config.domain);
         tracing::info!("Navigating to login page: {}", login_url);
-        tab.navigate_to(&login_url)?;
-        tab.wait_until_navigated()?;
+        self.load_until(tab, &login_url, |tab| tab.wait_until_navigated().map(|_| ()))?;
         sleep(Duration::from_secs(3));
+        self.pass_challenge(tab)?;
 
         // Check if we're already logged in after navigation
-        if self.validate_session(&tab) {
+        if self.validate_session(tab) {
             tracing::info!("Already logged in!");
-            return Ok(());
+            return Ok(SignInMethod::ExistingSession);
         }
 
-        // Wait for and fill username field
-        tracing::info!("Filling login form...");
-        let username_field = tab.wait_for_element("#frm_login")
-            .map_err(|_| anyhow!("Could not find username field"))?;
-
-        username_field.focus()?;
-        sleep(Duration::from_millis(500));
-        self.type_fast(&tab, user);
-        sleep(Duration::from_secs(1));
+        // A consent banner would take the focus or the clicks meant for the form
+        self.dismiss_consent(tab);
 
-        // Wait for and fill password field
-        let password_field = tab.wait_for_element("#frm_password")
-            .map_err(|_| anyhow!("Could not find password field"))?;
-            
-        password_field.focus()?;
-        sleep(Duration::from_millis(500));
-        self.type_fast(&tab, pass);
-        sleep(Duration::from_secs(1));
+        for selector in ["#frm_login", "#frm_password", "#sbm"] {
+            self.require(tab, selector)?;
+        }
+        tracing::info!("Filling login form...");
+        self.fill_field(tab, "#frm_login", user)
+            .map_err(|e| anyhow!("Could not fill the username field: {}", e))?;
+        self.fill_field(tab, "#frm_password", pass)
+            .map_err(|e| anyhow!("Could not fill the password field: {}", e))?;
 
-        // Find and click submit button
         tracing::info!("Submitting login form...");
-        let submit_button = tab.wait_for_element("#sbm")
-            .map_err(|_| anyhow!("Could not find submit button"))?;
-            
-        submit_button.click()?;
+        self.wait_interactable(tab, "#sbm")
+            .map_err(|e| anyhow!("Could not find submit button: {}", e))?
+            .click()?;
 
         // Wait for navigation to complete
         tab.wait_until_navigated()?;
         sleep(Duration::from_secs(5));
+        // A CAPTCHA may come up on submitting; the session is checked once it's solved
+        self.pass_challenge(tab)?;
+        if let Some(message) = self.login_error(tab).filter(|message| credentials_rejected(message)) {
+            return Err(BadCredentials { message }.into());
+        }
+        self.pass_verification(tab)?;
         
         // Verify login success
-        if !self.validate_session(&tab) {
-            return Err(anyhow!("Login failed - unable to validate session"));
+        if !self.validate_session(tab) {
+            return Err(match self.login_error(tab) {
+                Some(message) if credentials_rejected(&message) => BadCredentials { message }.into(),
+                Some(message) => anyhow!("Login failed - the site said {:?}", message),
+                None => anyhow!("Login failed - unable to validate session"),
+            });
         }
         
         // Save new cookie for next time
         if let Ok(cookies) = tab.get_cookies() {
-            if let Some(session_cookie) = cookies.iter().find(|c| c.name == "karaoke-version") {
+            if let Some(session_cookie) = cookies.iter().find(|c| c.name == SESSION_COOKIE) {
                 tracing::info!("Saving new session cookie");
-                if let Err(e) = Keystore::set_auth_cookie(session_cookie) {
+                if let Err(e) = self.config.secrets.set_auth_cookie(&self.config.domain, session_cookie) {
                     tracing::warn!("Failed to save session cookie to keystore: {}", e);
                 }
             }
         }
         
         tracing::info!("Login successful!");
+        Ok(SignInMethod::Login)
+    }
+
+    /// Waits for the element `selector` of a sign-in page. One that never comes on a page
+    /// that finished loading is a [`PageLayoutChanged`], rather than a slow network.
+    fn require(&self, tab: &Tab, selector: &str) -> Result<()> {
+        let Err(e) = tab.wait_for_element(selector) else {
+            return Ok(());
+        };
+        let loaded = tab
+            .evaluate("document.readyState", false)
+            .ok()
+            .and_then(|result| result.value)
+            .is_some_and(|state| state == "complete");
+        if !loaded {
+            return Err(e);
+        }
+        Err(PageLayoutChanged {
+            url: tab.get_url(),
+            selector: selector.to_string(),
+        }
+        .into())
+    }
+
+    /// The text of the error the login page shows, if it shows one.
+    pub fn login_error(&self, tab: &Tab) -> Option<String> {
+        let script = format!(
+            "(() => {{ for (const selector of {}) {{ for (const element of document.querySelectorAll(selector)) {{ \
+             const text = element.innerText ? element.innerText.trim() : ''; \
+             if (text && element.offsetParent !== null) {{ return text; }} }} }} return ''; }})()",
+            serde_json::to_string(LOGIN_ERROR_SELECTORS).expect("selectors serialize")
+        );
+        let text = tab.evaluate(&script, false).ok()?.value?;
+        text.as_str().filter(|text| !text.is_empty()).map(str::to_string)
+    }
+
+    /// The selector of the field of the verification form `tab` shows, if it does.
+    fn find_verification_field(&self, tab: &Tab) -> Option<&'static str> {
+        if tab.find_element("#frm_login").is_ok() {
+            return None;
+        }
+        VERIFICATION_FIELDS.into_iter().find(|selector| tab.find_element(selector).is_ok())
+    }
+
+    /// Enters the code the site emails when it doesn't know the device, should it ask for one
+    /// after the login form.
+    fn pass_verification(&self, tab: &Tab) -> Result<()> {
+        let Some(selector) = self.find_verification_field(tab) else {
+            return Ok(());
+        };
+        tracing::info!("The site asks for the verification code it emailed");
+        let code = self.verification_code()?;
+
+        self.fill_field(tab, selector, &code)?;
+        // The form is submitted with whichever button it has
+        self.wait_interactable(tab, selector)?.call_js_fn(
+            "function() { const form = this.form; if (form.requestSubmit) { form.requestSubmit(); } else { form.submit(); } }",
+            vec![],
+            false,
+        )?;
+        tab.wait_until_navigated()?;
+        sleep(Duration::from_secs(5));
+        self.pass_challenge(tab)?;
+
+        if self.find_verification_field(tab).is_some() {
+            return Err(anyhow!("Login failed - the verification code was rejected"));
+        }
         Ok(())
     }
+
+    /// The verification code: `--verification-code`, [`VERIFICATION_CODE_VAR`], or asked for.
+    /// Without a terminal, a code is read from the standard input for a while, then
+    /// [`VerificationRequired`] is returned.
+    fn verification_code(&self) -> Result<String> {
+        let given = self.config.verification_code.clone().or_else(|| std::env::var(VERIFICATION_CODE_VAR).ok());
+        if let Some(code) = given.filter(|code| !code.trim().is_empty()) {
+            return Ok(code.trim().to_string());
+        }
+        if stdin().is_terminal() {
+            let code = prompt("Verification code from the site's email: ", false)?;
+            return if code.is_empty() { Err(VerificationRequired.into()) } else { Ok(code) };
+        }
+
+        tracing::info!("Waiting {:?} for the verification code on standard input", VERIFICATION_TIMEOUT);
+        let (sender, receiver) = mpsc::channel();
+        std::thread::spawn(move || {
+            let mut line = String::new();
+            if stdin().read_line(&mut line).is_ok() {
+                let _ = sender.send(line.trim().to_string());
+            }
+        });
+        match receiver.recv_timeout(VERIFICATION_TIMEOUT) {
+            Ok(code) if !code.is_empty() => Ok(code),
+            _ => Err(VerificationRequired.into()),
+        }
+    }
 }
\ No newline at end of file
@@ -0,0 +1,377 @@
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::audio::manifest::{StemManifest, MANIFEST_JSON};
+use crate::audio::track_map::{TrackMap, TRACKS_FILE};
+use crate::audio::{AudioProcessor, ProcessOptions};
+
+/// Seconds a stem may be longer or shorter than the click: every stem is padded to the
+/// click's length, give or take the frames resampling rounds off.
+pub const DURATION_TOLERANCE_SECS: f64 = 0.1;
+
+const STEM_DIRS: [&str; 2] = ["WAV ST", "WAV MONO"];
+const PROJECT_DIR: &str = "MT PROJECT";
+
+/// What `verify` found in one song folder.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SongCheck {
+    pub song_dir: PathBuf,
+    pub passed: bool,
+    /// One problem each, naming the file it's about.
+    pub findings: Vec<String>,
+    /// What `--fix` repaired before the folder was checked again.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub fixed: Vec<String>,
+    /// The projects are missing or refer to stems that aren't there.
+    #[serde(skip)]
+    projects_broken: bool,
+    /// `stems.json` doesn't describe the WAVs on disk.
+    #[serde(skip)]
+    manifest_stale: bool,
+    /// Every stem `tracks.json` lists has both WAVs, readable, so what's built from them
+    /// can be rebuilt.
+    #[serde(skip)]
+    stems_intact: bool,
+}
+
+impl SongCheck {
+    fn new(song_dir: &Path) -> Self {
+        Self {
+            song_dir: song_dir.to_path_buf(),
+            passed: true,
+            findings: vec![],
+            fixed: vec![],
+            projects_broken: false,
+            manifest_stale: false,
+            stems_intact: true,
+        }
+    }
+
+    fn find(&mut self, finding: String) {
+        self.passed = false;
+        self.findings.push(finding);
+    }
+}
+
+impl fmt::Display for SongCheck {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", if self.passed { "PASS" } else { "FAIL" }, self.song_dir.display())?;
+        for finding in &self.findings {
+            write!(f, "\n  - {}", finding)?;
+        }
+        for fixed in &self.fixed {
+            write!(f, "\n  fixed: {}", fixed)?;
+        }
+        Ok(())
+    }
+}
+
+/// Each of `paths` that's a song folder, otherwise the song folders directly inside it,
+/// sorted. A song folder has a `STEMS` folder or a `tracks.json`; hidden folders, such as
+/// the staging one, are left out.
+pub fn find_song_dirs(paths: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    let is_song_dir = |path: &Path| path.join("STEMS").is_dir() || path.join(TRACKS_FILE).is_file();
+    let mut song_dirs = Vec::new();
+    for path in paths {
+        if is_song_dir(path) {
+            song_dirs.push(path.clone());
+            continue;
+        }
+        let mut children: Vec<PathBuf> = fs::read_dir(path)
+            .map_err(|e| anyhow!("Unable to read {:?}: {}", path, e))?
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| !p.file_name().unwrap_or_default().to_string_lossy().starts_with('.'))
+            .filter(|p| is_song_dir(p))
+            .collect();
+        children.sort();
+        song_dirs.extend(children);
+    }
+    Ok(song_dirs)
+}
+
+/// Checks `song_dir` without changing anything: its folders, that both WAVs of every
+/// stem in `tracks.json` are there, readable and as long as the click, that the Reaper
+/// projects only refer to files that exist, and that `stems.json`, if there is one,
+/// records the WAVs' frame counts and formats.
+pub fn check(song_dir: &Path) -> SongCheck {
+    let mut check = SongCheck::new(song_dir);
+    let stems_dir = song_dir.join("STEMS");
+    for dir in STEM_DIRS {
+        if !stems_dir.join(dir).is_dir() {
+            check.find(format!("STEMS/{} is missing", dir));
+        }
+    }
+    let project_dir = song_dir.join(PROJECT_DIR);
+    if !project_dir.is_dir() {
+        check.projects_broken = true;
+        check.find(format!("{} is missing", PROJECT_DIR));
+    } else if fs::read_dir(&project_dir).is_ok_and(|mut entries| entries.next().is_none()) {
+        check.projects_broken = true;
+        check.find(format!("{} is empty", PROJECT_DIR));
+    }
+
+    let map = match TrackMap::load(song_dir) {
+        Ok(Some(map)) if map.tracks.is_empty() => {
+            check.find(format!("{} lists no stems", TRACKS_FILE));
+            None
+        }
+        Ok(Some(map)) => Some(map),
+        Ok(None) => {
+            check.find(format!("No {}: processing never finished, or a version from before it processed the song", TRACKS_FILE));
+            None
+        }
+        Err(e) => {
+            check.find(format!("{:#}", e));
+            None
+        }
+    };
+    match &map {
+        Some(map) => check_stems(&mut check, song_dir, map),
+        None => {
+            // Without the map the WAVs can't be paired, but each can still be read
+            check.stems_intact = false;
+            for dir in STEM_DIRS {
+                for path in wavs_in(&stems_dir.join(dir)) {
+                    if let Err(problem) = wav_length(&path) {
+                        check.find(format!("{}: {}", relative(song_dir, &path), problem));
+                    }
+                }
+            }
+        }
+    }
+    check_projects(&mut check, &project_dir);
+    if let Some(map) = &map {
+        check_manifest(&mut check, song_dir, map);
+    }
+    check
+}
+
+/// Rebuilds what [`check`] found broken in `song_dir` from the stems on disk: the projects,
+/// with `options`, and `stems.json`. Stems themselves are never replaced; nothing is
+/// downloaded. Returns the folder checked again, with what was repaired.
+pub fn fix(song_dir: &Path, found: &SongCheck, options: &ProcessOptions) -> SongCheck {
+    let mut fixed = Vec::new();
+    let mut failed = Vec::new();
+    if !found.stems_intact {
+        let mut after = check(song_dir);
+        if found.projects_broken || found.manifest_stale {
+            after.find("Not repaired: stems are missing or unreadable; download the song again".to_string());
+        }
+        return after;
+    }
+    if found.projects_broken {
+        match AudioProcessor::regenerate_projects(song_dir, options) {
+            Ok(report) => {
+                fixed.push(format!("Regenerated the projects in {}", PROJECT_DIR));
+                failed.extend(report.warnings);
+            }
+            Err(e) => failed.push(format!("Unable to regenerate the projects: {:#}", e)),
+        }
+    }
+    if found.manifest_stale {
+        match rebuild_manifest(song_dir) {
+            Ok(()) => fixed.push(format!("Rewrote {} from the WAVs", MANIFEST_JSON)),
+            Err(e) => failed.push(format!("Unable to rewrite {}: {:#}", MANIFEST_JSON, e)),
+        }
+    }
+    let mut after = check(song_dir);
+    after.fixed = fixed;
+    for finding in failed {
+        after.find(finding);
+    }
+    after
+}
+
+fn check_stems(check: &mut SongCheck, song_dir: &Path, map: &TrackMap) {
+    let mut lengths: HashMap<&str, Duration> = HashMap::new();
+    for track in &map.tracks {
+        for file in [&track.stereo_file, &track.mono_file] {
+            match wav_length(&song_dir.join(file)) {
+                Ok(length) => {
+                    lengths.insert(file.as_str(), length);
+                }
+                Err(problem) => {
+                    check.stems_intact = false;
+                    check.find(format!("{}: {}", file, problem));
+                }
+            }
+        }
+    }
+
+    let Some(click) = map.tracks.iter().find(|track| track.is_click) else {
+        return;
+    };
+    let Some(click_length) = lengths.get(click.stereo_file.as_str()).copied() else {
+        return;
+    };
+    for track in &map.tracks {
+        for file in [&track.stereo_file, &track.mono_file] {
+            let Some(length) = lengths.get(file.as_str()) else {
+                continue;
+            };
+            if (length.as_secs_f64() - click_length.as_secs_f64()).abs() > DURATION_TOLERANCE_SECS {
+                check.find(format!(
+                    "{} is {:.2}s long, the click {:.2}s",
+                    file,
+                    length.as_secs_f64(),
+                    click_length.as_secs_f64()
+                ));
+            }
+        }
+    }
+}
+
+/// Every `FILE` of every Reaper project in `project_dir`, resolved the way Reaper does:
+/// relative to the project.
+fn check_projects(check: &mut SongCheck, project_dir: &Path) {
+    let Ok(entries) = fs::read_dir(project_dir) else {
+        return;
+    };
+    let mut projects: Vec<PathBuf> = entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("rpp")))
+        .collect();
+    projects.sort();
+    for project in projects {
+        let name = project.file_name().unwrap_or_default().to_string_lossy().into_owned();
+        let text = match fs::read_to_string(&project) {
+            Ok(text) => text,
+            Err(e) => {
+                check.projects_broken = true;
+                check.find(format!("{}/{}: unreadable: {}", PROJECT_DIR, name, e));
+                continue;
+            }
+        };
+        for file in rpp_files(&text) {
+            let path = Path::new(file);
+            let path = if path.is_absolute() { path.to_path_buf() } else { project_dir.join(path) };
+            if !path.is_file() {
+                check.projects_broken = true;
+                check.find(format!("{}/{} refers to {}, which doesn't exist", PROJECT_DIR, name, file));
+            }
+        }
+    }
+}
+
+/// The media files a Reaper project refers to, as written in it.
+pub fn rpp_files(text: &str) -> Vec<&str> {
+    text.lines()
+        .filter_map(|line| line.trim_start().strip_prefix("FILE "))
+        .filter_map(|rest| {
+            let rest = rest.trim();
+            rest.strip_prefix('"').and_then(|quoted| quoted.rsplit_once('"')).map(|(file, _)| file)
+        })
+        .collect()
+}
+
+fn check_manifest(check: &mut SongCheck, song_dir: &Path, map: &TrackMap) {
+    let manifest = match StemManifest::load(song_dir) {
+        Ok(Some(manifest)) => manifest,
+        // Versions from before the manifest didn't write one
+        Ok(None) => return,
+        Err(e) => {
+            check.manifest_stale = true;
+            check.find(format!("{:#}", e));
+            return;
+        }
+    };
+    for track in &map.tracks {
+        if !manifest.stems.iter().any(|stem| stem.stereo_file == track.stereo_file) {
+            check.manifest_stale = true;
+            check.find(format!("{} doesn't list {}", MANIFEST_JSON, track.stereo_file));
+        }
+    }
+    for stem in &manifest.stems {
+        if !map.tracks.iter().any(|track| track.stereo_file == stem.stereo_file) {
+            check.manifest_stale = true;
+            check.find(format!("{} lists {}, which {} doesn't", MANIFEST_JSON, stem.stereo_file, TRACKS_FILE));
+            continue;
+        }
+        // A WAV that can't be read was reported with the stems
+        let Ok(reader) = hound::WavReader::open(song_dir.join(&stem.stereo_file)) else {
+            continue;
+        };
+        let spec = reader.spec();
+        if (reader.duration(), spec.sample_rate, spec.channels) != (stem.duration_samples, stem.sample_rate, stem.channels) {
+            check.manifest_stale = true;
+            check.find(format!(
+                "{} is {} frames at {} Hz in {} channels; {} records {} frames at {} Hz in {}",
+                stem.stereo_file,
+                reader.duration(),
+                spec.sample_rate,
+                spec.channels,
+                MANIFEST_JSON,
+                stem.duration_samples,
+                stem.sample_rate,
+                stem.channels
+            ));
+        }
+    }
+}
+
+/// `stems.json` built again from the WAVs, keeping the padding and drift the old one
+/// recorded, which only processing can measure.
+fn rebuild_manifest(song_dir: &Path) -> Result<()> {
+    let map = TrackMap::load(song_dir)?.ok_or_else(|| anyhow!("No {} in {:?}", TRACKS_FILE, song_dir))?;
+    let old = StemManifest::load(song_dir).ok().flatten().unwrap_or_default();
+    let padding = map
+        .tracks
+        .iter()
+        .filter_map(|track| {
+            let stem = old.stems.iter().find(|stem| stem.stereo_file == track.stereo_file)?;
+            Some((track.original_filename.clone(), Duration::from_secs_f64(stem.padding_secs.max(0.0))))
+        })
+        .collect();
+    let mut manifest = StemManifest::build(song_dir, &map, &padding)?;
+    for stem in &mut manifest.stems {
+        stem.time_drift_ms = old.stems.iter().find(|old| old.stereo_file == stem.stereo_file).and_then(|old| old.time_drift_ms);
+    }
+    manifest.save(song_dir)
+}
+
+/// The length of the WAV at `path`, or what's wrong with it.
+fn wav_length(path: &Path) -> Result<Duration, String> {
+    let size = match fs::metadata(path) {
+        Ok(metadata) => metadata.len(),
+        Err(_) => return Err("missing".to_string()),
+    };
+    if size == 0 {
+        return Err("empty (0 bytes)".to_string());
+    }
+    let reader = hound::WavReader::open(path).map_err(|e| format!("unreadable WAV header: {}", e))?;
+    let spec = reader.spec();
+    if reader.duration() == 0 || spec.sample_rate == 0 {
+        return Err("holds no audio".to_string());
+    }
+    // An interrupted write leaves a header promising more than the file holds
+    let data = reader.duration() as u64 * spec.channels as u64 * spec.bits_per_sample.div_ceil(8) as u64;
+    if data > size {
+        return Err(format!("cut short: {} bytes of audio in a file of {}", data, size));
+    }
+    Ok(Duration::from_secs_f64(reader.duration() as f64 / spec.sample_rate as f64))
+}
+
+fn wavs_in(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return vec![];
+    };
+    let mut wavs: Vec<PathBuf> = entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("wav")))
+        .collect();
+    wavs.sort();
+    wavs
+}
+
+fn relative(song_dir: &Path, path: &Path) -> String {
+    let rel = path.strip_prefix(song_dir).unwrap_or(path);
+    rel.components().map(|c| c.as_os_str().to_string_lossy()).collect::<Vec<_>>().join("/")
+}
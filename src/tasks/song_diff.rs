@@ -0,0 +1,319 @@
+use anyhow::{anyhow, Context, Result};
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::audio::manifest::StemManifest;
+use crate::audio::track_map::{TrackEntry, TrackMap};
+use crate::cdp_trace::TracedTab;
+use crate::driver::Driver;
+use crate::metadata::SongInfo;
+
+/// How alike two track names must be, from 0 to 1 after [`normalize_name`], for a stem the
+/// folder has and a track the page has to count as one renamed track.
+pub const RENAME_SIMILARITY: f64 = 0.7;
+
+/// What the song page says now.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LiveSong {
+    pub info: SongInfo,
+    /// Mixer track names, in mixer order.
+    pub tracks: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TrackChange {
+    Added(String),
+    Removed(String),
+    Renamed { from: String, to: String },
+}
+
+/// A credit or audio detail that differs between the folder and the page.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldChange {
+    pub field: &'static str,
+    pub before: String,
+    pub after: String,
+}
+
+/// Differences between a processed song folder and its page.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SongDiff {
+    pub tracks: Vec<TrackChange>,
+    pub fields: Vec<FieldChange>,
+}
+
+impl SongDiff {
+    /// Compares the stems in `manifest` with the page's tracks. Names are matched after
+    /// [`normalize_name`]; of those left over, pairs at least [`RENAME_SIMILARITY`] alike are
+    /// renames, most alike first. Fields are only compared when `saved` recorded them.
+    pub fn compare(manifest: &StemManifest, saved: Option<&SongInfo>, live: &LiveSong) -> Self {
+        let mut removed: Vec<&str> = Vec::new();
+        let mut added: Vec<&str> = live.tracks.iter().map(String::as_str).collect();
+        for stem in &manifest.stems {
+            let name = normalize_name(&stem.track_name);
+            match added.iter().position(|track| normalize_name(track) == name) {
+                Some(index) => {
+                    added.remove(index);
+                }
+                None => removed.push(&stem.track_name),
+            }
+        }
+
+        let mut pairs: Vec<(f64, usize, usize)> = Vec::new();
+        for (r, from) in removed.iter().enumerate() {
+            for (a, to) in added.iter().enumerate() {
+                let similarity = name_similarity(from, to);
+                if similarity >= RENAME_SIMILARITY {
+                    pairs.push((similarity, r, a));
+                }
+            }
+        }
+        pairs.sort_by(|x, y| y.0.total_cmp(&x.0).then((x.1, x.2).cmp(&(y.1, y.2))));
+        let mut renamed_from = vec![None; removed.len()];
+        let mut renamed_to = vec![false; added.len()];
+        for (_, r, a) in pairs {
+            if renamed_from[r].is_none() && !renamed_to[a] {
+                renamed_from[r] = Some(a);
+                renamed_to[a] = true;
+            }
+        }
+
+        let mut tracks = Vec::new();
+        for (r, from) in removed.iter().enumerate() {
+            tracks.push(match renamed_from[r] {
+                Some(a) => TrackChange::Renamed {
+                    from: from.to_string(),
+                    to: added[a].to_string(),
+                },
+                None => TrackChange::Removed(from.to_string()),
+            });
+        }
+        for (a, to) in added.iter().enumerate() {
+            if !renamed_to[a] {
+                tracks.push(TrackChange::Added(to.to_string()));
+            }
+        }
+
+        let mut fields = Vec::new();
+        if let Some(saved) = saved {
+            let compared = [
+                ("arrangement", &saved.arrangement_id, &live.info.arrangement_id),
+                ("tempo", &saved.tempo, &live.info.tempo),
+                ("key", &saved.key, &live.info.key),
+            ];
+            for (field, before, after) in compared {
+                if let (Some(before), Some(after)) = (before, after) {
+                    if before != after {
+                        fields.push(FieldChange {
+                            field,
+                            before: before.clone(),
+                            after: after.clone(),
+                        });
+                    }
+                }
+            }
+        }
+        Self { tracks, fields }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tracks.is_empty() && self.fields.is_empty()
+    }
+
+    /// Page names of the tracks an update downloads: the added ones, and the renamed ones
+    /// under their new name, since a rename may well be a new recording.
+    pub fn to_fetch(&self) -> Vec<String> {
+        self.tracks
+            .iter()
+            .filter_map(|change| match change {
+                TrackChange::Added(name) | TrackChange::Renamed { to: name, .. } => Some(name.clone()),
+                TrackChange::Removed(_) => None,
+            })
+            .collect()
+    }
+
+    /// Folder names of the stems a fetched track replaces.
+    pub fn replaced(&self) -> Vec<String> {
+        self.tracks
+            .iter()
+            .filter_map(|change| match change {
+                TrackChange::Renamed { from, .. } => Some(from.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+impl Display for SongDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_empty() {
+            return f.write_str("No changes");
+        }
+        for change in &self.tracks {
+            match change {
+                TrackChange::Added(name) => writeln!(f, "+ {}", name)?,
+                TrackChange::Removed(name) => writeln!(f, "- {}", name)?,
+                TrackChange::Renamed { from, to } => writeln!(f, "~ {} -> {}", from, to)?,
+            }
+        }
+        for change in &self.fields {
+            writeln!(f, "{}: {} -> {}", change.field, change.before, change.after)?;
+        }
+        Ok(())
+    }
+}
+
+/// Lowercase words of letters and digits, so `Drum-Kit` and `drum kit` are one name.
+pub fn normalize_name(name: &str) -> String {
+    name.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// 1 for names equal after [`normalize_name`], falling with the edit distance between them.
+/// A name that contains the other's words, plurals aside, such as `Bass` and `Bass Guitar`
+/// or `Drum Kit` and `Drums`, counts as a rename however long the rest is.
+pub fn name_similarity(a: &str, b: &str) -> f64 {
+    let (a, b) = (normalize_name(a), normalize_name(b));
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+    if a == b {
+        return 1.0;
+    }
+    let (sa, sb) = (singular(&a), singular(&b));
+    let contained = contains_words(&sa, &sb) || contains_words(&sb, &sa);
+    let (a, b): (Vec<char>, Vec<char>) = (a.chars().collect(), b.chars().collect());
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = (diagonal + usize::from(ca != cb)).min(above + 1).min(row[j] + 1);
+            diagonal = above;
+        }
+    }
+    let similarity = 1.0 - row[b.len()] as f64 / a.len().max(b.len()) as f64;
+    if contained {
+        similarity.max(RENAME_SIMILARITY)
+    } else {
+        similarity
+    }
+}
+
+/// `name` with the plural `s` of each word dropped.
+fn singular(name: &str) -> String {
+    name.split(' ')
+        .map(|word| match word.strip_suffix('s') {
+            Some(stem) if stem.len() > 2 && !stem.ends_with('s') => stem,
+            _ => word,
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Whether the words of `short` appear in a row in `long`.
+fn contains_words(long: &str, short: &str) -> bool {
+    let long: Vec<&str> = long.split(' ').collect();
+    let short: Vec<&str> = short.split(' ').collect();
+    long.windows(short.len()).any(|window| window == short.as_slice())
+}
+
+/// The stem's name as the manifest gives it: the mixer's, or the file's without one.
+fn stem_name(track: &TrackEntry) -> String {
+    track.mixer_name.clone().unwrap_or_else(|| {
+        Path::new(&track.mono_file)
+            .file_stem()
+            .map(|s| s.to_string_lossy().trim_end_matches("_mono").to_string())
+            .unwrap_or_default()
+    })
+}
+
+/// Copies the stems of `fetched` from `staged`, a song folder processed from just those
+/// tracks plus the click, into `song_dir`. The stems they replace are deleted, and the
+/// folder's track map and manifest are rewritten in `live`'s mixer order.
+pub fn merge_staged(song_dir: &Path, staged: &Path, diff: &SongDiff, live: &LiveSong) -> Result<()> {
+    let fetched = diff.to_fetch();
+    let replaced = diff.replaced();
+    let is_one_of = |names: &[String], track: &TrackEntry| {
+        let name = normalize_name(&stem_name(track));
+        names.iter().any(|n| normalize_name(n) == name)
+    };
+
+    let mut map = TrackMap::load(song_dir)?.ok_or_else(|| anyhow!("No track map in {:?}", song_dir))?;
+    let staged_map = TrackMap::load(staged)?.ok_or_else(|| anyhow!("No track map in {:?}", staged))?;
+    let mut padding = padding_by_download(song_dir, &map)?;
+    padding.extend(padding_by_download(staged, &staged_map)?);
+
+    for old in map.tracks.iter().filter(|t| is_one_of(&replaced, t)) {
+        for file in [&old.stereo_file, &old.mono_file] {
+            let path = song_dir.join(file);
+            if path.exists() {
+                fs::remove_file(&path).with_context(|| format!("Unable to remove {:?}", path))?;
+            }
+        }
+    }
+    map.tracks.retain(|t| !is_one_of(&replaced, t));
+
+    for new in staged_map.tracks.iter().filter(|t| is_one_of(&fetched, t)) {
+        for file in [&new.stereo_file, &new.mono_file] {
+            let target = song_dir.join(file);
+            if map.tracks.iter().any(|t| &t.stereo_file == file || &t.mono_file == file) {
+                return Err(anyhow!("{:?} already holds another stem of the song", target));
+            }
+            fs::copy(staged.join(file), &target).with_context(|| format!("Unable to write {:?}", target))?;
+        }
+        map.tracks.push(new.clone());
+    }
+
+    map.assign_mixer_names(&live.tracks, None);
+    map.save(song_dir)?;
+    StemManifest::build(song_dir, &map, &padding)?.save(song_dir)?;
+    Ok(())
+}
+
+/// Replaces the folder's song info with the page's, keeping the other storefronts' URLs
+/// and the user's tags.
+pub fn save_live_info(song_dir: &Path, live: &LiveSong) -> Result<()> {
+    let saved = SongInfo::load(song_dir)?.unwrap_or_default();
+    SongInfo {
+        alternate_urls: saved.alternate_urls,
+        tags: saved.tags,
+        ..live.info.clone()
+    }
+    .save(song_dir)
+}
+
+/// Padding of each stem of `song_dir` from its manifest, keyed by downloaded file name the
+/// way [`StemManifest::build`] wants it.
+fn padding_by_download(song_dir: &Path, map: &TrackMap) -> Result<HashMap<String, Duration>> {
+    let manifest = StemManifest::load(song_dir)?.unwrap_or_default();
+    Ok(map
+        .tracks
+        .iter()
+        .filter_map(|track| {
+            let entry = manifest.stems.iter().find(|s| s.mono_file == track.mono_file)?;
+            Some((track.original_filename.clone(), Duration::from_secs_f64(entry.padding_secs)))
+        })
+        .collect())
+}
+
+impl Driver {
+    /// Opens the song page and reads its mixer tracks and details.
+    pub fn read_live_song(&self, url: &str) -> Result<LiveSong> {
+        let raw_tab = self.new_tab()?;
+        let tab = TracedTab::plain(&raw_tab);
+        self.wait_until_loaded(tab.navigate_to(url)?)?;
+        tab.wait_for_element_with_custom_timeout(".mixer", self.config.timeouts.element)?;
+        let tracks = Self::extract_tracks(&raw_tab)?.into_iter().map(|t| t.name).collect();
+        let info = SongInfo::from_html(url, &raw_tab.get_content()?);
+        let _ = raw_tab.close(true);
+        Ok(LiveSong { info, tracks })
+    }
+}
@@ -0,0 +1,589 @@
+use crate::audio::title;
+use crate::driver::Driver;
+use crate::tasks::products::{self, ProductType};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use headless_chrome::Tab;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+/// The songs `-A` collected, saved in the download directory for `--reuse`.
+pub const TRACK_LIST_FILE: &str = "track_list.json";
+/// Days a saved track list may be reused for before `--reuse` warns it may be missing
+/// songs bought since.
+pub const DEFAULT_TRACK_LIST_MAX_AGE_DAYS: u64 = 7;
+
+/// Shown at the bottom of the infinite-scroll variant of the downloads page while it has
+/// more rows to load.
+const SCROLL_SENTINEL: &str = ".my-downloaded-files__loader, .infinite-scroll-loader, [data-infinite-scroll]";
+/// The scrollable element holding the rows on the infinite-scroll variant.
+const SCROLL_CONTAINER: &str = ".my-downloaded-files, #tab_files";
+/// The header element stating how many files the account has.
+const TOTAL_COUNT: &str = ".my-downloaded-files__count, .my-downloaded-files__total, #tab_files_count";
+/// How long the row count may stay put after a scroll before the list counts as complete.
+const QUIET_PERIOD: Duration = Duration::from_secs(10);
+
+/// How the downloads page splits up the song list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PaginationMode {
+    /// Numbered pages with a `.pagination a.next` link.
+    Pages,
+    /// One page that loads more rows as it's scrolled.
+    InfiniteScroll,
+}
+
+impl PaginationMode {
+    /// Picks the mode from what the first page shows. The scroll sentinel settles it; without
+    /// one, a page that has no pagination but advertises more files than it lists scrolls too.
+    pub fn detect(has_pagination: bool, has_sentinel: bool, advertised_total: Option<usize>, rows: usize) -> Self {
+        if has_sentinel || (!has_pagination && advertised_total.is_some_and(|total| total > rows)) {
+            Self::InfiniteScroll
+        } else {
+            Self::Pages
+        }
+    }
+}
+
+/// The songs found on the downloads page.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CollectionResult {
+    pub urls: Vec<String>,
+    /// The text of each song's link on the page, by URL.
+    pub titles: HashMap<String, String>,
+    /// The purchase date each song's row shows, as the page writes it, by URL.
+    pub purchased: HashMap<String, String>,
+    pub mode: PaginationMode,
+    /// The number of files the page says the account has, when it says.
+    pub advertised_total: Option<usize>,
+    /// Whether as many songs were collected as advertised; `true` when nothing is advertised.
+    pub reached_total: bool,
+}
+
+impl CollectionResult {
+    fn new(collected: Collected, mode: PaginationMode, advertised_total: Option<usize>) -> Self {
+        let Collected { urls, titles, purchased, .. } = collected;
+        let reached_total = advertised_total.is_none_or(|total| urls.len() >= total);
+        Self {
+            urls,
+            titles,
+            purchased,
+            mode,
+            advertised_total,
+            reached_total,
+        }
+    }
+
+    /// The songs in page order, with their titles.
+    pub fn songs(&self) -> Vec<ListedSong> {
+        self.urls
+            .iter()
+            .map(|url| ListedSong {
+                url: url.clone(),
+                title: self.titles.get(url).cloned(),
+                product: ProductType::Cbt,
+                purchased: self.purchased.get(url).cloned(),
+            })
+            .collect()
+    }
+}
+
+/// A song of the downloads page, as kept in [`TRACK_LIST_FILE`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ListedSong {
+    pub url: String,
+    /// The text of its link; `None` in lists saved before titles were kept.
+    #[serde(default)]
+    pub title: Option<String>,
+    /// What was bought; lists saved before `--product-types` only hold backing tracks.
+    #[serde(default, skip_serializing_if = "ProductType::is_cbt")]
+    pub product: ProductType,
+    /// The purchase date its row showed, as the page writes it; `None` where it shows none,
+    /// and in lists saved before dates were kept.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub purchased: Option<String>,
+}
+
+/// What `-A` collected, as kept in [`TRACK_LIST_FILE`].
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TrackList {
+    /// RFC 3339, UTC; `None` in lists saved before it was recorded.
+    #[serde(default)]
+    pub generated_at: Option<String>,
+    /// The number of backing tracks the downloads page said the account had, or the number
+    /// collected where it doesn't say.
+    #[serde(default)]
+    pub total: Option<usize>,
+    pub songs: Vec<ListedSong>,
+}
+
+impl TrackList {
+    /// A list collected just now.
+    pub fn new(songs: Vec<ListedSong>, total: Option<usize>) -> Self {
+        Self {
+            generated_at: Some(Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true)),
+            total,
+            songs,
+        }
+    }
+
+    pub fn generated_at(&self) -> Option<DateTime<Utc>> {
+        let generated_at = self.generated_at.as_deref()?;
+        DateTime::parse_from_rfc3339(generated_at).ok().map(|at| at.with_timezone(&Utc))
+    }
+
+    /// Why the list may be missing songs bought since it was collected at `generated_at`:
+    /// it's more than `max_age_days` old at `now`, or the downloads page shows a
+    /// `current_total` other than the one saved.
+    pub fn staleness(
+        &self,
+        generated_at: Option<DateTime<Utc>>,
+        now: DateTime<Utc>,
+        max_age_days: u64,
+        current_total: Option<usize>,
+    ) -> Vec<String> {
+        let mut reasons = Vec::new();
+        if let Some(generated_at) = generated_at {
+            let days = (now - generated_at).num_days();
+            if days > max_age_days as i64 {
+                reasons.push(format!("it was collected {} days ago", days));
+            }
+        }
+        if let (Some(saved), Some(current)) = (self.total, current_total) {
+            if saved != current {
+                reasons.push(format!("the account has {} backing tracks now, not the {} it lists", current, saved));
+            }
+        }
+        reasons
+    }
+}
+
+/// What `--new-only` downloads of a freshly collected list.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct NewPurchases {
+    /// In the fresh list's order.
+    pub songs: Vec<ListedSong>,
+    /// URLs of `songs` whose link text a song of the previous list had under a URL that's
+    /// gone now, with that URL: the site may have changed the song's slug.
+    pub renamed: Vec<(String, String)>,
+    /// How many of `songs` the previous list had, but an earlier run left unfinished.
+    pub unfinished: usize,
+}
+
+/// The songs of `fresh` that `previous` didn't list, or whose URL is among `unfinished`.
+pub fn new_purchases(fresh: &[ListedSong], previous: &[ListedSong], unfinished: &HashSet<String>) -> NewPurchases {
+    let known: HashSet<&str> = previous.iter().map(|song| song.url.as_str()).collect();
+    let listed: HashSet<&str> = fresh.iter().map(|song| song.url.as_str()).collect();
+    let same_title = |a: &ListedSong, b: &ListedSong| {
+        a.product == b.product
+            && matches!((&a.title, &b.title), (Some(a), Some(b)) if a.trim().to_lowercase() == b.trim().to_lowercase())
+    };
+    let mut purchases = NewPurchases::default();
+    for song in fresh {
+        if !known.contains(song.url.as_str()) {
+            if let Some(old) = previous.iter().find(|old| !listed.contains(old.url.as_str()) && same_title(old, song)) {
+                purchases.renamed.push((song.url.clone(), old.url.clone()));
+            }
+        } else if unfinished.contains(&song.url) {
+            purchases.unfinished += 1;
+        } else {
+            continue;
+        }
+        purchases.songs.push(song.clone());
+    }
+    purchases
+}
+
+/// A saved track list, which used to be a bare array of songs, and before that of URLs.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SavedTrackList {
+    // First, as a struct can be read from an array too
+    Songs(Vec<SavedSong>),
+    List(TrackList),
+}
+
+/// An entry of a saved track list, which used to hold only the URLs.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SavedSong {
+    Listed(ListedSong),
+    Url(String),
+}
+
+pub fn load_track_list(path: &Path) -> Result<TrackList> {
+    let data = fs::read_to_string(path).map_err(|e| anyhow!("Failed to read track list file: {}", e))?;
+    let saved: SavedTrackList = serde_json::from_str(&data).map_err(|e| anyhow!("Failed to parse track list: {}", e))?;
+    Ok(match saved {
+        SavedTrackList::List(list) => list,
+        SavedTrackList::Songs(songs) => TrackList {
+            songs: songs
+                .into_iter()
+                .map(|song| match song {
+                    SavedSong::Listed(song) => song,
+                    SavedSong::Url(url) => ListedSong {
+                        url,
+                        title: None,
+                        product: ProductType::Cbt,
+                        purchased: None,
+                    },
+                })
+                .collect(),
+            ..TrackList::default()
+        },
+    })
+}
+
+/// Writes `list` to `path` in one rename, so a run stopped while saving it leaves the
+/// list from before rather than half of the new one.
+pub fn save_track_list(path: &Path, list: &TrackList) -> Result<()> {
+    let partial = path.with_extension("json.partial");
+    fs::write(&partial, serde_json::to_string_pretty(list)?).map_err(|e| anyhow!("Failed to write track list file: {}", e))?;
+    fs::rename(&partial, path).map_err(|e| anyhow!("Failed to replace the track list file: {}", e))
+}
+
+/// A `--filter` pattern: a case-insensitive regex between slashes, like `/^toto\b/`, or a
+/// case-insensitive substring otherwise.
+#[derive(Debug, Clone)]
+pub enum TitlePattern {
+    Substring(String),
+    Regex(Regex),
+}
+
+impl FromStr for TitlePattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        match s.strip_prefix('/').and_then(|rest| rest.strip_suffix('/')) {
+            Some(pattern) if !pattern.is_empty() => Regex::new(&format!("(?i){}", pattern))
+                .map(Self::Regex)
+                .map_err(|e| format!("invalid regex {}: {}", s, e)),
+            _ if s.is_empty() => Err("expected a title substring or /regex/".to_string()),
+            _ => Ok(Self::Substring(s.to_lowercase())),
+        }
+    }
+}
+
+impl TitlePattern {
+    fn matches(&self, text: &str) -> bool {
+        match self {
+            Self::Substring(wanted) => text.to_lowercase().contains(wanted),
+            Self::Regex(regex) => regex.is_match(text),
+        }
+    }
+}
+
+impl ListedSong {
+    /// Whether any of `patterns` matches the song's link text or the "Title - Artist" its
+    /// URL spells out, so the artist can be matched even where the link only shows the title.
+    /// Nothing is fetched.
+    pub fn matches(&self, patterns: &[TitlePattern]) -> bool {
+        let from_url = title::from_url(&self.url);
+        let texts: Vec<&str> = self.title.iter().chain(from_url.iter()).map(String::as_str).collect();
+        patterns.iter().any(|pattern| texts.iter().any(|text| pattern.matches(text)))
+    }
+}
+
+/// The first number in the header's count, e.g. `1,234 files` gives 1234.
+pub fn parse_advertised_total(text: &str) -> Option<usize> {
+    let digits: String = text
+        .chars()
+        .skip_while(|c| !c.is_ascii_digit())
+        .take_while(|c| c.is_ascii_digit() || matches!(c, ',' | '.' | ' ' | '\u{a0}'))
+        .filter(char::is_ascii_digit)
+        .collect();
+    digits.parse().ok()
+}
+
+/// The count in the downloads page's header.
+fn advertised_total(tab: &Tab) -> Option<usize> {
+    tab.find_element(TOTAL_COUNT)
+        .ok()
+        .and_then(|el| el.get_inner_text().ok())
+        .and_then(|text| parse_advertised_total(&text))
+}
+
+/// Song URLs in the order they were first seen, whichever page or scroll step they came
+/// from, with their link text.
+#[derive(Debug, Default)]
+struct Collected {
+    urls: Vec<String>,
+    titles: HashMap<String, String>,
+    purchased: HashMap<String, String>,
+    seen: HashSet<String>,
+}
+
+impl Collected {
+    /// Adds the rows that are new and returns how many there were.
+    fn extend(&mut self, rows: Vec<ListedSong>) -> usize {
+        let before = self.urls.len();
+        for row in rows {
+            if self.seen.insert(row.url.clone()) {
+                if let Some(title) = row.title {
+                    self.titles.insert(row.url.clone(), title);
+                }
+                if let Some(purchased) = row.purchased {
+                    self.purchased.insert(row.url.clone(), purchased);
+                }
+                self.urls.push(row.url);
+            }
+        }
+        self.urls.len() - before
+    }
+}
+
+impl Driver {
+    pub fn collect_all_custom_track_urls(&self) -> Result<CollectionResult> {
+        let tab = self.new_tab()?;
+        if !self.filter_downloads(&tab, ProductType::Cbt)? {
+            tracing::warn!("The downloads page has no Custom Backing Track filter; collecting every file");
+        }
+        self.collect_song_list(&tab)
+    }
+
+    /// Collects the songs bought as each of `product_types`, one pass of the downloads
+    /// page per filter, backing tracks first. A product the page has no filter for is
+    /// warned about and left out.
+    pub fn collect_purchased(&self, product_types: &[ProductType]) -> Result<TrackList> {
+        let mut product_types = product_types.to_vec();
+        product_types.sort();
+        product_types.dedup();
+        let mut songs = Vec::new();
+        let mut total = None;
+        for product in product_types {
+            let tab = self.new_tab()?;
+            if !self.filter_downloads(&tab, product)? {
+                tracing::warn!("The downloads page has no filter for {} products; skipping them", product);
+                let _ = tab.close(true);
+                continue;
+            }
+            let collected = self.collect_song_list(&tab)?;
+            tracing::info!("Found {} {} products", collected.urls.len(), product);
+            if product.is_cbt() {
+                total = Some(collected.advertised_total.unwrap_or(collected.urls.len()));
+            }
+            songs.extend(collected.songs().into_iter().map(|song| ListedSong { product, ..song }));
+            let _ = tab.close(true);
+        }
+        Ok(TrackList::new(songs, total))
+    }
+
+    /// The number of backing tracks the first page of the downloads page says the account
+    /// has, without collecting them; `None` when it doesn't say.
+    pub fn advertised_track_total(&self) -> Result<Option<usize>> {
+        let tab = self.new_tab()?;
+        let total = if self.filter_downloads(&tab, ProductType::Cbt)? {
+            tab.wait_for_element_with_custom_timeout("#tab_files tbody tr", self.config.timeouts.navigation)?;
+            advertised_total(&tab)
+        } else {
+            None
+        };
+        let _ = tab.close(true);
+        Ok(total)
+    }
+
+    /// Opens the downloads page in `tab` and sets its `file_type` filter to `product`.
+    /// Returns whether the page offered it.
+    fn filter_downloads(&self, tab: &Tab, product: ProductType) -> Result<bool> {
+        tracing::info!("Navigating to downloads page...");
+        self.load(tab, &format!("https://{}/my/download.html", self.config.domain))?;
+        sleep(Duration::from_secs(2));
+
+        tracing::info!("Selecting the {} filter...", product);
+        // Wait for the select element and set the filter.
+        tab.wait_for_element("select[name='file_type']")?;
+        let selected = tab.evaluate(&products::filter_script(product), true)?;
+        sleep(Duration::from_secs(2));
+        Ok(selected.value.and_then(|v| v.as_bool()).unwrap_or(false))
+    }
+
+    /// Collects the songs from a downloads page `tab` is already on, following whichever
+    /// kind of pagination it has.
+    pub fn collect_song_list(&self, tab: &Tab) -> Result<CollectionResult> {
+        let mut collected = Collected::default();
+        if let Err(e) = tab.wait_for_element_with_custom_timeout("#tab_files tbody tr", self.config.timeouts.navigation) {
+            tracing::warn!("Rows did not appear on the downloads page: {}", e);
+            return Ok(CollectionResult::new(Collected::default(), PaginationMode::Pages, None));
+        }
+        sleep(Duration::from_secs(2)); // Allow extra time for the rows to be populated.
+
+        let has_pagination = tab.find_element(".pagination").is_ok();
+        let has_sentinel = tab.find_element(SCROLL_SENTINEL).is_ok();
+        let advertised_total = advertised_total(tab);
+        let rows = self.extract_song_rows(tab, 1)?;
+        let mode = PaginationMode::detect(has_pagination, has_sentinel, advertised_total, rows.len());
+        tracing::info!(
+            "Downloads page uses {:?} pagination{}",
+            mode,
+            advertised_total.map(|t| format!(", {} files advertised", t)).unwrap_or_default()
+        );
+        collected.extend(rows);
+
+        match mode {
+            PaginationMode::Pages => self.collect_pages(tab, &mut collected)?,
+            PaginationMode::InfiniteScroll => self.collect_by_scrolling(tab, &mut collected, advertised_total)?,
+        }
+
+        let result = CollectionResult::new(collected, mode, advertised_total);
+        if !result.reached_total {
+            tracing::warn!(
+                "Collected {} tracks but the page advertises {}",
+                result.urls.len(),
+                advertised_total.unwrap_or_default()
+            );
+        }
+        tracing::info!("Collection complete! Found {} total tracks", result.urls.len());
+        Ok(result)
+    }
+
+    /// Follows `.pagination a.next` until the last page.
+    fn collect_pages(&self, tab: &Tab, collected: &mut Collected) -> Result<()> {
+        let mut page_number = 1;
+        loop {
+            // Pagination: Get next page link.
+            let next_js = r#"
+              (function(){
+                let nextElem = document.querySelector('.pagination a.next');
+                return nextElem ? nextElem.getAttribute('href') : null;
+              })();
+            "#;
+            let next_result = tab.evaluate(next_js, true)?;
+            // Convert the result to an owned String.
+            let next_href_opt = next_result.value.and_then(|v| v.as_str().map(String::from));
+            let Some(next_href_value) = next_href_opt else {
+                tracing::info!("No more pages (current page: {})", page_number);
+                return Ok(());
+            };
+            tracing::info!("Found next page link: {}", next_href_value);
+            let full_next_url = if next_href_value.starts_with("http") {
+                next_href_value
+            } else {
+                format!("https://{}{}", self.config.domain, next_href_value)
+            };
+            tracing::info!("Navigating to next page: {}", full_next_url);
+            page_number += 1;
+            // A page that doesn't load would cut the list short, so it's an error after its retries
+            self.load_until(tab, &full_next_url, |tab| {
+                self.wait_until_loaded(tab)?;
+                sleep(Duration::from_secs(2));
+                tab.wait_for_element_with_custom_timeout("#tab_files tbody tr", self.config.timeouts.navigation)?;
+                Ok(())
+            })
+            .map_err(|e| e.context(format!("Page {} of the downloads page didn't load", page_number)))?;
+
+            tracing::info!("Processing page {}...", page_number);
+            sleep(Duration::from_secs(2)); // Allow extra time for the rows to be populated.
+            let rows = self.extract_song_rows(tab, page_number)?;
+            collected.extend(rows);
+        }
+    }
+
+    /// Scrolls the list to the bottom until it holds the advertised number of songs, or
+    /// stops growing for [`QUIET_PERIOD`].
+    fn collect_by_scrolling(&self, tab: &Tab, collected: &mut Collected, advertised_total: Option<usize>) -> Result<()> {
+        let scroll_js = format!(
+            r#"
+              (function(){{
+                let container = document.querySelector('{}');
+                if (container) {{ container.scrollTop = container.scrollHeight; }}
+                window.scrollTo(0, document.body.scrollHeight);
+                let rows = document.querySelectorAll('#tab_files tbody tr');
+                if (rows.length) {{ rows[rows.length - 1].scrollIntoView(); }}
+                return rows.length;
+              }})();
+            "#,
+            SCROLL_CONTAINER
+        );
+        let mut step = 1;
+        loop {
+            if advertised_total.is_some_and(|total| collected.urls.len() >= total) {
+                tracing::info!("Reached the advertised {} tracks", collected.urls.len());
+                return Ok(());
+            }
+
+            tab.evaluate(&scroll_js, false)?;
+            let started = Instant::now();
+            let mut added = 0;
+            while started.elapsed() < QUIET_PERIOD {
+                sleep(Duration::from_millis(500));
+                added = collected.extend(self.extract_song_rows(tab, step)?);
+                if added > 0 {
+                    break;
+                }
+            }
+            if added == 0 {
+                tracing::info!("No more rows after {}s of scrolling", QUIET_PERIOD.as_secs());
+                return Ok(());
+            }
+            tracing::info!("Scroll step {}: {} new rows, {} so far", step, added, collected.urls.len());
+            step += 1;
+        }
+    }
+
+    /// The song links in the downloads table, as absolute URLs with their text. `page` only
+    /// labels the logs.
+    fn extract_song_rows(&self, tab: &Tab, page: usize) -> Result<Vec<ListedSong>> {
+        // Evaluate our extraction snippet.
+        let extraction_js = r#"
+            (function(){
+            try {
+                let tbody = document.querySelector('#tab_files tbody');
+                if (!tbody) {
+                return JSON.stringify({error: "No tbody found"});
+                }
+                let rows = tbody.querySelectorAll('tr');
+                console.log("Number of rows found:", rows.length);
+                let links = Array.from(rows).map(function(row){
+                let anchor = row.querySelector('td.my-downloaded-files__song.min-w-120 a');
+                if (!anchor) { return null; }
+                // The purchase date: a cell named for it, else the first cell that looks like a date
+                let dateCell = row.querySelector('td[class*="date"]') || Array.from(row.querySelectorAll('td'))
+                    .find(cell => /\d{1,4}[\/.-]\d{1,2}[\/.-]\d{1,4}/.test(cell.textContent));
+                let purchased = dateCell ? dateCell.textContent.trim() : null;
+                return { href: anchor.getAttribute('href'), title: anchor.textContent.trim(), purchased: purchased || null };
+                }).filter(x => x !== null);
+                return JSON.stringify(links);
+            } catch(e) {
+                return JSON.stringify({error: e.toString()});
+            }
+            })();
+        "#;
+        let result = tab.evaluate(extraction_js, true)?;
+        tracing::debug!("Extraction result raw: {:?}", result.value);
+
+        let mut urls = Vec::new();
+        // Expect result.value to be a JSON string
+        if let Some(json_str) = result.value.and_then(|v| v.as_str().map(|s| s.to_owned())) {
+            let parsed: serde_json::Value = serde_json::from_str(&json_str)?;
+
+            if let serde_json::Value::Array(items) = parsed {
+                for item in items {
+                    if let (Some(href), Some(title)) = (
+                        item.get("href").and_then(|v| v.as_str()),
+                        item.get("title").and_then(|v| v.as_str())
+                    ) {
+                        let full_url = format!("https://{}{}", self.config.domain, href);
+                        tracing::debug!("Found track: {} at {}", title, full_url);
+                        urls.push(ListedSong {
+                            url: full_url,
+                            title: Some(title.to_string()),
+                            product: ProductType::Cbt,
+                            purchased: item.get("purchased").and_then(|v| v.as_str()).map(String::from),
+                        });
+                    }
+                }
+            } else if let Some(error) = parsed.get("error").and_then(|v| v.as_str()) {
+                tracing::warn!("Extraction error on page {}: {}", page, error);
+            }
+        } else {
+            tracing::warn!("Extraction result on page {}: None", page);
+        }
+        Ok(urls)
+    }
+}
@@ -0,0 +1,158 @@
+use crate::audio::title;
+use crate::domain;
+use crate::metadata;
+use crate::proxy;
+use std::collections::HashMap;
+
+/// What a song page says about the arrangement behind a URL.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SongCandidate {
+    pub url: String,
+    pub arrangement_id: Option<String>,
+    pub title: Option<String>,
+    /// Tracks in the page's mixer.
+    pub stem_count: Option<usize>,
+}
+
+impl SongCandidate {
+    pub fn from_html(url: &str, html: &str) -> Self {
+        let stem_count = html.matches("class=\"track__caption\"").count();
+        Self {
+            url: url.to_string(),
+            arrangement_id: metadata::arrangement_id(html),
+            title: metadata::audio_title(html).or_else(|| title::from_page(html)),
+            stem_count: (stem_count > 0).then_some(stem_count),
+        }
+    }
+
+    /// A URL whose page wasn't looked at; it only matches itself.
+    pub fn unknown(url: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            arrangement_id: None,
+            title: None,
+            stem_count: None,
+        }
+    }
+
+    /// Fetches and reads the song page, falling back to [`SongCandidate::unknown`].
+    pub fn fetch(url: &str) -> Self {
+        match proxy::get_text(url) {
+            Ok(html) => Self::from_html(url, &html),
+            Err(e) => {
+                tracing::warn!("Unable to fetch {} to tell which arrangement it is: {}", url, e);
+                Self::unknown(url)
+            }
+        }
+    }
+
+    pub fn identity(&self) -> SongIdentity {
+        if let Some(id) = &self.arrangement_id {
+            return SongIdentity::Arrangement(id.clone());
+        }
+        match (&self.title, self.stem_count) {
+            (Some(title), Some(stems)) => SongIdentity::TitleAndStems {
+                title: normalize_title(title),
+                stems,
+            },
+            _ => SongIdentity::Url(self.url.clone()),
+        }
+    }
+}
+
+/// What makes two URLs the same song.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum SongIdentity {
+    /// The site's arrangement ID, shared by every storefront.
+    Arrangement(String),
+    /// A guess for pages without an ID: the same title with as many stems.
+    TitleAndStems { title: String, stems: usize },
+    /// Nothing is known beyond the URL.
+    Url(String),
+}
+
+/// One arrangement to download.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlannedSong {
+    pub url: String,
+    /// The other URLs of the same arrangement, recorded but not downloaded.
+    pub alternate_urls: Vec<String>,
+    pub identity: SongIdentity,
+    /// Position in the URL list of the arrangement's first URL.
+    pub first_seen: usize,
+}
+
+/// Lowercase letters and digits only, so punctuation and accents the storefronts render
+/// differently don't split a song.
+pub fn normalize_title(title: &str) -> String {
+    title
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Groups `candidates` by [`SongIdentity`] so each arrangement is downloaded once, in the
+/// order it first appears. The URL on `preferred_domain` is downloaded when there is one,
+/// otherwise the first; the rest become alternates.
+pub fn plan_songs(candidates: &[SongCandidate], preferred_domain: &str) -> Vec<PlannedSong> {
+    let mut planned: Vec<PlannedSong> = Vec::new();
+    let mut by_identity: HashMap<SongIdentity, usize> = HashMap::new();
+    for (index, candidate) in candidates.iter().enumerate() {
+        let identity = candidate.identity();
+        let Some(&at) = by_identity.get(&identity) else {
+            by_identity.insert(identity.clone(), planned.len());
+            planned.push(PlannedSong {
+                url: candidate.url.clone(),
+                alternate_urls: vec![],
+                identity,
+                first_seen: index,
+            });
+            continue;
+        };
+        let song = &mut planned[at];
+        if song.url == candidate.url || song.alternate_urls.contains(&candidate.url) {
+            continue;
+        }
+        let preferred = |url: &str| domain::from_url(url).as_deref() == Some(preferred_domain);
+        if preferred(&candidate.url) && !preferred(&song.url) {
+            let replaced = std::mem::replace(&mut song.url, candidate.url.clone());
+            song.alternate_urls.insert(0, replaced);
+        } else {
+            song.alternate_urls.push(candidate.url.clone());
+        }
+    }
+
+    for song in planned.iter().filter(|song| !song.alternate_urls.is_empty()) {
+        if let SongIdentity::TitleAndStems { title, stems } = &song.identity {
+            tracing::warn!(
+                "No arrangement ID on the pages of {} and {}; treating them as one song by title ({:?}) and stem count ({})",
+                song.url,
+                song.alternate_urls.join(", "),
+                title,
+                stems
+            );
+        }
+    }
+    planned
+}
+
+/// Plans `urls`, fetching their pages only when they come from more than one storefront:
+/// URLs from a single site can only repeat each other exactly.
+pub fn plan_urls(urls: &[String], preferred_domain: &str) -> Vec<PlannedSong> {
+    let first_host = urls.first().and_then(|url| domain::from_url(url));
+    let one_site = urls.iter().all(|url| domain::from_url(url) == first_host);
+    let candidates: Vec<SongCandidate> = if one_site {
+        urls.iter().map(|url| SongCandidate::unknown(url)).collect()
+    } else {
+        tracing::info!("The track list spans several storefronts; reading each song page to find duplicates");
+        urls.iter().map(|url| SongCandidate::fetch(url)).collect()
+    };
+    let planned = plan_songs(&candidates, preferred_domain);
+    let duplicates = urls.len() - planned.len();
+    if duplicates > 0 {
+        tracing::info!("{} URLs repeat a song already in the list", duplicates);
+    }
+    planned
+}
@@ -0,0 +1,143 @@
+use anyhow::{anyhow, Result};
+use std::str::FromStr;
+
+use crate::audio::click::ClickDetection;
+use super::download_song::{StemDecision, TrackInfo};
+
+/// Comma-separated mixer track names. Each is a case-insensitive glob when it holds `*` or
+/// `?`, and a case-insensitive substring otherwise.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TrackPatterns {
+    patterns: Vec<String>,
+    /// Names only match themselves, ignoring case.
+    exact: bool,
+}
+
+impl FromStr for TrackPatterns {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let patterns: Vec<String> = s
+            .split(',')
+            .map(|p| p.trim().to_lowercase())
+            .filter(|p| !p.is_empty())
+            .collect();
+        if patterns.is_empty() {
+            return Err("expected a comma-separated list of track names".to_string());
+        }
+        Ok(Self { patterns, exact: false })
+    }
+}
+
+impl TrackPatterns {
+    /// Patterns matching exactly `names`, for tracks picked by the program rather than typed.
+    pub fn exact(names: &[String]) -> Self {
+        Self {
+            patterns: names.iter().map(|name| name.to_lowercase()).collect(),
+            exact: true,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+
+    pub fn matches(&self, name: &str) -> bool {
+        let name = name.to_lowercase();
+        self.patterns.iter().any(|p| self.pattern_matches(p, &name))
+    }
+
+    /// Patterns that match none of `names`.
+    pub fn unmatched(&self, names: &[String]) -> Vec<&str> {
+        self.patterns
+            .iter()
+            .filter(|p| !names.iter().any(|name| self.pattern_matches(p, &name.to_lowercase())))
+            .map(String::as_str)
+            .collect()
+    }
+
+    fn pattern_matches(&self, pattern: &str, name: &str) -> bool {
+        if self.exact {
+            pattern == name
+        } else {
+            matches(pattern, name)
+        }
+    }
+}
+
+/// The mixer tracks `--tracks` asks for, minus those `--skip-tracks` leaves out. An empty
+/// filter keeps every track.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TrackFilter {
+    pub only: TrackPatterns,
+    pub skip: TrackPatterns,
+}
+
+impl TrackFilter {
+    pub fn is_empty(&self) -> bool {
+        self.only.is_empty() && self.skip.is_empty()
+    }
+
+    /// Whether the track named `name` is downloaded. Every stem is padded to the click, so
+    /// `only` always keeps it; only an explicit skip drops it.
+    pub fn selects(&self, name: &str) -> bool {
+        if self.skip.matches(name) {
+            return false;
+        }
+        self.only.is_empty() || is_click(name) || self.only.matches(name)
+    }
+
+    /// Marks the tracks the filter leaves out as not selected, warning about any pattern
+    /// that matches nothing on the page. Errors when no track is left at all.
+    pub fn apply(&self, tracks: &[TrackInfo], decisions: &mut [StemDecision]) -> Result<()> {
+        if self.is_empty() {
+            return Ok(());
+        }
+        let names: Vec<String> = tracks.iter().map(|t| t.name.clone()).collect();
+        for (flag, patterns) in [("--tracks", &self.only), ("--skip-tracks", &self.skip)] {
+            let unmatched = patterns.unmatched(&names);
+            if !unmatched.is_empty() {
+                tracing::warn!("{} {} matched no track; the song has:\n - {}", flag, unmatched.join(", "), names.join("\n - "));
+            }
+        }
+
+        for (track, decision) in tracks.iter().zip(decisions.iter_mut()) {
+            if !self.selects(&track.name) {
+                *decision = StemDecision::SkipNotSelected;
+            }
+        }
+        if !tracks.is_empty() && decisions.iter().all(|d| *d == StemDecision::SkipNotSelected) {
+            return Err(anyhow!("The track filters leave none of the song's tracks:\n - {}", names.join("\n - ")));
+        }
+        for track in tracks.iter().filter(|t| is_click(&t.name) && self.skip.matches(&t.name)) {
+            tracing::warn!(
+                "--skip-tracks drops '{}'! The stems are lined up to the click; without its MP3 in the download folder processing can't pad the stems",
+                track.name
+            );
+        }
+        Ok(())
+    }
+}
+
+/// By its name in any storefront's language.
+fn is_click(name: &str) -> bool {
+    ClickDetection::default().is_click(name)
+}
+
+/// `name` and `pattern` are both lowercase.
+fn matches(pattern: &str, name: &str) -> bool {
+    if pattern.contains(['*', '?']) {
+        glob(&pattern.chars().collect::<Vec<_>>(), &name.chars().collect::<Vec<_>>())
+    } else {
+        name.contains(pattern)
+    }
+}
+
+pub(crate) fn glob(pattern: &[char], name: &[char]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some(('*', rest)) => (0..=name.len()).any(|skip| glob(rest, &name[skip..])),
+        Some(('?', rest)) => !name.is_empty() && glob(rest, &name[1..]),
+        Some((c, rest)) => name.first() == Some(c) && glob(rest, &name[1..]),
+    }
+}
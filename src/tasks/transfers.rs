@@ -0,0 +1,210 @@
+use crate::audit::{classify, FileKind};
+use anyhow::Result;
+use headless_chrome::protocol::cdp::types::Event;
+use headless_chrome::protocol::cdp::Browser::{
+    DownloadProgressEventStateOption, SetDownloadBehavior, SetDownloadBehaviorBehaviorOption,
+};
+use headless_chrome::Tab;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransferState {
+    InProgress,
+    Completed,
+    Canceled,
+}
+
+/// One download as Chrome reports it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transfer {
+    pub guid: String,
+    pub suggested_filename: String,
+    pub received_bytes: u64,
+    pub state: TransferState,
+}
+
+/// The downloads Chrome has started from a tab, by guid, kept up to date from the
+/// `Browser.downloadWillBegin` and `Browser.downloadProgress` events.
+#[derive(Clone, Default)]
+pub struct Transfers {
+    inner: Arc<(Mutex<HashMap<String, Transfer>>, Condvar)>,
+}
+
+impl Transfers {
+    /// Turns on the download events for `tab`, sending the downloads of its browser context
+    /// to `download_path`, and starts following them.
+    pub fn listen(&self, tab: &Tab, download_path: &str) -> Result<()> {
+        tab.call_method(SetDownloadBehavior {
+            browser_context_id: tab.get_browser_context_id()?,
+            behavior: SetDownloadBehaviorBehaviorOption::Allow,
+            download_path: Some(download_path.to_string()),
+            events_enabled: Some(true),
+        })?;
+        let transfers = self.clone();
+        tab.add_event_listener(Arc::new(move |event: &Event| match event {
+            Event::BrowserDownloadWillBegin(begin) => {
+                transfers.begin(&begin.params.guid, &begin.params.suggested_filename)
+            }
+            Event::BrowserDownloadProgress(progress) => {
+                let state = match progress.params.state {
+                    DownloadProgressEventStateOption::InProgress => TransferState::InProgress,
+                    DownloadProgressEventStateOption::Completed => TransferState::Completed,
+                    DownloadProgressEventStateOption::Canceled => TransferState::Canceled,
+                };
+                transfers.progress(&progress.params.guid, progress.params.received_bytes as u64, state)
+            }
+            _ => {}
+        }))?;
+        Ok(())
+    }
+
+    pub fn begin(&self, guid: &str, suggested_filename: &str) {
+        let (transfers, changed) = &*self.inner;
+        tracing::debug!("Download {} of {:?} began", guid, suggested_filename);
+        transfers.lock().unwrap().insert(
+            guid.to_string(),
+            Transfer {
+                guid: guid.to_string(),
+                suggested_filename: suggested_filename.to_string(),
+                received_bytes: 0,
+                state: TransferState::InProgress,
+            },
+        );
+        changed.notify_all();
+    }
+
+    /// Progress of a download. One that never announced itself is ignored, since it
+    /// can't be told apart from another tab's.
+    pub fn progress(&self, guid: &str, received_bytes: u64, state: TransferState) {
+        let (transfers, changed) = &*self.inner;
+        if let Some(transfer) = transfers.lock().unwrap().get_mut(guid) {
+            transfer.received_bytes = received_bytes;
+            transfer.state = state;
+        }
+        changed.notify_all();
+    }
+
+    /// Blocks until Chrome reports a download beginning or progressing, or `timeout` passes.
+    pub fn wait_for_change(&self, timeout: Duration) {
+        let (transfers, changed) = &*self.inner;
+        let transfers = transfers.lock().unwrap();
+        let _ = changed.wait_timeout(transfers, timeout).unwrap();
+    }
+
+    pub fn get(&self, guid: &str) -> Option<Transfer> {
+        self.inner.0.lock().unwrap().get(guid).cloned()
+    }
+
+    /// Every download seen so far, to tell later ones apart with [`Transfers::begun_since`].
+    pub fn guids(&self) -> Vec<String> {
+        let mut guids: Vec<String> = self.inner.0.lock().unwrap().keys().cloned().collect();
+        guids.sort();
+        guids
+    }
+
+    /// Downloads that began after `known` was taken from [`Transfers::guids`].
+    pub fn begun_since(&self, known: &[String]) -> Vec<Transfer> {
+        let mut begun: Vec<Transfer> = self
+            .inner
+            .0
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|t| !known.contains(&t.guid))
+            .cloned()
+            .collect();
+        begun.sort_by(|a, b| a.guid.cmp(&b.guid));
+        begun
+    }
+
+    /// Downloads that have neither completed nor been canceled.
+    pub fn in_flight(&self) -> Vec<Transfer> {
+        let mut in_flight: Vec<Transfer> = self
+            .inner
+            .0
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|t| t.state == TransferState::InProgress)
+            .cloned()
+            .collect();
+        in_flight.sort_by(|a, b| a.guid.cmp(&b.guid));
+        in_flight
+    }
+
+    /// Blocks until none of `guids` is in progress any more, or `timeout` passes. Returns
+    /// whether they all settled.
+    pub fn wait_until_settled(&self, guids: &[String], timeout: Duration) -> bool {
+        let (transfers, changed) = &*self.inner;
+        let transfers = transfers.lock().unwrap();
+        let (_transfers, result) = changed
+            .wait_timeout_while(transfers, timeout, |transfers| {
+                guids
+                    .iter()
+                    .any(|guid| transfers.get(guid).is_some_and(|t| t.state == TransferState::InProgress))
+            })
+            .unwrap();
+        !result.timed_out()
+    }
+}
+
+/// Tells a download that stopped making progress from one that is merely large: it only
+/// stalls after `timeout` passes without a byte more arriving.
+#[derive(Debug, Clone)]
+pub struct StallTimer {
+    timeout: Duration,
+    bytes: u64,
+    since: Instant,
+}
+
+impl StallTimer {
+    pub fn new(timeout: Duration, now: Instant) -> Self {
+        Self { timeout, bytes: 0, since: now }
+    }
+
+    /// Takes the download's byte count at `now`. Returns whether it has stalled.
+    pub fn observe(&mut self, bytes: u64, now: Instant) -> bool {
+        if bytes != self.bytes {
+            self.bytes = bytes;
+            self.since = now;
+            return false;
+        }
+        now.duration_since(self.since) > self.timeout
+    }
+}
+
+/// Deletes the unfinished downloads in `dir` written since `since`, along with any file
+/// named after one of the `canceled` transfers that appeared in that time, and returns
+/// what it removed. Files older than `since` belong to an earlier song and are left alone.
+pub fn remove_partials(dir: &Path, canceled: &[Transfer], since: SystemTime) -> Result<Vec<PathBuf>> {
+    let mut removed = Vec::new();
+    let mut entries: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.is_file())
+        .collect();
+    entries.sort();
+
+    for path in entries {
+        let Ok(modified) = fs::metadata(&path).and_then(|m| m.modified()) else {
+            continue;
+        };
+        if modified < since {
+            continue;
+        }
+        let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+        let from_canceled = canceled
+            .iter()
+            .any(|t| !t.suggested_filename.is_empty() && name.starts_with(&t.suggested_filename));
+        if classify(&path) == FileKind::Partial || from_canceled {
+            fs::remove_file(&path)?;
+            tracing::info!("Removed partial download {:?}", path);
+            removed.push(path);
+        }
+    }
+    Ok(removed)
+}
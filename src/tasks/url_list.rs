@@ -0,0 +1,58 @@
+use anyhow::{anyhow, Context, Result};
+use std::fs;
+use std::path::Path;
+
+/// The song URLs of a `--from-file` list: one per line, blank lines and lines starting
+/// with `#` skipped. Every URL must be a page on `site`, the storefront signed in to; the
+/// lines that aren't are all reported at once. A URL listed again is dropped with a note.
+pub fn parse(text: &str, site: &str) -> Result<Vec<String>> {
+    let mut urls: Vec<String> = Vec::new();
+    let mut invalid = Vec::new();
+    let mut duplicates = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let parsed = match url::Url::parse(line) {
+            Ok(parsed) if matches!(parsed.scheme(), "http" | "https") => parsed,
+            _ => {
+                invalid.push(format!("line {}: '{}' isn't a URL", number + 1, line));
+                continue;
+            }
+        };
+        let host = parsed.host_str().unwrap_or_default();
+        if !host.eq_ignore_ascii_case(site) {
+            invalid.push(format!("line {}: {} is on {}, not {}", number + 1, line, host, site));
+            continue;
+        }
+        if urls.iter().any(|url| same_url(url, line)) {
+            duplicates.push(line.to_string());
+            continue;
+        }
+        urls.push(line.to_string());
+    }
+    if !invalid.is_empty() {
+        return Err(anyhow!(
+            "Invalid song URLs (--domain picks the site):\n - {}",
+            invalid.join("\n - ")
+        ));
+    }
+    if !duplicates.is_empty() {
+        tracing::info!("Dropped {} URLs listed twice:\n - {}", duplicates.len(), duplicates.join("\n - "));
+    }
+    Ok(urls)
+}
+
+pub fn load(path: &Path, site: &str) -> Result<Vec<String>> {
+    let text = fs::read_to_string(path).with_context(|| format!("Unable to read the URL list {:?}", path))?;
+    let urls = parse(&text, site).with_context(|| format!("In {:?}", path))?;
+    if urls.is_empty() {
+        return Err(anyhow!("{:?} doesn't list any songs", path));
+    }
+    Ok(urls)
+}
+
+fn same_url(a: &str, b: &str) -> bool {
+    a.trim_end_matches('/').eq_ignore_ascii_case(b.trim_end_matches('/'))
+}
@@ -0,0 +1,203 @@
+mod common;
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::Path;
+
+use flate2::read::GzDecoder;
+use kv_downloader::audio::exporters::DawTargets;
+use kv_downloader::audio::{AudioProcessor, ProcessOptions};
+use roxmltree::{Document, Node};
+use common::{write_samples, RATE};
+
+/// Two seconds of mono clicks, one every half second.
+fn write_clicks(path: &Path) -> Result<(), Box<dyn Error>> {
+    let samples: Vec<i16> = (0..RATE * 2).map(|i| if i % (RATE / 2) < 100 { 20000 } else { 0 }).collect();
+    write_samples(path, 1, RATE, &samples)
+}
+
+fn value<'a>(node: Node<'a, '_>, path: &[&str]) -> Option<&'a str> {
+    let mut current = node;
+    for name in path {
+        current = current.children().find(|c| c.has_tag_name(*name))?;
+    }
+    current.attribute("Value")
+}
+
+/// The uncompressed XML of the set `generate_set` wrote.
+fn read_set(song_dir: &Path) -> Result<String, Box<dyn Error>> {
+    let mut xml = String::new();
+    GzDecoder::new(File::open(song_dir.join("MT PROJECT/Cherub Rock.als"))?).read_to_string(&mut xml)?;
+    Ok(xml)
+}
+
+/// The children of each element of `node`, by its path from the root, in the order of their
+/// first appearance.
+fn layout(node: Node, path: &str, children: &mut HashMap<String, Vec<String>>) {
+    let path = format!("{}/{}", path, node.tag_name().name());
+    for child in node.children().filter(Node::is_element) {
+        let names = children.entry(path.clone()).or_default();
+        let name = child.tag_name().name().to_string();
+        if !names.contains(&name) {
+            names.push(name);
+        }
+        layout(child, &path, children);
+    }
+}
+
+fn generate_set(song_dir: &Path, daws: &str) -> Result<(), Box<dyn Error>> {
+    let mono = song_dir.join("STEMS").join("WAV MONO");
+    fs::create_dir_all(&mono)?;
+    write_clicks(&mono.join("Click_mono.wav"))?;
+    write_clicks(&mono.join("Bass_mono.wav"))?;
+    write_clicks(&mono.join("Drum Kit_mono.wav"))?;
+
+    let options = ProcessOptions {
+        skip_fcpxml: true,
+        skip_midi: true,
+        daws: daws.parse()?,
+        ..Default::default()
+    };
+    let report = AudioProcessor::regenerate_projects(song_dir, &options)?;
+    assert!(report.is_clean(), "{:?}", report.warnings);
+    Ok(())
+}
+
+/// Checks the set against what Live reads from it rather than against a copy of the
+/// generator's own output: the version it's saved as, pointee ids that don't collide, and
+/// clips whose file references, lengths and rates are those of the WAVs on disk.
+#[test]
+fn writes_a_live_11_set_that_points_at_its_stems() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    let song_dir = tmp.path().join("Cherub Rock");
+    generate_set(&song_dir, "ableton")?;
+    assert!(!song_dir.join("MT PROJECT/Cherub Rock.rpp").exists());
+
+    // Live only opens sets that are gzipped XML
+    let set_path = song_dir.join("MT PROJECT/Cherub Rock.als");
+    let xml = read_set(&song_dir)?;
+    let doc = Document::parse(&xml)?;
+
+    let root = doc.root_element();
+    assert_eq!(root.tag_name().name(), "Ableton");
+    assert_eq!(root.attribute("MajorVersion"), Some("5"));
+    assert!(root.attribute("MinorVersion").is_some_and(|v| v.starts_with("11.")), "{:?}", root.attribute("MinorVersion"));
+    assert!(root.attribute("Creator").is_some_and(|v| v.starts_with("Ableton Live 11")));
+    let live_set = root.children().find(|n| n.has_tag_name("LiveSet")).expect("LiveSet");
+
+    // Every Id is unique, and below the next one Live hands out
+    let mut ids: Vec<u64> = doc.descendants().filter_map(|n| n.attribute("Id")).map(str::parse).collect::<Result<_, _>>()?;
+    let next: u64 = value(live_set, &["NextPointeeId"]).expect("NextPointeeId").parse()?;
+    assert!(ids.iter().all(|id| *id < next), "{:?} >= {}", ids, next);
+    let count = ids.len();
+    ids.sort();
+    ids.dedup();
+    assert_eq!(ids.len(), count, "duplicate Ids");
+
+    let tempo: f64 = value(live_set, &["MasterTrack", "DeviceChain", "Mixer", "Tempo", "Manual"]).expect("tempo").parse()?;
+    let tracks: Vec<_> = live_set
+        .children()
+        .find(|n| n.has_tag_name("Tracks"))
+        .expect("Tracks")
+        .children()
+        .filter(|n| n.has_tag_name("AudioTrack"))
+        .collect();
+    let mut names: Vec<_> = tracks.iter().map(|t| value(*t, &["Name", "UserName"]).unwrap()).collect();
+    names.sort();
+    assert_eq!(names, ["Bass", "Click", "Drum Kit"]);
+
+    for track in tracks {
+        let name = value(track, &["Name", "UserName"]).unwrap();
+        assert_eq!(value(track, &["Name", "EffectiveName"]), Some(name));
+        let pan = value(track, &["DeviceChain", "Mixer", "Pan", "Manual"]).unwrap();
+        // Centered unless the mix says otherwise
+        assert_eq!(pan, "0", "pan of {}", name);
+
+        let clip = track
+            .descendants()
+            .find(|n| n.has_tag_name("AudioClip"))
+            .unwrap_or_else(|| panic!("no clip on {}", name));
+        assert_eq!(value(clip, &["IsWarped"]), Some("false"));
+
+        // Type 1 paths are relative to the folder the set is in
+        let file_ref = |field| value(clip, &["SampleRef", "FileRef", field]);
+        assert_eq!(file_ref("RelativePathType"), Some("1"));
+        let relative = file_ref("RelativePath").unwrap();
+        let wav = set_path.parent().unwrap().join(relative);
+        assert!(wav.is_file(), "{} points at {:?}", name, wav);
+        assert_eq!(wav.file_stem().unwrap().to_string_lossy(), format!("{}_mono", name));
+        let absolute = file_ref("Path").unwrap();
+        assert_eq!(fs::canonicalize(absolute)?, fs::canonicalize(&wav)?);
+        assert_eq!(file_ref("OriginalFileSize"), Some(fs::metadata(&wav)?.len().to_string().as_str()));
+
+        let reader = hound::WavReader::open(&wav)?;
+        assert_eq!(value(clip, &["SampleRef", "DefaultDuration"]), Some(reader.duration().to_string().as_str()));
+        assert_eq!(value(clip, &["SampleRef", "DefaultSampleRate"]), Some(reader.spec().sample_rate.to_string().as_str()));
+        // The clip spans the whole file, in beats at the set's tempo
+        let end: f64 = value(clip, &["CurrentEnd"]).unwrap().parse()?;
+        let seconds = reader.duration() as f64 / reader.spec().sample_rate as f64;
+        assert!((end - seconds * tempo / 60.0).abs() < 1e-6, "{} ends at beat {}", name, end);
+    }
+    Ok(())
+}
+
+/// Checks the set against the skeleton of a Live 11 set: every element it writes is where Live
+/// puts it, in Live's order among its siblings, and the root claims the same version.
+#[test]
+fn follows_the_layout_of_a_live_11_set() -> Result<(), Box<dyn Error>> {
+    let skeleton = fs::read_to_string(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/ableton/live11-skeleton.xml"))?;
+    let skeleton = Document::parse(&skeleton)?;
+    let tmp = tempfile::tempdir()?;
+    let song_dir = tmp.path().join("Cherub Rock");
+    generate_set(&song_dir, "ableton")?;
+    let xml = read_set(&song_dir)?;
+    let generated = Document::parse(&xml)?;
+
+    for attribute in ["MajorVersion", "MinorVersion", "SchemaChangeCount", "Creator"] {
+        assert_eq!(
+            generated.root_element().attribute(attribute),
+            skeleton.root_element().attribute(attribute),
+            "{}",
+            attribute
+        );
+    }
+
+    let (mut live, mut ours) = (HashMap::new(), HashMap::new());
+    layout(skeleton.root_element(), "", &mut live);
+    layout(generated.root_element(), "", &mut ours);
+    for (path, children) in &ours {
+        let known = live.get(path).unwrap_or_else(|| panic!("Live writes no children in {}", path));
+        let mut positions = Vec::new();
+        for child in children {
+            let position = known.iter().position(|k| k == child);
+            positions.push(position.unwrap_or_else(|| panic!("Live writes no {} in {}", child, path)));
+        }
+        assert!(positions.is_sorted(), "{} has {:?}, Live writes {:?}", path, children, known);
+    }
+    Ok(())
+}
+
+#[test]
+fn reaper_only_by_default() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    let song_dir = tmp.path().join("Cherub Rock");
+    generate_set(&song_dir, "reaper")?;
+    assert!(song_dir.join("MT PROJECT/Cherub Rock.rpp").exists());
+    assert!(!song_dir.join("MT PROJECT/Cherub Rock.als").exists());
+    assert_eq!(DawTargets::default(), "reaper".parse().unwrap());
+    Ok(())
+}
+
+#[test]
+fn parses_daw_lists() {
+    let all = DawTargets {
+        reaper: true,
+        ableton: true,
+        dawproject: true,
+    };
+    assert_eq!("all".parse(), Ok(all));
+    assert_eq!("Reaper, ableton,dawproject".parse(), Ok(all));
+    assert!("logic".parse::<DawTargets>().is_err());
+}
@@ -0,0 +1,72 @@
+use kv_downloader::commands::auth::{AuthCheck, CredentialSource};
+use kv_downloader::tasks::sign_in::{find_email, SignInMethod};
+use serde_json::json;
+
+fn signed_in() -> AuthCheck {
+    AuthCheck {
+        ok: true,
+        domain: "www.karaoke-version.com".into(),
+        credentials: Some(CredentialSource::Keystore),
+        method: Some(SignInMethod::SavedCookie),
+        session_reused: Some(true),
+        account_email: Some("singer@example.com".into()),
+        custom_backing_tracks: Some(42),
+        error: None,
+    }
+}
+
+#[test]
+fn reports_the_check_as_json() {
+    let value = serde_json::to_value(signed_in()).unwrap();
+    assert_eq!(
+        value,
+        json!({
+            "ok": true,
+            "domain": "www.karaoke-version.com",
+            "credentials": "keystore",
+            "method": "saved-cookie",
+            "session_reused": true,
+            "account_email": "singer@example.com",
+            "custom_backing_tracks": 42,
+            "error": null,
+        })
+    );
+}
+
+#[test]
+fn reports_what_a_failed_check_got_to() {
+    let failed = AuthCheck {
+        domain: "www.karaoke-version.com".into(),
+        credentials: Some(CredentialSource::Environment),
+        error: Some("Login failed - unable to validate session".into()),
+        ..Default::default()
+    };
+    let text = failed.to_string();
+    assert!(text.contains("Credentials: KV_USERNAME and KV_PASSWORD"), "{}", text);
+    assert!(!text.contains("Signed in"), "{}", text);
+    assert!(text.ends_with("Authentication failed"), "{}", text);
+
+    let text = signed_in().to_string();
+    assert!(text.contains("Signed in with the saved session cookie"), "{}", text);
+    assert!(text.contains("Account: singer@example.com"), "{}", text);
+    assert!(text.contains("Custom backing tracks: 42"), "{}", text);
+}
+
+#[test]
+fn only_a_login_is_a_fresh_session() {
+    assert!(!SignInMethod::Login.reused_session());
+    for method in [
+        SignInMethod::ConnectedBrowser,
+        SignInMethod::CookiesFile,
+        SignInMethod::SavedCookie,
+        SignInMethod::ExistingSession,
+    ] {
+        assert!(method.reused_session(), "{:?}", method);
+    }
+}
+
+#[test]
+fn finds_the_account_email() {
+    assert_eq!(find_email("My account\nEmail: singer.one+kv@example.co.uk\nPassword"), Some("singer.one+kv@example.co.uk".into()));
+    assert_eq!(find_email("My account\nNo address here"), None);
+}
@@ -0,0 +1,158 @@
+mod common;
+
+use std::fs;
+use std::time::{Duration, Instant};
+
+use common::write_download;
+use kv_downloader::abort::AbortSignal;
+use kv_downloader::audio::{AudioProcessor, ProcessOptions, SongFolder};
+use kv_downloader::tasks::batch::{
+    prepare_staging, publish_staged, stage_song, staging_dir, Delay, RateLimiter, STAGING_DIR,
+};
+
+const CHERUB_ROCK: &str = "https://www.karaoke-version.com/custombackingtrack/the-smashing-pumpkins/cherub-rock.html";
+
+#[test]
+fn spaces_songs_out_across_workers() {
+    let limiter = RateLimiter::new(Duration::from_millis(200));
+    let abort = AbortSignal::default();
+    let start = Instant::now();
+    std::thread::scope(|scope| {
+        for _ in 0..3 {
+            scope.spawn(|| assert!(limiter.wait(&abort)));
+        }
+    });
+    // The first song starts at once, the other two a slot apart
+    let elapsed = start.elapsed();
+    assert!(elapsed >= Duration::from_millis(400), "{:?}", elapsed);
+    assert!(elapsed < Duration::from_millis(1000), "{:?}", elapsed);
+}
+
+#[test]
+fn stops_waiting_when_aborted() {
+    let limiter = RateLimiter::new(Duration::from_secs(60));
+    let abort = AbortSignal::default();
+    assert!(limiter.wait(&abort));
+    let start = Instant::now();
+    std::thread::scope(|scope| {
+        let waiting = scope.spawn(|| limiter.wait(&abort));
+        std::thread::sleep(Duration::from_millis(150));
+        abort.request();
+        assert!(!waiting.join().unwrap());
+    });
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+fn keeps_the_stems_an_interrupted_run_finished() -> Result<(), Box<dyn std::error::Error>> {
+    let root = tempfile::tempdir()?;
+    let staging = staging_dir(root.path(), CHERUB_ROCK);
+    assert_eq!(
+        staging,
+        root.path().join(STAGING_DIR).join("Cherub Rock - The Smashing Pumpkins")
+    );
+
+    fs::create_dir_all(&staging)?;
+    fs::write(staging.join("Cherub_Rock(Bass_Custom_Backing_Track).mp3"), b"done")?;
+    fs::write(staging.join("Cherub_Rock(Drum_Kit_Custom_Backing_Track).mp3.crdownload"), b"half")?;
+    prepare_staging(&staging)?;
+    let mut left: Vec<String> = fs::read_dir(&staging)?
+        .map(|e| e.map(|e| e.file_name().to_string_lossy().into_owned()))
+        .collect::<Result<_, _>>()?;
+    left.sort();
+    assert_eq!(left, ["Cherub_Rock(Bass_Custom_Backing_Track).mp3"]);
+    Ok(())
+}
+
+#[test]
+fn publishes_staged_songs_into_the_download_root() -> Result<(), Box<dyn std::error::Error>> {
+    let root = tempfile::tempdir()?;
+    let staging = staging_dir(root.path(), CHERUB_ROCK);
+    prepare_staging(&staging)?;
+
+    fs::create_dir(staging.join("Cherub Rock"))?;
+    fs::write(staging.join("Cherub Rock").join("Bass.wav"), b"RIFF")?;
+    let moved = publish_staged(&staging, root.path())?;
+    assert_eq!(moved, [root.path().join("Cherub Rock")]);
+    assert!(root.path().join("Cherub Rock").join("Bass.wav").exists());
+    assert!(!root.path().join(STAGING_DIR).exists());
+    Ok(())
+}
+
+#[test]
+fn leaves_a_song_staged_rather_than_overwrite_one() -> Result<(), Box<dyn std::error::Error>> {
+    let root = tempfile::tempdir()?;
+    fs::create_dir(root.path().join("Everlong"))?;
+    let staging = staging_dir(root.path(), "https://www.karaoke-version.com/custombackingtrack/foo-fighters/everlong.html");
+    prepare_staging(&staging)?;
+    fs::create_dir(staging.join("Everlong"))?;
+
+    assert!(publish_staged(&staging, root.path()).is_err());
+    assert!(staging.join("Everlong").exists());
+    Ok(())
+}
+
+#[test]
+fn finishes_an_unfinished_song_in_staging_and_leaves_the_root_alone() -> Result<(), Box<dyn std::error::Error>> {
+    let root = tempfile::tempdir()?;
+    // Titled from the name, without going to the page
+    let song = "cherub_rock";
+    // An earlier run left the song unfinished in the root, next to another song's MP3
+    fs::create_dir_all(root.path().join("Cherub Rock").join("STEMS"))?;
+    let stray = root.path().join("Foo_Fighters_Everlong(Drum_Kit_Custom_Backing_Track).mp3");
+    write_download(&stray, 1.0)?;
+    assert_eq!(AudioProcessor::song_folder(root.path(), song)?, SongFolder::Unfinished);
+
+    let staging = stage_song(root.path(), song)?;
+    assert_eq!(staging, staging_dir(root.path(), song));
+    assert!(staging.join("Cherub Rock").join("STEMS").is_dir());
+    assert_eq!(AudioProcessor::song_folder(root.path(), song)?, SongFolder::Missing);
+
+    // The download resumes from the stems it finished
+    write_download(&staging.join("Smashing_Pumpkins_Cherub_Rock(Click_Custom_Backing_Track).mp3"), 2.0)?;
+    fs::write(staging.join("Smashing_Pumpkins_Cherub_Rock(Bass_Custom_Backing_Track).mp3.crdownload"), b"half")?;
+    prepare_staging(&staging)?;
+    write_download(&staging.join("Smashing_Pumpkins_Cherub_Rock(Bass_Custom_Backing_Track).mp3"), 2.0)?;
+
+    let options = ProcessOptions {
+        skip_validation: true,
+        skip_rpp: true,
+        skip_fcpxml: true,
+        skip_midi: true,
+        ..Default::default()
+    };
+    AudioProcessor::process_downloads(&staging, song, &[], &options)?;
+    publish_staged(&staging, root.path())?;
+
+    assert_eq!(AudioProcessor::song_folder(root.path(), song)?, SongFolder::Processed);
+    assert!(root.path().join("Cherub Rock").join("STEMS").join("WAV ST").join("Bass.wav").exists());
+    assert!(!root.path().join(STAGING_DIR).exists());
+    // Only the song's own downloads were cleaned up
+    assert!(stray.exists());
+    Ok(())
+}
+
+#[test]
+fn jitters_each_delay_within_its_bounds() {
+    let delay = Delay::from_secs(0.5, 0.25);
+    let pauses: Vec<Duration> = (0..50).map(|_| delay.next()).collect();
+    assert!(pauses.iter().all(|p| (Duration::from_millis(500)..=Duration::from_millis(750)).contains(p)), "{:?}", pauses);
+    assert!(pauses.iter().any(|p| *p != pauses[0]), "no jitter in {:?}", pauses);
+
+    assert_eq!(Delay::from_secs(2.0, 0.0).next(), Duration::from_secs(2));
+    assert!(Delay::from_secs(0.0, 0.0).is_zero());
+    assert!(Delay::from_secs(-1.0, 0.0).is_zero());
+}
+
+#[test]
+fn spaces_songs_out_by_a_jittered_delay() {
+    let limiter = RateLimiter::with_delay(Delay::from_secs(0.1, 0.1));
+    let abort = AbortSignal::default();
+    let start = Instant::now();
+    for _ in 0..3 {
+        assert!(limiter.wait(&abort));
+    }
+    let elapsed = start.elapsed();
+    assert!(elapsed >= Duration::from_millis(200), "{:?}", elapsed);
+    assert!(elapsed < Duration::from_millis(1000), "{:?}", elapsed);
+}
@@ -0,0 +1,93 @@
+use std::error::Error;
+use std::fs;
+
+use kv_downloader::tasks::batch_report::{
+    BatchReport, Outcome, Stage, EXIT_ALL_FAILED, EXIT_SOME_FAILED, RETRY_LIST_FILE,
+};
+use kv_downloader::tasks::url_list;
+
+const SITE: &str = "www.karaoke-version.com";
+const ROSANNA: &str = "https://www.karaoke-version.com/custombackingtrack/toto/rosanna.html";
+const AFRICA: &str = "https://www.karaoke-version.com/custombackingtrack/toto/africa.html";
+const AJA: &str = "https://www.karaoke-version.com/custombackingtrack/steely-dan/aja.html";
+const PEG: &str = "https://www.karaoke-version.com/custombackingtrack/steely-dan/peg.html";
+
+fn mixed_batch() -> BatchReport {
+    let report = BatchReport::default();
+    report.record(ROSANNA, Outcome::Processed);
+    report.record(PEG, Outcome::Skipped);
+    report.record(
+        AFRICA,
+        Outcome::Failed {
+            stage: Stage::Download,
+            error: "Song not purchased:\n check your account".to_string(),
+        },
+    );
+    report.record(
+        AJA,
+        Outcome::Cancelled {
+            stage: Stage::Process,
+            error: "Cancelled".to_string(),
+        },
+    );
+    report
+}
+
+#[test]
+fn summarizes_the_batch_with_each_failure() {
+    let report = mixed_batch();
+    assert_eq!(report.processed(), 1);
+    assert_eq!(report.skipped(), 1);
+    assert_eq!(report.unfinished(), 2);
+    assert_eq!(
+        report.to_string(),
+        "1 succeeded, 1 skipped, 2 failed\n \
+         - Africa - Toto (download): Song not purchased: check your account\n \
+         - Aja - Steely Dan (cancelled during process): Cancelled"
+    );
+}
+
+#[test]
+fn retry_list_is_read_back_by_from_file() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    let path = mixed_batch().write_retry_list(tmp.path())?.expect("songs failed");
+    assert_eq!(path, tmp.path().join(RETRY_LIST_FILE));
+
+    let text = fs::read_to_string(&path)?;
+    assert!(text.contains("# Africa - Toto (download): Song not purchased: check your account\n"));
+    assert_eq!(url_list::parse(&text, SITE)?, [AFRICA, AJA]);
+    Ok(())
+}
+
+#[test]
+fn a_clean_batch_removes_the_old_retry_list() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    mixed_batch().write_retry_list(tmp.path())?;
+
+    let report = BatchReport::default();
+    report.record(AFRICA, Outcome::Processed);
+    assert_eq!(report.write_retry_list(tmp.path())?, None);
+    assert!(!tmp.path().join(RETRY_LIST_FILE).exists());
+    Ok(())
+}
+
+#[test]
+fn exit_code_tells_some_failures_from_all() {
+    let report = BatchReport::default();
+    report.record(ROSANNA, Outcome::Processed);
+    report.record(PEG, Outcome::Skipped);
+    assert_eq!(report.exit_code(), 0);
+
+    assert_eq!(mixed_batch().exit_code(), EXIT_SOME_FAILED);
+
+    let report = BatchReport::default();
+    report.record(PEG, Outcome::Skipped);
+    report.record(
+        AFRICA,
+        Outcome::Failed {
+            stage: Stage::Download,
+            error: "Session expired".to_string(),
+        },
+    );
+    assert_eq!(report.exit_code(), EXIT_ALL_FAILED);
+}
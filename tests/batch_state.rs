@@ -0,0 +1,85 @@
+use std::error::Error;
+use std::fs;
+
+use kv_downloader::tasks::batch_state::{BatchStateFile, Resume, SongStatus, BATCH_STATE_FILE};
+
+const ROSANNA: &str = "https://www.karaoke-version.com/custombackingtrack/toto/rosanna.html";
+const AFRICA: &str = "https://www.karaoke-version.com/custombackingtrack/toto/africa.html";
+const AJA: &str = "https://www.karaoke-version.com/custombackingtrack/steely-dan/aja.html";
+
+#[test]
+fn picks_up_where_an_earlier_run_left_off() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    let urls = [ROSANNA, AFRICA, AJA].map(String::from);
+    {
+        let state = BatchStateFile::open(tmp.path(), false)?;
+        state.update(|state| {
+            state.add_pending(&urls);
+            state.start(ROSANNA);
+            state.set(ROSANNA, SongStatus::Downloaded, None);
+            state.set(ROSANNA, SongStatus::Processed, None);
+            state.start(AFRICA);
+            state.set(AFRICA, SongStatus::Failed, Some("NotPurchased"));
+        })?;
+        // The run crashes here, before Aja
+    }
+
+    let state = BatchStateFile::open(tmp.path(), false)?;
+    assert_eq!(state.resume(ROSANNA, 3), Resume::Done);
+    assert_eq!(state.resume(AFRICA, 3), Resume::Run);
+    assert_eq!(state.resume(AJA, 3), Resume::Run);
+    // Once it has failed as often as allowed, the failure is left alone
+    assert_eq!(
+        state.resume(AFRICA, 1),
+        Resume::GaveUp {
+            attempts: 1,
+            error: Some("NotPurchased".to_string())
+        }
+    );
+
+    let snapshot = state.snapshot();
+    let africa = &snapshot.songs[AFRICA];
+    assert_eq!((africa.status, africa.attempts), (SongStatus::Failed, 1));
+    assert!(africa.updated_at.ends_with('Z'), "{}", africa.updated_at);
+    assert_eq!(snapshot.count(SongStatus::Pending), 1);
+
+    // Adding the list again keeps what's recorded
+    state.update(|state| state.add_pending(&urls))?;
+    assert_eq!(state.snapshot().songs[ROSANNA].status, SongStatus::Processed);
+    Ok(())
+}
+
+#[test]
+fn clears_the_error_once_a_retry_succeeds() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    let state = BatchStateFile::open(tmp.path(), false)?;
+    state.update(|state| {
+        state.start(AFRICA);
+        state.set(AFRICA, SongStatus::Failed, Some("timed out"));
+        state.start(AFRICA);
+        state.set(AFRICA, SongStatus::Processed, Some("ignored"));
+    })?;
+    let record = &state.snapshot().songs[AFRICA];
+    assert_eq!((record.status, record.attempts, record.error.as_deref()), (SongStatus::Processed, 2, None));
+    Ok(())
+}
+
+#[test]
+fn resets_and_replaces_the_file_whole() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    let state = BatchStateFile::open(tmp.path(), false)?;
+    state.update(|state| state.set(ROSANNA, SongStatus::Processed, None))?;
+    let saved = fs::read_to_string(tmp.path().join(BATCH_STATE_FILE))?;
+    assert!(saved.contains("\"status\": \"processed\""), "{}", saved);
+    // Nothing is left half-written next to it
+    assert_eq!(fs::read_dir(tmp.path())?.count(), 1);
+
+    let state = BatchStateFile::open(tmp.path(), true)?;
+    assert_eq!(state.resume(ROSANNA, 3), Resume::Run);
+    let saved = fs::read_to_string(tmp.path().join(BATCH_STATE_FILE))?;
+    assert!(!saved.contains(ROSANNA), "{}", saved);
+
+    fs::write(tmp.path().join(BATCH_STATE_FILE), "{ not json")?;
+    assert!(BatchStateFile::open(tmp.path(), false).is_err());
+    Ok(())
+}
@@ -0,0 +1,37 @@
+use kv_downloader::config::Config;
+use kv_downloader::driver::{HeadlessMode, Timeouts, WindowSize};
+use std::time::Duration;
+
+#[test]
+fn parses_the_window_size_and_headless_mode() {
+    assert_eq!("1920x1080".parse::<WindowSize>(), Ok(WindowSize { width: 1920, height: 1080 }));
+    assert_eq!(" 800 X 600 ".parse::<WindowSize>().map(<(u32, u32)>::from), Ok((800, 600)));
+    for invalid in ["1920", "1920x", "x1080", "1920x1080x2", "-1x600", "0x600", "20000x600"] {
+        assert!(invalid.parse::<WindowSize>().is_err(), "{} was taken", invalid);
+    }
+
+    assert_eq!("New".parse::<HeadlessMode>(), Ok(HeadlessMode::New));
+    assert_eq!("off".parse::<HeadlessMode>(), Ok(HeadlessMode::Off));
+    assert!("shell".parse::<HeadlessMode>().is_err());
+
+    let config = Config::parse("[download]\nheadless_mode = \"new\"\nwindow_size = \"1920x1080\"\n").unwrap();
+    assert_eq!(config.download.headless_mode, Some(HeadlessMode::New));
+    assert_eq!(config.download.window_size.map(|size| size.to_string()).as_deref(), Some("1920x1080"));
+    assert!(Config::parse("[download]\nwindow_size = \"big\"\n").is_err());
+}
+
+#[test]
+fn fills_in_the_timeouts_that_are_not_given() {
+    let timeouts = Timeouts::from_secs(Some(5), None, None);
+    assert_eq!(timeouts.element, Duration::from_secs(5));
+    assert_eq!(timeouts.navigation, Timeouts::default().navigation);
+    assert_eq!(timeouts.login, Timeouts::default().login);
+    assert_eq!(Timeouts::from_secs(None, None, None), Timeouts::default());
+
+    let config = Config::parse("[timeouts]\nelement_secs = 5\nlogin_secs = 90\n").unwrap();
+    assert_eq!(config.timeouts.element_secs, Some(5));
+    assert_eq!(config.timeouts.navigation_secs, None);
+    assert_eq!(config.timeouts.login_secs, Some(90));
+    assert!(Config::parse("[timeouts]\nelement_secs = 0\n").is_err());
+    assert_eq!(Config::parse("[timeouts]\npage_secs = 5\n").unwrap().unknown, ["timeouts.page_secs"]);
+}
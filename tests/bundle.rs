@@ -0,0 +1,210 @@
+mod common;
+
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use kv_downloader::audio::bundle::{self, BundleFormat, LIMITER_CEILING_DB, SET_LIST};
+use kv_downloader::audio::click::ClickDetection;
+use kv_downloader::audio::pipeline::Audio;
+use kv_downloader::audio::track_map::{TrackEntry, TrackMap};
+use common::{write_samples, RATE};
+
+fn read_wav(path: &Path) -> Result<(u16, Vec<i16>), Box<dyn Error>> {
+    let mut reader = hound::WavReader::open(path)?;
+    let channels = reader.spec().channels;
+    let samples = reader.samples::<i16>().collect::<Result<_, _>>()?;
+    Ok((channels, samples))
+}
+
+/// A click that ticks once every 100 frames and a quiet, steady bass, a tenth of a second long.
+fn click() -> Vec<i16> {
+    (0..4410).map(|i| if i % 100 == 0 { 20000 } else { 0 }).collect()
+}
+
+fn bass() -> Vec<i16> {
+    vec![3000; 4410]
+}
+
+/// A processed song folder with a click and a bass as mono stems and their stereo copies.
+fn song(root: &Path, title: &str) -> Result<PathBuf, Box<dyn Error>> {
+    song_with(root, title, &[("Click", click()), ("Bass", bass())])
+}
+
+fn song_with(root: &Path, title: &str, stems: &[(&str, Vec<i16>)]) -> Result<PathBuf, Box<dyn Error>> {
+    let song_dir = root.join(title);
+    let mono = song_dir.join("STEMS/WAV MONO");
+    let st = song_dir.join("STEMS/WAV ST");
+    fs::create_dir_all(&mono)?;
+    fs::create_dir_all(&st)?;
+    for (name, samples) in stems {
+        write_samples(&mono.join(format!("{}_mono.wav", name)), 1, RATE, samples)?;
+        let stereo: Vec<i16> = samples.iter().flat_map(|s| [*s, *s]).collect();
+        write_samples(&st.join(format!("{}.wav", name)), 2, RATE, &stereo)?;
+    }
+    Ok(song_dir)
+}
+
+fn file_names(dir: &Path) -> Result<Vec<String>, Box<dyn Error>> {
+    let mut names: Vec<String> = fs::read_dir(dir)?
+        .map(|e| e.map(|e| e.file_name().to_string_lossy().into_owned()))
+        .collect::<Result<_, _>>()?;
+    names.sort();
+    Ok(names)
+}
+
+#[test]
+fn parses_the_format() {
+    assert_eq!("lr-split".parse::<BundleFormat>(), Ok(BundleFormat::LrSplit));
+    assert_eq!("Stereo-Band".parse::<BundleFormat>(), Ok(BundleFormat::StereoBand));
+    assert_eq!(BundleFormat::default(), BundleFormat::LrSplit);
+    assert_eq!(BundleFormat::StereoBand.to_string(), "stereo-band");
+    assert!("surround".parse::<BundleFormat>().is_err());
+}
+
+#[test]
+fn puts_the_click_left_and_the_band_right() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    let songs = vec![song(tmp.path(), "Today")?, song(tmp.path(), "Mayonaise")?];
+    let out = tmp.path().join("gig");
+    bundle::write_bundle(&songs, &out, BundleFormat::LrSplit, &ClickDetection::default())?;
+
+    assert_eq!(file_names(&out)?, ["01 Today.wav", "02 Mayonaise.wav", SET_LIST]);
+    assert_eq!(fs::read_to_string(out.join(SET_LIST))?, "01 Today\n02 Mayonaise\n");
+
+    let (channels, samples) = read_wav(&out.join("01 Today.wav"))?;
+    assert_eq!(channels, 2);
+    let left: Vec<i16> = samples.iter().step_by(2).copied().collect();
+    let right: Vec<i16> = samples.iter().skip(1).step_by(2).copied().collect();
+    // The click is untouched, apart from the round trip through floating point
+    for (written, original) in left.iter().zip(click()) {
+        assert!((written - original).abs() <= 1, "{} vs {}", written, original);
+    }
+    assert_eq!(left.len(), 4410);
+    // The band is there, without any of the click in it
+    assert!(right.iter().all(|s| *s > 0), "the band channel has gaps");
+    assert!(right.iter().step_by(100).all(|s| *s < 3000), "the click leaked into the band");
+    Ok(())
+}
+
+#[test]
+fn keeps_the_band_in_stereo_as_its_own_file() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    let songs = vec![song(tmp.path(), "Zero")?];
+    let out = tmp.path().join("gig");
+    bundle::write_bundle(&songs, &out, BundleFormat::StereoBand, &ClickDetection::default())?;
+
+    assert_eq!(file_names(&out)?, ["01 Zero - Band.wav", "01 Zero - Click.wav", SET_LIST]);
+    let (click_channels, click_samples) = read_wav(&out.join("01 Zero - Click.wav"))?;
+    assert_eq!(click_channels, 1);
+    assert_eq!(click_samples.len(), 4410);
+    let (band_channels, band_samples) = read_wav(&out.join("01 Zero - Band.wav"))?;
+    assert_eq!(band_channels, 2);
+    assert_eq!(band_samples.len(), 4410 * 2);
+    Ok(())
+}
+
+#[test]
+fn limits_the_band_sum() {
+    let loud = Audio {
+        sample_rate: 44100,
+        channels: vec![vec![0.9; 4410]],
+    };
+    let mix = bundle::band_mix(&[loud.clone(), loud.clone(), loud], 1);
+    let ceiling = 10f32.powf(LIMITER_CEILING_DB as f32 / 20.0);
+    let peak = mix.channels[0].iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+    assert!(peak <= ceiling + 1e-6, "peak {} above the ceiling {}", peak, ceiling);
+    assert!(peak > ceiling * 0.9, "the limiter pulled the mix down to {}", peak);
+}
+
+#[test]
+fn numbers_long_sets_with_wider_prefixes() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    let one = song(tmp.path(), "Rocket")?;
+    let songs = vec![one; 100];
+    let out = tmp.path().join("gig");
+    bundle::write_bundle(&songs, &out, BundleFormat::LrSplit, &ClickDetection::default())?;
+
+    let set_list = fs::read_to_string(out.join(SET_LIST))?;
+    assert_eq!(set_list.lines().next(), Some("001 Rocket"));
+    assert_eq!(set_list.lines().last(), Some("100 Rocket"));
+    Ok(())
+}
+
+#[test]
+fn rejects_a_song_without_a_click() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    let song_dir = song(tmp.path(), "Soma")?;
+    fs::remove_file(song_dir.join("STEMS/WAV MONO/Click_mono.wav"))?;
+    let err = bundle::write_bundle(&[song_dir], &tmp.path().join("gig"), BundleFormat::LrSplit, &ClickDetection::default()).unwrap_err();
+    assert!(err.to_string().contains("No click"), "{}", err);
+    Ok(())
+}
+
+/// The right channel of an lr-split bundle of `song_dir` and its left, the click.
+fn split(song_dir: PathBuf, out: &Path, detection: &ClickDetection) -> Result<(Vec<i16>, Vec<i16>), Box<dyn Error>> {
+    let title = song_dir.file_name().unwrap_or_default().to_string_lossy().into_owned();
+    bundle::write_bundle(&[song_dir], out, BundleFormat::LrSplit, detection)?;
+    let (_, samples) = read_wav(&out.join(format!("01 {}.wav", title)))?;
+    let left = samples.iter().step_by(2).copied().collect();
+    let right = samples.iter().skip(1).step_by(2).copied().collect();
+    Ok((left, right))
+}
+
+#[test]
+fn finds_a_click_named_in_the_storefronts_language() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    let song_dir = song_with(tmp.path(), "Rosanna", &[("Klick", click()), ("Bass", bass())])?;
+    let (left, right) = split(song_dir, &tmp.path().join("gig"), &ClickDetection::default())?;
+    assert_eq!(left[0], 20000);
+    assert!(right.iter().all(|s| *s > 0), "the band channel has gaps");
+    Ok(())
+}
+
+#[test]
+fn takes_the_click_tracks_json_marks() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    // A band part named like a click, and the real one that isn't
+    let song_dir = song_with(tmp.path(), "Zero", &[("Clicky Guitar", bass()), ("Tick", click())])?;
+    let entry = |name: &str, is_click| TrackEntry {
+        mixer_name: Some(name.to_string()),
+        mixer_index: None,
+        original_filename: format!("Zero({}_Custom_Backing_Track).mp3", name),
+        stereo_file: format!("STEMS/WAV ST/{}.wav", name),
+        mono_file: format!("STEMS/WAV MONO/{}_mono.wav", name),
+        duration_secs: 0.1,
+        is_click,
+        stages: vec![],
+        decode_errors: 0,
+    };
+    TrackMap {
+        tracks: vec![entry("Tick", true), entry("Clicky Guitar", false)],
+    }
+    .save(&song_dir)?;
+
+    let (left, right) = split(song_dir.clone(), &tmp.path().join("gig"), &ClickDetection::default())?;
+    assert_eq!(left[0], 20000);
+    assert!(right.iter().all(|s| *s > 0), "the guitar was left out of the band");
+
+    // --click-track wins over the patterns when there's no map
+    fs::remove_file(song_dir.join("tracks.json"))?;
+    let detection = ClickDetection::default().with_track(Some("Tick".to_string()));
+    let (left, _) = split(song_dir, &tmp.path().join("gig2"), &detection)?;
+    assert_eq!(left[0], 20000);
+    Ok(())
+}
+
+#[test]
+fn leaves_the_full_mix_out_of_the_band() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    let plain = song(tmp.path(), "Today")?;
+    let with_mix = song_with(
+        tmp.path(),
+        "Mayonaise",
+        &[("Click", click()), ("Bass", bass()), ("Mayonaise (Full Mix)", vec![9000; 4410])],
+    )?;
+    let (_, plain_band) = split(plain, &tmp.path().join("gig"), &ClickDetection::default())?;
+    let (_, band) = split(with_mix, &tmp.path().join("gig2"), &ClickDetection::default())?;
+    assert_eq!(band, plain_band);
+    Ok(())
+}
@@ -0,0 +1,147 @@
+use std::cell::Cell;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use flate2::read::GzDecoder;
+use headless_chrome::protocol::cdp::types::Event;
+use kv_downloader::cdp_trace::{event_data, sanitize, CdpTrace, REDACTED, TRACE_VALUE_CAP};
+use serde_json::{json, Value};
+
+/// A writer the test can read back after handing it to the trace.
+#[derive(Clone, Default)]
+struct Shared(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Shared {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Shared {
+    fn records(&self) -> Vec<Value> {
+        let data = String::from_utf8(self.0.lock().unwrap().clone()).unwrap();
+        data.lines().map(|line| serde_json::from_str(line).unwrap()).collect()
+    }
+}
+
+#[test]
+fn redacts_credentials_and_cookies() {
+    let mut call = json!({
+        "expression": "document.querySelector('#password').value = 'hunter2'; login('me@example.com')",
+        "cookies": [{ "name": "PHPSESSID", "value": "abc123" }],
+        "headers": { "Set-Cookie": "PHPSESSID=abc123", "Accept": "text/html" },
+        "params": { "password": "hunter2", "authToken": "xyz" },
+    });
+    sanitize(&mut call, &["hunter2".to_string(), "me@example.com".to_string()]);
+
+    assert_eq!(
+        call["expression"],
+        format!("document.querySelector('#password').value = '{}'; login('{}')", REDACTED, REDACTED)
+    );
+    assert_eq!(call["cookies"], REDACTED);
+    assert_eq!(call["headers"]["Set-Cookie"], REDACTED);
+    assert_eq!(call["headers"]["Accept"], "text/html");
+    assert_eq!(call["params"]["password"], REDACTED);
+    assert_eq!(call["params"]["authToken"], REDACTED);
+}
+
+#[test]
+fn records_events_as_their_fields() -> Result<(), Box<dyn Error>> {
+    let event: Event = serde_json::from_value(json!({
+        "method": "Network.responseReceivedExtraInfo",
+        "params": {
+            "requestId": "1000.1",
+            "blockedCookies": [],
+            "headers": { "Set-Cookie": "PHPSESSID=abc123", "Content-Type": "text/html" },
+            "resourceIPAddressSpace": "Public",
+            "statusCode": 200,
+            "headersText": "HTTP/1.1 200 OK\r\nSet-Cookie: PHPSESSID=abc123\r\n",
+        },
+    }))?;
+    let out = Shared::default();
+    let trace = CdpTrace::to_writer(out.clone(), vec![]);
+    trace.record("event", "NetworkResponseReceivedExtraInfo", || json!({ "event": event_data(&event) }));
+
+    let params = &out.records()[0]["data"]["event"]["params"];
+    assert_eq!(params["statusCode"], 200);
+    assert_eq!(params["headers"]["Content-Type"], "text/html");
+    assert_eq!(params["headers"]["Set-Cookie"], REDACTED);
+    assert_eq!(params["headersText"], REDACTED);
+    Ok(())
+}
+
+#[test]
+fn caps_large_evaluation_results() {
+    let out = Shared::default();
+    let trace = CdpTrace::to_writer(out.clone(), vec![]);
+    let page = "<div>é</div>".repeat(2000);
+    trace.record("result", "Runtime.evaluate", || json!({ "value": page }));
+
+    let records = out.records();
+    let value = records[0]["data"]["value"].as_str().unwrap();
+    assert!(value.len() < TRACE_VALUE_CAP + 32, "{} bytes kept", value.len());
+    assert!(page.starts_with(value.split('…').next().unwrap()));
+    assert!(value.ends_with("more bytes]"), "{}", &value[value.len() - 40..]);
+}
+
+#[test]
+fn writes_one_json_line_per_record() {
+    let out = Shared::default();
+    let trace = CdpTrace::to_writer(out.clone(), vec![]);
+    trace.record("call", "find_element", || json!({ "selector": "div.mixer" }));
+    trace.record("error", "find_element", || json!("No element found"));
+    trace.finish().unwrap();
+    trace.record("call", "find_element", || json!({ "selector": "dropped" }));
+
+    let records = out.records();
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0]["kind"], "call");
+    assert_eq!(records[0]["name"], "find_element");
+    assert_eq!(records[0]["data"]["selector"], "div.mixer");
+    assert_eq!(records[1]["kind"], "error");
+}
+
+#[test]
+fn compresses_the_trace_into_the_debug_dir() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    let debug_dir = tmp.path().join("debug");
+    let url = "https://www.karaoke-version.com/custombackingtrack/the-smashing-pumpkins/cherub-rock.html";
+    let (trace, path) = CdpTrace::create(&debug_dir, url, vec!["hunter2".to_string()])?;
+    trace.record("call", "Runtime.evaluate", || json!({ "expression": "type('hunter2')" }));
+    trace.finish()?;
+
+    assert_eq!(path.parent(), Some(debug_dir.as_path()));
+    let name = path.file_name().unwrap().to_string_lossy().into_owned();
+    assert!(name.starts_with("cdp-trace-cherub-rock-") && name.ends_with(".ndjson.gz"), "{}", name);
+    let mut data = String::new();
+    GzDecoder::new(File::open(&path)?).read_to_string(&mut data)?;
+    assert_eq!(data.lines().count(), 2);
+    assert!(data.contains(url));
+    assert!(!data.contains("hunter2"));
+    assert_eq!(fs::read_dir(&debug_dir)?.count(), 1);
+    Ok(())
+}
+
+#[test]
+fn a_disabled_trace_builds_nothing() {
+    let trace = CdpTrace::default();
+    assert!(!trace.is_enabled());
+    let built = Cell::new(0);
+    let started = Instant::now();
+    for _ in 0..1_000_000 {
+        trace.record("call", "Runtime.evaluate", || {
+            built.set(built.get() + 1);
+            json!({ "expression": "true;" })
+        });
+    }
+    assert_eq!(built.get(), 0);
+    assert!(started.elapsed() < Duration::from_millis(500), "{:?}", started.elapsed());
+    trace.finish().unwrap();
+}
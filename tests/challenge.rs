@@ -0,0 +1,40 @@
+use kv_downloader::challenge::{detect, ChallengeDetected};
+
+#[test]
+fn detects_cloudflare_by_title_or_markup() {
+    assert_eq!(detect("Just a moment...", "<html></html>"), Some("just a moment"));
+    assert_eq!(
+        detect("karaoke-version.com", r#"<form id="challenge-form" action="/cdn-cgi/challenge-platform/h/b"></form>"#),
+        Some("id=\"challenge-form\"")
+    );
+    assert_eq!(detect("", r#"<div class="cf-turnstile" data-sitekey="x"></div>"#), Some("class=\"cf-turnstile\""));
+}
+
+#[test]
+fn detects_a_captcha_only_away_from_the_login_form() {
+    let captcha = r#"<script src="https://www.google.com/recaptcha/api.js"></script><div class="g-recaptcha"></div>"#;
+    assert!(detect("Security check", captcha).is_some());
+
+    let login = format!(r#"<form><input id="frm_login" name="login">{}</form>"#, captcha);
+    assert_eq!(detect("Log in", &login), None);
+}
+
+#[test]
+fn leaves_ordinary_pages_alone() {
+    assert_eq!(detect("Custom Backing Track - Queen", r#"<div class="mixer"></div>"#), None);
+    assert_eq!(detect("My downloads", r#"<table id="tab_files"></table>"#), None);
+}
+
+#[test]
+fn ignores_the_scripts_cloudflare_puts_in_every_page() {
+    let page = r#"<div class="mixer"></div><script>(function(){var a=document.createElement('script');a.src='/cdn-cgi/challenge-platform/scripts/jsd/main.js';document.getElementsByTagName('head')[0].appendChild(a);})();</script><iframe src="https://challenges.cloudflare.com/cdn-cgi/challenge-platform/h/b/turnstile/if/ov2/av0/rcv/0" hidden></iframe>"#;
+    assert_eq!(detect("Custom Backing Track - Queen", page), None);
+}
+
+#[test]
+fn suggests_running_with_a_window() {
+    let error = ChallengeDetected { url: "https://www.karaoke-version.com/my/login.html".into(), marker: "just a moment".into() };
+    let message = error.to_string();
+    assert!(message.contains("/my/login.html"), "{}", message);
+    assert!(message.contains("without --headless"), "{}", message);
+}
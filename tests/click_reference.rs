@@ -0,0 +1,114 @@
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use kv_downloader::audio::validation::{self, Mp3Probe, ReferenceSource};
+
+/// MPEG-1 Layer III, 128 kbps, 44.1 kHz, stereo, no CRC: 417-byte frames of 1152 samples.
+const FRAME_HEADER: [u8; 4] = [0xFF, 0xFB, 0x90, 0x64];
+const FRAME_LEN: usize = 417;
+
+/// An MP3 of `frames` frames that sync and parse but carry garbage that can't be decoded,
+/// optionally preceded by a Xing header claiming `claimed_frames` frames.
+fn write_undecodable_mp3(path: &Path, claimed_frames: Option<u32>, frames: usize) -> Result<(), Box<dyn Error>> {
+    let mut data = Vec::new();
+    if let Some(claimed_frames) = claimed_frames {
+        let mut xing = vec![0u8; FRAME_LEN];
+        xing[..4].copy_from_slice(&FRAME_HEADER);
+        xing[36..40].copy_from_slice(b"Xing");
+        xing[40..44].copy_from_slice(&1u32.to_be_bytes());
+        xing[44..48].copy_from_slice(&claimed_frames.to_be_bytes());
+        data.extend(xing);
+    }
+    for i in 0..frames {
+        let mut frame = vec![0u8; FRAME_LEN];
+        frame[..4].copy_from_slice(&FRAME_HEADER);
+        // main_data_begin pointing far into a bit reservoir that doesn't exist
+        frame[4] = 0xFF;
+        frame[5] = 0x80;
+        for (j, byte) in frame.iter_mut().enumerate().skip(6) {
+            *byte = (i * 31 + j * 17) as u8;
+        }
+        data.extend(frame);
+    }
+    fs::write(path, data)?;
+    Ok(())
+}
+
+fn secs(s: u64) -> Duration {
+    Duration::from_secs(s)
+}
+
+#[test]
+fn fixture_decodes_to_nothing_but_has_a_header_duration() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    let path = tmp.path().join("Song(Click_Custom_Backing_Track).mp3");
+    write_undecodable_mp3(&path, Some(2000), 200)?;
+
+    let probe = Mp3Probe::probe(&path)?;
+    assert_eq!(probe.decoded_duration, Duration::ZERO);
+    assert_eq!(probe.decode_errors, 200);
+
+    let expected = Duration::from_secs_f64(2000.0 * 1152.0 / 44100.0);
+    assert_eq!(validation::header_duration(&path)?, Some(expected));
+    Ok(())
+}
+
+#[test]
+fn decoded_click_is_the_normal_reference() {
+    let resolved = validation::resolve_reference(
+        secs(200),
+        Some(secs(100)),
+        || panic!("header shouldn't be probed"),
+        || panic!("stems shouldn't be decoded"),
+    );
+    assert_eq!(resolved.unwrap(), (secs(200), ReferenceSource::Click));
+}
+
+#[test]
+fn falls_back_through_header_then_stems() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    let with_header = tmp.path().join("with_header.mp3");
+    let without_header = tmp.path().join("without_header.mp3");
+    write_undecodable_mp3(&with_header, Some(2000), 50)?;
+    write_undecodable_mp3(&without_header, None, 50)?;
+    let stems = || vec![secs(200), Duration::from_millis(100), secs(210), secs(205)];
+
+    let (reference, source) = validation::resolve_reference(
+        Duration::ZERO,
+        None,
+        || validation::header_duration(&with_header).ok().flatten(),
+        || panic!("stems shouldn't be decoded"),
+    )?;
+    assert_eq!(source, ReferenceSource::ClickHeader);
+    assert_eq!(reference.as_secs(), 52);
+
+    let resolved = validation::resolve_reference(
+        Duration::ZERO,
+        None,
+        || validation::header_duration(&without_header).ok().flatten(),
+        stems,
+    )?;
+    assert_eq!(resolved, (secs(205), ReferenceSource::StemMedian));
+
+    // an explicit override wins over the guesses
+    let resolved = validation::resolve_reference(Duration::ZERO, Some(secs(180)), || None, stems)?;
+    assert_eq!(resolved, (secs(180), ReferenceSource::Override));
+
+    assert!(validation::resolve_reference(Duration::from_millis(10), None, || None, Vec::new).is_err());
+    Ok(())
+}
+
+#[test]
+fn validation_measures_stems_against_the_fallback() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    let click = tmp.path().join("Song(Click_Custom_Backing_Track).mp3");
+    write_undecodable_mp3(&click, Some(2000), 50)?;
+
+    let err = validation::validate_tracks(&click, &[], None).unwrap_err();
+    assert!(err.to_string().contains("Click"), "{}", err);
+
+    validation::validate_tracks(&click, &[], Some(secs(52)))?;
+    Ok(())
+}
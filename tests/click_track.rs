@@ -0,0 +1,105 @@
+mod common;
+
+use std::error::Error;
+use std::path::PathBuf;
+
+use kv_downloader::audio::click::{self, ClickDetection, ClickMatch};
+use kv_downloader::audio::track_map::TrackMap;
+use kv_downloader::audio::{AudioProcessor, ProcessOptions};
+use kv_downloader::config::Config;
+use common::write_download;
+
+fn download(part: &str) -> PathBuf {
+    PathBuf::from(format!("Toto_Rosanna({}_Custom_Backing_Track).mp3", part))
+}
+
+fn names(parts: &[&str]) -> Vec<String> {
+    parts.iter().map(|s| s.to_string()).collect()
+}
+
+#[test]
+fn recognizes_localized_click_names() -> Result<(), Box<dyn Error>> {
+    for part in ["Klick", "Claqueta", "Métronome", "Metronomo", "Click"] {
+        let tracks = click::find(vec![download("Bass"), download(part)], &ClickDetection::default(), &[])?;
+        assert_eq!(tracks.reference, download(part), "{}", part);
+        assert_eq!(tracks.click, ClickMatch::Pattern);
+        assert_eq!(tracks.others, [download("Bass")]);
+    }
+    Ok(())
+}
+
+#[test]
+fn falls_back_to_the_full_mix_then_the_mixer_order() -> Result<(), Box<dyn Error>> {
+    let paths = vec![download("Bass"), download("Taktgeber"), PathBuf::from("Rosanna (Full Mix).mp3")];
+    let tracks = click::find(paths.clone(), &ClickDetection::default(), &names(&["Taktgeber", "Bass"]))?;
+    assert_eq!(tracks.click, ClickMatch::FullMix);
+
+    let tracks = click::find(paths[..2].to_vec(), &ClickDetection::default(), &names(&["Taktgeber", "Bass"]))?;
+    assert_eq!(tracks.click, ClickMatch::MixerOrder);
+    assert_eq!(tracks.reference, download("Taktgeber"));
+
+    let tracks = click::find(paths[..2].to_vec(), &ClickDetection::default(), &[])?;
+    assert_eq!(tracks.click, ClickMatch::None);
+    assert_eq!(tracks.others.len(), 1);
+    Ok(())
+}
+
+#[test]
+fn click_track_overrides_detection() -> Result<(), Box<dyn Error>> {
+    let detection = ClickDetection::default().with_track(Some("cowbell".to_string()));
+    let tracks = click::find(vec![download("Click"), download("Cowbell")], &detection, &[])?;
+    assert_eq!(tracks.reference, download("Cowbell"));
+    assert_eq!(tracks.click, ClickMatch::Named);
+
+    let err = click::find(vec![download("Click")], &detection, &[]).unwrap_err();
+    assert!(err.to_string().contains("--click-track 'cowbell'"), "{}", err);
+    Ok(())
+}
+
+#[test]
+fn patterns_come_from_the_config() -> Result<(), Box<dyn Error>> {
+    let config = Config::parse("[click]\npatterns = [\"taktgeber\"]\n")?;
+    let tracks = click::find(vec![download("Bass"), download("Taktgeber")], &config.click, &[])?;
+    assert_eq!(tracks.click, ClickMatch::Pattern);
+    assert!(Config::parse("[click]\npatterns = [\"\"]\n").is_err());
+    Ok(())
+}
+
+fn options() -> ProcessOptions {
+    ProcessOptions {
+        skip_fcpxml: true,
+        skip_midi: true,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn processes_a_song_with_a_localized_click() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    write_download(&tmp.path().join(download("Klick")), 2.0)?;
+    write_download(&tmp.path().join(download("Bass")), 1.5)?;
+    let report = AudioProcessor::process_downloads(tmp.path(), "rosanna", &[], &options())?;
+    assert!(report.warnings.is_empty(), "{:?}", report.warnings);
+
+    let map = TrackMap::load(&tmp.path().join("Rosanna"))?.expect("no track map");
+    let clicks: Vec<_> = map.tracks.iter().filter(|t| t.is_click).map(|t| t.stereo_file.as_str()).collect();
+    assert_eq!(clicks, ["STEMS/WAV ST/Klick.wav"]);
+    let bass = map.tracks.iter().find(|t| t.stereo_file.ends_with("Bass.wav")).expect("no bass");
+    assert_eq!(bass.duration_secs, 2.0);
+    Ok(())
+}
+
+#[test]
+fn processes_a_song_without_a_click_unpadded() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    write_download(&tmp.path().join(download("Drums")), 2.0)?;
+    write_download(&tmp.path().join(download("Bass")), 1.5)?;
+    let report = AudioProcessor::process_downloads(tmp.path(), "rosanna", &[], &options())?;
+    assert!(report.warnings.iter().any(|w| w.contains("No click track")), "{:?}", report.warnings);
+
+    let map = TrackMap::load(&tmp.path().join("Rosanna"))?.expect("no track map");
+    assert!(map.tracks.iter().all(|t| !t.is_click));
+    let bass = map.tracks.iter().find(|t| t.stereo_file.ends_with("Bass.wav")).expect("no bass");
+    assert_eq!(bass.duration_secs, 1.5);
+    Ok(())
+}
@@ -0,0 +1,62 @@
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+
+/// A config file the binary warns about, so every run below logs something, and whose
+/// keystore has no credentials.
+fn noisy_config(dir: &Path) -> Result<String, Box<dyn Error>> {
+    let path = dir.join("config.toml");
+    fs::write(&path, "not_a_setting = 1\n\n[keystore]\nbackend = \"command\"\ncommand = [\"false\"]\n")?;
+    Ok(path.to_string_lossy().into_owned())
+}
+
+/// Runs the binary with `args` and no credentials from the environment.
+fn run(args: &[&str]) -> Result<Output, Box<dyn Error>> {
+    let output = Command::new(env!("CARGO_BIN_EXE_kv_downloader"))
+        .args(args)
+        .env_remove("KV_USERNAME")
+        .env_remove("KV_PASSWORD")
+        .output()?;
+    Ok(output)
+}
+
+#[test]
+fn list_logs_to_stderr_only() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    let output = run(&["list", "--json", "--config", &noisy_config(tmp.path())?])?;
+
+    // Without credentials it stops before listing, having warned about the config
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)?.contains("not_a_setting"));
+    assert!(output.stdout.is_empty(), "{}", String::from_utf8_lossy(&output.stdout));
+    Ok(())
+}
+
+#[test]
+fn auth_check_prints_only_json() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    let output = run(&["auth", "--config", &noisy_config(tmp.path())?, "check", "--json"])?;
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)?.contains("not_a_setting"));
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    assert_eq!(report["ok"], false);
+    assert!(report["error"].as_str().unwrap().contains("Authentication required"), "{}", report);
+    Ok(())
+}
+
+#[test]
+fn verify_prints_only_json() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    let song = tmp.path().join("Cherub Rock");
+    fs::create_dir_all(song.join("STEMS"))?;
+    let output = run(&["verify", "--json", "--config", &noisy_config(tmp.path())?, &song.to_string_lossy()])?;
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)?.contains("not_a_setting"));
+    let checks: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    assert_eq!(checks[0]["passed"], false);
+    assert!(checks[0]["findings"].as_array().is_some_and(|findings| !findings.is_empty()), "{}", checks);
+    Ok(())
+}
@@ -0,0 +1,49 @@
+//! WAV writers the integration tests share. Each test binary uses only some of them.
+#![allow(dead_code)]
+
+use std::error::Error;
+use std::path::Path;
+
+use hound::{SampleFormat, WavSpec, WavWriter};
+
+/// The rate the site serves stems at.
+pub const RATE: u32 = 44100;
+
+/// Writes 16-bit `samples`, interleaved over `channels`.
+pub fn write_samples(path: &Path, channels: u16, sample_rate: u32, samples: &[i16]) -> Result<(), Box<dyn Error>> {
+    let spec = WavSpec {
+        channels,
+        sample_rate,
+        bits_per_sample: 16,
+        sample_format: SampleFormat::Int,
+    };
+    let mut writer = WavWriter::create(path, spec)?;
+    for sample in samples {
+        writer.write_sample(*sample)?;
+    }
+    writer.finalize()?;
+    Ok(())
+}
+
+/// `seconds` of a 220 Hz tone at `RATE`, the same in every channel.
+pub fn write_tone(path: &Path, channels: u16, seconds: f64) -> Result<(), Box<dyn Error>> {
+    let samples: Vec<i16> = (0..(seconds * RATE as f64) as u32)
+        .flat_map(|i| {
+            let sample = ((i as f32 * 220.0 * std::f32::consts::TAU / RATE as f32).sin() * 8000.0) as i16;
+            std::iter::repeat_n(sample, channels as usize)
+        })
+        .collect();
+    write_samples(path, channels, RATE, &samples)
+}
+
+/// A stem as the site serves it, decoded: stereo at `RATE`. The tests write WAVs under
+/// the MP3 names, which the decoder reads by their content.
+pub fn write_download(path: &Path, seconds: f64) -> Result<(), Box<dyn Error>> {
+    write_tone(path, 2, seconds)
+}
+
+/// `frames` of a sawtooth, for tests that only need a WAV of a given length and layout.
+pub fn write_ramp(path: &Path, channels: u16, sample_rate: u32, frames: u32) -> Result<(), Box<dyn Error>> {
+    let samples: Vec<i16> = (0..frames * channels as u32).map(|i| ((i % 100) as i16 - 50) * 100).collect();
+    write_samples(path, channels, sample_rate, &samples)
+}
@@ -0,0 +1,30 @@
+// The one test of its binary: setting a variable while other tests' commands read the
+// environment would race them.
+
+use anyhow::Result;
+use clap::{value_parser, Arg, Command};
+use kv_downloader::config::{self, Config};
+
+#[test]
+fn reads_a_flag_from_its_environment_variable() -> Result<()> {
+    let command = || {
+        Command::new("kv-downloader")
+            .subcommand(Command::new("download").arg(Arg::new("delay").long("delay").value_parser(value_parser!(f64))))
+    };
+    let flags = Config::parse("[download]\ndelay = 5.0\n")?.flags;
+
+    // Read as the command is built, so it's set before
+    std::env::set_var("KV_DELAY", "8");
+    let (with_variable, _) = config::with_flag_defaults(command(), &flags, None);
+    std::env::remove_var("KV_DELAY");
+    let matches = with_variable.get_matches_from(["kv-downloader", "download"]);
+    assert_eq!(matches.subcommand_matches("download").unwrap().get_one::<f64>("delay"), Some(&8.0));
+
+    // A flag given still wins over it
+    std::env::set_var("KV_DELAY", "8");
+    let (with_variable, _) = config::with_flag_defaults(command(), &flags, None);
+    std::env::remove_var("KV_DELAY");
+    let matches = with_variable.get_matches_from(["kv-downloader", "download", "--delay", "2"]);
+    assert_eq!(matches.subcommand_matches("download").unwrap().get_one::<f64>("delay"), Some(&2.0));
+    Ok(())
+}
@@ -0,0 +1,82 @@
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use clap::{value_parser, Arg, ArgAction, Command};
+use kv_downloader::config::{self, Config};
+
+const CONFIG: &str = r#"
+[download]
+keep_mp3s = true
+transpose = -2
+timeout_secs = 40
+
+[project]
+daw = ["reaper", "ableton"]
+
+[driver]
+chrome-path = "/opt/chrome/chrome"
+no_such_flag = 1
+"#;
+
+fn command() -> Command {
+    Command::new("kv-downloader").subcommand(
+        Command::new("download")
+            .arg(Arg::new("keep_mp3s").long("keep-mp3s").action(ArgAction::SetTrue))
+            .arg(Arg::new("transpose").long("transpose").value_parser(value_parser!(i8)).allow_hyphen_values(true))
+            .arg(Arg::new("daw").long("daw").value_delimiter(','))
+            .arg(Arg::new("chrome_path").long("chrome-path").value_parser(value_parser!(PathBuf)))
+            .arg(Arg::new("delay").long("delay").value_parser(value_parser!(f64)))
+            .arg(Arg::new("config").long("config").value_parser(value_parser!(PathBuf))),
+    )
+}
+
+#[test]
+fn takes_the_flag_tables_apart_from_the_settings() -> Result<()> {
+    let config = Config::parse(CONFIG)?;
+    assert_eq!(config.download.timeout_secs, Some(40));
+    let keys: Vec<&str> = config.flags.iter().map(|flag| flag.key.as_str()).collect();
+    assert_eq!(keys, ["download.keep_mp3s", "download.transpose", "project.daw", "driver.chrome-path", "driver.no_such_flag"]);
+    assert_eq!(config.flags[3].id, "chrome_path");
+    assert_eq!(config.flags[2].values(), Some(vec!["reaper".to_string(), "ableton".to_string()]));
+    assert!(config.unknown.is_empty(), "{:?}", config.unknown);
+    Ok(())
+}
+
+#[test]
+fn makes_the_file_the_flags_defaults() -> Result<()> {
+    let config = Config::parse(CONFIG)?;
+    let file = Path::new("/home/singer/.config/kv-downloader/config.toml");
+    let (command, unused) = config::with_flag_defaults(command(), &config.flags, Some(file));
+    assert_eq!(unused, ["driver.no_such_flag"]);
+
+    let matches = command.clone().get_matches_from(["kv-downloader", "download"]);
+    let download = matches.subcommand_matches("download").unwrap();
+    assert!(download.get_flag("keep_mp3s"));
+    assert_eq!(download.get_one::<i8>("transpose"), Some(&-2));
+    assert_eq!(download.get_many::<String>("daw").unwrap().collect::<Vec<_>>(), ["reaper", "ableton"]);
+    assert_eq!(download.get_one::<PathBuf>("chrome_path").unwrap(), Path::new("/opt/chrome/chrome"));
+    assert_eq!(download.get_one::<PathBuf>("config").unwrap(), file);
+
+    // A flag given wins over the file
+    let matches = command.get_matches_from(["kv-downloader", "download", "--transpose", "3"]);
+    assert_eq!(matches.subcommand_matches("download").unwrap().get_one::<i8>("transpose"), Some(&3));
+    Ok(())
+}
+
+#[test]
+fn takes_the_files_default_without_an_environment_variable() -> Result<()> {
+    let (command, _) = config::with_flag_defaults(command(), &Config::parse("[download]\ndelay = 5.0\n")?.flags, None);
+    let matches = command.get_matches_from(["kv-downloader", "download"]);
+    assert_eq!(matches.subcommand_matches("download").unwrap().get_one::<f64>("delay"), Some(&5.0));
+    Ok(())
+}
+
+#[test]
+fn finds_the_config_flag_before_parsing() {
+    let args = |args: &[&str]| args.iter().map(OsString::from).collect::<Vec<_>>();
+    assert_eq!(config::path_in_args(&args(&["kv", "download", "--config", "kv.toml"])), Some(PathBuf::from("kv.toml")));
+    assert_eq!(config::path_in_args(&args(&["kv", "process", "--config=kv.toml"])), Some(PathBuf::from("kv.toml")));
+    assert_eq!(config::path_in_args(&args(&["kv", "download", "--", "--config"])), None);
+    assert_eq!(config::path_in_args(&args(&["kv", "download", "https://example.com"])), None);
+}
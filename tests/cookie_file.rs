@@ -0,0 +1,68 @@
+use std::fs;
+
+use headless_chrome::protocol::cdp::Network::{Cookie, CookiePriority, CookieSourceScheme};
+use kv_downloader::keystore::{belongs_to, CookieFile};
+
+const SITE: &str = "www.karaoke-version.com";
+
+fn cookie(name: &str, domain: &str, expires: f64) -> Cookie {
+    Cookie {
+        name: name.to_string(),
+        value: format!("{}-value", name),
+        domain: domain.to_string(),
+        path: "/".to_string(),
+        expires,
+        size: 10,
+        http_only: true,
+        secure: true,
+        session: expires < 0.0,
+        same_site: None,
+        priority: CookiePriority::Medium,
+        same_party: false,
+        source_scheme: CookieSourceScheme::Secure,
+        source_port: 443,
+        partition_key: None,
+        partition_key_opaque: None,
+    }
+}
+
+#[test]
+fn keeps_the_whole_jar_of_the_site_only() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = CookieFile::new(dir.path().join("state/cookies.json"));
+    let saved = file
+        .save(
+            SITE,
+            vec![
+                cookie("karaoke-version", "www.karaoke-version.com", -1.0),
+                cookie("consent", ".karaoke-version.com", 4_000_000_000.0),
+                cookie("tracker", ".ads.example", 4_000_000_000.0),
+                cookie("old", ".karaoke-version.com", 1_000.0),
+            ],
+        )
+        .unwrap();
+    assert_eq!(saved, 3);
+
+    let names: Vec<String> = file.load(SITE).unwrap().into_iter().map(|cookie| cookie.name).collect();
+    // The expired one is left to the password sign-in to replace
+    assert_eq!(names, ["karaoke-version", "consent"]);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        assert_eq!(fs::metadata(file.path()).unwrap().permissions().mode() & 0o777, 0o600);
+    }
+
+    assert!(file.load("www.karaoke-version.co.uk").unwrap().is_empty());
+    assert!(CookieFile::new(dir.path().join("missing.json")).load(SITE).unwrap().is_empty());
+    fs::write(file.path(), "not json").unwrap();
+    assert!(file.load(SITE).is_err());
+}
+
+#[test]
+fn tells_the_site_cookies_by_domain() {
+    assert!(belongs_to(".karaoke-version.com", SITE));
+    assert!(belongs_to("www.karaoke-version.com", SITE));
+    assert!(belongs_to("karaoke-version.com", "karaoke-version.com"));
+    assert!(!belongs_to(".karaoke-version.co.uk", SITE));
+    assert!(!belongs_to("notkaraoke-version.com", SITE));
+}
@@ -0,0 +1,92 @@
+mod common;
+
+use std::error::Error;
+use std::path::Path;
+use std::time::Duration;
+
+use kv_downloader::audio::validation::{check_count_in, CountInMismatch};
+use kv_downloader::audio::{AudioProcessor, ProcessOptions};
+use kv_downloader::metadata::SongInfo;
+use common::write_download;
+
+fn secs(s: f64) -> Duration {
+    Duration::from_secs_f64(s)
+}
+
+/// A click two bars at 120 BPM longer than the bass.
+fn downloads(root: &Path) -> Result<(), Box<dyn Error>> {
+    for (part, seconds) in [("Click", 5.0), ("Bass", 1.0)] {
+        let name = format!("Smashing_Pumpkins_Cherub_Rock({}_Custom_Backing_Track).mp3", part);
+        write_download(&root.join(name), seconds)?;
+    }
+    Ok(())
+}
+
+fn options(count_in_bars: u32) -> ProcessOptions {
+    ProcessOptions {
+        skip_rpp: true,
+        skip_fcpxml: true,
+        skip_midi: true,
+        count_in_bars,
+        song_info: Some(SongInfo {
+            url: "https://www.karaoke-version.com/custombackingtrack/smashing-pumpkins/cherub-rock.html".to_string(),
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn the_click_is_longer_exactly_with_a_count_in() {
+    assert_eq!(check_count_in(secs(184.0), secs(180.0), true), Ok(secs(4.0)));
+    // MP3 framing isn't a count-in
+    assert_eq!(check_count_in(secs(180.3), secs(180.0), false), Ok(Duration::ZERO));
+    assert_eq!(check_count_in(secs(179.0), secs(180.0), false), Ok(Duration::ZERO));
+
+    let missing = check_count_in(secs(180.2), secs(180.0), true).unwrap_err();
+    assert!(missing.requested);
+    assert!(missing.to_string().contains("toggle may not have registered"), "{}", missing);
+    assert_eq!(
+        check_count_in(secs(184.0), secs(180.0), false),
+        Err(CountInMismatch {
+            requested: false,
+            measured: secs(4.0),
+        })
+    );
+}
+
+#[test]
+fn records_the_measured_count_in() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    downloads(tmp.path())?;
+    let report = AudioProcessor::process_downloads(tmp.path(), "cherub_rock", &[], &options(2))?;
+    assert!(report.is_clean(), "{:?}", report.warnings);
+
+    let info = SongInfo::load(&tmp.path().join("Cherub Rock"))?.expect("no song.json");
+    assert_eq!(info.count_in_secs, Some(4.0));
+    Ok(())
+}
+
+#[test]
+fn warns_of_a_count_in_that_wasnt_asked_for() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    downloads(tmp.path())?;
+    let report = AudioProcessor::process_downloads(tmp.path(), "cherub_rock", &[], &options(0))?;
+    assert_eq!(report.warnings.len(), 1);
+    assert!(report.warnings[0].contains("has a count-in"), "{}", report.warnings[0]);
+    Ok(())
+}
+
+#[test]
+fn strict_count_in_fails_the_song() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    downloads(tmp.path())?;
+    let options = ProcessOptions {
+        strict_count_in: true,
+        ..options(0)
+    };
+    let err = AudioProcessor::process_downloads(tmp.path(), "cherub_rock", &[], &options).unwrap_err();
+    assert!(err.downcast_ref::<CountInMismatch>().is_some(), "{}", err);
+    assert!(!tmp.path().join("Cherub Rock").exists());
+    Ok(())
+}
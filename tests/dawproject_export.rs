@@ -0,0 +1,406 @@
+mod common;
+
+use std::collections::HashSet;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use kv_downloader::audio::dawproject::{self, DawTrack};
+use kv_downloader::audio::mix::MonitorMix;
+use kv_downloader::audio::reaper::ReaperLayout;
+use kv_downloader::audio::tempo::TimeSignature;
+use kv_downloader::audio::{AudioProcessor, ProcessOptions};
+use roxmltree::{Document, Node};
+use zip::ZipArchive;
+use common::{write_ramp, RATE};
+
+/// The DAWproject 1.0 schema of `project.xml`.
+const SCHEMA: &str = include_str!("fixtures/dawproject/Project.xsd");
+
+/// Validates documents against an XML schema written with the parts of XSD the DAWproject
+/// one uses: global elements and groups, complex types extending one another, sequences and
+/// choices with their occurrence bounds, and attributes of the built-in, enumerated and
+/// list types, with `xs:ID`s unique and every `xs:IDREF` pointing at one.
+struct Validator<'a, 'input> {
+    schema: Node<'a, 'input>,
+    ids: HashSet<String>,
+    refs: Vec<String>,
+}
+
+impl<'a, 'input> Validator<'a, 'input> {
+    fn validate(schema: &'a Document<'input>, root: Node) -> Result<(), String> {
+        let mut validator = Validator {
+            schema: schema.root_element(),
+            ids: HashSet::new(),
+            refs: Vec::new(),
+        };
+        let decl = validator.global("element", root.tag_name().name())?;
+        validator.element(root, decl)?;
+        match validator.refs.iter().find(|id| !validator.ids.contains(*id)) {
+            Some(id) => Err(format!("nothing has the id {}", id)),
+            None => Ok(()),
+        }
+    }
+
+    fn global(&self, kind: &str, name: &str) -> Result<Node<'a, 'input>, String> {
+        self.schema
+            .children()
+            .find(|n| n.tag_name().name() == kind && n.attribute("name") == Some(name))
+            .ok_or_else(|| format!("the schema has no {} {}", kind, name))
+    }
+
+    /// The declaration an element declaration or reference stands for.
+    fn declaration(&self, particle: Node<'a, 'input>) -> Result<Node<'a, 'input>, String> {
+        match particle.attribute("ref") {
+            Some(name) => self.global("element", name),
+            None => Ok(particle),
+        }
+    }
+
+    /// The particles and attributes of a complex type, its bases' first.
+    fn content(&self, ty: Node<'a, 'input>) -> Result<(Vec<Node<'a, 'input>>, Vec<Node<'a, 'input>>), String> {
+        let (mut particles, mut attributes, own) = match xs(ty, "complexContent").next() {
+            Some(complex) => {
+                let extension = xs(complex, "extension").next().ok_or("only extensions are supported")?;
+                let base = self.global("complexType", extension.attribute("base").unwrap_or_default())?;
+                let (particles, attributes) = self.content(base)?;
+                (particles, attributes, extension)
+            }
+            None => (Vec::new(), Vec::new(), ty),
+        };
+        particles.extend(own.children().filter(|n| ["sequence", "choice", "group"].contains(&n.tag_name().name())));
+        attributes.extend(xs(own, "attribute"));
+        Ok((particles, attributes))
+    }
+
+    fn element(&mut self, node: Node, decl: Node<'a, 'input>) -> Result<(), String> {
+        let name = node.tag_name().name();
+        let ty = match decl.attribute("type") {
+            Some(ty) => self.global("complexType", ty)?,
+            None => xs(decl, "complexType").next().ok_or_else(|| format!("<{}> has no type", name))?,
+        };
+        if ty.attribute("abstract") == Some("true") {
+            return Err(format!("<{}> is abstract", name));
+        }
+        let (particles, attributes) = self.content(ty)?;
+
+        for attribute in node.attributes().filter(|a| a.namespace().is_none()) {
+            let declared = attributes
+                .iter()
+                .find(|a| a.attribute("name") == Some(attribute.name()))
+                .ok_or_else(|| format!("<{}> can't have {}", name, attribute.name()))?;
+            self.value(*declared, attribute.value())
+                .map_err(|e| format!("<{}> {}=\"{}\": {}", name, attribute.name(), attribute.value(), e))?;
+        }
+        for declared in attributes.iter().filter(|a| a.attribute("use") == Some("required")) {
+            let attribute = declared.attribute("name").unwrap_or_default();
+            if node.attribute(attribute).is_none() {
+                return Err(format!("<{}> is missing {}", name, attribute));
+            }
+        }
+
+        let children: Vec<Node> = node.children().filter(|n| n.is_element()).collect();
+        let mut at = 0;
+        for particle in particles {
+            at = self.particle(particle, &children, at).map_err(|e| format!("in <{}>: {}", name, e))?;
+        }
+        match children.get(at) {
+            Some(child) => Err(format!("<{}> can't hold <{}> there", name, child.tag_name().name())),
+            None => Ok(()),
+        }
+    }
+
+    /// Matches `particle` as often as it may occur from `children[at]` on, returning where
+    /// it stopped.
+    fn particle(&mut self, particle: Node<'a, 'input>, children: &[Node], mut at: usize) -> Result<usize, String> {
+        let min: usize = particle.attribute("minOccurs").map_or(Ok(1), str::parse).map_err(|e| format!("{}", e))?;
+        let max = match particle.attribute("maxOccurs") {
+            Some("unbounded") => usize::MAX,
+            Some(max) => max.parse().map_err(|e| format!("{}", e))?,
+            None => 1,
+        };
+        let mut count = 0;
+        while count < max && at < children.len() && self.starts(particle, children[at].tag_name().name())? {
+            at = self.once(particle, children, at)?;
+            count += 1;
+        }
+        if count < min && !self.emptiable(particle)? {
+            return Err(format!("expected {}", self.describe(particle)?));
+        }
+        Ok(at)
+    }
+
+    fn once(&mut self, particle: Node<'a, 'input>, children: &[Node], at: usize) -> Result<usize, String> {
+        match particle.tag_name().name() {
+            "element" => {
+                let decl = self.declaration(particle)?;
+                self.element(children[at], decl)?;
+                Ok(at + 1)
+            }
+            "sequence" => particle
+                .children()
+                .filter(|n| n.is_element())
+                .try_fold(at, |at, inner| self.particle(inner, children, at)),
+            "choice" => {
+                let name = children[at].tag_name().name();
+                for inner in particle.children().filter(|n| n.is_element()) {
+                    if self.starts(inner, name)? {
+                        return self.particle(inner, children, at);
+                    }
+                }
+                Ok(at)
+            }
+            "group" => {
+                let group = self.global("group", particle.attribute("ref").unwrap_or_default())?;
+                let inner = group.children().find(|n| n.is_element()).ok_or("empty group")?;
+                self.particle(inner, children, at)
+            }
+            other => Err(format!("xs:{} isn't supported", other)),
+        }
+    }
+
+    /// Whether an element named `name` can begin `particle`.
+    fn starts(&self, particle: Node<'a, 'input>, name: &str) -> Result<bool, String> {
+        match particle.tag_name().name() {
+            "element" => Ok(self.declaration(particle)?.attribute("name") == Some(name)),
+            "group" => {
+                let group = self.global("group", particle.attribute("ref").unwrap_or_default())?;
+                self.any_starts(group, name)
+            }
+            _ => self.any_starts(particle, name),
+        }
+    }
+
+    fn any_starts(&self, parent: Node<'a, 'input>, name: &str) -> Result<bool, String> {
+        for inner in parent.children().filter(|n| n.is_element()) {
+            if self.starts(inner, name)? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    fn emptiable(&self, particle: Node<'a, 'input>) -> Result<bool, String> {
+        if particle.attribute("minOccurs") == Some("0") {
+            return Ok(true);
+        }
+        let inner: Vec<_> = match particle.tag_name().name() {
+            "element" => return Ok(false),
+            "group" => self.global("group", particle.attribute("ref").unwrap_or_default())?.children().filter(|n| n.is_element()).collect(),
+            _ => particle.children().filter(|n| n.is_element()).collect(),
+        };
+        let emptiable: Vec<bool> = inner.into_iter().map(|n| self.emptiable(n)).collect::<Result<_, _>>()?;
+        Ok(match particle.tag_name().name() {
+            "choice" => emptiable.contains(&true),
+            _ => !emptiable.contains(&false),
+        })
+    }
+
+    fn describe(&self, particle: Node<'a, 'input>) -> Result<String, String> {
+        Ok(match particle.tag_name().name() {
+            "element" => format!("<{}>", self.declaration(particle)?.attribute("name").unwrap_or_default()),
+            other => format!("an xs:{}", other),
+        })
+    }
+
+    /// Checks `value` against the type of an attribute declaration.
+    fn value(&mut self, declared: Node<'a, 'input>, value: &str) -> Result<(), String> {
+        match declared.attribute("type") {
+            Some(ty) => self.typed(ty, value),
+            None => {
+                let simple = xs(declared, "simpleType").next().ok_or("untyped attribute")?;
+                self.simple(simple, value)
+            }
+        }
+    }
+
+    fn typed(&mut self, ty: &str, value: &str) -> Result<(), String> {
+        let valid = match ty {
+            "xs:string" => true,
+            "xs:double" => value.parse::<f64>().is_ok(),
+            "xs:int" => value.parse::<i32>().is_ok(),
+            "xs:boolean" => ["true", "false", "1", "0"].contains(&value),
+            "xs:ID" => {
+                let name = value.starts_with(|c: char| c.is_alphabetic() || c == '_');
+                name && self.ids.insert(value.to_string())
+            }
+            "xs:IDREF" => {
+                self.refs.push(value.to_string());
+                true
+            }
+            _ => return self.simple(self.global("simpleType", ty)?, value),
+        };
+        if valid {
+            Ok(())
+        } else {
+            Err(format!("not a valid {}, or a repeated id", ty))
+        }
+    }
+
+    fn simple(&mut self, simple: Node<'a, 'input>, value: &str) -> Result<(), String> {
+        if let Some(list) = xs(simple, "list").next() {
+            let item = list.attribute("itemType").unwrap_or("xs:string");
+            return value.split_whitespace().try_for_each(|v| self.typed(item, v));
+        }
+        let restriction = xs(simple, "restriction").next().ok_or("only lists and restrictions are supported")?;
+        let allowed: Vec<_> = xs(restriction, "enumeration").filter_map(|e| e.attribute("value")).collect();
+        if allowed.is_empty() || allowed.contains(&value) {
+            self.typed(restriction.attribute("base").unwrap_or("xs:string"), value)
+        } else {
+            Err(format!("not one of {:?}", allowed))
+        }
+    }
+}
+
+/// The XSD children of `node` named `name`.
+fn xs<'a, 'input>(node: Node<'a, 'input>, name: &'static str) -> impl Iterator<Item = Node<'a, 'input>> {
+    node.children().filter(move |n| n.is_element() && n.tag_name().name() == name)
+}
+
+fn validate(xml: &str) -> Result<(), String> {
+    let schema = Document::parse(SCHEMA).map_err(|e| e.to_string())?;
+    let doc = Document::parse(xml).map_err(|e| e.to_string())?;
+    Validator::validate(&schema, doc.root_element())
+}
+
+fn two_stem_song(root: &Path) -> Result<PathBuf, Box<dyn Error>> {
+    let song_dir = root.join("Cherub Rock");
+    let mono = song_dir.join("STEMS").join("WAV MONO");
+    fs::create_dir_all(&mono)?;
+    write_ramp(&mono.join("Click_mono.wav"), 1, RATE, 88200)?;
+    write_ramp(&mono.join("Bass_mono.wav"), 1, RATE, 88200)?;
+    Ok(song_dir)
+}
+
+fn export(song_dir: &Path, embed: bool) -> Result<ZipArchive<File>, Box<dyn Error>> {
+    let options = ProcessOptions {
+        skip_fcpxml: true,
+        skip_midi: true,
+        daws: "dawproject".parse()?,
+        mix: MonitorMix::legacy(),
+        tempo: Some(96.0),
+        dawproject_embed: embed,
+        ..Default::default()
+    };
+    let report = AudioProcessor::regenerate_projects(song_dir, &options)?;
+    assert!(report.is_clean(), "{:?}", report.warnings);
+    assert!(!song_dir.join("MT PROJECT/Cherub Rock.rpp").exists());
+    Ok(ZipArchive::new(File::open(song_dir.join("MT PROJECT/Cherub Rock.dawproject"))?)?)
+}
+
+fn read_entry(archive: &mut ZipArchive<File>, name: &str) -> Result<String, Box<dyn Error>> {
+    let mut data = String::new();
+    archive.by_name(name)?.read_to_string(&mut data)?;
+    Ok(data)
+}
+
+#[test]
+fn writes_a_project_matching_the_schema() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    let song_dir = two_stem_song(tmp.path())?;
+    let mut archive = export(&song_dir, false)?;
+    let xml = read_entry(&mut archive, "project.xml")?;
+    let doc = Document::parse(&xml)?;
+    let root = doc.root_element();
+    assert_eq!(root.tag_name().name(), "Project");
+    assert_eq!(root.attribute("version"), Some(dawproject::VERSION));
+    validate(&xml)?;
+
+    // Ids are unique and every lane points at a track
+    let ids: Vec<_> = doc.descendants().filter_map(|n| n.attribute("id")).collect();
+    assert_eq!(ids.len(), ids.iter().collect::<HashSet<_>>().len());
+    let tracks: Vec<_> = doc.descendants().filter(|n| n.has_tag_name("Track")).collect();
+    for lanes in doc.descendants().filter(|n| n.has_tag_name("Lanes") && n.attribute("track").is_some()) {
+        assert!(tracks.iter().any(|t| t.attribute("id") == lanes.attribute("track")));
+    }
+
+    let tempo = doc.descendants().find(|n| n.has_tag_name("Tempo")).unwrap();
+    assert_eq!(tempo.attribute("value"), Some("96"));
+
+    // Same names, colors and pans as the Reaper project: the click hard left (0), the
+    // band hard right (1) with the legacy mix
+    let layout = ReaperLayout::default();
+    let names: Vec<_> = tracks.iter().map(|t| t.attribute("name").unwrap()).collect();
+    assert_eq!(names, ["Click_mono", "Bass_mono"]);
+    for track in &tracks {
+        let name = track.attribute("name").unwrap();
+        assert_eq!(track.attribute("color"), Some(layout.color_for(name).to_string().as_str()));
+        let pan = track.descendants().find(|n| n.has_tag_name("Pan")).unwrap();
+        assert_eq!(pan.attribute("value"), Some(if name == "Click_mono" { "0" } else { "1" }));
+    }
+
+    let files: Vec<_> = doc.descendants().filter(|n| n.has_tag_name("File")).collect();
+    assert_eq!(files[1].attribute("path"), Some("../STEMS/WAV MONO/Bass_mono.wav"));
+    assert_eq!(files[1].attribute("external"), Some("true"));
+    let audio = files[1].parent().unwrap();
+    assert_eq!(audio.attribute("duration"), Some("2"));
+    assert_eq!(audio.attribute("sampleRate"), Some("44100"));
+
+    assert!(read_entry(&mut archive, "metadata.xml")?.contains("<Title>Cherub Rock</Title>"));
+    assert!(archive.by_name("audio/Bass_mono.wav").is_err());
+    Ok(())
+}
+
+#[test]
+fn embeds_the_stems_when_asked() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    let song_dir = two_stem_song(tmp.path())?;
+    let mut archive = export(&song_dir, true)?;
+    let xml = read_entry(&mut archive, "project.xml")?;
+    validate(&xml)?;
+    let doc = Document::parse(&xml)?;
+
+    for file in doc.descendants().filter(|n| n.has_tag_name("File")) {
+        let path = file.attribute("path").unwrap();
+        assert!(path.starts_with("audio/"), "{}", path);
+        assert_eq!(file.attribute("external"), Some("false"));
+        let mut embedded = Vec::new();
+        archive.by_name(path)?.read_to_end(&mut embedded)?;
+        let name = path.trim_start_matches("audio/");
+        assert_eq!(embedded, fs::read(song_dir.join("STEMS/WAV MONO").join(name))?);
+    }
+    Ok(())
+}
+
+#[test]
+fn escapes_names() {
+    let track = DawTrack {
+        name: "Guitar \"Lead\" & <Solo>".to_string(),
+        color: ReaperLayout::default().color_for("Guitar"),
+        source: PathBuf::from("Guitar_mono.wav"),
+        relative_path: "../STEMS/WAV MONO/Guitar_mono.wav".to_string(),
+        sample_rate: 44100,
+        frames: 44100,
+        pan: 0.0,
+        volume: 1.0,
+    };
+    let xml = dawproject::project_xml(&[track], 120.0, TimeSignature::default(), false);
+    let doc = Document::parse(&xml).unwrap();
+    let track = doc.descendants().find(|n| n.has_tag_name("Track")).unwrap();
+    assert_eq!(track.attribute("name"), Some("Guitar \"Lead\" & <Solo>"));
+    let pan = doc.descendants().find(|n| n.has_tag_name("Pan")).unwrap();
+    assert_eq!(pan.attribute("value"), Some("0.5"));
+}
+
+#[test]
+fn the_schema_catches_misplaced_and_missing_parts() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    let song_dir = two_stem_song(tmp.path())?;
+    let xml = read_entry(&mut export(&song_dir, false)?, "project.xml")?;
+    validate(&xml)?;
+
+    // Volume ahead of Pan, as the exporter once wrote them
+    let mut lines: Vec<&str> = xml.lines().collect();
+    let pan = lines.iter().position(|line| line.trim_start().starts_with("<Pan ")).unwrap();
+    lines.swap(pan, pan + 1);
+    assert!(lines[pan].trim_start().starts_with("<Volume "));
+    assert!(validate(&lines.join("\n")).unwrap_err().contains("can't hold <Pan> there"));
+    let no_rate = xml.replacen(r#" sampleRate="44100""#, "", 1);
+    assert!(validate(&no_rate).unwrap_err().contains("missing sampleRate"));
+    let bad_unit = xml.replacen(r#"unit="bpm""#, r#"unit="bananas""#, 1);
+    assert!(validate(&bad_unit).is_err());
+    let dangling = xml.replacen(r#"<Lanes track="id2""#, r#"<Lanes track="id999""#, 1);
+    assert!(validate(&dangling).unwrap_err().contains("id999"));
+    Ok(())
+}
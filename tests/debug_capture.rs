@@ -0,0 +1,70 @@
+use std::error::Error;
+use std::fs::{self, File};
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use kv_downloader::retention::{prune, RetentionPolicy, CAPTURE_MARKER};
+
+const HOUR: Duration = Duration::from_secs(60 * 60);
+
+fn fabricate_capture(debug_dir: &Path, name: &str, age: Duration) -> Result<(), Box<dyn Error>> {
+    let dir = debug_dir.join(name);
+    fs::create_dir_all(&dir)?;
+    fs::write(dir.join(CAPTURE_MARKER), "https://www.karaoke-version.com/custombackingtrack/smashing-pumpkins/cherub-rock.html\n")?;
+    fs::write(dir.join("page.html"), "<html></html>")?;
+    File::open(&dir)?.set_modified(SystemTime::now() - age)?;
+    Ok(())
+}
+
+fn remaining(dir: &Path) -> Result<Vec<String>, Box<dyn Error>> {
+    let mut names: Vec<String> = fs::read_dir(dir)?
+        .map(|e| e.map(|e| e.file_name().to_string_lossy().into_owned()))
+        .collect::<Result<_, _>>()?;
+    names.sort();
+    Ok(names)
+}
+
+#[test]
+fn keeps_only_the_newest_captures() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    fabricate_capture(tmp.path(), "20250301-120000-cherub-rock", 4 * HOUR)?;
+    fabricate_capture(tmp.path(), "20250302-090000-aja", 3 * HOUR)?;
+    fabricate_capture(tmp.path(), "20250302-090000-aja-2", 2 * HOUR)?;
+    fabricate_capture(tmp.path(), "20250303-180000-rosanna", HOUR)?;
+    // Other debug artifacts don't count towards the captures
+    fs::write(tmp.path().join("cdp-trace-aja-20250101-000000.ndjson.gz"), b"")?;
+    File::open(tmp.path().join("cdp-trace-aja-20250101-000000.ndjson.gz"))?.set_modified(SystemTime::now() - 5 * HOUR)?;
+    fs::create_dir(tmp.path().join("notes"))?;
+    File::open(tmp.path().join("notes"))?.set_modified(SystemTime::now() - 5 * HOUR)?;
+
+    let policy = RetentionPolicy {
+        max_captures: 2,
+        ..Default::default()
+    };
+    let report = prune(tmp.path(), &policy, SystemTime::now())?;
+
+    assert_eq!(report.removed, vec![tmp.path().join("20250301-120000-cherub-rock"), tmp.path().join("20250302-090000-aja")]);
+    assert_eq!(
+        remaining(tmp.path())?,
+        ["20250302-090000-aja-2", "20250303-180000-rosanna", "cdp-trace-aja-20250101-000000.ndjson.gz", "notes"]
+    );
+    Ok(())
+}
+
+#[test]
+fn spares_the_running_sessions_captures() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    fabricate_capture(tmp.path(), "20250301-120000-cherub-rock", 3 * HOUR)?;
+    fabricate_capture(tmp.path(), "20250302-090000-aja", HOUR / 2)?;
+    fabricate_capture(tmp.path(), "20250303-180000-rosanna", Duration::ZERO)?;
+
+    let policy = RetentionPolicy {
+        max_captures: 1,
+        ..Default::default()
+    };
+    prune(tmp.path(), &policy, SystemTime::now() - HOUR)?;
+
+    // Over the cap, but both were captured since the session began
+    assert_eq!(remaining(tmp.path())?, ["20250302-090000-aja", "20250303-180000-rosanna"]);
+    Ok(())
+}
@@ -0,0 +1,54 @@
+use kv_downloader::config::Config;
+use kv_downloader::domain::{self, DEFAULT_DOMAIN};
+use kv_downloader::keystore::{credentials_key, session_key};
+
+const UK_SONG: &str = "https://www.karaoke-version.co.uk/custombackingtrack/the-smashing-pumpkins/cherub-rock.html";
+
+#[test]
+fn song_url_beats_flag_beats_config_beats_default() {
+    assert_eq!(
+        domain::resolve(Some(UK_SONG), Some("karaoke-version.de"), Some("version-karaoke.fr")),
+        "www.karaoke-version.co.uk"
+    );
+    assert_eq!(
+        domain::resolve(None, Some("karaoke-version.de"), Some("version-karaoke.fr")),
+        "www.karaoke-version.de"
+    );
+    assert_eq!(domain::resolve(None, None, Some("version-karaoke.fr")), "www.version-karaoke.fr");
+    assert_eq!(domain::resolve(None, None, None), DEFAULT_DOMAIN);
+}
+
+#[test]
+fn normalizes_hosts_domains_and_urls() {
+    for input in [
+        "karaoke-version.co.uk",
+        "www.karaoke-version.co.uk",
+        "WWW.Karaoke-Version.co.uk",
+        "https://www.karaoke-version.co.uk/",
+        " karaoke-version.co.uk/ ",
+    ] {
+        assert_eq!(domain::normalize(input), "www.karaoke-version.co.uk", "{:?}", input);
+    }
+}
+
+#[test]
+fn unknown_domains_are_allowed() {
+    assert!(!domain::is_known("staging.example.com"));
+    assert_eq!(domain::resolve(None, Some("staging.example.com"), None), "staging.example.com");
+}
+
+#[test]
+fn domain_from_the_config_file() -> Result<(), Box<dyn std::error::Error>> {
+    let config = Config::parse("domain = \"karaoke-version.co.uk\"\n")?;
+    assert_eq!(config.domain.as_deref(), Some("karaoke-version.co.uk"));
+    assert_eq!(Config::parse("")?.domain, None);
+    Ok(())
+}
+
+#[test]
+fn keystore_keys_are_per_domain() {
+    assert_eq!(credentials_key(None), "KV_CREDENTIALS");
+    assert_eq!(credentials_key(Some("www.karaoke-version.co.uk")), "KV_CREDENTIALS@www.karaoke-version.co.uk");
+    assert_ne!(session_key("www.karaoke-version.com"), session_key("www.karaoke-version.co.uk"));
+    assert_eq!(session_key("www.karaoke-version.co.uk"), "KV_SESSION@www.karaoke-version.co.uk");
+}
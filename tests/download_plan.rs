@@ -0,0 +1,286 @@
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime};
+
+use kv_downloader::config::Config;
+use kv_downloader::tasks::download_song::{
+    expected_stems, locate_track, pair_mixer_rows, plan_track_downloads, retry_delay, solo_state, DownloadClock, DownloadError, DownloadPhase, DownloadWait, ExpectedDownload, MixerRerender, MixerRow, MixerState, PitchControl, SoloState, StemDecision, TrackInfo, DEFAULT_DOWNLOAD_TIMEOUT,
+    DEFAULT_GENERATION_TIMEOUT, DEFAULT_STABILITY_INTERVAL, RETRY_BACKOFF, RETRY_BACKOFF_CAP,
+};
+
+fn track(index: usize, name: &str, downloaded_on_site: Option<bool>) -> TrackInfo {
+    TrackInfo {
+        index,
+        name: name.to_string(),
+        downloaded_on_site,
+    }
+}
+
+#[test]
+fn skips_rows_missing_a_caption_or_solo_button() {
+    let row = |index, name: &str, has_solo| MixerRow {
+        index,
+        name: name.to_string(),
+        has_solo,
+        downloaded_on_site: None,
+    };
+    let rows = vec![
+        row(0, "Click", true),
+        row(1, "", true),
+        row(2, "Drum Kit", false),
+        row(3, "", false),
+        row(4, "Bass", true),
+    ];
+    assert_eq!(pair_mixer_rows(rows), [track(0, "Click", None), track(4, "Bass", None)]);
+}
+
+#[test]
+fn local_files_are_always_skipped() {
+    let tracks = vec![track(0, "Click", None), track(1, "Bass", None)];
+    let local = vec!["bass".to_string()];
+
+    assert_eq!(
+        plan_track_downloads(&tracks, &local, false),
+        vec![StemDecision::Download, StemDecision::SkipLocalFile]
+    );
+}
+
+#[test]
+fn processing_expects_only_the_stems_in_the_folder() {
+    let tracks = vec![
+        track(0, "Click", None),
+        track(1, "Drum Kit", Some(true)),
+        track(2, "Bass", None),
+        track(3, "Lead Vocal", None),
+    ];
+    let names: Vec<String> = tracks.iter().map(|t| t.name.clone()).collect();
+    let mut decisions = plan_track_downloads(&tracks, &["Bass".to_string()], true);
+    decisions[3] = StemDecision::SkipNotSelected;
+
+    assert_eq!(decisions[1], StemDecision::SkipSiteState);
+    assert_eq!(expected_stems(&names, &decisions), ["Click", "Bass"]);
+}
+
+#[test]
+fn site_state_is_only_used_when_trusted() {
+    let tracks = vec![
+        track(0, "Click", Some(true)),
+        track(1, "Drum Kit", Some(false)),
+        track(2, "Bass", Some(true)),
+    ];
+    let local = vec!["Bass".to_string()];
+
+    assert_eq!(
+        plan_track_downloads(&tracks, &local, false),
+        vec![
+            StemDecision::Download,
+            StemDecision::Download,
+            StemDecision::SkipLocalFile
+        ]
+    );
+    assert_eq!(
+        plan_track_downloads(&tracks, &local, true),
+        vec![
+            StemDecision::SkipSiteState,
+            StemDecision::Download,
+            StemDecision::SkipLocalFile
+        ]
+    );
+}
+
+#[test]
+fn missing_indicators_fall_back_to_local_only() {
+    let tracks = vec![track(0, "Click", None), track(1, "Bass", None)];
+
+    assert_eq!(
+        plan_track_downloads(&tracks, &[], true),
+        vec![StemDecision::Download, StemDecision::Download]
+    );
+}
+
+#[test]
+fn retries_back_off_exponentially_up_to_a_cap() {
+    assert_eq!(retry_delay(1), RETRY_BACKOFF);
+    assert_eq!(retry_delay(2), RETRY_BACKOFF * 2);
+    assert_eq!(retry_delay(3), RETRY_BACKOFF * 4);
+    assert_eq!(retry_delay(30), RETRY_BACKOFF_CAP);
+    assert_eq!(retry_delay(u32::MAX), RETRY_BACKOFF_CAP);
+}
+
+#[test]
+fn download_wait_flags_beat_the_config() -> anyhow::Result<()> {
+    let none = Config::default();
+    assert_eq!(
+        DownloadWait::resolve(None, None, &none.download),
+        DownloadWait {
+            timeout: DEFAULT_DOWNLOAD_TIMEOUT,
+            stability_interval: DEFAULT_STABILITY_INTERVAL,
+            generation_timeout: DEFAULT_GENERATION_TIMEOUT,
+        }
+    );
+
+    let config = Config::parse("[download]\ntimeout_secs = 120\nstability_interval_ms = 100\n")?;
+    let wait = DownloadWait::resolve(None, None, &config.download);
+    assert_eq!(wait.timeout, Duration::from_secs(120));
+    assert_eq!(wait.stability_interval, Duration::from_millis(100));
+    let wait = DownloadWait::resolve(Some(90), None, &config.download);
+    assert_eq!(wait.timeout, Duration::from_secs(90));
+    assert_eq!(wait.stability_interval, Duration::from_millis(100));
+
+    let config = Config::parse("[download]\ngeneration_timeout_secs = 1200\n")?;
+    assert_eq!(DownloadWait::resolve(None, None, &config.download).generation_timeout, Duration::from_secs(1200));
+
+    assert!(Config::parse("[download]\ntimeout_secs = 0\n").is_err());
+    assert!(Config::parse("[download]\ngeneration_timeout_secs = 0\n").is_err());
+    // The flag's name is the default of --stability-interval rather than a setting
+    let config = Config::parse("[download]\nstability_interval = 100\n")?;
+    assert_eq!(config.download.stability_interval_ms, None);
+    assert_eq!(config.flags[0].id, "stability_interval");
+    Ok(())
+}
+
+#[test]
+fn generating_a_track_gets_its_own_time_limit() {
+    let wait = DownloadWait {
+        timeout: Duration::from_secs(30),
+        generation_timeout: Duration::from_secs(300),
+        ..DownloadWait::default()
+    };
+    let start = Instant::now();
+    let at = |secs| start + Duration::from_secs(secs);
+    let mut clock = DownloadClock::new(&wait, start);
+    assert_eq!(clock.observe(false, false, at(10)), Ok(DownloadPhase::Requested));
+    // The progress shows well past the download's timeout
+    assert_eq!(clock.observe(true, false, at(20)), Ok(DownloadPhase::Generating));
+    assert_eq!(clock.observe(true, false, at(200)), Ok(DownloadPhase::Generating));
+    // Once generated, the download gets its full timeout to begin
+    assert_eq!(clock.observe(false, false, at(250)), Ok(DownloadPhase::Requested));
+    assert_eq!(clock.observe(false, false, at(270)), Ok(DownloadPhase::Requested));
+    assert_eq!(clock.observe(false, true, at(275)), Ok(DownloadPhase::Receiving));
+    assert_eq!(clock.since(), at(275));
+    // Whatever the page shows afterwards, the file is on its way
+    assert_eq!(clock.observe(true, false, at(300)), Ok(DownloadPhase::Receiving));
+    assert_eq!(clock.observe(false, false, at(306)), Err(DownloadError::DownloadTimeout));
+
+    let mut clock = DownloadClock::new(&wait, start);
+    assert_eq!(clock.observe(false, false, at(31)), Err(DownloadError::DownloadTimeout));
+    let mut clock = DownloadClock::new(&wait, start);
+    clock.observe(true, false, at(5)).unwrap();
+    assert_eq!(
+        clock.observe(true, false, at(306)),
+        Err(DownloadError::GenerationTimeout(Duration::from_secs(300)))
+    );
+}
+
+#[test]
+fn the_mixer_is_back_once_reloaded() {
+    let state = |rows, stale_rows| MixerState { rows, stale_rows };
+    // Right after the click, the old rows are still up
+    assert!(!state(4, 4).is_interactive());
+    assert!(!state(4, 2).is_interactive());
+    assert!(!state(0, 0).is_interactive());
+    // Rendered again
+    assert!(state(4, 0).is_interactive());
+}
+
+#[test]
+fn the_pitch_range_ends_where_a_button_is_disabled() {
+    let pitch = |current, min, max, up_disabled, down_disabled| PitchControl { current, min, max, up_disabled, down_disabled };
+    // Nothing declared: as far as the site goes
+    assert_eq!(pitch(0, None, None, false, false).range(), (-12, 12));
+    assert_eq!(pitch(0, Some(-5), Some(3), false, false).range(), (-5, 3));
+    // A disabled button beats what's declared
+    assert_eq!(pitch(2, Some(-5), Some(3), true, false).range(), (-5, 2));
+    assert_eq!(pitch(-1, None, None, false, true).range(), (-1, 12));
+
+    let control = pitch(0, Some(-5), Some(3), false, false);
+    assert!(control.check(3).is_ok() && control.check(-5).is_ok());
+    assert_eq!(
+        control.check(4),
+        Err(DownloadError::TransposeOutOfRange { requested: 4, min: -5, max: 3 })
+    );
+    assert_eq!(
+        control.check(4).unwrap_err().to_string(),
+        "This song can only be transposed from -5 to 3 semitones, not 4"
+    );
+}
+
+fn expected(track_name: Option<&str>, suggested_filename: Option<&str>) -> ExpectedDownload {
+    ExpectedDownload {
+        track_name: track_name.map(String::from),
+        suggested_filename: suggested_filename.map(String::from),
+    }
+}
+
+#[test]
+fn only_the_soloed_tracks_file_is_its_download() {
+    let bass = expected(Some("Bass"), None);
+    assert!(bass.matches("Smashing_Pumpkins_Cherub_Rock(Bass_Custom_Backing_Track).mp3"));
+    assert!(bass.matches("Smashing_Pumpkins_Cherub_Rock(Bass_Custom_Backing_Track) (1).mp3"));
+    assert!(bass.matches("Smashing_Pumpkins_Cherub_Rock(Bass_Playback_Personnalise).MP3"));
+    // An earlier song's late stem, a sync client's conflicted copy, a partial file
+    assert!(!bass.matches("Smashing_Pumpkins_Cherub_Rock(Drum_Kit_Custom_Backing_Track).mp3"));
+    assert!(!bass.matches("Smashing_Pumpkins_Cherub_Rock(Bass_Custom_Backing_Track) (conflicted copy).mp3"));
+    assert!(!bass.matches("Smashing_Pumpkins_Cherub_Rock(Bass_Custom_Backing_Track).mp3.crdownload"));
+    assert!(!bass.matches("notes.mp3"));
+
+    let full_mix = expected(None, None);
+    assert!(full_mix.matches("Smashing_Pumpkins_Cherub_Rock(Custom_Backing_Track).mp3"));
+    assert!(!full_mix.matches("Cherub Rock.mp3"));
+}
+
+#[test]
+fn chromes_suggested_filename_wins() {
+    let bass = expected(Some("Bass"), Some("Cherub_Rock(Bass_Custom_Backing_Track).mp3"));
+    assert!(bass.matches("Cherub_Rock(Bass_Custom_Backing_Track).mp3"));
+    assert!(bass.matches("Cherub_Rock(Bass_Custom_Backing_Track) (2).mp3"));
+    assert!(!bass.matches("Smashing_Pumpkins_Cherub_Rock(Bass_Custom_Backing_Track).mp3"));
+    assert!(!bass.matches("Cherub_Rock(Bass_Custom_Backing_Track) (2).mp3.crdownload"));
+}
+
+#[test]
+fn picks_the_newest_candidate() {
+    let now = SystemTime::now();
+    let file = |name: &str, age: u64| (PathBuf::from(name), now - Duration::from_secs(age));
+    let bass = expected(Some("Bass"), None);
+    let new_files = vec![
+        file("Cherub_Rock(Bass_Custom_Backing_Track).mp3", 5),
+        file("Cherub_Rock(Bass_Custom_Backing_Track) (1).mp3", 1),
+        file("Cherub_Rock(Drum_Kit_Custom_Backing_Track).mp3", 0),
+        file("Cherub_Rock(Bass_Custom_Backing_Track).mp3.crdownload", 0),
+    ];
+    assert_eq!(
+        bass.pick(&new_files),
+        Some(PathBuf::from("Cherub_Rock(Bass_Custom_Backing_Track) (1).mp3"))
+    );
+    assert_eq!(bass.pick(&new_files[2..]), None);
+}
+
+#[test]
+fn locates_a_track_after_the_mixer_moves() {
+    let captions: Vec<String> = ["Lead Vocal", "Click", "Backing Vocals", "Bass", "Backing Vocals"]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+    assert_eq!(locate_track(&captions, "Bass", 0), Some(3));
+    // Two tracks of one name keep their order
+    assert_eq!(locate_track(&captions, "Backing Vocals", 0), Some(2));
+    assert_eq!(locate_track(&captions, "Backing Vocals", 1), Some(4));
+    assert_eq!(locate_track(&captions, "Backing Vocals", 2), None);
+    assert_eq!(locate_track(&captions, "Drum Kit", 0), None);
+
+    let moved = MixerRerender {
+        track_name: "Bass".to_string(),
+        before: Some(2),
+        after: Some(3),
+    };
+    assert_eq!(moved.to_string(), "'Bass' moved from row 3 to row 4");
+}
+
+#[test]
+fn tells_a_stale_solo_from_an_exclusive_one() {
+    assert_eq!(solo_state(&[2], 2), SoloState::Exclusive);
+    assert_eq!(solo_state(&[1, 2], 2), SoloState::Stale(vec![1]));
+    assert_eq!(solo_state(&[0, 2, 3], 2), SoloState::Stale(vec![0, 3]));
+    assert_eq!(solo_state(&[1], 2), SoloState::Missing);
+    assert_eq!(solo_state(&[], 2), SoloState::Missing);
+}
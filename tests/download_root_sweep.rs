@@ -0,0 +1,86 @@
+use std::error::Error;
+use std::fs::{self, File};
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use kv_downloader::audit::{self, FileKind};
+
+fn touch(path: &Path, modified: SystemTime) -> Result<(), Box<dyn Error>> {
+    fs::write(path, b"data")?;
+    File::options().write(true).open(path)?.set_modified(modified)?;
+    Ok(())
+}
+
+#[test]
+fn classifies_download_root_files() {
+    let cases = [
+        ("Song(Bass_Custom_Backing_Track).mp3", FileKind::Stem),
+        ("Song_lyrics.PDF", FileKind::Lyrics),
+        ("cover.jpg", FileKind::Cover),
+        ("Song(Drums_Custom_Backing_Track).mp3.crdownload", FileKind::Partial),
+        ("track_list.json", FileKind::Bookkeeping),
+        ("track_list.bandA.json", FileKind::Bookkeeping),
+        ("notes.txt", FileKind::Unknown),
+    ];
+    for (name, kind) in cases {
+        assert_eq!(audit::classify(Path::new(name)), kind, "{}", name);
+    }
+}
+
+#[test]
+fn sweeps_only_this_songs_auxiliary_files() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    let root = tmp.path();
+    let song_dir = root.join("Cherub Rock");
+    fs::create_dir(&song_dir)?;
+
+    let started = SystemTime::now() - Duration::from_secs(60);
+    let fresh = SystemTime::now();
+    let old = started - Duration::from_secs(24 * 60 * 60);
+
+    touch(&root.join("Cherub_Rock(Click_Custom_Backing_Track).mp3"), fresh)?;
+    touch(&root.join("Cherub_Rock(Bass_Custom_Backing_Track).mp3"), fresh)?;
+    touch(&root.join("Cherub_Rock_lyrics.pdf"), fresh)?;
+    touch(&root.join("Cherub_Rock(Drums_Custom_Backing_Track).mp3.crdownload"), fresh)?;
+    touch(&root.join("readme.txt"), fresh)?;
+    touch(&root.join("Older_Song_lyrics.pdf"), old)?;
+    touch(&root.join("taxes.xlsx"), old)?;
+
+    let report = audit::sweep_download_root(root, &song_dir, started)?;
+
+    assert!(song_dir.join("LYRICS.pdf").exists());
+    assert!(!root.join("Cherub_Rock_lyrics.pdf").exists());
+    assert_eq!(report.relocated.len(), 1);
+
+    // stems are the processor's business
+    assert!(root.join("Cherub_Rock(Bass_Custom_Backing_Track).mp3").exists());
+
+    assert_eq!(
+        report.partial,
+        vec![root.join("Cherub_Rock(Drums_Custom_Backing_Track).mp3.crdownload")]
+    );
+    assert_eq!(report.unknown, vec![root.join("readme.txt")]);
+    assert!(root.join("readme.txt").exists());
+
+    // files that predate the song are neither moved nor reported
+    assert!(root.join("Older_Song_lyrics.pdf").exists());
+    assert!(root.join("taxes.xlsx").exists());
+    Ok(())
+}
+
+#[test]
+fn never_overwrites_an_existing_lyric_sheet() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    let root = tmp.path();
+    let song_dir = root.join("Cherub Rock");
+    fs::create_dir(&song_dir)?;
+    fs::write(song_dir.join("LYRICS.pdf"), b"first")?;
+    let started = SystemTime::now() - Duration::from_secs(60);
+    touch(&root.join("lyrics.pdf"), SystemTime::now())?;
+
+    audit::sweep_download_root(root, &song_dir, started)?;
+
+    assert_eq!(fs::read(song_dir.join("LYRICS.pdf"))?, b"first");
+    assert!(song_dir.join("LYRICS 2.pdf").exists());
+    Ok(())
+}
@@ -1,10 +1,75 @@
 mod server;
 
 use std::error::Error;
+use std::fs;
+use std::io::{self, Read};
+use std::thread::sleep;
+use std::time::{Duration, Instant, SystemTime};
 
 use server::Server;
 
+use kv_downloader::debug_capture::DebugCapture;
 use kv_downloader::driver::{Config, Driver};
+use kv_downloader::retention::{DebugRetention, RetentionPolicy};
+use kv_downloader::tasks::download_song::{DownloadError, DownloadOptions};
+use kv_downloader::tasks::song_list::PaginationMode;
+use kv_downloader::tasks::transfers::Transfers;
+
+/// A body served a chunk at a time, slowly enough to abort it mid-transfer.
+struct Trickle {
+    remaining: usize,
+}
+
+impl Read for Trickle {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        sleep(Duration::from_millis(100));
+        let n = buf.len().min(16 * 1024).min(self.remaining);
+        buf[..n].fill(0);
+        self.remaining -= n;
+        Ok(n)
+    }
+}
+
+const DOWNLOAD_PAGE: &str = r#"<html><body>
+<a id="slow" href="/Cherub_Rock(Bass_Custom_Backing_Track).mp3">slow</a>
+<a id="fast" href="/Cherub_Rock(Drum_Kit_Custom_Backing_Track).mp3">fast</a>
+</body></html>"#;
+
+fn download_server() -> Server {
+    Server::new(|request: tiny_http::Request| {
+        let attachment = tiny_http::Header::from_bytes(&b"Content-Disposition"[..], &b"attachment"[..]).unwrap();
+        let mp3 = tiny_http::Header::from_bytes(&b"Content-Type"[..], &b"audio/mpeg"[..]).unwrap();
+        let html = tiny_http::Header::from_bytes(&b"Content-Type"[..], &b"text/html"[..]).unwrap();
+        if request.url().contains("Bass") {
+            // Served from its own thread so the rest of the site stays responsive
+            std::thread::spawn(move || {
+                let size = 50 * 1024 * 1024;
+                let body = Trickle { remaining: size };
+                let response = tiny_http::Response::new(200.into(), vec![attachment, mp3], body, Some(size), None);
+                let _ = request.respond(response);
+            });
+            Ok(())
+        } else if request.url().contains("Drum_Kit") {
+            let body = vec![0u8; 4096];
+            let response = tiny_http::Response::new(200.into(), vec![attachment, mp3], io::Cursor::new(body), Some(4096), None);
+            request.respond(response)
+        } else {
+            let response = tiny_http::Response::new(200.into(), vec![html], DOWNLOAD_PAGE.as_bytes(), Some(DOWNLOAD_PAGE.len()), None);
+            request.respond(response)
+        }
+    })
+}
+
+fn wait_until(timeout: Duration, mut done: impl FnMut() -> bool) -> bool {
+    let start = Instant::now();
+    while start.elapsed() < timeout {
+        if done() {
+            return true;
+        }
+        sleep(Duration::from_millis(100));
+    }
+    false
+}
 
 #[test]
 fn extracts_track_names() -> Result<(), Box<dyn Error>> {
@@ -41,3 +106,390 @@ fn extracts_track_names() -> Result<(), Box<dyn Error>> {
 
     Ok(())
 }
+
+#[test]
+fn scrapes_site_download_indicators() -> Result<(), Box<dyn Error>> {
+    let driver = Driver::new(Config {
+        headless: true,
+        ..Default::default()
+    });
+
+    let tab = driver.browser.new_tab().unwrap();
+    let file_server = Server::with_dumb_html(include_str!("./fixtures/mixer-download-status.html"));
+    tab.navigate_to(&file_server.url())?;
+    tab.wait_until_navigated()?;
+
+    let tracks = Driver::extract_tracks(&tab)?;
+    let states: Vec<(&str, Option<bool>)> = tracks
+        .iter()
+        .map(|t| (t.name.as_str(), t.downloaded_on_site))
+        .collect();
+    assert_eq!(
+        states,
+        vec![("Click", Some(true)), ("Drum Kit", Some(false)), ("Bass", Some(true))]
+    );
+
+    // pages without the indicator fall back to "unknown"
+    let file_server = Server::with_dumb_html(include_str!("./fixtures/cherub-rock.html"));
+    tab.navigate_to(&file_server.url())?;
+    tab.wait_until_navigated()?;
+    let tracks = Driver::extract_tracks(&tab)?;
+    assert!(tracks.iter().all(|t| t.downloaded_on_site.is_none()));
+
+    Ok(())
+}
+
+#[test]
+fn skips_mixer_rows_missing_a_caption_or_solo_button() -> Result<(), Box<dyn Error>> {
+    let driver = Driver::new(Config {
+        headless: true,
+        ..Default::default()
+    });
+
+    let tab = driver.browser.new_tab().unwrap();
+    let file_server = Server::with_dumb_html(include_str!("./fixtures/mixer-malformed-row.html"));
+    tab.navigate_to(&file_server.url())?;
+    tab.wait_until_navigated()?;
+
+    // Each track keeps the index of its own row, past the ones left out
+    let tracks = Driver::extract_tracks(&tab)?;
+    let rows: Vec<(usize, &str)> = tracks.iter().map(|t| (t.index, t.name.as_str())).collect();
+    assert_eq!(rows, [(0, "Click"), (3, "Bass")]);
+
+    Ok(())
+}
+
+#[test]
+fn cancels_the_in_flight_download_of_an_aborted_song() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    let download_path = tmp.path().to_string_lossy().into_owned();
+    let driver = Driver::new(Config {
+        headless: true,
+        download_path: Some(download_path.clone()),
+        ..Default::default()
+    });
+    let site = download_server();
+
+    let tab = driver.browser.new_tab()?;
+    let transfers = Transfers::default();
+    transfers.listen(&tab, &download_path)?;
+    tab.navigate_to(&site.url())?;
+    tab.wait_until_navigated()?;
+
+    // The aborted song: its stem is still trickling in
+    let since = SystemTime::now();
+    tab.find_element("a#slow")?.click()?;
+    assert!(wait_until(Duration::from_secs(20), || {
+        transfers.in_flight().iter().any(|t| t.received_bytes > 0)
+    }));
+    driver.abort_downloads(&tab, &transfers, &download_path, since)?;
+    assert!(transfers.in_flight().is_empty());
+    sleep(Duration::from_secs(2));
+    assert_eq!(fs::read_dir(tmp.path())?.count(), 0, "stray files after the abort");
+
+    // The next song downloads as usual
+    tab.find_element("a#fast")?.click()?;
+    assert!(wait_until(Duration::from_secs(20), || {
+        !transfers.in_flight().is_empty() || fs::read_dir(tmp.path()).map_or(0, |d| d.count()) > 0
+    }));
+    assert!(wait_until(Duration::from_secs(20), || transfers.in_flight().is_empty()));
+    let names: Vec<String> = fs::read_dir(tmp.path())?
+        .filter_map(|e| e.ok())
+        .map(|e| e.file_name().to_string_lossy().into_owned())
+        .collect();
+    assert_eq!(names, vec!["Cherub_Rock(Drum_Kit_Custom_Backing_Track).mp3".to_string()]);
+
+    Ok(())
+}
+
+#[test]
+fn collects_an_infinite_scroll_song_list() -> Result<(), Box<dyn Error>> {
+    let driver = Driver::new(Config {
+        headless: true,
+        ..Default::default()
+    });
+
+    let tab = driver.browser.new_tab().unwrap();
+    let file_server = Server::with_dumb_html(include_str!("./fixtures/downloads-infinite-scroll.html"));
+    tab.navigate_to(&file_server.url())?;
+    tab.wait_until_navigated()?;
+
+    let collection = driver.collect_song_list(&tab)?;
+    assert_eq!(collection.mode, PaginationMode::InfiniteScroll);
+    assert_eq!(collection.advertised_total, Some(45));
+    assert!(collection.reached_total);
+    assert_eq!(collection.urls.len(), 45);
+    assert_eq!(
+        collection.urls[44],
+        "https://www.karaoke-version.com/custombackingtrack/artist-44/song-44.html"
+    );
+    assert_eq!(collection.songs()[44].title.as_deref(), Some("Song 44"));
+
+    Ok(())
+}
+
+#[test]
+fn pairs_the_tracks_again_when_the_mixer_re_renders() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    let driver = Driver::new(Config {
+        headless: true,
+        download_path: Some(tmp.path().to_string_lossy().into_owned()),
+        ..Default::default()
+    });
+    // Each stem's body is the track it belongs to
+    let site = Server::new(|request: tiny_http::Request| {
+        let url = request.url().to_string();
+        if let Some(stem) = url.strip_prefix("/Cherub_Rock(").and_then(|u| u.strip_suffix("_Custom_Backing_Track).mp3")) {
+            let attachment = tiny_http::Header::from_bytes(&b"Content-Disposition"[..], &b"attachment"[..]).unwrap();
+            let mp3 = tiny_http::Header::from_bytes(&b"Content-Type"[..], &b"audio/mpeg"[..]).unwrap();
+            let body = stem.replace('_', " ").into_bytes();
+            let len = body.len();
+            request.respond(tiny_http::Response::new(200.into(), vec![attachment, mp3], io::Cursor::new(body), Some(len), None))
+        } else {
+            let page = include_str!("./fixtures/mixer-rerender.html");
+            let html = tiny_http::Header::from_bytes(&b"Content-Type"[..], &b"text/html"[..]).unwrap();
+            request.respond(tiny_http::Response::new(200.into(), vec![html], page.as_bytes(), Some(page.len()), None))
+        }
+    });
+
+    let downloaded = driver.download_song(&site.url(), DownloadOptions::default())?;
+    assert_eq!(downloaded.track_names, ["Click", "Drum Kit", "Bass", "Lead Vocal"]);
+    // Noticed before each track after the first
+    let rerendered: Vec<&str> = downloaded.mixer_rerenders.iter().map(|r| r.track_name.as_str()).collect();
+    assert_eq!(rerendered, ["Drum Kit", "Bass", "Lead Vocal"]);
+
+    for track in &downloaded.track_names {
+        let file = tmp.path().join(format!("Cherub_Rock({}_Custom_Backing_Track).mp3", track.replace(' ', "_")));
+        assert_eq!(&fs::read_to_string(&file)?, track, "{:?} holds another track's stem", file);
+    }
+    assert_eq!(fs::read_dir(tmp.path())?.count(), 4);
+    Ok(())
+}
+
+#[test]
+fn unsolos_the_previous_track_before_each_download() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    let driver = Driver::new(Config {
+        headless: true,
+        download_path: Some(tmp.path().to_string_lossy().into_owned()),
+        ..Default::default()
+    });
+    // Each stem's body is the track it belongs to
+    let site = Server::new(|request: tiny_http::Request| {
+        let url = request.url().to_string();
+        if let Some(stem) = url.strip_prefix("/Cherub_Rock(").and_then(|u| u.strip_suffix("_Custom_Backing_Track).mp3")) {
+            let attachment = tiny_http::Header::from_bytes(&b"Content-Disposition"[..], &b"attachment"[..]).unwrap();
+            let mp3 = tiny_http::Header::from_bytes(&b"Content-Type"[..], &b"audio/mpeg"[..]).unwrap();
+            let body = stem.replace('_', " ").into_bytes();
+            let len = body.len();
+            request.respond(tiny_http::Response::new(200.into(), vec![attachment, mp3], io::Cursor::new(body), Some(len), None))
+        } else {
+            let page = include_str!("./fixtures/mixer-sticky-solo.html");
+            let html = tiny_http::Header::from_bytes(&b"Content-Type"[..], &b"text/html"[..]).unwrap();
+            request.respond(tiny_http::Response::new(200.into(), vec![html], page.as_bytes(), Some(page.len()), None))
+        }
+    });
+
+    let downloaded = driver.download_song(&site.url(), DownloadOptions::default())?;
+    assert_eq!(downloaded.track_names, ["Click", "Drum Kit", "Bass", "Lead Vocal"]);
+    assert!(downloaded.mixer_rerenders.is_empty());
+
+    for track in &downloaded.track_names {
+        let file = tmp.path().join(format!("Cherub_Rock({}_Custom_Backing_Track).mp3", track.replace(' ', "_")));
+        assert_eq!(&fs::read_to_string(&file)?, track, "{:?} has another track bleeding into it", file);
+    }
+    assert_eq!(fs::read_dir(tmp.path())?.count(), 4);
+    Ok(())
+}
+
+#[test]
+fn follows_the_track_generation_progress() -> Result<(), Box<dyn Error>> {
+    let driver = Driver::new(Config {
+        headless: true,
+        ..Default::default()
+    });
+
+    let tab = driver.browser.new_tab().unwrap();
+    let file_server = Server::with_dumb_html(include_str!("./fixtures/mixer-generating.html"));
+    tab.navigate_to(&file_server.url())?;
+    tab.wait_until_navigated()?;
+
+    // The page keeps its loader hidden until a download is clicked
+    assert!(Driver::generation_progress(&tab).is_none());
+    tab.find_element("a.download")?.click()?;
+    let progress = Driver::generation_progress(&tab).expect("no generation progress");
+    assert_eq!(progress.percent, None);
+    assert!(wait_until(Duration::from_secs(5), || Driver::generation_progress(&tab).is_none()));
+
+    // The captured song page, at rest
+    let file_server = Server::with_dumb_html(include_str!("./fixtures/cherub-rock.html"));
+    tab.navigate_to(&file_server.url())?;
+    tab.wait_until_navigated()?;
+    assert!(Driver::generation_progress(&tab).is_none());
+
+    // A mixer without the progress
+    let file_server = Server::with_dumb_html(include_str!("./fixtures/mixer-download-status.html"));
+    tab.navigate_to(&file_server.url())?;
+    tab.wait_until_navigated()?;
+    assert!(Driver::generation_progress(&tab).is_none());
+    Ok(())
+}
+
+#[test]
+fn waits_for_the_mixer_to_reload_after_pitching() -> Result<(), Box<dyn Error>> {
+    let driver = Driver::new(Config {
+        headless: true,
+        ..Default::default()
+    });
+
+    let tab = driver.browser.new_tab().unwrap();
+    let file_server = Server::with_dumb_html(include_str!("./fixtures/mixer-reloading.html"));
+    tab.navigate_to(&file_server.url())?;
+    tab.wait_until_navigated()?;
+
+    Driver::mark_mixer_stale(&tab)?;
+    let state = Driver::mixer_state(&tab)?;
+    assert_eq!((state.rows, state.stale_rows), (2, 2));
+    tab.find_element("a#pitch-link")?.click()?;
+    let start = Instant::now();
+    driver.wait_for_mixer(&tab, Duration::from_secs(10))?;
+    // Not before the rows were rendered again
+    assert!(start.elapsed() >= Duration::from_millis(800));
+    let state = Driver::mixer_state(&tab)?;
+    assert_eq!((state.rows, state.stale_rows), (2, 0));
+
+    // A mixer updated in place is used after a short wait, not the whole timeout
+    Driver::mark_mixer_stale(&tab)?;
+    let start = Instant::now();
+    driver.wait_for_mixer(&tab, Duration::from_secs(30))?;
+    assert!(start.elapsed() < Duration::from_secs(10), "{:?}", start.elapsed());
+    Ok(())
+}
+
+#[test]
+fn stops_pitching_at_the_end_of_the_songs_range() -> Result<(), Box<dyn Error>> {
+    let driver = Driver::new(Config {
+        headless: true,
+        ..Default::default()
+    });
+
+    let tab = driver.browser.new_tab().unwrap();
+    let file_server = Server::with_dumb_html(include_str!("./fixtures/mixer-pitch-limits.html"));
+    tab.navigate_to(&file_server.url())?;
+    tab.wait_until_navigated()?;
+
+    assert!(Driver::set_pitch(&tab, 2)?);
+    let pitch = Driver::read_pitch(&tab)?;
+    assert_eq!((pitch.current, pitch.up_disabled), (2, true));
+    assert!(!Driver::set_pitch(&tab, 2)?);
+
+    // The disabled button shows where the range ends; the page declares the other end
+    let too_high = Driver::set_pitch(&tab, 3).unwrap_err();
+    let expected = DownloadError::TransposeOutOfRange { requested: 3, min: -3, max: 2 };
+    assert_eq!(too_high.downcast_ref::<DownloadError>(), Some(&expected));
+    assert!(Driver::set_pitch(&tab, -4).is_err());
+    assert_eq!(Driver::read_pitch(&tab)?.current, 2);
+    Ok(())
+}
+
+#[test]
+fn reads_a_german_mixer() -> Result<(), Box<dyn Error>> {
+    let driver = Driver::new(Config {
+        headless: true,
+        accept_language: Some("de-DE,de".to_string()),
+        ..Default::default()
+    });
+
+    let tab = driver.new_tab()?;
+    let file_server = Server::with_dumb_html(include_str!("./fixtures/mixer-de.html"));
+    tab.navigate_to(&file_server.url())?;
+    tab.wait_until_navigated()?;
+
+    let language = tab.evaluate("navigator.language", false)?.value;
+    assert_eq!(language.as_ref().and_then(|v| v.as_str()), Some("de-DE"));
+    assert_eq!(Driver::extract_track_names(&tab)?, vec!["Click", "Schlagzeug", "Bass"]);
+
+    // The pitch buttons are found without their titles
+    assert!(Driver::set_pitch(&tab, -2)?);
+    let pitch = Driver::read_pitch(&tab)?;
+    assert_eq!((pitch.current, pitch.min), (-2, Some(-3)));
+    assert!(Driver::set_pitch(&tab, 1)?);
+    assert_eq!(Driver::read_pitch(&tab)?.current, 1);
+    Ok(())
+}
+
+#[test]
+fn captures_the_page_of_a_failed_download() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    let driver = Driver::new(Config {
+        headless: true,
+        download_path: Some(tmp.path().to_string_lossy().into_owned()),
+        ..Default::default()
+    });
+
+    let file_server = Server::with_dumb_html("<html><body><p>Not a song</p></body></html>");
+    let debug_dir = tmp.path().join("debug");
+    let retention = DebugRetention {
+        dir: debug_dir.clone(),
+        policy: RetentionPolicy {
+            max_captures: 1,
+            ..Default::default()
+        },
+        // Nothing is this session's, so the first capture can go
+        session_start: SystemTime::now() + Duration::from_secs(60 * 60),
+    };
+    let options = DownloadOptions {
+        debug_capture: Some(DebugCapture { retention }),
+        ..Default::default()
+    };
+    let error = driver.download_song(&file_server.url(), options.clone()).unwrap_err();
+    assert_eq!(error.downcast_ref::<DownloadError>(), Some(&DownloadError::NotASongPage));
+
+    let captures: Vec<_> = fs::read_dir(&debug_dir)?.map(|e| e.unwrap().path()).collect();
+    assert_eq!(captures.len(), 1);
+    assert!(error.to_string().contains(&*captures[0].to_string_lossy()));
+    assert!(fs::read_to_string(captures[0].join("page.html"))?.contains("Not a song"));
+    assert!(fs::read_to_string(captures[0].join("url.txt"))?.starts_with(&file_server.url()));
+    assert!(fs::read(captures[0].join("screenshot.png"))?.starts_with(b"\x89PNG"));
+
+    // Only the newest capture is kept
+    driver.download_song(&file_server.url(), options).unwrap_err();
+    assert_eq!(fs::read_dir(&debug_dir)?.count(), 1);
+    Ok(())
+}
+
+#[test]
+fn downloads_of_two_contexts_go_to_their_own_folders() -> Result<(), Box<dyn Error>> {
+    let driver = Driver::new(Config {
+        headless: true,
+        ..Default::default()
+    });
+    let site = download_server();
+    let (first, second) = (tempfile::tempdir()?, tempfile::tempdir()?);
+
+    let mut tabs = vec![];
+    for dir in [&first, &second] {
+        let context = driver.create_context(Some(&dir.path().to_string_lossy()))?;
+        let tab = driver.new_tab_in(Some(&context))?;
+        tab.navigate_to(&site.url())?;
+        tab.wait_until_navigated()?;
+        tabs.push(tab);
+    }
+    // Each context keeps its cookies to itself
+    tabs[0].evaluate("document.cookie = 'session=first'", false)?;
+    let cookie = tabs[1].evaluate("document.cookie", false)?.value;
+    assert_eq!(cookie.as_ref().and_then(|v| v.as_str()), Some(""));
+
+    for tab in &tabs {
+        tab.find_element("a#fast")?.click()?;
+    }
+    for dir in [&first, &second] {
+        assert!(wait_until(Duration::from_secs(20), || {
+            fs::read_dir(dir.path()).is_ok_and(|entries| {
+                entries.filter_map(|e| e.ok()).any(|e| e.file_name() == "Cherub_Rock(Drum_Kit_Custom_Backing_Track).mp3")
+            })
+        }));
+        assert_eq!(fs::read_dir(dir.path())?.count(), 1);
+    }
+    Ok(())
+}
@@ -0,0 +1,102 @@
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use kv_downloader::audio::track_map::TrackMap;
+use kv_downloader::tasks::dry_run::{DryRun, DryRunStatus};
+use kv_downloader::tasks::song_plan::{PlannedSong, SongIdentity};
+use kv_downloader::tasks::track_filter::TrackFilter;
+
+fn planned(url: &str, first_seen: usize) -> PlannedSong {
+    PlannedSong {
+        url: url.to_string(),
+        alternate_urls: vec![],
+        identity: SongIdentity::Url(url.to_string()),
+        first_seen,
+    }
+}
+
+/// A processed song folder holding `bytes` of stems.
+fn processed(root: &Path, title: &str, bytes: usize) -> Result<(), Box<dyn Error>> {
+    let song_dir = root.join(title);
+    fs::create_dir_all(song_dir.join("STEMS"))?;
+    fs::write(song_dir.join("STEMS").join("Bass.wav"), vec![0u8; bytes])?;
+    TrackMap::default().save(&song_dir)?;
+    Ok(())
+}
+
+#[test]
+fn reports_what_each_song_of_a_batch_would_do() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    processed(tmp.path(), "Cherub Rock", 3 * 1024 * 1024)?;
+    processed(tmp.path(), "Today", 1024 * 1024)?;
+    fs::create_dir(tmp.path().join("Rosanna"))?;
+
+    let mut songs = vec![
+        planned("intro", 0),
+        planned("cherub_rock", 1),
+        planned("rosanna", 2),
+        planned("africa", 3),
+    ];
+    songs[3].alternate_urls = vec!["https://www.karaoke-version.co.uk/africa.html".to_string()];
+    let plan = DryRun::plan(tmp.path(), &songs, 1)?;
+
+    let statuses: Vec<(&str, DryRunStatus)> = plan.songs.iter().map(|s| (s.url.as_str(), s.status)).collect();
+    assert_eq!(
+        statuses,
+        [
+            ("intro", DryRunStatus::Skip),
+            ("cherub_rock", DryRunStatus::Skip),
+            ("rosanna", DryRunStatus::Resume),
+            ("africa", DryRunStatus::Download),
+        ]
+    );
+    assert_eq!(plan.songs[0].reason, "among the first 1 skipped");
+    assert_eq!(plan.songs[1].reason, "folder already exists");
+    assert!(plan.songs[3].reason.contains("1 other storefront URL"), "{}", plan.songs[3].reason);
+    assert!(plan.has_work());
+
+    // Two songs to fetch at the 2 MB the processed ones average
+    assert_eq!(plan.sampled_songs, 2);
+    let estimate = plan.estimated_bytes().unwrap();
+    assert!((4 * 1024 * 1024..4 * 1024 * 1024 + 8192).contains(&estimate), "{}", estimate);
+
+    let table = plan.to_string();
+    assert!(table.starts_with("SONG"), "{}", table);
+    assert!(table.contains("4 songs: 1 to download, 1 to resume, 2 skipped"), "{}", table);
+    assert!(table.contains("About 4 MB of disk"), "{}", table);
+    Ok(())
+}
+
+#[test]
+fn has_no_work_when_every_folder_exists() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    processed(tmp.path(), "Cherub Rock", 1024)?;
+    let plan = DryRun::plan(tmp.path(), &[planned("cherub_rock", 0)], 0)?;
+    assert!(!plan.has_work());
+    assert!(!plan.to_string().contains("disk"));
+
+    // Nothing on disk to estimate from
+    let empty = tempfile::tempdir()?;
+    let plan = DryRun::plan(empty.path(), &[planned("cherub_rock", 0)], 0)?;
+    assert!(plan.has_work());
+    assert_eq!(plan.estimated_bytes(), None);
+    assert!(plan.to_string().contains("No processed songs yet"));
+    Ok(())
+}
+
+#[test]
+fn lists_the_tracks_a_single_song_would_download() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    let mut plan = DryRun::plan(tmp.path(), &[planned("cherub_rock", 0)], 0)?;
+    let filter = TrackFilter {
+        skip: "Lead Vocal".parse()?,
+        ..Default::default()
+    };
+    let page = ["Click", "Bass", "Lead Vocal"].map(String::from);
+    plan.songs[0].read_tracks(&page, &filter);
+    assert_eq!(plan.songs[0].tracks, Some(vec!["Click".to_string(), "Bass".to_string()]));
+    assert_eq!(plan.songs[0].reason, "not downloaded yet; 2 of 3 tracks");
+    assert!(plan.to_string().contains("Tracks of cherub_rock: Click, Bass"));
+    Ok(())
+}
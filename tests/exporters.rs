@@ -0,0 +1,69 @@
+use std::error::Error;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use kv_downloader::audio::exporters::{run_exporters, ExportContext, Exporter};
+use kv_downloader::audio::ProcessReport;
+
+fn context() -> ExportContext {
+    ExportContext {
+        mt_project_dir: "MT PROJECT".into(),
+        stems_dir: "STEMS".into(),
+        mono_paths: vec![],
+    }
+}
+
+fn exporters() -> Vec<Exporter> {
+    vec![
+        Exporter::new("Broken", |_| Err(anyhow!("strip_prefix failed"))),
+        Exporter::new("Panicking", |_| panic!("boom")),
+        Exporter::new("Fine", |_| Ok(())),
+    ]
+}
+
+#[test]
+fn failing_exporters_become_warnings() -> Result<(), Box<dyn Error>> {
+    let mut report = ProcessReport::default();
+    run_exporters(&exporters(), &context(), Duration::from_secs(5), false, &mut report)?;
+
+    assert_eq!(report.failed_exporters, vec!["Broken", "Panicking"]);
+    assert!(report.warnings[0].contains("strip_prefix failed"));
+    assert!(report.audio_complete_exporters_failed());
+    Ok(())
+}
+
+#[test]
+fn hung_exporters_hit_their_time_budget() -> Result<(), Box<dyn Error>> {
+    let slow = Exporter::new("Slow", |_| {
+        std::thread::sleep(Duration::from_secs(5));
+        Ok(())
+    });
+    let mut report = ProcessReport::default();
+    run_exporters(&[slow], &context(), Duration::from_millis(50), false, &mut report)?;
+
+    assert_eq!(report.failed_exporters, vec!["Slow"]);
+    assert!(report.warnings[0].contains("timed out"));
+    Ok(())
+}
+
+#[test]
+fn advisory_exporters_leave_the_song_clean() -> Result<(), Box<dyn Error>> {
+    let midi = Exporter::advisory("MIDI file", |_| Err(anyhow!("no steady tempo in the click")));
+    let mut report = ProcessReport::default();
+    run_exporters(std::slice::from_ref(&midi), &context(), Duration::from_secs(5), false, &mut report)?;
+    assert_eq!(report.failed_exporters, vec!["MIDI file"]);
+    assert!(report.is_clean(), "{:?}", report.warnings);
+
+    // Unless every exporter was asked to succeed
+    let result = run_exporters(&[midi], &context(), Duration::from_secs(5), true, &mut ProcessReport::default());
+    assert!(result.unwrap_err().to_string().contains("no steady tempo"));
+    Ok(())
+}
+
+#[test]
+fn strict_mode_fails_hard() {
+    let mut report = ProcessReport::default();
+    let result = run_exporters(&exporters(), &context(), Duration::from_secs(5), true, &mut report);
+
+    assert!(result.unwrap_err().to_string().contains("Broken"));
+}
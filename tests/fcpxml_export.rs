@@ -0,0 +1,71 @@
+mod common;
+
+use std::error::Error;
+use std::fs;
+
+use kv_downloader::audio::{AudioProcessor, ProcessOptions};
+use roxmltree::{Document, ParsingOptions};
+use common::write_ramp;
+
+#[test]
+fn lays_out_every_stem_at_zero_with_relative_paths() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    let song_dir = tmp.path().join("Cherub Rock");
+    let mono = song_dir.join("STEMS").join("WAV MONO");
+    fs::create_dir_all(&mono)?;
+    write_ramp(&mono.join("Click_mono.wav"), 1, 44100, 88200)?;
+    write_ramp(&mono.join("Lead Vocal & Harmony_mono.wav"), 1, 44100, 88200)?;
+    write_ramp(&mono.join("Bass_mono.wav"), 1, 48000, 96000)?;
+
+    let options = ProcessOptions {
+        skip_rpp: true,
+        skip_midi: true,
+        ..Default::default()
+    };
+    let report = AudioProcessor::regenerate_projects(&song_dir, &options)?;
+    assert!(report.is_clean(), "{:?}", report.warnings);
+    assert!(!song_dir.join("MT PROJECT/project.omf").exists());
+
+    let xml = fs::read_to_string(song_dir.join("MT PROJECT/Cherub Rock.fcpxml"))?;
+    let doc = Document::parse_with_options(
+        &xml,
+        ParsingOptions {
+            allow_dtd: true,
+            ..Default::default()
+        },
+    )?;
+    assert_eq!(doc.root_element().attribute("version"), Some("1.8"));
+
+    let assets: Vec<_> = doc.descendants().filter(|n| n.has_tag_name("asset")).collect();
+    assert_eq!(assets.len(), 3);
+    let vocal = assets
+        .iter()
+        .find(|a| a.attribute("name") == Some("Lead Vocal & Harmony_mono"))
+        .expect("vocal asset");
+    assert_eq!(
+        vocal.attribute("src"),
+        Some("../STEMS/WAV%20MONO/Lead%20Vocal%20%26%20Harmony_mono.wav")
+    );
+    assert_eq!(vocal.attribute("duration"), Some("88200/44100s"));
+    assert_eq!(vocal.attribute("audioRate"), Some("44100"));
+    let bass = assets.iter().find(|a| a.attribute("name") == Some("Bass_mono")).unwrap();
+    assert_eq!(bass.attribute("duration"), Some("96000/48000s"));
+    assert_eq!(bass.attribute("audioRate"), Some("48000"));
+
+    let clips: Vec<_> = doc.descendants().filter(|n| n.has_tag_name("asset-clip")).collect();
+    assert_eq!(clips.len(), 3);
+    for clip in &clips {
+        assert_eq!(clip.attribute("offset"), Some("0s"));
+        assert_eq!(clip.attribute("start"), Some("0s"));
+        let asset = assets.iter().find(|a| a.attribute("id") == clip.attribute("ref")).expect("clip asset");
+        assert_eq!(clip.attribute("duration"), asset.attribute("duration"));
+    }
+    let mut lanes: Vec<_> = clips.iter().map(|c| c.attribute("lane").unwrap()).collect();
+    lanes.sort();
+    lanes.dedup();
+    assert_eq!(lanes.len(), 3, "each stem gets its own lane");
+
+    let gap = doc.descendants().find(|n| n.has_tag_name("gap")).unwrap();
+    assert_eq!(gap.attribute("duration"), Some("50/25s"));
+    Ok(())
+}
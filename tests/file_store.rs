@@ -0,0 +1,90 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::Result;
+use kv_downloader::config::Config;
+use kv_downloader::keystore::{self, Backend, FileStore, SecretStore};
+
+const DOMAIN: &str = "www.karaoke-version.com";
+
+#[test]
+fn keeps_credentials_and_sessions_encrypted() -> Result<()> {
+    let tmp = tempfile::tempdir()?;
+    let path = tmp.path().join("kv").join("secrets.enc");
+    let store = FileStore::with_passphrase(path.clone(), "correct horse");
+    store.login("singer", "hunter2", None)?;
+    store.set_secret("KV_SESSION@www.karaoke-version.com", "cookie")?;
+
+    let text = fs::read_to_string(&path)?;
+    assert!(!text.contains("hunter2") && !text.contains("singer"), "{}", text);
+
+    // A store opened afresh reads what the first one wrote
+    let reopened = FileStore::with_passphrase(path.clone(), "correct horse");
+    assert_eq!(reopened.get_credentials(DOMAIN)?.user, "singer");
+    assert_eq!(reopened.get_secret("KV_SESSION@www.karaoke-version.com")?.as_deref(), Some("cookie"));
+
+    reopened.delete_secret("KV_SESSION@www.karaoke-version.com")?;
+    reopened.delete_secret("KV_SESSION@www.karaoke-version.com")?;
+    assert_eq!(store.get_secret("KV_SESSION@www.karaoke-version.com")?, None);
+    Ok(())
+}
+
+#[test]
+fn refuses_a_wrong_passphrase() -> Result<()> {
+    let tmp = tempfile::tempdir()?;
+    let path = tmp.path().join("secrets.enc");
+    FileStore::with_passphrase(path.clone(), "correct horse").set_secret("name", "secret")?;
+
+    let error = FileStore::with_passphrase(path.clone(), "battery staple").get_secret("name").unwrap_err();
+    assert!(error.to_string().contains("wrong passphrase"), "{}", error);
+    Ok(())
+}
+
+#[test]
+fn starts_empty_without_a_file() -> Result<()> {
+    let tmp = tempfile::tempdir()?;
+    let store = FileStore::with_passphrase(tmp.path().join("secrets.enc"), "correct horse");
+    assert_eq!(store.get_secret("KV_CREDENTIALS")?, None);
+    assert!(!store.path().exists());
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn is_only_readable_by_its_owner() -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let tmp = tempfile::tempdir()?;
+    let path = tmp.path().join("secrets.enc");
+    FileStore::with_passphrase(path.clone(), "correct horse").set_secret("name", "secret")?;
+    assert_eq!(fs::metadata(&path)?.permissions().mode() & 0o777, 0o600);
+    Ok(())
+}
+
+#[test]
+fn copies_secrets_between_stores() -> Result<()> {
+    let tmp = tempfile::tempdir()?;
+    let from = FileStore::with_passphrase(tmp.path().join("from.enc"), "one");
+    let to = FileStore::with_passphrase(tmp.path().join("to.enc"), "two");
+    from.login("singer", "hunter2", None)?;
+    from.login("uk-singer", "hunter3", Some("www.karaoke-version.co.uk"))?;
+    from.set_secret("KV_SESSION@www.karaoke-version.de", "cookie")?;
+    from.set_secret("unrelated", "left alone")?;
+
+    assert_eq!(keystore::copy_secrets(&from, &to)?, 3);
+    assert_eq!(to.get_credentials(DOMAIN)?.user, "singer");
+    assert_eq!(to.get_credentials("www.karaoke-version.co.uk")?.user, "uk-singer");
+    assert_eq!(to.get_secret("KV_SESSION@www.karaoke-version.de")?.as_deref(), Some("cookie"));
+    assert_eq!(to.get_secret("unrelated")?, None);
+    // The source keeps its secrets
+    assert_eq!(from.get_credentials(DOMAIN)?.user, "singer");
+    Ok(())
+}
+
+#[test]
+fn reads_the_file_backend_from_the_config() -> Result<()> {
+    let config = Config::parse("[keystore]\nbackend = \"file\"\nfile = \"/srv/kv/secrets.enc\"\n")?;
+    assert_eq!(config.keystore.backend, Backend::File);
+    assert_eq!(config.keystore.file.as_deref(), Some(Path::new("/srv/kv/secrets.enc")));
+    Ok(())
+}
@@ -0,0 +1,19 @@
+use kv_downloader::forms::{FieldNotFilled, FillProblem};
+
+#[test]
+fn reads_the_problem_the_page_reported() {
+    assert_eq!(FillProblem::from_name(""), None);
+    assert_eq!(FillProblem::from_name("focus"), Some(FillProblem::Focus));
+    assert_eq!(FillProblem::from_name("value"), Some(FillProblem::Value));
+}
+
+#[test]
+fn names_the_field_it_could_not_fill() {
+    let error = FieldNotFilled {
+        selector: "#frm_password".into(),
+        problem: FillProblem::Focus,
+    };
+    let text = error.to_string();
+    assert!(text.contains("#frm_password"), "{}", text);
+    assert!(text.contains("kept the focus"), "{}", text);
+}
@@ -0,0 +1,73 @@
+mod common;
+
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use kv_downloader::audio::track_map::TrackMap;
+use kv_downloader::audio::{AudioProcessor, ProcessOptions, FULL_MIX};
+use common::write_download;
+
+#[test]
+fn names_the_full_mix() {
+    assert_eq!(AudioProcessor::normalize_track_name("Cherub Rock (Full Mix).mp3"), FULL_MIX);
+    assert_eq!(AudioProcessor::normalize_track_name("Cherub Rock (Full Mix) (1).mp3"), FULL_MIX);
+    assert_eq!(AudioProcessor::normalize_track_name("Full Mix.wav"), FULL_MIX);
+    assert!(AudioProcessor::is_full_mix(Path::new("STEMS/WAV MONO/Full Mix_mono.wav")));
+    assert!(!AudioProcessor::is_full_mix(Path::new(
+        "Smashing_Pumpkins_Cherub_Rock(Bass_Custom_Backing_Track).mp3"
+    )));
+}
+
+fn write_song(dir: &Path) -> Result<(), Box<dyn Error>> {
+    for (part, seconds) in [("Click", 2.0), ("Bass", 1.5)] {
+        let name = format!("Smashing_Pumpkins_Cherub_Rock({}_Custom_Backing_Track).mp3", part);
+        write_download(&dir.join(name), seconds)?;
+    }
+    write_download(&dir.join("Cherub Rock (Full Mix).mp3"), 2.0)
+}
+
+fn options(include_full_mix: bool) -> ProcessOptions {
+    ProcessOptions {
+        skip_fcpxml: true,
+        skip_midi: true,
+        include_full_mix,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn keeps_the_full_mix_out_of_the_project() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    write_song(tmp.path())?;
+    let mixer: Vec<String> = ["Click", "Bass"].iter().map(|s| s.to_string()).collect();
+    AudioProcessor::process_downloads(tmp.path(), "cherub_rock", &mixer, &options(false))?;
+
+    let song_dir = tmp.path().join("Cherub Rock");
+    assert!(song_dir.join("STEMS/WAV ST/Full Mix.wav").is_file());
+    assert!(song_dir.join("STEMS/WAV MONO/Full Mix_mono.wav").is_file());
+    let map = TrackMap::load(&song_dir)?.expect("no track map");
+    let clicks: Vec<_> = map.tracks.iter().filter(|t| t.is_click).map(|t| t.stereo_file.as_str()).collect();
+    assert_eq!(clicks, ["STEMS/WAV ST/Click.wav"]);
+
+    let rpp = fs::read_to_string(song_dir.join("MT PROJECT/Cherub Rock.rpp"))?;
+    assert_eq!(rpp.matches("<SOURCE WAVE").count(), 2);
+    assert!(!rpp.contains("Full Mix"));
+
+    AudioProcessor::regenerate_projects(&song_dir, &options(true))?;
+    let rpp = fs::read_to_string(song_dir.join("MT PROJECT/Cherub Rock.rpp"))?;
+    assert_eq!(rpp.matches("<SOURCE WAVE").count(), 3);
+    Ok(())
+}
+
+#[test]
+fn processes_a_full_mix_on_its_own() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    write_download(&tmp.path().join("Cherub Rock (Full Mix).mp3"), 2.0)?;
+    AudioProcessor::process_downloads(tmp.path(), "cherub_rock", &[], &options(false))?;
+
+    let song_dir = tmp.path().join("Cherub Rock");
+    assert!(song_dir.join("STEMS/WAV MONO/Full Mix_mono.wav").is_file());
+    assert!(!song_dir.join("MT PROJECT/Cherub Rock.rpp").exists());
+    Ok(())
+}
@@ -0,0 +1,61 @@
+use std::error::Error;
+use std::fs::{self, File};
+use std::time::{Duration, SystemTime};
+
+use kv_downloader::retention::{self, DebugRetention, RetentionPolicy, LAST_RUN_FILE};
+use kv_downloader::tasks::issue_report::write_issue_report;
+use zip::ZipArchive;
+
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[test]
+fn bundles_the_last_runs_artifacts_and_warns_about_pruned_ones() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    let debug_dir = tmp.path().join("debug");
+    let retention = DebugRetention {
+        dir: debug_dir.clone(),
+        policy: RetentionPolicy {
+            max_age: 10 * DAY,
+            ..Default::default()
+        },
+        session_start: SystemTime::now() - DAY,
+    };
+
+    // An artifact of an earlier run isn't part of the report
+    fs::create_dir_all(&debug_dir)?;
+    fs::write(debug_dir.join("cdp-trace-aja-20250101-000000.ndjson.gz"), b"earlier")?;
+    retention.start_run();
+    let capture = debug_dir.join("20250302-090000-cherub-rock");
+    fs::create_dir(&capture)?;
+    fs::write(capture.join(retention::CAPTURE_MARKER), "https://www.karaoke-version.com/custombackingtrack/smashing-pumpkins/cherub-rock.html\n")?;
+    fs::write(capture.join("page.html"), "<html></html>")?;
+    retention.record(&capture);
+    let trace = debug_dir.join("cdp-trace-rosanna-20250302-090500.ndjson.gz");
+    fs::write(&trace, b"trace")?;
+    retention.record(&trace);
+
+    // The trace outlives the run it belongs to, and the record of it is kept
+    File::open(&trace)?.set_modified(SystemTime::now() - 20 * DAY)?;
+    File::open(debug_dir.join(LAST_RUN_FILE))?.set_modified(SystemTime::now() - 20 * DAY)?;
+    retention.prune();
+    assert!(!trace.exists());
+
+    let out = tmp.path().join("report.zip");
+    let report = write_issue_report(&debug_dir, &out)?;
+    assert_eq!(report.included, vec![capture]);
+    assert_eq!(report.pruned, vec![trace]);
+
+    let mut zip = ZipArchive::new(File::open(&out)?)?;
+    let mut names: Vec<&str> = zip.file_names().collect();
+    names.sort();
+    assert_eq!(names, ["20250302-090000-cherub-rock/page.html", "20250302-090000-cherub-rock/url.txt"]);
+    assert_eq!(std::io::read_to_string(zip.by_name("20250302-090000-cherub-rock/page.html")?)?, "<html></html>");
+    Ok(())
+}
+
+#[test]
+fn a_debug_directory_without_a_record_has_nothing_to_report() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    assert!(retention::last_run_artifacts(&tmp.path().join("debug"))?.is_empty());
+    Ok(())
+}
@@ -0,0 +1,43 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use kv_downloader::keepalive::{interval_from_secs, KeepAlive, DEFAULT_KEEPALIVE_INTERVAL};
+
+#[test]
+fn pings_until_dropped() {
+    let pings = Arc::new(AtomicUsize::new(0));
+    let keepalive = {
+        let pings = Arc::clone(&pings);
+        KeepAlive::start(Duration::from_millis(10), move || {
+            pings.fetch_add(1, Ordering::SeqCst);
+        })
+    };
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while pings.load(Ordering::SeqCst) < 3 {
+        assert!(Instant::now() < deadline, "the keep-alive never pinged");
+        std::thread::sleep(Duration::from_millis(5));
+    }
+
+    drop(keepalive);
+    // The thread has finished, and its ping with it
+    assert_eq!(Arc::strong_count(&pings), 1);
+    let stopped_at = pings.load(Ordering::SeqCst);
+    std::thread::sleep(Duration::from_millis(50));
+    assert_eq!(pings.load(Ordering::SeqCst), stopped_at);
+}
+
+#[test]
+fn stops_without_waiting_out_the_interval() {
+    let keepalive = KeepAlive::start(Duration::from_secs(3600), || panic!("pinged before the interval"));
+    let started = Instant::now();
+    drop(keepalive);
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+fn turns_the_keepalive_off_for_zero() {
+    assert_eq!(interval_from_secs(None), Some(DEFAULT_KEEPALIVE_INTERVAL));
+    assert_eq!(interval_from_secs(Some(5)), Some(Duration::from_secs(5)));
+    assert_eq!(interval_from_secs(Some(0)), None);
+}
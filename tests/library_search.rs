@@ -0,0 +1,125 @@
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use kv_downloader::audio::manifest::{ManifestEntry, StemManifest};
+use kv_downloader::audio::taxonomy::Instrument;
+use kv_downloader::metadata::SongInfo;
+use kv_downloader::tasks::library_search::{scan_library, Field, MatchedField, Query};
+
+/// A processed song folder holding only the JSON search reads.
+fn write_song(library: &Path, title: &str, artist: &str, tags: &[&str], stems: &[&str]) -> Result<(), Box<dyn Error>> {
+    let song_dir = library.join(title);
+    fs::create_dir_all(song_dir.join("STEMS").join("WAV MONO"))?;
+    SongInfo {
+        url: format!("https://www.karaoke-version.com/custombackingtrack/{}.html", title),
+        performer: Some(artist.to_string()),
+        tags: tags.iter().map(|t| t.to_string()).collect(),
+        ..Default::default()
+    }
+    .save(&song_dir)?;
+    StemManifest {
+        stems: stems
+            .iter()
+            .map(|name| ManifestEntry {
+                track_name: name.to_string(),
+                mono_file: format!("STEMS/WAV MONO/{}_mono.wav", name),
+                stereo_file: format!("STEMS/WAV/{}.wav", name),
+                duration_secs: 180.0,
+                duration_samples: 180 * 44100,
+                sample_rate: 44100,
+                channels: 2,
+                is_click: *name == "Click",
+                padding_secs: 0.0,
+                time_drift_ms: None,
+                mono_loop: None,
+                stereo_loop: None,
+            })
+            .collect(),
+    }
+    .save(&song_dir)?;
+    Ok(())
+}
+
+fn library() -> Result<tempfile::TempDir, Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    write_song(tmp.path(), "Rosanna", "Toto", &["setlist2025"], &["Click", "Drum Kit", "Tenor Sax", "Lead Vocal"])?;
+    write_song(tmp.path(), "Africa", "Toto", &[], &["Click", "Marimba", "Lead Vocal"])?;
+    write_song(tmp.path(), "Careless Whisper", "George Michael", &["setlist2025", "ballads"], &["Click", "Saxophone"])?;
+    write_song(tmp.path(), "Aja", "Steely Dan", &[], &["Click", "Alto Sax", "Piano"])?;
+    // Not a song folder
+    fs::create_dir(tmp.path().join("Downloads"))?;
+    Ok(tmp)
+}
+
+fn titles(query: &str, library: &Path) -> Result<Vec<String>, Box<dyn Error>> {
+    let query: Query = query.parse()?;
+    Ok(query.search(&scan_library(library)?).into_iter().map(|m| m.title).collect())
+}
+
+#[test]
+fn parses_fields_and_quoted_values() {
+    let query: Query = "toto artist:\"steely dan\" STEM:sax".parse().unwrap();
+    let terms: Vec<(Option<Field>, &str)> = query.terms.iter().map(|t| (t.field, t.value.as_str())).collect();
+    assert_eq!(
+        terms,
+        [(None, "toto"), (Some(Field::Artist), "steely dan"), (Some(Field::Stem), "sax")]
+    );
+    assert!("genre:rock".parse::<Query>().is_err());
+    assert!("tag:".parse::<Query>().is_err());
+    assert!("artist:\"steely".parse::<Query>().is_err());
+    assert!("  ".parse::<Query>().is_err());
+}
+
+#[test]
+fn matches_each_field() -> Result<(), Box<dyn Error>> {
+    let tmp = library()?;
+    assert_eq!(titles("title:aja", tmp.path())?, ["Aja"]);
+    assert_eq!(titles("artist:toto", tmp.path())?, ["Africa", "Rosanna"]);
+    assert_eq!(titles("tag:ballads", tmp.path())?, ["Careless Whisper"]);
+    assert_eq!(titles("stem:marimba", tmp.path())?, ["Africa"]);
+    // A bare word looks in every field
+    assert_eq!(titles("whisper", tmp.path())?, ["Careless Whisper"]);
+    assert_eq!(titles("setlist2025", tmp.path())?, ["Careless Whisper", "Rosanna"]);
+    assert!(titles("title:toto", tmp.path())?.is_empty());
+    Ok(())
+}
+
+#[test]
+fn combines_filters() -> Result<(), Box<dyn Error>> {
+    let tmp = library()?;
+    assert_eq!(titles("artist:toto stem:saxophone tag:setlist2025", tmp.path())?, ["Rosanna"]);
+    assert_eq!(titles("tag:setlist2025 stem:sax", tmp.path())?, ["Careless Whisper", "Rosanna"]);
+    assert!(titles("artist:toto tag:ballads", tmp.path())?.is_empty());
+
+    let query: Query = "artist:toto stem:sax".parse()?;
+    let found = query.search(&scan_library(tmp.path())?);
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].path, tmp.path().join("Rosanna"));
+    assert_eq!(
+        found[0].matched,
+        [
+            MatchedField { field: Field::Artist, value: "Toto".to_string() },
+            MatchedField { field: Field::Stem, value: "Tenor Sax".to_string() },
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn expands_stems_through_the_instrument_taxonomy() -> Result<(), Box<dyn Error>> {
+    let tmp = library()?;
+    for query in ["stem:sax", "stem:saxophone", "stem:saxophones"] {
+        assert_eq!(titles(query, tmp.path())?, ["Aja", "Careless Whisper", "Rosanna"], "{}", query);
+    }
+    assert_eq!(titles("stem:vocals", tmp.path())?, ["Africa", "Rosanna"]);
+    assert_eq!(titles("stem:drums", tmp.path())?, ["Rosanna"]);
+    assert_eq!(titles("stem:keys", tmp.path())?, ["Aja"]);
+
+    assert_eq!(Instrument::classify("Tenor Sax"), Some(Instrument::Saxophone));
+    assert_eq!(Instrument::classify("Bass Drum"), Some(Instrument::Drums));
+    assert_eq!(Instrument::classify("Bassoon"), Some(Instrument::Woodwinds));
+    assert_eq!(Instrument::classify("Theremin"), None);
+    assert_eq!(Instrument::from_query("sa"), []);
+    Ok(())
+}
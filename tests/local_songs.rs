@@ -0,0 +1,112 @@
+mod common;
+
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use kv_downloader::audio::{AudioProcessor, ProcessOptions, SongFolder};
+use kv_downloader::metadata::SongInfo;
+use kv_downloader::tasks::local_songs::{default_root, find_local_songs, is_song_source, publish, LocalSong};
+use common::write_download;
+
+fn downloads(dir: &Path, song: &str) -> Result<(), Box<dyn Error>> {
+    fs::create_dir_all(dir)?;
+    for (part, seconds) in [("Click", 2.0), ("Bass", 1.5)] {
+        write_download(&dir.join(format!("{}({}_Custom_Backing_Track).mp3", song, part)), seconds)?;
+    }
+    Ok(())
+}
+
+fn options() -> ProcessOptions {
+    ProcessOptions {
+        skip_rpp: true,
+        skip_fcpxml: true,
+        skip_midi: true,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn finds_the_song_folders_holding_mp3s() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    let staging = tmp.path().join(".kv-staging");
+    downloads(&staging.join("cherub-rock"), "Smashing_Pumpkins_Cherub_Rock")?;
+    downloads(&staging.join("rosanna"), "Toto_Rosanna")?;
+    fs::create_dir_all(staging.join("empty"))?;
+    SongInfo {
+        url: "https://www.karaoke-version.com/custombackingtrack/toto/rosanna.html".to_string(),
+        ..Default::default()
+    }
+    .save(&staging.join("rosanna"))?;
+
+    let songs = find_local_songs(&staging.to_string_lossy())?;
+    let urls: Vec<_> = songs.iter().map(|song| song.url.as_str()).collect();
+    // Without a song.json, the folder's name stands in for the URL
+    assert_eq!(urls, ["cherub-rock", "https://www.karaoke-version.com/custombackingtrack/toto/rosanna.html"]);
+    // Titled from the URL, so the page isn't fetched
+    assert_eq!(songs[1].info.title.as_deref(), Some("Rosanna - Toto"));
+
+    let pattern = format!("{}/ros*", staging.to_string_lossy());
+    assert!(is_song_source(&pattern));
+    assert_eq!(find_local_songs(&pattern)?.len(), 1);
+    assert!(find_local_songs(&format!("{}/*/x", staging.to_string_lossy())).is_err());
+
+    // A folder that holds the MP3s itself is a song of its own
+    assert_eq!(find_local_songs(&staging.join("cherub-rock").to_string_lossy())?.len(), 1);
+    assert!(!is_song_source("https://www.karaoke-version.com/custombackingtrack/toto/rosanna.html"));
+    Ok(())
+}
+
+#[test]
+fn processes_a_song_folder_into_the_download_path() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    // Named after its song, like the folder processing would write
+    let dir = tmp.path().join("Cherub Rock");
+    downloads(&dir, "Smashing_Pumpkins_Cherub_Rock")?;
+
+    let song = find_local_songs(&dir.to_string_lossy())?.remove(0);
+    let options = ProcessOptions {
+        song_info: Some(song.info.clone()),
+        ..options()
+    };
+    AudioProcessor::process_downloads(&song.dir, &song.url, &[], &options)?;
+    assert_eq!(publish(&song.dir, tmp.path())?, vec![dir.clone()]);
+
+    assert!(dir.join("tracks.json").exists());
+    assert!(dir.join("STEMS/WAV ST/Bass.wav").exists());
+    let entries: Vec<_> = fs::read_dir(tmp.path())?.collect();
+    assert_eq!(entries.len(), 1);
+    Ok(())
+}
+
+#[test]
+fn goes_next_to_its_folder_without_a_download_path() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    let root = fs::canonicalize(tmp.path())?;
+    downloads(&tmp.path().join(".kv-staging").join("rosanna"), "Toto_Rosanna")?;
+    downloads(&tmp.path().join("Cherub Rock"), "Smashing_Pumpkins_Cherub_Rock")?;
+
+    assert_eq!(default_root(&tmp.path().join(".kv-staging").join("rosanna")), root);
+    assert_eq!(default_root(&tmp.path().join("Cherub Rock")), root);
+    Ok(())
+}
+
+#[test]
+fn tells_a_processed_song_by_its_known_title() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    let song = LocalSong::with_url(tmp.path().to_path_buf(), "https://www.karaoke-version.com/custombackingtrack/toto/rosanna.html");
+    // Left to the page, then the tags, rather than the slug
+    assert_eq!(song.info.title, None);
+    let info = SongInfo {
+        title: Some("Rosanna - Toto".to_string()),
+        ..song.info
+    };
+
+    // Found without fetching the page
+    assert_eq!(AudioProcessor::known_song_folder(tmp.path(), &info)?, SongFolder::Missing);
+    fs::create_dir_all(tmp.path().join("Rosanna - Toto"))?;
+    assert_eq!(AudioProcessor::known_song_folder(tmp.path(), &info)?, SongFolder::Unfinished);
+    fs::write(tmp.path().join("Rosanna - Toto").join("tracks.json"), "{}")?;
+    assert_eq!(AudioProcessor::known_song_folder(tmp.path(), &info)?, SongFolder::Processed);
+    Ok(())
+}
@@ -0,0 +1,110 @@
+use std::error::Error;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use kv_downloader::audio::{AudioProcessor, ProcessOptions};
+use serde_json::Value;
+
+/// MPEG-1 Layer III, 128 kbps, 44.1 kHz, stereo, no CRC: 417-byte frames of 1152 samples.
+const FRAME_HEADER: [u8; 4] = [0xFF, 0xFB, 0x90, 0x64];
+const FRAME_LEN: usize = 417;
+
+/// An MP3 whose frames sync but don't decode; processing pads it to the reference length.
+fn write_undecodable_mp3(path: &Path, frames: usize) -> Result<(), Box<dyn Error>> {
+    let mut data = Vec::new();
+    for i in 0..frames {
+        let mut frame = vec![0u8; FRAME_LEN];
+        frame[..4].copy_from_slice(&FRAME_HEADER);
+        frame[4] = 0xFF;
+        frame[5] = 0x80;
+        for (j, byte) in frame.iter_mut().enumerate().skip(6) {
+            *byte = (i * 31 + j * 17) as u8;
+        }
+        data.extend(frame);
+    }
+    fs::write(path, data)?;
+    Ok(())
+}
+
+#[derive(Clone, Default)]
+struct Captured(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Captured {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The value of `field` on the innermost span called `name` an event was logged in.
+fn span_field<'a>(event: &'a Value, name: &str, field: &str) -> Option<&'a Value> {
+    event["spans"]
+        .as_array()?
+        .iter()
+        .rev()
+        .find(|span| span["name"] == name)
+        .and_then(|span| span.get(field))
+}
+
+#[test]
+fn every_line_of_a_song_carries_its_song_and_stem() -> Result<(), Box<dyn Error>> {
+    let captured = Captured::default();
+    let writer = captured.clone();
+    let subscriber = tracing_subscriber::fmt()
+        .json()
+        .with_current_span(false)
+        .with_span_list(true)
+        .with_max_level(tracing::Level::DEBUG)
+        .with_writer(move || writer.clone())
+        .finish();
+    // Global, so the exporter threads log into it too
+    tracing::subscriber::set_global_default(subscriber)?;
+
+    let tmp = tempfile::tempdir()?;
+    for part in ["Click", "Bass", "Drum_Kit"] {
+        let name = format!("Smashing_Pumpkins_Cherub_Rock({}_Custom_Backing_Track).mp3", part);
+        write_undecodable_mp3(&tmp.path().join(name), 20)?;
+    }
+    let options = ProcessOptions {
+        skip_validation: true,
+        skip_midi: true,
+        reference_duration: Some(Duration::from_secs(1)),
+        ..Default::default()
+    };
+    AudioProcessor::song_span("cherub_rock", Some(3))
+        .in_scope(|| AudioProcessor::process_downloads(tmp.path(), "cherub_rock", &[], &options))?;
+
+    let output = String::from_utf8(captured.0.lock().unwrap().clone())?;
+    let events: Vec<Value> = output.lines().map(serde_json::from_str).collect::<Result<_, _>>()?;
+    assert!(!events.is_empty());
+
+    for event in &events {
+        assert_eq!(span_field(event, "song", "title"), Some(&Value::from("Cherub Rock")), "{}", event);
+        assert_eq!(span_field(event, "song", "index"), Some(&Value::from(3)), "{}", event);
+        let phase = span_field(event, "phase", "phase").and_then(Value::as_str);
+        if matches!(phase, Some("transcode" | "mono")) {
+            assert!(span_field(event, "stem", "stem").is_some(), "no stem on {}", event);
+        }
+    }
+
+    let stems: Vec<_> = events.iter().filter_map(|e| span_field(e, "stem", "stem")?.as_str()).collect();
+    for stem in ["Click", "Bass", "Drum Kit"] {
+        assert!(stems.contains(&stem), "nothing logged for {}", stem);
+    }
+
+    // Written on the exporter's own thread
+    let fcpxml = events
+        .iter()
+        .find(|e| e["fields"]["message"].as_str().is_some_and(|m| m.contains(".fcpxml")))
+        .expect("FCPXML export logged");
+    assert_eq!(span_field(fcpxml, "exporter", "exporter"), Some(&Value::from("FCPXML")));
+    assert_eq!(span_field(fcpxml, "phase", "phase"), Some(&Value::from("export")));
+    Ok(())
+}
@@ -0,0 +1,90 @@
+mod server;
+
+use std::cell::Cell;
+use std::error::Error;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use kv_downloader::driver::{Config, Driver};
+use kv_downloader::navigation::Retries;
+use kv_downloader::tasks::sign_in::{credentials_rejected, BadCredentials, PageLayoutChanged};
+use server::Server;
+
+#[test]
+fn recognises_the_sites_credential_errors() {
+    assert!(credentials_rejected("Invalid email or password"));
+    assert!(credentials_rejected("Identifiant ou mot de passe incorrect"));
+    assert!(credentials_rejected("Falsches Passwort"));
+    assert!(!credentials_rejected("Please accept the terms of use"));
+    // Notices that only mention a password or something invalid
+    assert!(!credentials_rejected("Password reset links expire after 24 hours."));
+    assert!(!credentials_rejected("Invalid voucher code"));
+}
+
+/// The error [`Driver::login_error`] reads off the login page `html`.
+fn login_error_of(html: &'static str) -> Result<Option<String>, Box<dyn Error>> {
+    let driver = Driver::new(Config {
+        headless: true,
+        ..Default::default()
+    });
+    let tab = driver.new_tab()?;
+    let file_server = Server::with_dumb_html(html);
+    tab.navigate_to(&file_server.url())?;
+    tab.wait_until_navigated()?;
+    Ok(driver.login_error(&tab))
+}
+
+#[test]
+fn reads_the_login_forms_rejection() -> Result<(), Box<dyn Error>> {
+    let message = login_error_of(include_str!("./fixtures/login-rejected.html"))?;
+    assert_eq!(message.as_deref(), Some("Invalid email or password."));
+    assert!(credentials_rejected(&message.unwrap()));
+    Ok(())
+}
+
+#[test]
+fn leaves_other_notices_on_the_login_page_alone() -> Result<(), Box<dyn Error>> {
+    // The hidden alert and the newsletter's notice aren't read; the form's own error is
+    let message = login_error_of(include_str!("./fixtures/login-notice.html"))?;
+    assert_eq!(message.as_deref(), Some("Please accept the terms of use."));
+    assert!(!credentials_rejected(&message.unwrap()));
+    Ok(())
+}
+
+#[test]
+fn finds_bad_credentials_under_context() {
+    let error = anyhow::Error::from(BadCredentials {
+        message: "Invalid email or password".into(),
+    })
+    .context("Loading the sign-in failed");
+    assert!(BadCredentials::caused(&error));
+    assert!(!BadCredentials::caused(&anyhow!("Login failed - unable to validate session")));
+}
+
+#[test]
+fn does_not_retry_wrong_credentials_or_a_changed_page() {
+    let retries = Retries {
+        retries: 2,
+        backoff: Duration::ZERO,
+    };
+    let attempts = Cell::new(0);
+    let result: anyhow::Result<()> = retries.run("the sign-in", || {
+        attempts.set(attempts.get() + 1);
+        Err(BadCredentials { message: "Wrong password".into() }.into())
+    });
+    assert!(result.is_err());
+    assert_eq!(attempts.get(), 1);
+
+    attempts.set(0);
+    let result: anyhow::Result<()> = retries.run("the sign-in", || {
+        attempts.set(attempts.get() + 1);
+        Err(PageLayoutChanged {
+            url: "https://www.karaoke-version.com/my/login.html".into(),
+            selector: "#frm_login".into(),
+        }
+        .into())
+    });
+    let text = result.unwrap_err().to_string();
+    assert!(text.contains("#frm_login"), "{}", text);
+    assert_eq!(attempts.get(), 1);
+}
@@ -0,0 +1,63 @@
+mod common;
+
+use std::error::Error;
+use std::path::Path;
+use std::time::Duration;
+
+use hound::WavReader;
+use kv_downloader::audio::loops::{read_smpl_loop, write_loop, LoopRegion};
+use common::write_samples;
+
+fn write_wav(path: &Path, sample_rate: u32, seconds: u32) -> Result<Vec<i16>, Box<dyn Error>> {
+    let samples: Vec<i16> = (0..sample_rate * seconds * 2).map(|i| (i % 997) as i16).collect();
+    write_samples(path, 2, sample_rate, &samples)?;
+    Ok(samples)
+}
+
+#[test]
+fn parses_loop_regions() {
+    let region: LoopRegion = "01:00-01:30.5".parse().unwrap();
+    assert_eq!(region.start, Duration::from_secs(60));
+    assert_eq!(region.end, Duration::from_millis(90_500));
+
+    assert!("90-60".parse::<LoopRegion>().is_err());
+    assert!("1:00".parse::<LoopRegion>().is_err());
+    assert!("a-b".parse::<LoopRegion>().is_err());
+}
+
+#[test]
+fn writes_sample_accurate_smpl_chunks() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    let region: LoopRegion = "1-2.5".parse()?;
+
+    for rate in [44100, 48000] {
+        let path = tmp.path().join(format!("{}.wav", rate));
+        write_wav(&path, rate, 3)?;
+        write_loop(&path, &region)?;
+        assert_eq!(read_smpl_loop(&path)?, Some((rate, rate * 5 / 2 - 1)));
+    }
+    Ok(())
+}
+
+#[test]
+fn audio_is_unchanged_by_the_chunk() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    let path = tmp.path().join("stem.wav");
+    let samples = write_wav(&path, 44100, 3)?;
+
+    write_loop(&path, &"0:01-0:02".parse()?)?;
+    // writing again replaces rather than stacks the chunk
+    write_loop(&path, &"0:00.5-0:02".parse()?)?;
+
+    let read: Vec<i16> = WavReader::open(&path)?.samples().collect::<Result<_, _>>()?;
+    assert_eq!(read, samples);
+    assert_eq!(read_smpl_loop(&path)?, Some((22050, 88199)));
+    Ok(())
+}
+
+#[test]
+fn rejects_loops_past_the_reference_duration() {
+    let region: LoopRegion = "0:10-0:40".parse().unwrap();
+    assert!(region.validate(Duration::from_secs(30)).is_err());
+    assert!(region.validate(Duration::from_secs(40)).is_ok());
+}
@@ -0,0 +1,121 @@
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::sync::{mpsc, Barrier, Mutex};
+use std::time::Duration;
+
+use kv_downloader::audio::budget::{self, MemoryBudget};
+use kv_downloader::audio::{AudioProcessor, ProcessOptions};
+
+/// MPEG-1 Layer III, 128 kbps, 44.1 kHz, stereo, no CRC: 417-byte frames of 1152 samples.
+const FRAME_HEADER: [u8; 4] = [0xFF, 0xFB, 0x90, 0x64];
+const FRAME_LEN: usize = 417;
+
+/// An MP3 whose frames sync but don't decode; processing pads it to the reference length.
+fn write_undecodable_mp3(path: &Path, frames: usize) -> Result<(), Box<dyn Error>> {
+    let mut data = Vec::new();
+    for i in 0..frames {
+        let mut frame = vec![0u8; FRAME_LEN];
+        frame[..4].copy_from_slice(&FRAME_HEADER);
+        frame[4] = 0xFF;
+        frame[5] = 0x80;
+        for (j, byte) in frame.iter_mut().enumerate().skip(6) {
+            *byte = (i * 31 + j * 17) as u8;
+        }
+        data.extend(frame);
+    }
+    fs::write(path, data)?;
+    Ok(())
+}
+
+/// Runs `workers` threads that all ask for their `bytes` of the budget at once and hold it
+/// for a moment, returning how many held it together, and how many bytes, at most.
+fn peak_use(budget: &MemoryBudget, workers: usize, bytes: impl Fn(usize) -> u64 + Sync) -> (usize, u64) {
+    let start = Barrier::new(workers);
+    let holding = Mutex::new((0usize, 0u64));
+    let peak = Mutex::new((0usize, 0u64));
+    std::thread::scope(|scope| {
+        for i in 0..workers {
+            let (start, holding, peak, bytes) = (&start, &holding, &peak, &bytes);
+            scope.spawn(move || {
+                let wanted = bytes(i).min(budget.capacity());
+                start.wait();
+                let _permit = budget.acquire(bytes(i));
+                {
+                    let mut holding = holding.lock().unwrap();
+                    holding.0 += 1;
+                    holding.1 += wanted;
+                    let mut peak = peak.lock().unwrap();
+                    *peak = (peak.0.max(holding.0), peak.1.max(holding.1));
+                }
+                std::thread::sleep(Duration::from_millis(20));
+                let mut holding = holding.lock().unwrap();
+                holding.0 -= 1;
+                holding.1 -= wanted;
+            });
+        }
+    });
+    let peak = *peak.lock().unwrap();
+    peak
+}
+
+#[test]
+fn estimates_decoded_size() {
+    assert_eq!(budget::decoded_size(Duration::from_secs(360), 44_100, 2), 127_008_000);
+}
+
+#[test]
+fn narrows_concurrency_to_what_fits() {
+    let budget = MemoryBudget::new(100);
+    let (stems, bytes) = peak_use(&budget, 6, |_| 40);
+    assert!(stems <= 2 && bytes <= 100, "{} stems held {} bytes", stems, bytes);
+    let (stems, bytes) = peak_use(&budget, 6, |_| 20);
+    assert!(stems <= 5 && bytes <= 100, "{} stems held {} bytes", stems, bytes);
+    assert_eq!(budget.in_use(), 0);
+}
+
+#[test]
+fn oversized_stem_runs_alone_instead_of_deadlocking() {
+    let budget = MemoryBudget::new(100);
+    let (tx, rx) = mpsc::channel();
+    std::thread::scope(|scope| {
+        let budget = &budget;
+        scope.spawn(move || {
+            let (stems, bytes) = peak_use(budget, 4, |i| if i == 0 { 500 } else { 30 });
+            assert!(stems <= 3 && bytes <= 100, "{} stems held {} bytes", stems, bytes);
+            tx.send(()).unwrap();
+        });
+        rx.recv_timeout(Duration::from_secs(10)).expect("budget deadlocked");
+    });
+    assert_eq!(budget.in_use(), 0);
+}
+
+#[test]
+fn parallel_processing_under_a_tiny_budget_finishes() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    for part in ["Click", "Bass", "Drum_Kit", "Lead_Vocal", "Rhythm_Guitar"] {
+        let name = format!("Smashing_Pumpkins_Cherub_Rock({}_Custom_Backing_Track).mp3", part);
+        write_undecodable_mp3(&tmp.path().join(name), 20)?;
+    }
+    let options = ProcessOptions {
+        skip_validation: true,
+        skip_rpp: true,
+        skip_fcpxml: true,
+        skip_midi: true,
+        reference_duration: Some(Duration::from_secs(1)),
+        process_threads: 4,
+        // Less than one padded stem, so they go one at a time
+        memory_budget: Some(100_000),
+        ..Default::default()
+    };
+    AudioProcessor::process_downloads(tmp.path(), "cherub_rock", &[], &options)?;
+
+    let mono = tmp.path().join("Cherub Rock/STEMS/WAV MONO");
+    for stem in ["Click", "Bass", "Drum Kit", "Lead Vocal", "Rhythm Guitar"] {
+        let reader = hound::WavReader::open(mono.join(format!("{}_mono.wav", stem)))?;
+        if stem != "Click" {
+            assert_eq!(reader.duration(), 44_100, "{} padded to the reference", stem);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,180 @@
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use hound::{SampleFormat, WavSpec, WavWriter};
+use kv_downloader::audio::midi::{self, MidiCountIn, MidiLayout};
+use kv_downloader::audio::tempo::{self, TimeSignature};
+use kv_downloader::audio::{AudioProcessor, ProcessOptions};
+use midly::{MetaMessage, Smf, TrackEventKind};
+
+const RATE: u32 = 44100;
+
+/// A mono click at 120 BPM whose first hit lands at 0.25s, 5s long.
+fn write_click(path: &Path) -> Result<(), Box<dyn Error>> {
+    let spec = WavSpec {
+        channels: 1,
+        sample_rate: RATE,
+        bits_per_sample: 16,
+        sample_format: SampleFormat::Int,
+    };
+    let mut writer = WavWriter::create(path, spec)?;
+    let hit = RATE as usize / 100;
+    for i in 0..RATE as usize * 5 {
+        let since_first = i as i64 - RATE as i64 / 4;
+        let in_hit = since_first >= 0 && (since_first as usize % (RATE as usize / 2)) < hit;
+        writer.write_sample(if in_hit { if i % 2 == 0 { 20000i16 } else { -20000 } } else { 0 })?;
+    }
+    writer.finalize()?;
+    Ok(())
+}
+
+/// (absolute tick, text) of every marker.
+type Markers = Vec<(u32, String)>;
+
+/// Markers, plus the tick each track ends at.
+fn read_midi(path: &Path) -> Result<(Markers, Vec<u32>), Box<dyn Error>> {
+    let data = fs::read(path)?;
+    let smf = Smf::parse(&data)?;
+    let mut markers = Vec::new();
+    let mut ends = Vec::new();
+    for track in &smf.tracks {
+        let mut tick = 0;
+        for event in track {
+            tick += event.delta.as_int();
+            match event.kind {
+                TrackEventKind::Meta(MetaMessage::Marker(text)) => {
+                    markers.push((tick, String::from_utf8_lossy(text).into_owned()))
+                }
+                TrackEventKind::Meta(MetaMessage::EndOfTrack) => ends.push(tick),
+                _ => {}
+            }
+        }
+    }
+    Ok((markers, ends))
+}
+
+fn layout(dir: &Path, count_in: MidiCountIn) -> Result<MidiLayout, Box<dyn Error>> {
+    let click = dir.join("Click_mono.wav");
+    write_click(&click)?;
+    Ok(MidiLayout {
+        timing: tempo::detect_click_timing(&click, None)?,
+        signature: TimeSignature::default(),
+        count_in_bars: 1,
+        count_in,
+        length: Duration::from_secs(5),
+    })
+}
+
+#[test]
+fn detects_tempo_and_first_click() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    let click = tmp.path().join("Click_mono.wav");
+    write_click(&click)?;
+
+    let timing = tempo::detect_click_timing(&click, None)?;
+    assert_eq!(timing.bpm, 120.0);
+    assert!((timing.first_beat.as_secs_f64() - 0.25).abs() < 0.001, "{:?}", timing.first_beat);
+
+    let overridden = tempo::detect_click_timing(&click, Some(96.0))?;
+    assert_eq!(overridden.bpm, 96.0);
+    assert_eq!(overridden.first_beat, timing.first_beat);
+    Ok(())
+}
+
+#[test]
+fn includes_count_in_bars() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    let path = tmp.path().join("song.mid");
+    midi::write_midi_file(&path, &layout(tmp.path(), MidiCountIn::Include)?)?;
+
+    // the 0.25s before the first click becomes one lead-in beat of 480 ticks
+    let (markers, ends) = read_midi(&path)?;
+    assert_eq!(markers, vec![(480, "Count-in".to_string()), (2400, "Bar 1".to_string())]);
+    // both tracks run as long as the stems: 480 + 4.75s at 960 ticks per second
+    assert_eq!(ends, vec![5040, 5040]);
+    Ok(())
+}
+
+#[test]
+fn can_start_at_the_first_downbeat() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    let path = tmp.path().join("song.mid");
+    midi::write_midi_file(&path, &layout(tmp.path(), MidiCountIn::Skip)?)?;
+
+    let (markers, ends) = read_midi(&path)?;
+    assert_eq!(markers, vec![(480, "Bar 1".to_string())]);
+    assert_eq!(ends, vec![3120, 3120]);
+    Ok(())
+}
+
+#[test]
+fn parses_time_signatures() {
+    assert_eq!("6/8".parse(), Ok(TimeSignature { numerator: 6, denominator: 8 }));
+    assert!("6/7".parse::<TimeSignature>().is_err());
+    assert!("0/4".parse::<TimeSignature>().is_err());
+    assert!("waltz".parse::<TimeSignature>().is_err());
+}
+
+#[test]
+fn regenerating_projects_writes_the_midi_file() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    let song_dir = tmp.path().join("Cherub Rock");
+    let mono = song_dir.join("STEMS").join("WAV MONO");
+    fs::create_dir_all(&mono)?;
+    write_click(&mono.join("Click_mono.wav"))?;
+
+    let options = ProcessOptions {
+        skip_rpp: true,
+        skip_fcpxml: true,
+        ..Default::default()
+    };
+    let report = AudioProcessor::regenerate_projects(&song_dir, &options)?;
+    assert!(report.is_clean(), "{:?}", report.warnings);
+    assert!(song_dir.join("MT PROJECT/Cherub Rock.mid").exists());
+    Ok(())
+}
+
+#[test]
+fn a_song_without_a_click_still_processes_cleanly() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    let song_dir = tmp.path().join("Cherub Rock");
+    let mono = song_dir.join("STEMS").join("WAV MONO");
+    fs::create_dir_all(&mono)?;
+    write_click(&mono.join("Bass_mono.wav"))?;
+
+    let options = ProcessOptions {
+        skip_rpp: true,
+        skip_fcpxml: true,
+        ..Default::default()
+    };
+    let report = AudioProcessor::regenerate_projects(&song_dir, &options)?;
+    assert!(report.is_clean(), "{:?}", report.warnings);
+    assert_eq!(report.failed_exporters, ["MIDI file"]);
+    assert!(!song_dir.join("MT PROJECT/Cherub Rock.mid").exists());
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn names_the_midi_file_of_a_song_folder_that_isnt_utf8() -> Result<(), Box<dyn Error>> {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let tmp = tempfile::tempdir()?;
+    let song_dir = tmp.path().join(OsStr::from_bytes(b"Cherub Rock \xff"));
+    let mono = song_dir.join("STEMS").join("WAV MONO");
+    fs::create_dir_all(&mono)?;
+    write_click(&mono.join("Click_mono.wav"))?;
+
+    let options = ProcessOptions {
+        skip_rpp: true,
+        skip_fcpxml: true,
+        ..Default::default()
+    };
+    let report = AudioProcessor::regenerate_projects(&song_dir, &options)?;
+    assert!(report.is_clean(), "{:?}", report.warnings);
+    assert!(song_dir.join("MT PROJECT/Cherub Rock \u{fffd}.mid").exists());
+    Ok(())
+}
@@ -0,0 +1,151 @@
+mod common;
+
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use flate2::read::GzDecoder;
+use kv_downloader::audio::mix::{MixOverrides, MonitorMix};
+use kv_downloader::audio::{AudioProcessor, ProcessOptions};
+use kv_downloader::config::Config;
+use common::{write_ramp, RATE};
+
+fn generate(root: &Path, mix: MonitorMix) -> Result<PathBuf, Box<dyn Error>> {
+    let song_dir = root.join("Cherub Rock");
+    let mono = song_dir.join("STEMS").join("WAV MONO");
+    fs::create_dir_all(&mono)?;
+    for name in ["Click", "Bass"] {
+        write_ramp(&mono.join(format!("{}_mono.wav", name)), 1, RATE, 4410)?;
+    }
+    let options = ProcessOptions {
+        skip_fcpxml: true,
+        skip_midi: true,
+        daws: "all".parse()?,
+        mix,
+        ..Default::default()
+    };
+    let report = AudioProcessor::regenerate_projects(&song_dir, &options)?;
+    assert!(report.is_clean(), "{:?}", report.warnings);
+    Ok(song_dir)
+}
+
+/// The lines of the Reaper track called `name`, up to its item.
+fn rpp_track(rpp: &str, name: &str) -> String {
+    let start = rpp.find(&format!("NAME \"{}\"", name)).expect(name);
+    let end = start + rpp[start..].find("<ITEM").unwrap();
+    rpp[start..end].to_string()
+}
+
+#[test]
+fn centers_everything_by_default() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    let song_dir = generate(tmp.path(), MonitorMix::default())?;
+    let rpp = fs::read_to_string(song_dir.join("MT PROJECT/Cherub Rock.rpp"))?;
+    for name in ["Click_mono", "Bass_mono"] {
+        let track = rpp_track(&rpp, name);
+        assert!(track.contains("VOLPAN 1 0 -1 -1 1"), "{}", track);
+        assert!(track.contains("MAINSEND 1 0"), "{}", track);
+        assert!(!track.contains("HWOUT"), "{}", track);
+    }
+    Ok(())
+}
+
+#[test]
+fn legacy_panning_splits_click_and_band() -> Result<(), Box<dyn Error>> {
+    let mix = MonitorMix::default().with_overrides(MixOverrides {
+        legacy_panning: true,
+        ..Default::default()
+    })?;
+    assert_eq!(mix, MonitorMix::legacy());
+
+    let tmp = tempfile::tempdir()?;
+    let song_dir = generate(tmp.path(), mix)?;
+    let rpp = fs::read_to_string(song_dir.join("MT PROJECT/Cherub Rock.rpp"))?;
+    assert!(rpp_track(&rpp, "Click_mono").contains("VOLPAN 1 -1 -1 -1 1"));
+    assert!(rpp_track(&rpp, "Bass_mono").contains("VOLPAN 1 1 -1 -1 1"));
+    Ok(())
+}
+
+#[test]
+fn routes_the_click_to_a_hardware_output() -> Result<(), Box<dyn Error>> {
+    let mix = MonitorMix {
+        click_volume_db: -6.0,
+        click_output: Some(3),
+        ..Default::default()
+    };
+    let tmp = tempfile::tempdir()?;
+    let song_dir = generate(tmp.path(), mix)?;
+
+    let rpp = fs::read_to_string(song_dir.join("MT PROJECT/Cherub Rock.rpp"))?;
+    let click = rpp_track(&rpp, "Click_mono");
+    let volume: f64 = click
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("VOLPAN "))
+        .and_then(|rest| rest.split(' ').next())
+        .unwrap()
+        .parse()?;
+    assert!((volume - 0.501).abs() < 0.001, "{}", volume);
+    assert!(click.contains("MAINSEND 0 0"), "{}", click);
+    // Mono output 3 is channel index 2 with the mono flag
+    assert!(click.contains("    HWOUT 1026 0 1 0 0 0 0 -1:U -1\n"), "{}", click);
+    let bass = rpp_track(&rpp, "Bass_mono");
+    assert!(bass.contains("MAINSEND 1 0") && !bass.contains("HWOUT"), "{}", bass);
+
+    let mut xml = String::new();
+    GzDecoder::new(File::open(song_dir.join("MT PROJECT/Cherub Rock.als"))?).read_to_string(&mut xml)?;
+    let doc = roxmltree::Document::parse(&xml)?;
+    let click = doc
+        .descendants()
+        .find(|n| n.has_tag_name("AudioTrack") && n.descendants().any(|d| d.attribute("Value") == Some("Click")))
+        .unwrap();
+    let target = click.descendants().find(|n| n.has_tag_name("Target")).unwrap();
+    assert_eq!(target.attribute("Value"), Some("AudioOut/External/M2"));
+    let bass = doc
+        .descendants()
+        .find(|n| n.has_tag_name("AudioTrack") && n.descendants().any(|d| d.attribute("Value") == Some("Bass")))
+        .unwrap();
+    assert!(!bass.descendants().any(|n| n.has_tag_name("AudioOutputRouting")));
+    Ok(())
+}
+
+#[test]
+fn flags_override_the_config() -> Result<(), Box<dyn Error>> {
+    let config = Config::parse(
+        r#"
+[mix]
+click_pan = -0.5
+band_pan = 0.5
+click_output = 2
+"#,
+    )?;
+    assert_eq!(config.mix.click_output, Some(2));
+
+    let mix = config.mix.clone().with_overrides(MixOverrides {
+        band_pan: Some(0.0),
+        ..Default::default()
+    })?;
+    assert_eq!((mix.click_pan, mix.band_pan, mix.click_output), (-0.5, 0.0, Some(2)));
+
+    // --legacy-panning replaces the config's pans, explicit pans still win over it
+    let mix = config.mix.with_overrides(MixOverrides {
+        legacy_panning: true,
+        click_pan: Some(-0.25),
+        ..Default::default()
+    })?;
+    assert_eq!((mix.click_pan, mix.band_pan), (-0.25, 1.0));
+    Ok(())
+}
+
+#[test]
+fn rejects_out_of_range_settings() {
+    assert!(Config::parse("[mix]\nclick_pan = -2.0\n").is_err());
+    assert!(Config::parse("[mix]\nclick_output = 0\n").is_err());
+    // An unknown key is only warned about
+    assert_eq!(Config::parse("[mix]\nclick_gain = 1.0\n").unwrap().unknown, ["mix.click_gain"]);
+    let overrides = MixOverrides {
+        band_pan: Some(1.5),
+        ..Default::default()
+    };
+    assert!(MonitorMix::default().with_overrides(overrides).is_err());
+}
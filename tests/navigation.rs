@@ -0,0 +1,67 @@
+use std::cell::Cell;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use headless_chrome::util::Timeout;
+use kv_downloader::navigation::{is_transient, Retries, NAVIGATION_BACKOFF, NAVIGATION_RETRIES};
+
+const NO_WAIT: Retries = Retries { retries: 3, backoff: Duration::ZERO };
+
+#[test]
+fn tells_transient_errors_from_permanent_ones() {
+    assert!(is_transient(&anyhow!("Navigate failed: net::ERR_NETWORK_CHANGED")));
+    assert!(is_transient(&anyhow!("Navigate failed: net::ERR_TIMED_OUT").context("Loading the page")));
+    assert!(is_transient(&anyhow::Error::from(Timeout)));
+
+    assert!(!is_transient(&anyhow!("Navigate failed: net::ERR_ABORTED")));
+    assert!(!is_transient(&anyhow!("Unable to make method calls because underlying connection is closed")));
+}
+
+#[test]
+fn retries_transient_failures_until_the_page_loads() {
+    let attempts = Cell::new(0);
+    let loaded = NO_WAIT.run("https://example.com", || {
+        attempts.set(attempts.get() + 1);
+        match attempts.get() {
+            1 => Err(anyhow!("Navigate failed: net::ERR_NETWORK_CHANGED")),
+            2 => Err(Timeout.into()),
+            _ => Ok("loaded"),
+        }
+    });
+    assert_eq!(loaded.unwrap(), "loaded");
+    assert_eq!(attempts.get(), 3);
+}
+
+#[test]
+fn gives_up_on_permanent_failures_at_once() {
+    let attempts = Cell::new(0);
+    let result: anyhow::Result<()> = NO_WAIT.run("https://example.com", || {
+        attempts.set(attempts.get() + 1);
+        Err(anyhow!("Navigate failed: net::ERR_ABORTED"))
+    });
+    assert_eq!(result.unwrap_err().to_string(), "Navigate failed: net::ERR_ABORTED");
+    assert_eq!(attempts.get(), 1);
+}
+
+#[test]
+fn gives_up_after_the_last_retry() {
+    let attempts = Cell::new(0);
+    let result: anyhow::Result<()> = NO_WAIT.run("https://example.com", || {
+        attempts.set(attempts.get() + 1);
+        Err(anyhow!("Navigate failed: net::ERR_INTERNET_DISCONNECTED"))
+    });
+    let error = result.unwrap_err();
+    assert_eq!(attempts.get(), 4);
+    assert!(error.to_string().contains("failed 4 times"), "{}", error);
+    assert!(is_transient(&error));
+}
+
+#[test]
+fn backs_off_exponentially() {
+    let retries = Retries::default();
+    assert_eq!(retries.retries, NAVIGATION_RETRIES);
+    assert_eq!(retries.delay(1), NAVIGATION_BACKOFF);
+    assert_eq!(retries.delay(2), NAVIGATION_BACKOFF * 2);
+    assert_eq!(retries.delay(3), NAVIGATION_BACKOFF * 4);
+    assert_eq!(retries.delay(30), Duration::from_secs(15));
+}
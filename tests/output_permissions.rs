@@ -0,0 +1,114 @@
+#![cfg(unix)]
+
+mod common;
+
+use std::error::Error;
+use std::ffi::CStr;
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::path::Path;
+use std::process::Command;
+
+use kv_downloader::audio::exporters::DawTargets;
+use kv_downloader::audio::{AudioProcessor, ProcessOptions};
+use kv_downloader::config::Config;
+use kv_downloader::permissions::parse_mode;
+use common::write_download;
+
+/// Every path under `root`, `root` included.
+fn tree(root: &Path) -> Result<Vec<std::path::PathBuf>, Box<dyn Error>> {
+    let mut paths = vec![root.to_path_buf()];
+    let mut i = 0;
+    while i < paths.len() {
+        if paths[i].is_dir() {
+            for entry in std::fs::read_dir(&paths[i])? {
+                paths.push(entry?.path());
+            }
+        }
+        i += 1;
+    }
+    Ok(paths)
+}
+
+fn assert_applied(root: &Path, gid: u32) -> Result<(), Box<dyn Error>> {
+    for path in tree(root)? {
+        let metadata = std::fs::metadata(&path)?;
+        let mode = metadata.permissions().mode() & 0o7777;
+        let wanted = if metadata.is_dir() { 0o2775 } else { 0o664 };
+        assert_eq!(mode, wanted, "{:?} is {:o}", path, mode);
+        assert_eq!(metadata.gid(), gid, "{:?}", path);
+    }
+    Ok(())
+}
+
+#[test]
+fn parses_octal_modes() {
+    assert_eq!(parse_mode("664"), Ok(0o664));
+    assert_eq!(parse_mode("0o2775"), Ok(0o2775));
+    assert_eq!(parse_mode("0640"), Ok(0o640));
+    assert!(parse_mode("rw-r--r--").is_err());
+    assert!(parse_mode("19").is_err());
+    assert!(parse_mode("17777").is_err());
+
+    assert!(Config::parse("[output]\nfile_mode = \"rw\"").is_err());
+    assert_eq!(Config::parse("[output]\nowner = \"band\"").unwrap().unknown, ["output.owner"]);
+    assert!(Config::parse("[output]\ngroup = \"no-such-group-here\"").is_err());
+}
+
+#[test]
+fn applies_the_modes_and_group_to_every_output() -> Result<(), Box<dyn Error>> {
+    // Nothing would be group-writable by default
+    unsafe { libc::umask(0o077) };
+    let gid = unsafe { libc::getegid() };
+    let group = unsafe { CStr::from_ptr((*libc::getgrgid(gid)).gr_name) }.to_string_lossy().into_owned();
+    let toml = format!("[output]\nfile_mode = \"664\"\ndir_mode = \"2775\"\ngroup = \"{}\"", group);
+    let config = Config::parse(&toml)?;
+
+    let tmp = tempfile::tempdir()?;
+    for (part, seconds) in [("Click", 2.0), ("Bass", 1.5)] {
+        let name = format!("Smashing_Pumpkins_Cherub_Rock({}_Custom_Backing_Track).mp3", part);
+        write_download(&tmp.path().join(name), seconds)?;
+    }
+    let options = ProcessOptions {
+        keep_mp3s: true,
+        daws: DawTargets {
+            reaper: true,
+            ableton: true,
+            dawproject: true,
+        },
+        permissions: config.output.clone(),
+        ..Default::default()
+    };
+    let report = AudioProcessor::process_downloads(tmp.path(), "cherub_rock", &[], &options)?;
+    assert!(report.is_clean(), "{:?}", report.warnings);
+
+    // Stems, MP3s, manifests and every project format
+    let song_dir = tmp.path().join("Cherub Rock");
+    let files: Vec<String> = tree(&song_dir)?
+        .iter()
+        .filter_map(|p| p.extension().map(|e| e.to_string_lossy().into_owned()))
+        .collect();
+    for extension in ["wav", "mp3", "json", "csv", "rpp", "fcpxml", "mid", "als", "dawproject"] {
+        assert!(files.iter().any(|e| e == extension), "no .{} in {:?}", extension, files);
+    }
+    assert_applied(&song_dir, gid)?;
+
+    // Regenerating the projects gives the new ones the same
+    AudioProcessor::regenerate_projects(&song_dir, &options)?;
+    assert_applied(&song_dir, gid)?;
+
+    // The bundle command reads the same settings from its --config
+    let config_file = tmp.path().join("config.toml");
+    std::fs::write(&config_file, toml)?;
+    let out = tmp.path().join("gig");
+    let status = Command::new(env!("CARGO_BIN_EXE_kv_downloader"))
+        .args(["bundle", "--format", "stereo-band", "--songs"])
+        .arg(&song_dir)
+        .arg("--out")
+        .arg(&out)
+        .arg("--config")
+        .arg(&config_file)
+        .status()?;
+    assert!(status.success());
+    assert_applied(&out, gid)?;
+    Ok(())
+}
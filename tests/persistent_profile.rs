@@ -0,0 +1,28 @@
+use std::error::Error;
+use std::path::Path;
+
+use kv_downloader::config::Config;
+use kv_downloader::driver::lock_profile;
+
+#[test]
+fn locks_the_profile_for_one_run_at_a_time() -> Result<(), Box<dyn Error>> {
+    let dir = tempfile::tempdir()?;
+    let profile = dir.path().join("chrome-profile");
+
+    let lock = lock_profile(&profile)?;
+    assert!(profile.is_dir());
+    let second = lock_profile(&profile).unwrap_err();
+    assert!(second.to_string().contains("in use"), "{}", second);
+
+    drop(lock);
+    lock_profile(&profile)?;
+    Ok(())
+}
+
+#[test]
+fn reads_the_persistent_profile_from_the_config() -> Result<(), Box<dyn Error>> {
+    let config = Config::parse("[download]\npersistent_profile = \"/var/lib/kv/profile\"\n")?;
+    assert_eq!(config.download.persistent_profile.as_deref(), Some(Path::new("/var/lib/kv/profile")));
+    assert!(Config::default().download.persistent_profile.is_none());
+    Ok(())
+}
@@ -0,0 +1,177 @@
+use kv_downloader::audio::pipeline::{Audio, Pipeline, Stage, StageSpec, DEFAULT_RATE};
+use kv_downloader::config::Config;
+
+fn stereo(left: &[f32], right: &[f32]) -> Audio {
+    Audio {
+        sample_rate: 1000,
+        channels: vec![left.to_vec(), right.to_vec()],
+    }
+}
+
+fn spec(stage: Stage, tracks: &[&str]) -> StageSpec {
+    StageSpec {
+        stage,
+        tracks: tracks.iter().map(|t| t.to_string()).collect(),
+    }
+}
+
+fn assert_close(actual: &[f32], expected: &[f32]) {
+    assert_eq!(actual.len(), expected.len(), "{:?} vs {:?}", actual, expected);
+    for (a, e) in actual.iter().zip(expected) {
+        assert!((a - e).abs() < 1e-4, "{:?} vs {:?}", actual, expected);
+    }
+}
+
+#[test]
+fn stage_order_matters() {
+    let gain_then_normalize = Pipeline {
+        stages: vec![
+            spec(Stage::Gain { db: -6.0 }, &[]),
+            spec(Stage::Normalize { peak_db: 0.0 }, &[]),
+        ],
+    };
+    let normalize_then_gain = Pipeline {
+        stages: vec![
+            spec(Stage::Normalize { peak_db: 0.0 }, &[]),
+            spec(Stage::Gain { db: -6.0 }, &[]),
+        ],
+    };
+
+    let mut a = stereo(&[0.25, -0.5], &[0.1, 0.0]);
+    let mut b = a.clone();
+    gain_then_normalize.apply("Bass", &mut a);
+    normalize_then_gain.apply("Bass", &mut b);
+
+    assert_close(&a.channels[0], &[0.5, -1.0]);
+    assert_close(&b.channels[0], &[0.2506, -0.5012]);
+}
+
+#[test]
+fn matchers_scope_stages_to_tracks() {
+    let pipeline = Pipeline {
+        stages: vec![
+            spec(Stage::Invert, &["guitar"]),
+            spec(Stage::SwapChannels, &["Bass", "Keys"]),
+            spec(Stage::Gain { db: 0.0 }, &[]),
+        ],
+    };
+
+    let mut guitar = stereo(&[0.5], &[0.25]);
+    let applied = pipeline.apply("Electric Guitar", &mut guitar);
+    assert_eq!(applied, vec!["invert", "gain(0 dB)"]);
+    assert_close(&guitar.channels[0], &[-0.5]);
+
+    let mut bass = stereo(&[0.5], &[0.25]);
+    assert_eq!(pipeline.apply("bass", &mut bass), vec!["swap-channels", "gain(0 dB)"]);
+    assert_close(&bass.channels[0], &[0.25]);
+
+    let mut click = stereo(&[0.5], &[0.25]);
+    pipeline.apply("Click", &mut click);
+    assert_eq!(click, stereo(&[0.5], &[0.25]));
+}
+
+#[test]
+fn shape_changing_stages() {
+    let pipeline = Pipeline {
+        stages: vec![
+            spec(Stage::Trim { start: 0.002, end: Some(0.006) }, &[]),
+            spec(Stage::Mono, &[]),
+            spec(Stage::Fade { fade_in: 0.002, fade_out: 0.0 }, &[]),
+            spec(Stage::Resample { rate: 500 }, &[]),
+        ],
+    };
+    let mut audio = stereo(
+        &[0.0, 0.0, 0.4, 0.4, 0.4, 0.4, 0.0, 0.0],
+        &[0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+    );
+    pipeline.apply("Bass", &mut audio);
+
+    assert_eq!(audio.sample_rate, 500);
+    assert_eq!(audio.channels.len(), 2);
+    assert_close(&audio.channels[0], &[0.0, 0.2]);
+    assert_eq!(audio.channels[0], audio.channels[1]);
+}
+
+#[test]
+fn dc_filter_removes_offset() {
+    let pipeline = Pipeline {
+        stages: vec![spec(Stage::DcFilter, &[])],
+    };
+    let mut audio = Audio {
+        sample_rate: 1000,
+        channels: vec![vec![0.5; 2000]],
+    };
+    pipeline.apply("Bass", &mut audio);
+    assert!(audio.channels[0][1999].abs() < 0.01);
+}
+
+#[test]
+fn default_pipeline_keeps_stems_at_kvs_rate() {
+    let pipeline = Pipeline::default();
+    assert_eq!(pipeline.stages, vec![spec(Stage::Resample { rate: DEFAULT_RATE }, &[])]);
+
+    let samples = [i16::MIN, -12345, -1, 0, 1, 20000, i16::MAX, 7];
+    let mut audio = Audio::from_interleaved(&samples, 2, DEFAULT_RATE);
+    assert_eq!(pipeline.apply("Bass", &mut audio), vec!["resample(44100 Hz)"]);
+    assert_eq!(audio.to_interleaved(), samples);
+
+    let mut odd = Audio {
+        sample_rate: 88_200,
+        channels: vec![vec![0.5; 8]],
+    };
+    pipeline.apply("Bass", &mut odd);
+    assert_eq!(odd.sample_rate, DEFAULT_RATE);
+    assert_eq!(odd.frames(), 4);
+}
+
+#[test]
+fn a_pipeline_table_replaces_the_default_stages() {
+    assert_eq!(Config::parse("").unwrap().pipeline, Pipeline::default());
+    assert_eq!(Config::parse("[pipeline]").unwrap().pipeline, Pipeline::default());
+    assert!(Config::parse("[pipeline]\nstages = []").unwrap().pipeline.stages.is_empty());
+}
+
+#[test]
+fn reads_pipeline_from_config() {
+    let config = Config::parse(
+        r#"
+        [[pipeline.stages]]
+        stage = "dc-filter"
+
+        [[pipeline.stages]]
+        stage = "gain"
+        db = -3.0
+        tracks = ["Bass"]
+
+        [[pipeline.stages]]
+        stage = "fade"
+        out = 2.5
+        "#,
+    )
+    .unwrap();
+
+    assert_eq!(
+        config.pipeline.stages,
+        vec![
+            spec(Stage::DcFilter, &[]),
+            spec(Stage::Gain { db: -3.0 }, &["Bass"]),
+            spec(Stage::Fade { fade_in: 0.0, fade_out: 2.5 }, &[]),
+        ]
+    );
+    assert_eq!(config.pipeline.stages_for("Drums").len(), 2);
+}
+
+#[test]
+fn rejects_invalid_stages() {
+    let invalid = [
+        "[[pipeline.stages]]\nstage = \"reverb\"",
+        "[[pipeline.stages]]\nstage = \"gain\"",
+        "[[pipeline.stages]]\nstage = \"gain\"\ndb = 3.0\ndecibels = 3.0",
+        "[[pipeline.stages]]\nstage = \"resample\"\nrate = 12",
+        "[[pipeline.stages]]\nstage = \"normalize\"\npeak_db = 3.0",
+        "[[pipeline.stages]]\nstage = \"trim\"\nstart = 5.0\nend = 2.0",
+    ];
+    for config in invalid {
+        assert!(Config::parse(config).is_err(), "{}", config);
+    }
+}
@@ -0,0 +1,119 @@
+mod common;
+
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use kv_downloader::abort::Interrupted;
+use kv_downloader::audio::track_map::TrackMap;
+use kv_downloader::audio::{AudioProcessor, ProcessOptions, SongFolder};
+use kv_downloader::tasks::download_song::DownloadError;
+use common::{write_ramp, RATE};
+
+fn fabricate_song(root: &Path, title: &str) -> Result<std::path::PathBuf, Box<dyn Error>> {
+    let song_dir = root.join(title);
+    let mono = song_dir.join("STEMS").join("WAV MONO");
+    let stereo = song_dir.join("STEMS").join("WAV ST");
+    fs::create_dir_all(&mono)?;
+    fs::create_dir_all(&stereo)?;
+    for name in ["Bass", "Click"] {
+        write_ramp(&mono.join(format!("{}_mono.wav", name)), 1, RATE, (RATE as f32 * 0.5) as u32)?;
+        write_ramp(&stereo.join(format!("{}.wav", name)), 2, RATE, (RATE as f32 * 0.5) as u32)?;
+    }
+    Ok(song_dir)
+}
+
+#[test]
+fn regenerates_projects_from_existing_wavs() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    let song_dir = fabricate_song(tmp.path(), "Cherub Rock")?;
+    let wav_before = fs::read(song_dir.join("STEMS/WAV MONO/Bass_mono.wav"))?;
+
+    let report = AudioProcessor::regenerate_projects(&song_dir, &ProcessOptions::default())?;
+    assert!(report.is_clean(), "{:?}", report.warnings);
+
+    let rpp = fs::read_to_string(song_dir.join("MT PROJECT/Cherub Rock.rpp"))?;
+    let click_at = rpp.find("NAME \"Click_mono\"").expect("click track");
+    let bass_at = rpp.find("NAME \"Bass_mono\"").expect("bass track");
+    assert!(click_at < bass_at, "click should be the first track");
+    assert!(song_dir.join("MT PROJECT/Cherub Rock.fcpxml").exists());
+
+    // audio is left untouched
+    assert_eq!(fs::read(song_dir.join("STEMS/WAV MONO/Bass_mono.wav"))?, wav_before);
+    Ok(())
+}
+
+#[test]
+fn skipping_every_generator_creates_no_project_folder() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    let song_dir = fabricate_song(tmp.path(), "Cherub Rock")?;
+
+    let options = ProcessOptions {
+        skip_rpp: true,
+        skip_fcpxml: true,
+        skip_midi: true,
+        ..Default::default()
+    };
+    AudioProcessor::regenerate_projects(&song_dir, &options)?;
+
+    assert!(!song_dir.join("MT PROJECT").exists());
+    Ok(())
+}
+
+#[test]
+fn reaper_project_points_at_stems_relative_to_itself() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    let song_dir = fabricate_song(tmp.path(), "Cherub Rock")?;
+
+    AudioProcessor::regenerate_projects(&song_dir, &ProcessOptions::default())?;
+    let rpp = fs::read_to_string(song_dir.join("MT PROJECT/Cherub Rock.rpp"))?;
+    assert!(rpp.contains("FILE \"../STEMS/WAV MONO/Bass_mono.wav\""), "{}", rpp);
+
+    let options = ProcessOptions {
+        rpp_absolute_paths: true,
+        ..Default::default()
+    };
+    AudioProcessor::regenerate_projects(&song_dir, &options)?;
+    let rpp = fs::read_to_string(song_dir.join("MT PROJECT/Cherub Rock.rpp"))?;
+    let absolute = std::path::absolute(song_dir.join("STEMS/WAV MONO/Bass_mono.wav"))?;
+    assert!(rpp.contains(&format!("FILE \"{}\"", absolute.to_string_lossy().replace('\\', "/"))));
+    Ok(())
+}
+
+#[test]
+fn picks_up_a_folder_processing_left_unfinished() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    assert_eq!(AudioProcessor::song_folder(tmp.path(), "cherub_rock")?, SongFolder::Missing);
+    let song_dir = fabricate_song(tmp.path(), "Cherub Rock")?;
+    assert_eq!(AudioProcessor::song_folder(tmp.path(), "cherub_rock")?, SongFolder::Unfinished);
+    TrackMap::default().save(&song_dir)?;
+    assert_eq!(AudioProcessor::song_folder(tmp.path(), "cherub_rock")?, SongFolder::Processed);
+    Ok(())
+}
+
+#[test]
+fn an_interrupted_song_is_left_to_resume() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    for part in ["Click", "Bass", "Drums"] {
+        let name = format!("Smashing_Pumpkins_Cherub_Rock({}_Custom_Backing_Track).mp3", part);
+        write_ramp(&tmp.path().join(name), 2, RATE, (RATE as f32 * 2.0) as u32)?;
+    }
+    let options = ProcessOptions {
+        skip_validation: true,
+        skip_rpp: true,
+        skip_fcpxml: true,
+        skip_midi: true,
+        ..Default::default()
+    };
+    options.abort.request();
+
+    let error = AudioProcessor::process_downloads(tmp.path(), "cherub_rock", &[], &options).unwrap_err();
+    assert!(error.downcast_ref::<Interrupted>().is_some());
+    // Recorded as cancelled, so the batch state keeps it pending
+    assert!(DownloadError::is_cancelled(&error));
+    // The downloads stay, and the folder is picked up again rather than skipped
+    assert_eq!(AudioProcessor::song_folder(tmp.path(), "cherub_rock")?, SongFolder::Unfinished);
+    let mp3s = fs::read_dir(tmp.path())?.filter(|e| e.as_ref().is_ok_and(|e| e.path().extension().is_some_and(|ext| ext == "mp3")));
+    assert_eq!(mp3s.count(), 3);
+    Ok(())
+}
@@ -0,0 +1,150 @@
+mod common;
+
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use kv_downloader::audio::pipeline::{Pipeline, Stage, StageSpec};
+use kv_downloader::audio::validation::ReferenceSource;
+use kv_downloader::audio::{AudioProcessor, ProcessOptions};
+use common::{write_download, RATE};
+
+fn write_song(dir: &Path, parts: &[(&str, f64)]) -> Result<(), Box<dyn Error>> {
+    for (i, (part, seconds)) in parts.iter().enumerate() {
+        // Duplicate parts get Chrome's " (1)" marker
+        let marker = if parts[..i].iter().any(|(p, _)| p == part) { " (1)" } else { "" };
+        let name = format!("Smashing_Pumpkins_Cherub_Rock({}_Custom_Backing_Track){}.mp3", part, marker);
+        write_download(&dir.join(name), *seconds)?;
+    }
+    Ok(())
+}
+
+fn listing(dir: &Path) -> Result<Vec<String>, Box<dyn Error>> {
+    let mut names: Vec<String> = fs::read_dir(dir)?
+        .map(|e| e.map(|e| e.file_name().to_string_lossy().into_owned()))
+        .collect::<Result<_, _>>()?;
+    names.sort();
+    Ok(names)
+}
+
+#[test]
+fn plans_every_stem_from_the_headers_without_writing_anything() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    write_song(tmp.path(), &[("Click", 2.0), ("Bass", 1.5)])?;
+    let before = listing(tmp.path())?;
+
+    let plan = AudioProcessor::plan_downloads(tmp.path(), "cherub_rock", &ProcessOptions::default())?;
+    assert_eq!(listing(tmp.path())?, before);
+
+    let song_dir = tmp.path().join("Cherub Rock");
+    assert_eq!(plan.song_dir, song_dir);
+    assert_eq!(plan.reference_secs, 2.0);
+    assert_eq!(plan.reference_source, ReferenceSource::ClickHeader);
+    let names: Vec<&str> = plan.stems.iter().map(|s| s.name.as_str()).collect();
+    assert_eq!(names, ["Click", "Bass"]);
+
+    let (click, bass) = (&plan.stems[0], &plan.stems[1]);
+    assert!(click.is_reference && !bass.is_reference);
+    assert_eq!(click.padding_secs, 0.0);
+    assert!((bass.padding_secs - 0.5).abs() < 1e-6, "{}", bass.padding_secs);
+    assert_eq!((bass.sample_rate, bass.channels), (Some(RATE), Some(2)));
+    assert!(!bass.resamples());
+    assert_eq!(bass.stages, ["resample(44100 Hz)"]);
+    assert_eq!(bass.stereo, song_dir.join("STEMS/WAV ST/Bass.wav"));
+    assert_eq!(bass.mono, song_dir.join("STEMS/WAV MONO/Bass_mono.wav"));
+    assert_eq!(bass.mp3, None);
+    assert_eq!(plan.exporters, ["Reaper project", "FCPXML", "MIDI file"]);
+
+    let table = plan.to_string();
+    assert!(table.contains("Padded to 2.00s from the click's header"), "{}", table);
+    assert!(table.contains("Bass: STEMS/WAV ST/Bass.wav, STEMS/WAV MONO/Bass_mono.wav"), "{}", table);
+    Ok(())
+}
+
+#[test]
+fn plans_the_pipeline_stages_of_each_stem() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    write_song(tmp.path(), &[("Click", 2.0), ("Bass", 2.0), ("Lead Vocal", 2.0)])?;
+    let options = ProcessOptions {
+        pipeline: Pipeline {
+            stages: vec![
+                StageSpec {
+                    stage: Stage::Gain { db: 3.0 },
+                    tracks: vec!["bass".to_string()],
+                },
+                StageSpec {
+                    stage: Stage::Resample { rate: 48000 },
+                    tracks: vec![],
+                },
+                StageSpec {
+                    stage: Stage::Gain { db: -1.5 },
+                    tracks: vec!["bass".to_string()],
+                },
+                StageSpec {
+                    stage: Stage::Normalize { peak_db: -1.0 },
+                    tracks: vec!["vocal".to_string()],
+                },
+            ],
+        },
+        keep_mp3s: true,
+        skip_rpp: true,
+        skip_fcpxml: true,
+        skip_midi: true,
+        ..Default::default()
+    };
+    let plan = AudioProcessor::plan_downloads(tmp.path(), "cherub_rock", &options)?;
+
+    let bass = &plan.stems[1];
+    assert_eq!(bass.stages, ["gain(3 dB)", "resample(48000 Hz)", "gain(-1.5 dB)"]);
+    assert_eq!(bass.gain_db, 1.5);
+    assert_eq!(bass.normalize_peak_db, None);
+    assert!(bass.resamples());
+    assert_eq!(bass.output_rate, Some(48000));
+
+    let vocal = &plan.stems[2];
+    assert_eq!(vocal.gain_db, 0.0);
+    assert_eq!(vocal.normalize_peak_db, Some(-1.0));
+    assert_eq!(vocal.mp3, Some(tmp.path().join("Cherub Rock/STEMS/MP3/Lead Vocal.mp3")));
+
+    assert!(plan.exporters.is_empty());
+    let table = plan.to_string();
+    assert!(table.contains("44100 -> 48000"), "{}", table);
+    assert!(table.ends_with("No projects"), "{}", table);
+    Ok(())
+}
+
+#[test]
+fn processing_writes_what_was_planned() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    write_song(tmp.path(), &[("Click", 2.0), ("Bass", 1.5), ("Bass", 1.0)])?;
+    let options = ProcessOptions {
+        skip_rpp: true,
+        skip_fcpxml: true,
+        skip_midi: true,
+        ..Default::default()
+    };
+    let plan = AudioProcessor::plan_downloads(tmp.path(), "cherub_rock", &options)?;
+    let names: Vec<&str> = plan.stems.iter().map(|s| s.name.as_str()).collect();
+    assert_eq!(names, ["Click", "Bass", "Bass 2"]);
+
+    AudioProcessor::process_downloads(tmp.path(), "cherub_rock", &[], &options)?;
+    for stem in &plan.stems {
+        assert!(stem.stereo.is_file(), "{:?}", stem.stereo);
+        assert!(stem.mono.is_file(), "{:?}", stem.mono);
+    }
+    Ok(())
+}
+
+#[test]
+fn serializes_the_plan() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    write_song(tmp.path(), &[("Click", 2.0), ("Bass", 1.5)])?;
+    let plan = AudioProcessor::plan_downloads(tmp.path(), "cherub_rock", &ProcessOptions::default())?;
+
+    let json: serde_json::Value = serde_json::to_value(&plan)?;
+    assert_eq!(json["reference_source"], "click-header");
+    assert_eq!(json["stems"][1]["name"], "Bass");
+    assert_eq!(json["stems"][1]["sample_rate"], RATE);
+    assert!(json["stems"][1]["mp3"].is_null());
+    Ok(())
+}
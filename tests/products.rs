@@ -0,0 +1,41 @@
+use std::error::Error;
+use std::fs;
+
+use kv_downloader::tasks::products::{filter_script, product_dir, product_files, ProductType};
+
+#[test]
+fn parses_product_types() {
+    assert_eq!("cbt".parse(), Ok(ProductType::Cbt));
+    assert_eq!(" Video ".parse(), Ok(ProductType::Video));
+    assert_eq!("lyrics".parse(), Ok(ProductType::Lyrics));
+    assert!("mp4".parse::<ProductType>().unwrap_err().contains("cbt, video or lyrics"));
+    assert_eq!(ProductType::Lyrics.to_string(), "lyrics");
+}
+
+#[test]
+fn files_products_into_their_folder_of_the_song() {
+    let root = std::path::Path::new("/music");
+    assert_eq!(product_dir(root, "Africa - Toto", ProductType::Video), root.join("Africa - Toto").join("VIDEO"));
+    assert_eq!(product_dir(root, "Africa - Toto", ProductType::Lyrics), root.join("Africa - Toto").join("LYRICS"));
+    assert_eq!(product_dir(root, "Africa - Toto", ProductType::Cbt), root.join("Africa - Toto"));
+}
+
+#[test]
+fn picks_the_filter_by_value_or_label() {
+    let cbt = filter_script(ProductType::Cbt);
+    assert!(cbt.contains(r#"let value = "1";"#));
+    let video = filter_script(ProductType::Video);
+    assert!(video.contains("let value = null;"));
+    assert!(video.contains(r#""vidéo""#));
+}
+
+#[test]
+fn partial_downloads_are_not_products() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    assert!(product_files(&tmp.path().join("VIDEO")).is_empty());
+    fs::write(tmp.path().join("Africa.mp4.crdownload"), b"partial")?;
+    assert!(product_files(tmp.path()).is_empty());
+    fs::write(tmp.path().join("Africa.mp4"), b"video")?;
+    assert_eq!(product_files(tmp.path()), vec![tmp.path().join("Africa.mp4")]);
+    Ok(())
+}
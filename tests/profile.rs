@@ -0,0 +1,96 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use kv_downloader::keystore::SecretStore;
+use kv_downloader::profile::{self, file_name, parse_profile};
+use kv_downloader::tasks::batch_state::{BatchStateFile, SongStatus, BATCH_STATE_FILE};
+
+const DOMAIN: &str = "www.karaoke-version.com";
+
+/// Keeps the secrets in memory.
+#[derive(Default)]
+struct MemoryStore(Mutex<HashMap<String, String>>);
+
+impl SecretStore for MemoryStore {
+    fn get_secret(&self, name: &str) -> Result<Option<String>> {
+        Ok(self.0.lock().unwrap().get(name).cloned())
+    }
+
+    fn set_secret(&self, name: &str, secret: &str) -> Result<()> {
+        self.0.lock().unwrap().insert(name.to_string(), secret.to_string());
+        Ok(())
+    }
+
+    fn delete_secret(&self, name: &str) -> Result<()> {
+        self.0.lock().unwrap().remove(name);
+        Ok(())
+    }
+}
+
+#[test]
+fn keeps_each_profiles_credentials_apart() -> Result<()> {
+    let store: Arc<MemoryStore> = Arc::default();
+    let default = profile::secrets(store.clone(), None);
+    let band_a = profile::secrets(store.clone(), Some("bandA"));
+    let band_b = profile::secrets(store.clone(), Some("bandB"));
+
+    default.login("me", "hunter2", None)?;
+    band_a.login("band-a", "hunter3", None)?;
+    assert_eq!(default.get_credentials(DOMAIN)?.user, "me");
+    assert_eq!(band_a.get_credentials(DOMAIN)?.user, "band-a");
+    assert!(band_b.get_credentials(DOMAIN).is_err());
+
+    // The default profile's entries are those from before profiles
+    assert!(store.get_secret("KV_CREDENTIALS")?.is_some());
+    assert!(store.get_secret("KV_CREDENTIALS#bandA")?.is_some());
+
+    band_a.logout(None)?;
+    assert!(band_a.get_credentials(DOMAIN).is_err());
+    assert_eq!(default.get_credentials(DOMAIN)?.user, "me");
+    Ok(())
+}
+
+#[test]
+fn lists_the_registered_profiles() -> Result<()> {
+    let store = MemoryStore::default();
+    assert!(profile::list(&store)?.is_empty());
+
+    profile::register(&store, "bandB")?;
+    profile::register(&store, "bandA")?;
+    profile::register(&store, "bandB")?;
+    assert_eq!(profile::list(&store)?, ["bandA", "bandB"]);
+
+    profile::unregister(&store, "bandB")?;
+    profile::unregister(&store, "nobody")?;
+    assert_eq!(profile::list(&store)?, ["bandA"]);
+    Ok(())
+}
+
+#[test]
+fn names_the_files_of_a_profile() {
+    assert_eq!(file_name("track_list.json", None), "track_list.json");
+    assert_eq!(file_name("track_list.json", Some("bandA")), "track_list.bandA.json");
+    assert_eq!(file_name("state", Some("bandA")), "state.bandA");
+}
+
+#[test]
+fn takes_only_plain_profile_names() {
+    assert_eq!(parse_profile("band_A-2").unwrap(), "band_A-2");
+    assert!(parse_profile("").is_err());
+    assert!(parse_profile("../other").is_err());
+    assert!(parse_profile("band a").is_err());
+}
+
+#[test]
+fn keeps_a_batch_state_per_profile() -> Result<()> {
+    let tmp = tempfile::tempdir()?;
+    let url = "https://www.karaoke-version.com/custombackingtrack/a/b.html";
+    BatchStateFile::open_for(tmp.path(), Some("bandA"), false)?.update(|state| state.set(url, SongStatus::Processed, None))?;
+
+    assert!(tmp.path().join("batch_state.bandA.json").exists());
+    assert!(!tmp.path().join(BATCH_STATE_FILE).exists());
+    assert!(BatchStateFile::open(tmp.path(), false)?.snapshot().songs.is_empty());
+    assert_eq!(BatchStateFile::open_for(tmp.path(), Some("bandA"), false)?.snapshot().count(SongStatus::Processed), 1);
+    Ok(())
+}
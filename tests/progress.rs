@@ -0,0 +1,158 @@
+use std::sync::{Arc, Barrier, Mutex};
+use std::thread;
+use std::thread::sleep;
+use std::time::Duration;
+
+use kv_downloader::status::{format_eta, ProgressEvent, StatusHandle, ROLLING_SONGS};
+
+fn recorder(status: &StatusHandle) -> Arc<Mutex<Vec<ProgressEvent>>> {
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let sink = events.clone();
+    status.subscribe(move |event| sink.lock().unwrap().push(event.clone()));
+    events
+}
+
+#[test]
+fn reports_songs_and_tracks_as_they_go() {
+    let status = StatusHandle::new(3);
+    let events = recorder(&status);
+
+    status.start_song(0, "https://example.com/song-1");
+    status.song(0).start_track(0, 2, "Click");
+    assert_eq!(status.snapshot().track, Some((1, 2)));
+    status.song(0).start_track(1, 2, "Bass");
+    status.finish_song(0);
+    status.skip_song(1, "https://example.com/song-2");
+    status.start_song(2, "https://example.com/song-3");
+    status.fail_song(2, "https://example.com/song-3", "NotPurchased");
+    status.finish_batch();
+
+    let events = events.lock().unwrap();
+    let kinds: Vec<String> = events
+        .iter()
+        .map(|event| serde_json::to_value(event).unwrap()["event"].as_str().unwrap().to_string())
+        .collect();
+    assert_eq!(
+        kinds,
+        [
+            "song-started",
+            "track-started",
+            "track-started",
+            "song-finished",
+            "song-skipped",
+            "song-started",
+            "song-failed",
+            "batch-finished"
+        ]
+    );
+    assert_eq!(
+        events[2],
+        ProgressEvent::TrackStarted {
+            song: 1,
+            index: 2,
+            total: 2,
+            name: "Bass".to_string()
+        }
+    );
+    assert_eq!(
+        events[7],
+        ProgressEvent::BatchFinished {
+            completed: 1,
+            failed: 1,
+            cancelled: 0,
+            skipped: 1
+        }
+    );
+}
+
+#[test]
+fn skipped_songs_stay_out_of_the_eta() {
+    let status = StatusHandle::new(10);
+    status.start_song(0, "https://example.com/song-1");
+    sleep(Duration::from_millis(100));
+    status.finish_song(0);
+    let eta = status.snapshot().eta_seconds.unwrap();
+    assert!((0.9..2.0).contains(&eta), "{}", eta);
+
+    // Found at once, so they'd drag the average towards nothing
+    for index in 1..4 {
+        status.skip_song(index, "https://example.com/done");
+    }
+    status.start_song(4, "https://example.com/song-5");
+    sleep(Duration::from_millis(100));
+    status.finish_song(4);
+    let eta = status.snapshot().eta_seconds.unwrap();
+    // Five songs left at about 100ms each
+    assert!((0.5..1.0).contains(&eta), "{}", eta);
+}
+
+#[test]
+fn averages_only_the_latest_songs() {
+    let status = StatusHandle::new(ROLLING_SONGS + 2);
+    status.start_song(0, "https://example.com/slow");
+    sleep(Duration::from_millis(500));
+    status.finish_song(0);
+    for index in 1..=ROLLING_SONGS {
+        status.start_song(index, "https://example.com/fast");
+        status.finish_song(index);
+    }
+    // The slow song has rolled out of the average
+    let eta = status.snapshot().eta_seconds.unwrap();
+    assert!(eta < 0.05, "{}", eta);
+}
+
+#[test]
+fn keeps_concurrent_songs_apart() {
+    let status = StatusHandle::new(2);
+    let events = recorder(&status);
+    let barrier = Barrier::new(2);
+
+    thread::scope(|scope| {
+        for index in 0..2 {
+            let (status, barrier) = (&status, &barrier);
+            scope.spawn(move || {
+                let url = format!("https://example.com/song-{}", index + 1);
+                status.start_song(index, &url);
+                barrier.wait();
+                status.song(index).start_track(index, 2, "Click");
+                status.finish_phase(index, "download");
+                barrier.wait();
+                if index == 0 {
+                    status.finish_song(index);
+                }
+            });
+        }
+    });
+
+    // The second song is still in flight, with its own track and phases
+    let snapshot = status.snapshot();
+    assert_eq!(snapshot.completed, 1);
+    assert_eq!(snapshot.current_song.as_deref(), Some("https://example.com/song-2"));
+    assert_eq!(snapshot.index, 2);
+    assert_eq!(snapshot.track, Some((2, 2)));
+    assert_eq!(snapshot.in_flight.len(), 1);
+    assert_eq!(snapshot.in_flight[0].phases.len(), 1);
+
+    let finished: Vec<_> = events
+        .lock()
+        .unwrap()
+        .iter()
+        .filter_map(|event| match event {
+            ProgressEvent::SongFinished { index, .. } => Some(*index),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(finished, [1]);
+
+    status.finish_song(1);
+    let snapshot = status.snapshot();
+    assert_eq!(snapshot.completed, 2);
+    assert!(snapshot.in_flight.is_empty());
+}
+
+#[test]
+fn formats_the_eta() {
+    assert_eq!(format_eta(45.2), "45s");
+    assert_eq!(format_eta(750.0), "12m 30s");
+    assert_eq!(format_eta(3900.0), "1h 05m");
+}
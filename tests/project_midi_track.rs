@@ -0,0 +1,119 @@
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use hound::{SampleFormat, WavSpec, WavWriter};
+use kv_downloader::audio::reaper::{midi_item_events, ProjectMidiTrack, ProjectTempo};
+use kv_downloader::audio::tempo::{ClickTiming, TimeSignature};
+use kv_downloader::audio::{AudioProcessor, ProcessOptions};
+
+const RATE: u32 = 44100;
+
+/// A mono click at `bpm` whose first hit lands at 0.25s, plus a quiet bass, 3s long.
+fn song(root: &Path, bpm: f64) -> Result<PathBuf, Box<dyn Error>> {
+    let song_dir = root.join("Cherub Rock");
+    let mono = song_dir.join("STEMS").join("WAV MONO");
+    fs::create_dir_all(&mono)?;
+    let spec = WavSpec {
+        channels: 1,
+        sample_rate: RATE,
+        bits_per_sample: 16,
+        sample_format: SampleFormat::Int,
+    };
+    let beat = (RATE as f64 * 60.0 / bpm) as usize;
+    let mut click = WavWriter::create(mono.join("Click_mono.wav"), spec)?;
+    let mut bass = WavWriter::create(mono.join("Bass_mono.wav"), spec)?;
+    for i in 0..RATE as usize * 3 {
+        let since_first = i as i64 - RATE as i64 / 4;
+        let in_hit = since_first >= 0 && (since_first as usize % beat) < RATE as usize / 100;
+        click.write_sample(if in_hit { if i % 2 == 0 { 20000i16 } else { -20000 } } else { 0 })?;
+        bass.write_sample(((i % 200) as i16 - 100) * 10)?;
+    }
+    click.finalize()?;
+    bass.finalize()?;
+    Ok(song_dir)
+}
+
+fn project(song_dir: &Path, mode: ProjectMidiTrack) -> Result<String, Box<dyn Error>> {
+    let options = ProcessOptions {
+        skip_fcpxml: true,
+        skip_midi: true,
+        project_midi_track: mode,
+        ..Default::default()
+    };
+    let report = AudioProcessor::regenerate_projects(song_dir, &options)?;
+    assert!(report.is_clean(), "{:?}", report.warnings);
+    Ok(fs::read_to_string(song_dir.join("MT PROJECT/Cherub Rock.rpp"))?)
+}
+
+/// Compares `rpp` with `tests/fixtures/rpp/<name>`; `UPDATE_GOLDEN=1` rewrites it instead.
+fn assert_golden(name: &str, rpp: &str) -> Result<(), Box<dyn Error>> {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/rpp").join(name);
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::create_dir_all(path.parent().unwrap())?;
+        fs::write(&path, rpp)?;
+    }
+    let golden = fs::read_to_string(&path)?;
+    assert!(golden == rpp, "{} differs from the project written:\n{}", name, rpp);
+    Ok(())
+}
+
+#[test]
+fn off_writes_no_midi_track() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    let rpp = project(&song(tmp.path(), 120.0)?, ProjectMidiTrack::Off)?;
+    assert!(!rpp.contains("NAME \"MIDI\""));
+    assert!(!rpp.contains("<SOURCE MIDI"));
+    assert!(!rpp.contains("<ITEM MIDI"));
+    assert_eq!(rpp.matches("<TRACK").count(), 2);
+    assert_golden("off.rpp", &rpp)
+}
+
+#[test]
+fn empty_spans_the_stems_at_the_click_tempo() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    let rpp = project(&song(tmp.path(), 90.0)?, ProjectMidiTrack::Empty)?;
+    assert!(rpp.contains("TEMPO 90 4 4"));
+    // 3s at 90 BPM is 4.5 quarter notes of 960 ticks
+    assert!(rpp.contains("E 0 b0 7b 00\n        E 4320 b0 7b 00\n"), "{}", rpp);
+    assert_golden("empty.rpp", &rpp)
+}
+
+#[test]
+fn click_notes_land_on_the_clicks() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    let rpp = project(&song(tmp.path(), 120.0)?, ProjectMidiTrack::ClickNotes)?;
+    assert!(rpp.contains("TEMPO 120 4 4"));
+    // Six clicks from 0.25s, a bar of four then two
+    assert_eq!(rpp.matches(" 99 4c 7f").count(), 2);
+    assert_eq!(rpp.matches(" 99 4d 64").count(), 4);
+    assert_golden("click-notes.rpp", &rpp)
+}
+
+#[test]
+fn tick_math_follows_the_tempo_and_signature() -> anyhow::Result<()> {
+    let six_eight = TimeSignature { numerator: 6, denominator: 8 };
+    let click = ClickTiming {
+        bpm: 180.0,
+        first_beat: std::time::Duration::ZERO,
+    };
+    // Eighth-note clicks at 180 are quarter notes at 90
+    let tempo = ProjectTempo::from_click(&click, six_eight);
+    assert_eq!(tempo.bpm, 90.0);
+    assert_eq!(tempo.ticks(2.0)?, 2880);
+
+    let events = midi_item_events(&tempo, 2.0, Some(&click))?;
+    let lines: Vec<&str> = events.lines().collect();
+    // all-notes-off, six clicks of a note-on and a note-off, all-notes-off
+    assert_eq!(lines.len(), 2 + 6 * 2);
+    assert_eq!(lines[1], "E 0 99 4c 7f");
+    assert_eq!(lines[2], "E 120 89 4c 00");
+    // the next eighth is 480 ticks after the first
+    assert_eq!(lines[3], "E 360 99 4d 64");
+    assert_eq!(lines[13], "E 360 b0 7b 00");
+    assert_eq!(lines.iter().filter(|l| l.ends_with("4c 7f")).count(), 1);
+
+    assert_eq!("click-notes".parse(), Ok(ProjectMidiTrack::ClickNotes));
+    assert!("notes".parse::<ProjectMidiTrack>().is_err());
+    Ok(())
+}
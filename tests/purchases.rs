@@ -0,0 +1,79 @@
+use std::fs;
+
+use anyhow::Result;
+use kv_downloader::audio::track_map::TRACKS_FILE;
+use kv_downloader::metadata::SongInfo;
+use kv_downloader::tasks::products::ProductType;
+use kv_downloader::tasks::purchases::{self, Downloads, Purchase};
+use kv_downloader::tasks::song_list::ListedSong;
+
+const AFRICA: &str = "https://www.karaoke-version.com/custombackingtrack/toto/africa.html";
+const ROSANNA: &str = "https://www.karaoke-version.com/custombackingtrack/toto/rosanna.html";
+
+fn listed(url: &str, product: ProductType) -> ListedSong {
+    ListedSong {
+        url: url.to_string(),
+        title: Some("Toto, \"Africa\"".into()),
+        product,
+        purchased: Some("03/14/2024".into()),
+    }
+}
+
+#[test]
+fn finds_downloaded_songs_by_their_song_file_or_title() -> Result<()> {
+    let tmp = tempfile::tempdir()?;
+    // A folder named unlike the URL's title is found by its song.json
+    let renamed = tmp.path().join("Africa (Toto)");
+    fs::create_dir(&renamed)?;
+    SongInfo {
+        url: AFRICA.into(),
+        ..SongInfo::default()
+    }
+    .save(&renamed)?;
+    fs::write(renamed.join(TRACKS_FILE), "{}")?;
+    // Rosanna's folder was left unfinished, without its track map
+    fs::create_dir(tmp.path().join("Rosanna - Toto"))?;
+    fs::create_dir_all(tmp.path().join("Africa (Toto)").join("VIDEO"))?;
+    fs::write(tmp.path().join("Africa (Toto)").join("VIDEO").join("africa.mp4"), "")?;
+
+    let downloads = Downloads::scan(tmp.path());
+    assert!(downloads.has(&listed(AFRICA, ProductType::Cbt)));
+    assert!(!downloads.has(&listed(ROSANNA, ProductType::Cbt)));
+    assert!(downloads.has(&listed(AFRICA, ProductType::Video)));
+    assert!(!downloads.has(&listed(AFRICA, ProductType::Lyrics)));
+    Ok(())
+}
+
+#[test]
+fn prints_purchases_as_csv() {
+    let purchases = [Purchase::new(listed(AFRICA, ProductType::Cbt), None)];
+    assert_eq!(
+        purchases::csv(&purchases),
+        format!("title,product,purchased,downloaded,url\n\"Toto, \"\"Africa\"\"\",cbt,03/14/2024,,{}\n", AFRICA)
+    );
+}
+
+#[test]
+fn prints_purchases_as_a_table() {
+    let mut missing = Purchase::new(listed(ROSANNA, ProductType::Cbt), None);
+    missing.downloaded = Some(false);
+    let table = purchases::table(&[missing.clone()]);
+    let lines: Vec<&str> = table.lines().collect();
+    assert!(lines[0].starts_with("TITLE"), "{}", table);
+    assert!(lines[0].contains("DOWNLOADED"), "{}", table);
+    assert!(lines[1].contains(" no "), "{}", table);
+    assert!(lines[1].ends_with(ROSANNA), "{}", table);
+
+    // Without a download directory there's nothing to say about it
+    missing.downloaded = None;
+    assert!(!purchases::table(&[missing]).contains("DOWNLOADED"));
+}
+
+#[test]
+fn leaves_out_unchecked_downloads_in_json() -> Result<()> {
+    let value = serde_json::to_value(Purchase::new(listed(AFRICA, ProductType::Video), None))?;
+    assert_eq!(value["product"], "video");
+    assert_eq!(value["purchased"], "03/14/2024");
+    assert!(value.get("downloaded").is_none());
+    Ok(())
+}
@@ -0,0 +1,114 @@
+mod common;
+
+use std::error::Error;
+use std::fs;
+
+use kv_downloader::audio::reaper::{FolderDepth, ReaperLayout, Rgb};
+use kv_downloader::audio::{AudioProcessor, ProcessOptions};
+use kv_downloader::config::Config;
+use common::{write_ramp, RATE};
+
+fn names(names: &[&str]) -> Vec<String> {
+    names.iter().map(|n| n.to_string()).collect()
+}
+
+#[test]
+fn colors_instruments_by_name() {
+    let layout = ReaperLayout::default();
+    let drums = layout.color_for("Drum Kit");
+    assert_eq!(layout.color_for("Bass Drum"), drums);
+    assert_eq!(layout.color_for("Snare"), drums);
+    assert_ne!(layout.color_for("Bass"), drums);
+    assert_eq!(layout.color_for("Lead Vocal"), layout.color_for("Backing Vocals"));
+    assert_eq!(layout.color_for("Click"), Rgb(0x80, 0x80, 0x80));
+
+    // Unknown names get a color of their own that doesn't change between runs
+    let theremin = layout.color_for("Theremin");
+    assert_eq!(theremin, ReaperLayout::default().color_for("theremin"));
+    assert_ne!(theremin, layout.color_for("Kazoo"));
+}
+
+#[test]
+fn configured_colors_win_and_longest_match_first() -> Result<(), Box<dyn Error>> {
+    let config = Config::parse(
+        r##"
+[reaper]
+folder_threshold = 1
+
+[reaper.colors]
+vocal = "#00ff00"
+"lead vocal" = "#0000FF"
+"##,
+    )?;
+    let layout = config.reaper;
+    assert_eq!(layout.folder_threshold, 1);
+    assert_eq!(layout.color_for("Backing Vocals"), Rgb(0, 0xFF, 0));
+    assert_eq!(layout.color_for("Lead Vocal"), Rgb(0, 0, 0xFF));
+    assert_eq!(Rgb(0x12, 0x34, 0x56).peakcol(), 0x0156_3412);
+
+    assert!(Config::parse("[reaper.colors]\nbass = \"blue\"\n").is_err());
+    Ok(())
+}
+
+#[test]
+fn groups_stems_sharing_a_prefix_into_folders() {
+    let layout = ReaperLayout::default();
+    let slots = layout.arrange(&names(&[
+        "Click",
+        "Drum Kick",
+        "Bass",
+        "Drum Snare",
+        "Drum Overheads",
+        "Guitar Left",
+        "Guitar Right",
+    ]));
+    let order: Vec<_> = slots.iter().map(|s| (s.name.as_str(), s.folder)).collect();
+    assert_eq!(
+        order,
+        [
+            ("Click", FolderDepth::None),
+            ("Drum", FolderDepth::Open),
+            ("Drum Kick", FolderDepth::None),
+            ("Drum Snare", FolderDepth::None),
+            ("Drum Overheads", FolderDepth::Close),
+            ("Bass", FolderDepth::None),
+            ("Guitar Left", FolderDepth::None),
+            ("Guitar Right", FolderDepth::None),
+        ]
+    );
+    assert_eq!(slots[1].stem, None);
+    assert_eq!(slots[4].stem, Some(4));
+}
+
+#[test]
+fn writes_colors_and_folders_into_the_project() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    let song_dir = tmp.path().join("Cherub Rock");
+    let mono = song_dir.join("STEMS").join("WAV MONO");
+    fs::create_dir_all(&mono)?;
+    for name in ["Click", "Bass", "Drum Kick", "Drum Snare", "Drum Overheads"] {
+        write_ramp(&mono.join(format!("{}_mono.wav", name)), 1, RATE, 4410)?;
+    }
+
+    let options = ProcessOptions {
+        skip_fcpxml: true,
+        skip_midi: true,
+        ..Default::default()
+    };
+    let report = AudioProcessor::regenerate_projects(&song_dir, &options)?;
+    assert!(report.is_clean(), "{:?}", report.warnings);
+
+    let rpp = fs::read_to_string(song_dir.join("MT PROJECT/Cherub Rock.rpp"))?;
+    let layout = ReaperLayout::default();
+    let track_of = |name: &str| {
+        let start = rpp.find(&format!("NAME \"{}\"", name)).expect(name);
+        rpp[start..].lines().take(10).collect::<Vec<_>>().join("\n")
+    };
+    for name in ["Click_mono", "Bass_mono", "Drum Kick_mono"] {
+        let peakcol = format!("PEAKCOL {}", layout.color_for(name).peakcol());
+        assert!(track_of(name).contains(&peakcol), "{} should have {}", name, peakcol);
+    }
+    assert!(track_of("Drum").contains("ISBUS 1 1"));
+    assert!(track_of("Drum Snare_mono").contains("ISBUS 2 -1"));
+    Ok(())
+}
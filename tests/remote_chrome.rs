@@ -0,0 +1,30 @@
+mod server;
+
+use kv_downloader::driver::{Config, Driver, RemoteChrome};
+use server::Server;
+
+#[test]
+fn reads_the_address_off_the_debugging_port() {
+    let chrome = Server::with_dumb_html(r#"{"Browser": "Chrome/124.0", "webSocketDebuggerUrl": "ws://127.0.0.1:9222/devtools/browser/abc"}"#);
+    let address = RemoteChrome::Port(chrome.port()).websocket_url().unwrap();
+    assert_eq!(address, "ws://127.0.0.1:9222/devtools/browser/abc");
+
+    let given = RemoteChrome::from_args(Some("ws://host:9222/devtools/browser/x"), None);
+    assert_eq!(given, Some(RemoteChrome::WebSocket("ws://host:9222/devtools/browser/x".to_string())));
+    assert_eq!(RemoteChrome::from_args(None, Some(9222)), Some(RemoteChrome::Port(9222)));
+}
+
+#[test]
+fn names_the_address_it_could_not_connect_to() {
+    let error = Driver::start(Config {
+        connect: Some(RemoteChrome::WebSocket("ws://127.0.0.1:1/devtools/browser/gone".to_string())),
+        ..Default::default()
+    })
+    .err()
+    .expect("connected to nothing");
+    assert!(error.to_string().contains("ws://127.0.0.1:1/devtools/browser/gone"), "{}", error);
+
+    // A port nothing listens on
+    let error = RemoteChrome::Port(1).websocket_url().unwrap_err();
+    assert!(format!("{:#}", error).contains("http://127.0.0.1:1/json/version"));
+}
@@ -0,0 +1,81 @@
+use std::error::Error;
+use std::fs::{self, File};
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use kv_downloader::retention::{prune, RetentionPolicy};
+
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+fn fabricate_artifact(dir: &Path, name: &str, bytes: usize, age: Duration) -> Result<(), Box<dyn Error>> {
+    let artifact = dir.join(name);
+    fs::create_dir_all(&artifact)?;
+    fs::write(artifact.join("page.html"), vec![b'x'; bytes])?;
+    File::open(&artifact)?.set_modified(SystemTime::now() - age)?;
+    Ok(())
+}
+
+fn remaining(dir: &Path) -> Result<Vec<String>, Box<dyn Error>> {
+    let mut names: Vec<String> = fs::read_dir(dir)?
+        .map(|e| e.map(|e| e.file_name().to_string_lossy().into_owned()))
+        .collect::<Result<_, _>>()?;
+    names.sort();
+    Ok(names)
+}
+
+#[test]
+fn prunes_artifacts_older_than_max_age() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    fabricate_artifact(tmp.path(), "old", 10, 40 * DAY)?;
+    fabricate_artifact(tmp.path(), "recent", 10, DAY)?;
+
+    let report = prune(tmp.path(), &RetentionPolicy::default(), SystemTime::now())?;
+
+    assert_eq!(report.removed.len(), 1);
+    assert_eq!(remaining(tmp.path())?, vec!["recent"]);
+    Ok(())
+}
+
+#[test]
+fn prunes_oldest_first_until_under_size_cap() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    fabricate_artifact(tmp.path(), "a", 400, 3 * DAY)?;
+    fabricate_artifact(tmp.path(), "b", 400, 2 * DAY)?;
+    fabricate_artifact(tmp.path(), "c", 400, DAY)?;
+
+    let policy = RetentionPolicy {
+        max_total_bytes: 900,
+        ..Default::default()
+    };
+    let report = prune(tmp.path(), &policy, SystemTime::now())?;
+
+    assert_eq!(report.freed_bytes, 400);
+    assert_eq!(remaining(tmp.path())?, vec!["b", "c"]);
+    Ok(())
+}
+
+#[test]
+fn never_prunes_current_session_artifacts() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    fabricate_artifact(tmp.path(), "previous", 400, 2 * DAY)?;
+    fabricate_artifact(tmp.path(), "current", 400, Duration::ZERO)?;
+
+    let policy = RetentionPolicy {
+        max_total_bytes: 100,
+        max_age: Duration::ZERO,
+        max_captures: 0,
+    };
+    let session_start = SystemTime::now() - Duration::from_secs(60);
+    prune(tmp.path(), &policy, session_start)?;
+
+    assert_eq!(remaining(tmp.path())?, vec!["current"]);
+    Ok(())
+}
+
+#[test]
+fn missing_directory_is_not_an_error() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    let report = prune(&tmp.path().join("debug"), &RetentionPolicy::default(), SystemTime::now())?;
+    assert!(report.removed.is_empty());
+    Ok(())
+}
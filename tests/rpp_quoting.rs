@@ -0,0 +1,91 @@
+mod common;
+
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use kv_downloader::audio::reaper_template::quote;
+use kv_downloader::audio::{AudioProcessor, ProcessOptions};
+use common::{write_ramp, RATE};
+
+/// Splits an RPP line into tokens the way Reaper reads them: a token starting with `"`, `'`
+/// or `` ` `` runs to the next one of the same character, anything else to the next space.
+fn tokenize(line: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut rest = line.trim_start();
+    while !rest.is_empty() {
+        let first = rest.chars().next().unwrap();
+        if matches!(first, '"' | '\'' | '`') {
+            let end = rest[1..].find(first).expect("unterminated string") + 1;
+            tokens.push(rest[1..end].to_string());
+            rest = &rest[end + 1..];
+        } else {
+            let end = rest.find(' ').unwrap_or(rest.len());
+            tokens.push(rest[..end].to_string());
+            rest = &rest[end..];
+        }
+        assert!(rest.is_empty() || rest.starts_with(' '), "junk after a string in {:?}", line);
+        rest = rest.trim_start();
+    }
+    tokens
+}
+
+#[test]
+fn quotes_with_whatever_the_value_does_not_contain() {
+    assert_eq!(quote("Bass"), "\"Bass\"");
+    assert_eq!(quote("Electric Guitar \"Dirty\""), "'Electric Guitar \"Dirty\"'");
+    assert_eq!(quote("Rock 'n' \"Roll\""), "`Rock 'n' \"Roll\"`");
+    // All three: Reaper turns the backticks into single quotes
+    assert_eq!(quote("a \"b\" 'c' `d`"), "`a \"b\" 'c' 'd'`");
+    assert_eq!(quote("two\nlines"), "\"two lines\"");
+
+    for value in ["Bass", "Electric Guitar \"Dirty\"", "Rock 'n' \"Roll\"", "C:\\Songs\\100%", "Beyoncé – Halo"] {
+        assert_eq!(tokenize(&format!("NAME {}", quote(value))), vec!["NAME".to_string(), value.to_string()]);
+    }
+}
+
+#[test]
+fn project_lines_round_trip_through_the_tokenizer() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    let title = "100% \"Pure\" Love\\Hate – Beyoncé";
+    let song_dir = tmp.path().join(title);
+    let mono = song_dir.join("STEMS/WAV MONO");
+    fs::create_dir_all(&mono)?;
+    let stems = ["Click", "Electric Guitar \"Dirty\"", "Rock 'n' Roll \"Keys\"", "Chœurs"];
+    for name in stems {
+        write_ramp(&mono.join(format!("{}_mono.wav", name)), 1, RATE, 4410)?;
+    }
+
+    let options = ProcessOptions {
+        skip_fcpxml: true,
+        skip_midi: true,
+        rpp_absolute_paths: true,
+        strict_exporters: true,
+        ..Default::default()
+    };
+    AudioProcessor::regenerate_projects(&song_dir, &options)?;
+    let project = fs::read_dir(song_dir.join("MT PROJECT"))?.next().unwrap()?.path();
+    let rpp = fs::read_to_string(project)?;
+
+    let values = |key: &str| -> Vec<String> {
+        rpp.lines()
+            .map(tokenize)
+            .filter(|tokens| tokens.first().map(String::as_str) == Some(key))
+            .map(|tokens| {
+                assert_eq!(tokens.len(), 2, "{:?}", tokens);
+                tokens[1].clone()
+            })
+            .collect()
+    };
+    let names = values("NAME");
+    for stem in stems {
+        assert!(names.contains(&format!("{}_mono", stem)), "{:?}", names);
+        assert!(names.contains(&format!("{}_mono.wav", stem)), "{:?}", names);
+    }
+    let files = values("FILE");
+    assert_eq!(files.len(), stems.len());
+    for file in files {
+        assert!(Path::new(&file).is_file(), "{:?} doesn't point at the stem", file);
+    }
+    Ok(())
+}
@@ -0,0 +1,89 @@
+mod common;
+
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use kv_downloader::audio::reaper::RppStems;
+use kv_downloader::audio::{AudioProcessor, ProcessOptions};
+use common::{write_ramp, RATE};
+
+/// A processed song with mono stems a tenth of a second long and, with `stereo`, stereo
+/// ones twice that.
+fn song(root: &Path, stereo: bool) -> Result<PathBuf, Box<dyn Error>> {
+    let song_dir = root.join("Cherub Rock");
+    let mono = song_dir.join("STEMS/WAV MONO");
+    let st = song_dir.join("STEMS/WAV ST");
+    fs::create_dir_all(&mono)?;
+    fs::create_dir_all(&st)?;
+    for name in ["Click", "Bass"] {
+        write_ramp(&mono.join(format!("{}_mono.wav", name)), 1, RATE, 4410)?;
+        if stereo {
+            write_ramp(&st.join(format!("{}.wav", name)), 2, RATE, 8820)?;
+        }
+    }
+    Ok(song_dir)
+}
+
+fn options(rpp_stems: RppStems) -> ProcessOptions {
+    ProcessOptions {
+        skip_fcpxml: true,
+        skip_midi: true,
+        strict_exporters: true,
+        rpp_stems,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn parses_the_flag() {
+    assert_eq!("stereo".parse::<RppStems>(), Ok(RppStems::Stereo));
+    assert_eq!("Both".parse::<RppStems>(), Ok(RppStems::Both));
+    assert_eq!(RppStems::default(), RppStems::Mono);
+    assert!("quad".parse::<RppStems>().is_err());
+}
+
+#[test]
+fn builds_the_project_from_the_stereo_stems() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    let song_dir = song(tmp.path(), true)?;
+    AudioProcessor::regenerate_projects(&song_dir, &options(RppStems::Stereo))?;
+
+    let rpp = fs::read_to_string(song_dir.join("MT PROJECT/Cherub Rock.rpp"))?;
+    assert!(rpp.contains("FILE \"../STEMS/WAV ST/Bass.wav\""), "{}", rpp);
+    assert!(rpp.contains("FILE \"../STEMS/WAV ST/Click.wav\""), "{}", rpp);
+    assert!(!rpp.contains("WAV MONO"), "{}", rpp);
+    assert!(rpp.contains("NAME \"Bass.wav\""), "{}", rpp);
+    assert!(rpp.contains("LENGTH 0.2\n"), "{}", rpp);
+    assert!(rpp.contains("NCHAN 2\n"), "{}", rpp);
+    Ok(())
+}
+
+#[test]
+fn writes_a_project_per_stem_set() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    let song_dir = song(tmp.path(), true)?;
+    AudioProcessor::regenerate_projects(&song_dir, &options(RppStems::Both))?;
+
+    let project_dir = song_dir.join("MT PROJECT");
+    assert!(!project_dir.join("Cherub Rock.rpp").exists());
+    let mono = fs::read_to_string(project_dir.join("Cherub Rock (Mono).rpp"))?;
+    let stereo = fs::read_to_string(project_dir.join("Cherub Rock (Stereo).rpp"))?;
+    assert!(mono.contains("FILE \"../STEMS/WAV MONO/Bass_mono.wav\""), "{}", mono);
+    assert!(mono.contains("LENGTH 0.1\n"), "{}", mono);
+    assert!(stereo.contains("FILE \"../STEMS/WAV ST/Bass.wav\""), "{}", stereo);
+    assert!(stereo.contains("LENGTH 0.2\n"), "{}", stereo);
+    Ok(())
+}
+
+#[test]
+fn refuses_a_stem_set_that_was_not_written() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    let song_dir = song(tmp.path(), false)?;
+    let err = AudioProcessor::regenerate_projects(&song_dir, &options(RppStems::Both)).unwrap_err();
+    let message = format!("{:#}", err);
+    assert!(message.contains("WAV ST"), "{}", message);
+    // Not even the mono project is written when one of the sets is missing
+    assert!(!song_dir.join("MT PROJECT/Cherub Rock (Mono).rpp").exists());
+    Ok(())
+}
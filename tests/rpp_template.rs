@@ -0,0 +1,118 @@
+mod common;
+
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use kv_downloader::audio::mix::MonitorMix;
+use kv_downloader::audio::reaper_template::{ReaperTemplate, DEFAULT_TEMPLATE};
+use kv_downloader::audio::{AudioProcessor, ProcessOptions};
+use common::{write_ramp, RATE};
+
+const CUSTOM: &str = r#"<REAPER_PROJECT 0.1 "7.0/linux64" 0
+  RECORD_PATH "Recordings" ""
+  ; {{title}}
+{{#track}}
+  <TRACK
+    NAME "{{track_name}}"
+    TRACKHEIGHT 120 0 0 0 0 0
+    VOLPAN 1 {{pan}} -1 -1 1
+    TRACKID {{ guid }}
+    <ITEM
+      LENGTH {{length}}
+      <SOURCE WAVE
+        FILE "{{file_path}}"
+      >
+    >
+  >
+{{/track}}
+>
+"#;
+
+fn song_with_stems(root: &Path, names: &[&str]) -> Result<std::path::PathBuf, Box<dyn Error>> {
+    let song_dir = root.join("Cherub Rock");
+    let mono = song_dir.join("STEMS").join("WAV MONO");
+    fs::create_dir_all(&mono)?;
+    for name in names {
+        write_ramp(&mono.join(format!("{}_mono.wav", name)), 1, RATE, 4410)?;
+    }
+    Ok(song_dir)
+}
+
+#[test]
+fn built_in_template_parses() {
+    assert_eq!(ReaperTemplate::parse(DEFAULT_TEMPLATE).unwrap(), ReaperTemplate::default());
+}
+
+#[test]
+fn fills_a_custom_template_per_stem() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    let song_dir = song_with_stems(tmp.path(), &["Click", "Bass", "Drum Kick", "Drum Snare", "Drum Toms"])?;
+    let options = ProcessOptions {
+        skip_fcpxml: true,
+        skip_midi: true,
+        rpp_template: ReaperTemplate::parse(CUSTOM)?,
+        mix: MonitorMix::legacy(),
+        ..Default::default()
+    };
+    let report = AudioProcessor::regenerate_projects(&song_dir, &options)?;
+    assert!(report.is_clean(), "{:?}", report.warnings);
+
+    let rpp = fs::read_to_string(song_dir.join("MT PROJECT/Cherub Rock.rpp"))?;
+    assert!(rpp.starts_with("<REAPER_PROJECT 0.1 \"7.0/linux64\" 0\n  RECORD_PATH"), "{}", rpp);
+    assert!(rpp.contains("; Cherub Rock\n"), "{}", rpp);
+    assert_eq!(rpp.matches("TRACKHEIGHT 120 0 0 0 0 0").count(), 5);
+    assert!(rpp.contains("FILE \"../STEMS/WAV MONO/Bass_mono.wav\""), "{}", rpp);
+    assert!(rpp.contains("LENGTH 0.1\n"), "{}", rpp);
+
+    let click = &rpp[rpp.find("NAME \"Click_mono\"").expect("click track")..];
+    assert!(click.lines().nth(2).unwrap().contains("VOLPAN 1 -1 "), "{}", click);
+    let bass = &rpp[rpp.find("NAME \"Bass_mono\"").expect("bass track")..];
+    assert!(bass.lines().nth(2).unwrap().contains("VOLPAN 1 1 "), "{}", bass);
+    assert!(bass.lines().nth(3).unwrap().contains("TRACKID {7FE0D07C-"), "{}", bass);
+
+    // No folder block in the template, so the drum folder comes from the built-in one
+    let folder = &rpp[rpp.find("NAME \"Drum\"").expect("drum folder")..];
+    assert!(folder.lines().take(10).any(|line| line.trim() == "ISBUS 1 1"), "{}", folder);
+    // And no footer: the MIDI track was part of the built-in footer
+    assert!(!rpp.contains("NAME \"MIDI\""));
+    assert!(rpp.ends_with("  >\n>\n"));
+    Ok(())
+}
+
+#[test]
+fn rejects_unknown_placeholders() {
+    let err = ReaperTemplate::parse(&CUSTOM.replace("{{pan}}", "{{panning}}")).unwrap_err();
+    let message = format!("{:#}", err);
+    assert!(message.contains("{{panning}}"), "{}", message);
+    assert!(message.contains("track"), "{}", message);
+
+    // Per-track values aren't available in the header
+    let err = ReaperTemplate::parse(&CUSTOM.replace("; {{title}}", "; {{file_path}}")).unwrap_err();
+    assert!(format!("{:#}", err).contains("{{file_path}}"), "{:#}", err);
+}
+
+#[test]
+fn rejects_broken_blocks() {
+    let no_track = CUSTOM.replace("{{#track}}\n", "").replace("{{/track}}\n", "");
+    assert!(format!("{:#}", ReaperTemplate::parse(&no_track).unwrap_err()).contains("{{#track}}"));
+
+    let unclosed = CUSTOM.replace("{{/track}}\n", "");
+    assert!(format!("{:#}", ReaperTemplate::parse(&unclosed).unwrap_err()).contains("never closed"));
+
+    let unknown_block = CUSTOM.replace("{{#track}}", "{{#item}}").replace("{{/track}}", "{{/item}}");
+    assert!(ReaperTemplate::parse(&unknown_block).is_err());
+
+    assert!(ReaperTemplate::parse(&CUSTOM.replace("{{length}}", "{{length")).is_err());
+}
+
+#[test]
+fn loading_names_the_template() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    let path = tmp.path().join("broken.rpp");
+    fs::write(&path, CUSTOM.replace("{{ guid }}", "{{ uuid }}"))?;
+    let message = format!("{:#}", ReaperTemplate::load(&path).unwrap_err());
+    assert!(message.contains("broken.rpp"), "{}", message);
+    assert!(message.contains("{{uuid}}"), "{}", message);
+    Ok(())
+}
@@ -0,0 +1,109 @@
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use kv_downloader::audio::limits::{self, WAV_MAX_DATA_BYTES};
+use kv_downloader::audio::midi::{self, MidiCountIn, MidiLayout};
+use kv_downloader::audio::tempo::{ClickTiming, TimeSignature};
+use midly::{MetaMessage, Smf, TrackEventKind};
+
+/// A 90-minute medley, the longest song we've seen.
+const MEDLEY: Duration = Duration::from_secs(90 * 60);
+/// Ticks per second of the Reaper project's MIDI item, 120 BPM at 960 PPQN.
+const RPP_TICKS_PER_SEC: f64 = 120.0 * 960.0 / 60.0;
+
+fn layout(first_beat: Duration, length: Duration) -> MidiLayout {
+    MidiLayout {
+        timing: ClickTiming { bpm: 120.0, first_beat },
+        signature: TimeSignature::default(),
+        count_in_bars: 0,
+        count_in: MidiCountIn::Include,
+        length,
+    }
+}
+
+/// The tick each track ends at.
+fn track_ends(path: &Path) -> Result<Vec<u64>, Box<dyn Error>> {
+    let data = fs::read(path)?;
+    let smf = Smf::parse(&data)?;
+    let mut ends = Vec::new();
+    for track in &smf.tracks {
+        let mut tick = 0u64;
+        for event in track {
+            tick += event.delta.as_int() as u64;
+            if let TrackEventKind::Meta(MetaMessage::EndOfTrack) = event.kind {
+                ends.push(tick);
+            }
+        }
+    }
+    Ok(ends)
+}
+
+#[test]
+fn counts_padding_of_long_songs_at_high_rates_exactly() -> Result<(), Box<dyn Error>> {
+    for rate in [44100, 48000, 88200, 96000, 192000] {
+        let frames = limits::frames_in(MEDLEY, rate);
+        assert_eq!(frames, 5400 * rate as u64);
+        assert_eq!(limits::frames_to_secs(frames, rate), 5400.0);
+    }
+    // 90 minutes of 96 kHz stereo is over a billion samples, still inside a WAV
+    assert_eq!(limits::padding_samples(MEDLEY, 96000, 2, 16)?, 1_036_800_000);
+    // sub-second padding rounds down to whole frames
+    assert_eq!(limits::padding_samples(Duration::from_micros(1_500_010), 96000, 2, 16)?, 2 * 144_000);
+    Ok(())
+}
+
+#[test]
+fn refuses_padding_a_wav_cant_hold() {
+    let err = limits::padding_samples(MEDLEY, 96000, 8, 16).unwrap_err();
+    assert!(err.to_string().contains("4 GB"), "{}", err);
+    assert!(limits::padding_samples(Duration::from_secs(u64::MAX / 1_000_000), 192000, 2, 16).is_err());
+
+    let just_fits = WAV_MAX_DATA_BYTES / 2;
+    assert!(limits::check_wav_size(just_fits, 16).is_ok());
+    assert!(limits::check_wav_size(just_fits + 1, 16).is_err());
+    assert!(limits::check_wav_size(u64::MAX, 24).is_err());
+}
+
+#[test]
+fn converts_long_project_lengths_without_wrapping() -> Result<(), Box<dyn Error>> {
+    let secs = limits::frames_to_secs(limits::frames_in(MEDLEY, 96000), 96000);
+    assert_eq!(limits::secs_to_ticks(secs, RPP_TICKS_PER_SEC)?, 10_368_000);
+    // a day of audio is still a valid length, where an `as u32` would have saturated far sooner
+    assert_eq!(limits::secs_to_ticks(86_400.0, RPP_TICKS_PER_SEC)?, 165_888_000);
+
+    assert!(limits::secs_to_ticks(3_000_000.0, RPP_TICKS_PER_SEC).is_err());
+    assert!(limits::secs_to_ticks(-1.0, RPP_TICKS_PER_SEC).is_err());
+    assert!(limits::secs_to_ticks(f64::NAN, RPP_TICKS_PER_SEC).is_err());
+    Ok(())
+}
+
+#[test]
+fn writes_midi_for_a_90_minute_medley() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    let path = tmp.path().join("medley.mid");
+    midi::write_midi_file(&path, &layout(Duration::ZERO, MEDLEY))?;
+
+    // 120 BPM at 480 PPQ is 960 ticks per second
+    assert_eq!(track_ends(&path)?, vec![5_184_000, 5_184_000]);
+    Ok(())
+}
+
+#[test]
+fn refuses_midi_too_long_to_store() {
+    let tmp = tempfile::tempdir().unwrap();
+    let path = tmp.path().join("endless.mid");
+
+    // past a MIDI delta's 28 bits, but well inside a u32 tick
+    let too_long = Duration::from_secs(300_000);
+    let err = midi::write_midi_file(&path, &layout(Duration::ZERO, too_long)).unwrap_err();
+    assert!(err.to_string().contains("delta"), "{}", err);
+
+    // a lead-in needing more pickup beats than a time signature can count
+    let err = midi::write_midi_file(&path, &layout(Duration::from_secs(2000), MEDLEY)).unwrap_err();
+    assert!(err.to_string().contains("lead-in"), "{}", err);
+
+    let err = midi::write_midi_file(&path, &layout(Duration::ZERO, Duration::from_secs(10_000_000))).unwrap_err();
+    assert!(err.to_string().contains("out of range"), "{}", err);
+}
@@ -0,0 +1,196 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use anyhow::Result;
+use headless_chrome::protocol::cdp::Network::Cookie;
+use kv_downloader::config::Config;
+use kv_downloader::keystore::{self, Backend, CommandStore, Credentials, SecretStore};
+
+const UK: &str = "www.karaoke-version.co.uk";
+
+/// Keeps the secrets in memory.
+#[derive(Default)]
+struct MemoryStore(Mutex<HashMap<String, String>>);
+
+impl SecretStore for MemoryStore {
+    fn get_secret(&self, name: &str) -> Result<Option<String>> {
+        Ok(self.0.lock().unwrap().get(name).cloned())
+    }
+
+    fn set_secret(&self, name: &str, secret: &str) -> Result<()> {
+        self.0.lock().unwrap().insert(name.to_string(), secret.to_string());
+        Ok(())
+    }
+
+    fn delete_secret(&self, name: &str) -> Result<()> {
+        self.0.lock().unwrap().remove(name);
+        Ok(())
+    }
+}
+
+fn credentials(user: &str) -> Credentials {
+    Credentials {
+        user: user.to_string(),
+        password: "hunter2".to_string(),
+    }
+}
+
+fn cookie() -> Cookie {
+    serde_json::from_value(serde_json::json!({
+        "name": "karaoke-version",
+        "value": "abc123",
+        "domain": UK,
+        "path": "/",
+        "expires": 1.0e10,
+        "size": 21,
+        "httpOnly": true,
+        "secure": true,
+        "session": false,
+        "priority": "Medium",
+        "sameParty": false,
+        "sourceScheme": "Secure",
+        "sourcePort": 443
+    }))
+    .unwrap()
+}
+
+#[test]
+fn falls_back_to_the_shared_credentials() -> Result<()> {
+    let store = MemoryStore::default();
+    assert!(store.get_credentials(UK).is_err());
+
+    store.login("everywhere", "hunter2", None)?;
+    assert_eq!(store.get_credentials(UK)?, credentials("everywhere"));
+    store.login("uk-only", "hunter2", Some(UK))?;
+    assert_eq!(store.get_credentials(UK)?, credentials("uk-only"));
+    assert_eq!(store.get_credentials("www.karaoke-version.com")?, credentials("everywhere"));
+
+    store.logout(Some(UK))?;
+    assert_eq!(store.get_credentials(UK)?, credentials("everywhere"));
+    Ok(())
+}
+
+#[test]
+fn keeps_a_session_cookie_per_site() -> Result<()> {
+    let store = MemoryStore::default();
+    assert!(store.get_auth_cookie(UK)?.is_none());
+    store.set_auth_cookie(UK, &cookie())?;
+    let restored = store.get_auth_cookie(UK)?.expect("no cookie");
+    assert_eq!(restored.value, "abc123");
+    assert_eq!(restored.domain.as_deref(), Some(UK));
+    assert!(store.get_auth_cookie("www.karaoke-version.com")?.is_none());
+
+    store.logout(Some(UK))?;
+    assert!(store.get_auth_cookie(UK)?.is_none());
+    Ok(())
+}
+
+#[test]
+fn removes_every_stored_secret() -> Result<()> {
+    let store = MemoryStore::default();
+    store.login("singer", "hunter2", None)?;
+    store.set_auth_cookie(UK, &cookie())?;
+    store.set_secret("unrelated", "left alone")?;
+
+    let removed = keystore::remove_secrets(&store)?;
+    assert_eq!(removed, ["KV_CREDENTIALS", "KV_SESSION@www.karaoke-version.co.uk"]);
+    assert!(store.get_credentials(UK).is_err());
+    assert!(store.get_auth_cookie(UK)?.is_none());
+    assert_eq!(store.get_secret("unrelated")?.as_deref(), Some("left alone"));
+
+    // Nothing left is no error
+    assert!(keystore::remove_secrets(&store)?.is_empty());
+    Ok(())
+}
+
+#[test]
+fn selects_the_backend_from_the_config() -> Result<()> {
+    assert_eq!(Config::parse("")?.keystore.backend, Backend::Keyring);
+    let config = Config::parse("[keystore]\nbackend = \"command\"\ncommand = [\"my-vault\", \"--vault\", \"studio\"]\n")?;
+    assert_eq!(config.keystore.backend, Backend::Command);
+    assert_eq!(config.keystore.command, ["my-vault", "--vault", "studio"]);
+
+    let err = Config::parse("[keystore]\nbackend = \"command\"\n").unwrap_err();
+    assert!(format!("{:#}", err).contains("keystore.command"), "{:#}", err);
+    assert!(Config::parse("[keystore]\nbackend = \"vault\"\n").is_err());
+    Ok(())
+}
+
+#[cfg(unix)]
+mod command {
+    use super::*;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::Path;
+
+    /// A keystore command keeping each secret in a file of `dir`, and the requests it got
+    /// in `dir/requests`.
+    fn stub_command(dir: &Path) -> Vec<String> {
+        let script = dir.join("store.sh");
+        fs::write(
+            &script,
+            r#"#!/bin/sh
+dir=$(dirname "$0")
+request=$(cat)
+echo "$request" >> "$dir/requests"
+name=$(echo "$request" | sed 's/.*"name":"\([^"]*\)".*/\1/')
+case "$request" in
+  *'"action":"get"'*)
+    if [ -f "$dir/$name" ]; then cat "$dir/$name"; else echo '{"secret":null}'; fi ;;
+  *'"action":"set"'*)
+    echo "$request" | sed 's/.*\("secret":.*\)}/{\1}/' > "$dir/$name" ;;
+  *'"action":"delete"'*)
+    rm -f "$dir/$name" ;;
+esac
+"#,
+        )
+        .unwrap();
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+        vec![script.to_string_lossy().into_owned()]
+    }
+
+    #[test]
+    fn speaks_the_json_contract() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let store = CommandStore::new(stub_command(tmp.path()))?;
+        assert_eq!(store.get_secret("KV_CREDENTIALS")?, None);
+
+        store.login("studio", "hunter2", Some(UK))?;
+        assert_eq!(store.get_credentials(UK)?, credentials("studio"));
+        store.logout(Some(UK))?;
+        assert!(store.get_credentials(UK).is_err());
+
+        let requests = fs::read_to_string(tmp.path().join("requests"))?;
+        let requests: Vec<serde_json::Value> =
+            requests.lines().map(serde_json::from_str).collect::<Result<_, _>>()?;
+        let actions: Vec<_> = requests.iter().map(|r| r["action"].as_str().unwrap()).collect();
+        assert_eq!(actions, ["get", "set", "get", "delete", "delete", "get", "get"]);
+        assert_eq!(requests[1]["name"], format!("KV_CREDENTIALS@{}", UK));
+        assert!(requests[1]["secret"].as_str().unwrap().contains("\"studio\""));
+        assert!(requests[0].get("secret").is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn reports_a_failing_command() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let script = tmp.path().join("locked.sh");
+        fs::write(&script, "#!/bin/sh\ncat > /dev/null\necho 'vault is sealed' >&2\nexit 2\n")?;
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755))?;
+        let store = CommandStore::new(vec![script.to_string_lossy().into_owned()])?;
+        let err = store.get_credentials(UK).unwrap_err();
+        assert!(err.to_string().contains("vault is sealed"), "{}", err);
+
+        let garbled = tmp.path().join("garbled.sh");
+        fs::write(&garbled, "#!/bin/sh\ncat > /dev/null\necho not json\n")?;
+        fs::set_permissions(&garbled, fs::Permissions::from_mode(0o755))?;
+        let store = CommandStore::new(vec![garbled.to_string_lossy().into_owned()])?;
+        assert!(store.get_secret("KV_CREDENTIALS").is_err());
+
+        assert!(CommandStore::new(vec![]).is_err());
+        assert!(keystore::open(&Config::parse("[keystore]\nbackend = \"command\"\ncommand = [\"/nonexistent/kv-vault\"]\n")?.keystore)?
+            .get_secret("KV_CREDENTIALS")
+            .is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,16 @@
+use std::time::{Duration, UNIX_EPOCH};
+
+use kv_downloader::tasks::sign_in::cookie_expiry;
+
+#[test]
+fn reads_when_the_session_cookie_expires() {
+    assert_eq!(cookie_expiry(1_767_225_600.0), Some(UNIX_EPOCH + Duration::from_secs(1_767_225_600)));
+    assert_eq!(cookie_expiry(1_767_225_600.5), Some(UNIX_EPOCH + Duration::from_millis(1_767_225_600_500)));
+}
+
+#[test]
+fn a_session_cookie_never_expires() {
+    // Chrome gives a cookie without an expiry -1
+    assert_eq!(cookie_expiry(-1.0), None);
+    assert_eq!(cookie_expiry(0.0), None);
+}
@@ -0,0 +1,104 @@
+use kv_downloader::tasks::setlist::{order_songs, Setlist, SetlistEntry, SetlistSong};
+use kv_downloader::tasks::song_plan::{plan_songs, SongCandidate};
+
+const CHERUB_ROCK: &str = "https://www.karaoke-version.com/custombackingtrack/the-smashing-pumpkins/cherub-rock.html";
+const COME_AS_YOU_ARE: &str = "https://www.karaoke-version.com/custombackingtrack/nirvana/come-as-you-are.html";
+const EVERLONG: &str = "https://www.karaoke-version.com/custombackingtrack/foo-fighters/everlong.html";
+const MR_BRIGHTSIDE: &str = "https://www.karaoke-version.com/custombackingtrack/the-killers/mr-brightside.html";
+
+/// The batch as `-A` plans it: only Cherub Rock's page was read.
+fn batch() -> Vec<SetlistSong> {
+    let mut cherub_rock = SongCandidate::unknown(CHERUB_ROCK);
+    cherub_rock.arrangement_id = Some("68109".to_string());
+    let candidates = vec![
+        cherub_rock,
+        SongCandidate::unknown(COME_AS_YOU_ARE),
+        SongCandidate::unknown(EVERLONG),
+        SongCandidate::unknown(MR_BRIGHTSIDE),
+    ];
+    plan_songs(&candidates, "www.karaoke-version.com")
+        .iter()
+        .map(SetlistSong::from_planned)
+        .collect()
+}
+
+const SETLIST: &str = "\
+# Friday at the Roundhouse
+Everlong
+https://www.version-karaoke.fr/custombackingtrack/nirvana/come-as-you-are.html
+
+68109
+Mister Brightside
+Wonderwall
+";
+
+#[test]
+fn reads_titles_urls_and_ids() {
+    let setlist = Setlist::parse(SETLIST);
+    assert_eq!(
+        setlist.entries,
+        [
+            SetlistEntry::Title("Everlong".to_string()),
+            SetlistEntry::Url("https://www.version-karaoke.fr/custombackingtrack/nirvana/come-as-you-are.html".to_string()),
+            SetlistEntry::ArrangementId("68109".to_string()),
+            SetlistEntry::Title("Mister Brightside".to_string()),
+            SetlistEntry::Title("Wonderwall".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn puts_the_setlist_first_in_performance_order() {
+    let order = order_songs(&Setlist::parse(SETLIST), &batch());
+    assert_eq!(order.order, [2, 1, 0, 3]);
+    assert_eq!(order.setlist_songs(), [2, 1, 0, 3]);
+
+    // Songs left off the setlist follow in their usual order
+    let order = order_songs(&Setlist::parse("Mr Brightside\nEverlong\n"), &batch());
+    assert_eq!(order.order, [3, 2, 0, 1]);
+    assert_eq!(order.setlist_songs(), [3, 2]);
+}
+
+#[test]
+fn matches_by_url_id_and_title() {
+    let songs = batch();
+    let order = order_songs(&Setlist::parse(SETLIST), &songs);
+    let similarity: Vec<f64> = order.matched.iter().map(|m| m.similarity).collect();
+    // Everlong by title, Nirvana by another storefront's URL path, Cherub Rock by ID
+    assert_eq!(similarity[..3], [1.0, 1.0, 1.0]);
+    assert_eq!(songs[order.matched[3].song].name, MR_BRIGHTSIDE);
+
+    let uncertain: Vec<&SetlistEntry> = order.uncertain().map(|m| &m.entry).collect();
+    assert_eq!(uncertain, [&SetlistEntry::Title("Mister Brightside".to_string())]);
+
+    let order = order_songs(&Setlist::parse(&format!("{}\neverlong - foo fighters\n", CHERUB_ROCK)), &songs);
+    assert_eq!(order.setlist_songs(), [0, 2]);
+    assert_eq!(order.uncertain().count(), 0);
+}
+
+#[test]
+fn reports_entries_nothing_matches() {
+    let order = order_songs(&Setlist::parse(SETLIST), &batch());
+    assert_eq!(order.unmatched, [SetlistEntry::Title("Wonderwall".to_string())]);
+
+    // A song is only played once; the second entry finds nothing left
+    let order = order_songs(&Setlist::parse("Everlong\nEverlong\n12345\n"), &batch());
+    assert_eq!(order.setlist_songs(), [2]);
+    assert_eq!(
+        order.unmatched,
+        [
+            SetlistEntry::Title("Everlong".to_string()),
+            SetlistEntry::ArrangementId("12345".to_string())
+        ]
+    );
+}
+
+#[test]
+fn rejects_an_empty_setlist() -> Result<(), Box<dyn std::error::Error>> {
+    let tmp = tempfile::tempdir()?;
+    let path = tmp.path().join("setlist.txt");
+    std::fs::write(&path, "# nothing booked yet\n\n")?;
+    assert!(Setlist::load(&path).is_err());
+    assert!(Setlist::load(&tmp.path().join("missing.txt")).is_err());
+    Ok(())
+}
@@ -0,0 +1,129 @@
+mod common;
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use kv_downloader::audio::manifest::StemManifest;
+use kv_downloader::audio::track_map::{TrackEntry, TrackMap};
+use kv_downloader::audio::ProcessOptions;
+use kv_downloader::tasks::song_check::{check, find_song_dirs, fix, rpp_files};
+use common::{write_tone, RATE};
+
+/// A song folder as processing writes it: a click and a bass, both WAVs of each, the map,
+/// the manifest and a Reaper project of the mono stems.
+fn song_folder(dir: &Path) -> Result<TrackMap, Box<dyn Error>> {
+    fs::create_dir_all(dir.join("STEMS/WAV ST"))?;
+    fs::create_dir_all(dir.join("STEMS/WAV MONO"))?;
+    fs::create_dir_all(dir.join("MT PROJECT"))?;
+    let mut tracks = Vec::new();
+    for (name, is_click) in [("Click", true), ("Bass", false)] {
+        write_tone(&dir.join(format!("STEMS/WAV ST/{}.wav", name)), 2, 1.0)?;
+        write_tone(&dir.join(format!("STEMS/WAV MONO/{}_mono.wav", name)), 1, 1.0)?;
+        tracks.push(TrackEntry {
+            mixer_name: Some(name.to_string()),
+            mixer_index: None,
+            original_filename: format!("Song({}_Custom_Backing_Track).mp3", name),
+            stereo_file: format!("STEMS/WAV ST/{}.wav", name),
+            mono_file: format!("STEMS/WAV MONO/{}_mono.wav", name),
+            duration_secs: 1.0,
+            is_click,
+            stages: vec![],
+            decode_errors: 0,
+        });
+    }
+    let map = TrackMap { tracks };
+    map.save(dir)?;
+    StemManifest::build(dir, &map, &HashMap::new())?.save(dir)?;
+    fs::write(
+        dir.join("MT PROJECT/Song.rpp"),
+        "<REAPER_PROJECT\n  <TRACK\n    <SOURCE WAVE\n      FILE \"../STEMS/WAV MONO/Click_mono.wav\"\n    >\n  >\n  <TRACK\n    <SOURCE WAVE\n      FILE \"../STEMS/WAV MONO/Bass_mono.wav\"\n    >\n  >\n>\n",
+    )?;
+    Ok(map)
+}
+
+#[test]
+fn passes_a_complete_song_folder() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    song_folder(&tmp.path().join("Cherub Rock"))?;
+    fs::create_dir_all(tmp.path().join(".kv-staging/rosanna/STEMS"))?;
+    fs::create_dir_all(tmp.path().join("not a song"))?;
+
+    let song_dirs = find_song_dirs(&[tmp.path().to_path_buf()])?;
+    assert_eq!(song_dirs, [tmp.path().join("Cherub Rock")]);
+    let found = check(&song_dirs[0]);
+    assert!(found.passed, "{}", found);
+    assert!(found.to_string().starts_with("PASS"), "{}", found);
+    Ok(())
+}
+
+#[test]
+fn names_what_a_damaged_folder_lacks() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    let dir = tmp.path().join("Rosanna");
+    song_folder(&dir)?;
+    // An interrupted run, and a stem cut short
+    fs::write(dir.join("STEMS/WAV MONO/Bass_mono.wav"), "")?;
+    write_tone(&dir.join("STEMS/WAV ST/Bass.wav"), 2, 0.5)?;
+    fs::remove_file(dir.join("STEMS/WAV MONO/Click_mono.wav"))?;
+
+    let found = check(&dir);
+    assert!(!found.passed);
+    let text = found.to_string();
+    assert!(text.starts_with("FAIL"), "{}", text);
+    assert!(text.contains("STEMS/WAV MONO/Bass_mono.wav: empty"), "{}", text);
+    assert!(text.contains("STEMS/WAV MONO/Click_mono.wav: missing"), "{}", text);
+    assert!(text.contains("STEMS/WAV ST/Bass.wav is 0.50s long, the click 1.00s"), "{}", text);
+    assert!(text.contains("Song.rpp refers to ../STEMS/WAV MONO/Click_mono.wav"), "{}", text);
+    assert!(text.contains("stems.json records"), "{}", text);
+
+    // Nothing is rebuilt from stems that aren't there
+    let after = fix(&dir, &found, &ProcessOptions::default());
+    assert!(after.fixed.is_empty());
+    assert!(after.findings.iter().any(|finding| finding.contains("download the song again")), "{}", after);
+    Ok(())
+}
+
+#[test]
+fn rewrites_a_stale_manifest() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    let dir = tmp.path().join("Cherub Rock");
+    song_folder(&dir)?;
+    let mut manifest = StemManifest::load(&dir)?.unwrap();
+    manifest.stems[1].duration_samples = 1;
+    manifest.stems[1].padding_secs = 0.25;
+    manifest.save(&dir)?;
+
+    let found = check(&dir);
+    assert!(!found.passed);
+    let after = fix(&dir, &found, &ProcessOptions::default());
+    assert!(after.passed, "{}", after);
+    assert_eq!(after.fixed, ["Rewrote stems.json from the WAVs"]);
+    let manifest = StemManifest::load(&dir)?.unwrap();
+    assert_eq!(manifest.stems[1].duration_samples, RATE);
+    // Only processing measures the padding
+    assert_eq!(manifest.stems[1].padding_secs, 0.25);
+
+    let json = serde_json::to_value(&after)?;
+    assert_eq!(json["passed"], true);
+    assert_eq!(json["findings"], serde_json::json!([]));
+    Ok(())
+}
+
+#[test]
+fn flags_an_empty_project_folder() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    let dir = tmp.path().join("Cherub Rock");
+    song_folder(&dir)?;
+    fs::remove_file(dir.join("MT PROJECT/Song.rpp"))?;
+    let found = check(&dir);
+    assert_eq!(found.findings, ["MT PROJECT is empty"]);
+    Ok(())
+}
+
+#[test]
+fn reads_the_files_of_a_reaper_project() {
+    let text = "<SOURCE WAVE\n  FILE \"../STEMS/WAV MONO/Lead Vocal_mono.wav\" 1\n  FILE \"/music/Click.wav\"\n>\nFILEX \"no\"\n";
+    assert_eq!(rpp_files(text), ["../STEMS/WAV MONO/Lead Vocal_mono.wav", "/music/Click.wav"]);
+}
@@ -0,0 +1,168 @@
+mod common;
+
+use std::error::Error;
+use std::path::Path;
+
+use kv_downloader::audio::manifest::{ManifestEntry, StemManifest};
+use kv_downloader::audio::track_map::TrackMap;
+use kv_downloader::audio::{AudioProcessor, ProcessOptions};
+use kv_downloader::metadata::SongInfo;
+use kv_downloader::tasks::song_diff::{
+    self, name_similarity, FieldChange, LiveSong, SongDiff, TrackChange, RENAME_SIMILARITY,
+};
+use common::RATE;
+
+fn manifest(names: &[&str]) -> StemManifest {
+    StemManifest {
+        stems: names
+            .iter()
+            .map(|name| ManifestEntry {
+                track_name: name.to_string(),
+                mono_file: format!("STEMS/WAV MONO/{}_mono.wav", name),
+                stereo_file: format!("STEMS/WAV ST/{}.wav", name),
+                duration_secs: 2.0,
+                duration_samples: 2 * RATE,
+                sample_rate: RATE,
+                channels: 2,
+                is_click: *name == "Click",
+                padding_secs: 0.0,
+                time_drift_ms: None,
+                mono_loop: None,
+                stereo_loop: None,
+            })
+            .collect(),
+    }
+}
+
+fn live(names: &[&str]) -> LiveSong {
+    LiveSong {
+        info: SongInfo::default(),
+        tracks: names.iter().map(|name| name.to_string()).collect(),
+    }
+}
+
+#[test]
+fn an_unchanged_song_has_no_differences() {
+    let diff = SongDiff::compare(&manifest(&["Click", "Drum Kit", "Bass"]), None, &live(&["Click", "drum kit", "Bass"]));
+    assert!(diff.is_empty());
+    assert_eq!(diff.to_string(), "No changes");
+}
+
+#[test]
+fn reports_added_removed_and_renamed_tracks() {
+    let folder = manifest(&["Click", "Drum Kit", "Bass", "Tambourine", "Lead Vocal"]);
+    let page = live(&["Click", "Drums", "Bass", "Lead Vocals", "Piano"]);
+    let diff = SongDiff::compare(&folder, None, &page);
+    assert_eq!(
+        diff.tracks,
+        vec![
+            TrackChange::Renamed { from: "Drum Kit".to_string(), to: "Drums".to_string() },
+            TrackChange::Removed("Tambourine".to_string()),
+            TrackChange::Renamed { from: "Lead Vocal".to_string(), to: "Lead Vocals".to_string() },
+            TrackChange::Added("Piano".to_string()),
+        ]
+    );
+    assert_eq!(diff.to_fetch(), ["Drums", "Lead Vocals", "Piano"]);
+    assert_eq!(diff.replaced(), ["Drum Kit", "Lead Vocal"]);
+    assert_eq!(diff.to_string(), "~ Drum Kit -> Drums\n- Tambourine\n~ Lead Vocal -> Lead Vocals\n+ Piano\n");
+}
+
+#[test]
+fn pairs_each_rename_with_its_closest_name() {
+    let folder = manifest(&["Click", "Electric Guitar", "Acoustic Guitar"]);
+    let page = live(&["Click", "Acoustic Guitars", "Electric Guitar Solo"]);
+    let diff = SongDiff::compare(&folder, None, &page);
+    assert_eq!(
+        diff.tracks,
+        vec![
+            TrackChange::Renamed { from: "Electric Guitar".to_string(), to: "Electric Guitar Solo".to_string() },
+            TrackChange::Renamed { from: "Acoustic Guitar".to_string(), to: "Acoustic Guitars".to_string() },
+        ]
+    );
+}
+
+#[test]
+fn unrelated_names_are_not_renames() {
+    assert!(name_similarity("Bass", "Piano") < 0.3);
+    assert!(name_similarity("Electric Guitar", "Acoustic Guitar") < RENAME_SIMILARITY);
+    assert!(name_similarity("Bass", "Bass Guitar") >= RENAME_SIMILARITY);
+    assert_eq!(name_similarity("Drum-Kit", "drum kit"), 1.0);
+
+    let diff = SongDiff::compare(&manifest(&["Click", "Bass"]), None, &live(&["Click", "Piano"]));
+    assert_eq!(
+        diff.tracks,
+        vec![TrackChange::Removed("Bass".to_string()), TrackChange::Added("Piano".to_string())]
+    );
+}
+
+#[test]
+fn compares_only_the_details_the_folder_recorded() {
+    let saved = SongInfo {
+        arrangement_id: Some("68109".to_string()),
+        tempo: Some("87 BPM".to_string()),
+        key: None,
+        ..Default::default()
+    };
+    let mut page = live(&["Click"]);
+    page.info = SongInfo {
+        arrangement_id: Some("68109".to_string()),
+        tempo: Some("90 BPM".to_string()),
+        key: Some("E".to_string()),
+        ..Default::default()
+    };
+    let diff = SongDiff::compare(&manifest(&["Click"]), Some(&saved), &page);
+    assert_eq!(
+        diff.fields,
+        vec![FieldChange { field: "tempo", before: "87 BPM".to_string(), after: "90 BPM".to_string() }]
+    );
+    assert_eq!(diff.to_string(), "tempo: 87 BPM -> 90 BPM\n");
+    assert!(SongDiff::compare(&manifest(&["Click"]), None, &page).is_empty());
+}
+
+/// Stereo WAV of a steady tone, standing in for a download.
+fn write_download(dir: &Path, part: &str, seconds: f64) -> Result<(), Box<dyn Error>> {
+    let name = format!("Smashing_Pumpkins_Cherub_Rock({}_Custom_Backing_Track).mp3", part);
+    common::write_download(&dir.join(name), seconds)
+}
+
+/// A processed song folder of `parts`, each with its mixer name.
+fn song_folder(root: &Path, parts: &[(&str, f64)]) -> Result<std::path::PathBuf, Box<dyn Error>> {
+    std::fs::create_dir_all(root)?;
+    for (part, seconds) in parts {
+        write_download(root, part, *seconds)?;
+    }
+    let names: Vec<String> = parts.iter().map(|(part, _)| part.replace('_', " ")).collect();
+    let options = ProcessOptions {
+        skip_validation: true,
+        skip_rpp: true,
+        skip_fcpxml: true,
+        skip_midi: true,
+        ..Default::default()
+    };
+    AudioProcessor::process_downloads(root, "cherub_rock", &names, &options)?;
+    Ok(root.join("Cherub Rock"))
+}
+
+#[test]
+fn merges_fetched_stems_into_the_folder() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    let song_dir = song_folder(&tmp.path().join("library"), &[("Click", 2.0), ("Drum_Kit", 2.0), ("Bass", 2.0)])?;
+    let staged = song_folder(&tmp.path().join("staging"), &[("Click", 2.0), ("Drums", 2.0), ("Piano", 1.5)])?;
+
+    let page = live(&["Click", "Drums", "Piano", "Bass"]);
+    let diff = SongDiff::compare(&StemManifest::load(&song_dir)?.unwrap(), None, &page);
+    song_diff::merge_staged(&song_dir, &staged, &diff, &page)?;
+
+    let map = TrackMap::load(&song_dir)?.unwrap();
+    let names: Vec<_> = map.tracks.iter().map(|t| t.mixer_name.as_deref().unwrap_or_default()).collect();
+    assert_eq!(names, ["Click", "Drums", "Piano", "Bass"]);
+    assert!(!song_dir.join("STEMS/WAV ST/Drum Kit.wav").exists());
+    assert!(!song_dir.join("STEMS/WAV MONO/Drum Kit_mono.wav").exists());
+    assert!(song_dir.join("STEMS/WAV MONO/Drums_mono.wav").exists());
+
+    let manifest = StemManifest::load(&song_dir)?.unwrap();
+    let piano = manifest.stems.iter().find(|s| s.track_name == "Piano").unwrap();
+    assert_eq!(piano.padding_secs, 0.5);
+    assert_eq!(manifest.stems.len(), 4);
+    Ok(())
+}
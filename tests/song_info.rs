@@ -0,0 +1,149 @@
+use std::error::Error;
+use std::fs;
+
+use kv_downloader::metadata::{self, SongInfo, LEGACY_SONG_INFO_FILE, SONG_INFO_FILE};
+
+const PAGE: &str = include_str!("fixtures/cherub-rock.html");
+const URL: &str = "https://www.karaoke-version.com/custombackingtrack/the-smashing-pumpkins/cherub-rock.html";
+
+#[test]
+fn captures_performer_and_composers_separately() {
+    let info = SongInfo::from_html(URL, PAGE);
+    assert_eq!(info.performer.as_deref(), Some("The Smashing Pumpkins"));
+    assert_eq!(info.composers, ["Billy Corgan"]);
+    assert_eq!(info.url, URL);
+}
+
+#[test]
+fn page_without_songwriter_has_only_the_performer() {
+    let page = PAGE.replace("<b>Original songwriter:</b> Billy Corgan<br>", "");
+    let info = SongInfo::from_html(URL, &page);
+    assert_eq!(info.performer.as_deref(), Some("The Smashing Pumpkins"));
+    assert!(info.composers.is_empty());
+}
+
+#[test]
+fn page_without_either_degrades_to_empty_credits() {
+    let page = PAGE
+        .replace("<b>Original songwriter:</b> Billy Corgan<br>", "")
+        .replace("song-details__description", "song-details__summary")
+        .replace("og:audio:artist", "og:audio:title");
+    let info = SongInfo::from_html(URL, &page);
+    assert_eq!(info.performer, None);
+    assert!(info.composers.is_empty());
+}
+
+#[test]
+fn splits_localized_composer_lists() {
+    let page = PAGE.replace(
+        "<b>Original songwriter:</b> Billy Corgan<br>",
+        "<b>Auteur-compositeur :</b> Paul Simon &amp; Art Garfunkel, Jean-Jacques Goldman<br>",
+    );
+    let info = SongInfo::from_html(URL, &page);
+    assert_eq!(info.composers, ["Paul Simon & Art Garfunkel", "Jean-Jacques Goldman"]);
+}
+
+#[test]
+fn keeps_linked_names_whole() {
+    let page = PAGE.replace(
+        "<b>Original songwriter:</b> Billy Corgan<br>",
+        "<b>Original songwriter:</b> <a href='/artist/ewf.html'>Earth, Wind &amp; Fire</a>, <a href='/artist/ac-dc.html'>AC/DC</a><br>",
+    );
+    let info = SongInfo::from_html(URL, &page);
+    assert_eq!(info.composers, ["Earth, Wind & Fire", "AC/DC"]);
+}
+
+#[test]
+fn reads_names_wrapped_in_markup() {
+    let page = PAGE.replace(
+        "<b>Original songwriter:</b> Billy Corgan<br>",
+        "<b>Original <i>songwriter</i>:</b> <!-- credits --><span title=\"a > b\">Billy</span> Corgan<br/>",
+    );
+    let info = SongInfo::from_html(URL, &page);
+    assert_eq!(info.composers, ["Billy Corgan"]);
+    assert_eq!(info.genres, ["Alternative", "Rock", "In English"]);
+}
+
+#[test]
+fn round_trips_through_the_song_folder() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    assert_eq!(SongInfo::load(tmp.path())?, None);
+    let info = SongInfo::from_html(URL, PAGE);
+    info.save(tmp.path())?;
+    assert_eq!(SongInfo::load(tmp.path())?, Some(info));
+    Ok(())
+}
+
+#[test]
+fn captures_the_arrangement_id() {
+    let info = SongInfo::from_html(URL, PAGE);
+    assert_eq!(info.arrangement_id.as_deref(), Some("68109"));
+    let page = PAGE.replace(r#" data-prodsongid="68109-1""#, "");
+    assert_eq!(SongInfo::from_html(URL, &page).arrangement_id, None);
+}
+
+#[test]
+fn captures_tempo_and_key() {
+    let info = SongInfo::from_html(URL, PAGE);
+    assert_eq!(info.tempo.as_deref(), Some("variable (around 87 BPM)"));
+    assert_eq!(info.key.as_deref(), Some("E"));
+
+    let page = PAGE.replace("In the same key as the original: E", "Dans la tonalité d'origine : F#");
+    assert_eq!(SongInfo::from_html(URL, &page).key.as_deref(), Some("F#"));
+}
+
+#[test]
+fn captures_duration_and_genres() {
+    let info = SongInfo::from_html(URL, PAGE);
+    assert_eq!(info.duration.as_deref(), Some("04:58"));
+    assert_eq!(info.genres, ["Alternative", "Rock", "In English"]);
+    assert_eq!(info.title.as_deref(), Some("Cherub Rock - The Smashing Pumpkins"));
+}
+
+#[test]
+fn merging_keeps_what_the_new_scrape_lacks() {
+    let saved = SongInfo {
+        genres: vec!["Rock".to_string()],
+        downloaded_at: Some("2026-01-02T03:04:05Z".to_string()),
+        transpose: Some(-2),
+        alternate_urls: vec!["https://www.version-karaoke.fr/custombackingtrack/x/y.html".to_string()],
+        tags: vec!["setlist2025".to_string()],
+        ..SongInfo::from_html(URL, PAGE)
+    };
+    let page = PAGE.replace("Duration: <b>04:58</b>", "");
+    let resumed = SongInfo {
+        transpose: Some(1),
+        tracks: vec!["Click".to_string(), "Lead Vocal".to_string()],
+        ..SongInfo::from_html(URL, &page)
+    };
+
+    let merged = resumed.merge(saved);
+    assert_eq!(merged.duration.as_deref(), Some("04:58"));
+    assert_eq!(merged.genres, ["Alternative", "Rock", "In English"]);
+    assert_eq!(merged.transpose, Some(1));
+    assert_eq!(merged.downloaded_at.as_deref(), Some("2026-01-02T03:04:05Z"));
+    assert_eq!(merged.tracks, ["Click", "Lead Vocal"]);
+    assert_eq!(merged.alternate_urls.len(), 1);
+    assert_eq!(merged.tags, ["setlist2025"]);
+}
+
+#[test]
+fn reads_the_legacy_file_and_replaces_it_on_save() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    let song_dir = tmp.path().join("Cherub Rock - The Smashing Pumpkins");
+    fs::create_dir(&song_dir)?;
+    fs::write(
+        song_dir.join(LEGACY_SONG_INFO_FILE),
+        format!(r#"{{"url": "{}", "performer": "The Smashing Pumpkins"}}"#, URL),
+    )?;
+
+    let info = SongInfo::load(&song_dir)?.expect("legacy file");
+    assert_eq!(info.performer.as_deref(), Some("The Smashing Pumpkins"));
+    assert_eq!(metadata::find_song_dir(tmp.path(), URL), Some(song_dir.clone()));
+    assert_eq!(metadata::find_song_dir(tmp.path(), "https://www.karaoke-version.com/x/y.html"), None);
+
+    info.save(&song_dir)?;
+    assert!(song_dir.join(SONG_INFO_FILE).exists());
+    assert!(!song_dir.join(LEGACY_SONG_INFO_FILE).exists());
+    Ok(())
+}
@@ -0,0 +1,22 @@
+use kv_downloader::tasks::song_list::{parse_advertised_total, PaginationMode};
+
+#[test]
+fn reads_the_advertised_total() {
+    assert_eq!(parse_advertised_total("137 files"), Some(137));
+    assert_eq!(parse_advertised_total("My files (1,234)"), Some(1234));
+    assert_eq!(parse_advertised_total("1.234 Dateien"), Some(1234));
+    assert_eq!(parse_advertised_total("1\u{a0}234 fichiers"), Some(1234));
+    assert_eq!(parse_advertised_total("No files yet"), None);
+}
+
+#[test]
+fn detects_the_infinite_scroll_variant() {
+    // The classic page, with or without a stated total
+    assert_eq!(PaginationMode::detect(true, false, Some(137), 20), PaginationMode::Pages);
+    assert_eq!(PaginationMode::detect(false, false, None, 20), PaginationMode::Pages);
+    // A single page that already lists everything
+    assert_eq!(PaginationMode::detect(false, false, Some(12), 12), PaginationMode::Pages);
+
+    assert_eq!(PaginationMode::detect(false, true, None, 20), PaginationMode::InfiniteScroll);
+    assert_eq!(PaginationMode::detect(false, false, Some(137), 20), PaginationMode::InfiniteScroll);
+}
@@ -0,0 +1,100 @@
+use kv_downloader::tasks::song_plan::{plan_songs, plan_urls, SongCandidate, SongIdentity};
+
+const PAGE: &str = include_str!("fixtures/cherub-rock.html");
+const COM: &str = "https://www.karaoke-version.com/custombackingtrack/the-smashing-pumpkins/cherub-rock.html";
+const FR: &str = "https://www.version-karaoke.fr/custombackingtrack/the-smashing-pumpkins/cherub-rock.html";
+const UK: &str = "https://www.karaoke-version.co.uk/custombackingtrack/the-smashing-pumpkins/cherub-rock.html";
+
+fn candidate(url: &str, id: Option<&str>, title: Option<&str>, stems: Option<usize>) -> SongCandidate {
+    SongCandidate {
+        url: url.to_string(),
+        arrangement_id: id.map(String::from),
+        title: title.map(String::from),
+        stem_count: stems,
+    }
+}
+
+fn song(n: usize) -> String {
+    format!("https://www.karaoke-version.com/custombackingtrack/artist/song-{}.html", n)
+}
+
+#[test]
+fn reads_the_identity_from_the_song_page() {
+    let page = candidate(COM, Some("68109"), Some("Cherub Rock - The Smashing Pumpkins"), Some(9));
+    assert_eq!(SongCandidate::from_html(COM, PAGE), page);
+    assert_eq!(page.identity(), SongIdentity::Arrangement("68109".to_string()));
+}
+
+#[test]
+fn downloads_each_arrangement_once() {
+    let candidates = vec![
+        candidate(FR, Some("68109"), Some("Cherub Rock"), Some(9)),
+        candidate(&song(1), Some("100"), Some("Song 1"), Some(5)),
+        candidate(COM, Some("68109"), Some("Cherub Rock"), Some(9)),
+        candidate(UK, Some("68109"), Some("Cherub Rock"), Some(9)),
+    ];
+    let planned = plan_songs(&candidates, "www.karaoke-version.com");
+
+    assert_eq!(planned.len(), 2);
+    assert_eq!(planned[0].url, COM, "the configured storefront wins");
+    assert_eq!(planned[0].alternate_urls, [FR, UK]);
+    assert_eq!(planned[0].first_seen, 0);
+    assert_eq!(planned[1].url, song(1));
+    assert!(planned[1].alternate_urls.is_empty());
+    assert_eq!(planned[1].first_seen, 1);
+}
+
+#[test]
+fn keeps_the_first_url_without_one_on_the_preferred_site() {
+    let candidates = vec![
+        candidate(FR, Some("68109"), None, None),
+        candidate(UK, Some("68109"), None, None),
+    ];
+    let planned = plan_songs(&candidates, "www.karaoke-version.com");
+    assert_eq!(planned.len(), 1);
+    assert_eq!(planned[0].url, FR);
+    assert_eq!(planned[0].alternate_urls, [UK]);
+}
+
+#[test]
+fn falls_back_to_title_and_stem_count_without_ids() {
+    let candidates = vec![
+        candidate(FR, None, Some("Cherub Rock - The Smashing Pumpkins"), Some(9)),
+        candidate(COM, None, Some("cherub rock – the smashing pumpkins"), Some(9)),
+        // Same title but another arrangement, with a different line-up
+        candidate(UK, None, Some("Cherub Rock - The Smashing Pumpkins"), Some(7)),
+    ];
+    let planned = plan_songs(&candidates, "www.karaoke-version.com");
+
+    assert_eq!(planned.len(), 2);
+    assert_eq!(planned[0].url, COM);
+    assert_eq!(planned[0].alternate_urls, [FR]);
+    assert_eq!(
+        planned[0].identity,
+        SongIdentity::TitleAndStems {
+            title: "cherub rock the smashing pumpkins".to_string(),
+            stems: 9
+        }
+    );
+    assert_eq!(planned[1].url, UK);
+}
+
+#[test]
+fn unknown_pages_only_match_their_own_url() {
+    let candidates = vec![
+        SongCandidate::unknown(COM),
+        SongCandidate::unknown(FR),
+        SongCandidate::unknown(COM),
+    ];
+    let planned = plan_songs(&candidates, "www.karaoke-version.com");
+    assert_eq!(planned.len(), 2);
+    assert!(planned.iter().all(|song| song.alternate_urls.is_empty()));
+}
+
+#[test]
+fn a_single_storefront_list_is_planned_without_fetching() {
+    let urls: Vec<String> = (0..5).map(song).chain([song(2)]).collect();
+    let planned = plan_urls(&urls, "www.karaoke-version.com");
+    assert_eq!(planned.iter().map(|s| s.url.clone()).collect::<Vec<_>>(), urls[..5]);
+    assert_eq!(planned[4].first_seen, 4);
+}
@@ -0,0 +1,128 @@
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use kv_downloader::audio::title;
+use kv_downloader::audio::{AudioProcessor, ProcessOptions};
+
+const PAGE: &str = include_str!("fixtures/cherub-rock.html");
+const URL: &str = "https://www.karaoke-version.com/custombackingtrack/the-smashing-pumpkins/cherub-rock.html";
+
+/// MPEG-1 Layer III, 128 kbps, 44.1 kHz, stereo, no CRC: 417-byte frames of 1152 samples.
+const FRAME_HEADER: [u8; 4] = [0xFF, 0xFB, 0x90, 0x64];
+const FRAME_LEN: usize = 417;
+
+/// A few undecodable MP3 frames behind an ID3v2.3 tag holding `frames` (id, text).
+fn write_tagged_mp3(path: &Path, tags: &[(&str, &str)]) -> Result<(), Box<dyn Error>> {
+    let mut frames = Vec::new();
+    for (id, text) in tags {
+        frames.extend(id.as_bytes());
+        frames.extend((text.len() as u32 + 1).to_be_bytes());
+        frames.extend([0, 0, 0]);
+        frames.extend(text.as_bytes());
+    }
+    let size = frames.len() as u32;
+    let mut data = b"ID3\x03\x00\x00".to_vec();
+    data.extend([(size >> 21) as u8 & 0x7F, (size >> 14) as u8 & 0x7F, (size >> 7) as u8 & 0x7F, size as u8 & 0x7F]);
+    data.extend(frames);
+    for i in 0..20 {
+        let mut frame = vec![0u8; FRAME_LEN];
+        frame[..4].copy_from_slice(&FRAME_HEADER);
+        frame[4] = 0xFF;
+        frame[5] = 0x80;
+        for (j, byte) in frame.iter_mut().enumerate().skip(6) {
+            *byte = (i * 31 + j * 17) as u8;
+        }
+        data.extend(frame);
+    }
+    fs::write(path, data)?;
+    Ok(())
+}
+
+#[test]
+fn title_from_the_song_page() {
+    assert_eq!(title::from_page(PAGE).as_deref(), Some("Cherub Rock - The Smashing Pumpkins"));
+    assert_eq!(title::from_page("<html><h2>Maintenance</h2></html>"), None);
+    assert_eq!(
+        title::from_page(r#"<h1 class="song-details__title">Back in Black - AC/DC - Custom Backing Track MP3</h1>"#).as_deref(),
+        Some("Back in Black - AC-DC")
+    );
+}
+
+#[test]
+fn titles_are_safe_folder_names() {
+    assert_eq!(title::folder_name("Back in Black - AC/DC").as_deref(), Some("Back in Black - AC-DC"));
+    assert_eq!(title::folder_name("What? Why: Now\\Then | \"Live\" <2>*").as_deref(), Some("What- Why- Now-Then - -Live- -2--"));
+    assert_eq!(title::folder_name("Tab\tStop...  ").as_deref(), Some("TabStop"));
+    assert_eq!(title::folder_name(".."), None);
+    assert_eq!(title::folder_name(" "), None);
+}
+
+#[test]
+fn title_from_the_stem_tags() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    let tagged = tmp.path().join("tagged.mp3");
+    write_tagged_mp3(
+        &tagged,
+        &[("TIT2", "Cherub Rock (Bass Custom Backing Track)"), ("TPE1", "The Smashing Pumpkins")],
+    )?;
+    assert_eq!(title::from_tags(&tagged).as_deref(), Some("Cherub Rock - The Smashing Pumpkins"));
+
+    let album_only = tmp.path().join("album.mp3");
+    write_tagged_mp3(&album_only, &[("TALB", "Cherub Rock")])?;
+    assert_eq!(title::from_tags(&album_only).as_deref(), Some("Cherub Rock"));
+
+    let separator = tmp.path().join("separator.mp3");
+    write_tagged_mp3(&separator, &[("TIT2", "Back in Black (Drum Custom Backing Track)"), ("TPE1", "AC/DC")])?;
+    assert_eq!(title::from_tags(&separator).as_deref(), Some("Back in Black - AC-DC"));
+
+    let untagged = tmp.path().join("untagged.mp3");
+    write_tagged_mp3(&untagged, &[])?;
+    assert_eq!(title::from_tags(&untagged), None);
+    Ok(())
+}
+
+#[test]
+fn title_from_the_url_path() {
+    assert_eq!(title::from_url(URL).as_deref(), Some("Cherub Rock - The Smashing Pumpkins"));
+    assert_eq!(
+        title::from_url("https://www.karaoke-version.com/custombackingtrack/cherub-rock.html?aff=1").as_deref(),
+        Some("Cherub Rock")
+    );
+    assert_eq!(title::from_url("https://www.karaoke-version.com/"), None);
+    assert_eq!(title::from_url("not a url"), None);
+}
+
+#[test]
+fn url_fallback_never_capitalizes_the_whole_url() {
+    // The old fallback produced "Https://www.karaoke-version.com/custombackingtrack/..."
+    let fallback = title::from_url(URL).unwrap();
+    assert!(!fallback.contains("Https"), "{}", fallback);
+    assert!(!fallback.contains("www"), "{}", fallback);
+    assert!(!fallback.contains('/'), "{}", fallback);
+}
+
+#[test]
+fn processing_offline_names_the_folder_from_the_tags() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    for part in ["Click", "Bass"] {
+        write_tagged_mp3(
+            &tmp.path().join(format!("Smashing_Pumpkins_Cherub_Rock({}_Custom_Backing_Track).mp3", part)),
+            &[("TIT2", "Cherub Rock"), ("TPE1", "The Smashing Pumpkins")],
+        )?;
+    }
+    let options = ProcessOptions {
+        skip_validation: true,
+        skip_rpp: true,
+        skip_fcpxml: true,
+        skip_midi: true,
+        reference_duration: Some(Duration::from_secs(1)),
+        ..Default::default()
+    };
+    // Nothing listens on the discard port, so the page can't be fetched
+    let url = "http://127.0.0.1:9/custombackingtrack/the-smashing-pumpkins/cherub-rock.html";
+    AudioProcessor::process_downloads(tmp.path(), url, &[], &options)?;
+    assert!(tmp.path().join("Cherub Rock - The Smashing Pumpkins/STEMS/WAV MONO").is_dir());
+    Ok(())
+}
@@ -0,0 +1,79 @@
+#![cfg(feature = "net")]
+
+use std::error::Error;
+
+use kv_downloader::status::{StatusHandle, StatusServer};
+
+#[test]
+fn serves_batch_status_as_json() -> Result<(), Box<dyn Error>> {
+    let status = StatusHandle::new(3);
+    let server = StatusServer::start("127.0.0.1:0", None, status.clone())?;
+    let base = format!("http://{}", server.local_addr().unwrap());
+
+    status.start_song(0, "https://example.com/song-1");
+    status.finish_phase(0, "download");
+    status.finish_song(0);
+    status.start_song(1, "https://example.com/song-2");
+    status.fail_song(1, "https://example.com/song-2", "NotPurchased");
+    status.start_song(2, "https://example.com/song-3");
+
+    let body = reqwest::blocking::get(format!("{}/status.json", base))?.text()?;
+    let json: serde_json::Value = serde_json::from_str(&body)?;
+    assert_eq!(json["current_song"], "https://example.com/song-3");
+    assert_eq!(json["index"], 3);
+    assert_eq!(json["total"], 3);
+    assert_eq!(json["completed"], 1);
+    assert_eq!(json["failed"], 1);
+    assert_eq!(json["recent_failures"][0]["error"], "NotPurchased");
+    assert!(json["eta_seconds"].is_number());
+    assert!(json["phases"].is_array());
+
+    let page = reqwest::blocking::get(format!("{}/", base))?;
+    assert!(page.status().is_success());
+    let page = page.text()?;
+    assert!(page.contains("status.json"));
+    // Song URLs and errors come from the site, so they are never parsed as markup
+    assert!(!page.contains("innerHTML"));
+
+    Ok(())
+}
+
+#[test]
+fn enforces_token_when_configured() -> Result<(), Box<dyn Error>> {
+    let server = StatusServer::start(
+        "127.0.0.1:0",
+        Some("s3cret".to_string()),
+        StatusHandle::new(1),
+    )?;
+    let base = format!("http://{}", server.local_addr().unwrap());
+    let client = reqwest::blocking::Client::new();
+
+    let denied = client.get(format!("{}/status.json", base)).send()?;
+    assert_eq!(denied.status().as_u16(), 401);
+
+    let wrong = client.get(format!("{}/status.json?token=nope", base)).send()?;
+    assert_eq!(wrong.status().as_u16(), 401);
+
+    let by_query = client.get(format!("{}/status.json?token=s3cret", base)).send()?;
+    assert_eq!(by_query.status().as_u16(), 200);
+
+    let by_header = client
+        .get(format!("{}/status.json", base))
+        .header("Authorization", "Bearer s3cret")
+        .send()?;
+    assert_eq!(by_header.status().as_u16(), 200);
+
+    Ok(())
+}
+
+#[test]
+fn decodes_the_token_in_the_query() -> Result<(), Box<dyn Error>> {
+    let server = StatusServer::start("127.0.0.1:0", Some("a b&c=d".to_string()), StatusHandle::new(1))?;
+    let base = format!("http://{}", server.local_addr().unwrap());
+
+    let encoded = reqwest::blocking::get(format!("{}/status.json?token=a%20b%26c%3Dd", base))?;
+    assert_eq!(encoded.status().as_u16(), 200);
+    let raw = reqwest::blocking::get(format!("{}/status.json?token=a%20b", base))?;
+    assert_eq!(raw.status().as_u16(), 401);
+    Ok(())
+}
@@ -0,0 +1,175 @@
+mod common;
+
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use kv_downloader::audio::manifest::{LoopPoints, ManifestEntry, StemManifest, MANIFEST_CSV, MANIFEST_JSON};
+use kv_downloader::audio::{AudioProcessor, ProcessOptions};
+use kv_downloader::metadata::SongInfo;
+use common::{write_download, RATE};
+
+fn downloads(root: &Path) -> Result<(), Box<dyn Error>> {
+    for (part, seconds) in [("Click", 2.0), ("Bass", 1.5)] {
+        let name = format!("Smashing_Pumpkins_Cherub_Rock({}_Custom_Backing_Track).mp3", part);
+        write_download(&root.join(name), seconds)?;
+    }
+    Ok(())
+}
+
+fn options() -> ProcessOptions {
+    ProcessOptions {
+        skip_validation: true,
+        skip_rpp: true,
+        skip_fcpxml: true,
+        skip_midi: true,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn lists_every_stem_as_written() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    downloads(tmp.path())?;
+    AudioProcessor::process_downloads(tmp.path(), "cherub_rock", &[], &options())?;
+
+    let song_dir = tmp.path().join("Cherub Rock");
+    let manifest = StemManifest::load(&song_dir)?.expect("no stems.json");
+    assert_eq!(manifest.stems.len(), 2);
+
+    let click = &manifest.stems[0];
+    assert_eq!(click.track_name, "Click");
+    assert!(click.is_click);
+    assert_eq!(click.mono_file, "STEMS/WAV MONO/Click_mono.wav");
+    assert_eq!(click.stereo_file, "STEMS/WAV ST/Click.wav");
+    assert_eq!(click.padding_secs, 0.0);
+
+    // The bass is padded to the click's length, and the manifest says so
+    let bass = &manifest.stems[1];
+    assert_eq!(bass.track_name, "Bass");
+    assert!(!bass.is_click);
+    assert_eq!(bass.sample_rate, RATE);
+    assert_eq!(bass.channels, 2);
+    assert_eq!(bass.padding_secs, 0.5);
+    assert_eq!(bass.duration_samples, 2 * RATE);
+    assert_eq!(bass.duration_secs, 2.0);
+
+    let csv = fs::read_to_string(song_dir.join(MANIFEST_CSV))?;
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(
+        lines[0],
+        "track_name,mono_file,stereo_file,duration_secs,duration_samples,sample_rate,channels,is_click,padding_secs"
+    );
+    assert_eq!(
+        lines[2],
+        "Bass,STEMS/WAV MONO/Bass_mono.wav,STEMS/WAV ST/Bass.wav,2,88200,44100,2,false,0.5"
+    );
+    Ok(())
+}
+
+#[test]
+fn records_the_loop_of_each_file() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    downloads(tmp.path())?;
+    let looped = ProcessOptions {
+        loop_region: Some("0:00.5-0:01.5".parse()?),
+        ..options()
+    };
+    AudioProcessor::process_downloads(tmp.path(), "cherub_rock", &[], &looped)?;
+
+    let song_dir = tmp.path().join("Cherub Rock");
+    let manifest = StemManifest::load(&song_dir)?.expect("no stems.json");
+    let points = LoopPoints {
+        start_samples: RATE / 2,
+        end_samples: RATE * 3 / 2 - 1,
+    };
+    for stem in &manifest.stems {
+        assert_eq!(stem.mono_loop, Some(points), "{}", stem.mono_file);
+        assert_eq!(stem.stereo_loop, Some(points), "{}", stem.stereo_file);
+    }
+    let json: serde_json::Value = serde_json::from_str(&fs::read_to_string(song_dir.join(MANIFEST_JSON))?)?;
+    assert_eq!(json["stems"][1]["stereo_loop"], serde_json::json!({"start_samples": 22050, "end_samples": 66149}));
+
+    // Without a loop, there's nothing to record
+    let plain = tempfile::tempdir()?;
+    downloads(plain.path())?;
+    AudioProcessor::process_downloads(plain.path(), "cherub_rock", &[], &options())?;
+    let json = fs::read_to_string(plain.path().join("Cherub Rock").join(MANIFEST_JSON))?;
+    assert!(!json.contains("_loop"));
+    Ok(())
+}
+
+#[test]
+fn reprocessing_replaces_the_manifest() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    downloads(tmp.path())?;
+    AudioProcessor::process_downloads(tmp.path(), "cherub_rock", &[], &options())?;
+    let song_dir = tmp.path().join("Cherub Rock");
+    let first_csv = fs::read_to_string(song_dir.join(MANIFEST_CSV))?;
+    let first_json = fs::read_to_string(song_dir.join(MANIFEST_JSON))?;
+
+    downloads(tmp.path())?;
+    AudioProcessor::process_downloads(tmp.path(), "cherub_rock", &[], &options())?;
+    assert_eq!(fs::read_to_string(song_dir.join(MANIFEST_CSV))?, first_csv);
+    assert_eq!(fs::read_to_string(song_dir.join(MANIFEST_JSON))?, first_json);
+    Ok(())
+}
+
+#[test]
+fn quotes_csv_fields_that_need_it() {
+    let manifest = StemManifest {
+        stems: vec![ManifestEntry {
+            track_name: "Backing Vocals, \"Oohs\"".to_string(),
+            mono_file: "STEMS/WAV MONO/Backing Vocals_mono.wav".to_string(),
+            stereo_file: "STEMS/WAV ST/Backing Vocals.wav".to_string(),
+            duration_secs: 1.25,
+            duration_samples: 55125,
+            sample_rate: RATE,
+            channels: 2,
+            is_click: false,
+            padding_secs: 0.0,
+            time_drift_ms: None,
+            mono_loop: None,
+            stereo_loop: None,
+        }],
+    };
+    let csv = manifest.to_csv();
+    assert_eq!(
+        csv.lines().nth(1),
+        Some("\"Backing Vocals, \"\"Oohs\"\"\",STEMS/WAV MONO/Backing Vocals_mono.wav,STEMS/WAV ST/Backing Vocals.wav,1.25,55125,44100,2,false,0")
+    );
+}
+
+#[test]
+fn numbers_the_stems_in_mixer_order() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    let options = ProcessOptions {
+        numbered_stems: true,
+        // Saves the song.json the second run finds the song folder by
+        song_info: Some(SongInfo {
+            url: "cherub_rock".to_string(),
+            ..Default::default()
+        }),
+        ..options()
+    };
+    let mixer = ["Bass".to_string(), "Click".to_string()];
+    downloads(tmp.path())?;
+    AudioProcessor::process_downloads(tmp.path(), "cherub_rock", &mixer, &options)?;
+
+    let song_dir = tmp.path().join("Cherub Rock");
+    let manifest = StemManifest::load(&song_dir)?.expect("no stems.json");
+    let files = |manifest: &StemManifest| -> Vec<String> { manifest.stems.iter().map(|s| s.stereo_file.clone()).collect() };
+    assert_eq!(files(&manifest), ["STEMS/WAV ST/01 Bass.wav", "STEMS/WAV ST/02 Click.wav"]);
+    assert_eq!(manifest.stems[0].track_name, "Bass");
+    assert!(manifest.stems[1].is_click);
+    assert!(song_dir.join("STEMS/WAV MONO/02 Click_mono.wav").exists());
+
+    // Processing it again without the mixer at hand numbers them from the track map
+    downloads(tmp.path())?;
+    AudioProcessor::process_downloads(tmp.path(), "cherub_rock", &[], &options)?;
+    let manifest = StemManifest::load(&song_dir)?.expect("no stems.json");
+    assert_eq!(files(&manifest), ["STEMS/WAV ST/01 Bass.wav", "STEMS/WAV ST/02 Click.wav"]);
+    assert_eq!(AudioProcessor::strip_stem_number("01 Bass"), "Bass");
+    assert_eq!(AudioProcessor::strip_stem_number("7 Seconds"), "7 Seconds");
+    Ok(())
+}
@@ -0,0 +1,104 @@
+use std::error::Error;
+use std::path::Path;
+
+use hound::{SampleFormat, WavSpec, WavWriter};
+use kv_downloader::audio::click::ClickDetection;
+use kv_downloader::audio::drift;
+use kv_downloader::audio::manifest::StemManifest;
+use kv_downloader::audio::{AudioProcessor, ProcessOptions};
+
+const RATE: u32 = 44100;
+const SECONDS: f64 = 30.0;
+
+/// Gaps between the bursts, uneven so no two stretches of the song look alike.
+const GAPS: [f64; 7] = [0.37, 0.52, 0.61, 0.44, 0.58, 0.49, 0.66];
+
+/// Stereo WAV of 30 ms bursts of a tone, the bursts' times multiplied by `stretch`,
+/// standing in for a download since Symphonia probes the contents.
+fn write_bursts(path: &Path, hz: f32, stretch: f64) -> Result<(), Box<dyn Error>> {
+    let mut starts = vec![0.0];
+    while *starts.last().unwrap() < SECONDS {
+        let next = starts.last().unwrap() + GAPS[starts.len() % GAPS.len()];
+        starts.push(next);
+    }
+    let spec = WavSpec {
+        channels: 2,
+        sample_rate: RATE,
+        bits_per_sample: 16,
+        sample_format: SampleFormat::Int,
+    };
+    let mut writer = WavWriter::create(path, spec)?;
+    let mut burst = 0;
+    for i in 0..(SECONDS * RATE as f64) as u32 {
+        let t = i as f64 / RATE as f64 / stretch;
+        while t >= starts[burst] + 0.03 {
+            burst += 1;
+        }
+        let sample = if t >= starts[burst] {
+            ((i as f32 * hz * std::f32::consts::TAU / RATE as f32).sin() * 8000.0) as i16
+        } else {
+            0
+        };
+        writer.write_sample(sample)?;
+        writer.write_sample(sample)?;
+    }
+    writer.finalize()?;
+    Ok(())
+}
+
+fn download(dir: &Path, part: &str) -> std::path::PathBuf {
+    dir.join(format!("Smashing_Pumpkins_Cherub_Rock({}_Custom_Backing_Track).mp3", part))
+}
+
+fn options() -> ProcessOptions {
+    ProcessOptions {
+        skip_rpp: true,
+        skip_fcpxml: true,
+        skip_midi: true,
+        verify_drift: true,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn detects_a_stretched_stem() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    let (click, bass) = (download(tmp.path(), "Click"), download(tmp.path(), "Bass"));
+    write_bursts(&click, 1000.0, 1.0)?;
+    // Rendered 1% slow, the way a transpose at the wrong sample rate comes out
+    write_bursts(&bass, 110.0, 1.01)?;
+
+    let check = drift::measure(&click, &bass, Default::default(), std::time::Duration::from_secs_f64(SECONDS))?
+        .expect("inconclusive");
+    assert!(check.is_drifted(), "{:?}", check);
+    assert!(check.drift_ms() > 150.0, "{:?}", check);
+    assert_eq!(AudioProcessor::drifted_downloads(tmp.path(), &ClickDetection::default())?, [bass]);
+
+    let report = AudioProcessor::process_downloads(tmp.path(), "cherub_rock", &[], &options())?;
+    assert!(report.warnings.iter().any(|w| w.starts_with("Bass is time-drifted")), "{:?}", report.warnings);
+    let manifest = StemManifest::load(&tmp.path().join("Cherub Rock"))?.expect("no stems.json");
+    assert_eq!(manifest.stems[0].time_drift_ms, None);
+    let drift = manifest.stems[1].time_drift_ms.expect("Bass wasn't flagged");
+    assert!(drift > 150.0, "{}", drift);
+    Ok(())
+}
+
+#[test]
+fn leaves_a_stem_in_time_alone() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    let (click, bass) = (download(tmp.path(), "Click"), download(tmp.path(), "Bass"));
+    write_bursts(&click, 1000.0, 1.0)?;
+    write_bursts(&bass, 110.0, 1.0)?;
+
+    let check = drift::measure(&click, &bass, Default::default(), std::time::Duration::from_secs_f64(SECONDS))?
+        .expect("inconclusive");
+    assert!(!check.is_drifted(), "{:?}", check);
+    assert!(check.offsets_ms.iter().all(|offset| offset.abs() <= 10.0), "{:?}", check);
+    assert!(AudioProcessor::drifted_downloads(tmp.path(), &ClickDetection::default())?.is_empty());
+
+    let report = AudioProcessor::process_downloads(tmp.path(), "cherub_rock", &[], &options())?;
+    assert!(report.is_clean(), "{:?}", report.warnings);
+    let manifest = StemManifest::load(&tmp.path().join("Cherub Rock"))?.expect("no stems.json");
+    assert!(manifest.stems.iter().all(|stem| stem.time_drift_ms.is_none()));
+    Ok(())
+}
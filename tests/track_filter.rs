@@ -0,0 +1,154 @@
+mod common;
+
+use std::error::Error;
+use std::fs;
+
+use kv_downloader::audio::track_map::TrackMap;
+use kv_downloader::audio::{AudioProcessor, ProcessOptions};
+use kv_downloader::tasks::download_song::{plan_track_downloads, StemDecision, TrackInfo};
+use kv_downloader::tasks::track_filter::{TrackFilter, TrackPatterns};
+use common::write_download;
+
+fn tracks(names: &[&str]) -> Vec<TrackInfo> {
+    names
+        .iter()
+        .enumerate()
+        .map(|(index, name)| TrackInfo {
+            index,
+            name: name.to_string(),
+            downloaded_on_site: None,
+        })
+        .collect()
+}
+
+fn filter(only: &str, skip: &str) -> TrackFilter {
+    TrackFilter {
+        only: if only.is_empty() { TrackPatterns::default() } else { only.parse().unwrap() },
+        skip: if skip.is_empty() { TrackPatterns::default() } else { skip.parse().unwrap() },
+    }
+}
+
+fn selected(only: &str, names: &[&str]) -> Vec<bool> {
+    let filter = filter(only, "");
+    names.iter().map(|name| filter.selects(name)).collect()
+}
+
+#[test]
+fn matches_names_case_insensitively() {
+    let names = ["Click", "Drum Kit", "Bass", "Lead Electric Guitar", "Rhythm Electric Guitar"];
+    assert_eq!(selected("drum, BASS", &names), vec![true, true, true, false, false]);
+    assert_eq!(selected("*electric guitar", &names), vec![true, false, false, true, true]);
+    assert_eq!(selected("r?ythm*", &names), vec![true, false, false, false, true]);
+    // a glob has to match the whole name
+    assert_eq!(selected("lead*", &names), vec![true, false, false, true, false]);
+    assert_eq!(selected("guitar*", &names), vec![true, false, false, false, false]);
+}
+
+#[test]
+fn rejects_an_empty_list() {
+    assert!("".parse::<TrackPatterns>().is_err());
+    assert!(" , ".parse::<TrackPatterns>().is_err());
+    assert!(TrackFilter::default().selects("Tambourine"));
+}
+
+#[test]
+fn reports_patterns_that_match_nothing() {
+    let patterns: TrackPatterns = "Drums,Bass,Kazoo*".parse().unwrap();
+    let names = vec!["Click".to_string(), "Drum Kit".to_string(), "Bass".to_string()];
+    assert_eq!(patterns.unmatched(&names), vec!["drums", "kazoo*"]);
+}
+
+#[test]
+fn keeps_the_click_and_earlier_decisions() {
+    let tracks = tracks(&["Click", "Drum Kit", "Bass", "Piano"]);
+    let mut decisions = plan_track_downloads(&tracks, &["Drum Kit".to_string()], false);
+    filter("drum,bass", "").apply(&tracks, &mut decisions).unwrap();
+    assert_eq!(
+        decisions,
+        vec![
+            StemDecision::Download,
+            StemDecision::SkipLocalFile,
+            StemDecision::Download,
+            StemDecision::SkipNotSelected
+        ]
+    );
+}
+
+#[test]
+fn resumes_past_stems_chrome_renamed() {
+    let tracks = tracks(&["Click", "Guitar/Keys", "Bass: Synth", "Lead Vocal"]);
+    let local = [
+        AudioProcessor::normalize_track_name("Cherub_Rock(Guitar_Keys_Custom_Backing_Track).mp3"),
+        AudioProcessor::normalize_track_name("Cherub_Rock(Bass__Synth_Custom_Backing_Track) (1).mp3"),
+    ];
+    assert_eq!(
+        plan_track_downloads(&tracks, &local, false),
+        vec![
+            StemDecision::Download,
+            StemDecision::SkipLocalFile,
+            StemDecision::SkipLocalFile,
+            StemDecision::Download
+        ]
+    );
+}
+
+#[test]
+fn skips_guide_vocals() -> Result<(), Box<dyn Error>> {
+    let tracks = tracks(&["Click", "Lead Vocal", "Backing Vocals", "Bass"]);
+    let mut decisions = plan_track_downloads(&tracks, &[], false);
+    filter("", "lead vocal,backing*").apply(&tracks, &mut decisions)?;
+    assert_eq!(
+        decisions,
+        vec![
+            StemDecision::Download,
+            StemDecision::SkipNotSelected,
+            StemDecision::SkipNotSelected,
+            StemDecision::Download
+        ]
+    );
+
+    // a skip wins over an include, even for the click
+    let both = filter("vocal", "backing*,click");
+    let kept: Vec<bool> = tracks.iter().map(|t| both.selects(&t.name)).collect();
+    assert_eq!(kept, vec![false, true, false, false]);
+    Ok(())
+}
+
+#[test]
+fn refuses_to_skip_every_track() {
+    let tracks = tracks(&["Click", "Bass"]);
+    let mut decisions = plan_track_downloads(&tracks, &[], false);
+    let err = filter("", "*").apply(&tracks, &mut decisions).unwrap_err();
+    assert!(err.to_string().contains("none of the song's tracks"), "{}", err);
+}
+
+#[test]
+fn processes_a_partial_stem_set() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    for (part, seconds) in [("Click", 2.0), ("Bass", 1.5)] {
+        let name = format!("Smashing_Pumpkins_Cherub_Rock({}_Custom_Backing_Track).mp3", part);
+        write_download(&tmp.path().join(name), seconds)?;
+    }
+    let mixer: Vec<String> = ["Click", "Drum Kit", "Bass", "Lead Vocal"].iter().map(|s| s.to_string()).collect();
+    // Only some of the mixer's tracks were downloaded, on purpose
+    let options = ProcessOptions {
+        skip_fcpxml: true,
+        skip_midi: true,
+        allow_partial: true,
+        ..Default::default()
+    };
+    let report = AudioProcessor::process_downloads(tmp.path(), "cherub_rock", &mixer, &options)?;
+    assert_eq!(
+        report.warnings,
+        ["no MP3 was downloaded for 'Drum Kit'", "no MP3 was downloaded for 'Lead Vocal'"]
+    );
+
+    let song_dir = tmp.path().join("Cherub Rock");
+    let map = TrackMap::load(&song_dir)?.expect("no track map");
+    let names: Vec<_> = map.tracks.iter().map(|t| (t.mixer_name.as_deref(), t.mixer_index)).collect();
+    assert_eq!(names, vec![(Some("Click"), Some(0)), (Some("Bass"), Some(2))]);
+
+    let rpp = fs::read_to_string(song_dir.join("MT PROJECT/Cherub Rock.rpp"))?;
+    assert_eq!(rpp.matches("<SOURCE WAVE").count(), 2);
+    Ok(())
+}
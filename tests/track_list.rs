@@ -0,0 +1,145 @@
+use std::collections::HashSet;
+use std::error::Error;
+use std::fs;
+
+use kv_downloader::tasks::products::ProductType;
+use chrono::{DateTime, Duration, Utc};
+use kv_downloader::config::Config;
+use kv_downloader::tasks::song_list::{
+    load_track_list, new_purchases, save_track_list, ListedSong, NewPurchases, TitlePattern, TrackList,
+};
+
+fn listed(url: &str, title: Option<&str>) -> ListedSong {
+    ListedSong {
+        url: url.to_string(),
+        title: title.map(String::from),
+        product: ProductType::Cbt,
+        purchased: None,
+    }
+}
+
+fn patterns(patterns: &[&str]) -> Vec<TitlePattern> {
+    patterns.iter().map(|p| p.parse().unwrap()).collect()
+}
+
+#[test]
+fn keeps_the_titles_in_the_saved_track_list() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    let path = tmp.path().join("track_list.json");
+    let songs = vec![
+        listed("https://www.karaoke-version.com/custombackingtrack/toto/rosanna.html", Some("Rosanna")),
+        listed("https://www.karaoke-version.com/custombackingtrack/toto/africa.html", None),
+    ];
+    let list = TrackList::new(songs.clone(), Some(2));
+    save_track_list(&path, &list)?;
+    assert_eq!(load_track_list(&path)?, list);
+
+    // Lists saved before titles were kept still load
+    fs::write(&path, r#"["https://www.karaoke-version.com/custombackingtrack/toto/africa.html"]"#)?;
+    assert_eq!(load_track_list(&path)?.songs, songs[1..]);
+
+    // As do those saved as a bare array, without a date or count
+    fs::write(&path, serde_json::to_string(&songs)?)?;
+    let old = load_track_list(&path)?;
+    assert_eq!(old.songs, songs);
+    assert_eq!((old.generated_at, old.total), (None, None));
+    Ok(())
+}
+
+#[test]
+fn filters_by_title_or_artist() {
+    let rosanna = listed("https://www.karaoke-version.com/custombackingtrack/toto/rosanna.html", Some("Rosanna"));
+    let aja = listed("https://www.karaoke-version.com/custombackingtrack/steely-dan/aja.html", Some("Aja"));
+    let untitled = listed("https://www.karaoke-version.com/custombackingtrack/toto/africa.html", None);
+
+    // The artist only shows in the URL
+    let toto = patterns(&["TOTO"]);
+    assert!(rosanna.matches(&toto));
+    assert!(untitled.matches(&toto));
+    assert!(!aja.matches(&toto));
+
+    let either = patterns(&["rosanna", "steely"]);
+    assert!(rosanna.matches(&either) && aja.matches(&either));
+    assert!(!untitled.matches(&either));
+
+    let regex = patterns(&["/^a(ja|frica)/"]);
+    assert!(aja.matches(&regex) && untitled.matches(&regex));
+    assert!(!rosanna.matches(&regex));
+
+    assert!("/(unclosed/".parse::<TitlePattern>().is_err());
+    assert!(" ".parse::<TitlePattern>().is_err());
+}
+
+#[test]
+fn keeps_the_product_type_of_videos_and_lyrics() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    let path = tmp.path().join("track_list.json");
+    let video = ListedSong {
+        product: ProductType::Video,
+        ..listed("https://www.karaoke-version.com/mp4/toto/africa.html", Some("Africa"))
+    };
+    let songs = vec![listed("https://www.karaoke-version.com/custombackingtrack/toto/africa.html", None), video];
+    save_track_list(&path, &TrackList::new(songs.clone(), None))?;
+    let saved = fs::read_to_string(&path)?;
+    // Backing tracks are saved as before, so older versions still read the list
+    assert_eq!(saved.matches("\"product\"").count(), 1);
+    assert!(saved.contains(r#""product": "video""#));
+    assert_eq!(load_track_list(&path)?.songs, songs);
+    Ok(())
+}
+
+#[test]
+fn warns_of_an_old_list_or_a_changed_count() {
+    let list = TrackList {
+        generated_at: Some("2025-03-01T12:00:00Z".to_string()),
+        total: Some(120),
+        songs: vec![],
+    };
+    let generated_at = list.generated_at();
+    let at: DateTime<Utc> = "2025-03-01T12:00:00Z".parse().unwrap();
+    assert_eq!(generated_at, Some(at));
+
+    assert!(list.staleness(generated_at, at + Duration::days(7), 7, Some(120)).is_empty());
+    // The page doesn't say how many there are
+    assert!(list.staleness(generated_at, at + Duration::days(3), 7, None).is_empty());
+    assert_eq!(
+        list.staleness(generated_at, at + Duration::days(9), 7, Some(122)),
+        [
+            "it was collected 9 days ago",
+            "the account has 122 backing tracks now, not the 120 it lists"
+        ]
+    );
+
+    let config = Config::parse("[download]\ntrack_list_max_age_days = 30\n").unwrap();
+    assert_eq!(config.download.track_list_max_age_days, Some(30));
+    assert!(Config::parse("[download]\ntrack_list_max_age_days = 0\n").is_err());
+}
+
+#[test]
+fn only_the_songs_bought_since_are_new() {
+    let rosanna = listed("https://www.karaoke-version.com/custombackingtrack/toto/rosanna.html", Some("Rosanna"));
+    let africa = listed("https://www.karaoke-version.com/custombackingtrack/toto/africa.html", Some("Africa"));
+    let aja = listed("https://www.karaoke-version.com/custombackingtrack/steely-dan/aja.html", Some("Aja"));
+    let previous = vec![rosanna.clone(), africa.clone()];
+
+    let fresh = vec![aja.clone(), rosanna.clone(), africa.clone()];
+    assert_eq!(
+        new_purchases(&fresh, &previous, &HashSet::new()),
+        NewPurchases {
+            songs: vec![aja.clone()],
+            ..Default::default()
+        }
+    );
+
+    // A song an earlier run didn't finish is downloaded again
+    let unfinished = HashSet::from([africa.url.clone()]);
+    let purchases = new_purchases(&fresh, &previous, &unfinished);
+    assert_eq!(purchases.songs, [aja.clone(), africa.clone()]);
+    assert_eq!(purchases.unfinished, 1);
+
+    // The same title under a URL that replaced the old one
+    let moved = listed("https://www.karaoke-version.com/custombackingtrack/toto/rosanna-1982.html", Some("Rosanna"));
+    let purchases = new_purchases(&[moved.clone(), africa.clone()], &previous, &HashSet::new());
+    assert_eq!(purchases.songs, vec![moved.clone()]);
+    assert_eq!(purchases.renamed, [(moved.url.clone(), rosanna.url.clone())]);
+}
@@ -0,0 +1,83 @@
+use std::error::Error;
+use std::fs;
+
+use kv_downloader::audio::track_map::{TrackEntry, TrackMap, TRACKS_FILE};
+
+fn entry(name: &str, original: &str, is_click: bool) -> TrackEntry {
+    TrackEntry {
+        mixer_name: None,
+        mixer_index: None,
+        original_filename: original.to_string(),
+        stereo_file: format!("STEMS/WAV ST/{}.wav", name),
+        mono_file: format!("STEMS/WAV MONO/{}_mono.wav", name),
+        duration_secs: 210.5,
+        is_click,
+        stages: vec![],
+        decode_errors: 0,
+    }
+}
+
+fn processed() -> TrackMap {
+    TrackMap {
+        tracks: vec![
+            entry("Click", "Cherub_Rock(Click_Custom_Backing_Track).mp3", true),
+            entry("Lead Vocal", "Cherub_Rock(Lead_Vocal_Custom_Backing_Track).mp3", false),
+            entry("Bass", "Cherub_Rock(Bass_Custom_Backing_Track).mp3", false),
+            entry("Tambourine", "Cherub_Rock(Tambourine_Custom_Backing_Track).mp3", false),
+        ],
+    }
+}
+
+fn names(map: &TrackMap) -> Vec<&str> {
+    map.tracks
+        .iter()
+        .map(|t| t.stereo_file.trim_start_matches("STEMS/WAV ST/"))
+        .collect()
+}
+
+#[test]
+fn orders_stems_like_the_mixer() {
+    let mixer: Vec<String> = ["Bass", "lead vocal", "Click"].iter().map(|s| s.to_string()).collect();
+    let mut map = processed();
+    map.assign_mixer_names(&mixer, None);
+
+    assert_eq!(names(&map), vec!["Bass.wav", "Lead Vocal.wav", "Click.wav", "Tambourine.wav"]);
+    assert_eq!(map.tracks[1].mixer_name.as_deref(), Some("lead vocal"));
+    assert_eq!(map.tracks[1].mixer_index, Some(1));
+    assert_eq!(map.tracks[3].mixer_name, None);
+}
+
+#[test]
+fn without_mixer_names_the_click_leads() {
+    let mut map = processed();
+    map.assign_mixer_names(&[], None);
+    assert_eq!(names(&map), vec!["Click.wav", "Bass.wav", "Lead Vocal.wav", "Tambourine.wav"]);
+}
+
+#[test]
+fn reprocessing_keeps_names_and_output() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    let mixer: Vec<String> = ["Click", "Bass", "Lead Vocal"].iter().map(|s| s.to_string()).collect();
+
+    let mut first = processed();
+    first.assign_mixer_names(&mixer, None);
+    first.save(tmp.path())?;
+    let written = fs::read(tmp.path().join(TRACKS_FILE))?;
+
+    // a later run from files on disk doesn't know the mixer
+    let previous = TrackMap::load(tmp.path())?.expect("tracks.json");
+    let mut second = processed();
+    second.assign_mixer_names(&[], Some(&previous));
+    second.save(tmp.path())?;
+
+    assert_eq!(second, first);
+    assert_eq!(fs::read(tmp.path().join(TRACKS_FILE))?, written);
+    Ok(())
+}
+
+#[test]
+fn missing_map_loads_as_none() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    assert!(TrackMap::load(tmp.path())?.is_none());
+    Ok(())
+}
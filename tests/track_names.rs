@@ -0,0 +1,50 @@
+use std::collections::HashMap;
+
+use kv_downloader::audio::AudioProcessor;
+
+#[test]
+fn normalizes_downloaded_filenames() {
+    let cases = [
+        ("The_Smashing_Pumpkins_Cherub_Rock(Bass_Custom_Backing_Track).mp3", "Bass"),
+        ("The_Smashing_Pumpkins_Cherub_Rock(Drum_Kit_Custom_Backing_Track).mp3", "Drum Kit"),
+        ("the_smashing_pumpkins_cherub_rock(bass_custom_backing_track).mp3", "bass"),
+        ("Cherub_Rock(Bass_Custom_Backing_Track) (1).mp3", "Bass"),
+        ("Cherub_Rock(Bass_Custom_Backing_Track)(2).mp3", "Bass"),
+        ("Guns_N_Roses_Sweet_Child_O_Mine_(Live)(Lead_Vocal_Custom_Backing_Track).mp3", "Lead Vocal"),
+        ("Cherub_Rock(Electric_Guitar_(intro)_Custom_Backing_Track).mp3", "Electric Guitar (intro)"),
+        ("Cherub_Rock(Click_Custom_Backing_Track)", "Click"),
+        ("Cherub_Rock(Click_Custom_Backing_Track).wav", "Click"),
+        ("Indochine_L_aventurier(Basse_Playback_personnalise).mp3", "Basse"),
+        ("Nena_99_Luftballons(Schlagzeug_Individueller_Playback).mp3", "Schlagzeug"),
+        ("Some_random_file.mp3", "Some random file"),
+        ("Some_random_file (2).mp3", "Some random file"),
+        ("Song_(Live).mp3", "Song (Live)"),
+        // Lowercasing "İ" makes it longer
+        ("İstanbul_İstanbul(Bass_Custom_Backing_Track).mp3", "Bass"),
+        ("Ⱥ_Song(Drum_Kit_CUSTOM_BACKING_TRACK).mp3", "Drum Kit"),
+    ];
+
+    for (filename, expected) in cases {
+        assert_eq!(AudioProcessor::normalize_track_name(filename), expected, "{}", filename);
+    }
+}
+
+#[test]
+fn disambiguates_duplicate_names() {
+    let mut used = HashMap::new();
+    let names: Vec<String> = ["Bass", "Click", "bass", "Bass", "Bass 2"]
+        .iter()
+        .map(|n| AudioProcessor::disambiguate(n, &mut used))
+        .collect();
+
+    assert_eq!(names, vec!["Bass", "Click", "bass 2", "Bass 3", "Bass 2 2"]);
+}
+
+#[test]
+fn compares_track_names_past_filename_quirks() {
+    assert!(AudioProcessor::same_track_name("Guitar/Keys", "Guitar Keys"));
+    assert!(AudioProcessor::same_track_name("Backing Vocals (Male)", "backing vocals _Male_"));
+    assert!(AudioProcessor::same_track_name("Rock 'n' Roll Piano", "Rock _n_ Roll  Piano"));
+    assert!(!AudioProcessor::same_track_name("Bass", "Bass 2"));
+    assert!(!AudioProcessor::same_track_name("Guitar 1", "Guitar 12"));
+}
@@ -0,0 +1,56 @@
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+
+use kv_downloader::audio::validation::{check_track_set, TrackSetMismatch};
+use kv_downloader::audio::{AudioProcessor, ProcessOptions};
+
+fn download(part: &str) -> PathBuf {
+    PathBuf::from(format!("/downloads/Toto_Rosanna({}_Custom_Backing_Track).mp3", part))
+}
+
+fn names(names: &[&str]) -> Vec<String> {
+    names.iter().map(|s| s.to_string()).collect()
+}
+
+#[test]
+fn pairs_each_mixer_track_with_one_mp3() {
+    let paths = vec![download("Click"), download("Lead_Vocal"), download("Bass"), download("Full_Mix")];
+    // The full mix comes on top of the stems
+    assert_eq!(check_track_set(&paths, &names(&["Click", "Bass", "Lead Vocal"])), Ok(()));
+
+    // Two tracks of the mixer may share a name, one MP3 each
+    let twice = vec![download("Click"), download("Guitar"), download("Guitar").with_file_name("Toto_Rosanna(Guitar_Custom_Backing_Track) (1).mp3")];
+    assert_eq!(check_track_set(&twice, &names(&["Click", "Guitar", "Guitar"])), Ok(()));
+}
+
+#[test]
+fn lists_missing_and_unexpected_mp3s() {
+    let stale = PathBuf::from("/downloads/Toto_Africa(Percussion_Custom_Backing_Track).mp3");
+    let paths = vec![download("Click"), download("Bass"), stale];
+    let mismatch = check_track_set(&paths, &names(&["Click", "Drum Kit", "Bass"])).unwrap_err();
+    assert_eq!(
+        mismatch,
+        TrackSetMismatch {
+            missing: names(&["Drum Kit"]),
+            unexpected: names(&["Toto_Africa(Percussion_Custom_Backing_Track).mp3"]),
+        }
+    );
+    let message = mismatch.to_string();
+    assert!(message.contains("missing: Drum Kit"), "{}", message);
+    assert!(message.contains("--allow-partial"), "{}", message);
+}
+
+#[test]
+fn fails_before_creating_the_song_folder() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    for part in ["Click", "Bass"] {
+        fs::write(tmp.path().join(download(part).file_name().unwrap()), b"not decoded")?;
+    }
+    let mixer = names(&["Click", "Drum Kit", "Bass"]);
+    let err = AudioProcessor::process_downloads(tmp.path(), "rosanna", &mixer, &ProcessOptions::default()).unwrap_err();
+    assert!(err.downcast_ref::<TrackSetMismatch>().is_some(), "{}", err);
+    let dirs = fs::read_dir(tmp.path())?.filter(|entry| entry.as_ref().is_ok_and(|e| e.path().is_dir())).count();
+    assert_eq!(dirs, 0);
+    Ok(())
+}
@@ -0,0 +1,130 @@
+use std::error::Error;
+use std::fs::{self, File};
+use std::time::{Duration, Instant, SystemTime};
+
+use kv_downloader::status::StatusHandle;
+use kv_downloader::tasks::transfers::{remove_partials, StallTimer, TransferState, Transfers};
+
+#[test]
+fn follows_downloads_by_guid() {
+    let transfers = Transfers::default();
+    transfers.begin("a", "Bass.mp3");
+    transfers.begin("b", "Drums.mp3");
+    transfers.progress("a", 1024, TransferState::InProgress);
+    transfers.progress("b", 2048, TransferState::Completed);
+    // Progress for a download that never began belongs to someone else
+    transfers.progress("c", 10, TransferState::InProgress);
+
+    let in_flight = transfers.in_flight();
+    assert_eq!(in_flight.len(), 1);
+    assert_eq!(in_flight[0].guid, "a");
+    assert_eq!(in_flight[0].received_bytes, 1024);
+    assert_eq!(transfers.get("b").unwrap().state, TransferState::Completed);
+    assert!(transfers.get("c").is_none());
+}
+
+#[test]
+fn tells_new_downloads_from_known_ones() {
+    let transfers = Transfers::default();
+    transfers.begin("a", "Bass.mp3");
+    let known = transfers.guids();
+    assert!(transfers.begun_since(&known).is_empty());
+    transfers.begin("b", "Drums.mp3");
+    let begun = transfers.begun_since(&known);
+    assert_eq!(begun.len(), 1);
+    assert_eq!(begun[0].suggested_filename, "Drums.mp3");
+}
+
+#[test]
+fn waits_for_cancellation_to_be_confirmed() {
+    let transfers = Transfers::default();
+    transfers.begin("a", "Bass.mp3");
+    let guids = vec!["a".to_string()];
+    assert!(!transfers.wait_until_settled(&guids, Duration::from_millis(50)));
+
+    let events = transfers.clone();
+    let confirm = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(100));
+        events.progress("a", 512, TransferState::Canceled);
+    });
+    assert!(transfers.wait_until_settled(&guids, Duration::from_secs(5)));
+    assert_eq!(transfers.get("a").unwrap().state, TransferState::Canceled);
+    confirm.join().unwrap();
+}
+
+#[test]
+fn wakes_as_soon_as_a_download_completes() {
+    let transfers = Transfers::default();
+    transfers.begin("a", "Bass.mp3");
+
+    let events = transfers.clone();
+    let complete = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(100));
+        events.progress("a", 4096, TransferState::Completed);
+    });
+    let start = Instant::now();
+    while transfers.get("a").unwrap().state != TransferState::Completed {
+        transfers.wait_for_change(Duration::from_secs(10));
+    }
+    // Woken by the event, not the timeout
+    assert!(start.elapsed() < Duration::from_secs(5));
+    complete.join().unwrap();
+}
+
+#[test]
+fn removes_only_the_aborted_songs_partials() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    let earlier = SystemTime::now() - Duration::from_secs(3600);
+    // Left over from another song before this one started
+    let old_partial = tmp.path().join("Unconfirmed 1234.crdownload");
+    File::create(&old_partial)?.set_modified(earlier)?;
+    let since = SystemTime::now() - Duration::from_secs(60);
+
+    let finished = tmp.path().join("Cherub_Rock(Click_Custom_Backing_Track).mp3");
+    let partial = tmp.path().join("Cherub_Rock(Bass_Custom_Backing_Track).mp3.crdownload");
+    let canceled_name = tmp.path().join("Cherub_Rock(Drum_Kit_Custom_Backing_Track).mp3");
+    for path in [&finished, &partial, &canceled_name] {
+        fs::write(path, b"partial")?;
+    }
+
+    let transfers = Transfers::default();
+    transfers.begin("drums", "Cherub_Rock(Drum_Kit_Custom_Backing_Track).mp3");
+    transfers.progress("drums", 7, TransferState::Canceled);
+    let canceled = vec![transfers.get("drums").unwrap()];
+
+    let removed = remove_partials(tmp.path(), &canceled, since)?;
+    assert_eq!(removed, vec![partial.clone(), canceled_name.clone()]);
+    assert!(finished.exists());
+    assert!(old_partial.exists());
+    assert!(!partial.exists());
+    assert!(!canceled_name.exists());
+    Ok(())
+}
+
+#[test]
+fn records_cancelled_songs_apart_from_failures() {
+    let status = StatusHandle::new(3);
+    status.start_song(0, "https://example.com/song-1");
+    status.cancel_song(0, "https://example.com/song-1", "Cancelled: Download operation timed out");
+    status.start_song(1, "https://example.com/song-2");
+    status.fail_song(1, "https://example.com/song-2", "This track has not been purchased");
+
+    let snapshot = status.snapshot();
+    assert_eq!(snapshot.cancelled, 1);
+    assert_eq!(snapshot.failed, 1);
+    let cancelled: Vec<bool> = snapshot.recent_failures.iter().map(|f| f.cancelled).collect();
+    assert_eq!(cancelled, vec![true, false]);
+}
+
+#[test]
+fn a_slow_download_only_stalls_without_progress() {
+    let start = Instant::now();
+    let at = |secs| start + Duration::from_secs(secs);
+    let mut stall = StallTimer::new(Duration::from_secs(30), start);
+    // Hundreds of MB trickling in for far longer than the timeout
+    for secs in (10..600).step_by(10) {
+        assert!(!stall.observe(secs * 1024 * 1024, at(secs)));
+    }
+    assert!(!stall.observe(590 * 1024 * 1024, at(620)));
+    assert!(stall.observe(590 * 1024 * 1024, at(621)));
+}
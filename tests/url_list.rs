@@ -0,0 +1,61 @@
+use std::error::Error;
+
+use kv_downloader::tasks::url_list;
+
+const SITE: &str = "www.karaoke-version.com";
+
+#[test]
+fn reads_urls_skipping_comments_and_blank_lines() -> Result<(), Box<dyn Error>> {
+    let text = "\
+# Friday gig
+https://www.karaoke-version.com/custombackingtrack/toto/rosanna.html
+
+  https://www.karaoke-version.com/custombackingtrack/toto/africa.html  
+# https://www.karaoke-version.com/custombackingtrack/toto/hold-the-line.html
+";
+    assert_eq!(
+        url_list::parse(text, SITE)?,
+        [
+            "https://www.karaoke-version.com/custombackingtrack/toto/rosanna.html",
+            "https://www.karaoke-version.com/custombackingtrack/toto/africa.html",
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn drops_urls_listed_twice() -> Result<(), Box<dyn Error>> {
+    let text = "\
+https://www.karaoke-version.com/custombackingtrack/toto/rosanna.html
+https://www.karaoke-version.com/custombackingtrack/toto/africa.html
+https://www.karaoke-version.com/custombackingtrack/toto/rosanna.html/
+";
+    assert_eq!(url_list::parse(text, SITE)?.len(), 2);
+    Ok(())
+}
+
+#[test]
+fn reports_every_line_that_isnt_a_song_on_the_site() {
+    let text = "\
+https://www.karaoke-version.com/custombackingtrack/toto/rosanna.html
+rosanna
+https://www.karaoke-version.co.uk/custombackingtrack/toto/africa.html
+ftp://www.karaoke-version.com/rosanna.html
+";
+    let error = url_list::parse(text, SITE).unwrap_err().to_string();
+    assert!(error.contains("line 2: 'rosanna' isn't a URL"), "{}", error);
+    assert!(error.contains("line 3:") && error.contains("on www.karaoke-version.co.uk"), "{}", error);
+    assert!(error.contains("line 4:"), "{}", error);
+    assert!(!error.contains("line 1:"), "{}", error);
+}
+
+#[test]
+fn loads_a_file_and_rejects_an_empty_one() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    let path = tmp.path().join("gig.txt");
+    std::fs::write(&path, "https://www.karaoke-version.com/custombackingtrack/toto/rosanna.html\n")?;
+    assert_eq!(url_list::load(&path, SITE)?.len(), 1);
+    std::fs::write(&path, "# nothing yet\n")?;
+    assert!(url_list::load(&path, SITE).is_err());
+    Ok(())
+}
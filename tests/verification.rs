@@ -0,0 +1,15 @@
+use kv_downloader::tasks::sign_in::{VerificationRequired, VERIFICATION_CODE_VAR};
+
+#[test]
+fn tells_how_to_give_the_verification_code() {
+    let text = VerificationRequired.to_string();
+    assert!(text.contains("run once interactively"), "{}", text);
+    assert!(text.contains("--verification-code"), "{}", text);
+    assert!(text.contains(VERIFICATION_CODE_VAR), "{}", text);
+}
+
+#[test]
+fn is_told_apart_from_other_failures() {
+    let error: anyhow::Error = VerificationRequired.into();
+    assert!(error.downcast_ref::<VerificationRequired>().is_some());
+}
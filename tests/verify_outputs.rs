@@ -0,0 +1,133 @@
+mod common;
+
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use kv_downloader::audio::verify::{self, OutputPair};
+use kv_downloader::audio::{AudioProcessor, ProcessOptions};
+use common::{write_samples, RATE};
+
+/// A 440 Hz tone gated on for 50 ms every `period_ms`, so each stem has its own envelope.
+fn bursts(seconds: f64, period_ms: u32) -> Vec<i16> {
+    let frames = (seconds * RATE as f64) as u32;
+    let period = RATE * period_ms / 1000;
+    (0..frames)
+        .map(|i| {
+            if i % period < RATE / 20 {
+                ((i as f32 * 440.0 * std::f32::consts::TAU / RATE as f32).sin() * 12000.0) as i16
+            } else {
+                0
+            }
+        })
+        .collect()
+}
+
+/// Stereo 16-bit WAV of `samples` after `silence` seconds of silence.
+fn write_wav(path: &Path, samples: &[i16], silence: f64) -> Result<(), Box<dyn Error>> {
+    let leading = vec![0; (silence * RATE as f64) as usize];
+    let stereo: Vec<i16> = leading.iter().chain(samples).flat_map(|sample| [*sample, *sample]).collect();
+    write_samples(path, 2, RATE, &stereo)
+}
+
+fn pair(source: &Path, output: &Path, padding: f64) -> OutputPair {
+    OutputPair {
+        source: source.to_path_buf(),
+        outputs: vec![output.to_path_buf()],
+        padding: Duration::from_secs_f64(padding),
+    }
+}
+
+#[test]
+fn decodes_a_window_without_the_audio_before_it() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    let path = tmp.path().join("Bass.wav");
+    write_wav(&path, &bursts(6.0, 250), 0.0)?;
+
+    let start = Duration::from_millis(2500);
+    let length = Duration::from_millis(500);
+    let decoded = verify::decode_window(&path, start, length)?;
+    let read = verify::wav_window(&path, start, length)?;
+    assert_eq!(decoded.samples.len(), (RATE / 2) as usize);
+    assert!(verify::similarity(&decoded, &read) > 0.99);
+
+    // Windows past the end come back short rather than failing
+    let tail = verify::decode_window(&path, Duration::from_millis(5800), length)?;
+    assert!(tail.samples.len() < (RATE / 2) as usize);
+    Ok(())
+}
+
+#[test]
+fn accepts_padded_outputs() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    let source = tmp.path().join("Bass.mp3");
+    let output = tmp.path().join("Bass.wav");
+    write_wav(&source, &bursts(6.0, 250), 0.0)?;
+    write_wav(&output, &bursts(6.0, 250), 0.75)?;
+    verify::verify_outputs(&[pair(&source, &output, 0.75)])?;
+
+    // Without the offset the same files don't line up
+    assert!(verify::verify_outputs(&[pair(&source, &output, 0.0)]).is_err());
+    Ok(())
+}
+
+#[test]
+fn detects_swapped_outputs() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    let paths = |name: &str| -> (PathBuf, PathBuf) {
+        (tmp.path().join(format!("{}.mp3", name)), tmp.path().join(format!("{}_mono.wav", name)))
+    };
+    let (bass_source, bass_output) = paths("Bass");
+    let (drums_source, drums_output) = paths("Drums");
+    write_wav(&bass_source, &bursts(6.0, 250), 0.0)?;
+    write_wav(&drums_source, &bursts(6.0, 370), 0.0)?;
+    // The mapping bug: each output holds the other stem
+    write_wav(&bass_output, &bursts(6.0, 370), 0.0)?;
+    write_wav(&drums_output, &bursts(6.0, 250), 0.0)?;
+
+    let err = verify::verify_outputs(&[
+        pair(&bass_source, &bass_output, 0.0),
+        pair(&drums_source, &drums_output, 0.0),
+    ])
+    .unwrap_err()
+    .to_string();
+    for name in ["Bass.mp3", "Bass_mono.wav", "Drums.mp3", "Drums_mono.wav"] {
+        assert!(err.contains(name), "{} not named in {}", name, err);
+    }
+    Ok(())
+}
+
+#[test]
+fn silence_only_matches_silence() {
+    let silent = verify::Window {
+        sample_rate: RATE,
+        samples: vec![0.0; RATE as usize],
+    };
+    let loud = verify::Window {
+        sample_rate: RATE,
+        samples: bursts(1.0, 250).iter().map(|s| *s as f32 / i16::MAX as f32).collect(),
+    };
+    assert_eq!(verify::similarity(&silent, &silent), 1.0);
+    assert_eq!(verify::similarity(&silent, &loud), 0.0);
+}
+
+#[test]
+fn processing_verifies_every_stem() -> Result<(), Box<dyn Error>> {
+    let tmp = tempfile::tempdir()?;
+    // Symphonia probes the contents, so WAV data stands in for the downloads
+    for (part, seconds, period) in [("Click", 6.0, 500), ("Bass", 5.0, 250), ("Drum_Kit", 6.0, 370)] {
+        let name = format!("Smashing_Pumpkins_Cherub_Rock({}_Custom_Backing_Track).mp3", part);
+        write_wav(&tmp.path().join(name), &bursts(seconds, period), 0.0)?;
+    }
+    let options = ProcessOptions {
+        skip_validation: true,
+        skip_rpp: true,
+        skip_fcpxml: true,
+        skip_midi: true,
+        verify_outputs: true,
+        ..Default::default()
+    };
+    AudioProcessor::process_downloads(tmp.path(), "cherub_rock", &[], &options)?;
+    assert!(tmp.path().join("Cherub Rock/STEMS/WAV MONO/Bass_mono.wav").exists());
+    Ok(())
+}